@@ -0,0 +1,105 @@
+//! Secret key storage backed by the Android Keystore
+//!
+//! The Android project registers a `net.sneaker.app.KeystoreBridge` at
+//! startup by calling its native `register()`. The bridge keeps an AES-GCM
+//! key in the Android Keystore (in StrongBox where the device has one) and
+//! exposes `wrap(ByteArray): ByteArray` and `unwrap(ByteArray): ByteArray`.
+//! The Nostr secret key is stored only in wrapped form; the wrapping key
+//! can't be exported from the Keystore.
+//!
+//! Not implemented on the Android side yet: `KeystoreBridge` isn't in this
+//! repository, so this is only built with the `android-keystore` feature.
+//! Without it Android keeps keys in the app store, as on desktop.
+
+use crate::keys::{KeyError, KeyStorage, PersistedKeys, StoredKeys, WrappedKeys};
+use jni::objects::{GlobalRef, JByteArray, JObject, JValue};
+use jni::{JNIEnv, JavaVM};
+use std::sync::OnceLock;
+
+/// `backend` of keys wrapped here
+pub const BACKEND: &str = "androidKeystore";
+
+struct Bridge {
+    vm: JavaVM,
+    bridge: GlobalRef,
+}
+
+static BRIDGE: OnceLock<Bridge> = OnceLock::new();
+
+fn storage_error(e: impl ToString) -> KeyError {
+    KeyError::StorageError(e.to_string())
+}
+
+/// Key storage using the registered Keystore bridge
+pub struct AndroidKeystore {
+    bridge: &'static Bridge,
+}
+
+impl AndroidKeystore {
+    /// The Keystore backend, if the app registered its bridge
+    pub fn registered() -> Option<Self> {
+        BRIDGE.get().map(|bridge| Self { bridge })
+    }
+
+    /// Call a `ByteArray -> ByteArray` method on the bridge
+    fn call(&self, method: &str, input: &[u8]) -> Result<Vec<u8>, KeyError> {
+        let mut env = self
+            .bridge
+            .vm
+            .attach_current_thread()
+            .map_err(storage_error)?;
+        let input = env.byte_array_from_slice(input).map_err(storage_error)?;
+
+        let output = env
+            .call_method(
+                self.bridge.bridge.as_obj(),
+                method,
+                "([B)[B",
+                &[JValue::Object(&input)],
+            )
+            .and_then(|value| value.l());
+        let output = match output {
+            Ok(output) => JByteArray::from(output),
+            Err(e) => {
+                // A thrown KeyStoreException must not stay pending
+                let _ = env.exception_clear();
+                return Err(storage_error(format!("{} failed: {}", method, e)));
+            }
+        };
+
+        env.convert_byte_array(&output).map_err(storage_error)
+    }
+}
+
+impl KeyStorage for AndroidKeystore {
+    fn persist(&self, stored: &StoredKeys) -> Result<PersistedKeys, KeyError> {
+        WrappedKeys::new(stored, BACKEND, |secret| self.call("wrap", secret))
+            .map(PersistedKeys::Wrapped)
+    }
+
+    fn load(&self, persisted: PersistedKeys) -> Result<Option<StoredKeys>, KeyError> {
+        match persisted {
+            PersistedKeys::Wrapped(wrapped) if wrapped.backend == BACKEND => {
+                wrapped.open(|bytes| self.call("unwrap", bytes)).map(Some)
+            }
+            PersistedKeys::Wrapped(wrapped) => Err(storage_error(format!(
+                "key is held by {}, which is not available",
+                wrapped.backend
+            ))),
+            PersistedKeys::Plain(stored) => Ok(Some(stored)),
+            PersistedKeys::Sealed(_) | PersistedKeys::WatchOnly(_) => Ok(None),
+        }
+    }
+}
+
+/// JNI entry point for `KeystoreBridge.register()`
+#[no_mangle]
+pub extern "system" fn Java_net_sneaker_app_KeystoreBridge_register<'local>(
+    env: JNIEnv<'local>,
+    bridge: JObject<'local>,
+) {
+    let (Ok(vm), Ok(bridge)) = (env.get_java_vm(), env.new_global_ref(&bridge)) else {
+        return;
+    };
+    let _ = BRIDGE.set(Bridge { vm, bridge });
+}
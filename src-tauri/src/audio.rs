@@ -0,0 +1,411 @@
+//! Audio exchange transport
+//!
+//! When neither NFC nor a camera is usable, the exchange payload can be
+//! played through one device's speaker and picked up by the other's
+//! microphone. The modem is multi-tone FSK in the spirit of ggwave: each
+//! symbol carries one byte as two simultaneous tones, the low nibble from
+//! one group of 16 frequencies and the high nibble from a second group.
+//! Everything stays between roughly 1.9 and 5.6 kHz, which phone speakers
+//! and microphones handle well.
+//!
+//! A frame is a preamble of marker symbols, a two-byte length, the payload
+//! and a CRC-16:
+//!
+//! ```text
+//! [SYNC_A x (PREAMBLE_SYMBOLS - 1)][SYNC_B][len: u16 BE][payload][crc16 BE]
+//! ```
+//!
+//! The receiver finds the preamble by the `SYNC_A` tone and locks onto the
+//! `SYNC_A` to `SYNC_B` transition, then reads each symbol from its middle
+//! half so small timing errors and echoes don't matter. A corrupted frame is
+//! dropped; the broadcast loops, so the next repetition is tried instead.
+
+use thiserror::Error;
+
+/// Length of one symbol
+pub const SYMBOL_SECS: f32 = 2048.0 / 48_000.0;
+
+/// Marker symbols before the data
+pub const PREAMBLE_SYMBOLS: usize = 4;
+
+/// Silence after each frame, so a looped broadcast has gaps between repeats
+pub const TRAILING_SILENCE_SYMBOLS: usize = 4;
+
+/// Largest payload a frame can carry; exchange messages are far smaller
+pub const MAX_AUDIO_PAYLOAD: usize = 2048;
+
+/// Sample rate used for broadcast audio; plenty for tones below 6 kHz
+pub const BROADCAST_SAMPLE_RATE: u32 = 24_000;
+
+const BASE_FREQ: f32 = 1875.0;
+const TONE_SPACING: f32 = 93.75;
+const TONES_PER_GROUP: usize = 16;
+const SYNC_A_FREQ: f32 = BASE_FREQ + 34.0 * TONE_SPACING;
+const SYNC_B_FREQ: f32 = BASE_FREQ + 39.0 * TONE_SPACING;
+
+/// Peak amplitude of a symbol
+const AMPLITUDE: f32 = 0.7;
+
+/// Share of a window's energy a tone needs to count as the preamble
+const DETECT_THRESHOLD: f32 = 0.5;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AudioError {
+    #[error("Payload too large for an audio frame")]
+    TooLarge,
+    #[error("Unsupported sample rate: {0}")]
+    InvalidSampleRate(u32),
+}
+
+fn tone_freq(group: usize, nibble: u8) -> f32 {
+    BASE_FREQ + (group * (TONES_PER_GROUP + 1) + nibble as usize) as f32 * TONE_SPACING
+}
+
+fn symbol_len(sample_rate: u32) -> Result<usize, AudioError> {
+    // Nyquist must clear the highest tone
+    if (sample_rate as f32) < 2.5 * SYNC_B_FREQ {
+        return Err(AudioError::InvalidSampleRate(sample_rate));
+    }
+    Ok((SYMBOL_SECS * sample_rate as f32).round() as usize)
+}
+
+/// CRC-16/CCITT-FALSE
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Append one symbol of `freqs` played together, with short fades at the
+/// edges so symbol changes don't click
+fn push_symbol(out: &mut Vec<f32>, freqs: &[f32], len: usize, sample_rate: u32) {
+    let ramp = len / 16;
+    let amplitude = AMPLITUDE / freqs.len() as f32;
+    for n in 0..len {
+        let t = n as f32 / sample_rate as f32;
+        let edge = n.min(len - 1 - n);
+        let envelope = if edge < ramp {
+            0.5 - 0.5 * (std::f32::consts::PI * edge as f32 / ramp as f32).cos()
+        } else {
+            1.0
+        };
+        let sample: f32 = freqs
+            .iter()
+            .map(|f| (2.0 * std::f32::consts::PI * f * t).sin())
+            .sum();
+        out.push(sample * amplitude * envelope);
+    }
+}
+
+/// Modulate `payload` into mono samples at `sample_rate`
+pub fn encode(payload: &[u8], sample_rate: u32) -> Result<Vec<f32>, AudioError> {
+    if payload.len() > MAX_AUDIO_PAYLOAD {
+        return Err(AudioError::TooLarge);
+    }
+    let len = symbol_len(sample_rate)?;
+
+    let mut body = (payload.len() as u16).to_be_bytes().to_vec();
+    body.extend_from_slice(payload);
+    let crc = crc16(&body);
+    body.extend_from_slice(&crc.to_be_bytes());
+
+    let symbols = PREAMBLE_SYMBOLS + body.len() + TRAILING_SILENCE_SYMBOLS;
+    let mut out = Vec::with_capacity(symbols * len);
+    for _ in 1..PREAMBLE_SYMBOLS {
+        push_symbol(&mut out, &[SYNC_A_FREQ], len, sample_rate);
+    }
+    push_symbol(&mut out, &[SYNC_B_FREQ], len, sample_rate);
+    for byte in body {
+        let tones = [tone_freq(0, byte & 0x0F), tone_freq(1, byte >> 4)];
+        push_symbol(&mut out, &tones, len, sample_rate);
+    }
+    out.resize(out.len() + TRAILING_SILENCE_SYMBOLS * len, 0.0);
+    Ok(out)
+}
+
+/// 16-bit mono WAV file of `samples`, for the frontend to play in a loop
+pub fn wav_bytes(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let data_len = (samples.len() * 2) as u32;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        wav.extend_from_slice(&value.to_le_bytes());
+    }
+    wav
+}
+
+/// Goertzel power of `freq` in `window`
+fn tone_power(window: &[f32], freq: f32, sample_rate: f32) -> f32 {
+    let coeff = 2.0 * (2.0 * std::f32::consts::PI * freq / sample_rate).cos();
+    let (mut s1, mut s2) = (0.0f32, 0.0f32);
+    for &x in window {
+        let s0 = x + coeff * s1 - s2;
+        s2 = s1;
+        s1 = s0;
+    }
+    s1 * s1 + s2 * s2 - coeff * s1 * s2
+}
+
+/// Share of `window`'s energy at `freq`: about 1 for a pure tone, near 0
+/// for noise or other tones
+fn tone_share(window: &[f32], freq: f32, sample_rate: f32) -> f32 {
+    let energy: f32 = window.iter().map(|x| x * x).sum();
+    if energy < 1e-9 {
+        return 0.0;
+    }
+    2.0 * tone_power(window, freq, sample_rate) / (window.len() as f32 * energy)
+}
+
+/// Outcome of one search, with where the next one starts
+enum Scan {
+    /// Not enough audio yet
+    NeedMore(usize),
+    /// A false start or corrupted frame; carry on searching
+    Skip(usize),
+    Frame {
+        payload: Vec<u8>,
+        end: usize,
+    },
+}
+
+/// Streaming demodulator fed with microphone samples
+pub struct AudioDecoder {
+    sample_rate: u32,
+    symbol_len: usize,
+    samples: Vec<f32>,
+    /// Where the preamble search resumes
+    cursor: usize,
+}
+
+impl AudioDecoder {
+    pub fn new(sample_rate: u32) -> Result<Self, AudioError> {
+        Ok(Self {
+            sample_rate,
+            symbol_len: symbol_len(sample_rate)?,
+            samples: Vec::new(),
+            cursor: 0,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Add recorded samples. Returns a payload once a whole frame has
+    /// arrived with a valid checksum.
+    pub fn push(&mut self, samples: &[f32]) -> Option<Vec<u8>> {
+        self.samples.extend_from_slice(samples);
+        let found = loop {
+            match self.scan() {
+                Scan::NeedMore(to) => {
+                    self.cursor = to;
+                    break None;
+                }
+                Scan::Skip(to) => self.cursor = to,
+                Scan::Frame { payload, end } => {
+                    self.cursor = end;
+                    break Some(payload);
+                }
+            }
+        };
+
+        // Forget audio the search has moved past
+        let consumed = self.cursor.min(self.samples.len());
+        self.samples.drain(..consumed);
+        self.cursor -= consumed;
+        found
+    }
+
+    fn share(&self, at: usize, freq: f32) -> f32 {
+        tone_share(
+            &self.samples[at..at + self.symbol_len],
+            freq,
+            self.sample_rate as f32,
+        )
+    }
+
+    /// Look for a frame from the cursor on
+    fn scan(&self) -> Scan {
+        let len = self.symbol_len;
+        let step = len / 8;
+        let mut pos = self.cursor;
+
+        while pos + len <= self.samples.len() {
+            if self.share(pos, SYNC_A_FREQ) < DETECT_THRESHOLD {
+                pos += step;
+                continue;
+            }
+
+            // In the preamble: find where SYNC_A gives way to SYNC_B
+            let mut prev = self.share(pos, SYNC_A_FREQ) - self.share(pos, SYNC_B_FREQ);
+            let mut at = pos + step;
+            loop {
+                if at + len > self.samples.len() {
+                    return Scan::NeedMore(pos);
+                }
+                let (a, b) = (self.share(at, SYNC_A_FREQ), self.share(at, SYNC_B_FREQ));
+                let diff = a - b;
+                if diff <= 0.0 {
+                    if a + b < DETECT_THRESHOLD / 2.0 {
+                        return Scan::Skip(at);
+                    }
+                    // The window straddles the boundary equally where the
+                    // two shares cross
+                    let offset = step as f32 * prev / (prev - diff);
+                    let boundary = at - step + offset.round() as usize + len / 2;
+                    return self.read_frame(boundary + len, pos);
+                }
+                if at > pos + (PREAMBLE_SYMBOLS + 1) * len {
+                    return Scan::Skip(at);
+                }
+                prev = diff;
+                at += step;
+            }
+        }
+        Scan::NeedMore(pos)
+    }
+
+    /// Read the byte of data symbol `index` from its middle half
+    fn read_byte(&self, data_start: usize, index: usize) -> Option<u8> {
+        let len = self.symbol_len;
+        let start = data_start + index * len + len / 4;
+        let window = self.samples.get(start..start + len / 2)?;
+        let rate = self.sample_rate as f32;
+
+        let nibble = |group: usize| {
+            (0..TONES_PER_GROUP as u8)
+                .map(|n| (n, tone_power(window, tone_freq(group, n), rate)))
+                .max_by(|x, y| x.1.total_cmp(&y.1))
+                .map(|(n, _)| n)
+                .unwrap_or_default()
+        };
+        Some(nibble(0) | (nibble(1) << 4))
+    }
+
+    fn read_frame(&self, data_start: usize, preamble: usize) -> Scan {
+        let header = (self.read_byte(data_start, 0), self.read_byte(data_start, 1));
+        let (Some(hi), Some(lo)) = header else {
+            return Scan::NeedMore(preamble);
+        };
+        let payload_len = u16::from_be_bytes([hi, lo]) as usize;
+        if payload_len > MAX_AUDIO_PAYLOAD {
+            return Scan::Skip(data_start);
+        }
+
+        let mut body = vec![hi, lo];
+        for index in 2..payload_len + 4 {
+            match self.read_byte(data_start, index) {
+                Some(byte) => body.push(byte),
+                // Keep the preamble so the frame is read again when complete
+                None => return Scan::NeedMore(preamble),
+            }
+        }
+
+        let crc = u16::from_be_bytes([body[body.len() - 2], body[body.len() - 1]]);
+        body.truncate(body.len() - 2);
+        if crc16(&body) != crc {
+            return Scan::Skip(data_start);
+        }
+        Scan::Frame {
+            payload: body.split_off(2),
+            end: data_start + (payload_len + 4) * self.symbol_len,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic noise in [-amplitude, amplitude]
+    fn noise(len: usize, amplitude: f32) -> Vec<f32> {
+        let mut state: u32 = 0x1234_5678;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                ((state >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0) * amplitude
+            })
+            .collect()
+    }
+
+    /// Delay, attenuate and add noise, like a speaker across a table
+    fn through_air(signal: &[f32], delay: usize) -> Vec<f32> {
+        let mut recorded = noise(delay + signal.len(), 0.05);
+        for (i, s) in signal.iter().enumerate() {
+            recorded[delay + i] += s * 0.4;
+        }
+        recorded
+    }
+
+    #[test]
+    fn test_round_trip_across_sample_rates() {
+        let payload = b"sn2:some-exchange-payload-bytes-0123456789";
+        for rate in [48_000, 44_100, 24_000] {
+            let signal = encode(payload, rate).unwrap();
+            let recorded = through_air(&signal, 3_333);
+
+            let mut decoder = AudioDecoder::new(rate).unwrap();
+            let mut decoded = None;
+            for chunk in recorded.chunks(4096) {
+                if let Some(found) = decoder.push(chunk) {
+                    decoded = Some(found);
+                }
+            }
+            assert_eq!(decoded.as_deref(), Some(&payload[..]));
+        }
+    }
+
+    #[test]
+    fn test_looped_broadcast_decodes_each_repeat() {
+        let signal = encode(b"hello", 48_000).unwrap();
+        let looped: Vec<f32> = signal.iter().chain(signal.iter()).copied().collect();
+
+        let mut decoder = AudioDecoder::new(48_000).unwrap();
+        let frames: Vec<_> = looped
+            .chunks(1000)
+            .filter_map(|chunk| decoder.push(chunk))
+            .collect();
+        assert_eq!(frames, vec![b"hello".to_vec(), b"hello".to_vec()]);
+    }
+
+    #[test]
+    fn test_corrupted_frame_is_dropped() {
+        let mut signal = encode(b"hello", 48_000).unwrap();
+        let len = symbol_len(48_000).unwrap();
+        // Replace the third data byte's symbol with a different byte
+        let start = (PREAMBLE_SYMBOLS + 4) * len;
+        let mut other = Vec::new();
+        push_symbol(&mut other, &[tone_freq(0, 1), tone_freq(1, 1)], len, 48_000);
+        signal[start..start + len].copy_from_slice(&other);
+
+        let mut decoder = AudioDecoder::new(48_000).unwrap();
+        assert_eq!(decoder.push(&signal), None);
+        assert_eq!(decoder.push(&vec![0.0; 10 * len]), None);
+
+        assert!(matches!(
+            AudioDecoder::new(8_000),
+            Err(AudioError::InvalidSampleRate(8_000))
+        ));
+    }
+}
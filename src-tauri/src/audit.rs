@@ -0,0 +1,111 @@
+//! Log of what our secret key has been used for
+//!
+//! Entries are only ever appended; past `MAX_AUDIT_ENTRIES` the oldest are
+//! dropped. The log records the purpose and time of each use, never key
+//! material.
+
+use serde::{Deserialize, Serialize};
+
+/// Entries kept in the log
+pub const MAX_AUDIT_ENTRIES: usize = 1000;
+
+/// Why the secret key was used
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum KeyUse {
+    /// The secret key was handed out as an nsec
+    IdentityExport,
+    /// The key signed a rotation statement for its successor
+    KeyRotation,
+    /// The key signed its own revocation
+    KeyRevocation,
+    /// The secret key was split into backup shares
+    ShareBackup,
+    /// The secret key was split among guardians for social recovery
+    SocialRecovery,
+    /// The key signed our initial exchange message (QR or NFC)
+    ExchangeSign,
+    /// The key signed an exchange response to a peer
+    ResponseSign,
+    /// An Iroh identity for a contact was derived from the key
+    IrohDerivation,
+    /// The key certified a device subkey
+    DeviceCertificate,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyAuditEntry {
+    /// Unix timestamp
+    pub at: u64,
+    pub purpose: KeyUse,
+    /// Contact pubkey or other context, when there is one
+    pub detail: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct KeyAuditLog {
+    entries: Vec<KeyAuditEntry>,
+}
+
+impl KeyAuditLog {
+    pub fn append(&mut self, purpose: KeyUse, detail: Option<String>, now: u64) {
+        self.entries.push(KeyAuditEntry {
+            at: now,
+            purpose,
+            detail,
+        });
+        if self.entries.len() > MAX_AUDIT_ENTRIES {
+            let excess = self.entries.len() - MAX_AUDIT_ENTRIES;
+            self.entries.drain(..excess);
+        }
+    }
+
+    /// Entries, oldest first
+    pub fn entries(&self) -> &[KeyAuditEntry] {
+        &self.entries
+    }
+
+    /// Entries since `since` (inclusive), newest first
+    pub fn recent(&self, since: u64) -> Vec<KeyAuditEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .take_while(|e| e.at >= since)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_keeps_newest() {
+        let mut log = KeyAuditLog::default();
+        for i in 0..MAX_AUDIT_ENTRIES as u64 + 3 {
+            log.append(KeyUse::IdentityExport, None, i);
+        }
+
+        assert_eq!(log.entries().len(), MAX_AUDIT_ENTRIES);
+        assert_eq!(log.entries()[0].at, 3);
+
+        let json = serde_json::to_value(&log.entries()[0]).unwrap();
+        assert_eq!(json["purpose"], "identityExport");
+    }
+
+    #[test]
+    fn test_recent_newest_first() {
+        let mut log = KeyAuditLog::default();
+        log.append(KeyUse::ExchangeSign, None, 10);
+        log.append(KeyUse::ResponseSign, Some("peer".to_string()), 20);
+        log.append(KeyUse::IrohDerivation, Some("peer".to_string()), 30);
+
+        let recent = log.recent(20);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].purpose, KeyUse::IrohDerivation);
+        assert_eq!(recent[1].purpose, KeyUse::ResponseSign);
+        assert_eq!(log.recent(0).len(), 3);
+    }
+}
@@ -0,0 +1,303 @@
+//! Shamir secret sharing backups of the secret key
+//!
+//! The 32-byte secret key is split byte-wise over GF(256) into `n` shares,
+//! any `k` of which recover it. Each share is a small JSON document meant
+//! for a printed QR code. It names the set it belongs to and the public key
+//! it restores, and carries a checksum so a damaged or mistyped share is
+//! rejected on its own instead of silently producing the wrong key.
+
+use crate::keys::{restore_keys, StoredKeys};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// `type` field of a key share
+pub const SHARE_TYPE: &str = "sneakernet-key-share";
+
+/// Key share format version
+pub const SHARE_VERSION: u32 = 1;
+
+/// Most shares a key can be split into
+pub const MAX_SHARES: u8 = 16;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum BackupError {
+    #[error("Need 2 ≤ threshold ≤ shares ≤ {MAX_SHARES}")]
+    InvalidParameters,
+    #[error("Not a key share: {0}")]
+    InvalidShare(String),
+    #[error("Unsupported key share version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Share {0} is damaged (checksum mismatch)")]
+    ChecksumMismatch(u8),
+    #[error("Shares are from different backups")]
+    MixedSets,
+    #[error("Need {needed} different shares, got {got}")]
+    NotEnoughShares { needed: u8, got: u8 },
+    #[error("The shares don't recover the expected key")]
+    RecoveryFailed,
+}
+
+/// One share of a split secret key
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyShare {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub version: u32,
+    /// Random ID shared by all shares of one split
+    pub set_id: String,
+    /// Public key the shares recover (hex)
+    pub pubkey: String,
+    pub threshold: u8,
+    pub total: u8,
+    /// x coordinate, 1-based
+    pub index: u8,
+    pub data: String,
+    /// First 4 bytes of SHA256 over the fields above (hex)
+    pub checksum: String,
+}
+
+impl KeyShare {
+    fn compute_checksum(&self) -> String {
+        let content = format!(
+            "{}:{}:{}:{}:{}:{}:{}:{}",
+            self.msg_type,
+            self.version,
+            self.set_id,
+            self.pubkey,
+            self.threshold,
+            self.total,
+            self.index,
+            self.data
+        );
+        hex::encode(&Sha256::digest(content.as_bytes())[..4])
+    }
+
+    pub fn to_json(&self) -> Result<String, BackupError> {
+        serde_json::to_string(self).map_err(|e| BackupError::InvalidShare(e.to_string()))
+    }
+
+    /// Parse a scanned share and check its integrity
+    pub fn from_json(json: &str) -> Result<Self, BackupError> {
+        let share: Self = serde_json::from_str(json.trim())
+            .map_err(|e| BackupError::InvalidShare(e.to_string()))?;
+
+        if share.msg_type != SHARE_TYPE {
+            return Err(BackupError::InvalidShare("wrong type".to_string()));
+        }
+        if share.version != SHARE_VERSION {
+            return Err(BackupError::UnsupportedVersion(share.version));
+        }
+        if share.checksum != share.compute_checksum() {
+            return Err(BackupError::ChecksumMismatch(share.index));
+        }
+        if share.index == 0 || share.threshold < 2 || share.threshold > share.total {
+            return Err(BackupError::InvalidShare("bad parameters".to_string()));
+        }
+
+        Ok(share)
+    }
+}
+
+/// Multiplication in GF(256) with the AES polynomial
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1B;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse in GF(256) (a^254); `a` must not be zero
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    for _ in 0..254 {
+        result = gf_mul(result, a);
+    }
+    result
+}
+
+/// Split `stored`'s secret key into `total` shares, any `threshold` of which
+/// recover it
+pub fn split_key(
+    stored: &StoredKeys,
+    total: u8,
+    threshold: u8,
+) -> Result<Vec<KeyShare>, BackupError> {
+    if threshold < 2 || threshold > total || total > MAX_SHARES {
+        return Err(BackupError::InvalidParameters);
+    }
+    let secret = stored
+        .secret_bytes()
+        .map_err(|e| BackupError::InvalidShare(e.to_string()))?;
+    let set_id = hex::encode(rand::random::<[u8; 8]>());
+
+    // One random polynomial per secret byte, constant term = the byte
+    let polynomials: Zeroizing<Vec<Vec<u8>>> = Zeroizing::new(
+        secret
+            .iter()
+            .map(|&byte| {
+                let mut coefficients = vec![byte];
+                coefficients.extend((1..threshold).map(|_| rand::random::<u8>()));
+                coefficients
+            })
+            .collect(),
+    );
+
+    let shares = (1..=total)
+        .map(|x| {
+            let data: Vec<u8> = polynomials
+                .iter()
+                .map(|coefficients| {
+                    // Horner's rule
+                    coefficients
+                        .iter()
+                        .rev()
+                        .fold(0, |acc, &c| gf_mul(acc, x) ^ c)
+                })
+                .collect();
+
+            let mut share = KeyShare {
+                msg_type: SHARE_TYPE.to_string(),
+                version: SHARE_VERSION,
+                set_id: set_id.clone(),
+                pubkey: stored.public_key_hex.clone(),
+                threshold,
+                total,
+                index: x,
+                data: hex::encode(data),
+                checksum: String::new(),
+            };
+            share.checksum = share.compute_checksum();
+            share
+        })
+        .collect();
+
+    Ok(shares)
+}
+
+/// Recover the keys from at least `threshold` shares of one set
+pub fn recover_key(shares: &[KeyShare]) -> Result<StoredKeys, BackupError> {
+    let first = shares
+        .first()
+        .ok_or(BackupError::NotEnoughShares { needed: 2, got: 0 })?;
+
+    let mut distinct: Vec<&KeyShare> = Vec::new();
+    for share in shares {
+        if share.set_id != first.set_id || share.pubkey != first.pubkey {
+            return Err(BackupError::MixedSets);
+        }
+        if !distinct.iter().any(|s| s.index == share.index) {
+            distinct.push(share);
+        }
+    }
+    if distinct.len() < first.threshold as usize {
+        return Err(BackupError::NotEnoughShares {
+            needed: first.threshold,
+            got: distinct.len() as u8,
+        });
+    }
+    let distinct = &distinct[..first.threshold as usize];
+
+    let points: Vec<(u8, Vec<u8>)> = distinct
+        .iter()
+        .map(|share| {
+            hex::decode(&share.data)
+                .map(|data| (share.index, data))
+                .map_err(|e| BackupError::InvalidShare(e.to_string()))
+        })
+        .collect::<Result<_, _>>()?;
+    let len = points[0].1.len();
+    if points.iter().any(|(_, data)| data.len() != len) {
+        return Err(BackupError::MixedSets);
+    }
+
+    // Lagrange interpolation at x = 0 (subtraction is XOR in GF(256))
+    let mut secret = Zeroizing::new(vec![0u8; len]);
+    for (i, (xi, yi)) in points.iter().enumerate() {
+        let mut basis = 1u8;
+        for (j, (xj, _)) in points.iter().enumerate() {
+            if i != j {
+                basis = gf_mul(basis, gf_mul(*xj, gf_inv(xj ^ xi)));
+            }
+        }
+        for (byte, y) in secret.iter_mut().zip(yi) {
+            *byte ^= gf_mul(basis, *y);
+        }
+    }
+
+    let stored = StoredKeys {
+        secret_key_hex: hex::encode(secret.as_slice()).into(),
+        public_key_hex: first.pubkey.clone(),
+    };
+    match restore_keys(&stored) {
+        Ok(keys) if keys.public_key().to_hex() == first.pubkey => Ok(stored),
+        _ => Err(BackupError::RecoveryFailed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::generate_keypair;
+
+    #[test]
+    fn test_any_two_of_three() {
+        let (_, stored) = generate_keypair().unwrap();
+        let shares = split_key(&stored, 3, 2).unwrap();
+
+        for pair in [[0, 1], [0, 2], [2, 1]] {
+            let picked: Vec<KeyShare> = pair
+                .iter()
+                .map(|&i| KeyShare::from_json(&shares[i].to_json().unwrap()).unwrap())
+                .collect();
+            assert_eq!(
+                recover_key(&picked).unwrap().secret_key_hex,
+                stored.secret_key_hex
+            );
+        }
+
+        // One share (even repeated) is not enough
+        assert_eq!(
+            recover_key(&[shares[0].clone(), shares[0].clone()]).unwrap_err(),
+            BackupError::NotEnoughShares { needed: 2, got: 1 }
+        );
+        assert_eq!(
+            split_key(&stored, 2, 3).unwrap_err(),
+            BackupError::InvalidParameters
+        );
+    }
+
+    #[test]
+    fn test_share_integrity() {
+        let (_, stored) = generate_keypair().unwrap();
+        let shares = split_key(&stored, 3, 2).unwrap();
+
+        // Flip one bit of the data
+        let mut damaged = shares[1].clone();
+        let mut data = hex::decode(&damaged.data).unwrap();
+        data[0] ^= 1;
+        damaged.data = hex::encode(data);
+        let json = serde_json::to_string(&damaged).unwrap();
+        assert_eq!(
+            KeyShare::from_json(&json).unwrap_err(),
+            BackupError::ChecksumMismatch(2)
+        );
+
+        let other = split_key(&stored, 3, 2).unwrap();
+        assert_eq!(
+            recover_key(&[shares[0].clone(), other[1].clone()]).unwrap_err(),
+            BackupError::MixedSets
+        );
+    }
+}
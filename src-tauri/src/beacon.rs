@@ -0,0 +1,102 @@
+//! Conference beacon mode
+//!
+//! At events the usual tap-confirm-save loop is too slow. A beacon keeps our
+//! initial exchange payload armed continuously, re-signing it every
+//! `BEACON_ROTATE_SECS` so a photographed code goes stale quickly, and every
+//! verified response addressed to us is saved straight away with
+//! `needs_review` set instead of waiting for per-contact confirmation.
+
+use crate::exchange::{ExchangeError, ExchangeMessage};
+use nostr::prelude::*;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// How long a beacon payload is shown before it is re-signed
+pub const BEACON_ROTATE_SECS: u64 = 30;
+
+/// A continuously armed exchange session
+#[derive(Debug)]
+pub struct Beacon {
+    payload: String,
+    issued: Instant,
+    /// Contacts saved since the beacon started
+    pub completed: u32,
+}
+
+/// Beacon state returned to the frontend
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BeaconStatus {
+    /// Current payload to show as QR / serve over HCE
+    pub payload: String,
+    /// Seconds until the payload rotates
+    pub expires_in_secs: u64,
+    pub completed: u32,
+}
+
+impl Beacon {
+    pub fn new(keys: &Keys) -> Result<Self, ExchangeError> {
+        Ok(Self {
+            payload: ExchangeMessage::new_initial(keys)?.to_compact()?,
+            issued: Instant::now(),
+            completed: 0,
+        })
+    }
+
+    pub fn needs_rotation(&self) -> bool {
+        self.issued.elapsed() >= Duration::from_secs(BEACON_ROTATE_SECS)
+    }
+
+    /// Re-sign the payload if it has expired. Returns true if it changed.
+    pub fn rotate_if_needed(&mut self, keys: &Keys) -> Result<bool, ExchangeError> {
+        if !self.needs_rotation() {
+            return Ok(false);
+        }
+
+        self.payload = ExchangeMessage::new_initial(keys)?.to_compact()?;
+        self.issued = Instant::now();
+        Ok(true)
+    }
+
+    pub fn payload(&self) -> &str {
+        &self.payload
+    }
+
+    pub fn status(&self) -> BeaconStatus {
+        BeaconStatus {
+            payload: self.payload.clone(),
+            expires_in_secs: BEACON_ROTATE_SECS.saturating_sub(self.issued.elapsed().as_secs()),
+            completed: self.completed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fresh_beacon_is_valid() {
+        let keys = Keys::generate();
+        let mut beacon = Beacon::new(&keys).unwrap();
+
+        assert!(!beacon.needs_rotation());
+        assert!(!beacon.rotate_if_needed(&keys).unwrap());
+
+        let msg = ExchangeMessage::decode(beacon.payload().as_bytes()).unwrap();
+        msg.verify(None).unwrap();
+        assert!(msg.their_pubkey.is_none());
+    }
+
+    #[test]
+    fn test_rotation_resigns_payload() {
+        let keys = Keys::generate();
+        let mut beacon = Beacon::new(&keys).unwrap();
+        let original = beacon.payload().to_string();
+
+        beacon.issued -= Duration::from_secs(BEACON_ROTATE_SECS);
+        assert!(beacon.rotate_if_needed(&keys).unwrap());
+        assert_ne!(beacon.payload(), original);
+        assert!(!beacon.needs_rotation());
+    }
+}
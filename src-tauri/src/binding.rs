@@ -0,0 +1,216 @@
+//! Identity binding for chat connections
+//!
+//! A contact's Iroh endpoint is derived from both Nostr keys, but nothing on
+//! the QUIC connection itself shows that the peer holds the contact's key.
+//! When a chat connection opens, each side sends the other a challenge on a
+//! bidirectional stream (magic + 32-byte nonce) and expects back a
+//! signature by the contact's Nostr key over the nonce and both node IDs,
+//! so a proof can't be replayed on another connection. No chat frame is
+//! read from or sent to a peer before it has answered.
+
+use crate::keys::{sign_content, verify_content};
+use iroh_quinn::{Connection, RecvStream, SendStream};
+use nostr::Keys;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Magic opening a challenge stream
+pub const BIND_MAGIC: &[u8; 8] = b"SNKBIND1";
+
+/// How long the peer has to answer a challenge
+pub const BIND_TIMEOUT_SECS: u64 = 10;
+
+/// Prefix of the signed text; bumped whenever the scheme changes
+const BIND_CONTEXT: &str = "sneakernet-bind-v1";
+const NONCE_LEN: usize = 32;
+const MAX_PROOF_LEN: usize = 1024;
+
+#[derive(Error, Debug)]
+pub enum BindError {
+    #[error("Identity challenge failed: {0}")]
+    Stream(String),
+    #[error("No identity proof within {0}s")]
+    Timeout(u64),
+    #[error("Malformed identity proof")]
+    Malformed,
+    #[error("Peer proved a different key")]
+    WrongKey,
+    #[error("Identity proof doesn't verify")]
+    InvalidSignature,
+}
+
+/// Answer to a challenge
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BindProof {
+    pub pubkey: String,
+    pub signature: String,
+}
+
+/// Text signed for a challenge
+pub fn binding_content(nonce: &[u8], prover_node: &str, verifier_node: &str) -> String {
+    format!(
+        "{}:{}:{}:{}",
+        BIND_CONTEXT,
+        hex::encode(nonce),
+        prover_node,
+        verifier_node
+    )
+}
+
+/// Check a proof against the key the peer must hold
+pub fn check(
+    proof: &BindProof,
+    expected_pubkey: &str,
+    nonce: &[u8],
+    prover_node: &str,
+    verifier_node: &str,
+) -> Result<(), BindError> {
+    if proof.pubkey != expected_pubkey {
+        return Err(BindError::WrongKey);
+    }
+    let content = binding_content(nonce, prover_node, verifier_node);
+    verify_content(expected_pubkey, &content, &proof.signature)
+        .map_err(|_| BindError::InvalidSignature)
+}
+
+/// Our side of one connection: answers the peer's challenges
+#[derive(Clone)]
+pub struct Prover {
+    keys: Keys,
+    local_node: String,
+    remote_node: String,
+}
+
+impl Prover {
+    pub fn new(keys: Keys, local_node: String, remote_node: String) -> Self {
+        Self {
+            keys,
+            local_node,
+            remote_node,
+        }
+    }
+
+    /// Sign a challenge nonce
+    pub fn prove(&self, nonce: &[u8]) -> Result<BindProof, BindError> {
+        let content = binding_content(nonce, &self.local_node, &self.remote_node);
+        let signature =
+            sign_content(&self.keys, &content).map_err(|e| BindError::Stream(e.to_string()))?;
+        Ok(BindProof {
+            pubkey: self.keys.public_key().to_hex(),
+            signature,
+        })
+    }
+
+    /// Answer a challenge whose magic has already been read off `recv`
+    pub async fn answer(
+        &self,
+        send: &mut SendStream,
+        recv: &mut RecvStream,
+    ) -> Result<(), BindError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        recv.read_exact(&mut nonce)
+            .await
+            .map_err(|e| BindError::Stream(e.to_string()))?;
+        let proof = serde_json::to_vec(&self.prove(&nonce)?)
+            .map_err(|e| BindError::Stream(e.to_string()))?;
+        send.write_all(&proof)
+            .await
+            .map_err(|e| BindError::Stream(e.to_string()))?;
+        send.finish().map_err(|e| BindError::Stream(e.to_string()))
+    }
+}
+
+/// Challenge the peer on `connection` to prove it holds `expected_pubkey`
+pub async fn challenge(
+    connection: &Connection,
+    local_node: &str,
+    remote_node: &str,
+    expected_pubkey: &str,
+) -> Result<(), BindError> {
+    let nonce: [u8; NONCE_LEN] = rand::random();
+
+    let round_trip = async {
+        let (mut send, mut recv) = connection
+            .open_bi()
+            .await
+            .map_err(|e| BindError::Stream(e.to_string()))?;
+        let mut frame = BIND_MAGIC.to_vec();
+        frame.extend_from_slice(&nonce);
+        send.write_all(&frame)
+            .await
+            .map_err(|e| BindError::Stream(e.to_string()))?;
+        send.finish()
+            .map_err(|e| BindError::Stream(e.to_string()))?;
+
+        let reply = recv
+            .read_to_end(MAX_PROOF_LEN)
+            .await
+            .map_err(|e| BindError::Stream(e.to_string()))?;
+        let proof: BindProof = serde_json::from_slice(&reply).map_err(|_| BindError::Malformed)?;
+        check(&proof, expected_pubkey, &nonce, remote_node, local_node)
+    };
+
+    tokio::time::timeout(Duration::from_secs(BIND_TIMEOUT_SECS), round_trip)
+        .await
+        .map_err(|_| BindError::Timeout(BIND_TIMEOUT_SECS))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE_NODE: &str = "alice-node";
+    const BOB_NODE: &str = "bob-node";
+
+    #[test]
+    fn test_proof_verifies() {
+        let keys = Keys::generate();
+        let prover = Prover::new(keys.clone(), ALICE_NODE.into(), BOB_NODE.into());
+        let nonce = [7u8; NONCE_LEN];
+
+        let proof = prover.prove(&nonce).unwrap();
+        let pubkey = keys.public_key().to_hex();
+        assert!(check(&proof, &pubkey, &nonce, ALICE_NODE, BOB_NODE).is_ok());
+    }
+
+    #[test]
+    fn test_proof_is_bound_to_its_challenge() {
+        let keys = Keys::generate();
+        let prover = Prover::new(keys.clone(), ALICE_NODE.into(), BOB_NODE.into());
+        let nonce = [7u8; NONCE_LEN];
+        let proof = prover.prove(&nonce).unwrap();
+        let pubkey = keys.public_key().to_hex();
+
+        // Another nonce, or the same one on another connection
+        assert!(matches!(
+            check(&proof, &pubkey, &[8u8; NONCE_LEN], ALICE_NODE, BOB_NODE),
+            Err(BindError::InvalidSignature)
+        ));
+        assert!(matches!(
+            check(&proof, &pubkey, &nonce, ALICE_NODE, "mallory-node"),
+            Err(BindError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_other_keys_are_refused() {
+        let keys = Keys::generate();
+        let expected = Keys::generate().public_key().to_hex();
+        let prover = Prover::new(keys, ALICE_NODE.into(), BOB_NODE.into());
+        let nonce = [7u8; NONCE_LEN];
+        let mut proof = prover.prove(&nonce).unwrap();
+
+        assert!(matches!(
+            check(&proof, &expected, &nonce, ALICE_NODE, BOB_NODE),
+            Err(BindError::WrongKey)
+        ));
+        // Claiming the expected key doesn't help without its signature
+        proof.pubkey = expected.clone();
+        assert!(matches!(
+            check(&proof, &expected, &nonce, ALICE_NODE, BOB_NODE),
+            Err(BindError::InvalidSignature)
+        ));
+    }
+}
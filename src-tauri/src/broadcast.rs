@@ -0,0 +1,216 @@
+//! One-to-many broadcast channels
+//!
+//! A channel belongs to one owner and is addressed by a 32-byte topic ID
+//! derived from the owner's pubkey and the channel name, so subscribers can
+//! compute it without asking. Only the owner can publish: every post is signed
+//! and subscribers drop anything not signed by the owner.
+
+use crate::keys::{sign_content, verify_content};
+use nostr::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// Maximum post length in bytes
+pub const MAX_POST_LEN: usize = 4096;
+
+/// Posts kept per channel (oldest dropped first)
+pub const MAX_STORED_POSTS: usize = 500;
+
+#[derive(Error, Debug)]
+pub enum BroadcastError {
+    #[error("Only the channel owner can publish")]
+    NotOwner,
+    #[error("Post is for a different channel")]
+    WrongChannel,
+    #[error("Post signature is invalid")]
+    InvalidSignature,
+    #[error("Post too large")]
+    PostTooLarge,
+    #[error("Signing error: {0}")]
+    SigningError(String),
+}
+
+/// Derive the topic ID (hex) for a channel
+pub fn topic_id(owner_pubkey: &str, name: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"sneakernet-broadcast-v1:");
+    hasher.update(owner_pubkey.as_bytes());
+    hasher.update(b":");
+    hasher.update(name.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// A signed post on a channel
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BroadcastPost {
+    pub channel_id: String,
+    pub author: String,
+    /// Per-channel sequence number, used to drop duplicates
+    pub seq: u64,
+    pub content: String,
+    pub timestamp: u64,
+    pub signature: String,
+}
+
+impl BroadcastPost {
+    fn signing_content(&self) -> String {
+        format!(
+            "sneakernet-broadcast:{}:{}:{}:{}:{}",
+            self.channel_id, self.author, self.seq, self.timestamp, self.content
+        )
+    }
+
+    pub fn verify(&self) -> Result<(), BroadcastError> {
+        verify_content(&self.author, &self.signing_content(), &self.signature)
+            .map_err(|_| BroadcastError::InvalidSignature)
+    }
+}
+
+/// A channel we own or subscribe to
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BroadcastChannel {
+    /// Topic ID (hex)
+    pub id: String,
+    pub name: String,
+    /// Owner pubkey (hex)
+    pub owner: String,
+    /// True if we own this channel, false if we subscribe to it
+    pub is_owner: bool,
+    pub posts: Vec<BroadcastPost>,
+}
+
+impl BroadcastChannel {
+    /// Create a channel we publish to
+    pub fn new_owned(keys: &Keys, name: &str) -> Self {
+        let owner = keys.public_key().to_hex();
+
+        Self {
+            id: topic_id(&owner, name),
+            name: name.to_string(),
+            owner,
+            is_owner: true,
+            posts: Vec::new(),
+        }
+    }
+
+    /// Subscribe to someone else's channel
+    pub fn subscription(owner_pubkey: &str, name: &str) -> Self {
+        Self {
+            id: topic_id(owner_pubkey, name),
+            name: name.to_string(),
+            owner: owner_pubkey.to_string(),
+            is_owner: false,
+            posts: Vec::new(),
+        }
+    }
+
+    fn next_seq(&self) -> u64 {
+        self.posts.iter().map(|p| p.seq + 1).max().unwrap_or(0)
+    }
+
+    /// Sign and store a new post (owner only)
+    pub fn publish(&mut self, keys: &Keys, content: &str) -> Result<BroadcastPost, BroadcastError> {
+        if keys.public_key().to_hex() != self.owner {
+            return Err(BroadcastError::NotOwner);
+        }
+        if content.len() > MAX_POST_LEN {
+            return Err(BroadcastError::PostTooLarge);
+        }
+
+        let mut post = BroadcastPost {
+            channel_id: self.id.clone(),
+            author: self.owner.clone(),
+            seq: self.next_seq(),
+            content: content.to_string(),
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            signature: String::new(),
+        };
+        post.signature = sign_content(keys, &post.signing_content())
+            .map_err(|e| BroadcastError::SigningError(e.to_string()))?;
+
+        self.store(post.clone());
+
+        Ok(post)
+    }
+
+    /// Verify and store a received post. Returns false for duplicates.
+    pub fn receive(&mut self, post: BroadcastPost) -> Result<bool, BroadcastError> {
+        if post.channel_id != self.id {
+            return Err(BroadcastError::WrongChannel);
+        }
+        // Read-only for everyone but the owner
+        if post.author != self.owner {
+            return Err(BroadcastError::NotOwner);
+        }
+        if post.content.len() > MAX_POST_LEN {
+            return Err(BroadcastError::PostTooLarge);
+        }
+        post.verify()?;
+
+        if self.posts.iter().any(|p| p.seq == post.seq) {
+            return Ok(false);
+        }
+
+        self.store(post);
+        Ok(true)
+    }
+
+    fn store(&mut self, post: BroadcastPost) {
+        self.posts.push(post);
+        self.posts.sort_by_key(|p| p.seq);
+
+        if self.posts.len() > MAX_STORED_POSTS {
+            let excess = self.posts.len() - MAX_STORED_POSTS;
+            self.posts.drain(..excess);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_and_receive() {
+        let owner = Keys::generate();
+        let mut channel = BroadcastChannel::new_owned(&owner, "announcements");
+        let mut subscription =
+            BroadcastChannel::subscription(&owner.public_key().to_hex(), "announcements");
+
+        assert_eq!(channel.id, subscription.id);
+
+        let post = channel.publish(&owner, "Talk starts at 3pm").unwrap();
+        assert!(subscription.receive(post.clone()).unwrap());
+        // Duplicate delivery is ignored
+        assert!(!subscription.receive(post).unwrap());
+        assert_eq!(subscription.posts.len(), 1);
+    }
+
+    #[test]
+    fn test_subscribers_are_read_only() {
+        let owner = Keys::generate();
+        let subscriber = Keys::generate();
+        let mut subscription =
+            BroadcastChannel::subscription(&owner.public_key().to_hex(), "announcements");
+
+        assert!(matches!(
+            subscription.publish(&subscriber, "spam"),
+            Err(BroadcastError::NotOwner)
+        ));
+
+        // A post forged by someone else is rejected
+        let mut forged_channel = BroadcastChannel::new_owned(&subscriber, "announcements");
+        let mut forged = forged_channel.publish(&subscriber, "spam").unwrap();
+        forged.channel_id = subscription.id.clone();
+        assert!(matches!(
+            subscription.receive(forged),
+            Err(BroadcastError::NotOwner)
+        ));
+    }
+}
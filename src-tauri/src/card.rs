@@ -0,0 +1,457 @@
+//! Signed contact and profile cards
+//!
+//! A contact card vouches for one of our contacts outside the live exchange:
+//! it carries their pubkey, the name we know them by and when we met, signed
+//! with our key. Whoever imports it gets an introduced contact that still
+//! needs review, with us recorded as the introducer.
+//!
+//! A profile card describes ourselves (npub, display name, avatar) and can be
+//! shown on screen or printed. Scanning one adds an unverified contact; only
+//! an in-person exchange later confirms it. Neither kind of card expires.
+//!
+//! Any stored contact can also be shown to a third person as a plain
+//! `nostr:` npub or nprofile URI. Nothing is signed, so it reads in any Nostr
+//! app and is saved as unverified, the same as any other npub.
+
+use crate::exchange::{Contact, ContactOrigin};
+use crate::identifier::NOSTR_URI_PREFIX;
+use crate::keys::{sign_content, verify_content};
+use nostr::nips::nip19::{FromBech32, Nip19Profile, ToBech32};
+use nostr::{Keys, PublicKey};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// `type` field of a contact card
+pub const CARD_TYPE: &str = "sneakernet-contact-card";
+
+/// Contact card format version
+pub const CARD_VERSION: u32 = 1;
+
+/// `type` field of a profile card
+pub const PROFILE_CARD_TYPE: &str = "sneakernet-profile";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CardError {
+    #[error("Invalid contact card: {0}")]
+    InvalidCard(String),
+    #[error("Unsupported contact card version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Contact card signature is invalid")]
+    InvalidSignature,
+    #[error("This card is about you")]
+    OwnCard,
+    #[error("Signing error: {0}")]
+    SigningError(String),
+    #[error("Invalid npub: {0}")]
+    InvalidNpub(String),
+}
+
+/// A contact vouched for by the signer
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedContactCard {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub version: u32,
+    /// The contact being introduced (hex)
+    pub subject_pubkey: String,
+    /// Name the signer knows them by
+    pub name: Option<String>,
+    /// When the signer exchanged keys with them
+    pub exchanged_at: u64,
+    /// Who signed the card (hex)
+    pub attester_pubkey: String,
+    pub issued_at: u64,
+    pub signature: String,
+}
+
+impl SignedContactCard {
+    /// Sign a card for `contact`, introduced under `name`
+    pub fn new(
+        keys: &Keys,
+        contact: &Contact,
+        name: Option<String>,
+        now: u64,
+    ) -> Result<Self, CardError> {
+        let mut card = Self {
+            msg_type: CARD_TYPE.to_string(),
+            version: CARD_VERSION,
+            subject_pubkey: contact.nostr_pubkey.clone(),
+            name,
+            exchanged_at: contact.exchanged_at,
+            attester_pubkey: keys.public_key().to_hex(),
+            issued_at: now,
+            signature: String::new(),
+        };
+        card.signature = sign_content(keys, &card.signing_content())
+            .map_err(|e| CardError::SigningError(e.to_string()))?;
+
+        Ok(card)
+    }
+
+    fn signing_content(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{}:{}:{}",
+            CARD_TYPE,
+            self.version,
+            self.subject_pubkey,
+            self.name.as_deref().unwrap_or(""),
+            self.exchanged_at,
+            self.attester_pubkey,
+            self.issued_at
+        )
+    }
+
+    pub fn verify(&self) -> Result<(), CardError> {
+        if self.msg_type != CARD_TYPE {
+            return Err(CardError::InvalidCard("not a contact card".to_string()));
+        }
+        if self.version != CARD_VERSION {
+            return Err(CardError::UnsupportedVersion(self.version));
+        }
+
+        verify_content(
+            &self.attester_pubkey,
+            &self.signing_content(),
+            &self.signature,
+        )
+        .map_err(|_| CardError::InvalidSignature)
+    }
+
+    /// Parse and verify a scanned card, rejecting cards about `our_pubkey`
+    pub fn import(json: &str, our_pubkey: Option<&str>) -> Result<Self, CardError> {
+        let card = Self::from_json(json.trim())?;
+        card.verify()?;
+
+        if our_pubkey == Some(card.subject_pubkey.as_str()) {
+            return Err(CardError::OwnCard);
+        }
+
+        Ok(card)
+    }
+
+    /// The introduced contact, pending review
+    pub fn to_contact(&self, iroh_endpoint_id: &str) -> Contact {
+        let mut contact = Contact::new(&self.subject_pubkey, iroh_endpoint_id);
+        contact.introduced_as = self.name.clone();
+        contact.introduced_by = Some(self.attester_pubkey.clone());
+        contact.needs_review = true;
+        contact.origin = ContactOrigin::Introduction;
+        contact
+    }
+
+    pub fn to_json(&self) -> Result<String, CardError> {
+        serde_json::to_string(self).map_err(|e| CardError::InvalidCard(e.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, CardError> {
+        serde_json::from_str(json).map_err(|e| CardError::InvalidCard(e.to_string()))
+    }
+}
+
+/// Our own card, signed so the name and avatar are bound to our key
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedProfileCard {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub version: u32,
+    pub pubkey: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub issued_at: u64,
+    pub signature: String,
+}
+
+/// Profile card as shown to the user, with the payload to put in a QR code
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileCard {
+    pub npub: String,
+    pub pubkey: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub qr_payload: String,
+}
+
+impl SignedProfileCard {
+    pub fn new(
+        keys: &Keys,
+        display_name: Option<String>,
+        avatar_url: Option<String>,
+        now: u64,
+    ) -> Result<Self, CardError> {
+        let mut card = Self {
+            msg_type: PROFILE_CARD_TYPE.to_string(),
+            version: CARD_VERSION,
+            pubkey: keys.public_key().to_hex(),
+            display_name,
+            avatar_url,
+            issued_at: now,
+            signature: String::new(),
+        };
+        card.signature = sign_content(keys, &card.signing_content())
+            .map_err(|e| CardError::SigningError(e.to_string()))?;
+
+        Ok(card)
+    }
+
+    fn signing_content(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{}:{}",
+            PROFILE_CARD_TYPE,
+            self.version,
+            self.pubkey,
+            self.display_name.as_deref().unwrap_or(""),
+            self.avatar_url.as_deref().unwrap_or(""),
+            self.issued_at
+        )
+    }
+
+    pub fn verify(&self) -> Result<(), CardError> {
+        if self.msg_type != PROFILE_CARD_TYPE {
+            return Err(CardError::InvalidCard("not a profile card".to_string()));
+        }
+        if self.version != CARD_VERSION {
+            return Err(CardError::UnsupportedVersion(self.version));
+        }
+
+        verify_content(&self.pubkey, &self.signing_content(), &self.signature)
+            .map_err(|_| CardError::InvalidSignature)
+    }
+
+    /// The card with its npub and QR payload
+    pub fn info(&self) -> Result<ProfileCard, CardError> {
+        let npub = PublicKey::from_hex(&self.pubkey)
+            .map_err(|e| CardError::InvalidNpub(e.to_string()))?
+            .to_bech32()
+            .map_err(|e| CardError::InvalidNpub(e.to_string()))?;
+
+        Ok(ProfileCard {
+            npub,
+            pubkey: self.pubkey.clone(),
+            display_name: self.display_name.clone(),
+            avatar_url: self.avatar_url.clone(),
+            qr_payload: serde_json::to_string(self)
+                .map_err(|e| CardError::InvalidCard(e.to_string()))?,
+        })
+    }
+}
+
+/// A stored contact to show someone else, with the QR payload
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactShare {
+    pub npub: String,
+    pub pubkey: String,
+    /// Name we know them by, shown as a caption; not part of the payload
+    pub display_name: Option<String>,
+    /// Nostr relays carried in the nprofile
+    pub relays: Vec<String>,
+    /// `nostr:nprofile1…` when we know relays for them, else `nostr:npub1…`
+    pub qr_payload: String,
+}
+
+impl ContactShare {
+    pub fn new(contact: &Contact, display_name: Option<String>) -> Result<Self, CardError> {
+        let public_key = PublicKey::from_hex(&contact.nostr_pubkey)
+            .map_err(|e| CardError::InvalidNpub(e.to_string()))?;
+        let npub = public_key
+            .to_bech32()
+            .map_err(|e| CardError::InvalidNpub(e.to_string()))?;
+
+        // Relays that don't parse are left out rather than failing the share
+        let profile = Nip19Profile {
+            public_key,
+            relays: contact
+                .nostr_relays
+                .iter()
+                .filter_map(|r| r.parse().ok())
+                .collect(),
+        };
+        let bech32 = if profile.relays.is_empty() {
+            npub.clone()
+        } else {
+            profile
+                .to_bech32()
+                .map_err(|e| CardError::InvalidNpub(e.to_string()))?
+        };
+
+        Ok(Self {
+            npub,
+            pubkey: contact.nostr_pubkey.clone(),
+            display_name,
+            relays: profile.relays.iter().map(|r| r.to_string()).collect(),
+            qr_payload: format!("{}{}", NOSTR_URI_PREFIX, bech32),
+        })
+    }
+}
+
+/// Who a scanned profile card is about
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScannedProfile {
+    pub pubkey: String,
+    pub display_name: Option<String>,
+    /// Relays from an nprofile
+    pub relays: Vec<String>,
+    /// `Nip19` for a bare npub or nprofile, which carries no signature
+    pub origin: ContactOrigin,
+}
+
+impl ScannedProfile {
+    /// The contact to save, unverified until an in-person exchange
+    pub fn to_contact(&self, iroh_endpoint_id: &str) -> Contact {
+        let mut contact = Contact::new(&self.pubkey, iroh_endpoint_id);
+        contact.profile_name = self.display_name.clone();
+        contact.needs_review = true;
+        contact.origin = self.origin;
+        contact.nostr_relays = self.relays.clone();
+        contact
+    }
+}
+
+/// Parse a scanned profile card: our signed JSON card, or a bare `npub1…`,
+/// `nprofile1…` or `nostr:` URI of either from another Nostr app
+pub fn parse_profile_card(
+    payload: &str,
+    our_pubkey: Option<&str>,
+) -> Result<ScannedProfile, CardError> {
+    let payload = payload.trim();
+
+    let profile = if payload.starts_with('{') {
+        let card: SignedProfileCard =
+            serde_json::from_str(payload).map_err(|e| CardError::InvalidCard(e.to_string()))?;
+        card.verify()?;
+        ScannedProfile {
+            pubkey: card.pubkey,
+            display_name: card.display_name,
+            relays: Vec::new(),
+            origin: ContactOrigin::Exchange,
+        }
+    } else {
+        let bech32 = payload.strip_prefix(NOSTR_URI_PREFIX).unwrap_or(payload);
+        let (pubkey, relays) = if bech32.starts_with("nprofile1") {
+            Nip19Profile::from_bech32(bech32).map(|profile| {
+                let relays = profile.relays.iter().map(|r| r.to_string()).collect();
+                (profile.public_key, relays)
+            })
+        } else {
+            PublicKey::from_bech32(bech32).map(|pubkey| (pubkey, Vec::new()))
+        }
+        .map_err(|e| CardError::InvalidNpub(e.to_string()))?;
+        ScannedProfile {
+            pubkey: pubkey.to_hex(),
+            display_name: None,
+            relays,
+            origin: ContactOrigin::Nip19,
+        }
+    };
+
+    if our_pubkey == Some(profile.pubkey.as_str()) {
+        return Err(CardError::OwnCard);
+    }
+
+    Ok(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_card_roundtrip() {
+        let ours = Keys::generate();
+        let friend = Contact::new(&Keys::generate().public_key().to_hex(), "endpoint");
+
+        let card = SignedContactCard::new(&ours, &friend, Some("Alice".to_string()), 1_000)
+            .unwrap()
+            .to_json()
+            .unwrap();
+
+        let reader = Keys::generate().public_key().to_hex();
+        let imported = SignedContactCard::import(&card, Some(&reader)).unwrap();
+        assert_eq!(imported.subject_pubkey, friend.nostr_pubkey);
+        assert_eq!(imported.exchanged_at, friend.exchanged_at);
+
+        let contact = imported.to_contact("derived");
+        assert_eq!(contact.introduced_as.as_deref(), Some("Alice"));
+        assert_eq!(contact.introduced_by, Some(ours.public_key().to_hex()));
+        assert!(contact.needs_review);
+        assert_eq!(contact.origin, ContactOrigin::Introduction);
+
+        // Cards about ourselves are rejected
+        assert_eq!(
+            SignedContactCard::import(&card, Some(&friend.nostr_pubkey)).unwrap_err(),
+            CardError::OwnCard
+        );
+    }
+
+    #[test]
+    fn test_tampered_card() {
+        let ours = Keys::generate();
+        let friend = Contact::new(&Keys::generate().public_key().to_hex(), "endpoint");
+        let mut card =
+            SignedContactCard::new(&ours, &friend, Some("Alice".to_string()), 1_000).unwrap();
+
+        card.name = Some("Mallory".to_string());
+        assert_eq!(card.verify().unwrap_err(), CardError::InvalidSignature);
+    }
+
+    #[test]
+    fn test_profile_card() {
+        let theirs = Keys::generate();
+        let info = SignedProfileCard::new(&theirs, Some("Bob".to_string()), None, 1_000)
+            .unwrap()
+            .info()
+            .unwrap();
+        assert!(info.npub.starts_with("npub1"));
+
+        let scanned = parse_profile_card(&info.qr_payload, None).unwrap();
+        assert_eq!(scanned.pubkey, theirs.public_key().to_hex());
+        assert_eq!(scanned.display_name.as_deref(), Some("Bob"));
+        assert!(scanned.to_contact("derived").needs_review);
+
+        // A bare npub from another app works too, without a name
+        let uri = format!("nostr:{}", info.npub);
+        let bare = parse_profile_card(&uri, None).unwrap();
+        assert_eq!(bare.pubkey, info.pubkey);
+        assert!(bare.display_name.is_none());
+
+        // nprofile from NIP-19, with its relays
+        let nprofile = "nostr:nprofile1qqsrhuxx8l9ex335q7he0f09aej04zpazpl0ne2cgukyawd24mayt8gpp4m\
+                        hxue69uhhytnc9e3k7mgpz4mhxue69uhkg6nzv9ejuumpv34kytnrdaksjlyr9p";
+        let scanned = parse_profile_card(nprofile, None).unwrap();
+        assert_eq!(
+            scanned.pubkey,
+            "3bf0c63fcb93463407af97a5e5ee64fa883d107ef9e558472c4eb9aaaefa459d"
+        );
+        assert_eq!(scanned.relays.len(), 2);
+        assert!(scanned.relays[0].starts_with("wss://r.x.com"));
+
+        let contact = scanned.to_contact("derived");
+        assert_eq!(contact.origin, ContactOrigin::Nip19);
+        assert!(contact.needs_review);
+        assert_eq!(contact.nostr_relays, scanned.relays);
+
+        assert_eq!(
+            parse_profile_card(&info.npub, Some(&info.pubkey)).unwrap_err(),
+            CardError::OwnCard
+        );
+    }
+
+    #[test]
+    fn test_contact_share() {
+        let mut friend = Contact::new(&Keys::generate().public_key().to_hex(), "endpoint");
+        let share = ContactShare::new(&friend, Some("Alice".to_string())).unwrap();
+        assert_eq!(share.qr_payload, format!("nostr:{}", share.npub));
+
+        // Relay hints make it an nprofile that scans back with them
+        friend.nostr_relays = vec!["wss://relay.example.com".to_string()];
+        let share = ContactShare::new(&friend, None).unwrap();
+        assert!(share.qr_payload.starts_with("nostr:nprofile1"));
+        let scanned = parse_profile_card(&share.qr_payload, None).unwrap();
+        assert_eq!(scanned.pubkey, friend.nostr_pubkey);
+        assert_eq!(scanned.relays.len(), 1);
+        assert_eq!(scanned.origin, ContactOrigin::Nip19);
+    }
+}
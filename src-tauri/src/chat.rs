@@ -1,20 +1,70 @@
 //! Chat protocol implementation over Iroh
 //!
-//! Simple text messaging between contacts using Iroh's QUIC streams.
+//! Simple text messaging between contacts using Iroh's QUIC streams. Once a
+//! contact has sent us a ratchet key, frames to them are sealed (see
+//! `ratchet`), and contacts who both support it can add a post-quantum
+//! hybrid handshake (see `hybrid`). Conversations can be set to disappear
+//! (see `disappearing`).
+//!
+//! A group message is sent to each member on their own connection, sealed
+//! like any other frame, and kept in one conversation keyed by the group ID.
+//! Members acknowledge it to the sender, naming the group, and the sender
+//! counts the receipts per member (see `delivery::GroupDelivery`).
 
+use crate::delivery::{
+    DeliveryInfo, DeliveryLog, GroupDelivery, MessageStatus, ReceiptKind, TRANSPORT_IROH,
+};
+use crate::disappearing::{expiry, sooner, ExpiredMessage};
+use crate::filters::{FilterVerdict, MessageFilter};
+use crate::groups::Group;
+use crate::hybrid::{self, Handshake, PendingOffer};
+use crate::message_store::MessageStore;
+use crate::message_types::{
+    Edit, MessageTypeRegistry, Tombstone, KIND_DELETE, KIND_EDIT, KIND_TEXT,
+};
+use crate::moderation::{FilterReason, InboundPolicy};
+use crate::petname::{NamedContact, ResolvedName};
+use crate::plugins::PluginRegistry;
+use crate::ratchet::{Ratchet, RatchetError, RatchetHeader};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use iroh_quinn::Connection;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use thiserror::Error;
 #[allow(unused_imports)]
 use tokio::io::AsyncWriteExt;
 use tokio::sync::RwLock;
 use uuid::Uuid;
+use zeroize::Zeroizing;
 
 /// Maximum message size (64KB)
 const MAX_MESSAGE_SIZE: usize = 65536;
 
+/// How long after sending a message it can still be edited
+pub const EDIT_WINDOW_SECS: u64 = 15 * 60;
+
+/// Content of a message its sender deleted for everyone
+pub const DELETED_PLACEHOLDER: &str = "Message deleted";
+
+/// Chat capability flag: answers hybrid post-quantum handshakes
+pub const CHAT_CAP_HYBRID: u32 = 1 << 0;
+
+/// Chat capability flag: acknowledges the messages it receives
+pub const CHAT_CAP_ACK: u32 = 1 << 1;
+
+/// Chat capability flag: advertises its disappearing message timer, if any
+pub const CHAT_CAP_DISAPPEARING: u32 = 1 << 2;
+
+/// Chat capability flag: sends delivery and read receipts for group messages
+pub const CHAT_CAP_GROUP_RECEIPTS: u32 = 1 << 3;
+
+/// Everything this version of chat understands, sent in every frame
+pub const CHAT_CAPABILITIES: u32 =
+    CHAT_CAP_HYBRID | CHAT_CAP_ACK | CHAT_CAP_DISAPPEARING | CHAT_CAP_GROUP_RECEIPTS;
+
 #[derive(Error, Debug)]
 pub enum ChatError {
     #[error("Not connected to contact")]
@@ -27,6 +77,24 @@ pub enum ChatError {
     MessageTooLarge,
     #[error("Invalid message format: {0}")]
     InvalidFormat(String),
+    #[error("Message dropped: {0}")]
+    Filtered(FilterReason),
+    #[error("Message quarantined: {0}")]
+    Quarantined(FilterReason),
+    #[error("{0}")]
+    InvalidPayload(String),
+    #[error("Could not open sealed message: {0}")]
+    Sealed(#[from] RatchetError),
+    #[error("Contact sent an unencrypted message after encryption was set up")]
+    Unsealed,
+    #[error("Peer hasn't proven it holds the contact's key")]
+    Unbound,
+    #[error("Can't edit message: {0}")]
+    EditRejected(&'static str),
+    #[error("Can't delete message: {0}")]
+    DeleteRejected(&'static str),
+    #[error("Sender isn't a member of that group")]
+    NotGroupMember,
 }
 
 /// A chat message
@@ -38,6 +106,64 @@ pub struct ChatMessage {
     pub sender_pubkey: String,
     pub timestamp: u64,
     pub is_outgoing: bool,
+    /// Payload kind (see `message_types`); `content` is the plain-text fallback
+    #[serde(default = "default_kind")]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<Value>,
+    /// Labels attached by content filters
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<String>,
+    /// Delivery status (outgoing messages only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<MessageStatus>,
+    /// When `content` was last replaced by an edit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edited_at: Option<u64>,
+    /// Versions replaced by edits, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edit_history: Vec<PreviousVersion>,
+    /// When the sender deleted the message, leaving `DELETED_PLACEHOLDER`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<u64>,
+    /// When the message disappears from both sides
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Group the message was sent to, if it isn't a direct message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_id: Option<String>,
+}
+
+/// An earlier version of an edited message
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviousVersion {
+    pub content: String,
+    /// When this version was written
+    pub timestamp: u64,
+}
+
+/// A message with its delivery timeline (outgoing messages only)
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageInfo {
+    pub message: ChatMessage,
+    pub delivery: Option<DeliveryInfo>,
+    /// Per-member delivery, for a message we sent a group
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_delivery: Option<GroupDelivery>,
+}
+
+/// An incoming message held back by a content filter
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QuarantinedMessage {
+    pub message: ChatMessage,
+    pub reason: String,
+}
+
+fn default_kind() -> String {
+    KIND_TEXT.to_string()
 }
 
 impl ChatMessage {
@@ -52,41 +178,262 @@ impl ChatMessage {
                 .unwrap()
                 .as_secs(),
             is_outgoing: true,
+            kind: default_kind(),
+            payload: None,
+            flags: Vec::new(),
+            status: Some(MessageStatus::Pending),
+            edited_at: None,
+            edit_history: Vec::new(),
+            deleted_at: None,
+            expires_at: None,
+            group_id: None,
+        }
+    }
+
+    /// Create a new outgoing message with a typed payload
+    pub fn new_outgoing_typed(
+        kind: &str,
+        payload: Value,
+        fallback: &str,
+        sender_pubkey: &str,
+    ) -> Self {
+        Self {
+            kind: kind.to_string(),
+            payload: Some(payload),
+            ..Self::new_outgoing(fallback, sender_pubkey)
         }
     }
 
     /// Create from received wire format
+    #[cfg(test)]
     fn from_wire(data: &[u8], sender_pubkey: &str) -> Result<Self, ChatError> {
-        let wire: WireMessage =
-            serde_json::from_slice(data).map_err(|e| ChatError::InvalidFormat(e.to_string()))?;
+        Ok(Self::from_wire_message(
+            WireMessage::parse(data)?,
+            sender_pubkey,
+        ))
+    }
 
-        Ok(Self {
+    fn from_wire_message(wire: WireMessage, sender_pubkey: &str) -> Self {
+        Self {
             id: wire.id,
             content: wire.content,
             sender_pubkey: sender_pubkey.to_string(),
             timestamp: wire.timestamp,
             is_outgoing: false,
-        })
+            kind: wire.kind,
+            payload: wire.payload,
+            flags: Vec::new(),
+            status: None,
+            edited_at: None,
+            edit_history: Vec::new(),
+            deleted_at: None,
+            expires_at: wire.expires_at,
+            group_id: wire.group,
+        }
+    }
+
+    /// Conversation an incoming message belongs in: its group's, or else
+    /// the sender's
+    fn conversation(&self) -> &str {
+        self.group_id.as_deref().unwrap_or(&self.sender_pubkey)
     }
 
     /// Convert to wire format
     fn to_wire(&self) -> Result<Vec<u8>, ChatError> {
+        self.to_wire_advertising(None, None, None)
+    }
+
+    /// Convert to wire format, advertising our ratchet key, chat
+    /// capabilities and disappearing message timer if given
+    fn to_wire_advertising(
+        &self,
+        ratchet_key: Option<String>,
+        capabilities: Option<u32>,
+        disappear_after: Option<u64>,
+    ) -> Result<Vec<u8>, ChatError> {
         let wire = WireMessage {
             id: self.id.clone(),
             content: self.content.clone(),
             timestamp: self.timestamp,
+            kind: self.kind.clone(),
+            payload: self.payload.clone(),
+            ratchet_key,
+            capabilities,
+            disappear_after,
+            expires_at: self.expires_at,
+            group: self.group_id.clone(),
         };
 
         serde_json::to_vec(&wire).map_err(|e| ChatError::SendFailed(e.to_string()))
     }
 }
 
-/// Wire format for messages (minimal, without local-only fields)
+/// Overview of a conversation for the conversation list
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationSummary {
+    pub contact_id: String,
+    pub contact_pubkey: String,
+    pub display_name: ResolvedName,
+    pub last_message: Option<ChatMessage>,
+    pub message_count: usize,
+}
+
+/// Wire format for messages (minimal, without local-only fields).
+///
+/// `kind` and `payload` were added later; older peers ignore them and show
+/// `content`, and messages from older peers default to plain text. The same
+/// goes for `ratchetKey` and `capabilities`: an older peer never sends them,
+/// so we never send it frames it couldn't read. An older peer keeps messages
+/// past `expiresAt`, but its user's own timer still applies on our side.
+/// Group messages only go to members, who all understand `groupId`.
 #[derive(Serialize, Deserialize)]
 struct WireMessage {
     id: String,
     content: String,
     timestamp: u64,
+    #[serde(default = "default_kind")]
+    kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    payload: Option<Value>,
+    #[serde(
+        rename = "ratchetKey",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    ratchet_key: Option<String>,
+    /// Chat capability flags (`CHAT_CAP_*`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    capabilities: Option<u32>,
+    /// Sender's disappearing message timer, from peers with
+    /// `CHAT_CAP_DISAPPEARING`; absent if it is off
+    #[serde(
+        rename = "disappearAfter",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    disappear_after: Option<u64>,
+    #[serde(rename = "expiresAt", default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+    /// Group the message was sent to
+    #[serde(rename = "groupId", default, skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+}
+
+impl WireMessage {
+    fn parse(data: &[u8]) -> Result<Self, ChatError> {
+        serde_json::from_slice(data).map_err(|e| ChatError::InvalidFormat(e.to_string()))
+    }
+}
+
+/// A `WireMessage` encrypted with the sender's ratchet (base64 ciphertext)
+#[derive(Serialize, Deserialize)]
+struct SealedFrame {
+    ratchet: RatchetHeader,
+    ciphertext: String,
+}
+
+/// A hybrid handshake step, only sent to peers with `CHAT_CAP_HYBRID`
+#[derive(Serialize, Deserialize)]
+struct HandshakeFrame {
+    handshake: Handshake,
+}
+
+/// Receipt for a message, only sent to peers with `CHAT_CAP_ACK`.
+///
+/// Receipts for group messages also name the group, the member sending it
+/// and what it says, and only go to peers with `CHAT_CAP_GROUP_RECEIPTS`.
+#[derive(Serialize, Deserialize)]
+struct AckFrame {
+    /// ID of the message received
+    ack: String,
+    #[serde(rename = "groupId", default, skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    member: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    receipt: Option<ReceiptKind>,
+}
+
+/// What a frame from a contact held
+pub enum Incoming {
+    Message(ChatMessage),
+    /// A handshake step, with the frame to answer it with, if any
+    Handshake(Option<Vec<u8>>),
+    /// A receipt, with the message it marked delivered if it was one of ours
+    Ack(Option<ChatMessage>),
+    /// A member's receipt for a group message of ours, with the receipts so
+    /// far if it counted
+    GroupReceipt(Option<GroupDelivery>),
+}
+
+/// Write one length-prefixed frame on a new unidirectional stream
+pub async fn write_frame(connection: &Connection, data: &[u8]) -> Result<(), ChatError> {
+    // Open a unidirectional stream and send
+    let mut send_stream = connection
+        .open_uni()
+        .await
+        .map_err(|e| ChatError::SendFailed(e.to_string()))?;
+
+    // Write length prefix (4 bytes, big endian)
+    let len_bytes = (data.len() as u32).to_be_bytes();
+    send_stream
+        .write_all(&len_bytes)
+        .await
+        .map_err(|e| ChatError::SendFailed(e.to_string()))?;
+
+    // Write the message
+    send_stream
+        .write_all(data)
+        .await
+        .map_err(|e| ChatError::SendFailed(e.to_string()))?;
+
+    // Finish the stream
+    send_stream
+        .finish()
+        .map_err(|e| ChatError::SendFailed(e.to_string()))
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Wait for the next frame on `connection`.
+///
+/// This doesn't touch a `ChatManager`, so a listener can wait here without
+/// holding the manager lock and only take it for `open_frame` and
+/// `process_incoming`.
+pub async fn read_frame(connection: &Connection) -> Result<Vec<u8>, ChatError> {
+    // Accept a unidirectional stream
+    let mut recv_stream = connection
+        .accept_uni()
+        .await
+        .map_err(|e| ChatError::ReceiveFailed(e.to_string()))?;
+
+    // Read length prefix
+    let mut len_bytes = [0u8; 4];
+    recv_stream
+        .read_exact(&mut len_bytes)
+        .await
+        .map_err(|e| ChatError::ReceiveFailed(e.to_string()))?;
+
+    let len = u32::from_be_bytes(len_bytes) as usize;
+
+    if len > MAX_MESSAGE_SIZE {
+        return Err(ChatError::MessageTooLarge);
+    }
+
+    // Read the message
+    let mut data = vec![0u8; len];
+    recv_stream
+        .read_exact(&mut data)
+        .await
+        .map_err(|e| ChatError::ReceiveFailed(e.to_string()))?;
+
+    Ok(data)
 }
 
 /// Chat session with a contact
@@ -94,10 +441,9 @@ pub struct ChatSession {
     /// Contact's Nostr pubkey
     #[allow(dead_code)]
     contact_pubkey: String,
-    /// Message history (in-memory, configurable persistence later)
+    /// Message history, loaded from the message database when persisted
     messages: Vec<ChatMessage>,
     /// Whether to persist messages
-    #[allow(dead_code)]
     persist: bool,
 }
 
@@ -134,6 +480,39 @@ pub struct ChatManager {
     our_pubkey: String,
     /// Default persistence setting
     default_persist: bool,
+    /// Content filters applied to incoming messages, in order
+    filters: Vec<Box<dyn MessageFilter>>,
+    /// Incoming messages held back by a filter
+    quarantine: Vec<QuarantinedMessage>,
+    /// Known message kinds
+    registry: MessageTypeRegistry,
+    /// Extensions that receive messages of their `custom/*` kind
+    plugins: Arc<PluginRegistry>,
+    /// Delivery timelines of outgoing messages
+    deliveries: DeliveryLog,
+    /// Where ratchets, and persisted conversations if message persistence is
+    /// on, are saved
+    store: Option<Mutex<MessageStore>>,
+    /// Chat ratchets keyed by contact pubkey, loaded from the store on use
+    ratchets: HashMap<String, Ratchet>,
+    /// Relationship secrets seeding each contact's ratchet
+    chat_secrets: HashMap<String, Zeroizing<[u8; 32]>>,
+    /// Chat capabilities each contact last advertised
+    peer_capabilities: HashMap<String, u32>,
+    /// Offer hybrid handshakes to contacts who support them
+    hybrid_handshake: bool,
+    /// Hybrid handshakes we offered, by contact pubkey
+    pending_offers: HashMap<String, PendingOffer>,
+    /// Connection each contact proved its key on (see `binding`), by stable ID
+    bound: HashMap<String, usize>,
+    /// Our disappearing message timer for each contact, in seconds
+    disappear_after: HashMap<String, u64>,
+    /// Disappearing message timers contacts last advertised
+    peer_disappear_after: HashMap<String, u64>,
+    /// Member pubkeys of each group we are in, by group ID
+    group_members: HashMap<String, Vec<String>>,
+    /// Per-member delivery of group messages we sent, by message ID
+    group_deliveries: HashMap<String, GroupDelivery>,
 }
 
 impl ChatManager {
@@ -142,14 +521,463 @@ impl ChatManager {
             sessions: HashMap::new(),
             our_pubkey: our_pubkey.to_string(),
             default_persist,
+            filters: Vec::new(),
+            quarantine: Vec::new(),
+            registry: MessageTypeRegistry::default(),
+            plugins: Arc::new(PluginRegistry::default()),
+            deliveries: DeliveryLog::default(),
+            store: None,
+            ratchets: HashMap::new(),
+            chat_secrets: HashMap::new(),
+            peer_capabilities: HashMap::new(),
+            hybrid_handshake: false,
+            pending_offers: HashMap::new(),
+            bound: HashMap::new(),
+            disappear_after: HashMap::new(),
+            peer_disappear_after: HashMap::new(),
+            group_members: HashMap::new(),
+            group_deliveries: HashMap::new(),
         }
     }
 
-    /// Get or create a session for a contact
+    /// Use a new pubkey for outgoing messages after a key rotation
+    pub fn set_our_pubkey(&mut self, our_pubkey: &str) {
+        self.our_pubkey = our_pubkey.to_string();
+    }
+
+    /// Message kinds this client understands
+    pub fn registry(&self) -> &MessageTypeRegistry {
+        &self.registry
+    }
+
+    pub fn registry_mut(&mut self) -> &mut MessageTypeRegistry {
+        &mut self.registry
+    }
+
+    /// Add a content filter to the end of the incoming pipeline
+    pub fn register_filter(&mut self, filter: Box<dyn MessageFilter>) {
+        self.filters.push(filter);
+    }
+
+    /// Replace all content filters
+    pub fn set_filters(&mut self, filters: Vec<Box<dyn MessageFilter>>) {
+        self.filters = filters;
+    }
+
+    /// Route plugin message kinds to `plugins`
+    pub fn set_plugins(&mut self, plugins: Arc<PluginRegistry>) {
+        self.plugins = plugins;
+    }
+
+    /// Save ratchets and persisted conversations to `store`, and load them
+    /// from it
+    pub fn set_store(&mut self, store: MessageStore) {
+        self.store = Some(Mutex::new(store));
+    }
+
+    /// Seal messages to a contact with ratchets seeded from `secret` (see
+    /// `iroh_derive::derive_chat_secret`). A different secret than before,
+    /// after a key rotation, starts the ratchet over.
+    pub fn set_chat_secret(&mut self, contact_pubkey: &str, secret: Zeroizing<[u8; 32]>) {
+        let previous = self
+            .chat_secrets
+            .insert(contact_pubkey.to_string(), secret.clone());
+        if previous.is_some_and(|p| p != secret) {
+            self.ratchets
+                .insert(contact_pubkey.to_string(), Ratchet::default());
+            self.save_ratchet(contact_pubkey);
+        }
+    }
+
+    /// Take the current rosters of the groups we are in. Group messages are
+    /// only accepted from, and sent to, members.
+    pub fn set_groups(&mut self, groups: &[Group]) {
+        self.group_members = groups
+            .iter()
+            .map(|group| {
+                let members = group.members.iter().map(|m| m.pubkey.clone()).collect();
+                (group.id.clone(), members)
+            })
+            .collect();
+    }
+
+    /// Whether `pubkey` is on the roster of `group_id`
+    fn is_group_member(&self, group_id: &str, pubkey: &str) -> bool {
+        self.group_members
+            .get(group_id)
+            .is_some_and(|members| members.iter().any(|m| m == pubkey))
+    }
+
+    /// Offer contacts who support it a hybrid post-quantum handshake before
+    /// the next message to them. Offers from contacts are answered either
+    /// way.
+    pub fn set_hybrid_handshake(&mut self, enabled: bool) {
+        self.hybrid_handshake = enabled;
+    }
+
+    /// Make new messages with a contact disappear `secs` after they are sent,
+    /// or keep them if `None`. The contact learns of it from our next frame.
+    pub fn set_disappear_after(&mut self, contact_pubkey: &str, secs: Option<u64>) {
+        match secs {
+            Some(secs) => self
+                .disappear_after
+                .insert(contact_pubkey.to_string(), secs),
+            None => self.disappear_after.remove(contact_pubkey),
+        };
+    }
+
+    /// Timer applying to the conversation with a contact: the shorter of
+    /// ours and theirs
+    pub fn disappear_after(&self, contact_pubkey: &str) -> Option<u64> {
+        sooner(
+            self.disappear_after.get(contact_pubkey).copied(),
+            self.peer_disappear_after.get(contact_pubkey).copied(),
+        )
+    }
+
+    /// The chat ratchet of a contact, loaded from the store the first time
+    fn ratchet(&mut self, contact_pubkey: &str) -> &mut Ratchet {
+        self.ratchets
+            .entry(contact_pubkey.to_string())
+            .or_insert_with(|| {
+                self.store
+                    .as_ref()
+                    .and_then(|store| store.lock().unwrap().load_ratchet(contact_pubkey).ok())
+                    .flatten()
+                    .unwrap_or_default()
+            })
+    }
+
+    /// Save a contact's ratchet. Best effort, like `record`: without a store
+    /// it lasts until the app closes.
+    fn save_ratchet(&self, contact_pubkey: &str) {
+        if let (Some(store), Some(ratchet)) = (&self.store, self.ratchets.get(contact_pubkey)) {
+            let _ = store.lock().unwrap().save_ratchet(contact_pubkey, ratchet);
+        }
+    }
+
+    /// Frame for `message` to a contact: sealed if they sent us a ratchet
+    /// key, otherwise plain and advertising ours
+    fn encode_frame(
+        &mut self,
+        contact_pubkey: &str,
+        message: &ChatMessage,
+    ) -> Result<Vec<u8>, ChatError> {
+        let Some(secret) = self.chat_secrets.get(contact_pubkey).cloned() else {
+            return message.to_wire();
+        };
+        let disappear_after = self.disappear_after.get(contact_pubkey).copied();
+        let ratchet = self.ratchet(contact_pubkey);
+        let data = if ratchet.can_seal() {
+            let wire =
+                message.to_wire_advertising(None, Some(CHAT_CAPABILITIES), disappear_after)?;
+            let (header, ciphertext) = ratchet.seal(&secret, &wire)?;
+            let frame = SealedFrame {
+                ratchet: header,
+                ciphertext: STANDARD.encode(ciphertext),
+            };
+            serde_json::to_vec(&frame).map_err(|e| ChatError::SendFailed(e.to_string()))?
+        } else {
+            message.to_wire_advertising(
+                Some(ratchet.advertised_key()),
+                Some(CHAT_CAPABILITIES),
+                disappear_after,
+            )?
+        };
+        self.save_ratchet(contact_pubkey);
+        Ok(data)
+    }
+
+    /// A hybrid handshake offer to send a contact before the next message,
+    /// if we offer them, they support them, and none is done or under way
+    fn handshake_offer(&mut self, contact_pubkey: &str) -> Option<Vec<u8>> {
+        let supported = self
+            .peer_capabilities
+            .get(contact_pubkey)
+            .is_some_and(|caps| caps & CHAT_CAP_HYBRID != 0);
+        if !self.hybrid_handshake
+            || !supported
+            || !self.chat_secrets.contains_key(contact_pubkey)
+            || self.pending_offers.contains_key(contact_pubkey)
+            || self.ratchet(contact_pubkey).hybrid_id().is_some()
+        {
+            return None;
+        }
+
+        let (pending, handshake) = hybrid::offer();
+        let frame = serde_json::to_vec(&HandshakeFrame { handshake }).ok()?;
+        self.pending_offers
+            .insert(contact_pubkey.to_string(), pending);
+        Some(frame)
+    }
+
+    /// Answer a handshake frame from a contact
+    fn handle_handshake(
+        &mut self,
+        handshake: Handshake,
+        sender_pubkey: &str,
+    ) -> Result<Option<Vec<u8>>, ChatError> {
+        // The ratchet needs a relationship secret before a hybrid one helps
+        if !self.chat_secrets.contains_key(sender_pubkey) {
+            return Ok(None);
+        }
+
+        match handshake {
+            Handshake::Offer { x25519, ml_kem } => {
+                // When both sides offer at once, the lower offer ID wins, so
+                // both end up with the same secret
+                let theirs = hybrid::offer_id(&x25519, &ml_kem);
+                if self
+                    .pending_offers
+                    .get(sender_pubkey)
+                    .is_some_and(|ours| ours.id() < theirs.as_str())
+                {
+                    return Ok(None);
+                }
+                self.pending_offers.remove(sender_pubkey);
+
+                let (secret, answer) = hybrid::accept(&x25519, &ml_kem)
+                    .map_err(|e| ChatError::InvalidFormat(e.to_string()))?;
+                self.ratchet(sender_pubkey).set_hybrid(secret, false);
+                self.save_ratchet(sender_pubkey);
+                let frame = serde_json::to_vec(&HandshakeFrame { handshake: answer })
+                    .map_err(|e| ChatError::SendFailed(e.to_string()))?;
+                Ok(Some(frame))
+            }
+            Handshake::Accept {
+                offer,
+                x25519,
+                ml_kem,
+            } => {
+                let Some(pending) = self.pending_offers.remove(sender_pubkey) else {
+                    return Ok(None);
+                };
+                let secret = pending
+                    .complete(&offer, &x25519, &ml_kem)
+                    .map_err(|e| ChatError::InvalidFormat(e.to_string()))?;
+                self.ratchet(sender_pubkey).set_hybrid(secret, true);
+                self.save_ratchet(sender_pubkey);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Receipt to send a contact for a message we accepted from them, if
+    /// they take receipts
+    pub fn ack_frame(&self, contact_pubkey: &str, message: &ChatMessage) -> Option<Vec<u8>> {
+        if message.group_id.is_some() {
+            return self.group_receipt_frame(contact_pubkey, message, ReceiptKind::Delivered);
+        }
+        let supported = self
+            .peer_capabilities
+            .get(contact_pubkey)
+            .is_some_and(|caps| caps & CHAT_CAP_ACK != 0);
+        if !supported {
+            return None;
+        }
+        serde_json::to_vec(&AckFrame {
+            ack: message.id.clone(),
+            group: None,
+            member: None,
+            receipt: None,
+        })
+        .ok()
+    }
+
+    /// Read receipt to send the member who sent us a group message, if they
+    /// take group receipts
+    pub fn read_receipt_frame(&self, message: &ChatMessage) -> Option<Vec<u8>> {
+        if message.is_outgoing || message.group_id.is_none() {
+            return None;
+        }
+        self.group_receipt_frame(&message.sender_pubkey, message, ReceiptKind::Read)
+    }
+
+    /// Receipt in our name for a group message from a member
+    fn group_receipt_frame(
+        &self,
+        contact_pubkey: &str,
+        message: &ChatMessage,
+        receipt: ReceiptKind,
+    ) -> Option<Vec<u8>> {
+        let supported = self
+            .peer_capabilities
+            .get(contact_pubkey)
+            .is_some_and(|caps| caps & CHAT_CAP_GROUP_RECEIPTS != 0);
+        if !supported {
+            return None;
+        }
+        serde_json::to_vec(&AckFrame {
+            ack: message.id.clone(),
+            group: message.group_id.clone(),
+            member: Some(self.our_pubkey.clone()),
+            receipt: Some(receipt),
+        })
+        .ok()
+    }
+
+    /// Count a member's receipt for a group message we sent, returning the
+    /// receipts so far. It has to come from the member it names, for the
+    /// group the message went to. Once every member has it, the message
+    /// itself is marked delivered.
+    fn handle_group_receipt(
+        &mut self,
+        frame: AckFrame,
+        sender_pubkey: &str,
+    ) -> Option<GroupDelivery> {
+        if frame.member.as_deref() != Some(sender_pubkey) {
+            return None;
+        }
+        let delivery = self
+            .group_deliveries
+            .get_mut(&frame.ack)
+            .filter(|delivery| frame.group.as_ref() == Some(&delivery.group_id))?;
+        let receipt = frame.receipt.unwrap_or(ReceiptKind::Delivered);
+        if !delivery.apply(sender_pubkey, receipt, now_secs()) {
+            return None;
+        }
+        let delivery = delivery.clone();
+
+        if delivery.fully_delivered() {
+            let message = self
+                .get_or_create_session(&delivery.group_id)
+                .messages
+                .iter_mut()
+                .find(|m| m.id == delivery.message_id && m.is_outgoing);
+            if let Some(message) = message {
+                message.status = Some(MessageStatus::Delivered);
+                let message = message.clone();
+                self.save_changed(&delivery.group_id, &message);
+            }
+        }
+        Some(delivery)
+    }
+
+    /// Mark a message we sent a contact delivered once they acknowledge it,
+    /// returning it with its new status
+    fn handle_ack(&mut self, message_id: &str, sender_pubkey: &str) -> Option<ChatMessage> {
+        if let Some(info) = self
+            .deliveries
+            .get_mut(message_id)
+            .filter(|info| info.contact_pubkey == sender_pubkey)
+        {
+            info.mark_delivered(now_secs());
+        }
+
+        let session = self.get_or_create_session(sender_pubkey);
+        let persist = session.persist;
+        let message = session
+            .messages
+            .iter_mut()
+            .find(|m| m.id == message_id && m.is_outgoing)?;
+        message.status = Some(MessageStatus::Delivered);
+        let message = message.clone();
+        if let Some(store) = self.store.as_ref().filter(|_| persist) {
+            let _ = store.lock().unwrap().insert(sender_pubkey, &message);
+        }
+        Some(message)
+    }
+
+    /// Note what a contact's frame advertised
+    fn learn_advertised(&mut self, wire: &WireMessage, sender_pubkey: &str) {
+        if let Some(capabilities) = wire.capabilities {
+            self.peer_capabilities
+                .insert(sender_pubkey.to_string(), capabilities);
+            if capabilities & CHAT_CAP_DISAPPEARING != 0 {
+                match wire.disappear_after {
+                    Some(secs) => self
+                        .peer_disappear_after
+                        .insert(sender_pubkey.to_string(), secs),
+                    None => self.peer_disappear_after.remove(sender_pubkey),
+                };
+            }
+        }
+        let seeded = self.chat_secrets.contains_key(sender_pubkey);
+        if let Some(key) = wire.ratchet_key.as_deref().filter(|_| seeded) {
+            self.ratchet(sender_pubkey).learn(key);
+            self.save_ratchet(sender_pubkey);
+        }
+    }
+
+    /// Parse a frame from a contact, opening it with their ratchet if it is
+    /// sealed. Once a sealed message from them has opened, plain messages
+    /// are refused, so nobody can quietly fall back to plaintext.
+    pub fn open_frame(&mut self, data: &[u8], sender_pubkey: &str) -> Result<Incoming, ChatError> {
+        if let Ok(frame) = serde_json::from_slice::<AckFrame>(data) {
+            if frame.group.is_some() {
+                return Ok(Incoming::GroupReceipt(
+                    self.handle_group_receipt(frame, sender_pubkey),
+                ));
+            }
+            return Ok(Incoming::Ack(self.handle_ack(&frame.ack, sender_pubkey)));
+        }
+        if let Ok(frame) = serde_json::from_slice::<HandshakeFrame>(data) {
+            return Ok(Incoming::Handshake(
+                self.handle_handshake(frame.handshake, sender_pubkey)?,
+            ));
+        }
+        let Ok(frame) = serde_json::from_slice::<SealedFrame>(data) else {
+            if self.chat_secrets.contains_key(sender_pubkey)
+                && self.ratchet(sender_pubkey).has_opened()
+            {
+                return Err(ChatError::Unsealed);
+            }
+            let wire = WireMessage::parse(data)?;
+            self.learn_advertised(&wire, sender_pubkey);
+            return Ok(Incoming::Message(ChatMessage::from_wire_message(
+                wire,
+                sender_pubkey,
+            )));
+        };
+
+        let secret = self
+            .chat_secrets
+            .get(sender_pubkey)
+            .cloned()
+            .ok_or(RatchetError::UnknownKey)?;
+        let ciphertext = STANDARD
+            .decode(&frame.ciphertext)
+            .map_err(|e| ChatError::InvalidFormat(e.to_string()))?;
+        let plaintext = self
+            .ratchet(sender_pubkey)
+            .open(&secret, &frame.ratchet, &ciphertext)?;
+        self.save_ratchet(sender_pubkey);
+
+        let wire = WireMessage::parse(&plaintext)?;
+        self.learn_advertised(&wire, sender_pubkey);
+        Ok(Incoming::Message(ChatMessage::from_wire_message(
+            wire,
+            sender_pubkey,
+        )))
+    }
+
+    /// Get or create a session for a contact, loading its history from the
+    /// message database the first time (if history is kept)
     pub fn get_or_create_session(&mut self, contact_pubkey: &str) -> &mut ChatSession {
         self.sessions
             .entry(contact_pubkey.to_string())
-            .or_insert_with(|| ChatSession::new(contact_pubkey, self.default_persist))
+            .or_insert_with(|| {
+                let mut session = ChatSession::new(contact_pubkey, self.default_persist);
+                if let Some(store) = self.store.as_ref().filter(|_| session.persist) {
+                    let store = store.lock().unwrap();
+                    session.messages = store.conversation(contact_pubkey).unwrap_or_default();
+                }
+                session
+            })
+    }
+
+    /// Add a message to the conversation with `contact_pubkey`, saving it if
+    /// that conversation is persisted. Saving is best effort: the message is
+    /// already sent or received either way.
+    fn record(&mut self, contact_pubkey: &str, message: &ChatMessage) {
+        let session = self.get_or_create_session(contact_pubkey);
+        session.add_message(message.clone());
+        if !session.persist {
+            return;
+        }
+        if let Some(store) = &self.store {
+            let _ = store.lock().unwrap().insert(contact_pubkey, message);
+        }
     }
 
     /// Get session if it exists
@@ -164,102 +992,621 @@ impl ChatManager {
         contact_pubkey: &str,
         content: &str,
     ) -> Result<ChatMessage, ChatError> {
-        // Create the message
-        let message = ChatMessage::new_outgoing(content, &self.our_pubkey);
+        let message = self.compose(content);
 
-        // Serialize to wire format
-        let data = message.to_wire()?;
+        self.send(connection, contact_pubkey, message).await
+    }
+
+    /// A new text message from us, to send now or queue (see `outbox`)
+    pub fn compose(&self, content: &str) -> ChatMessage {
+        ChatMessage::new_outgoing(content, &self.our_pubkey)
+    }
 
+    /// A new text message from us to a group
+    pub fn compose_group(&self, group_id: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            group_id: Some(group_id.to_string()),
+            ..self.compose(content)
+        }
+    }
+
+    /// Send a text message to every other member of a group, each over
+    /// their own connection in `connections` (by pubkey), and track its
+    /// delivery per member. Members we can't reach are marked failed; it
+    /// only fails as a whole if it reached none of them.
+    pub async fn send_group_message(
+        &mut self,
+        connections: &HashMap<String, Connection>,
+        group_id: &str,
+        content: &str,
+    ) -> Result<ChatMessage, ChatError> {
+        if !self.is_group_member(group_id, &self.our_pubkey) {
+            return Err(ChatError::NotGroupMember);
+        }
+        let members: Vec<String> = self.group_members[group_id]
+            .iter()
+            .filter(|m| **m != self.our_pubkey)
+            .cloned()
+            .collect();
+
+        let mut message = self.compose_group(group_id, content);
+        let mut delivery = GroupDelivery::new(&message.id, group_id, &members, message.timestamp);
+        let mut reached = 0;
+        for member in &members {
+            let result = match connections.get(member) {
+                Some(connection) => self.send_group_frame(connection, member, &message).await,
+                None => Err(ChatError::NotConnected),
+            };
+            let Some(info) = delivery.member_mut(member) else {
+                continue;
+            };
+            match result {
+                Ok(()) => {
+                    info.mark_sent(TRANSPORT_IROH, now_secs());
+                    reached += 1;
+                }
+                Err(e) => info.mark_failed(&e.to_string()),
+            }
+        }
+        if reached == 0 && !members.is_empty() {
+            return Err(ChatError::NotConnected);
+        }
+
+        message.status = Some(MessageStatus::Sent);
+        self.group_deliveries.insert(message.id.clone(), delivery);
+        self.record(group_id, &message);
+
+        Ok(message)
+    }
+
+    /// Write a group message to one member, sealed for them
+    async fn send_group_frame(
+        &mut self,
+        connection: &Connection,
+        member_pubkey: &str,
+        message: &ChatMessage,
+    ) -> Result<(), ChatError> {
+        let data = self.encode_frame(member_pubkey, message)?;
         if data.len() > MAX_MESSAGE_SIZE {
             return Err(ChatError::MessageTooLarge);
         }
+        write_frame(connection, &data).await
+    }
 
-        // Open a unidirectional stream and send
-        let mut send_stream = connection
-            .open_uni()
-            .await
-            .map_err(|e| ChatError::SendFailed(e.to_string()))?;
+    /// Send a message composed earlier, e.g. one queued while the contact
+    /// was unreachable. It keeps its ID and timestamp.
+    pub async fn send_composed(
+        &mut self,
+        connection: &Connection,
+        contact_pubkey: &str,
+        message: ChatMessage,
+    ) -> Result<ChatMessage, ChatError> {
+        self.send(connection, contact_pubkey, message).await
+    }
 
-        // Write length prefix (4 bytes, big endian)
-        let len_bytes = (data.len() as u32).to_be_bytes();
-        send_stream
-            .write_all(&len_bytes)
-            .await
-            .map_err(|e| ChatError::SendFailed(e.to_string()))?;
+    /// Send a typed payload, with its fallback text as the content
+    pub async fn send_typed_message(
+        &mut self,
+        connection: &Connection,
+        contact_pubkey: &str,
+        kind: &str,
+        payload: Value,
+    ) -> Result<ChatMessage, ChatError> {
+        let fallback = self
+            .registry
+            .prepare(kind, &payload)
+            .map_err(|e| ChatError::InvalidPayload(e.to_string()))?;
+        let message = ChatMessage::new_outgoing_typed(kind, payload, &fallback, &self.our_pubkey);
+
+        self.send(connection, contact_pubkey, message).await
+    }
+
+    /// Edit a text message we sent a contact, within `EDIT_WINDOW_SECS` of
+    /// sending it. Returns the message with its new content.
+    pub async fn edit_message(
+        &mut self,
+        connection: &Connection,
+        contact_pubkey: &str,
+        message_id: &str,
+        content: &str,
+    ) -> Result<ChatMessage, ChatError> {
+        // Refuse before sending anything the contact would refuse too
+        self.editable(contact_pubkey, message_id, true, now_secs())?;
 
-        // Write the message
-        send_stream
-            .write_all(&data)
+        let edit = Edit {
+            message_id: message_id.to_string(),
+            content: content.to_string(),
+        };
+        let payload =
+            serde_json::to_value(&edit).map_err(|e| ChatError::InvalidPayload(e.to_string()))?;
+        self.send_typed_message(connection, contact_pubkey, KIND_EDIT, payload)
             .await
-            .map_err(|e| ChatError::SendFailed(e.to_string()))?;
+    }
+
+    /// The message an edit made at `at` may replace: a text message from the
+    /// same side of the conversation, sent no more than `EDIT_WINDOW_SECS`
+    /// before
+    fn editable(
+        &mut self,
+        contact_pubkey: &str,
+        message_id: &str,
+        outgoing: bool,
+        at: u64,
+    ) -> Result<&mut ChatMessage, ChatError> {
+        let message = self
+            .get_or_create_session(contact_pubkey)
+            .messages
+            .iter_mut()
+            .find(|m| m.id == message_id && m.is_outgoing == outgoing)
+            .ok_or(ChatError::EditRejected("no such message"))?;
+        if message.kind != KIND_TEXT || message.deleted_at.is_some() {
+            return Err(ChatError::EditRejected("only text messages can be edited"));
+        }
+        if at.saturating_sub(message.timestamp) > EDIT_WINDOW_SECS {
+            return Err(ChatError::EditRejected("too late to edit"));
+        }
+        Ok(message)
+    }
+
+    /// Replace the content of the message an edit names, keeping the old
+    /// version in its history. The edit itself isn't stored.
+    fn apply_edit(
+        &mut self,
+        contact_pubkey: &str,
+        edit: &ChatMessage,
+    ) -> Result<ChatMessage, ChatError> {
+        let Edit {
+            message_id,
+            content,
+        } = edit
+            .payload
+            .clone()
+            .and_then(|payload| serde_json::from_value(payload).ok())
+            .ok_or(ChatError::EditRejected("malformed edit"))?;
+
+        let message = self.editable(
+            contact_pubkey,
+            &message_id,
+            edit.is_outgoing,
+            edit.timestamp,
+        )?;
+        message.edit_history.push(PreviousVersion {
+            timestamp: message.edited_at.unwrap_or(message.timestamp),
+            content: std::mem::replace(&mut message.content, content),
+        });
+        message.edited_at = Some(edit.timestamp);
+        let message = message.clone();
+        self.save_changed(contact_pubkey, &message);
+        Ok(message)
+    }
 
-        // Finish the stream
-        send_stream
-            .finish()
-            .map_err(|e| ChatError::SendFailed(e.to_string()))?;
+    /// Save a message changed in place, if its conversation is persisted
+    fn save_changed(&self, contact_pubkey: &str, message: &ChatMessage) {
+        let persist = self
+            .get_session(contact_pubkey)
+            .is_some_and(|session| session.persist);
+        if let Some(store) = self.store.as_ref().filter(|_| persist) {
+            let _ = store.lock().unwrap().insert(contact_pubkey, message);
+        }
+    }
 
-        // Add to session
+    /// Contact whose conversation holds `message_id`
+    pub fn contact_of(&self, message_id: &str) -> Option<String> {
+        self.sessions
+            .iter()
+            .find(|(_, session)| session.messages.iter().any(|m| m.id == message_id))
+            .map(|(contact_pubkey, _)| contact_pubkey.clone())
+            .or_else(|| {
+                let store = self.store.as_ref()?.lock().unwrap();
+                store.contact_of(message_id).ok().flatten()
+            })
+    }
+
+    /// Remove a message from its conversation on this device only
+    pub fn delete_message(
+        &mut self,
+        contact_pubkey: &str,
+        message_id: &str,
+    ) -> Result<(), ChatError> {
         let session = self.get_or_create_session(contact_pubkey);
-        session.add_message(message.clone());
+        let count = session.messages.len();
+        session.messages.retain(|m| m.id != message_id);
+        let removed = session.messages.len() < count;
+
+        // Also from the database, even if the conversation is no longer kept
+        let stored = self
+            .store
+            .as_ref()
+            .is_some_and(|store| store.lock().unwrap().delete(message_id).unwrap_or(false));
+        if !removed && !stored {
+            return Err(ChatError::DeleteRejected("no such message"));
+        }
+        Ok(())
+    }
+
+    /// Delete a message we sent a contact on both sides. `tombstone`, signed
+    /// by the caller, replaces the contact's copy with a placeholder.
+    pub async fn delete_for_everyone(
+        &mut self,
+        connection: &Connection,
+        contact_pubkey: &str,
+        tombstone: Tombstone,
+    ) -> Result<(), ChatError> {
+        let ours = self
+            .get_or_create_session(contact_pubkey)
+            .messages
+            .iter()
+            .any(|m| m.id == tombstone.message_id && m.is_outgoing);
+        if !ours {
+            return Err(ChatError::DeleteRejected(
+                "only messages we sent can be deleted for everyone",
+            ));
+        }
+
+        let message_id = tombstone.message_id.clone();
+        let payload = serde_json::to_value(&tombstone)
+            .map_err(|e| ChatError::InvalidPayload(e.to_string()))?;
+        self.send_typed_message(connection, contact_pubkey, KIND_DELETE, payload)
+            .await?;
+        self.delete_message(contact_pubkey, &message_id)
+    }
+
+    /// Replace the contact's message a tombstone names with a placeholder.
+    /// The tombstone itself isn't stored.
+    fn apply_tombstone(
+        &mut self,
+        contact_pubkey: &str,
+        message: &ChatMessage,
+    ) -> Result<ChatMessage, ChatError> {
+        let tombstone: Tombstone = message
+            .payload
+            .clone()
+            .and_then(|payload| serde_json::from_value(payload).ok())
+            .ok_or(ChatError::DeleteRejected("malformed tombstone"))?;
+        tombstone
+            .verify(contact_pubkey)
+            .map_err(|_| ChatError::DeleteRejected("tombstone not signed by the sender"))?;
+
+        let target = self
+            .get_or_create_session(contact_pubkey)
+            .messages
+            .iter_mut()
+            .find(|m| m.id == tombstone.message_id && !m.is_outgoing)
+            .ok_or(ChatError::DeleteRejected("no such message"))?;
+        target.content = DELETED_PLACEHOLDER.to_string();
+        target.kind = default_kind();
+        target.payload = None;
+        target.flags.clear();
+        target.edited_at = None;
+        target.edit_history.clear();
+        target.deleted_at = Some(tombstone.deleted_at);
+        let target = target.clone();
+        self.save_changed(contact_pubkey, &target);
+        Ok(target)
+    }
+
+    async fn send(
+        &mut self,
+        connection: &Connection,
+        contact_pubkey: &str,
+        mut message: ChatMessage,
+    ) -> Result<ChatMessage, ChatError> {
+        // A failed offer is dropped, so the next message offers again
+        if let Some(offer) = self.handshake_offer(contact_pubkey) {
+            if write_frame(connection, &offer).await.is_err() {
+                self.pending_offers.remove(contact_pubkey);
+            }
+        }
+
+        message.expires_at = expiry(message.timestamp, self.disappear_after(contact_pubkey));
+
+        // Serialize to wire format, sealed when the contact supports it
+        let data = self.encode_frame(contact_pubkey, &message)?;
+
+        if data.len() > MAX_MESSAGE_SIZE {
+            return Err(ChatError::MessageTooLarge);
+        }
+
+        let mut delivery = DeliveryInfo::new(&message.id, contact_pubkey, message.timestamp);
+        let result = write_frame(connection, &data).await;
+        match &result {
+            Ok(()) => delivery.mark_sent(TRANSPORT_IROH, now_secs()),
+            Err(e) => delivery.mark_failed(&e.to_string()),
+        }
+        self.deliveries.insert(delivery);
+        result?;
+
+        message.status = Some(MessageStatus::Sent);
+        // Edits and tombstones change an earlier message instead of being stored
+        match message.kind.as_str() {
+            KIND_EDIT => return self.apply_edit(contact_pubkey, &message),
+            KIND_DELETE => return Ok(message),
+            _ => {}
+        }
+        self.record(contact_pubkey, &message);
+        self.plugins.route(&message);
 
         Ok(message)
     }
 
-    /// Receive a message from a unidirectional stream
+    /// Delivery timeline of an outgoing message
+    pub fn delivery(&self, message_id: &str) -> Option<&DeliveryInfo> {
+        self.deliveries.get(message_id)
+    }
+
+    /// Note that a sent message waited since `queued_at` and took `retries`
+    /// failed attempts first (e.g. a scheduled message)
+    pub fn record_retries(&mut self, message_id: &str, queued_at: u64, retries: u32) {
+        if let Some(info) = self.deliveries.get_mut(message_id) {
+            info.queued_at = info.queued_at.min(queued_at);
+            info.retries = retries;
+        }
+    }
+
+    /// A message from any conversation, with its delivery timeline
+    pub fn message_info(&self, message_id: &str) -> Option<MessageInfo> {
+        let loaded = self
+            .sessions
+            .values()
+            .flat_map(|s| s.get_messages())
+            .find(|m| m.id == message_id)
+            .cloned();
+        let message = match (loaded, self.history()) {
+            (Some(message), _) => message,
+            (None, Some(store)) => store.lock().unwrap().get(message_id).ok()??,
+            (None, None) => return None,
+        };
+
+        Some(MessageInfo {
+            delivery: self.deliveries.get(message_id).cloned(),
+            group_delivery: self.group_deliveries.get(message_id).cloned(),
+            message,
+        })
+    }
+
+    /// Record that the peer on `connection` proved it holds `contact_pubkey`
+    pub fn mark_bound(&mut self, contact_pubkey: &str, connection: &Connection) {
+        self.bound
+            .insert(contact_pubkey.to_string(), connection.stable_id());
+    }
+
+    /// Whether the peer on `connection` proved it holds `contact_pubkey`
+    pub fn is_bound(&self, contact_pubkey: &str, connection: &Connection) -> bool {
+        self.bound.get(contact_pubkey) == Some(&connection.stable_id())
+    }
+
+    /// Receive a message from a unidirectional stream.
+    ///
+    /// The peer must have proven it holds `sender_pubkey` on this connection.
+    /// Messages rejected by `policy` are read off the stream but never stored.
     pub async fn receive_message(
         &mut self,
         connection: &Connection,
         sender_pubkey: &str,
+        policy: &InboundPolicy,
     ) -> Result<ChatMessage, ChatError> {
-        // Accept a unidirectional stream
-        let mut recv_stream = connection
-            .accept_uni()
-            .await
-            .map_err(|e| ChatError::ReceiveFailed(e.to_string()))?;
+        if !self.is_bound(sender_pubkey, connection) {
+            return Err(ChatError::Unbound);
+        }
+        loop {
+            let data = read_frame(connection).await?;
+            match self.open_frame(&data, sender_pubkey)? {
+                Incoming::Message(message) => {
+                    let message = self.process_incoming(message, policy)?;
+                    if let Some(ack) = self.ack_frame(sender_pubkey, &message) {
+                        let _ = write_frame(connection, &ack).await;
+                    }
+                    return Ok(message);
+                }
+                Incoming::Handshake(Some(answer)) => write_frame(connection, &answer).await?,
+                Incoming::Handshake(None) | Incoming::Ack(_) | Incoming::GroupReceipt(_) => {}
+            }
+        }
+    }
 
-        // Read length prefix
-        let mut len_bytes = [0u8; 4];
-        recv_stream
-            .read_exact(&mut len_bytes)
-            .await
-            .map_err(|e| ChatError::ReceiveFailed(e.to_string()))?;
+    /// Run a parsed incoming message through the policy and content filters,
+    /// storing it unless it was dropped or quarantined
+    pub fn process_incoming(
+        &mut self,
+        mut message: ChatMessage,
+        policy: &InboundPolicy,
+    ) -> Result<ChatMessage, ChatError> {
+        policy
+            .check(&message.sender_pubkey, &message.content)
+            .map_err(ChatError::Filtered)?;
+        if let Some(group_id) = &message.group_id {
+            if !self.is_group_member(group_id, &message.sender_pubkey) {
+                return Err(ChatError::NotGroupMember);
+            }
+        }
 
-        let len = u32::from_be_bytes(len_bytes) as usize;
+        // Our own timer runs from when it arrived, whatever the sender says
+        let timer = self.disappear_after(&message.sender_pubkey);
+        message.expires_at = sooner(message.expires_at, expiry(now_secs(), timer));
 
-        if len > MAX_MESSAGE_SIZE {
-            return Err(ChatError::MessageTooLarge);
+        // A malformed payload of a kind we know is shown as plain text
+        if !self
+            .registry
+            .accepts(&message.kind, message.payload.as_ref())
+        {
+            message.kind = default_kind();
+            message.payload = None;
         }
 
-        // Read the message
-        let mut data = vec![0u8; len];
-        recv_stream
-            .read_exact(&mut data)
-            .await
-            .map_err(|e| ChatError::ReceiveFailed(e.to_string()))?;
-
-        // Parse the message
-        let message = ChatMessage::from_wire(&data, sender_pubkey)?;
+        for filter in &self.filters {
+            match filter.check(&message) {
+                FilterVerdict::Accept => {}
+                FilterVerdict::Mark(label) => message.flags.push(label),
+                FilterVerdict::Quarantine(reason) => {
+                    self.quarantine.push(QuarantinedMessage {
+                        message,
+                        reason: reason.to_string(),
+                    });
+                    return Err(ChatError::Quarantined(reason));
+                }
+                FilterVerdict::Drop(reason) => return Err(ChatError::Filtered(reason)),
+            }
+        }
 
-        // Add to session
-        let session = self.get_or_create_session(sender_pubkey);
-        session.add_message(message.clone());
+        // Edits and tombstones change the message they name instead of being
+        // stored
+        let sender_pubkey = message.sender_pubkey.clone();
+        match message.kind.as_str() {
+            KIND_EDIT => return self.apply_edit(&sender_pubkey, &message),
+            KIND_DELETE => return self.apply_tombstone(&sender_pubkey, &message),
+            _ => {}
+        }
+        self.record(message.conversation(), &message);
+        self.plugins.route(&message);
 
         Ok(message)
     }
 
-    /// Get messages for a contact
-    pub fn get_messages(&self, contact_pubkey: &str) -> Vec<ChatMessage> {
-        self.get_session(contact_pubkey)
-            .map(|s| s.get_messages().to_vec())
+    /// Remove messages that expired at or before `now` from every
+    /// conversation, quarantine and the message database
+    pub fn purge_expired(&mut self, now: u64) -> Vec<ExpiredMessage> {
+        let expired = |m: &ChatMessage| m.expires_at.is_some_and(|at| at <= now);
+        let mut purged = Vec::new();
+        for (contact_pubkey, session) in &mut self.sessions {
+            for message in session.messages.iter().filter(|m| expired(m)) {
+                purged.push(ExpiredMessage {
+                    contact_pubkey: contact_pubkey.clone(),
+                    message_id: message.id.clone(),
+                });
+            }
+            session.messages.retain(|m| !expired(m));
+        }
+        self.quarantine.retain(|q| !expired(&q.message));
+
+        // Also from conversations not loaded yet, or no longer kept
+        let stored = self
+            .store
+            .as_ref()
+            .and_then(|store| store.lock().unwrap().delete_expired(now).ok())
+            .unwrap_or_default();
+        for (contact_pubkey, message_id) in stored {
+            if !purged.iter().any(|p| p.message_id == message_id) {
+                purged.push(ExpiredMessage {
+                    contact_pubkey,
+                    message_id,
+                });
+            }
+        }
+        for expired in &purged {
+            self.deliveries.remove(&expired.message_id);
+            self.group_deliveries.remove(&expired.message_id);
+        }
+        purged
+    }
+
+    /// Messages currently held in quarantine
+    pub fn quarantined(&self) -> &[QuarantinedMessage] {
+        &self.quarantine
+    }
+
+    /// Move a quarantined message into its conversation
+    pub fn release_quarantined(&mut self, message_id: &str) -> Option<ChatMessage> {
+        let index = self
+            .quarantine
+            .iter()
+            .position(|q| q.message.id == message_id)?;
+        let message = self.quarantine.remove(index).message;
+
+        self.record(message.conversation(), &message);
+        let session = self.get_or_create_session(message.conversation());
+        session.messages.sort_by_key(|m| m.timestamp);
+        self.plugins.route(&message);
+
+        Some(message)
+    }
+
+    /// Delete a quarantined message
+    pub fn discard_quarantined(&mut self, message_id: &str) -> bool {
+        let before = self.quarantine.len();
+        self.quarantine.retain(|q| q.message.id != message_id);
+        self.quarantine.len() != before
+    }
+
+    /// Get messages for a contact, loading them from the message database
+    /// on first use
+    pub fn get_messages(&mut self, contact_pubkey: &str) -> Vec<ChatMessage> {
+        self.get_or_create_session(contact_pubkey)
+            .get_messages()
+            .to_vec()
+    }
+
+    /// The latest message with a contact and how many there are, from the
+    /// message database for a conversation not loaded yet
+    pub fn summary(&self, contact_pubkey: &str) -> (Option<ChatMessage>, usize) {
+        if let Some(session) = self.get_session(contact_pubkey) {
+            return (session.messages.last().cloned(), session.messages.len());
+        }
+        self.history()
+            .and_then(|store| store.lock().unwrap().summary(contact_pubkey).ok())
             .unwrap_or_default()
     }
 
-    /// Clear all sessions (for cleanup)
+    /// The message database, if history is kept in it
+    fn history(&self) -> Option<&Mutex<MessageStore>> {
+        self.store.as_ref().filter(|_| self.default_persist)
+    }
+
+    /// Move the history of one contact's session into another's
+    pub fn merge_sessions(&mut self, from_pubkey: &str, into_pubkey: &str) {
+        if from_pubkey == into_pubkey {
+            return;
+        }
+
+        // Both histories are loaded before the stored one moves over
+        self.get_or_create_session(from_pubkey);
+        self.get_or_create_session(into_pubkey);
+        if let Some(store) = self.history() {
+            let _ = store.lock().unwrap().reassign(from_pubkey, into_pubkey);
+        }
+
+        if let Some(from) = self.sessions.remove(from_pubkey) {
+            let into = self.get_or_create_session(into_pubkey);
+            into.messages.extend(from.messages);
+            into.messages.sort_by_key(|m| m.timestamp);
+        }
+    }
+
+    /// Clear all sessions (for cleanup); persisted history stays saved
     pub fn clear_all(&mut self) {
         self.sessions.clear();
+        self.deliveries.clear();
+        self.group_deliveries.clear();
     }
 }
 
+/// Summaries for each contact, most recently active first. Contacts without
+/// history (or with no chat manager running) have no messages.
+pub fn conversation_summaries(
+    manager: Option<&ChatManager>,
+    contacts: Vec<NamedContact>,
+) -> Vec<ConversationSummary> {
+    let mut summaries: Vec<ConversationSummary> = contacts
+        .into_iter()
+        .map(|named| {
+            let (last_message, message_count) = manager
+                .map(|m| m.summary(&named.contact.nostr_pubkey))
+                .unwrap_or_default();
+
+            ConversationSummary {
+                contact_id: named.contact.id,
+                contact_pubkey: named.contact.nostr_pubkey,
+                display_name: named.display_name,
+                last_message,
+                message_count,
+            }
+        })
+        .collect();
+
+    summaries
+        .sort_by_key(|s| std::cmp::Reverse(s.last_message.as_ref().map_or(0, |m| m.timestamp)));
+    summaries
+}
+
 /// Thread-safe wrapper for ChatManager
 pub type SharedChatManager = Arc<RwLock<Option<ChatManager>>>;
 
@@ -271,6 +1618,8 @@ pub fn create_shared_manager() -> SharedChatManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::delivery::DeliveryStatus;
+    use nostr::Keys;
 
     #[test]
     fn test_create_outgoing_message() {
@@ -296,6 +1645,431 @@ mod tests {
         assert!(!restored.is_outgoing);
     }
 
+    #[test]
+    fn test_typed_wire_compatibility() {
+        // A message from a client that predates typed payloads
+        let legacy = br#"{"id":"1","content":"hi","timestamp":5}"#;
+        let msg = ChatMessage::from_wire(legacy, "sender").unwrap();
+        assert_eq!(msg.kind, KIND_TEXT);
+        assert!(msg.payload.is_none());
+
+        // A typed message round-trips with its fallback content
+        let typed = ChatMessage::new_outgoing_typed(
+            "custom/sticker",
+            serde_json::json!({ "id": 7 }),
+            "Sticker",
+            "sender",
+        );
+        let restored = ChatMessage::from_wire(&typed.to_wire().unwrap(), "sender").unwrap();
+        assert_eq!(restored.kind, "custom/sticker");
+        assert_eq!(restored.content, "Sticker");
+        assert_eq!(restored.payload, typed.payload);
+    }
+
+    /// The message a frame held
+    fn opened(incoming: Result<Incoming, ChatError>) -> ChatMessage {
+        match incoming.unwrap() {
+            Incoming::Message(message) => message,
+            Incoming::Handshake(_) | Incoming::Ack(_) | Incoming::GroupReceipt(_) => {
+                panic!("expected a message")
+            }
+        }
+    }
+
+    /// Managers for Alice and Bob with a shared relationship secret
+    fn chat_pair() -> (ChatManager, ChatManager) {
+        let secret = Zeroizing::new([3u8; 32]);
+        let mut alice = ChatManager::new("alice", false);
+        let mut bob = ChatManager::new("bob", false);
+        alice.set_chat_secret("bob", secret.clone());
+        bob.set_chat_secret("alice", secret);
+        (alice, bob)
+    }
+
+    #[test]
+    fn test_frames_are_sealed_once_keys_are_known() {
+        let (mut alice, mut bob) = chat_pair();
+
+        // The first frame is plain, advertising Alice's ratchet key
+        let hello = ChatMessage::new_outgoing("Hello", "alice");
+        let frame = alice.encode_frame("bob", &hello).unwrap();
+        assert!(serde_json::from_slice::<SealedFrame>(&frame).is_err());
+        assert_eq!(opened(bob.open_frame(&frame, "alice")).content, "Hello");
+
+        // From then on both directions are sealed
+        let reply = ChatMessage::new_outgoing("Hi Alice", "bob");
+        let frame = bob.encode_frame("alice", &reply).unwrap();
+        assert!(serde_json::from_slice::<SealedFrame>(&frame).is_ok());
+        assert_eq!(opened(alice.open_frame(&frame, "bob")).id, reply.id);
+
+        let sealed = alice.encode_frame("bob", &hello).unwrap();
+        assert!(!String::from_utf8_lossy(&sealed).contains("Hello"));
+        assert_eq!(opened(bob.open_frame(&sealed, "alice")).id, hello.id);
+        // A replayed frame doesn't open twice
+        assert!(matches!(
+            bob.open_frame(&sealed, "alice"),
+            Err(ChatError::Sealed(RatchetError::Replayed))
+        ));
+    }
+
+    #[test]
+    fn test_plain_frame_after_sealed_is_rejected() {
+        let (mut alice, mut bob) = chat_pair();
+        let hello = ChatMessage::new_outgoing("Hello", "alice");
+        let frame = alice.encode_frame("bob", &hello).unwrap();
+        opened(bob.open_frame(&frame, "alice"));
+        let reply = ChatMessage::new_outgoing("Hi Alice", "bob");
+        let frame = bob.encode_frame("alice", &reply).unwrap();
+        opened(alice.open_frame(&frame, "bob"));
+
+        // Plain frames are still fine until a sealed one has opened
+        let early = ChatMessage::new_outgoing("Still plain", "alice");
+        opened(bob.open_frame(&early.to_wire().unwrap(), "alice"));
+
+        let sealed = alice.encode_frame("bob", &hello).unwrap();
+        opened(bob.open_frame(&sealed, "alice"));
+
+        // After that, falling back to plaintext is refused
+        let downgraded = ChatMessage::new_outgoing("Plain again", "alice");
+        assert!(matches!(
+            bob.open_frame(&downgraded.to_wire().unwrap(), "alice"),
+            Err(ChatError::Unsealed)
+        ));
+    }
+
+    #[test]
+    fn test_acks_mark_messages_delivered() {
+        let (mut alice, mut bob) = chat_pair();
+        let mut hello = ChatMessage::new_outgoing("Hello", "alice");
+        assert_eq!(hello.status, Some(MessageStatus::Pending));
+        hello.status = Some(MessageStatus::Sent);
+        alice.record("bob", &hello);
+        alice
+            .deliveries
+            .insert(DeliveryInfo::new(&hello.id, "bob", hello.timestamp));
+
+        let frame = alice.encode_frame("bob", &hello).unwrap();
+        let received = opened(bob.open_frame(&frame, "alice"));
+        assert_eq!(received.status, None);
+        let ack = bob.ack_frame("alice", &received).unwrap();
+
+        // Only the contact it was sent to can acknowledge it
+        assert!(matches!(
+            alice.open_frame(&ack, "mallory"),
+            Ok(Incoming::Ack(None))
+        ));
+        let Ok(Incoming::Ack(Some(delivered))) = alice.open_frame(&ack, "bob") else {
+            panic!("expected a delivered message");
+        };
+        assert_eq!(delivered.status, Some(MessageStatus::Delivered));
+        assert_eq!(
+            alice.get_messages("bob")[0].status,
+            Some(MessageStatus::Delivered)
+        );
+        assert_eq!(
+            alice.delivery(&hello.id).unwrap().status,
+            DeliveryStatus::Delivered
+        );
+
+        // Peers that never advertised receipts don't get them
+        assert!(ChatManager::new("bob", false)
+            .ack_frame("alice", &received)
+            .is_none());
+    }
+
+    #[test]
+    fn test_group_receipts_are_counted_per_member() {
+        use crate::groups::{Group, GroupMember};
+
+        let group = Group {
+            id: "g1".to_string(),
+            name: "Hikers".to_string(),
+            creator: "alice".to_string(),
+            created_at: 0,
+            members: ["alice", "bob", "carol"]
+                .map(|pubkey| GroupMember {
+                    pubkey: pubkey.to_string(),
+                    admin: pubkey == "alice",
+                })
+                .to_vec(),
+            changes: Vec::new(),
+            key: None,
+        };
+        let policy = InboundPolicy::default();
+        let (mut alice, mut bob) = chat_pair();
+        alice.set_groups(&[group.clone()]);
+        bob.set_groups(&[group]);
+
+        let mut hello = alice.compose_group("g1", "Hello all");
+        hello.status = Some(MessageStatus::Sent);
+        alice.record("g1", &hello);
+        let members = ["bob", "carol"].map(String::from);
+        alice.group_deliveries.insert(
+            hello.id.clone(),
+            GroupDelivery::new(&hello.id, "g1", &members, hello.timestamp),
+        );
+
+        // Bob keeps it in the group's conversation, not Alice's
+        let frame = alice.encode_frame("bob", &hello).unwrap();
+        let received = opened(bob.open_frame(&frame, "alice"));
+        let received = bob.process_incoming(received, &policy).unwrap();
+        assert_eq!(bob.get_messages("g1").len(), 1);
+        assert!(bob.get_messages("alice").is_empty());
+
+        // A receipt counts only from the member it names
+        let ack = bob.ack_frame("alice", &received).unwrap();
+        assert!(matches!(
+            alice.open_frame(&ack, "carol"),
+            Ok(Incoming::GroupReceipt(None))
+        ));
+        let Ok(Incoming::GroupReceipt(Some(receipts))) = alice.open_frame(&ack, "bob") else {
+            panic!("expected a counted receipt");
+        };
+        assert_eq!((receipts.delivered, receipts.read), (1, 0));
+
+        let read = bob.read_receipt_frame(&received).unwrap();
+        let Ok(Incoming::GroupReceipt(Some(receipts))) = alice.open_frame(&read, "bob") else {
+            panic!("expected a counted receipt");
+        };
+        assert_eq!((receipts.delivered, receipts.read), (1, 1));
+        // Carol hasn't acknowledged it, so it isn't delivered to the group yet
+        let info = alice.message_info(&hello.id).unwrap();
+        assert_eq!(info.message.status, Some(MessageStatus::Sent));
+        assert_eq!(info.group_delivery.unwrap().members.len(), 2);
+
+        // Someone off the roster can't post to the group
+        let stray = ChatManager::new("mallory", false).compose_group("g1", "Hi");
+        let stray = opened(bob.open_frame(&stray.to_wire().unwrap(), "mallory"));
+        assert!(matches!(
+            bob.process_incoming(stray, &policy),
+            Err(ChatError::NotGroupMember)
+        ));
+    }
+
+    #[test]
+    fn test_disappearing_timers_are_negotiated() {
+        let (mut alice, mut bob) = chat_pair();
+        alice.set_disappear_after("bob", Some(3600));
+        bob.set_disappear_after("alice", Some(60));
+
+        let hello = ChatMessage::new_outgoing("Hello", "alice");
+        let frame = alice.encode_frame("bob", &hello).unwrap();
+        let received = opened(bob.open_frame(&frame, "alice"));
+        assert_eq!(bob.disappear_after("alice"), Some(60));
+        // Bob's shorter timer holds even though Alice sent no expiry
+        let received = bob
+            .process_incoming(received, &InboundPolicy::default())
+            .unwrap();
+        assert!(received.expires_at.unwrap() <= now_secs() + 60);
+
+        // Alice learns Bob's timer from his next frame, and loses it once he
+        // turns it off
+        assert_eq!(alice.disappear_after("bob"), Some(3600));
+        let reply = ChatMessage::new_outgoing("Hi", "bob");
+        let frame = bob.encode_frame("alice", &reply).unwrap();
+        opened(alice.open_frame(&frame, "bob"));
+        assert_eq!(alice.disappear_after("bob"), Some(60));
+
+        bob.set_disappear_after("alice", None);
+        let frame = bob.encode_frame("alice", &reply).unwrap();
+        opened(alice.open_frame(&frame, "bob"));
+        assert_eq!(alice.disappear_after("bob"), Some(3600));
+    }
+
+    #[test]
+    fn test_purge_expired() {
+        let store = MessageStore::open_in_memory().unwrap();
+        let unloaded = ChatMessage {
+            expires_at: Some(100),
+            ..ChatMessage::new_outgoing("Not loaded", "my_pubkey")
+        };
+        store.insert("carol", &unloaded).unwrap();
+
+        let mut manager = ChatManager::new("my_pubkey", true);
+        manager.set_store(store);
+        let expiring = ChatMessage {
+            expires_at: Some(150),
+            ..ChatMessage::new_outgoing("Gone soon", "my_pubkey")
+        };
+        manager.record("them", &expiring);
+        manager.record("them", &ChatMessage::new_outgoing("Kept", "my_pubkey"));
+
+        assert!(manager.purge_expired(99).is_empty());
+        let purged = manager.purge_expired(150);
+        assert_eq!(purged.len(), 2);
+        assert!(purged.contains(&ExpiredMessage {
+            contact_pubkey: "carol".to_string(),
+            message_id: unloaded.id.clone(),
+        }));
+
+        let kept = manager.get_messages("them");
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].content, "Kept");
+        assert!(manager.message_info(&expiring.id).is_none());
+        assert!(manager.get_messages("carol").is_empty());
+    }
+
+    /// An edit of `original` from the same side, `delay` seconds after it
+    fn edit_of(original: &ChatMessage, content: &str, delay: u64) -> ChatMessage {
+        let payload = serde_json::to_value(Edit {
+            message_id: original.id.clone(),
+            content: content.to_string(),
+        })
+        .unwrap();
+        let mut edit = ChatMessage::new_outgoing_typed(KIND_EDIT, payload, "", "them");
+        edit.timestamp = original.timestamp + delay;
+        edit.is_outgoing = original.is_outgoing;
+        edit
+    }
+
+    #[test]
+    fn test_edits_replace_content_and_keep_history() {
+        let mut manager = ChatManager::new("my_pubkey", false);
+        let policy = InboundPolicy::default();
+        let mut hello = ChatMessage::new_outgoing("Helo", "them");
+        hello.is_outgoing = false;
+        manager.process_incoming(hello.clone(), &policy).unwrap();
+
+        let edited = manager
+            .process_incoming(edit_of(&hello, "Hello", 30), &policy)
+            .unwrap();
+        assert_eq!(edited.id, hello.id);
+        assert_eq!(edited.content, "Hello");
+        assert_eq!(edited.edited_at, Some(hello.timestamp + 30));
+        assert_eq!(
+            edited.edit_history,
+            vec![PreviousVersion {
+                content: "Helo".to_string(),
+                timestamp: hello.timestamp,
+            }]
+        );
+
+        // The conversation holds the latest version, not the edit
+        let messages = manager.get_messages("them");
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, "Hello");
+
+        // Our own messages go through the same path
+        let ours = ChatMessage::new_outgoing("Sea you", "my_pubkey");
+        manager.record("them", &ours);
+        let mine = manager.apply_edit("them", &edit_of(&ours, "See you", 5));
+        assert_eq!(mine.unwrap().content, "See you");
+    }
+
+    #[test]
+    fn test_edits_are_limited() {
+        let mut manager = ChatManager::new("my_pubkey", false);
+        let policy = InboundPolicy::default();
+        let mut hello = ChatMessage::new_outgoing("Hello", "them");
+        hello.is_outgoing = false;
+        manager.process_incoming(hello.clone(), &policy).unwrap();
+        let ours = ChatMessage::new_outgoing("Hi", "my_pubkey");
+        manager.record("them", &ours);
+
+        let late = edit_of(&hello, "Too late", EDIT_WINDOW_SECS + 1);
+        assert!(matches!(
+            manager.process_incoming(late, &policy),
+            Err(ChatError::EditRejected(_))
+        ));
+        // The contact can't edit our messages
+        let mut hijack = edit_of(&ours, "Not what I said", 1);
+        hijack.is_outgoing = false;
+        assert!(matches!(
+            manager.process_incoming(hijack, &policy),
+            Err(ChatError::EditRejected(_))
+        ));
+        assert_eq!(manager.get_messages("them")[0].content, "Hello");
+        assert_eq!(manager.get_messages("them")[1].content, "Hi");
+    }
+
+    #[test]
+    fn test_tombstones_leave_a_placeholder() {
+        let keys = Keys::generate();
+        let them = keys.public_key().to_hex();
+        let mut manager = ChatManager::new("my_pubkey", false);
+        let policy = InboundPolicy::default();
+        let mut hello = ChatMessage::new_outgoing("Hello", &them);
+        hello.is_outgoing = false;
+        manager.process_incoming(hello.clone(), &policy).unwrap();
+
+        let tombstone_from = |keys: &Keys| {
+            let tombstone = Tombstone::new(keys, &hello.id, 500).unwrap();
+            let payload = serde_json::to_value(tombstone).unwrap();
+            let mut message = ChatMessage::new_outgoing_typed(KIND_DELETE, payload, "", &them);
+            message.is_outgoing = false;
+            message
+        };
+
+        // Only the sender's signature counts
+        assert!(matches!(
+            manager.process_incoming(tombstone_from(&Keys::generate()), &policy),
+            Err(ChatError::DeleteRejected(_))
+        ));
+        let deleted = manager
+            .process_incoming(tombstone_from(&keys), &policy)
+            .unwrap();
+        assert_eq!(deleted.id, hello.id);
+        assert_eq!(deleted.content, DELETED_PLACEHOLDER);
+        assert_eq!(deleted.deleted_at, Some(500));
+
+        let messages = manager.get_messages(&them);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content, DELETED_PLACEHOLDER);
+    }
+
+    #[test]
+    fn test_local_delete() {
+        let mut manager = ChatManager::new("my_pubkey", true);
+        manager.set_store(MessageStore::open_in_memory().unwrap());
+        let oops = ChatMessage::new_outgoing("Oops", "my_pubkey");
+        manager.record("them", &oops);
+        assert_eq!(manager.contact_of(&oops.id).as_deref(), Some("them"));
+
+        manager.delete_message("them", &oops.id).unwrap();
+        assert!(manager.get_messages("them").is_empty());
+        assert!(manager.contact_of(&oops.id).is_none());
+        assert!(matches!(
+            manager.delete_message("them", &oops.id),
+            Err(ChatError::DeleteRejected(_))
+        ));
+    }
+
+    #[test]
+    fn test_hybrid_handshake() {
+        let (mut alice, mut bob) = chat_pair();
+        alice.set_hybrid_handshake(true);
+        let hello = ChatMessage::new_outgoing("Hello", "alice");
+
+        // Nothing is offered until Bob advertises support
+        assert!(alice.handshake_offer("bob").is_none());
+        let frame = alice.encode_frame("bob", &hello).unwrap();
+        opened(bob.open_frame(&frame, "alice"));
+        let frame = bob.encode_frame("alice", &hello).unwrap();
+        opened(alice.open_frame(&frame, "bob"));
+
+        let offer = alice.handshake_offer("bob").unwrap();
+        assert!(alice.handshake_offer("bob").is_none());
+        let Ok(Incoming::Handshake(Some(answer))) = bob.open_frame(&offer, "alice") else {
+            panic!("expected an answer");
+        };
+        assert!(matches!(
+            alice.open_frame(&answer, "bob"),
+            Ok(Incoming::Handshake(None))
+        ));
+
+        // Both directions now name the handshake in their chains
+        let hybrid_frame = |from: &mut ChatManager, to: &str| {
+            let frame = from.encode_frame(to, &hello).unwrap();
+            let sealed: SealedFrame = serde_json::from_slice(&frame).unwrap();
+            assert!(sealed.ratchet.hybrid.is_some());
+            frame
+        };
+        let frame = hybrid_frame(&mut alice, "bob");
+        opened(bob.open_frame(&frame, "alice"));
+        let frame = hybrid_frame(&mut bob, "alice");
+        opened(alice.open_frame(&frame, "bob"));
+    }
+
     #[test]
     fn test_chat_session() {
         let mut session = ChatSession::new("contact123", false);
@@ -324,4 +2098,143 @@ mod tests {
         let messages = manager.get_messages("contact2");
         assert!(messages.is_empty());
     }
+
+    #[test]
+    fn test_merge_sessions() {
+        let mut manager = ChatManager::new("my_pubkey", false);
+        manager
+            .get_or_create_session("old")
+            .add_message(ChatMessage::new_outgoing("First", "my_pubkey"));
+        manager
+            .get_or_create_session("new")
+            .add_message(ChatMessage::new_outgoing("Second", "my_pubkey"));
+
+        manager.merge_sessions("old", "new");
+
+        assert_eq!(manager.get_messages("new").len(), 2);
+        assert!(manager.get_messages("old").is_empty());
+    }
+
+    #[test]
+    fn test_persisted_history() {
+        let store = MessageStore::open_in_memory().unwrap();
+        let earlier = ChatMessage {
+            timestamp: 100,
+            ..ChatMessage::new_outgoing("From last time", "my_pubkey")
+        };
+        store.insert("them", &earlier).unwrap();
+
+        let mut manager = ChatManager::new("my_pubkey", true);
+        manager.set_store(store);
+        // Summarised from the database until the conversation is loaded
+        assert_eq!(manager.summary("them").1, 1);
+        assert!(manager.get_session("them").is_none());
+
+        let mut reply = ChatMessage::new_outgoing("Welcome back", "them");
+        reply.is_outgoing = false;
+        manager
+            .process_incoming(reply.clone(), &InboundPolicy::default())
+            .unwrap();
+        let history = manager.get_messages("them");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].id, earlier.id);
+
+        // Saved as it arrived, so it outlives the session
+        manager.clear_all();
+        assert!(manager.message_info(&reply.id).is_some());
+        assert_eq!(manager.get_messages("them").len(), 2);
+    }
+
+    #[test]
+    fn test_message_info() {
+        let mut manager = ChatManager::new("my_pubkey", false);
+        let sent = ChatMessage::new_outgoing("Hi", "my_pubkey");
+        manager
+            .get_or_create_session("them")
+            .add_message(sent.clone());
+
+        let mut delivery = DeliveryInfo::new(&sent.id, "them", sent.timestamp);
+        delivery.mark_sent(TRANSPORT_IROH, sent.timestamp + 1);
+        manager.deliveries.insert(delivery);
+        manager.record_retries(&sent.id, sent.timestamp - 60, 2);
+
+        let info = manager.message_info(&sent.id).unwrap();
+        assert_eq!(info.message.content, "Hi");
+        let delivery = info.delivery.unwrap();
+        assert_eq!(delivery.retries, 2);
+        assert_eq!(delivery.queued_at, sent.timestamp - 60);
+        assert_eq!(delivery.sent_at, Some(sent.timestamp + 1));
+
+        assert!(manager.message_info("missing").is_none());
+    }
+
+    #[test]
+    fn test_filter_pipeline() {
+        use crate::filters::{KeywordFilter, SizeFilter};
+
+        struct Shouty;
+        impl MessageFilter for Shouty {
+            fn name(&self) -> &str {
+                "shouty"
+            }
+            fn check(&self, message: &ChatMessage) -> FilterVerdict {
+                if message.content.chars().all(|c| !c.is_lowercase()) {
+                    FilterVerdict::Mark("shouting".to_string())
+                } else {
+                    FilterVerdict::Accept
+                }
+            }
+        }
+
+        let mut manager = ChatManager::new("my_pubkey", false);
+        manager.register_filter(Box::new(KeywordFilter::new(&["spam".to_string()])));
+        manager.register_filter(Box::new(SizeFilter::new(20)));
+        manager.register_filter(Box::new(Shouty));
+        let policy = InboundPolicy::default();
+
+        let incoming = |content: &str| {
+            let mut msg = ChatMessage::new_outgoing(content, "them");
+            msg.is_outgoing = false;
+            msg
+        };
+
+        let marked = manager
+            .process_incoming(incoming("HELLO"), &policy)
+            .unwrap();
+        assert_eq!(marked.flags, vec!["shouting"]);
+
+        let dropped = manager.process_incoming(incoming("buy spam"), &policy);
+        assert!(matches!(dropped, Err(ChatError::Filtered(_))));
+
+        let held = incoming("this message is far too long");
+        let held_id = held.id.clone();
+        let quarantined = manager.process_incoming(held, &policy);
+        assert!(matches!(quarantined, Err(ChatError::Quarantined(_))));
+        assert_eq!(manager.get_messages("them").len(), 1);
+
+        assert!(manager.release_quarantined(&held_id).is_some());
+        assert!(manager.quarantined().is_empty());
+        assert_eq!(manager.get_messages("them").len(), 2);
+    }
+
+    #[test]
+    fn test_summaries() {
+        use crate::exchange::Contact;
+        use crate::petname::resolve_all;
+
+        let mut manager = ChatManager::new("my_pubkey", false);
+        manager
+            .get_or_create_session("bb")
+            .add_message(ChatMessage::new_outgoing("Hi Bob", "my_pubkey"));
+
+        let mut alice = Contact::new("aa", "endpoint");
+        alice.nickname = Some("Alice".to_string());
+        let contacts = resolve_all(vec![alice, Contact::new("bb", "endpoint")]);
+
+        let summaries = conversation_summaries(Some(&manager), contacts);
+        assert_eq!(summaries[0].contact_pubkey, "bb");
+        assert_eq!(summaries[0].message_count, 1);
+        assert_eq!(summaries[1].display_name.name, "Alice");
+        assert!(summaries[1].last_message.is_none());
+    }
 }
@@ -1,527 +1,6917 @@
 //! Tauri command handlers
 
-use crate::chat::{ChatManager, ChatMessage, SharedChatManager};
-use crate::exchange::{Contact, ExchangeMessage};
-use crate::iroh_derive::derive_endpoint_id;
-use crate::iroh_node::{IrohConfig, IrohNode, IrohStatus, SharedIrohNode};
+use crate::audio::{self, AudioDecoder};
+use crate::audit::{KeyAuditEntry, KeyAuditLog, KeyUse};
+use crate::backup::{recover_key, split_key, KeyShare};
+use crate::beacon::{Beacon, BeaconStatus};
+use crate::broadcast::{BroadcastChannel, BroadcastPost};
+use crate::card::{
+    parse_profile_card, CardError, ContactShare, ProfileCard, ScannedProfile, SignedContactCard,
+    SignedProfileCard,
+};
+use crate::chat::{
+    conversation_summaries, write_frame, ChatError, ChatManager, ChatMessage, ConversationSummary,
+    MessageInfo, QuarantinedMessage,
+};
+use crate::conference::{Conference, ConferenceStatus, DEFAULT_CONFERENCE_WINDOW_SECS};
+use crate::contact_store::{
+    ContactPage, ContactQuery, ContactStore, ContactStoreError, MAX_PAGE_SIZE,
+};
+use crate::device::{derive_device_keys, DeviceCertificate};
+use crate::exchange::{
+    exchange_uri, find_duplicates, open_sealed_payload, payload_from_text, payload_from_uri,
+    seal_payload, session_id, verify_scanned_payload, Contact, ContactOrigin, DuplicateGroup,
+    ExchangeError, ExchangeMessage, ExchangePhase, ExchangeProfile, ExchangeProgress,
+    ExchangeSession, Negotiated, NodeHints, ScanError, TimestampTolerance, CAP_NODE_HINTS,
+    CAP_PROFILE, CAP_SEALED_RESPONSE, CAP_SESSION_BINDING,
+};
+use crate::filters::default_filters;
+use crate::groups::{
+    Group, GroupInvite, GroupKeyDelivery, InviteAcceptance, MembershipAction, MembershipChange,
+};
+use crate::identifier::{resolve as resolve_identifier, Identifier, NOSTR_URI_PREFIX};
+use crate::intake::{InboundLimiter, QuarantinedPayload};
+use crate::iroh_derive::{derive_chat_secret, derive_endpoint_id, derive_peer_endpoint_id};
+use crate::iroh_node::{IrohConfig, IrohStatus};
 use crate::keys::{
-    generate_keypair, get_public_key_info_from_stored, restore_keys, NostrKeysInfo, StoredKeys,
+    generate_keypair, get_public_key_info_from_hex, get_public_key_info_from_stored,
+    import_keypair, platform_key_storage, restore_keys, secret_key_nsec, ExportConfirmation,
+    KdfParams, KeyError, KeyProtection, NostrKeysInfo, PersistedKeys, RevocationStatement,
+    RotationStatement, SealedKeys, Signer, StoredKeys, WatchOnlyKeys,
 };
-use serde_json::json;
+use crate::lan::{self, LanConnection, LanError, LanPeer};
+use crate::message_store::MessageStore;
+use crate::message_types::{
+    MessageTypeRegistry, Tombstone, KIND_INTRODUCTION, KIND_KEY_REVOCATION, KIND_KEY_ROTATION,
+    KIND_RECOVERY_REQUEST, KIND_RECOVERY_SHARE,
+};
+use crate::migration::{
+    self, MigrationAssembler, MigrationBundle, MigrationChunk, MigrationError, MigrationProgress,
+};
+use crate::moderation::{EvidenceBundle, Report};
+use crate::multipart::{self, MultipartProgress};
+use crate::nfc::{NfcError, NfcExchangeState};
+use crate::nip46::{BunkerUri, RemoteSigner, RemoteSignerSession};
+use crate::outbox::QueuedMessage;
+use crate::pcsc_nfc::CardChannel;
+use crate::petname::{resolve, resolve_all, NameSource, NamedContact};
+use crate::profile::{
+    is_app_data_file, PinLock, ProfileError, ProfileId, ProfileKey, PIN_LOCK_FILE,
+};
+use crate::ping::{ping, PingResult};
+use crate::plugins::PluginInfo;
+use crate::queue::{ExchangeQueue, PendingExchange, PendingExchangeInfo, PENDING_SWEEP_SECS};
+use crate::recovery::{
+    GuardianSet, GuardianStore, HeldShare, PendingRecoveryRequest, RecoveryRequest,
+    RecoverySession, RecoveryStatus, ShareEnvelope,
+};
+use crate::relay;
+use crate::remote::{
+    self, RemoteError, RemoteExchange, RemoteExchangeStore, RemoteRequest, RemoteRequestInfo,
+    REMOTE_EXCHANGE_KIND,
+};
+use crate::replay::NonceCache;
+use crate::roster::{Roster, RosterError, RosterMember, ROSTER_TOLERANCE};
+use crate::safety::{session_code, SafetyNumber, ShortAuthString};
+use crate::schedule::ScheduledMessage;
+use crate::settings::{migrate as migrate_settings, FeatureFlag, FeatureFlags, Settings};
+use crate::share::{PendingShareInfo, SharedContent};
+use crate::state::AppState;
+use crate::stats::{ExchangeStats, ExchangeStatsSummary, ExchangeTimer, Transport};
+use crate::trace::{self, ConnectionTrace, TraceEventKind};
+use crate::trust::{score_contact, ContactTrust, TrustLevel};
+use crate::weblink::WebLink;
+use crate::wipe;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use iroh_quinn::Connection;
+use nostr::nips::nip19::ToBech32;
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tauri::{AppHandle, State};
+use std::time::Instant;
+use serde_json::json;
+use tauri::{AppHandle, Emitter, Manager, State};
+use zeroize::Zeroizing;
 use tauri_plugin_store::StoreExt;
 
-/// Application state
-pub struct AppState {
-    /// Cached keys (loaded from store on startup)
-    pub keys: std::sync::Mutex<Option<StoredKeys>>,
-    /// Iroh node for p2p networking
-    pub iroh_node: SharedIrohNode,
-    /// Chat manager for messaging
-    pub chat_manager: SharedChatManager,
+const KEYS_KEY: &str = "nostr_keys";
+/// Key the contact list lived under before the contact database
+const CONTACTS_KEY: &str = "contacts";
+const STATS_KEY: &str = "exchange_stats";
+const SETTINGS_KEY: &str = "settings";
+const GROUPS_KEY: &str = "groups";
+const BROADCAST_KEY: &str = "broadcast_channels";
+const REPORTS_KEY: &str = "reports";
+const SCHEDULED_KEY: &str = "scheduled_messages";
+const OUTBOX_KEY: &str = "outbox";
+const KEY_AUDIT_KEY: &str = "key_audit_log";
+const ROTATIONS_KEY: &str = "key_rotations";
+const RECOVERY_GUARDIANS_KEY: &str = "recovery_guardians";
+const RECOVERY_GUARDIAN_KEY: &str = "recovery_held_shares";
+const RECOVERY_SESSION_KEY: &str = "recovery_session";
+const PIN_LOCK_KEY: &str = "pin_lock";
+const REMOTE_SIGNER_KEY: &str = "remote_signer";
+const DEVICE_CERT_KEY: &str = "device_certificate";
+const NONCE_CACHE_KEY: &str = "seen_nonces";
+const EXCHANGE_SESSION_KEY: &str = "exchange_session";
+const REMOTE_EXCHANGES_KEY: &str = "remote_exchanges";
+/// Set once contacts' endpoint IDs have been re-derived for the mutual
+/// derivation
+const ENDPOINT_IDS_DERIVED_KEY: &str = "endpoint_ids_derived";
+
+/// Error for anything that needs the secret key of a watch-only identity
+const WATCH_ONLY_ERROR: &str = "This identity is watch-only; its secret key is on another device";
+
+/// Event emitted with the updated `Contact` when a contact rotates their key
+pub const CONTACT_ROTATED_EVENT: &str = "contacts://rotated";
+
+/// Event emitted with the updated `Contact` when a contact revokes their key
+pub const CONTACT_REVOKED_EVENT: &str = "contacts://revoked";
+
+/// Event emitted with the `Contact` a contact introduced us to over chat
+pub const CONTACT_INTRODUCED_EVENT: &str = "contacts://introduced";
+
+/// Event emitted with a `PendingRecoveryRequest` when a contact asks for the
+/// share we hold
+pub const RECOVERY_REQUEST_EVENT: &str = "recovery://request";
+
+/// Event emitted with the `RecoveryStatus` when a guardian returns a share
+pub const RECOVERY_PROGRESS_EVENT: &str = "recovery://progress";
+
+/// Event emitted with the `ExchangeSession` on every handshake transition
+pub const EXCHANGE_SESSION_EVENT: &str = "exchange://session";
+
+/// Event emitted with an `ExchangeProgress` as an exchange command moves
+/// through its phases, so the UI can show more than a spinner
+pub const EXCHANGE_STATE_EVENT: &str = "exchange://state";
+
+/// Event emitted with the `PendingExchangeInfo`s of exchanges dropped for
+/// taking too long
+pub const PENDING_EXPIRED_EVENT: &str = "exchange://pending-expired";
+
+/// Event emitted with the `LanHostInfo` once a local-network host is advertised
+pub const LAN_HOST_EVENT: &str = "exchange://lan-host";
+
+/// Event emitted with the `QuarantinedPayload` when an inbound exchange
+/// payload fails its signature check
+pub const PAYLOAD_QUARANTINED_EVENT: &str = "exchange://quarantined";
+
+/// Event emitted with the `ConferenceStatus` (or null once it's over) when a
+/// guest joins, the window closes or attendees are approved
+pub const CONFERENCE_EVENT: &str = "exchange://conference";
+
+/// Event emitted with the `HceExchangeOutcome` when a one-tap exchange served
+/// over Host Card Emulation finishes
+pub const HCE_EXCHANGE_EVENT: &str = "exchange://hce";
+
+/// Event emitted when the app is opened with exchange links, to be collected
+/// with `take_pending_exchange_uris`
+pub const EXCHANGE_URI_EVENT: &str = "exchange://uri";
+
+/// Event emitted once `wipe_all_data` has erased everything
+pub const WIPE_COMPLETE_EVENT: &str = "app://wiped";
+
+/// Store file of the active profile
+fn store_file(app: &AppHandle) -> String {
+    app.state::<AppState>().profile.lock().unwrap().store_file()
 }
 
-impl Default for AppState {
-    fn default() -> Self {
-        Self {
-            keys: std::sync::Mutex::new(None),
-            iroh_node: Arc::new(RwLock::new(IrohNode::new(IrohConfig::default()))),
-            chat_manager: Arc::new(RwLock::new(None)),
+/// Helper to load keys from store as written, plain or sealed
+fn load_persisted_keys(app: &AppHandle) -> Option<PersistedKeys> {
+    let store = app.store(store_file(&app)).ok()?;
+    let value = store.get(KEYS_KEY)?;
+    serde_json::from_value(value).ok()
+}
+
+/// Helper to load keys from store. Sealed keys have to be unlocked first;
+/// plaintext keys move into the platform keystore when there is one. Keys
+/// failing the integrity check are an error and never cached.
+fn try_load_keys_from_store(app: &AppHandle) -> Result<Option<StoredKeys>, KeyError> {
+    let Some(persisted) = load_persisted_keys(app) else {
+        return Ok(None);
+    };
+    let was_plain = matches!(persisted, PersistedKeys::Plain(_));
+    let storage = platform_key_storage();
+    let Some(stored) = storage.load(persisted)? else {
+        return Ok(None);
+    };
+    stored.validate()?;
+
+    if was_plain {
+        if let Ok(wrapped @ PersistedKeys::Wrapped(_)) = storage.persist(&stored) {
+            let _ = save_persisted_keys(app, &wrapped);
         }
     }
-}
 
-const STORE_FILE: &str = "sneakernet.json";
-const KEYS_KEY: &str = "nostr_keys";
-const CONTACTS_KEY: &str = "contacts";
+    Ok(Some(stored))
+}
 
-/// Helper to load keys from store
+/// Keys from the store, if present and intact
 fn load_keys_from_store(app: &AppHandle) -> Option<StoredKeys> {
-    let store = app.store(STORE_FILE).ok()?;
-    let value = store.get(KEYS_KEY)?;
-    serde_json::from_value(value).ok()
+    try_load_keys_from_store(app).ok().flatten()
 }
 
-/// Helper to save keys to store
-fn save_keys_to_store(app: &AppHandle, keys: &StoredKeys) -> Result<(), String> {
-    let store = app.store(STORE_FILE).map_err(|e| e.to_string())?;
+fn save_persisted_keys(app: &AppHandle, keys: &PersistedKeys) -> Result<(), String> {
+    let store = app.store(store_file(&app)).map_err(|e| e.to_string())?;
     store.set(KEYS_KEY, json!(keys));
     store.save().map_err(|e| e.to_string())?;
     Ok(())
 }
 
-/// Helper to load contacts from store
-fn load_contacts_from_store(app: &AppHandle) -> Vec<Contact> {
-    let store = match app.store(STORE_FILE) {
+/// Helper to save keys to store, through the platform keystore if any
+fn save_keys_to_store(app: &AppHandle, keys: &StoredKeys) -> Result<(), String> {
+    let persisted = platform_key_storage()
+        .persist(keys)
+        .map_err(|e| e.to_string())?;
+    save_persisted_keys(app, &persisted)
+}
+
+/// Run `f` against the contact database, opening it (and migrating the old
+/// JSON contact list into it) on first use
+pub(crate) fn with_contact_db<T>(
+    app: &AppHandle,
+    f: impl FnOnce(&mut ContactStore) -> Result<T, ContactStoreError>,
+) -> Result<T, String> {
+    let state = app.state::<AppState>();
+    let mut db = state.contact_db.lock().map_err(|e| e.to_string())?;
+
+    if db.is_none() {
+        let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let file = state.profile.lock().map_err(|e| e.to_string())?.contacts_db_file();
+        let mut store = ContactStore::open(&dir.join(file)).map_err(|e| e.to_string())?;
+        migrate_json_contacts(app, &mut store)?;
+        *db = Some(store);
+    }
+
+    match db.as_mut() {
+        Some(store) => f(store).map_err(|e| e.to_string()),
+        None => Err("Contact database unavailable".to_string()),
+    }
+}
+
+/// Move contacts saved by older versions from the JSON store into the database
+fn migrate_json_contacts(app: &AppHandle, db: &mut ContactStore) -> Result<(), String> {
+    let store = app.store(store_file(&app)).map_err(|e| e.to_string())?;
+    let Some(value) = store.get(CONTACTS_KEY) else {
+        return Ok(());
+    };
+
+    if db.is_empty().map_err(|e| e.to_string())? {
+        let contacts: Vec<Contact> = serde_json::from_value(value).unwrap_or_default();
+        db.replace_all(&contacts).map_err(|e| e.to_string())?;
+    }
+    store.delete(CONTACTS_KEY);
+    store.save().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Helper to load contacts, from the cache when possible
+pub(crate) fn load_contacts_from_store(app: &AppHandle) -> Vec<Contact> {
+    app.state::<AppState>()
+        .contacts
+        .get_or_load(|| with_contact_db(app, |db| db.all()).unwrap_or_default())
+}
+
+/// Helper to save contacts to store, updating the cache
+fn save_contacts_to_store(app: &AppHandle, contacts: &[Contact]) -> Result<(), String> {
+    with_contact_db(app, |db| db.replace_all(contacts))?;
+    app.state::<AppState>().contacts.set(contacts.to_vec());
+    Ok(())
+}
+
+/// Helper to load exchange statistics from store
+fn load_stats_from_store(app: &AppHandle) -> ExchangeStats {
+    let store = match app.store(store_file(&app)) {
+        Ok(s) => s,
+        Err(_) => return ExchangeStats::default(),
+    };
+
+    match store.get(STATS_KEY) {
+        Some(value) => serde_json::from_value(value).unwrap_or_default(),
+        None => ExchangeStats::default(),
+    }
+}
+
+/// Helper to save exchange statistics to store
+fn save_stats_to_store(app: &AppHandle, stats: &ExchangeStats) -> Result<(), String> {
+    let store = app.store(store_file(&app)).map_err(|e| e.to_string())?;
+    store.set(STATS_KEY, json!(stats));
+    store.save().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Helper to load the key audit log from store
+fn load_key_audit_from_store(app: &AppHandle) -> KeyAuditLog {
+    let store = match app.store(store_file(&app)) {
+        Ok(s) => s,
+        Err(_) => return KeyAuditLog::default(),
+    };
+
+    match store.get(KEY_AUDIT_KEY) {
+        Some(value) => serde_json::from_value(value).unwrap_or_default(),
+        None => KeyAuditLog::default(),
+    }
+}
+
+/// Append a use of our secret key to the audit log
+fn record_key_use(app: &AppHandle, purpose: KeyUse, detail: Option<String>) -> Result<(), String> {
+    let mut log = load_key_audit_from_store(app);
+    log.append(purpose, detail, now_secs());
+
+    let store = app.store(store_file(&app)).map_err(|e| e.to_string())?;
+    store.set(KEY_AUDIT_KEY, json!(log));
+    store.save().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Helper to load settings from store, migrating older versions
+/// Falls back to defaults if the stored settings are missing or unreadable
+pub(crate) fn load_settings_from_store(app: &AppHandle) -> Settings {
+    app.state::<AppState>().settings.get_or_load(|| {
+        let store = match app.store(store_file(&app)) {
+            Ok(s) => s,
+            Err(_) => return Settings::default(),
+        };
+
+        match store.get(SETTINGS_KEY) {
+            Some(value) => migrate_settings(value).unwrap_or_default(),
+            None => Settings::default(),
+        }
+    })
+}
+
+/// Helper to save settings to store, updating the cache
+fn save_settings_to_store(app: &AppHandle, settings: &Settings) -> Result<(), String> {
+    let store = app.store(store_file(&app)).map_err(|e| e.to_string())?;
+    store.set(SETTINGS_KEY, json!(settings));
+    store.save().map_err(|e| e.to_string())?;
+    app.state::<AppState>().settings.set(settings.clone());
+    Ok(())
+}
+
+/// Fail unless an experimental feature has been enabled in settings
+fn require_feature(app: &AppHandle, flag: FeatureFlag) -> Result<(), String> {
+    if load_settings_from_store(app).features.is_enabled(flag) {
+        Ok(())
+    } else {
+        Err(format!("Feature {:?} is not enabled", flag))
+    }
+}
+
+/// Helper to load groups from store
+pub(crate) fn load_groups_from_store(app: &AppHandle) -> Vec<Group> {
+    let store = match app.store(store_file(&app)) {
         Ok(s) => s,
         Err(_) => return vec![],
     };
-    
-    match store.get(CONTACTS_KEY) {
+
+    match store.get(GROUPS_KEY) {
         Some(value) => serde_json::from_value(value).unwrap_or_default(),
         None => vec![],
     }
 }
 
-/// Helper to save contacts to store
-fn save_contacts_to_store(app: &AppHandle, contacts: &[Contact]) -> Result<(), String> {
-    let store = app.store(STORE_FILE).map_err(|e| e.to_string())?;
-    store.set(CONTACTS_KEY, json!(contacts));
+/// Helper to save groups to store
+fn save_groups_to_store(app: &AppHandle, groups: &[Group]) -> Result<(), String> {
+    let store = app.store(store_file(&app)).map_err(|e| e.to_string())?;
+    store.set(GROUPS_KEY, json!(groups));
     store.save().map_err(|e| e.to_string())?;
     Ok(())
 }
 
-// ============================================================================
-// Key Management Commands
-// ============================================================================
+/// Helper to load broadcast channels from store
+fn load_channels_from_store(app: &AppHandle) -> Vec<BroadcastChannel> {
+    let store = match app.store(store_file(&app)) {
+        Ok(s) => s,
+        Err(_) => return vec![],
+    };
 
-#[tauri::command]
-pub fn has_keys(state: State<AppState>, app: AppHandle) -> bool {
-    // First check cached state
-    {
-        let keys = state.keys.lock().unwrap();
-        if keys.is_some() {
-            return true;
-        }
+    match store.get(BROADCAST_KEY) {
+        Some(value) => serde_json::from_value(value).unwrap_or_default(),
+        None => vec![],
     }
-    
-    // Try to load from store
-    if let Some(stored) = load_keys_from_store(&app) {
-        let mut keys = state.keys.lock().unwrap();
-        *keys = Some(stored);
-        return true;
+}
+
+/// Helper to save broadcast channels to store
+fn save_channels_to_store(app: &AppHandle, channels: &[BroadcastChannel]) -> Result<(), String> {
+    let store = app.store(store_file(&app)).map_err(|e| e.to_string())?;
+    store.set(BROADCAST_KEY, json!(channels));
+    store.save().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Helper to load abuse reports from store
+fn load_reports_from_store(app: &AppHandle) -> Vec<Report> {
+    let store = match app.store(store_file(&app)) {
+        Ok(s) => s,
+        Err(_) => return vec![],
+    };
+
+    match store.get(REPORTS_KEY) {
+        Some(value) => serde_json::from_value(value).unwrap_or_default(),
+        None => vec![],
     }
-    
-    false
 }
 
-#[tauri::command]
-pub fn generate_keys(state: State<AppState>, app: AppHandle) -> Result<NostrKeysInfo, String> {
-    let (_, stored) = generate_keypair().map_err(|e| e.to_string())?;
-    
-    // Save to store
-    save_keys_to_store(&app, &stored)?;
-    
-    // Cache in state
-    {
-        let mut keys = state.keys.lock().unwrap();
-        *keys = Some(stored.clone());
+/// Helper to save abuse reports to store
+fn save_reports_to_store(app: &AppHandle, reports: &[Report]) -> Result<(), String> {
+    let store = app.store(store_file(&app)).map_err(|e| e.to_string())?;
+    store.set(REPORTS_KEY, json!(reports));
+    store.save().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Helper to load scheduled messages from store
+pub(crate) fn load_scheduled_from_store(app: &AppHandle) -> Vec<ScheduledMessage> {
+    let store = match app.store(store_file(&app)) {
+        Ok(s) => s,
+        Err(_) => return vec![],
+    };
+
+    match store.get(SCHEDULED_KEY) {
+        Some(value) => serde_json::from_value(value).unwrap_or_default(),
+        None => vec![],
     }
-    
-    get_public_key_info_from_stored(&stored).map_err(|e| e.to_string())
 }
 
-#[tauri::command]
-pub fn get_public_key(state: State<AppState>, app: AppHandle) -> Result<NostrKeysInfo, String> {
-    // Check cache first
-    {
-        let keys = state.keys.lock().unwrap();
-        if let Some(ref stored) = *keys {
-            return get_public_key_info_from_stored(stored).map_err(|e| e.to_string());
+/// Helper to save scheduled messages to store
+pub(crate) fn save_scheduled_to_store(
+    app: &AppHandle,
+    scheduled: &[ScheduledMessage],
+) -> Result<(), String> {
+    let store = app.store(store_file(&app)).map_err(|e| e.to_string())?;
+    store.set(SCHEDULED_KEY, json!(scheduled));
+    store.save().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Helper to load queued outgoing messages from store
+pub(crate) fn load_outbox_from_store(app: &AppHandle) -> Vec<QueuedMessage> {
+    let store = match app.store(store_file(&app)) {
+        Ok(s) => s,
+        Err(_) => return vec![],
+    };
+
+    match store.get(OUTBOX_KEY) {
+        Some(value) => serde_json::from_value(value).unwrap_or_default(),
+        None => vec![],
+    }
+}
+
+/// Helper to save queued outgoing messages to store
+pub(crate) fn save_outbox_to_store(
+    app: &AppHandle,
+    outbox: &[QueuedMessage],
+) -> Result<(), String> {
+    let store = app.store(store_file(&app)).map_err(|e| e.to_string())?;
+    store.set(OUTBOX_KEY, json!(outbox));
+    store.save().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Helper to derive the Iroh endpoint ID we use with `their_pubkey`, from
+/// this device's subkey if it has one
+fn endpoint_id_for(
+    app: &AppHandle,
+    stored: &StoredKeys,
+    their_pubkey: &str,
+) -> Result<String, String> {
+    with_device_identity(app, stored, |stored, _| {
+        let secret_key_bytes = stored.secret_bytes().map_err(|e| e.to_string())?;
+        derive_endpoint_id(&secret_key_bytes, &stored.public_key_hex, their_pubkey)
+            .map_err(|e| e.to_string())
+    })?
+}
+
+/// Helper to derive the Iroh endpoint ID `their_pubkey` uses with us, from
+/// the ECDH secret we share with them
+fn peer_endpoint_id_for(
+    app: &AppHandle,
+    stored: &StoredKeys,
+    their_pubkey: &str,
+) -> Result<String, String> {
+    with_device_identity(app, stored, |stored, _| {
+        let secret_key_bytes = stored.secret_bytes().map_err(|e| e.to_string())?;
+        derive_peer_endpoint_id(&secret_key_bytes, &stored.public_key_hex, their_pubkey)
+            .map_err(|e| e.to_string())
+    })?
+}
+
+/// The node an exchanged contact runs for us, always derived from our shared
+/// secret ("" for a watch-only profile, which can't), and whether the node ID
+/// they signed disagrees with it. Such a hint is flagged, never stored.
+fn exchanged_endpoint_id(
+    state: &AppState,
+    app: &AppHandle,
+    their_pubkey: &str,
+    hints: &NodeHints,
+) -> Result<(String, bool), String> {
+    let derived = state
+        .with_keys(|stored| peer_endpoint_id_for(app, stored, their_pubkey))
+        .transpose()?
+        .unwrap_or_default();
+    let mismatch = match &hints.node_id {
+        Some(hint) => !derived.is_empty() && *hint != derived,
+        None => false,
+    };
+    Ok((derived, mismatch))
+}
+
+/// Re-derive the node each contact runs for us from `stored`
+fn rederive_endpoint_ids(app: &AppHandle, stored: &StoredKeys, contacts: &mut [Contact]) {
+    for contact in contacts.iter_mut() {
+        contact.iroh_endpoint_id =
+            peer_endpoint_id_for(app, stored, &contact.nostr_pubkey).unwrap_or_default();
+    }
+}
+
+/// Once per profile, re-derive the endpoint IDs of contacts saved by older
+/// versions, which kept the one-sided (v1) ID or the node ID the peer signed.
+/// Either would now be refused as an unexpected peer.
+fn migrate_endpoint_ids(app: &AppHandle, stored: &StoredKeys) -> Result<(), String> {
+    let store = app.store(store_file(app)).map_err(|e| e.to_string())?;
+    if store.get(ENDPOINT_IDS_DERIVED_KEY).is_some() {
+        return Ok(());
+    }
+
+    let mut contacts = load_contacts_from_store(app);
+    rederive_endpoint_ids(app, stored, &mut contacts);
+    save_contacts_to_store(app, &contacts)?;
+    store.set(ENDPOINT_IDS_DERIVED_KEY, json!(true));
+    store.save().map_err(|e| e.to_string())
+}
+
+/// Current Unix time in seconds
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Helper to restore our Nostr keys from the cached state
+fn our_keys(state: &AppState) -> Result<nostr::Keys, String> {
+    let keys = state.with_keys(restore_keys).ok_or("No keys found")?;
+
+    keys.map_err(|e| e.to_string())
+}
+
+/// Run `f` on our keys, loading them from the store into the cache first if
+/// need be. `None` if there are none or they're locked.
+fn with_loaded_keys<R>(
+    state: &AppState,
+    app: &AppHandle,
+    f: impl FnOnce(&StoredKeys) -> R,
+) -> Option<R> {
+    if !state.keys.is_loaded() {
+        state.keys.set(load_keys_from_store(app)?);
+    }
+    state.with_keys(f)
+}
+
+/// Load this device's certificate, if a device subkey is enabled
+fn load_device_cert(app: &AppHandle) -> Option<DeviceCertificate> {
+    let store = app.store(store_file(app)).ok()?;
+    let value = store.get(DEVICE_CERT_KEY)?;
+    serde_json::from_value(value).ok()
+}
+
+/// Run `f` on the keys this device acts with under `master`: its certified
+/// subkey, or the master keys themselves if no subkey is enabled for that
+/// identity
+fn with_device_identity<R>(
+    app: &AppHandle,
+    master: &StoredKeys,
+    f: impl FnOnce(&StoredKeys, Option<DeviceCertificate>) -> R,
+) -> Result<R, String> {
+    match load_device_cert(app).filter(|c| c.master_pubkey == master.public_key_hex) {
+        Some(cert) => {
+            let device =
+                derive_device_keys(master, cert.device_index).map_err(|e| e.to_string())?;
+            Ok(f(&device, Some(cert)))
         }
+        None => Ok(f(master, None)),
     }
-    
-    // Try to load from store
-    let stored = load_keys_from_store(&app).ok_or("No keys found")?;
-    
-    // Cache it
-    {
-        let mut keys = state.keys.lock().unwrap();
-        *keys = Some(stored.clone());
+}
+
+/// Keys this device chats with, which also prove us on chat connections
+fn chat_keys(state: &AppState, app: &AppHandle) -> Result<nostr::Keys, String> {
+    if is_watch_only(app) {
+        return Err(WATCH_ONLY_ERROR.to_string());
     }
-    
-    get_public_key_info_from_stored(&stored).map_err(|e| e.to_string())
+    let keys = state
+        .with_keys(|stored| with_device_identity(app, stored, |device, _| restore_keys(device)))
+        .ok_or("No keys found")??;
+    keys.map_err(|e| e.to_string())
 }
 
-// ============================================================================
-// NFC Exchange Commands
-// ============================================================================
+/// What signs our exchange messages: the paired remote signer, this
+/// device's subkey (with its certificate), or local keys
+fn exchange_signer(
+    state: &AppState,
+    app: &AppHandle,
+) -> Result<(Arc<dyn Signer>, Option<DeviceCertificate>), String> {
+    if let Some(remote) = load_remote_signer(state, app) {
+        return Ok((remote, None));
+    }
+    let signer = state.with_keys(|stored| {
+        with_device_identity(app, stored, |device, cert| (restore_keys(device), cert))
+    });
+    let (keys, cert) = signer.ok_or_else(|| {
+        if is_watch_only(app) {
+            WATCH_ONLY_ERROR.to_string()
+        } else {
+            "No keys found".to_string()
+        }
+    })??;
+    let keys = keys.map_err(|e| e.to_string())?;
+    Ok((Arc::new(keys), cert))
+}
 
-#[tauri::command]
-pub async fn is_nfc_available(app: AppHandle) -> Result<bool, String> {
-    #[cfg(any(target_os = "android", target_os = "ios"))]
-    {
-        use tauri_plugin_nfc::NfcExt;
-        app.nfc()
-            .is_available()
-            .map_err(|e| e.to_string())
+/// Local keys behind our exchange signer, for opening responses sealed to
+/// it. `None` with a remote signer, whose secret never reaches us.
+fn exchange_keys(state: &AppState, app: &AppHandle) -> Option<nostr::Keys> {
+    local_exchange_signer(state, app).map(|(keys, _)| keys)
+}
+
+/// Our exchange signer when it is local keys (with this device's
+/// certificate), which sign without waiting on anyone
+fn local_exchange_signer(
+    state: &AppState,
+    app: &AppHandle,
+) -> Option<(nostr::Keys, Option<DeviceCertificate>)> {
+    if load_remote_signer(state, app).is_some() {
+        return None;
     }
-    
-    #[cfg(not(any(target_os = "android", target_os = "ios")))]
-    {
-        let _ = app;
-        Ok(false)
+    let signer = state.with_keys(|stored| {
+        with_device_identity(app, stored, |device, cert| Some((restore_keys(device).ok()?, cert)))
+    })?;
+    signer.ok().flatten()
+}
+
+/// Reject a verified message we accepted before, unless it is the one a
+/// still-pending exchange came from (a retried tap, or the same code scanned
+/// twice). Its nonce is kept for as long as `tolerance`, which it was
+/// verified with, would accept it.
+fn check_replay(
+    state: &AppState,
+    app: &AppHandle,
+    msg: &ExchangeMessage,
+    tolerance: TimestampTolerance,
+) -> Result<(), ScanError> {
+    let pending_nonce = state
+        .exchange_queue
+        .lock()
+        .unwrap()
+        .get(&msg.pubkey)
+        .and_then(|p| p.their_nonce.clone());
+
+    let store = app.store(store_file(app)).ok();
+    let mut cache: NonceCache = store
+        .as_ref()
+        .and_then(|store| store.get(NONCE_CACHE_KEY))
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default();
+    cache.check(&msg.pubkey, &msg.nonce, pending_nonce.as_deref())?;
+    if pending_nonce.is_some_and(|pending| pending.eq_ignore_ascii_case(&msg.nonce)) {
+        return Ok(());
+    }
+
+    cache.insert(
+        &msg.pubkey,
+        &msg.nonce,
+        msg.timestamp,
+        tolerance,
+        now_secs(),
+    );
+    if let Some(store) = store {
+        store.set(NONCE_CACHE_KEY, json!(cache));
+        let _ = store.save();
     }
+    Ok(())
 }
 
-/// Start broadcasting our exchange message via NFC (write mode)
-/// The other device should be in receive mode to read this
-#[tauri::command]
-pub async fn start_nfc_broadcast(
-    state: State<'_, AppState>,
-    app: AppHandle,
-) -> Result<String, String> {
-    // Get our keys
-    let stored = {
-        let keys = state.keys.lock().unwrap();
-        keys.clone().ok_or("No keys found")?
+/// Reject a response bound to a message we didn't send: our last initial
+/// message, or our latest response to them
+fn check_session(state: &AppState, msg: &ExchangeMessage) -> Result<(), ScanError> {
+    if msg.their_pubkey.is_none() {
+        return Ok(());
+    }
+    let initial_nonce = state.initial_nonce.lock().unwrap().clone();
+    let our_nonce = state
+        .exchange_queue
+        .lock()
+        .unwrap()
+        .get(&msg.pubkey)
+        .and_then(|p| p.our_nonce.clone());
+    let ours: Vec<&str> = [initial_nonce.as_deref(), our_nonce.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect();
+    Ok(msg.verify_session(&ours)?)
+}
+
+/// Queue a verified message's sender, with what we learned about them from it
+fn track_pending_exchange(state: &AppState, msg: &ExchangeMessage, transport: Transport) {
+    // A response to us answers the initial message we made last
+    let initial_nonce = match msg.their_pubkey {
+        Some(_) => state.initial_nonce.lock().unwrap().clone(),
+        None => None,
     };
+    let mut queue = state.exchange_queue.lock().unwrap();
+    queue.track(&msg.pubkey, transport);
+    queue.set_their_message(
+        &msg.pubkey,
+        &msg.nonce,
+        &msg.signature,
+        initial_nonce.as_deref(),
+    );
+    queue.set_protocol(&msg.pubkey, msg.negotiate());
+    queue.set_hints(&msg.pubkey, msg.node_hints());
+    queue.set_profile(&msg.pubkey, msg.profile.clone());
+    if let Some(cert) = &msg.device_cert {
+        queue.set_master(&msg.pubkey, &cert.master_pubkey);
+    }
+}
+
+/// The persisted exchange session, reset if it was abandoned too long ago
+fn load_exchange_session(app: &AppHandle) -> ExchangeSession {
+    app.store(store_file(app))
+        .ok()
+        .and_then(|store| store.get(EXCHANGE_SESSION_KEY))
+        .and_then(|value| serde_json::from_value::<ExchangeSession>(value).ok())
+        .unwrap_or_default()
+        .resumed(now_secs())
+}
+
+/// Apply a transition to the exchange session, persist it and announce it.
+/// A transition that doesn't fit the session, such as completing a queued
+/// peer while another is in the foreground, leaves it untouched.
+fn advance_session(
+    app: &AppHandle,
+    step: impl FnOnce(&mut ExchangeSession) -> Result<(), ExchangeError>,
+) {
+    let mut session = load_exchange_session(app);
+    if step(&mut session).is_err() {
+        return;
+    }
+    if let Ok(store) = app.store(store_file(app)) {
+        store.set(EXCHANGE_SESSION_KEY, json!(session));
+        let _ = store.save();
+    }
+    let _ = app.emit(EXCHANGE_SESSION_EVENT, &session);
+}
+
+/// The protocol to answer `their_pubkey` with; ours for initial messages and
+/// peers we haven't heard from
+fn peer_protocol(state: &AppState, their_pubkey: Option<&str>) -> Negotiated {
+    their_pubkey
+        .and_then(|pk| state.exchange_queue.lock().unwrap().get(pk).map(|p| p.protocol))
+        .unwrap_or(Negotiated::OURS)
+}
+
+/// Sign an exchange message and encode it the way the peer reads it
+async fn exchange_payload(
+    state: &AppState,
+    app: &AppHandle,
+    their_pubkey: Option<&str>,
+    protocol: Negotiated,
+) -> Result<String, String> {
+    let inputs = payload_inputs(state, app, their_pubkey, protocol)?;
+    let msg = sign_exchange_message(
+        state,
+        app,
+        their_pubkey,
+        inputs.answering.as_deref(),
+        inputs.hints,
+        inputs.profile,
+        inputs.offer,
+    )
+    .await?
+    .downgraded(protocol);
+    msg.encode(protocol).map_err(|e| e.to_string())
+}
+
+/// What goes into our exchange message for a peer besides the signature
+struct PayloadInputs {
+    hints: NodeHints,
+    profile: Option<ExchangeProfile>,
+    /// Nonce of the message we answer
+    answering: Option<String>,
+    offer: Negotiated,
+}
+
+/// Gather what our message to `their_pubkey` carries under `protocol`
+fn payload_inputs(
+    state: &AppState,
+    app: &AppHandle,
+    their_pubkey: Option<&str>,
+    protocol: Negotiated,
+) -> Result<PayloadInputs, String> {
+    let hints = if protocol.has(CAP_NODE_HINTS) {
+        our_node_hints(app, their_pubkey)?
+    } else {
+        NodeHints::default()
+    };
+    // Signed in, so only sent to peers that check it
+    let profile = if protocol.has(CAP_PROFILE) {
+        load_settings_from_store(app).profile.exchange_profile()
+    } else {
+        None
+    };
+    // Bound to the message we answer, for peers that check it
+    let answering = their_pubkey
+        .filter(|_| protocol.has(CAP_SESSION_BINDING))
+        .and_then(|pk| state.exchange_queue.lock().unwrap().get(pk)?.their_nonce.clone());
+    // Only ask for a sealed response if we can open it
+    let mut offer = protocol;
+    if exchange_keys(state, app).is_none() {
+        offer.capabilities &= !CAP_SEALED_RESPONSE;
+    }
+    Ok(PayloadInputs {
+        hints,
+        profile,
+        answering,
+        offer,
+    })
+}
+
+/// Create and sign an exchange message with our exchange signer, offering
+/// the version and capabilities in `offer`. A response is bound to the
+/// message with nonce `answering`, if given.
+async fn sign_exchange_message(
+    state: &AppState,
+    app: &AppHandle,
+    their_pubkey: Option<&str>,
+    answering: Option<&str>,
+    hints: NodeHints,
+    profile: Option<ExchangeProfile>,
+    offer: Negotiated,
+) -> Result<ExchangeMessage, String> {
+    let (signer, cert) = exchange_signer(state, app)?;
+    let msg = match (their_pubkey, answering) {
+        (Some(their_pk), Some(nonce)) => {
+            ExchangeMessage::new_bound_response(
+                signer.as_ref(),
+                their_pk,
+                nonce,
+                hints,
+                profile,
+                offer,
+            )
+            .await
+        }
+        _ => {
+            ExchangeMessage::new_signed(signer.as_ref(), their_pubkey, hints, profile, offer)
+                .await
+        }
+    }
+    .map_err(|e| e.to_string())?;
+
+    note_signed(state, app, their_pubkey, msg, cert)
+}
+
+/// Sign our response to `their_pubkey` with local keys, without awaiting:
+/// for the one-tap exchange, which signs while the phones touch
+fn sign_local_response(
+    state: &AppState,
+    app: &AppHandle,
+    signer: &(nostr::Keys, Option<DeviceCertificate>),
+    their_pubkey: &str,
+    protocol: Negotiated,
+) -> Result<String, String> {
+    let inputs = payload_inputs(state, app, Some(their_pubkey), protocol)?;
+    let (keys, cert) = signer;
+    let msg = ExchangeMessage::new_local_response(
+        keys,
+        their_pubkey,
+        inputs.answering.as_deref(),
+        inputs.hints,
+        inputs.profile,
+        inputs.offer,
+    )
+    .map_err(|e| e.to_string())?;
+    let msg = note_signed(state, app, Some(their_pubkey), msg, cert.clone())?;
+    msg.downgraded(protocol).encode(protocol).map_err(|e| e.to_string())
+}
+
+/// Remember the nonce of a message we just signed, so what answers it can
+/// be matched to it, and log the key use
+fn note_signed(
+    state: &AppState,
+    app: &AppHandle,
+    their_pubkey: Option<&str>,
+    msg: ExchangeMessage,
+    cert: Option<DeviceCertificate>,
+) -> Result<ExchangeMessage, String> {
+    let purpose = match their_pubkey {
+        Some(their_pk) => {
+            state.exchange_queue.lock().unwrap().set_our_nonce(their_pk, &msg.nonce);
+            KeyUse::ResponseSign
+        }
+        None => {
+            *state.initial_nonce.lock().unwrap() = Some(msg.nonce.clone());
+            KeyUse::ExchangeSign
+        }
+    };
+    record_key_use(app, purpose, their_pubkey.map(str::to_string))?;
+
+    Ok(msg.with_device_cert(cert))
+}
+
+/// Where a peer can dial us: the node we run for `their_pubkey` (responses
+/// only, as the node is derived per contact) and the configured relay
+fn our_node_hints(app: &AppHandle, their_pubkey: Option<&str>) -> Result<NodeHints, String> {
+    let relays = load_settings_from_store(app).relays;
+    let relay_url = relays.custom_relay_url.filter(|_| relays.use_relays);
+
+    let state = app.state::<AppState>();
+    let derived = their_pubkey.and_then(|their_pubkey| {
+        state.with_keys(|stored| endpoint_id_for(app, stored, their_pubkey))
+    });
+    let node_id = match (their_pubkey, derived) {
+        (Some(their_pubkey), Some(node_id)) => {
+            let node_id = node_id?;
+            record_key_use(app, KeyUse::IrohDerivation, Some(their_pubkey.to_string()))?;
+            Some(node_id)
+        }
+        _ => None,
+    };
+
+    Ok(NodeHints { node_id, relay_url })
+}
+
+/// The pubkey peers know us by, even while we can't sign (locked or
+/// watch-only keys)
+fn identity_pubkey(state: &AppState, app: &AppHandle) -> Option<String> {
+    if let Some(remote) = load_remote_signer(state, app) {
+        return Some(remote.public_key_hex());
+    }
+    let master = identity_master_pubkey(state, app)?;
+    match load_device_cert(app) {
+        Some(cert) if cert.master_pubkey == master => Some(cert.device_pubkey),
+        _ => Some(master),
+    }
+}
+
+/// Our own key's pubkey, known even while the secret key is locked
+fn identity_master_pubkey(state: &AppState, app: &AppHandle) -> Option<String> {
+    state
+        .our_pubkey()
+        .or_else(|| load_persisted_keys(app).map(|p| p.public_key_hex().to_string()))
+}
+
+/// Whether we only have the public key of our identity
+fn is_watch_only(app: &AppHandle) -> bool {
+    matches!(load_persisted_keys(app), Some(PersistedKeys::WatchOnly(_)))
+}
+
+/// Update exchange statistics (best effort, never fails the exchange itself)
+fn update_stats(app: &AppHandle, f: impl FnOnce(&mut ExchangeStats)) {
+    let mut stats = load_stats_from_store(app);
+    f(&mut stats);
+    let _ = save_stats_to_store(app, &stats);
+}
+
+/// Start timing an exchange unless one is already running on this transport
+fn begin_exchange_timer(state: &AppState, app: &AppHandle, transport: Transport) {
+    let mut timer = state.exchange_timer.lock().unwrap();
+    let running = matches!(*timer, Some(t) if t.transport == transport && !t.is_stale());
+
+    if !running {
+        *timer = Some(ExchangeTimer::start(transport));
+        update_stats(app, |s| s.record_start(transport));
+    }
+}
+
+/// Announce a phase of the exchange command in progress
+fn emit_exchange_state(
+    app: &AppHandle,
+    transport: Transport,
+    phase: ExchangePhase,
+    their_pubkey: Option<&str>,
+) {
+    let progress = ExchangeProgress {
+        phase,
+        transport,
+        their_pubkey: their_pubkey.map(str::to_string),
+        at: now_secs(),
+    };
+    let _ = app.emit(EXCHANGE_STATE_EVENT, progress);
+}
+
+// ============================================================================
+// Key Management Commands
+// ============================================================================
+
+#[tauri::command]
+pub fn has_keys(state: State<AppState>, app: AppHandle) -> bool {
+    // First check cached state
+    if state.keys.is_loaded() {
+        return true;
+    }
+
+    // Try to load from store; sealed keys exist but stay locked
+    if let Some(stored) = load_keys_from_store(&app) {
+        state.keys.set(stored);
+        return true;
+    }
+
+    load_persisted_keys(&app).is_some()
+}
+
+#[tauri::command]
+pub fn generate_keys(state: State<AppState>, app: AppHandle) -> Result<NostrKeysInfo, String> {
+    let (_, stored) = generate_keypair().map_err(|e| e.to_string())?;
     
-    let our_keys = restore_keys(&stored).map_err(|e| e.to_string())?;
-    
-    // Create initial exchange message (no their_pubkey yet)
-    let msg = ExchangeMessage::new_initial(&our_keys)
-        .map_err(|e| e.to_string())?;
-    
-    let json = msg.to_json().map_err(|e| e.to_string())?;
-    let our_pubkey = msg.pubkey.clone();
+    // Save to store
+    save_keys_to_store(&app, &stored)?;
+    let info = get_public_key_info_from_stored(&stored).map_err(|e| e.to_string())?;
     
-    #[cfg(any(target_os = "android", target_os = "ios"))]
-    {
-        use tauri_plugin_nfc::{NfcRecord, NfcExt, NFCTypeNameFormat};
-        
-        // Write our exchange message to NFC
-        // The plugin will prompt to tap a device/tag
-        app.nfc()
-            .write(vec![NfcRecord {
-                format: NFCTypeNameFormat::Media,
-                kind: crate::exchange::NDEF_MIME_TYPE.as_bytes().to_vec(),
-                id: vec![],
-                payload: json.into_bytes(),
-            }])
-            .map_err(|e| e.to_string())?;
-        
-        Ok(our_pubkey)
+    // Cache in state
+    state.keys.set(stored);
+
+    Ok(info)
+}
+
+/// Use an existing Nostr identity (nsec or hex secret key) instead of
+/// generating one. Re-importing the current key is a no-op; a different key
+/// is refused while an identity exists.
+#[tauri::command]
+pub fn import_keys(
+    secret_key: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<NostrKeysInfo, String> {
+    let secret_key = Zeroizing::new(secret_key);
+    let (_, stored) = import_keypair(&secret_key).map_err(|e| e.to_string())?;
+    adopt_keys(&state, &app, stored)
+}
+
+/// Make restored keys our identity, unless a different one already exists
+fn adopt_keys(
+    state: &AppState,
+    app: &AppHandle,
+    stored: StoredKeys,
+) -> Result<NostrKeysInfo, String> {
+    match load_persisted_keys(app) {
+        Some(existing) if existing.public_key_hex() != stored.public_key_hex => {
+            return Err("An identity already exists on this device".to_string());
+        }
+        // Keep the passphrase protection
+        Some(PersistedKeys::Sealed(_)) => {}
+        _ => save_keys_to_store(app, &stored)?,
     }
-    
-    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    let info = get_public_key_info_from_stored(&stored).map_err(|e| e.to_string())?;
+    state.keys.set(stored);
+
+    Ok(info)
+}
+
+/// Set up with only a public key (npub or hex) whose secret key is on
+/// another device. Exchange payloads can be received and contacts browsed,
+/// but nothing is signed: no exchange responses and no chat.
+#[tauri::command]
+pub fn init_watch_only(pubkey: String, app: AppHandle) -> Result<NostrKeysInfo, String> {
+    if load_persisted_keys(&app).is_some() {
+        return Err("An identity already exists on this device".to_string());
+    }
+
+    let watch_only = WatchOnlyKeys::parse(&pubkey).map_err(|e| e.to_string())?;
+    save_persisted_keys(&app, &PersistedKeys::WatchOnly(watch_only.clone()))?;
+
+    get_public_key_info_from_hex(&watch_only.public_key_hex).map_err(|e| e.to_string())
+}
+
+/// Split our secret key into `n` QR-printable shares, any `k` of which
+/// recover it with `recover_from_shares`
+#[tauri::command]
+pub fn split_key_shares(
+    n: u8,
+    k: u8,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<Vec<String>, String> {
+    let shares = with_loaded_keys(&state, &app, |stored| split_key(stored, n, k))
+        .ok_or("No keys found or keys are locked")?
+        .map_err(|e| e.to_string())?;
+    let shares = shares
+        .iter()
+        .map(|share| share.to_json())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    record_key_use(&app, KeyUse::ShareBackup, Some(format!("{} of {}", k, n)))?;
+
+    Ok(shares)
+}
+
+/// Restore our identity from scanned key shares
+#[tauri::command]
+pub fn recover_from_shares(
+    shares: Vec<String>,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<NostrKeysInfo, String> {
+    let shares = shares
+        .iter()
+        .map(|json| KeyShare::from_json(json))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let stored = recover_key(&shares).map_err(|e| e.to_string())?;
+    adopt_keys(&state, &app, stored)
+}
+
+/// Start a secret key export. The UI shows its warning and passes the
+/// returned token to `export_secret_key` once the user confirms.
+#[tauri::command]
+pub fn request_secret_key_export(state: State<AppState>, app: AppHandle) -> Result<String, String> {
+    if !state.keys.is_loaded() && load_keys_from_store(&app).is_none() {
+        return Err("No keys found".to_string());
+    }
+
+    let confirmation = ExportConfirmation::new(now_secs());
+    let token = confirmation.token.clone();
+    *state.export_confirmation.lock().unwrap() = Some(confirmation);
+
+    Ok(token)
+}
+
+/// Export our secret key as an nsec. Requires the token from
+/// `request_secret_key_export`, which is single-use, and records the export
+/// in the key audit log.
+#[tauri::command]
+pub fn export_secret_key(
+    confirmation_token: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<String, String> {
+    let confirmation = state.export_confirmation.lock().unwrap().take();
+    if !confirmation.is_some_and(|c| c.accepts(&confirmation_token, now_secs())) {
+        return Err("Export not confirmed or confirmation expired".to_string());
+    }
+
+    let nsec = with_loaded_keys(&state, &app, secret_key_nsec).ok_or("No keys found")?;
+    let nsec = nsec.map_err(|e| e.to_string())?;
+    record_key_use(&app, KeyUse::IdentityExport, None)?;
+
+    Ok(nsec)
+}
+
+/// What our secret key has been used for, newest first, optionally only
+/// since a Unix time
+#[tauri::command]
+pub fn get_key_audit_log(since: Option<u64>, app: AppHandle) -> Vec<KeyAuditEntry> {
+    load_key_audit_from_store(&app).recent(since.unwrap_or(0))
+}
+
+/// Whether our keys are passphrase-protected and unlocked
+#[tauri::command]
+pub fn get_key_protection(state: State<AppState>, app: AppHandle) -> KeyProtection {
+    let persisted = load_persisted_keys(&app);
+    let encrypted = matches!(persisted, Some(PersistedKeys::Sealed(_)));
+    let loaded = if state.keys.is_loaded() {
+        Ok(true)
+    } else if encrypted {
+        Ok(false)
+    } else {
+        try_load_keys_from_store(&app).map(|stored| stored.is_some())
+    };
+
+    KeyProtection {
+        encrypted,
+        unlocked: matches!(loaded, Ok(true)),
+        hardware_backed: matches!(persisted, Some(PersistedKeys::Wrapped(_))),
+        watch_only: matches!(persisted, Some(PersistedKeys::WatchOnly(_))),
+        corrupt: match loaded {
+            Err(KeyError::CorruptKeys(reason)) => Some(reason),
+            _ => None,
+        },
+    }
+}
+
+/// Encrypt the stored secret key with a passphrase, or change the passphrase.
+/// The keys must be unlocked.
+#[tauri::command]
+pub fn set_key_passphrase(
+    passphrase: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<(), String> {
+    if load_pin_lock(&app).is_some() {
+        return Err("Keys are protected by the app PIN".to_string());
+    }
+    let sealed = with_loaded_keys(&state, &app, |stored| SealedKeys::seal(stored, &passphrase))
+        .ok_or("Keys are locked")?
+        .map_err(|e| e.to_string())?;
+    save_persisted_keys(&app, &PersistedKeys::Sealed(sealed))?;
+
+    Ok(())
+}
+
+/// Decrypt the stored secret key for this session
+#[tauri::command]
+pub fn unlock_keys(
+    passphrase: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<NostrKeysInfo, String> {
+    let stored = match load_persisted_keys(&app).ok_or("No keys found")? {
+        PersistedKeys::Sealed(sealed) => sealed.unseal(&passphrase).map_err(|e| e.to_string())?,
+        persisted => platform_key_storage()
+            .load(persisted)
+            .map_err(|e| e.to_string())?
+            .ok_or("No keys found")?,
+    };
+    let info = get_public_key_info_from_stored(&stored).map_err(|e| e.to_string())?;
+    state.keys.set(stored);
+
+    Ok(info)
+}
+
+/// Forget the decrypted secret key until the next `unlock_keys`
+#[tauri::command]
+pub fn lock_keys(state: State<AppState>, app: AppHandle) -> Result<(), String> {
+    if !matches!(load_persisted_keys(&app), Some(PersistedKeys::Sealed(_))) {
+        return Err("Keys are not encrypted".to_string());
+    }
+
+    state.keys.invalidate();
+    state.export_confirmation.lock().unwrap().take();
+    state.profile_key.lock().unwrap().take();
+    Ok(())
+}
+
+/// Store the secret key in plaintext again
+#[tauri::command]
+pub fn remove_key_passphrase(
+    passphrase: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let PersistedKeys::Sealed(sealed) = load_persisted_keys(&app).ok_or("No keys found")? else {
+        return Err("Keys are not encrypted".to_string());
+    };
+
+    let stored = sealed.unseal(&passphrase).map_err(|e| e.to_string())?;
+    save_keys_to_store(&app, &stored)?;
+    state.keys.set(stored);
+
+    Ok(())
+}
+
+fn load_pin_lock(app: &AppHandle) -> Option<PinLock> {
+    app.store(PIN_LOCK_FILE)
+        .ok()
+        .and_then(|store| store.get(PIN_LOCK_KEY))
+        .and_then(|value| serde_json::from_value(value).ok())
+}
+
+fn save_pin_lock(app: &AppHandle, lock: &PinLock) -> Result<(), String> {
+    let store = app.store(PIN_LOCK_FILE).map_err(|e| e.to_string())?;
+    store.set(PIN_LOCK_KEY, json!(lock));
+    store.save().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Seal `stored` with a profile key and write it to `file`
+fn seal_profile_keys(
+    app: &AppHandle,
+    file: &str,
+    stored: &StoredKeys,
+    key: &ProfileKey,
+) -> Result<(), String> {
+    let sealed = SealedKeys::seal_with(stored, &key.passphrase(), KdfParams::for_random_secret())
+        .map_err(|e| e.to_string())?;
+    let store = app.store(file).map_err(|e| e.to_string())?;
+    store.set(KEYS_KEY, json!(PersistedKeys::Sealed(sealed)));
+    store.save().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Whether the app is locked with a PIN
+#[tauri::command]
+pub fn has_app_pin(app: AppHandle) -> bool {
+    load_pin_lock(&app).is_some()
+}
+
+/// Lock the app with a PIN, which replaces any key passphrase. Changing the
+/// PIN needs the current one and clears the duress PIN.
+#[tauri::command]
+pub fn set_app_pin(
+    pin: String,
+    current_pin: Option<String>,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let pin = Zeroizing::new(pin);
+    let key = match load_pin_lock(&app) {
+        Some(lock) => {
+            let current = Zeroizing::new(current_pin.ok_or("Current PIN required")?);
+            match lock.open(&current).map_err(|e| e.to_string())? {
+                (id, key) if id.is_primary() => key,
+                _ => return Err(ProfileError::WrongPin.to_string()),
+            }
+        }
+        None => {
+            let key = ProfileKey::new_random();
+            let file = ProfileId::PRIMARY.store_file();
+            with_loaded_keys(&state, &app, |stored| seal_profile_keys(&app, &file, stored, &key))
+                .ok_or("Keys are locked")??;
+            key
+        }
+    };
+
+    let lock = PinLock::new(&pin, &key, &KdfParams::new_random()).map_err(|e| e.to_string())?;
+    save_pin_lock(&app, &lock)?;
+    *state.profile_key.lock().unwrap() = Some(key);
+    Ok(())
+}
+
+/// Set a second PIN that opens an empty decoy identity instead of ours
+#[tauri::command]
+pub fn set_duress_pin(pin: String, duress_pin: String, app: AppHandle) -> Result<(), String> {
+    let (pin, duress_pin) = (Zeroizing::new(pin), Zeroizing::new(duress_pin));
+    let mut lock = load_pin_lock(&app).ok_or("Set an app PIN first")?;
+
+    let decoy = ProfileId::new_random();
+    let decoy_key = ProfileKey::new_random();
+    lock.set_duress(&pin, &duress_pin, decoy, &decoy_key, &KdfParams::new_random())
+        .map_err(|e| e.to_string())?;
+
+    let (_, stored) = generate_keypair().map_err(|e| e.to_string())?;
+    seal_profile_keys(&app, &decoy.store_file(), &stored, &decoy_key)?;
+    save_pin_lock(&app, &lock)
+}
+
+/// Unlock the app with a PIN, switching to the profile it opens. The result
+/// looks the same for the app PIN and the duress PIN.
+#[tauri::command]
+pub async fn unlock_with_pin(
+    pin: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<NostrKeysInfo, String> {
+    let pin = Zeroizing::new(pin);
+    let lock = load_pin_lock(&app).ok_or("No app PIN set")?;
+    let (id, key) = lock.open(&pin).map_err(|e| e.to_string())?;
+
+    if *state.profile.lock().unwrap() != id {
+        reset_profile_state(&state).await?;
+        *state.profile.lock().unwrap() = id;
+    }
+
+    let stored = match load_persisted_keys(&app).ok_or("No keys found")? {
+        PersistedKeys::Sealed(sealed) => sealed
+            .unseal(&key.passphrase())
+            .map_err(|e| e.to_string())?,
+        persisted => platform_key_storage()
+            .load(persisted)
+            .map_err(|e| e.to_string())?
+            .ok_or("No keys found")?,
+    };
+    let info = get_public_key_info_from_stored(&stored).map_err(|e| e.to_string())?;
+    state.keys.set(stored);
+    *state.profile_key.lock().unwrap() = Some(key);
+
+    Ok(info)
+}
+
+/// Outcome of `rotate_keys`
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RotationResult {
+    pub keys: NostrKeysInfo,
+    /// Statement to hand to contacts that weren't connected (NFC, QR, file)
+    pub statement: String,
+    /// Contacts told over an open chat connection
+    pub notified: Vec<String>,
+}
+
+fn load_rotations_from_store(app: &AppHandle) -> Vec<RotationStatement> {
+    app.store(store_file(&app))
+        .ok()
+        .and_then(|store| store.get(ROTATIONS_KEY))
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+/// Replace our Nostr key with a new one. The old key signs a statement
+/// naming the new key, which is sent to every connected contact and kept for
+/// the rest. Contacts' Iroh endpoints are re-derived and the Iroh node is
+/// stopped, since it ran under the old key. Sealed keys need their
+/// passphrase, which also seals the new key; under an app PIN, the unlocked
+/// profile key is used instead.
+#[tauri::command]
+pub async fn rotate_keys(
+    passphrase: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<RotationResult, String> {
+    let old_keys = our_keys(&state).map_err(|_| "Keys are locked".to_string())?;
+    let (new_keys, new_stored) = generate_keypair().map_err(|e| e.to_string())?;
+    let statement =
+        RotationStatement::new(&old_keys, &new_keys, now_secs()).map_err(|e| e.to_string())?;
+
+    // Persist the new key before anyone hears about it
+    match load_persisted_keys(&app) {
+        // Sealed under the app PIN's profile key
+        Some(PersistedKeys::Sealed(_)) if passphrase.is_none() => {
+            let profile_key = state.profile_key.lock().unwrap();
+            let key = profile_key.as_ref().ok_or("Passphrase required")?;
+            seal_profile_keys(&app, &store_file(&app), &new_stored, key)?;
+        }
+        Some(PersistedKeys::Sealed(sealed)) => {
+            let passphrase = passphrase.ok_or("Passphrase required")?;
+            sealed.unseal(&passphrase).map_err(|e| e.to_string())?;
+            let resealed = SealedKeys::seal(&new_stored, &passphrase).map_err(|e| e.to_string())?;
+            save_persisted_keys(&app, &PersistedKeys::Sealed(resealed))?;
+        }
+        _ => save_keys_to_store(&app, &new_stored)?,
+    }
+    let info = get_public_key_info_from_stored(&new_stored).map_err(|e| e.to_string())?;
+
+    // Their nodes for us were derived from our old key; once they apply the
+    // rotation they run the ones derived from the new key
+    let mut contacts = load_contacts_from_store(&app);
+    rederive_endpoint_ids(&app, &new_stored, &mut contacts);
+
+    state.keys.set(new_stored);
+    record_key_use(&app, KeyUse::KeyRotation, Some(statement.new_pubkey.clone()))?;
+
+    let mut rotations = load_rotations_from_store(&app);
+    rotations.push(statement.clone());
+    let store = app.store(store_file(&app)).map_err(|e| e.to_string())?;
+    store.set(ROTATIONS_KEY, json!(rotations));
+    // The device certificate was for the old key
+    store.delete(DEVICE_CERT_KEY);
+    store.save().map_err(|e| e.to_string())?;
+    save_contacts_to_store(&app, &contacts)?;
+
+    // Tell whoever is connected, then shut down the node of the old key
+    let payload = serde_json::to_value(&statement).map_err(|e| e.to_string())?;
+    let mut notified = Vec::new();
     {
-        let _ = app;
-        let _ = json;
-        Err("NFC not supported on this platform".to_string())
+        let mut node = state.iroh_node.write().await;
+        let mut chat_manager = state.chat_manager.write().await;
+        if let Some(manager) = chat_manager.as_mut() {
+            for (pubkey, connection) in node.connections() {
+                if manager
+                    .send_typed_message(connection, pubkey, KIND_KEY_ROTATION, payload.clone())
+                    .await
+                    .is_ok()
+                {
+                    notified.push(pubkey.to_string());
+                }
+            }
+            manager.set_our_pubkey(&statement.new_pubkey);
+        }
+        node.stop().await.map_err(|e| e.to_string())?;
+    }
+
+    Ok(RotationResult {
+        keys: info,
+        statement: serde_json::to_string(&statement).map_err(|e| e.to_string())?,
+        notified,
+    })
+}
+
+/// Our rotation statements, oldest first. A contact who missed several
+/// rotations imports them in order.
+#[tauri::command]
+pub fn get_rotation_statements(app: AppHandle) -> Vec<RotationStatement> {
+    load_rotations_from_store(&app)
+}
+
+/// Move a contact to the key named in their verified rotation statement
+pub(crate) async fn apply_contact_rotation(
+    app: &AppHandle,
+    statement: &RotationStatement,
+) -> Result<Contact, String> {
+    statement.verify().map_err(|e| e.to_string())?;
+
+    let endpoint_id = app
+        .state::<AppState>()
+        .with_keys(|stored| peer_endpoint_id_for(app, stored, &statement.new_pubkey))
+        .ok_or("Keys are locked")??;
+
+    let mut contacts = load_contacts_from_store(app);
+    let contact = contacts
+        .iter_mut()
+        .find(|c| c.nostr_pubkey == statement.old_pubkey)
+        .ok_or("Contact not found")?;
+    // A revoked key can't vouch for anything, least of all its successor
+    if contact.revoked_at.is_some() {
+        return Err("Their key was revoked; exchange with them again".to_string());
+    }
+    contact.apply_rotation(&statement.new_pubkey, &endpoint_id);
+    let contact = contact.clone();
+    save_contacts_to_store(app, &contacts)?;
+
+    let state = app.state::<AppState>();
+    if let Some(manager) = state.chat_manager.write().await.as_mut() {
+        manager.merge_sessions(&statement.old_pubkey, &statement.new_pubkey);
+    }
+    let _ = app.emit(CONTACT_ROTATED_EVENT, &contact);
+
+    Ok(contact)
+}
+
+/// Apply a contact's rotation statement received over NFC, QR or as a file
+#[tauri::command]
+pub async fn import_rotation_statement(payload: String, app: AppHandle) -> Result<Contact, String> {
+    let statement = RotationStatement::from_json(&payload).map_err(|e| e.to_string())?;
+    apply_contact_rotation(&app, &statement).await
+}
+
+/// Outcome of `revoke_key`
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevocationResult {
+    /// Statement to hand to contacts that weren't connected (NFC, QR, file)
+    pub statement: String,
+    /// Contacts told over an open chat connection
+    pub notified: Vec<String>,
+    /// Relays that accepted the statement as a Nostr event
+    pub published: Vec<String>,
+}
+
+/// Revoke our key as compromised. The key signs a statement saying so, which
+/// is sent to every connected contact and, given `relays`, published there.
+/// Contacts mark our entry revoked and drop whatever the key sends after.
+/// There is no successor to vouch for: contacts have to exchange with our
+/// new key in person.
+#[tauri::command]
+pub async fn revoke_key(
+    reason: Option<String>,
+    relays: Option<Vec<String>>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<RevocationResult, String> {
+    let keys = our_keys(&state).map_err(|_| "Keys are locked".to_string())?;
+    let relays = match relays.filter(|r| !r.is_empty()) {
+        Some(relays) => remote::parse_relays(&relays).map_err(|e| e.to_string())?,
+        None => Vec::new(),
+    };
+    let statement =
+        RevocationStatement::new(&keys, reason, now_secs()).map_err(|e| e.to_string())?;
+    record_key_use(&app, KeyUse::KeyRevocation, None)?;
+
+    // Tell whoever is connected
+    let payload = serde_json::to_value(&statement).map_err(|e| e.to_string())?;
+    let mut notified = Vec::new();
+    {
+        let node = state.iroh_node.read().await;
+        let mut chat_manager = state.chat_manager.write().await;
+        if let Some(manager) = chat_manager.as_mut() {
+            for (pubkey, connection) in node.connections() {
+                if manager
+                    .send_typed_message(connection, pubkey, KIND_KEY_REVOCATION, payload.clone())
+                    .await
+                    .is_ok()
+                {
+                    notified.push(pubkey.to_string());
+                }
+            }
+        }
+    }
+
+    let event = statement.to_event(&keys).map_err(|e| e.to_string())?;
+    let mut published = Vec::new();
+    for relay in relays {
+        if relay::publish(&relay, &event).await.is_ok() {
+            published.push(relay);
+        }
+    }
+
+    Ok(RevocationResult {
+        statement: serde_json::to_string(&statement).map_err(|e| e.to_string())?,
+        notified,
+        published,
+    })
+}
+
+/// Mark the contact whose key the verified revocation statement names
+fn apply_contact_revocation(
+    app: &AppHandle,
+    statement: &RevocationStatement,
+) -> Result<Contact, String> {
+    statement.verify().map_err(|e| e.to_string())?;
+
+    let mut contacts = load_contacts_from_store(app);
+    let contact = contacts
+        .iter_mut()
+        .find(|c| c.nostr_pubkey == statement.pubkey)
+        .ok_or("Contact not found")?;
+    contact.apply_revocation(statement.revoked_at);
+    let contact = contact.clone();
+    save_contacts_to_store(app, &contacts)?;
+    let _ = app.emit(CONTACT_REVOKED_EVENT, &contact);
+
+    Ok(contact)
+}
+
+/// Handle an incoming `key-revocation`. Only the key being revoked may
+/// announce it.
+pub(crate) fn handle_key_revocation(app: &AppHandle, message: &ChatMessage) -> Result<(), String> {
+    let statement: RevocationStatement = message
+        .payload
+        .clone()
+        .and_then(|payload| serde_json::from_value(payload).ok())
+        .ok_or("Invalid revocation")?;
+    if statement.pubkey != message.sender_pubkey {
+        return Err("Revocation for someone else's key".to_string());
+    }
+    apply_contact_revocation(app, &statement).map(|_| ())
+}
+
+/// Apply a contact's revocation statement received over NFC, QR or as a file
+#[tauri::command]
+pub fn import_revocation_statement(payload: String, app: AppHandle) -> Result<Contact, String> {
+    let statement = RevocationStatement::from_json(&payload).map_err(|e| e.to_string())?;
+    apply_contact_revocation(&app, &statement)
+}
+
+// ============================================================================
+// Remote Signer Commands (NIP-46)
+// ============================================================================
+
+/// A paired remote signer and the identity it signs for
+#[derive(serde::Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteSignerInfo {
+    pub public_key_hex: String,
+    pub npub: String,
+    pub relays: Vec<String>,
+}
+
+impl RemoteSignerInfo {
+    fn from_signer(signer: &RemoteSigner) -> Self {
+        let session = signer.session();
+        Self {
+            npub: nostr::PublicKey::from_hex(&session.user_pubkey)
+                .ok()
+                .and_then(|pk| pk.to_bech32().ok())
+                .unwrap_or_default(),
+            public_key_hex: session.user_pubkey.clone(),
+            relays: session.uri.relays.clone(),
+        }
+    }
+}
+
+/// The paired remote signer, restored from the store on first use
+fn load_remote_signer(state: &AppState, app: &AppHandle) -> Option<Arc<RemoteSigner>> {
+    let mut cached = state.remote_signer.lock().unwrap();
+    if cached.is_none() {
+        *cached = app
+            .store(store_file(app))
+            .ok()
+            .and_then(|store| store.get(REMOTE_SIGNER_KEY))
+            .and_then(|value| serde_json::from_value::<RemoteSignerSession>(value).ok())
+            .and_then(|session| RemoteSigner::restore(session).ok())
+            .map(Arc::new);
+    }
+    cached.clone()
+}
+
+/// Pair with a remote signer from its `bunker://` URI. Exchange messages are
+/// signed by it from then on, each after the user approves on the signer.
+#[tauri::command]
+pub async fn connect_remote_signer(
+    bunker_uri: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<RemoteSignerInfo, String> {
+    let uri = BunkerUri::parse(&bunker_uri).map_err(|e| e.to_string())?;
+    let signer = RemoteSigner::connect(uri).await.map_err(|e| e.to_string())?;
+
+    let store = app.store(store_file(&app)).map_err(|e| e.to_string())?;
+    store.set(REMOTE_SIGNER_KEY, json!(signer.session()));
+    store.save().map_err(|e| e.to_string())?;
+
+    let info = RemoteSignerInfo::from_signer(&signer);
+    *state.remote_signer.lock().unwrap() = Some(Arc::new(signer));
+    Ok(info)
+}
+
+/// The paired remote signer, if any
+#[tauri::command]
+pub fn get_remote_signer(state: State<AppState>, app: AppHandle) -> Option<RemoteSignerInfo> {
+    load_remote_signer(&state, &app).map(|signer| RemoteSignerInfo::from_signer(&signer))
+}
+
+/// Forget the remote signer and go back to signing with local keys
+#[tauri::command]
+pub fn disconnect_remote_signer(state: State<AppState>, app: AppHandle) -> Result<(), String> {
+    let store = app.store(store_file(&app)).map_err(|e| e.to_string())?;
+    store.delete(REMOTE_SIGNER_KEY);
+    store.save().map_err(|e| e.to_string())?;
+    state.remote_signer.lock().unwrap().take();
+    Ok(())
+}
+
+// ============================================================================
+// Device Migration Commands
+// ============================================================================
+
+/// Pack our secret key and contacts into passphrase-encrypted QR payloads,
+/// shown one after another for `scan_identity_qr` on the new phone
+#[tauri::command]
+pub fn export_identity_qr(
+    passphrase: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<Vec<String>, String> {
+    let contacts = load_contacts_from_store(&app);
+    let payloads = state
+        .with_keys(|stored| {
+            let bundle = MigrationBundle {
+                keys: stored,
+                contacts,
+                created_at: now_secs(),
+            };
+            migration::export(&bundle, &passphrase)
+        })
+        .ok_or("Keys are locked")?
+        .map_err(|e| e.to_string())?;
+    record_key_use(&app, KeyUse::IdentityExport, Some("migration".to_string()))?;
+
+    Ok(payloads)
+}
+
+/// Add a scanned migration QR code, in any order
+#[tauri::command]
+pub fn scan_identity_qr(
+    payload: String,
+    state: State<AppState>,
+) -> Result<MigrationProgress, String> {
+    let chunk = MigrationChunk::parse(&payload).map_err(|e| e.to_string())?;
+    let mut assembler = state.migration.lock().unwrap();
+    match assembler.add(chunk.clone()) {
+        // Scanning a new migration starts over
+        Err(MigrationError::DifferentMigration) => {
+            *assembler = MigrationAssembler::default();
+            assembler.add(chunk).map_err(|e| e.to_string())
+        }
+        result => result.map_err(|e| e.to_string()),
+    }
+}
+
+/// Open the scanned migration with its passphrase and take over the identity
+/// and contacts it carries. Contacts we already have are kept as they are.
+#[tauri::command]
+pub fn import_identity_qr(
+    passphrase: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<NostrKeysInfo, String> {
+    let bundle = state
+        .migration
+        .lock()
+        .unwrap()
+        .open(&passphrase)
+        .map_err(|e| e.to_string())?;
+    let info = adopt_keys(&state, &app, bundle.keys)?;
+
+    // Same identity, so their node IDs for us still hold
+    let mut contacts = load_contacts_from_store(&app);
+    for contact in bundle.contacts {
+        if contacts.iter().any(|c| c.nostr_pubkey == contact.nostr_pubkey) {
+            continue;
+        }
+        contacts.push(contact);
+    }
+    save_contacts_to_store(&app, &contacts)?;
+
+    *state.migration.lock().unwrap() = MigrationAssembler::default();
+    Ok(info)
+}
+
+// ============================================================================
+// Device Subkey Commands
+// ============================================================================
+
+/// Switch this device to `cert` (or back to the master key with `None`):
+/// contacts' node IDs for the old identity are forgotten, and the Iroh node
+/// is stopped so the next start uses the new identity
+async fn switch_device_identity(
+    state: &AppState,
+    app: &AppHandle,
+    master_pubkey: &str,
+    cert: Option<&DeviceCertificate>,
+) -> Result<(), String> {
+    let store = app.store(store_file(app)).map_err(|e| e.to_string())?;
+    match cert {
+        Some(cert) => store.set(DEVICE_CERT_KEY, json!(cert)),
+        None => {
+            store.delete(DEVICE_CERT_KEY);
+        }
+    }
+    store.save().map_err(|e| e.to_string())?;
+
+    // Their nodes for us are derived from the key we exchanged with
+    let mut contacts = load_contacts_from_store(app);
+    for contact in contacts.iter_mut() {
+        contact.iroh_endpoint_id.clear();
+    }
+    save_contacts_to_store(app, &contacts)?;
+
+    state.iroh_node.write().await.stop().await.map_err(|e| e.to_string())?;
+    if let Some(manager) = state.chat_manager.write().await.as_mut() {
+        let pubkey = cert.map_or(master_pubkey, |c| &c.device_pubkey);
+        manager.set_our_pubkey(pubkey);
+    }
+    Ok(())
+}
+
+/// Give this device its own subkey under our identity, certified by the
+/// master key. Other devices with the same identity use other indexes.
+#[tauri::command]
+pub async fn enable_device_subkey(
+    device_index: u32,
+    device_name: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<DeviceCertificate, String> {
+    if load_remote_signer(&state, &app).is_some() {
+        return Err("Device subkeys aren't used with a remote signer".to_string());
+    }
+    let issued = state.with_keys(|master| {
+        let issued = DeviceCertificate::issue(master, device_index, &device_name, now_secs());
+        issued.map(|(cert, _)| (cert, master.public_key_hex.clone()))
+    });
+    let (cert, master_pubkey) = issued
+        .ok_or("Keys are locked")?
+        .map_err(|e| e.to_string())?;
+    record_key_use(
+        &app,
+        KeyUse::DeviceCertificate,
+        Some(format!("{} ({})", cert.device_name, cert.device_index)),
+    )?;
+
+    switch_device_identity(&state, &app, &master_pubkey, Some(&cert)).await?;
+    Ok(cert)
+}
+
+/// This device's certificate, if it has a subkey for our current identity
+#[tauri::command]
+pub fn get_device_certificate(state: State<AppState>, app: AppHandle) -> Option<DeviceCertificate> {
+    let master = identity_master_pubkey(&state, &app)?;
+    load_device_cert(&app).filter(|c| c.master_pubkey == master)
+}
+
+/// Go back to using the master key on this device
+#[tauri::command]
+pub async fn disable_device_subkey(
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let master_pubkey = state.our_pubkey().ok_or("Keys are locked")?;
+    switch_device_identity(&state, &app, &master_pubkey, None).await
+}
+
+// ============================================================================
+// Social Recovery Commands
+// ============================================================================
+
+fn load_guardian_store(app: &AppHandle) -> GuardianStore {
+    app.store(store_file(&app))
+        .ok()
+        .and_then(|store| store.get(RECOVERY_GUARDIAN_KEY))
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+fn save_guardian_store(app: &AppHandle, guardian: &GuardianStore) -> Result<(), String> {
+    let store = app.store(store_file(&app)).map_err(|e| e.to_string())?;
+    store.set(RECOVERY_GUARDIAN_KEY, json!(guardian));
+    store.save().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn load_recovery_session(app: &AppHandle) -> Option<RecoverySession> {
+    app.store(store_file(&app))
+        .ok()
+        .and_then(|store| store.get(RECOVERY_SESSION_KEY))
+        .and_then(|value| serde_json::from_value(value).ok())
+}
+
+fn save_recovery_session(app: &AppHandle, session: Option<&RecoverySession>) -> Result<(), String> {
+    let store = app.store(store_file(&app)).map_err(|e| e.to_string())?;
+    match session {
+        Some(session) => store.set(RECOVERY_SESSION_KEY, json!(session)),
+        None => {
+            store.delete(RECOVERY_SESSION_KEY);
+        }
+    }
+    store.save().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Split our key among `guardians`, any `threshold` of whom can later help
+/// us recover it. Every guardian must be connected, so a split is never
+/// half-delivered.
+#[tauri::command]
+pub async fn distribute_recovery_shares(
+    guardians: Vec<String>,
+    threshold: u8,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<GuardianSet, String> {
+    let total = u8::try_from(guardians.len()).map_err(|_| "Too many guardians")?;
+    let (keys, shares) = state
+        .with_keys(|stored| {
+            let keys = restore_keys(stored).map_err(|e| e.to_string())?;
+            let shares = split_key(stored, total, threshold).map_err(|e| e.to_string())?;
+            Ok::<_, String>((keys, shares))
+        })
+        .ok_or("Keys are locked")??;
+
+    let node = state.iroh_node.read().await;
+    let connections = guardians
+        .iter()
+        .map(|pubkey| {
+            node.get_connection(pubkey)
+                .cloned()
+                .ok_or_else(|| format!("Not connected to guardian {}", pubkey))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    drop(node);
+
+    let mut chat_manager_guard = state.chat_manager.write().await;
+    let chat_manager = chat_manager_guard
+        .as_mut()
+        .ok_or("Chat manager not initialized")?;
+    for ((pubkey, connection), share) in guardians.iter().zip(&connections).zip(&shares) {
+        let envelope = ShareEnvelope::seal(&keys, pubkey, share).map_err(|e| e.to_string())?;
+        let payload = serde_json::to_value(&envelope).map_err(|e| e.to_string())?;
+        chat_manager
+            .send_typed_message(connection, pubkey, KIND_RECOVERY_SHARE, payload)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    drop(chat_manager_guard);
+
+    record_key_use(
+        &app,
+        KeyUse::SocialRecovery,
+        Some(format!("{} of {}", threshold, total)),
+    )?;
+    let set = GuardianSet {
+        set_id: shares[0].set_id.clone(),
+        threshold,
+        guardians,
+        created_at: now_secs(),
+    };
+    let store = app.store(store_file(&app)).map_err(|e| e.to_string())?;
+    store.set(RECOVERY_GUARDIANS_KEY, json!(set));
+    store.save().map_err(|e| e.to_string())?;
+
+    Ok(set)
+}
+
+/// Who holds shares of our key, from the last `distribute_recovery_shares`
+#[tauri::command]
+pub fn get_recovery_guardians(app: AppHandle) -> Option<GuardianSet> {
+    app.store(store_file(&app))
+        .ok()
+        .and_then(|store| store.get(RECOVERY_GUARDIANS_KEY))
+        .and_then(|value| serde_json::from_value(value).ok())
+}
+
+/// Shares we hold for contacts
+#[tauri::command]
+pub fn get_held_recovery_shares(app: AppHandle) -> Vec<HeldShare> {
+    load_guardian_store(&app).held().to_vec()
+}
+
+/// Requests for shares we hold, waiting for approval
+#[tauri::command]
+pub fn get_recovery_requests(app: AppHandle) -> Vec<PendingRecoveryRequest> {
+    load_guardian_store(&app).requests().to_vec()
+}
+
+/// Send the share we hold for `owner_pubkey` to the device asking for it.
+/// Only approve after confirming in person that the requester is the owner.
+#[tauri::command]
+pub async fn approve_recovery_request(
+    owner_pubkey: String,
+    requester_pubkey: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let keys = our_keys(&state)?;
+    let mut guardian = load_guardian_store(&app);
+    let envelope = guardian
+        .release(&keys, &owner_pubkey, &requester_pubkey)
+        .map_err(|e| e.to_string())?;
+
+    let node = state.iroh_node.read().await;
+    let connection = node
+        .get_connection(&requester_pubkey)
+        .ok_or("Not connected to the requester")?
+        .clone();
+    drop(node);
+
+    let payload = serde_json::to_value(&envelope).map_err(|e| e.to_string())?;
+    let mut chat_manager_guard = state.chat_manager.write().await;
+    chat_manager_guard
+        .as_mut()
+        .ok_or("Chat manager not initialized")?
+        .send_typed_message(&connection, &requester_pubkey, KIND_RECOVERY_SHARE, payload)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    guardian
+        .take_request(&owner_pubkey, &requester_pubkey)
+        .map_err(|e| e.to_string())?;
+    save_guardian_store(&app, &guardian)
+}
+
+#[tauri::command]
+pub fn decline_recovery_request(
+    owner_pubkey: String,
+    requester_pubkey: String,
+    app: AppHandle,
+) -> Result<(), String> {
+    let mut guardian = load_guardian_store(&app);
+    guardian
+        .take_request(&owner_pubkey, &requester_pubkey)
+        .map_err(|e| e.to_string())?;
+    save_guardian_store(&app, &guardian)
+}
+
+/// Ask `guardians` for their shares of `owner_pubkey`. Run from the
+/// temporary identity of a new device, connected to the guardians.
+#[tauri::command]
+pub async fn start_social_recovery(
+    owner_pubkey: String,
+    guardians: Vec<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<RecoveryStatus, String> {
+    let payload = serde_json::to_value(RecoveryRequest {
+        owner_pubkey: owner_pubkey.clone(),
+    })
+    .map_err(|e| e.to_string())?;
+
+    let mut requested_from = Vec::new();
+    {
+        let node = state.iroh_node.read().await;
+        let mut chat_manager_guard = state.chat_manager.write().await;
+        let chat_manager = chat_manager_guard
+            .as_mut()
+            .ok_or("Chat manager not initialized")?;
+        for pubkey in &guardians {
+            let Some(connection) = node.get_connection(pubkey) else {
+                continue;
+            };
+            if chat_manager
+                .send_typed_message(connection, pubkey, KIND_RECOVERY_REQUEST, payload.clone())
+                .await
+                .is_ok()
+            {
+                requested_from.push(pubkey.clone());
+            }
+        }
+    }
+    if requested_from.is_empty() {
+        return Err("Not connected to any of the guardians".to_string());
+    }
+
+    // Guardians asked again keep counting towards the same recovery
+    let session = match load_recovery_session(&app) {
+        Some(mut session) if session.owner_pubkey() == owner_pubkey => {
+            session.add_requested(&requested_from);
+            session
+        }
+        _ => RecoverySession::new(&owner_pubkey, requested_from, now_secs()),
+    };
+    save_recovery_session(&app, Some(&session))?;
+
+    Ok(session.status())
+}
+
+#[tauri::command]
+pub fn get_social_recovery_status(app: AppHandle) -> Option<RecoveryStatus> {
+    load_recovery_session(&app).map(|session| session.status())
+}
+
+#[tauri::command]
+pub fn cancel_social_recovery(app: AppHandle) -> Result<(), String> {
+    save_recovery_session(&app, None)
+}
+
+/// Replace the temporary identity with the one reassembled from the
+/// guardians' shares. Contacts' Iroh endpoints are re-derived and the node
+/// is stopped, as after a rotation.
+#[tauri::command]
+pub async fn complete_social_recovery(
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<NostrKeysInfo, String> {
+    let session = load_recovery_session(&app).ok_or("No recovery in progress")?;
+    let stored = session.recover().map_err(|e| e.to_string())?;
+
+    save_keys_to_store(&app, &stored)?;
+    let info = get_public_key_info_from_stored(&stored).map_err(|e| e.to_string())?;
+    state.keys.set(stored);
+    save_recovery_session(&app, None)?;
+
+    if let Some(manager) = state.chat_manager.write().await.as_mut() {
+        manager.set_our_pubkey(&info.public_key);
+    }
+    state
+        .iroh_node
+        .write()
+        .await
+        .stop()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(info)
+}
+
+/// Handle an incoming `recovery-share`: a contact depositing a share of
+/// their key with us, or a guardian returning one to our recovery
+pub(crate) fn handle_recovery_share(app: &AppHandle, message: &ChatMessage) -> Result<(), String> {
+    let envelope: ShareEnvelope = message
+        .payload
+        .clone()
+        .and_then(|payload| serde_json::from_value(payload).ok())
+        .ok_or("Invalid recovery share")?;
+    let keys = our_keys(&app.state::<AppState>())?;
+    let sender = &message.sender_pubkey;
+
+    if envelope.owner_pubkey == *sender {
+        // Check it opens before keeping it
+        envelope.open(&keys, sender).map_err(|e| e.to_string())?;
+        let mut guardian = load_guardian_store(app);
+        guardian.hold(envelope, now_secs());
+        return save_guardian_store(app, &guardian);
+    }
+
+    let mut session = load_recovery_session(app)
+        .filter(|s| s.owner_pubkey() == envelope.owner_pubkey && s.expects(sender))
+        .ok_or("Unexpected recovery share")?;
+    let share = envelope.open(&keys, sender).map_err(|e| e.to_string())?;
+    session.add_share(sender, share).map_err(|e| e.to_string())?;
+    save_recovery_session(app, Some(&session))?;
+    let _ = app.emit(RECOVERY_PROGRESS_EVENT, session.status());
+
+    Ok(())
+}
+
+/// Queue an incoming `recovery-request` for the user's approval
+pub(crate) fn handle_recovery_request(
+    app: &AppHandle,
+    message: &ChatMessage,
+) -> Result<(), String> {
+    let request: RecoveryRequest = message
+        .payload
+        .clone()
+        .and_then(|payload| serde_json::from_value(payload).ok())
+        .ok_or("Invalid recovery request")?;
+
+    let mut guardian = load_guardian_store(app);
+    if guardian.add_request(&request.owner_pubkey, &message.sender_pubkey, now_secs()) {
+        save_guardian_store(app, &guardian)?;
+        let _ = app.emit(RECOVERY_REQUEST_EVENT, guardian.requests().last());
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_public_key(state: State<AppState>, app: AppHandle) -> Result<NostrKeysInfo, String> {
+    // Check cache first
+    if let Some(info) = state.with_keys(get_public_key_info_from_stored) {
+        return info.map_err(|e| e.to_string());
+    }
+
+    // Try to load from store
+    let Some(stored) = try_load_keys_from_store(&app).map_err(|e| e.to_string())? else {
+        return match load_persisted_keys(&app) {
+            Some(PersistedKeys::WatchOnly(watch_only)) => {
+                get_public_key_info_from_hex(&watch_only.public_key_hex)
+                    .map_err(|e| e.to_string())
+            }
+            _ => Err("No keys found".to_string()),
+        };
+    };
+
+    // Cache it
+    let info = get_public_key_info_from_stored(&stored).map_err(|e| e.to_string())?;
+    state.keys.set(stored);
+
+    Ok(info)
+}
+
+// ============================================================================
+// Data Wipe
+// ============================================================================
+
+/// Stop networking and forget everything loaded from the active profile
+async fn reset_profile_state(state: &AppState) -> Result<(), String> {
+    state
+        .iroh_node
+        .write()
+        .await
+        .stop()
+        .await
+        .map_err(|e| e.to_string())?;
+    crate::hce::disarm();
+    drop(state.contact_db.lock().map_err(|e| e.to_string())?.take());
+
+    state.keys.invalidate();
+    state.contacts.invalidate();
+    state.settings.invalidate();
+    *state.chat_manager.write().await = None;
+    state.nfc_exchange.lock().unwrap().reset();
+    state.exchange_timer.lock().unwrap().take();
+    state.beacon.lock().unwrap().take();
+    state.conference.lock().unwrap().take();
+    state.conference_cancel.notify_waiters();
+    *state.exchange_queue.lock().unwrap() = ExchangeQueue::default();
+    *state.inbound_limiter.lock().unwrap() = InboundLimiter::default();
+    state.payload_quarantine.lock().unwrap().clear();
+    state.connection_trace.lock().unwrap().take();
+    state.export_confirmation.lock().unwrap().take();
+    state.profile_key.lock().unwrap().take();
+    state.remote_signer.lock().unwrap().take();
+    *state.migration.lock().unwrap() = MigrationAssembler::default();
+    state.qr_frames.lock().unwrap().reset();
+    Ok(())
+}
+
+/// Erase keys, contacts, chat history and everything else the app keeps, and
+/// close the Iroh endpoint. Stored data is either all removed or, if moving
+/// it aside fails, left untouched. Emits `app://wiped` when done so the UI
+/// can return to onboarding.
+#[tauri::command]
+pub async fn wipe_all_data(state: State<'_, AppState>, app: AppHandle) -> Result<(), String> {
+    // Stops the node and closes the contact database so its files can be moved
+    reset_profile_state(&state).await?;
+
+    let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let files: Vec<String> = std::fs::read_dir(&dir)
+        .map_err(|e| e.to_string())?
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| is_app_data_file(name))
+        .collect();
+    let files: Vec<&str> = files.iter().map(String::as_str).collect();
+    let staged = wipe::stage(&dir, &files).map_err(|e| format!("Nothing was wiped: {}", e))?;
+
+    // The store plugin still holds the old contents in memory
+    for file in [store_file(&app), PIN_LOCK_FILE.to_string()] {
+        let store = app.store(file).map_err(|e| e.to_string())?;
+        store.clear();
+        store.save().map_err(|e| e.to_string())?;
+    }
+    *state.profile.lock().unwrap() = ProfileId::PRIMARY;
+
+    // Leftovers are shredded on the next start if this fails
+    let _ = staged.shred();
+
+    let _ = app.emit(WIPE_COMPLETE_EVENT, ());
+    Ok(())
+}
+
+// ============================================================================
+// Exchange Transports
+// ============================================================================
+
+/// A way of carrying exchange payloads between two devices. A transport only
+/// decides how a payload travels; `send_via` and `receive` do the signing,
+/// verification, session and progress handling the same way for all of them.
+trait ExchangeTransport {
+    /// What the frontend gets back to show, play or pass on
+    type Output;
+    type Error: From<String>;
+
+    fn kind(&self) -> Transport;
+
+    /// Sign our message for `their_pubkey`, or our initial message
+    async fn prepare_payload(
+        &self,
+        state: &AppState,
+        app: &AppHandle,
+        their_pubkey: Option<&str>,
+    ) -> Result<String, Self::Error> {
+        let protocol = peer_protocol(state, their_pubkey);
+        Ok(exchange_payload(state, app, their_pubkey, protocol).await?)
+    }
+
+    /// Hand a signed payload to the other device, or turn it into what the
+    /// frontend hands over
+    fn deliver(
+        &self,
+        state: &AppState,
+        app: &AppHandle,
+        payload: &str,
+        their_pubkey: Option<&str>,
+    ) -> Result<Self::Output, Self::Error>;
+
+    /// Verify a payload the other device sent this way, and remember the peer
+    fn receive(
+        &self,
+        state: &AppState,
+        app: &AppHandle,
+        payload: &str,
+    ) -> Result<ExchangeMessage, ScanError> {
+        accept_scanned_payload(payload, state, app, self.kind())
+    }
+}
+
+/// Send our initial message, or our response to `their_pubkey`, over
+/// `transport` and move the session along once it is on its way
+async fn send_via<T: ExchangeTransport>(
+    transport: &T,
+    state: &AppState,
+    app: &AppHandle,
+    their_pubkey: Option<&str>,
+) -> Result<T::Output, T::Error> {
+    let kind = transport.kind();
+    if their_pubkey.is_none() {
+        begin_exchange_timer(state, app, kind);
+    }
+
+    let payload = transport.prepare_payload(state, app, their_pubkey).await?;
+    emit_exchange_state(app, kind, ExchangePhase::WaitingForTap, None);
+    let output = transport.deliver(state, app, &payload, their_pubkey)?;
+
+    match their_pubkey {
+        Some(their_pk) => {
+            state
+                .exchange_queue
+                .lock()
+                .unwrap()
+                .mark_responded(their_pk);
+            advance_session(app, |session| session.respond(their_pk, now_secs()));
+            emit_exchange_state(app, kind, ExchangePhase::Responded, Some(their_pk));
+        }
+        None => {
+            advance_session(app, |session| {
+                session.broadcast(kind, now_secs());
+                Ok(())
+            });
+            emit_exchange_state(app, kind, ExchangePhase::AwaitingResponse, None);
+        }
+    }
+    Ok(output)
+}
+
+/// Finish accepting a message that passed its transport's checks: refuse a
+/// replay or a response from another session, then queue the peer and note
+/// them in the session
+fn record_verified(
+    state: &AppState,
+    app: &AppHandle,
+    transport: Transport,
+    msg: &ExchangeMessage,
+    tolerance: TimestampTolerance,
+) -> Result<(), ScanError> {
+    check_session(state, msg)?;
+    check_replay(state, app, msg, tolerance)?;
+    emit_exchange_state(app, transport, ExchangePhase::Verified, Some(&msg.pubkey));
+    track_pending_exchange(state, msg, transport);
+    advance_session(app, |session| {
+        session.receive(&msg.pubkey, transport, now_secs());
+        Ok(())
+    });
+    Ok(())
+}
+
+/// NFC: served over Host Card Emulation on Android, written to the tag
+/// elsewhere. Returns whose payload was delivered.
+struct NfcTransport;
+
+impl ExchangeTransport for NfcTransport {
+    type Output = String;
+    type Error = NfcError;
+
+    fn kind(&self) -> Transport {
+        Transport::Nfc
+    }
+
+    async fn prepare_payload(
+        &self,
+        state: &AppState,
+        app: &AppHandle,
+        their_pubkey: Option<&str>,
+    ) -> Result<String, NfcError> {
+        prepare_nfc_payload(state, app, their_pubkey).await
+    }
+
+    fn deliver(
+        &self,
+        state: &AppState,
+        app: &AppHandle,
+        payload: &str,
+        their_pubkey: Option<&str>,
+    ) -> Result<String, NfcError> {
+        let stage = if their_pubkey.is_some() {
+            "respond"
+        } else {
+            "broadcast"
+        };
+        track_nfc_result(state, app, stage, deliver_nfc_payload(app, payload))?;
+        {
+            let mut nfc_state = state.nfc_exchange.lock().unwrap();
+            nfc_state.clear_pending();
+            nfc_state.awaiting_response |= their_pubkey.is_none();
+        }
+
+        // Whoever signed it, local keys or a remote signer
+        ExchangeMessage::decode(payload.as_bytes())
+            .map(|msg| msg.pubkey)
+            .map_err(|e| NfcError::Other(e.to_string()))
+    }
+}
+
+/// QR codes, animated when the payload doesn't fit in one. `legacy` makes
+/// an initial code that version 1 apps can scan.
+struct QrTransport {
+    legacy: bool,
+}
+
+impl ExchangeTransport for QrTransport {
+    type Output = Vec<String>;
+    type Error = String;
+
+    fn kind(&self) -> Transport {
+        Transport::Qr
+    }
+
+    async fn prepare_payload(
+        &self,
+        state: &AppState,
+        app: &AppHandle,
+        their_pubkey: Option<&str>,
+    ) -> Result<String, String> {
+        let protocol = if self.legacy {
+            Negotiated::V1
+        } else {
+            peer_protocol(state, their_pubkey)
+        };
+        let payload = exchange_payload(state, app, their_pubkey, protocol).await?;
+        // A response on screen is sealed to the one person who should scan it
+        match their_pubkey {
+            Some(their_pk) if protocol.has(CAP_SEALED_RESPONSE) => {
+                seal_payload(&payload, their_pk).map_err(|e| e.to_string())
+            }
+            _ => Ok(payload),
+        }
+    }
+
+    fn deliver(
+        &self,
+        _state: &AppState,
+        _app: &AppHandle,
+        payload: &str,
+        _their_pubkey: Option<&str>,
+    ) -> Result<Vec<String>, String> {
+        multipart::qr_frames(payload).map_err(|e| e.to_string())
+    }
+
+    fn receive(
+        &self,
+        state: &AppState,
+        app: &AppHandle,
+        payload: &str,
+    ) -> Result<ExchangeMessage, ScanError> {
+        let payload = open_sealed_payload(payload, exchange_keys(state, app).as_ref())?;
+        accept_scanned_payload(&payload, state, app, Transport::Qr)
+    }
+}
+
+/// Sound, for when there is neither NFC nor a camera: a `data:audio/wav`
+/// URL to play in a loop
+struct AudioTransport;
+
+impl ExchangeTransport for AudioTransport {
+    type Output = String;
+    type Error = String;
+
+    fn kind(&self) -> Transport {
+        Transport::Audio
+    }
+
+    fn deliver(
+        &self,
+        _state: &AppState,
+        _app: &AppHandle,
+        payload: &str,
+        _their_pubkey: Option<&str>,
+    ) -> Result<String, String> {
+        let samples = audio::encode(payload.as_bytes(), audio::BROADCAST_SAMPLE_RATE)
+            .map_err(|e| e.to_string())?;
+        let wav = audio::wav_bytes(&samples, audio::BROADCAST_SAMPLE_RATE);
+        Ok(format!("data:audio/wav;base64,{}", STANDARD.encode(wav)))
+    }
+}
+
+/// Any other channel the user sends it through themselves: an exchange link
+/// when `uri` is set, otherwise the compact `sn2:` text
+struct LinkTransport {
+    uri: bool,
+}
+
+impl ExchangeTransport for LinkTransport {
+    type Output = String;
+    type Error = String;
+
+    fn kind(&self) -> Transport {
+        Transport::Link
+    }
+
+    fn deliver(
+        &self,
+        _state: &AppState,
+        _app: &AppHandle,
+        payload: &str,
+        _their_pubkey: Option<&str>,
+    ) -> Result<String, String> {
+        if !self.uri {
+            return Ok(payload.to_string());
+        }
+        exchange_uri(payload)
+            .ok_or_else(|| "Their app is too old to open exchange links".to_string())
+    }
+}
+
+// ============================================================================
+// NFC Exchange Commands
+// ============================================================================
+
+#[tauri::command]
+pub async fn is_nfc_available(app: AppHandle) -> Result<bool, String> {
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    {
+        use tauri_plugin_nfc::NfcExt;
+        app.nfc()
+            .is_available()
+            .map_err(|e| e.to_string())
+    }
+    
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    {
+        let _ = app;
+        Ok(crate::pcsc_nfc::reader_available())
+    }
+}
+
+/// Write our payload as SneakerNet NDEF records (one unless it needs
+/// chunking), re-arming after transient failures
+#[cfg(any(target_os = "android", target_os = "ios"))]
+fn write_exchange_record(app: &AppHandle, payload: &str) -> Result<(), NfcError> {
+    use tauri_plugin_nfc::{NfcRecord, NfcExt, NFCTypeNameFormat};
+
+    let chunks = crate::nfc::chunk_payload(payload.as_bytes());
+    crate::nfc::retry_transient(|_| {
+        let records = chunks
+            .iter()
+            .map(|chunk| NfcRecord {
+                format: NFCTypeNameFormat::Media,
+                kind: crate::exchange::NDEF_MIME_TYPE.as_bytes().to_vec(),
+                id: vec![],
+                payload: chunk.clone(),
+            })
+            .collect();
+        app.nfc()
+            .write(records)
+            .map_err(|e| NfcError::classify(&e.to_string()))
+    })
+}
+
+/// Hand a signed payload to the other device.
+///
+/// Android phones can't be written to as tags, so where the build can
+/// emulate a card (`android-hce`) the payload is served via Host Card
+/// Emulation until the exchange ends; otherwise Android and iOS write it to
+/// the tag. On desktop it is written to a tag on a USB reader (`pcsc`
+/// feature).
+fn deliver_nfc_payload(app: &AppHandle, payload: &str) -> Result<(), NfcError> {
+    #[cfg(target_os = "android")]
+    if crate::hce::AVAILABLE {
+        crate::hce::arm(payload.as_bytes());
+        return Ok(());
+    }
+
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    {
+        write_exchange_record(app, payload)
+    }
+
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    {
+        let _ = app;
+        crate::nfc::retry_transient(|_| crate::pcsc_nfc::write_exchange_payload(payload))
+    }
+}
+
+/// Sign (or reuse) the payload we are about to write via NFC
+async fn prepare_nfc_payload(
+    state: &AppState,
+    app: &AppHandle,
+    their_pubkey: Option<&str>,
+) -> Result<String, NfcError> {
+    {
+        let nfc_state = state.nfc_exchange.lock().unwrap();
+        if let Some(payload) = nfc_state.reusable_payload(their_pubkey) {
+            return Ok(payload.to_string());
+        }
+    }
+
+    if exchange_signer(state, app).is_err() {
+        return Err(NfcError::NoKeys);
+    }
+    let payload = exchange_payload(state, app, their_pubkey, peer_protocol(state, their_pubkey))
+        .await
+        .map_err(NfcError::Other)?;
+
+    state
+        .nfc_exchange
+        .lock()
+        .unwrap()
+        .set_pending(payload.clone(), their_pubkey);
+
+    Ok(payload)
+}
+
+/// Record the outcome of an NFC operation in the resumable exchange state and stats
+fn track_nfc_result<T>(
+    state: &AppState,
+    app: &AppHandle,
+    stage: &str,
+    result: Result<T, NfcError>,
+) -> Result<T, NfcError> {
+    if let Err(ref e) = result {
+        state.nfc_exchange.lock().unwrap().last_error = Some(e.clone());
+        update_stats(app, |s| s.record_failure(Transport::Nfc, stage, e.kind()));
+        emit_exchange_state(app, Transport::Nfc, ExchangePhase::Failed, None);
+    }
+    result
+}
+
+/// Start broadcasting our exchange message via NFC
+/// On Android with card emulation this arms the HCE responder and returns
+/// immediately; the other device should be in receive mode to read this
+#[tauri::command]
+pub async fn start_nfc_broadcast(
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<String, NfcError> {
+    // Serve (Android with HCE) or write to a tag (elsewhere) our initial
+    // message, reused if an earlier write broke off
+    send_via(&NfcTransport, &state, &app, None).await
+}
+
+/// Receive and process an NFC exchange message (read mode)
+/// Returns their pubkey if successful
+#[tauri::command]
+pub async fn start_nfc_receive(
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<String, NfcError> {
+    begin_exchange_timer(&state, &app, Transport::Nfc);
+    emit_exchange_state(&app, Transport::Nfc, ExchangePhase::WaitingForTap, None);
+
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    {
+        use tauri_plugin_nfc::NfcExt;
+        
+        use tauri_plugin_nfc::NFCTypeNameFormat;
+
+        // Scan for any NDEF tag, re-arming on tag loss: ours carry a
+        // SneakerNet record, other Nostr apps write a `nostr:` URI
+        let scan_result = track_nfc_result(
+            &state,
+            &app,
+            "scan",
+            crate::nfc::retry_transient(|_| {
+                app.nfc()
+                    .scan(tauri_plugin_nfc::ScanRequest {
+                        kind: tauri_plugin_nfc::ScanKind::Ndef {
+                            mime_type: None,
+                            uri: None,
+                            tech_list: None,
+                        },
+                        keep_session_alive: true,
+                    })
+                    .map_err(|e| NfcError::classify(&e.to_string()))
+            }),
+        )?;
+        emit_exchange_state(&app, Transport::Nfc, ExchangePhase::TagDetected, None);
+        
+        // Extract the records from the tag, ours first
+        let (ours, others): (Vec<_>, Vec<_>) = scan_result
+            .tag
+            .records
+            .into_iter()
+            .partition(|r| r.format == NFCTypeNameFormat::Media);
+        let payloads = ours
+            .into_iter()
+            .filter(|r| r.kind == crate::exchange::NDEF_MIME_TYPE.as_bytes())
+            .map(|r| r.payload)
+            .chain(
+                others
+                    .into_iter()
+                    .filter(|r| r.format == NFCTypeNameFormat::NfcWellKnown && r.kind == b"U")
+                    .filter_map(|r| crate::pcsc_nfc::uri_from_payload(&r.payload))
+                    .filter(|uri| uri.starts_with(NOSTR_URI_PREFIX))
+                    .map(String::into_bytes),
+            )
+            .collect();
+        accept_nfc_payloads(&state, &app, payloads)
+    }
+    
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    {
+        // USB reader via PC/SC, when built with the `pcsc` feature
+        let payloads = track_nfc_result(
+            &state,
+            &app,
+            "scan",
+            tauri::async_runtime::spawn_blocking(|| {
+                crate::nfc::retry_transient(|_| crate::pcsc_nfc::read_exchange_payloads())
+            })
+            .await
+            .map_err(|e| NfcError::Other(e.to_string()))
+            .and_then(|result| result),
+        )?;
+        emit_exchange_state(&app, Transport::Nfc, ExchangePhase::TagDetected, None);
+        accept_nfc_payloads(&state, &app, payloads)
+    }
+}
+
+/// Timestamp tolerance for live exchanges, from settings
+fn live_tolerance(app: &AppHandle) -> TimestampTolerance {
+    load_settings_from_store(app).exchange.live()
+}
+
+/// Timestamp tolerance for an exchange message read from a tag or scanned
+/// off paper. Only messages marked static (`write_contact_tag`,
+/// `get_paper_card`) were written to be read long after.
+fn stored_tolerance(app: &AppHandle, msg: &ExchangeMessage) -> TimestampTolerance {
+    let settings = load_settings_from_store(app);
+    if msg.static_tag {
+        settings.exchange.static_tag()
+    } else {
+        settings.exchange.live()
+    }
+}
+
+/// Find and verify the exchange message among the records read from a tag.
+/// Returns their pubkey if successful
+fn accept_nfc_payloads(
+    state: &AppState,
+    app: &AppHandle,
+    payloads: Vec<Vec<u8>>,
+) -> Result<String, NfcError> {
+    // Get our pubkey for verification
+    let our_pubkey = identity_pubkey(state, app);
+    let awaiting_response = state.nfc_exchange.lock().unwrap().awaiting_response;
+
+    // Find our record, joining a payload written in chunks
+    let payloads = crate::nfc::reassemble_chunks(payloads);
+    for payload in &payloads {
+        // Try to parse the exchange message, JSON or compact
+        if let Ok(msg) = ExchangeMessage::decode(payload) {
+            emit_exchange_state(app, Transport::Nfc, ExchangePhase::PayloadRead, None);
+            // Verify the message. After our broadcast only a response naming
+            // us will do; otherwise a response must still match us if present
+            let tolerance = stored_tolerance(app, &msg);
+            let verified = match our_pubkey.as_deref() {
+                Some(ours) if awaiting_response => msg.verify_response(ours, tolerance),
+                ours => msg.verify_with(ours, tolerance),
+            };
+            track_nfc_result(
+                state,
+                app,
+                "verify",
+                verified.map_err(|e| NfcError::Verification(e.to_string())),
+            )?;
+            track_nfc_result(
+                state,
+                app,
+                "verify",
+                record_verified(state, app, Transport::Nfc, &msg, tolerance)
+                    .map_err(|e| NfcError::Verification(e.to_string())),
+            )?;
+
+            // Remember who we heard from so a failed response write can resume
+            let mut nfc_state = state.nfc_exchange.lock().unwrap();
+            nfc_state.their_pubkey = Some(msg.pubkey.clone());
+            nfc_state.awaiting_response = false;
+
+            return Ok(msg.pubkey);
+        }
+    }
+
+    // A contact tag from another Nostr app has nothing signed to check, so
+    // it is saved unverified until a full exchange. Not while we wait for a
+    // response: that must come from the device we broadcast to.
+    let nostr_uri = payloads
+        .iter()
+        .filter_map(|payload| std::str::from_utf8(payload).ok())
+        .find(|text| text.starts_with(NOSTR_URI_PREFIX));
+    if let (Some(uri), false) = (nostr_uri, awaiting_response) {
+        let saved = state.with_keys(|stored| {
+            let profile = parse_profile_card(uri, Some(&stored.public_key_hex))
+                .map_err(|e| e.to_string())?;
+            save_scanned_profile(app, stored, profile)
+        });
+        let contact = saved.ok_or(NfcError::NoKeys)?.map_err(NfcError::Other)?;
+        return Err(NfcError::ThirdPartyContact(contact.nostr_pubkey));
+    }
+
+    track_nfc_result(state, app, "scan", Err(NfcError::NoExchangeMessage))
+}
+
+/// Write a response after receiving their pubkey
+#[tauri::command]
+pub async fn write_nfc_response(
+    their_pubkey: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), NfcError> {
+    // Serve (Android) or write to a tag (iOS, desktop reader) our signed
+    // response, reused if an earlier write broke off
+    send_via(&NfcTransport, &state, &app, Some(&their_pubkey)).await?;
+    Ok(())
+}
+
+/// Write the prepared payload again after a write broke off, without signing
+/// a new one, so the user only has to tap again. Returns whose response it
+/// was, or `None` for our initial broadcast.
+#[tauri::command]
+pub async fn retry_nfc_write(
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Option<String>, NfcError> {
+    let pending_for = {
+        let nfc_state = state.nfc_exchange.lock().unwrap();
+        if nfc_state.pending_payload.is_none() {
+            return Err(NfcError::Other("Nothing is waiting to be written".to_string()));
+        }
+        nfc_state.pending_for.clone()
+    };
+
+    match pending_for {
+        Some(their_pubkey) => {
+            write_nfc_response(their_pubkey.clone(), state, app).await?;
+            Ok(Some(their_pubkey))
+        }
+        None => start_nfc_broadcast(state, app).await.map(|_| None),
+    }
+}
+
+// Legacy command for backward compatibility - now calls start_nfc_receive
+#[tauri::command]
+pub async fn start_nfc_scan(
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<String, NfcError> {
+    start_nfc_receive(state, app).await
+}
+
+/// Get the resumable NFC exchange state (pending payload, received pubkey, last error)
+#[tauri::command]
+pub fn get_nfc_exchange_state(state: State<AppState>) -> NfcExchangeState {
+    state.nfc_exchange.lock().unwrap().clone()
+}
+
+/// Abandon the current NFC exchange
+#[tauri::command]
+pub fn reset_nfc_exchange(state: State<AppState>) {
+    crate::hce::disarm();
+    crate::hce::cancel_tap();
+    state.nfc_exchange.lock().unwrap().reset();
+}
+
+/// Write our contact to a blank NFC sticker (NTAG21x) as a long-lived initial
+/// message, for a business card or poster. Readers accept it for the static
+/// tag age in settings rather than minutes. Whoever taps it can add us
+/// straight away and send their half by another route, such as a link.
+#[tauri::command]
+pub async fn write_contact_tag(
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), NfcError> {
+    let (signer, cert) = exchange_signer(&state, &app).map_err(|_| NfcError::NoKeys)?;
+    let hints = our_node_hints(&app, None).map_err(NfcError::Other)?;
+    let profile = load_settings_from_store(&app).profile.exchange_profile();
+    let msg = ExchangeMessage::new_static(signer.as_ref(), hints, profile)
+        .await
+        .map_err(|e| NfcError::Other(e.to_string()))?
+        .with_device_cert(cert);
+    record_key_use(&app, KeyUse::ExchangeSign, None).map_err(NfcError::Other)?;
+    let payload = msg.to_compressed().map_err(|e| NfcError::Other(e.to_string()))?;
+
+    #[cfg(any(target_os = "android", target_os = "ios"))]
+    let written = write_exchange_record(&app, &payload);
+
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    let written =
+        crate::nfc::retry_transient(|_| crate::pcsc_nfc::write_exchange_payload(&payload));
+
+    track_nfc_result(&state, &app, "write", written)
+}
+
+/// Stop serving our payload over Host Card Emulation
+#[tauri::command]
+pub fn stop_nfc_broadcast() {
+    crate::hce::disarm();
+}
+
+/// How a one-tap exchange served over Host Card Emulation ended
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HceExchangeOutcome {
+    pub their_pubkey: Option<String>,
+    pub error: Option<NfcError>,
+}
+
+/// Offer the one-tap exchange to a phone in reader mode (Android). Returns
+/// our pubkey; the outcome arrives as `exchange://hce` once they tap.
+#[tauri::command]
+pub async fn start_hce_exchange(
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<String, NfcError> {
+    begin_exchange_timer(&state, &app, Transport::Nfc);
+    // Only Android can emulate a card, and only builds with `android-hce`
+    if !crate::hce::AVAILABLE {
+        return track_nfc_result(&state, &app, "broadcast", Err(NfcError::NotAvailable));
+    }
+    let initial = prepare_nfc_payload(&state, &app, None).await?;
+
+    #[cfg(target_os = "android")]
+    {
+        let handle = app.clone();
+        crate::hce::arm_exchange(initial.as_bytes(), move |response| {
+            let app = handle.clone();
+            tauri::async_runtime::spawn(async move {
+                let outcome = match answer_hce_response(&app, &response).await {
+                    Ok(their_pubkey) => HceExchangeOutcome {
+                        their_pubkey: Some(their_pubkey),
+                        error: None,
+                    },
+                    Err(e) => {
+                        crate::hce::reject_exchange();
+                        HceExchangeOutcome {
+                            their_pubkey: None,
+                            error: Some(e),
+                        }
+                    }
+                };
+                let _ = app.emit(HCE_EXCHANGE_EVENT, outcome);
+            });
+        });
+    }
+
+    {
+        let mut nfc_state = state.nfc_exchange.lock().unwrap();
+        nfc_state.clear_pending();
+        nfc_state.awaiting_response = true;
+    }
+    advance_session(&app, |session| {
+        session.broadcast(Transport::Nfc, now_secs());
+        Ok(())
+    });
+    emit_exchange_state(&app, Transport::Nfc, ExchangePhase::WaitingForTap, None);
+
+    ExchangeMessage::decode(initial.as_bytes())
+        .map(|msg| msg.pubkey)
+        .map_err(|e| NfcError::Other(e.to_string()))
+}
+
+/// Card side of a one-tap exchange: verify the reader's response to our
+/// initial message and serve our reply to it. Returns their pubkey.
+#[cfg_attr(not(target_os = "android"), allow(dead_code))]
+async fn answer_hce_response(app: &AppHandle, response: &[u8]) -> Result<String, NfcError> {
+    let state = app.state::<AppState>();
+    emit_exchange_state(app, Transport::Nfc, ExchangePhase::TagDetected, None);
+    let msg = ExchangeMessage::decode(response)
+        .map_err(|e| NfcError::Verification(e.to_string()));
+    let msg = track_nfc_result(&state, app, "verify", msg)?;
+    emit_exchange_state(app, Transport::Nfc, ExchangePhase::PayloadRead, None);
+
+    let ours = identity_pubkey(&state, app).ok_or(NfcError::NoKeys)?;
+    let tolerance = live_tolerance(app);
+    let verified = msg
+        .verify_response(&ours, tolerance)
+        .map_err(|e| NfcError::Verification(e.to_string()))
+        .and_then(|_| {
+            record_verified(&state, app, Transport::Nfc, &msg, tolerance)
+                .map_err(|e| NfcError::Verification(e.to_string()))
+        });
+    track_nfc_result(&state, app, "verify", verified)?;
+
+    let reply = prepare_nfc_payload(&state, app, Some(&msg.pubkey)).await?;
+    crate::hce::answer_exchange(reply.as_bytes());
+    state.nfc_exchange.lock().unwrap().clear_pending();
+    state.exchange_queue.lock().unwrap().mark_responded(&msg.pubkey);
+    advance_session(app, |session| session.respond(&msg.pubkey, now_secs()));
+    emit_exchange_state(
+        app,
+        Transport::Nfc,
+        ExchangePhase::Responded,
+        Some(&msg.pubkey),
+    );
+
+    Ok(msg.pubkey)
+}
+
+/// Reader side of a one-tap exchange, run while the phones touch: verify
+/// their initial message, send our response and verify their reply.
+/// Returns their pubkey.
+fn run_hce_tap(app: &AppHandle, card: &mut dyn CardChannel) -> Result<String, NfcError> {
+    let state = app.state::<AppState>();
+    let ours = identity_pubkey(&state, app).ok_or(NfcError::NoKeys)?;
+    // Signing happens while the phones touch, so it can't wait on a remote
+    // signer or the async runtime
+    let signer = local_exchange_signer(&state, app).ok_or(NfcError::NoKeys)?;
+    let mut theirs: Option<String> = None;
+    emit_exchange_state(app, Transport::Nfc, ExchangePhase::TagDetected, None);
+
+    let reply = crate::pcsc_nfc::tap_exchange(card, &mut |initial| {
+        let msg = ExchangeMessage::decode(initial)
+            .map_err(|e| NfcError::Verification(e.to_string()))?;
+        emit_exchange_state(app, Transport::Nfc, ExchangePhase::PayloadRead, None);
+        let tolerance = stored_tolerance(app, &msg);
+        msg.verify_with(Some(ours.as_str()), tolerance)
+            .map_err(|e| NfcError::Verification(e.to_string()))?;
+        check_replay(&state, app, &msg, tolerance)
+            .map_err(|e| NfcError::Verification(e.to_string()))?;
+        // Queued now so our response is bound to their message
+        track_pending_exchange(&state, &msg, Transport::Nfc);
+        emit_exchange_state(
+            app,
+            Transport::Nfc,
+            ExchangePhase::Verified,
+            Some(&msg.pubkey),
+        );
+        theirs = Some(msg.pubkey.clone());
+
+        let response = sign_local_response(&state, app, &signer, &msg.pubkey, msg.negotiate())
+            .map_err(NfcError::Other)?;
+        state
+            .nfc_exchange
+            .lock()
+            .unwrap()
+            .set_pending(response.clone(), Some(&msg.pubkey));
+        Ok(response.into_bytes())
+    });
+    let reply = track_nfc_result(&state, app, "scan", reply)?;
+
+    let msg = ExchangeMessage::decode(&reply).map_err(|e| NfcError::Verification(e.to_string()));
+    let msg = track_nfc_result(&state, app, "verify", msg)?;
+    let tolerance = live_tolerance(app);
+    let verified = msg
+        .verify_response(&ours, tolerance)
+        .map_err(|e| NfcError::Verification(e.to_string()))
+        .and_then(|_| match theirs.as_deref() {
+            Some(pubkey) if pubkey == msg.pubkey => Ok(()),
+            _ => Err(NfcError::Verification(
+                "Reply came from a different key than the initial message".to_string(),
+            )),
+        })
+        .and_then(|_| {
+            // Bound to our response and never seen before, as any response
+            record_verified(&state, app, Transport::Nfc, &msg, tolerance)
+                .map_err(|e| NfcError::Verification(e.to_string()))
+        });
+    track_nfc_result(&state, app, "verify", verified)?;
+
+    state.nfc_exchange.lock().unwrap().clear_pending();
+    state.exchange_queue.lock().unwrap().mark_responded(&msg.pubkey);
+    advance_session(app, |session| session.respond(&msg.pubkey, now_secs()));
+    emit_exchange_state(
+        app,
+        Transport::Nfc,
+        ExchangePhase::Responded,
+        Some(&msg.pubkey),
+    );
+
+    Ok(msg.pubkey)
+}
+
+/// Tap a phone offering the one-tap exchange (`start_hce_exchange`) and
+/// complete the whole exchange with it. On Android this waits for the
+/// reader-mode activity to report a tap; on desktop it uses a USB reader
+/// (`pcsc` feature). Returns their pubkey for `complete_exchange`.
+#[tauri::command]
+pub async fn tap_hce_exchange(
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<String, NfcError> {
+    begin_exchange_timer(&state, &app, Transport::Nfc);
+    if local_exchange_signer(&state, &app).is_none() {
+        return Err(NfcError::NoKeys);
+    }
+    emit_exchange_state(&app, Transport::Nfc, ExchangePhase::WaitingForTap, None);
+
+    #[cfg(target_os = "android")]
+    {
+        // Reader mode comes with card emulation
+        if !crate::hce::AVAILABLE {
+            return track_nfc_result(&state, &app, "scan", Err(NfcError::NotAvailable));
+        }
+        let (done, result) = tokio::sync::oneshot::channel();
+        let handle = app.clone();
+        crate::hce::await_tap(move |card| {
+            let _ = done.send(run_hce_tap(&handle, card));
+        });
+
+        let waited = tokio::time::timeout(
+            std::time::Duration::from_secs(crate::pcsc_nfc::TAP_TIMEOUT_SECS),
+            result,
+        )
+        .await;
+        crate::hce::cancel_tap();
+        match waited {
+            Ok(Ok(result)) => result,
+            _ => track_nfc_result(&state, &app, "scan", Err(NfcError::Cancelled)),
+        }
+    }
+
+    #[cfg(not(target_os = "android"))]
+    {
+        let handle = app.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            crate::pcsc_nfc::with_reader_card(|card| run_hce_tap(&handle, card))
+        })
+        .await
+        .map_err(|e| NfcError::Other(e.to_string()))
+        .and_then(|result| result)
+    }
+}
+
+/// Which sides of the one-tap exchange this device can take, so the UI only
+/// offers what works
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OneTapSupport {
+    /// Be tapped, emulating a card (`start_hce_exchange`)
+    pub offer: bool,
+    /// Tap a phone offering it (`tap_hce_exchange`)
+    pub tap: bool,
+}
+
+#[tauri::command]
+pub fn get_one_tap_support() -> OneTapSupport {
+    // Android reads in reader mode, which comes with card emulation; desktop
+    // reads with a USB reader
+    let tap = if cfg!(target_os = "android") {
+        crate::hce::AVAILABLE
+    } else {
+        !cfg!(target_os = "ios") && crate::pcsc_nfc::reader_available()
+    };
+
+    OneTapSupport {
+        offer: crate::hce::AVAILABLE,
+        tap,
+    }
+}
+
+/// What a verified exchange message told us about its sender
+#[derive(Default)]
+struct PeerDetails {
+    /// Identity their device certificate names, if they used a device key
+    master_pubkey: Option<String>,
+    hints: NodeHints,
+    profile: Option<ExchangeProfile>,
+    /// How the exchange ran, if we know
+    transport: Option<Transport>,
+    /// Signature of their message
+    signature: Option<String>,
+    /// How they came to be a contact
+    origin: ContactOrigin,
+    /// Nostr relays they can be reached on
+    nostr_relays: Vec<String>,
+}
+
+impl PeerDetails {
+    fn from_message(msg: &ExchangeMessage) -> Self {
+        Self {
+            master_pubkey: msg.device_cert.as_ref().map(|c| c.master_pubkey.clone()),
+            hints: msg.node_hints(),
+            profile: msg.profile.clone(),
+            transport: None,
+            signature: Some(msg.signature.clone()),
+            ..Default::default()
+        }
+    }
+
+    fn from_pending(pending: &PendingExchange) -> Self {
+        Self {
+            master_pubkey: pending.master_pubkey.clone(),
+            hints: pending.hints.clone(),
+            profile: pending.profile.clone(),
+            transport: Some(pending.transport),
+            signature: pending.their_signature.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Derive the Iroh endpoint for a verified peer and save them as a contact,
+/// attaching `note` if given, the identity their device key is certified
+/// for and the profile they signed. Returns the stored contact (the
+/// existing one if we already had them).
+fn store_exchanged_contact(
+    state: &AppState,
+    app: &AppHandle,
+    their_pubkey: &str,
+    peer: PeerDetails,
+    needs_review: bool,
+    note: Option<&str>,
+) -> Result<Contact, String> {
+    let PeerDetails {
+        master_pubkey,
+        hints,
+        profile,
+        transport,
+        signature,
+        origin,
+        nostr_relays,
+    } = peer;
+    if !state.keys.is_loaded() && !is_watch_only(app) {
+        return Err("No keys found".to_string());
+    }
+
+    // Load existing contacts, add new one, save
+    let mut contacts = load_contacts_from_store(app);
+
+    // Check if contact already exists (by pubkey); a new exchange refreshes
+    // where they can be reached
+    if let Some(existing) = contacts.iter_mut().find(|c| c.nostr_pubkey == their_pubkey) {
+        let mut changed = false;
+        // A contact added unverified (npub, profile card, third-party tag)
+        // is confirmed by a signed exchange
+        if existing.needs_review && !needs_review {
+            existing.needs_review = false;
+            existing.origin = ContactOrigin::Exchange;
+            existing.exchange_method = transport;
+            existing.exchange_signature = signature;
+            changed = true;
+        } else if existing.needs_review
+            && origin == ContactOrigin::Remote
+            && existing.origin != ContactOrigin::Remote
+        {
+            // A remote exchange is signed, so it replaces a bare npub or an
+            // introduction, though they still haven't been met
+            existing.origin = ContactOrigin::Remote;
+            existing.exchange_method = transport;
+            existing.exchange_signature = signature;
+            changed = true;
+        }
+        if existing.nostr_relays.is_empty() && !nostr_relays.is_empty() {
+            existing.nostr_relays = nostr_relays;
+            changed = true;
+        }
+        if hints.node_id.is_some() {
+            let (endpoint_id, mismatch) =
+                exchanged_endpoint_id(state, app, their_pubkey, &hints)?;
+            if !endpoint_id.is_empty() {
+                existing.iroh_endpoint_id = endpoint_id;
+            }
+            existing.node_hint_mismatch = mismatch;
+            existing.relay_url = hints.relay_url;
+            changed = true;
+        }
+        if let Some(profile) = &profile {
+            changed |= existing.apply_profile(profile);
+        }
+        if let Some(note) = note {
+            existing.add_note(note);
+            changed = true;
+        }
+        let existing = existing.clone();
+        if changed {
+            save_contacts_to_store(app, &contacts)?;
+        }
+        return Ok(existing);
+    }
+
+    let (node_id, mismatch) = exchanged_endpoint_id(state, app, their_pubkey, &hints)?;
+    let mut contact = Contact::new(their_pubkey, &node_id);
+    contact.node_hint_mismatch = mismatch;
+    contact.relay_url = hints.relay_url;
+    contact.needs_review = needs_review;
+    contact.master_pubkey = master_pubkey;
+    contact.exchange_method = transport;
+    contact.exchange_signature = signature;
+    contact.origin = origin;
+    contact.nostr_relays = nostr_relays;
+    if let Some(profile) = &profile {
+        contact.apply_profile(profile);
+    }
+    if let Some(note) = note {
+        contact.add_note(note);
+    }
+
+    contacts.insert(0, contact.clone()); // Add to front
+    save_contacts_to_store(app, &contacts)?;
+
+    Ok(contact)
+}
+
+/// The short authentication string for the pending exchange with
+/// `their_pubkey`, once both messages of it are known. Both people compare
+/// theirs before a QR exchange can be completed.
+#[tauri::command]
+pub fn get_exchange_sas(
+    their_pubkey: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<ShortAuthString, String> {
+    let our_pubkey = identity_pubkey(&state, &app).ok_or("No keys found")?;
+    let queue = state.exchange_queue.lock().unwrap();
+    let pending = queue
+        .get(&their_pubkey)
+        .ok_or("No pending exchange with this contact")?;
+    match (&pending.our_nonce, &pending.their_nonce) {
+        (Some(our_nonce), Some(their_nonce)) => Ok(ShortAuthString::derive(
+            (&our_pubkey, our_nonce),
+            (&their_pubkey, their_nonce),
+        )),
+        _ => Err("Show your code to them first".to_string()),
+    }
+}
+
+/// The short code of the exchange session with `their_pubkey`, the same on
+/// both screens once our message and theirs are known. `None` until then,
+/// or if their app doesn't bind its exchanges to a session.
+#[tauri::command]
+pub fn get_session_code(
+    their_pubkey: String,
+    state: State<AppState>,
+) -> Result<Option<String>, String> {
+    let queue = state.exchange_queue.lock().unwrap();
+    let pending = queue
+        .get(&their_pubkey)
+        .ok_or("No pending exchange with this contact")?;
+    if !pending.protocol.has(CAP_SESSION_BINDING) {
+        return Ok(None);
+    }
+    // The session is named by the initial message and the response to it
+    let (Some(our_nonce), Some(their_nonce)) = (&pending.our_nonce, &pending.their_nonce) else {
+        return Ok(None);
+    };
+    let id = if pending.answered_us {
+        session_id(our_nonce, their_nonce)
+    } else {
+        session_id(their_nonce, our_nonce)
+    };
+    Ok(Some(session_code(&id)))
+}
+
+/// The user saw the same short authentication string on both devices
+#[tauri::command]
+pub fn confirm_exchange_sas(their_pubkey: String, state: State<AppState>) -> Result<(), String> {
+    let mut queue = state.exchange_queue.lock().unwrap();
+    if queue.get(&their_pubkey).is_none() {
+        return Err("No pending exchange with this contact".to_string());
+    }
+    queue.confirm_sas(&their_pubkey);
+    Ok(())
+}
+
+/// Save the verified peer as a contact, optionally with a context note
+/// ("Bitcoin meetup Nov 2025") so they can be recognised later. A QR
+/// exchange needs its short authentication string confirmed first, as
+/// nothing else shows the code came from the person in front of us.
+#[tauri::command]
+pub async fn complete_exchange(
+    their_pubkey: String,
+    note: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Contact, String> {
+    let pending = pending_peer(&state, &their_pubkey)?;
+
+    // An exchange begun after `start_reverify` refreshes that contact
+    let reverify = {
+        let mut target = state.reverify.lock().unwrap();
+        let applies = match (target.as_ref(), &pending) {
+            (Some((_, since)), Some(pending)) => pending.started >= *since,
+            _ => false,
+        };
+        if applies {
+            target.take()
+        } else {
+            None
+        }
+    };
+    let contact = match (reverify, pending) {
+        (Some((contact_id, _)), Some(pending)) => {
+            reverify_exchanged_contact(&state, &app, &contact_id, &their_pubkey, pending, note)
+                .await?
+        }
+        (_, pending) => {
+            let peer = pending.map(|p| p.peer).unwrap_or_default();
+            store_exchanged_contact(&state, &app, &their_pubkey, peer, false, note.as_deref())?
+        }
+    };
+
+    finish_exchange(&state, &app, &their_pubkey);
+    Ok(contact)
+}
+
+/// A pending exchange, ready to be saved
+struct PendingPeer {
+    peer: PeerDetails,
+    sas_confirmed: bool,
+    started: Instant,
+}
+
+/// What the pending exchange with `their_pubkey` told us. A QR exchange must
+/// have had its short authentication string confirmed.
+fn pending_peer(state: &AppState, their_pubkey: &str) -> Result<Option<PendingPeer>, String> {
+    let queue = state.exchange_queue.lock().unwrap();
+    let Some(pending) = queue.get(their_pubkey) else {
+        return Ok(None);
+    };
+    if pending.transport == Transport::Qr && !pending.sas_confirmed {
+        return Err("Confirm the verification code matches first".to_string());
+    }
+    Ok(Some(PendingPeer {
+        peer: PeerDetails::from_pending(pending),
+        sas_confirmed: pending.sas_confirmed,
+        started: pending.started,
+    }))
+}
+
+/// Have the next exchange re-verify an existing contact: their code is
+/// accepted although we have them, and `complete_exchange` refreshes this
+/// contact instead of adding one, following a key they rotated to
+#[tauri::command]
+pub fn start_reverify(
+    contact_id: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<(), String> {
+    if !load_contacts_from_store(&app).iter().any(|c| c.id == contact_id) {
+        return Err("Contact not found".to_string());
+    }
+    *state.reverify.lock().unwrap() = Some((contact_id, Instant::now()));
+    Ok(())
+}
+
+/// Stop waiting to re-verify a contact
+#[tauri::command]
+pub fn cancel_reverify(state: State<AppState>) {
+    state.reverify.lock().unwrap().take();
+}
+
+/// Re-verify `contact_id` with the fresh exchange just run with
+/// `their_pubkey`, whether or not `start_reverify` was called first
+#[tauri::command]
+pub async fn reverify_contact(
+    contact_id: String,
+    their_pubkey: String,
+    note: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Contact, String> {
+    let pending =
+        pending_peer(&state, &their_pubkey)?.ok_or("No fresh exchange with this contact")?;
+    state.reverify.lock().unwrap().take();
+    let contact =
+        reverify_exchanged_contact(&state, &app, &contact_id, &their_pubkey, pending, note)
+            .await?;
+
+    finish_exchange(&state, &app, &their_pubkey);
+    Ok(contact)
+}
+
+/// Update a contact from a fresh exchange with them instead of adding a
+/// duplicate. A different key is taken as a rotation, with their chats moved
+/// over, unless it already belongs to another contact.
+async fn reverify_exchanged_contact(
+    state: &AppState,
+    app: &AppHandle,
+    contact_id: &str,
+    their_pubkey: &str,
+    pending: PendingPeer,
+    note: Option<String>,
+) -> Result<Contact, String> {
+    let mut contacts = load_contacts_from_store(app);
+    if contacts
+        .iter()
+        .any(|c| c.nostr_pubkey == their_pubkey && c.id != contact_id)
+    {
+        return Err("That key belongs to another contact".to_string());
+    }
+    let contact = contacts
+        .iter_mut()
+        .find(|c| c.id == contact_id)
+        .ok_or("Contact not found")?;
+
+    let PendingPeer {
+        peer,
+        sas_confirmed,
+        ..
+    } = pending;
+    let (endpoint_id, mismatch) = exchanged_endpoint_id(state, app, their_pubkey, &peer.hints)?;
+    let old_pubkey = contact.nostr_pubkey.clone();
+    contact.apply_reverification(their_pubkey, &endpoint_id, now_secs(), sas_confirmed);
+    contact.node_hint_mismatch = mismatch;
+    contact.relay_url = peer.hints.relay_url;
+    contact.master_pubkey = peer.master_pubkey;
+    contact.exchange_method = peer.transport;
+    contact.exchange_signature = peer.signature;
+    if let Some(profile) = &peer.profile {
+        contact.apply_profile(profile);
+    }
+    if let Some(note) = note {
+        contact.add_note(&note);
+    }
+    let contact = contact.clone();
+    save_contacts_to_store(app, &contacts)?;
+
+    if old_pubkey != their_pubkey {
+        if let Some(manager) = state.chat_manager.write().await.as_mut() {
+            manager.merge_sessions(&old_pubkey, their_pubkey);
+        }
+        let _ = app.emit(CONTACT_ROTATED_EVENT, &contact);
+    }
+
+    Ok(contact)
+}
+
+/// Close out the exchange with `their_pubkey` once its contact is saved:
+/// session, queue entry, NFC state and statistics
+fn finish_exchange(state: &AppState, app: &AppHandle, their_pubkey: &str) {
+    advance_session(app, |session| session.complete(their_pubkey, now_secs()));
+
+    let (pending, others_pending) = {
+        let mut queue = state.exchange_queue.lock().unwrap();
+        let pending = queue.complete(their_pubkey);
+        (pending, !queue.is_empty())
+    };
+
+    // The NFC exchange is finished if it was with this peer, or if nobody else is waiting
+    {
+        let mut nfc_state = state.nfc_exchange.lock().unwrap();
+        let ours = nfc_state.their_pubkey.as_deref() == Some(their_pubkey)
+            || nfc_state.pending_for.as_deref() == Some(their_pubkey);
+        if ours || !others_pending {
+            crate::hce::disarm();
+            nfc_state.reset();
+        }
+    }
+
+    let timer = state.exchange_timer.lock().unwrap().take();
+    match (timer, pending) {
+        (Some(timer), _) => {
+            update_stats(app, |s| s.record_success(timer.transport, timer.started.elapsed()));
+        }
+        // A concurrent exchange that started while another was being timed
+        (None, Some(pending)) => update_stats(app, |s| {
+            s.record_start(pending.transport);
+            s.record_success(pending.transport, pending.started.elapsed());
+        }),
+        (None, None) => {}
+    }
+}
+
+/// Exchanges waiting to be completed, oldest first (stale ones are dropped)
+#[tauri::command]
+pub fn get_pending_exchanges(state: State<AppState>, app: AppHandle) -> Vec<PendingExchangeInfo> {
+    expire_pending_exchanges(&app);
+    state.exchange_queue.lock().unwrap().list()
+}
+
+/// Drop pending exchanges that outlived their TTL: count them as failed,
+/// release the NFC exchange or session they held and tell the frontend
+fn expire_pending_exchanges(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    let expired = state.exchange_queue.lock().unwrap().expire_stale();
+    if expired.is_empty() {
+        return;
+    }
+
+    update_stats(app, |s| {
+        for pending in &expired {
+            s.record_failure(pending.transport, "pending", "expired");
+        }
+    });
+    for pending in &expired {
+        advance_session(app, |session| session.cancel(&pending.their_pubkey));
+        let mut nfc_state = state.nfc_exchange.lock().unwrap();
+        if nfc_state.their_pubkey.as_deref() == Some(pending.their_pubkey.as_str()) {
+            crate::hce::disarm();
+            nfc_state.reset();
+        }
+    }
+
+    let infos: Vec<PendingExchangeInfo> = expired.iter().map(|p| p.info()).collect();
+    let _ = app.emit(PENDING_EXPIRED_EVENT, &infos);
+}
+
+/// Sweep stale pending exchanges every `PENDING_SWEEP_SECS` for the life of
+/// the app, so a half-done exchange is reported instead of vanishing
+pub fn spawn_pending_expiry(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut tick = tokio::time::interval(std::time::Duration::from_secs(PENDING_SWEEP_SECS));
+        loop {
+            tick.tick().await;
+            expire_pending_exchanges(&app);
+        }
+    });
+}
+
+/// Abandon the pending exchange with one peer, leaving the others untouched
+#[tauri::command]
+pub fn cancel_pending_exchange(their_pubkey: String, state: State<AppState>, app: AppHandle) {
+    state.exchange_queue.lock().unwrap().complete(&their_pubkey);
+    advance_session(&app, |session| session.cancel(&their_pubkey));
+
+    let mut nfc_state = state.nfc_exchange.lock().unwrap();
+    if nfc_state.their_pubkey.as_deref() == Some(their_pubkey.as_str()) {
+        crate::hce::disarm();
+        nfc_state.reset();
+    }
+}
+
+/// The exchange in the foreground, for resuming the handshake after the app
+/// was suspended
+#[tauri::command]
+pub fn get_exchange_session(app: AppHandle) -> ExchangeSession {
+    load_exchange_session(&app)
+}
+
+/// Forget the exchange in the foreground; queued exchanges are kept
+#[tauri::command]
+pub fn reset_exchange_session(app: AppHandle) {
+    advance_session(&app, |session| {
+        *session = ExchangeSession::default();
+        Ok(())
+    });
+}
+
+// ============================================================================
+// Beacon Mode Commands
+// ============================================================================
+
+/// Keep a rotating exchange payload armed for back-to-back exchanges at events
+#[tauri::command]
+pub fn start_beacon_mode(state: State<AppState>) -> Result<BeaconStatus, String> {
+    if !crate::hce::AVAILABLE {
+        return Err(NfcError::NotAvailable.to_string());
+    }
+    let keys = our_keys(&state)?;
+    let beacon = Beacon::new(&keys).map_err(|e| e.to_string())?;
+
+    crate::hce::arm(beacon.payload().as_bytes());
+    let status = beacon.status();
+    *state.beacon.lock().unwrap() = Some(beacon);
+
+    Ok(status)
+}
+
+/// Current beacon payload, re-signed if the previous one expired
+#[tauri::command]
+pub fn get_beacon_status(state: State<AppState>) -> Result<Option<BeaconStatus>, String> {
+    let mut beacon = state.beacon.lock().unwrap();
+    let Some(beacon) = beacon.as_mut() else {
+        return Ok(None);
+    };
+
+    let keys = our_keys(&state)?;
+    if beacon.rotate_if_needed(&keys).map_err(|e| e.to_string())? {
+        crate::hce::arm(beacon.payload().as_bytes());
+    }
+
+    Ok(Some(beacon.status()))
+}
+
+#[tauri::command]
+pub fn stop_beacon_mode(state: State<AppState>) {
+    if state.beacon.lock().unwrap().take().is_some() {
+        crate::hce::disarm();
+    }
+}
+
+/// Count an inbound payload against the configured limits before it is
+/// verified. One that doesn't decode is left for verification to reject.
+fn screen_inbound(state: &AppState, app: &AppHandle, payload: &str) -> Result<(), ScanError> {
+    let Ok(msg) = ExchangeMessage::decode(payload.as_bytes()) else {
+        return Ok(());
+    };
+    let limits = load_settings_from_store(app).exchange;
+    let mut limiter = state.inbound_limiter.lock().unwrap();
+    Ok(limiter.admit(&msg.pubkey, &msg.nonce, &limits, now_secs())?)
+}
+
+/// Hold a payload whose signature failed, unless the settings say not to,
+/// and announce it on `exchange://quarantined`
+fn quarantine_forged(
+    state: &AppState,
+    app: &AppHandle,
+    payload: &str,
+    transport: Option<Transport>,
+    error: &ScanError,
+) {
+    if *error != ScanError::BadSignature
+        || !load_settings_from_store(app).exchange.quarantine_bad_signatures
+    {
+        return;
+    }
+    let pubkey = ExchangeMessage::decode(payload.as_bytes())
+        .ok()
+        .map(|msg| msg.pubkey);
+    let held = state.payload_quarantine.lock().unwrap().hold(
+        payload,
+        pubkey.as_deref(),
+        transport,
+        now_secs(),
+    );
+    if let Some(entry) = held {
+        let _ = app.emit(PAYLOAD_QUARANTINED_EVENT, &entry);
+    }
+}
+
+/// Inbound exchange payloads held for failing their signature check, oldest first
+#[tauri::command]
+pub fn get_quarantined_payloads(state: State<AppState>) -> Vec<QuarantinedPayload> {
+    state.payload_quarantine.lock().unwrap().list()
+}
+
+/// Forget every quarantined exchange payload
+#[tauri::command]
+pub fn clear_quarantined_payloads(state: State<AppState>) {
+    state.payload_quarantine.lock().unwrap().clear();
+}
+
+/// Verify a payload read from an attendee (QR scan or NFC read) and save them
+/// for later review without asking for confirmation
+#[tauri::command]
+pub fn beacon_ingest(
+    payload: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<Contact, String> {
+    if state.beacon.lock().unwrap().is_none() {
+        return Err("Beacon mode is not active".to_string());
+    }
+
+    let our_pubkey = identity_pubkey(&state, &app);
+
+    screen_inbound(&state, &app, &payload).map_err(|e| e.to_string())?;
+    let tolerance = live_tolerance(&app);
+    let msg = verify_scanned_payload(&payload, our_pubkey.as_deref(), tolerance).map_err(|e| {
+        quarantine_forged(&state, &app, &payload, None, &e);
+        e.to_string()
+    })?;
+    check_replay(&state, &app, &msg, tolerance).map_err(|e| e.to_string())?;
+    let contact = store_exchanged_contact(
+        &state,
+        &app,
+        &msg.pubkey,
+        PeerDetails::from_message(&msg),
+        true,
+        None,
+    )?;
+
+    if let Some(beacon) = state.beacon.lock().unwrap().as_mut() {
+        beacon.completed += 1;
+    }
+
+    Ok(contact)
+}
+
+// ============================================================================
+// Conference Mode Commands
+// ============================================================================
+
+/// Host a conference: advertise a local-network exchange for `window_secs`
+/// (half an hour by default) and run the handshake with everyone who joins.
+/// Nobody is saved until `approve_conference_attendees`; progress is
+/// announced on `exchange://conference`.
+#[tauri::command]
+pub async fn start_conference_mode(
+    window_secs: Option<u64>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<ConferenceStatus, String> {
+    identity_pubkey(&state, &app).ok_or("No keys found")?;
+    if state
+        .conference
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(Conference::is_open)
+    {
+        return Err("A conference is already running".to_string());
+    }
+
+    let listener = tokio::net::TcpListener::bind((std::net::Ipv4Addr::UNSPECIFIED, 0))
+        .await
+        .map_err(|e| e.to_string())?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+    let advertisement = lan::Advertisement::start(port).map_err(|e| e.to_string())?;
+    let host = advertisement.info().clone();
+    let window_secs = window_secs.unwrap_or(DEFAULT_CONFERENCE_WINDOW_SECS);
+
+    let status = {
+        let mut conference = state.conference.lock().unwrap();
+        match conference.as_mut() {
+            Some(conference) => conference.reopen(host, window_secs),
+            None => *conference = Some(Conference::new(host, window_secs)),
+        }
+        conference.as_ref().map(Conference::status)
+    };
+    let _ = app.emit(CONFERENCE_EVENT, &status);
+
+    tauri::async_runtime::spawn(run_conference(app.clone(), listener, advertisement));
+    status.ok_or_else(|| "Conference not started".to_string())
+}
+
+/// Take conference guests one at a time until the window closes or it is
+/// stopped; anyone connecting meanwhile waits in the listen backlog
+async fn run_conference(
+    app: AppHandle,
+    listener: tokio::net::TcpListener,
+    advertisement: lan::Advertisement,
+) {
+    let state = app.state::<AppState>();
+    advance_session(&app, |session| {
+        session.broadcast(Transport::Lan, now_secs());
+        Ok(())
+    });
+
+    loop {
+        let (remaining, full) = match state.conference.lock().unwrap().as_ref() {
+            Some(conference) => (conference.remaining(), conference.is_full()),
+            None => break,
+        };
+        if remaining.is_zero() {
+            break;
+        }
+        let accepted = tokio::select! {
+            accepted = tokio::time::timeout(remaining, listener.accept()) => accepted,
+            _ = state.conference_cancel.notified() => break,
+        };
+        let Ok(Ok((stream, _))) = accepted else {
+            continue;
+        };
+        // Hang up on guests we couldn't hold
+        if full {
+            continue;
+        }
+
+        update_stats(&app, |s| s.record_start(Transport::Lan));
+        let mut conn = LanConnection::new(stream);
+        let result = tokio::select! {
+            result = lan_host_handshake(&state, &app, &mut conn) => result,
+            _ = state.conference_cancel.notified() => break,
+        };
+        match result {
+            Ok(their_pubkey) => admit_attendee(&state, &app, &their_pubkey),
+            Err(_) => update_stats(&app, |s| {
+                s.record_failure(Transport::Lan, "conference", "error")
+            }),
+        }
+    }
+
+    // Withdraw the advertisement before saying the window has closed
+    drop(advertisement);
+    if let Some(conference) = state.conference.lock().unwrap().as_mut() {
+        conference.close();
+    }
+    emit_conference_status(&state, &app);
+}
+
+/// Move a guest whose handshake finished from the exchange queue to the
+/// conference, and go back to broadcasting
+fn admit_attendee(state: &AppState, app: &AppHandle, their_pubkey: &str) {
+    let Some(pending) = state.exchange_queue.lock().unwrap().complete(their_pubkey) else {
+        return;
+    };
+    let admitted = match state.conference.lock().unwrap().as_mut() {
+        Some(conference) => conference.admit(pending, now_secs()).is_ok(),
+        None => false,
+    };
+    if !admitted {
+        update_stats(app, |s| {
+            s.record_failure(Transport::Lan, "conference", "closed")
+        });
+    }
+    advance_session(app, |session| {
+        session.broadcast(Transport::Lan, now_secs());
+        Ok(())
+    });
+    emit_conference_status(state, app);
+}
+
+/// Announce the conference, forgetting it once it is closed and nobody is
+/// left to approve
+fn emit_conference_status(state: &AppState, app: &AppHandle) -> Option<ConferenceStatus> {
+    let status = {
+        let mut conference = state.conference.lock().unwrap();
+        if conference.as_ref().is_some_and(Conference::is_finished) {
+            conference.take();
+        }
+        conference.as_ref().map(Conference::status)
+    };
+    let _ = app.emit(CONFERENCE_EVENT, &status);
+    status
+}
+
+/// The running conference and who is waiting for approval
+#[tauri::command]
+pub fn get_conference_status(state: State<AppState>) -> Option<ConferenceStatus> {
+    state
+        .conference
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(Conference::status)
+}
+
+/// Close the conference window early. Attendees keep waiting for approval.
+#[tauri::command]
+pub fn stop_conference_mode(state: State<AppState>, app: AppHandle) -> Option<ConferenceStatus> {
+    if let Some(conference) = state.conference.lock().unwrap().as_mut() {
+        conference.close();
+    }
+    state.conference_cancel.notify_waiters();
+    emit_conference_status(&state, &app)
+}
+
+/// Save the chosen conference attendees as contacts, with an optional shared
+/// note ("Nostr meetup Berlin"). Stops at the first one that can't be saved,
+/// leaving the rest waiting.
+#[tauri::command]
+pub fn approve_conference_attendees(
+    their_pubkeys: Vec<String>,
+    note: Option<String>,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<Vec<Contact>, String> {
+    let waiting = state
+        .conference
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|conference| conference.waiting(&their_pubkeys))
+        .unwrap_or_default();
+
+    let mut contacts = Vec::with_capacity(waiting.len());
+    for pending in waiting {
+        let contact = store_exchanged_contact(
+            &state,
+            &app,
+            &pending.their_pubkey,
+            PeerDetails::from_pending(&pending),
+            false,
+            note.as_deref(),
+        )?;
+        if let Some(conference) = state.conference.lock().unwrap().as_mut() {
+            conference.take(&[pending.their_pubkey.clone()]);
+        }
+        update_stats(&app, |s| {
+            s.record_success(pending.transport, pending.started.elapsed())
+        });
+        contacts.push(contact);
+    }
+
+    emit_conference_status(&state, &app);
+    Ok(contacts)
+}
+
+/// Turn away conference attendees without saving them
+#[tauri::command]
+pub fn dismiss_conference_attendees(
+    their_pubkeys: Vec<String>,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Option<ConferenceStatus> {
+    let dismissed = state
+        .conference
+        .lock()
+        .unwrap()
+        .as_mut()
+        .map(|conference| conference.take(&their_pubkeys))
+        .unwrap_or_default();
+    update_stats(&app, |s| {
+        for pending in &dismissed {
+            s.record_failure(pending.transport, "conference", "dismissed");
+        }
+    });
+    emit_conference_status(&state, &app)
+}
+
+// ============================================================================
+// Contact Management Commands
+// ============================================================================
+
+/// All contacts with their resolved display names
+#[tauri::command]
+pub fn get_contacts(app: AppHandle) -> Vec<NamedContact> {
+    resolve_all(load_contacts_from_store(&app))
+}
+
+#[tauri::command]
+pub fn delete_contact(id: String, app: AppHandle) -> Result<(), String> {
+    with_contact_db(&app, |db| db.delete(&id))?;
+    app.state::<AppState>().contacts.invalidate();
+    Ok(())
+}
+
+/// One page of contacts filtered by pubkey, tag and/or trust level, most
+/// recently exchanged first
+#[tauri::command]
+pub fn query_contacts(
+    query: ContactQuery,
+    trust_level: Option<TrustLevel>,
+    app: AppHandle,
+) -> Result<ContactPage<NamedContact>, String> {
+    let Some(level) = trust_level else {
+        let page = with_contact_db(&app, |db| db.query(&query))?;
+        return Ok(ContactPage {
+            contacts: resolve_all(page.contacts),
+            total: page.total,
+        });
+    };
+
+    // Trust is scored against the whole address book, so filter the cached
+    // list and page it here
+    let contacts = load_contacts_from_store(&app);
+    let groups = load_groups_from_store(&app);
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let mut filtered: Vec<Contact> = contacts
+        .iter()
+        .rev()
+        .filter(|c| query.pubkey.as_ref().map_or(true, |p| &c.nostr_pubkey == p))
+        .filter(|c| {
+            query
+                .tag
+                .as_ref()
+                .map_or(true, |t| c.tags.iter().any(|tag| tag.eq_ignore_ascii_case(t)))
+        })
+        .cloned()
+        .collect();
+    filtered.retain(|c| score_contact(c, &contacts, &groups, now).level == level);
+
+    let total = filtered.len() as u32;
+    let limit = match query.limit {
+        Some(limit) if limit > 0 => limit.min(MAX_PAGE_SIZE),
+        _ => MAX_PAGE_SIZE,
+    };
+    let page = filtered
+        .into_iter()
+        .skip(query.offset as usize)
+        .take(limit as usize)
+        .collect();
+
+    Ok(ContactPage {
+        contacts: resolve_all(page),
+        total,
+    })
+}
+
+/// Update a contact's nickname, notes, or tags. Omitted fields are left
+/// unchanged; an empty nickname or notes string clears the field.
+#[tauri::command]
+pub fn update_contact(
+    id: String,
+    nickname: Option<String>,
+    notes: Option<String>,
+    tags: Option<Vec<String>>,
+    app: AppHandle,
+) -> Result<Contact, String> {
+    let mut contacts = load_contacts_from_store(&app);
+    let contact = contacts
+        .iter_mut()
+        .find(|c| c.id == id)
+        .ok_or("Contact not found")?;
+
+    if let Some(nickname) = nickname {
+        let nickname = nickname.trim();
+        contact.nickname = (!nickname.is_empty()).then(|| nickname.to_string());
+    }
+    if let Some(notes) = notes {
+        let notes = notes.trim();
+        contact.notes = (!notes.is_empty()).then(|| notes.to_string());
+    }
+    if let Some(tags) = tags {
+        contact.tags.clear();
+        for tag in tags {
+            let tag = tag.trim();
+            if !tag.is_empty() && !contact.tags.iter().any(|t| t == tag) {
+                contact.tags.push(tag.to_string());
+            }
+        }
+    }
+
+    let contact = contact.clone();
+    save_contacts_to_store(&app, &contacts)?;
+
+    Ok(contact)
+}
+
+/// Record a local abuse report, optionally blocking the contact. With
+/// `export_evidence`, returns a signed evidence bundle (JSON) of the report
+/// and the conversation so the user can pass it on.
+#[tauri::command]
+pub async fn report_contact(
+    id: String,
+    reason: String,
+    block: bool,
+    export_evidence: bool,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Option<String>, String> {
+    let mut contacts = load_contacts_from_store(&app);
+    let contact = contacts
+        .iter_mut()
+        .find(|c| c.id == id)
+        .ok_or("Contact not found")?;
+
+    let report = Report::new(&contact.id, &contact.nostr_pubkey, &reason, block);
+
+    if block {
+        contact.blocked = true;
+        save_contacts_to_store(&app, &contacts)?;
+    }
+
+    let mut reports = load_reports_from_store(&app);
+    reports.push(report.clone());
+    save_reports_to_store(&app, &reports)?;
+
+    if !export_evidence {
+        return Ok(None);
+    }
+
+    let keys = our_keys(&state)?;
+    let messages = {
+        let mut chat_manager = state.chat_manager.write().await;
+        chat_manager
+            .as_mut()
+            .map(|m| m.get_messages(&report.contact_pubkey))
+            .unwrap_or_default()
+    };
+
+    let bundle = EvidenceBundle::new(&keys, report, messages).map_err(|e| e.to_string())?;
+    serde_json::to_string_pretty(&bundle)
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_reports(app: AppHandle) -> Vec<Report> {
+    load_reports_from_store(&app)
+}
+
+/// Block or unblock a contact without filing a report
+#[tauri::command]
+pub fn set_contact_blocked(id: String, blocked: bool, app: AppHandle) -> Result<Contact, String> {
+    let mut contacts = load_contacts_from_store(&app);
+    let contact = contacts
+        .iter_mut()
+        .find(|c| c.id == id)
+        .ok_or("Contact not found")?;
+
+    contact.blocked = blocked;
+    let contact = contact.clone();
+    save_contacts_to_store(&app, &contacts)?;
+
+    Ok(contact)
+}
+
+/// Confirm a contact that was saved automatically
+#[tauri::command]
+pub fn mark_contact_reviewed(id: String, app: AppHandle) -> Result<Contact, String> {
+    let mut contacts = load_contacts_from_store(&app);
+    let contact = contacts
+        .iter_mut()
+        .find(|c| c.id == id)
+        .ok_or("Contact not found")?;
+
+    contact.needs_review = false;
+    let contact = contact.clone();
+    save_contacts_to_store(&app, &contacts)?;
+
+    Ok(contact)
+}
+
+/// Safety number for a contact, to compare with theirs out of band
+#[tauri::command]
+pub fn get_safety_number(
+    contact_id: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<SafetyNumber, String> {
+    let contact = load_contacts_from_store(&app)
+        .into_iter()
+        .find(|c| c.id == contact_id)
+        .ok_or("Contact not found")?;
+    let our_pubkey = exchange_signer(&state, &app)?.0.public_key_hex();
+
+    Ok(SafetyNumber::derive(&our_pubkey, &contact.nostr_pubkey))
+}
+
+/// Record whether the contact's safety number matched theirs
+#[tauri::command]
+pub fn mark_contact_verified(
+    id: String,
+    verified: bool,
+    app: AppHandle,
+) -> Result<Contact, String> {
+    let mut contacts = load_contacts_from_store(&app);
+    let contact = contacts
+        .iter_mut()
+        .find(|c| c.id == id)
+        .ok_or("Contact not found")?;
+
+    contact.verified = verified;
+    let contact = contact.clone();
+    save_contacts_to_store(&app, &contacts)?;
+
+    Ok(contact)
+}
+
+/// Signed card vouching for a contact, as a JSON payload for a QR code or
+/// file. Whoever imports it gets them as an introduced contact.
+#[tauri::command]
+pub fn export_contact_card(
+    contact_id: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<String, String> {
+    sign_contact_card(&state, &app, &contact_id)?
+        .to_json()
+        .map_err(|e| e.to_string())
+}
+
+/// A stored contact as an npub or nprofile QR code, to show a third person.
+/// Unlike `export_contact_card`, nothing is signed.
+#[tauri::command]
+pub fn get_contact_share_payload(
+    contact_id: String,
+    app: AppHandle,
+) -> Result<ContactShare, String> {
+    let contacts = load_contacts_from_store(&app);
+    let contact = contacts
+        .iter()
+        .find(|c| c.id == contact_id)
+        .ok_or("Contact not found")?;
+    let name = match resolve(contact) {
+        (_, NameSource::Pubkey) => None,
+        (name, _) => Some(name),
+    };
+
+    ContactShare::new(contact, name).map_err(|e| e.to_string())
+}
+
+/// Our signed card vouching for the contact with `contact_id`
+fn sign_contact_card(
+    state: &AppState,
+    app: &AppHandle,
+    contact_id: &str,
+) -> Result<SignedContactCard, String> {
+    let keys = our_keys(state)?;
+    let contacts = load_contacts_from_store(app);
+    let contact = contacts
+        .iter()
+        .find(|c| c.id == contact_id)
+        .ok_or("Contact not found")?;
+
+    // Don't pass on a shortened pubkey as their name
+    let name = match resolve(contact) {
+        (_, NameSource::Pubkey) => None,
+        (name, _) => Some(name),
+    };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    SignedContactCard::new(&keys, contact, name, now).map_err(|e| e.to_string())
+}
+
+/// Import a contact card as an introduced contact pending review. If we
+/// already have them, the introduction is only recorded if they had none.
+#[tauri::command]
+pub fn import_contact_card(
+    payload: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<Contact, String> {
+    state
+        .with_keys(|stored| {
+            let card = SignedContactCard::import(&payload, Some(&stored.public_key_hex))
+                .map_err(|e| e.to_string())?;
+            save_introduced_contact(&app, stored, card)
+        })
+        .ok_or("No keys found")?
+}
+
+/// Save the subject of a verified card as an introduced contact
+fn save_introduced_contact(
+    app: &AppHandle,
+    stored: &StoredKeys,
+    card: SignedContactCard,
+) -> Result<Contact, String> {
+    let mut contacts = load_contacts_from_store(app);
+    if let Some(existing) = contacts
+        .iter_mut()
+        .find(|c| c.nostr_pubkey == card.subject_pubkey)
+    {
+        if existing.introduced_by.is_none() {
+            existing.introduced_as = existing.introduced_as.take().or(card.name);
+            existing.introduced_by = Some(card.attester_pubkey);
+        }
+        let existing = existing.clone();
+        save_contacts_to_store(app, &contacts)?;
+        return Ok(existing);
+    }
+
+    let contact = card.to_contact(&peer_endpoint_id_for(app, stored, &card.subject_pubkey)?);
+    contacts.insert(0, contact.clone());
+    save_contacts_to_store(app, &contacts)?;
+
+    Ok(contact)
+}
+
+/// Introduce one contact to another over chat: `to_pubkey` is sent our
+/// signed card for the contact with `contact_id` and gets them as an
+/// introduced, unverified contact
+#[tauri::command]
+pub async fn introduce_contact(
+    contact_id: String,
+    to_pubkey: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<ChatMessage, String> {
+    let card = sign_contact_card(&state, &app, &contact_id)?;
+    if card.subject_pubkey == to_pubkey {
+        return Err("Can't introduce a contact to themselves".to_string());
+    }
+    let payload = serde_json::to_value(&card).map_err(|e| e.to_string())?;
+
+    let node = state.iroh_node.read().await;
+    let connection = node
+        .get_connection(&to_pubkey)
+        .ok_or("Not connected to contact")?
+        .clone();
+    drop(node);
+
+    let mut chat_manager_guard = state.chat_manager.write().await;
+    let chat_manager = chat_manager_guard
+        .as_mut()
+        .ok_or("Chat manager not initialized")?;
+    chat_manager
+        .send_typed_message(&connection, &to_pubkey, KIND_INTRODUCTION, payload)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Handle an incoming `introduction`. Contacts may only vouch in their own
+/// name, so the card must be signed by the sender.
+pub(crate) fn handle_introduction(app: &AppHandle, message: &ChatMessage) -> Result<(), String> {
+    let card: SignedContactCard = message
+        .payload
+        .clone()
+        .and_then(|payload| serde_json::from_value(payload).ok())
+        .ok_or("Invalid introduction")?;
+    if card.attester_pubkey != message.sender_pubkey {
+        return Err("Introduction signed by someone else".to_string());
+    }
+    card.verify().map_err(|e| e.to_string())?;
+
+    let contact = app
+        .state::<AppState>()
+        .with_keys(|stored| {
+            if card.subject_pubkey == stored.public_key_hex {
+                return Err(CardError::OwnCard.to_string());
+            }
+            save_introduced_contact(app, stored, card)
+        })
+        .ok_or("No keys found")??;
+    let _ = app.emit(CONTACT_INTRODUCED_EVENT, &contact);
+    Ok(())
+}
+
+/// Add a contact by npub or NIP-05 address, before meeting in person. The
+/// contact is unverified, but its Iroh endpoint is derived so chat can be
+/// attempted right away; a later exchange upgrades trust.
+#[tauri::command]
+pub async fn add_contact_by_identifier(
+    identifier: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Contact, String> {
+    let our_pubkey = state.our_pubkey().ok_or("No keys found")?;
+    let identifier = Identifier::parse(&identifier).map_err(|e| e.to_string())?;
+    let pubkey = resolve_identifier(&identifier)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if pubkey == our_pubkey {
+        return Err("That's your own identifier".to_string());
+    }
+
+    let mut contacts = load_contacts_from_store(&app);
+    if let Some(existing) = contacts.iter().find(|c| c.nostr_pubkey == pubkey) {
+        return Ok(existing.clone());
+    }
+
+    let endpoint_id = state
+        .with_keys(|stored| peer_endpoint_id_for(&app, stored, &pubkey))
+        .ok_or("No keys found")??;
+    let mut contact = Contact::new(&pubkey, &endpoint_id);
+    contact.profile_name = identifier.nip05_address();
+    contact.needs_review = true;
+    contacts.insert(0, contact.clone());
+    save_contacts_to_store(&app, &contacts)?;
+
+    Ok(contact)
+}
+
+/// Our profile card: npub, display name and avatar from settings, and a
+/// signed QR payload to show or print
+#[tauri::command]
+pub fn get_my_profile_card(state: State<AppState>, app: AppHandle) -> Result<ProfileCard, String> {
+    let keys = our_keys(&state)?;
+    let profile = load_settings_from_store(&app).profile;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    SignedProfileCard::new(&keys, profile.display_name, profile.avatar_url, now)
+        .and_then(|card| card.info())
+        .map_err(|e| e.to_string())
+}
+
+/// A printable business card (SVG) with our static exchange payload as a QR
+/// code, our npub and fingerprint words. Like a written contact tag, the
+/// payload is marked static so it can be scanned long after printing.
+#[tauri::command]
+pub async fn get_paper_card(state: State<'_, AppState>, app: AppHandle) -> Result<String, String> {
+    let (signer, cert) = exchange_signer(&state, &app)?;
+    let hints = our_node_hints(&app, None)?;
+    let profile = load_settings_from_store(&app).profile;
+    let msg = ExchangeMessage::new_static(signer.as_ref(), hints, profile.exchange_profile())
+        .await
+        .map_err(|e| e.to_string())?
+        .with_device_cert(cert);
+    record_key_use(&app, KeyUse::ExchangeSign, None)?;
+    let payload = msg.to_compressed().map_err(|e| e.to_string())?;
+
+    let name = profile.display_name.as_deref();
+    crate::paper::render_card(&payload, msg.identity_pubkey(), name).map_err(|e| e.to_string())
+}
+
+/// Add a contact from a scanned profile card or bare npub, unverified until
+/// an in-person exchange
+#[tauri::command]
+pub fn import_profile_card(
+    payload: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<Contact, String> {
+    state
+        .with_keys(|stored| {
+            let profile = parse_profile_card(&payload, Some(&stored.public_key_hex))
+                .map_err(|e| e.to_string())?;
+            save_scanned_profile(&app, stored, profile)
+        })
+        .ok_or("No keys found")?
+}
+
+/// Save the subject of a profile card, npub or nprofile as an unverified
+/// contact, or return the contact we already have for them
+fn save_scanned_profile(
+    app: &AppHandle,
+    stored: &StoredKeys,
+    profile: ScannedProfile,
+) -> Result<Contact, String> {
+    let mut contacts = load_contacts_from_store(app);
+    if let Some(existing) = contacts
+        .iter_mut()
+        .find(|c| c.nostr_pubkey == profile.pubkey)
+    {
+        if existing.profile_name.is_none() && profile.display_name.is_some() {
+            existing.profile_name = profile.display_name;
+            let existing = existing.clone();
+            save_contacts_to_store(app, &contacts)?;
+            return Ok(existing);
+        }
+        return Ok(existing.clone());
+    }
+
+    let contact = profile.to_contact(&peer_endpoint_id_for(app, stored, &profile.pubkey)?);
+    contacts.insert(0, contact.clone());
+    save_contacts_to_store(app, &contacts)?;
+
+    Ok(contact)
+}
+
+/// Local trust score for a contact, with the factors behind it
+#[tauri::command]
+pub fn get_contact_trust(id: String, app: AppHandle) -> Result<ContactTrust, String> {
+    let contacts = load_contacts_from_store(&app);
+    let contact = contacts
+        .iter()
+        .find(|c| c.id == id)
+        .ok_or("Contact not found")?;
+
+    let groups = load_groups_from_store(&app);
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    Ok(score_contact(contact, &contacts, &groups, now))
+}
+
+/// Trust scores for all contacts, highest first
+#[tauri::command]
+pub fn get_trust_scores(app: AppHandle) -> Vec<ContactTrust> {
+    let contacts = load_contacts_from_store(&app);
+    let groups = load_groups_from_store(&app);
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let mut scores: Vec<ContactTrust> = contacts
+        .iter()
+        .map(|c| score_contact(c, &contacts, &groups, now))
+        .collect();
+    scores.sort_by(|a, b| b.score.cmp(&a.score));
+    scores
+}
+
+/// List contacts stored more than once under the same pubkey
+#[tauri::command]
+pub fn find_duplicate_contacts(app: AppHandle) -> Vec<DuplicateGroup> {
+    find_duplicates(&load_contacts_from_store(&app))
+}
+
+/// Merge `merge_ids` into `keep_id`, consolidating nicknames, notes, tags and chat history
+#[tauri::command]
+pub async fn merge_contacts(
+    keep_id: String,
+    merge_ids: Vec<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Contact, String> {
+    let mut contacts = load_contacts_from_store(&app);
+
+    let keep_index = contacts
+        .iter()
+        .position(|c| c.id == keep_id)
+        .ok_or("Contact not found")?;
+    let mut keep = contacts.remove(keep_index);
+
+    let (merged, rest): (Vec<Contact>, Vec<Contact>) = contacts
+        .into_iter()
+        .partition(|c| merge_ids.contains(&c.id) && c.id != keep_id);
+
+    if merged.len() != merge_ids.iter().filter(|id| **id != keep_id).count() {
+        return Err("Contact not found".to_string());
+    }
+
+    // Chat history is keyed by pubkey; fold any other pubkeys into the kept one
+    {
+        let mut chat_manager = state.chat_manager.write().await;
+        if let Some(manager) = chat_manager.as_mut() {
+            for contact in &merged {
+                manager.merge_sessions(&contact.nostr_pubkey, &keep.nostr_pubkey);
+            }
+        }
+    }
+
+    for contact in merged {
+        keep.merge_from(contact);
+    }
+
+    let mut contacts = rest;
+    contacts.insert(keep_index.min(contacts.len()), keep.clone());
+    save_contacts_to_store(&app, &contacts)?;
+
+    Ok(keep)
+}
+
+// ============================================================================
+// QR Exchange Commands
+// ============================================================================
+
+/// Get the exchange payload for QR code generation. Responses use the
+/// version the peer's code was made with; `legacy` makes an initial code
+/// that version 1 apps can scan. A payload too large for one code comes
+/// back as the frames of an animated code, to be shown in a loop.
+#[tauri::command]
+pub async fn get_exchange_qr_payload(
+    their_pubkey: Option<String>,
+    legacy: Option<bool>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Vec<String>, String> {
+    // A code on screen is delivered as far as we can tell
+    let transport = QrTransport {
+        legacy: legacy == Some(true),
+    };
+    send_via(&transport, &state, &app, their_pubkey.as_deref()).await
+}
+
+/// Outcome of scanning one QR code: the peer's pubkey once their payload is
+/// complete, and how far through an animated code the scan has got
+#[derive(serde::Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QrScanResult {
+    pub their_pubkey: Option<String>,
+    pub progress: MultipartProgress,
+    /// Set instead of `their_pubkey` for a plain npub or nprofile from another
+    /// Nostr app: saved unverified, with no exchange to continue
+    pub unverified_contact: Option<Contact>,
+}
+
+/// Whether a scanned code is a NIP-19 npub or nprofile rather than ours
+fn is_nip19_payload(qr_data: &str) -> bool {
+    let data = qr_data.trim();
+    let bech32 = data.strip_prefix(NOSTR_URI_PREFIX).unwrap_or(data);
+    bech32.starts_with("npub1") || bech32.starts_with("nprofile1")
+}
+
+/// Save the subject of a scanned npub or nprofile as an unverified contact
+fn scan_nip19_payload(
+    qr_data: &str,
+    state: &AppState,
+    app: &AppHandle,
+) -> Result<QrScanResult, ScanError> {
+    let contact = state
+        .with_keys(|stored| {
+            let profile =
+                parse_profile_card(qr_data, Some(&stored.public_key_hex)).map_err(|e| match e {
+                    CardError::OwnCard => ScanError::SelfScan,
+                    _ => ScanError::NotSneakernetPayload,
+                })?;
+            save_scanned_profile(app, stored, profile).map_err(|_| ScanError::NotSneakernetPayload)
+        })
+        .ok_or(ScanError::NotSneakernetPayload)??;
+
+    Ok(QrScanResult {
+        their_pubkey: None,
+        progress: MultipartProgress {
+            received: 1,
+            total: 1,
+            complete: true,
+        },
+        unverified_contact: Some(contact),
+    })
+}
+
+/// Process a scanned QR code, or one frame of an animated code. Plain npub
+/// and nprofile codes from other Nostr apps add an unverified contact.
+#[tauri::command]
+pub fn process_scanned_qr(
+    qr_data: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<QrScanResult, ScanError> {
+    if is_nip19_payload(&qr_data) {
+        return scan_nip19_payload(&qr_data, &state, &app);
+    }
+    begin_exchange_timer(&state, &app, Transport::Qr);
+
+    let result = if multipart::is_frame(&qr_data) {
+        scan_qr_frame(&qr_data, &state, &app)
+    } else {
+        scan_qr_payload(&qr_data, &state, &app).map(|their_pubkey| QrScanResult {
+            their_pubkey: Some(their_pubkey),
+            progress: MultipartProgress {
+                received: 1,
+                total: 1,
+                complete: true,
+            },
+            unverified_contact: None,
+        })
+    };
+    result.inspect_err(|e| {
+        update_stats(&app, |s| s.record_failure(Transport::Qr, "scan", e.code()));
+    })
+}
+
+/// Add a frame of an animated code, processing the payload once it's whole
+fn scan_qr_frame(
+    frame: &str,
+    state: &AppState,
+    app: &AppHandle,
+) -> Result<QrScanResult, ScanError> {
+    let (progress, message) = {
+        let mut frames = state.qr_frames.lock().unwrap();
+        let progress = frames
+            .add(frame)
+            .map_err(|_| ScanError::NotSneakernetPayload)?;
+        if !progress.complete {
+            return Ok(QrScanResult {
+                their_pubkey: None,
+                progress,
+                unverified_contact: None,
+            });
+        }
+        let message = frames.message();
+        frames.reset();
+        (progress, message)
+    };
+
+    let payload = message
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or(ScanError::NotSneakernetPayload)?;
+    let their_pubkey = scan_qr_payload(&payload, state, app)?;
+    Ok(QrScanResult {
+        their_pubkey: Some(their_pubkey),
+        progress,
+        unverified_contact: None,
+    })
+}
+
+fn scan_qr_payload(qr_data: &str, state: &AppState, app: &AppHandle) -> Result<String, ScanError> {
+    let transport = QrTransport { legacy: false };
+    transport.receive(state, app, qr_data).map(|msg| msg.pubkey)
+}
+
+/// Verify a payload picked up from the other device's screen, speaker or a
+/// link, and remember the peer
+fn accept_scanned_payload(
+    qr_data: &str,
+    state: &AppState,
+    app: &AppHandle,
+    transport: Transport,
+) -> Result<ExchangeMessage, ScanError> {
+    // Get our pubkey to verify if this is a response to us
+    let our_pubkey = identity_pubkey(state, app);
+    let failed = |e: ScanError| {
+        emit_exchange_state(app, transport, ExchangePhase::Failed, None);
+        e
+    };
+
+    // Parse and verify the message. A static one may be from a printed card.
+    let tolerance = ExchangeMessage::decode(qr_data.as_bytes())
+        .map(|msg| stored_tolerance(app, &msg))
+        .unwrap_or_else(|_| live_tolerance(app));
+    screen_inbound(state, app, qr_data).map_err(failed)?;
+    let msg = verify_scanned_payload(qr_data, our_pubkey.as_deref(), tolerance).map_err(|e| {
+        quarantine_forged(state, app, qr_data, Some(transport), &e);
+        failed(e)
+    })?;
+
+    // An initial code from someone we already have adds nothing unless we
+    // are re-verifying them; a response to us is still needed to finish the
+    // exchange on this side
+    let reverify = state.reverify.lock().unwrap().clone().map(|(id, _)| id);
+    if msg.their_pubkey.is_none()
+        && load_contacts_from_store(app)
+            .iter()
+            .any(|c| c.nostr_pubkey == msg.pubkey && reverify.as_deref() != Some(c.id.as_str()))
+    {
+        return Err(failed(ScanError::AlreadyContact));
+    }
+    record_verified(state, app, transport, &msg, tolerance).map_err(failed)?;
+
+    Ok(msg)
+}
+
+// ============================================================================
+// Group Exchange Commands
+// ============================================================================
+
+/// Outcome of scanning a group exchange roster, or one frame of it
+#[derive(serde::Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RosterScanResult {
+    pub progress: MultipartProgress,
+    /// Everyone on the roster, once it's whole
+    pub members: Vec<RosterMember>,
+    /// Contacts this scan added
+    pub added: Vec<Contact>,
+    /// The roster with us on it, to show the next person
+    pub frames: Vec<String>,
+}
+
+/// Our initial exchange message for a roster entry. Unlike a pairwise
+/// exchange, nothing answers it, so no nonce is kept for a SAS.
+async fn roster_message(state: &AppState, app: &AppHandle) -> Result<ExchangeMessage, String> {
+    let (signer, cert) = exchange_signer(state, app)?;
+    let hints = our_node_hints(app, None)?;
+    let profile = load_settings_from_store(app).profile.exchange_profile();
+    let msg =
+        ExchangeMessage::new_signed(signer.as_ref(), None, hints, profile, Negotiated::OURS)
+            .await
+            .map_err(|e| e.to_string())?;
+    record_key_use(app, KeyUse::ExchangeSign, None)?;
+    Ok(msg.with_device_cert(cert))
+}
+
+/// Add ourselves to `roster` if we aren't on it yet
+async fn join_roster(state: &AppState, app: &AppHandle, roster: &mut Roster) -> Result<(), String> {
+    let our_pubkey = identity_pubkey(state, app).ok_or("No keys found")?;
+    if roster.contains(&our_pubkey) {
+        return Ok(());
+    }
+    let msg = roster_message(state, app).await?;
+    let (signer, _) = exchange_signer(state, app)?;
+    roster
+        .append(signer.as_ref(), msg)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+fn roster_frames(roster: &Roster) -> Result<Vec<String>, String> {
+    let json = roster.to_json().map_err(|e| e.to_string())?;
+    multipart::qr_frames(&json).map_err(|e| e.to_string())
+}
+
+/// Start a round-robin exchange for three or more people. Returns the
+/// frames of the roster's QR code for the next person to scan.
+#[tauri::command]
+pub async fn start_group_exchange(
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Vec<String>, String> {
+    let msg = roster_message(&state, &app).await?;
+    let (signer, _) = exchange_signer(&state, &app)?;
+    let roster = Roster::start(signer.as_ref(), msg)
+        .await
+        .map_err(|e| e.to_string())?;
+    let frames = roster_frames(&roster)?;
+    *state.roster.lock().unwrap() = Some(roster);
+    state.roster_frames.lock().unwrap().reset();
+    Ok(frames)
+}
+
+/// Frames of the roster we hold, to show again
+#[tauri::command]
+pub fn get_group_exchange_payload(state: State<AppState>) -> Result<Vec<String>, String> {
+    let roster = state.roster.lock().unwrap();
+    roster_frames(roster.as_ref().ok_or("No group exchange in progress")?)
+}
+
+/// Scan the roster from the person before us (or a frame of its animated
+/// code). Once it's whole and verified, we add ourselves if we aren't on it,
+/// save everyone on it we don't have yet and get the longer roster to pass on.
+#[tauri::command]
+pub async fn scan_group_exchange(
+    qr_data: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<RosterScanResult, String> {
+    let (progress, json) = if multipart::is_frame(&qr_data) {
+        let mut frames = state.roster_frames.lock().unwrap();
+        let progress = frames.add(&qr_data).map_err(|e| e.to_string())?;
+        if !progress.complete {
+            return Ok(RosterScanResult {
+                progress,
+                members: Vec::new(),
+                added: Vec::new(),
+                frames: Vec::new(),
+            });
+        }
+        let message = frames.message();
+        frames.reset();
+        let json = message
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or_else(|| RosterError::InvalidFormat.to_string())?;
+        (progress, json)
+    } else {
+        let progress = MultipartProgress {
+            received: 1,
+            total: 1,
+            complete: true,
+        };
+        (progress, qr_data)
+    };
+
+    let scanned = Roster::from_json(&json).map_err(|e| e.to_string())?;
+    scanned
+        .verify(ROSTER_TOLERANCE)
+        .map_err(|e| e.to_string())?;
+
+    // Continue the roster we hold, or take this one if we hold none
+    let held = state.roster.lock().unwrap().clone();
+    let mut roster = match held {
+        Some(mut held) => {
+            held.merge(scanned).map_err(|e| e.to_string())?;
+            held
+        }
+        None => scanned,
+    };
+    join_roster(&state, &app, &mut roster).await?;
+
+    // Everyone on it is verified by their own signature and the chain
+    let our_pubkey = identity_pubkey(&state, &app).ok_or("No keys found")?;
+    let known: Vec<String> = load_contacts_from_store(&app)
+        .into_iter()
+        .map(|c| c.nostr_pubkey)
+        .collect();
+    let mut added = Vec::new();
+    for entry in &roster.entries {
+        let msg = &entry.message;
+        if msg.pubkey == our_pubkey || known.contains(&msg.pubkey) {
+            continue;
+        }
+        let peer = PeerDetails {
+            transport: Some(Transport::Qr),
+            ..PeerDetails::from_message(msg)
+        };
+        let contact = store_exchanged_contact(&state, &app, &msg.pubkey, peer, false, None)?;
+        added.push(contact);
+    }
+
+    let result = RosterScanResult {
+        progress,
+        members: roster.members(),
+        added,
+        frames: roster_frames(&roster)?,
+    };
+    *state.roster.lock().unwrap() = Some(roster);
+    Ok(result)
+}
+
+/// Leave the group exchange, e.g. once the roster has been round twice
+#[tauri::command]
+pub fn end_group_exchange(state: State<AppState>) {
+    *state.roster.lock().unwrap() = None;
+    state.roster_frames.lock().unwrap().reset();
+}
+
+// ============================================================================
+// Audio Exchange Commands
+// ============================================================================
+
+/// Our exchange payload as a sound, for when there is neither NFC nor a
+/// camera: a `data:audio/wav` URL to play in a loop until the other device
+/// has heard it. Responses work as for `get_exchange_qr_payload`.
+#[tauri::command]
+pub async fn start_audio_broadcast(
+    their_pubkey: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<String, String> {
+    send_via(&AudioTransport, &state, &app, their_pubkey.as_deref()).await
+}
+
+/// Start listening for an audio broadcast. Microphone samples recorded at
+/// `sample_rate` are then fed to `push_audio_samples`.
+#[tauri::command]
+pub fn start_audio_receive(
+    sample_rate: u32,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let decoder = AudioDecoder::new(sample_rate).map_err(|e| e.to_string())?;
+    *state.audio_decoder.lock().unwrap() = Some(decoder);
+    begin_exchange_timer(&state, &app, Transport::Audio);
+    Ok(())
+}
+
+/// Feed recorded samples to the listener. Returns their pubkey once a
+/// broadcast has been heard and verified, after which listening stops.
+#[tauri::command]
+pub fn push_audio_samples(
+    samples: Vec<f32>,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<Option<String>, ScanError> {
+    let heard = match state.audio_decoder.lock().unwrap().as_mut() {
+        Some(decoder) => decoder.push(&samples),
+        None => return Ok(None),
+    };
+    let Some(payload) = heard else {
+        return Ok(None);
+    };
+
+    let payload = String::from_utf8(payload).map_err(|_| ScanError::NotSneakernetPayload)?;
+    match AudioTransport.receive(&state, &app, &payload) {
+        Ok(msg) => {
+            *state.audio_decoder.lock().unwrap() = None;
+            Ok(Some(msg.pubkey))
+        }
+        // Our own broadcast, picked up while both devices take turns
+        Err(ScanError::SelfScan) => Ok(None),
+        Err(e) => {
+            update_stats(&app, |s| s.record_failure(Transport::Audio, "listen", e.code()));
+            Err(e)
+        }
+    }
+}
+
+/// Stop listening for an audio broadcast
+#[tauri::command]
+pub fn stop_audio_receive(state: State<AppState>) {
+    *state.audio_decoder.lock().unwrap() = None;
+}
+
+// ============================================================================
+// Link Exchange Commands
+// ============================================================================
+
+/// Outcome of opening an exchange link
+#[derive(serde::Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExchangeUriResult {
+    pub their_pubkey: String,
+    /// Their link was their first message, so they are waiting for ours
+    pub needs_reply: bool,
+}
+
+impl From<ExchangeMessage> for ExchangeUriResult {
+    fn from(msg: ExchangeMessage) -> Self {
+        Self {
+            needs_reply: msg.their_pubkey.is_none(),
+            their_pubkey: msg.pubkey,
+        }
+    }
+}
+
+/// Our exchange message as a `sneakernet://exchange/...` link to send
+/// through any messenger. Pass their pubkey to answer a link they sent.
+#[tauri::command]
+pub async fn get_exchange_uri(
+    their_pubkey: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<String, String> {
+    let transport = LinkTransport { uri: true };
+    send_via(&transport, &state, &app, their_pubkey.as_deref()).await
+}
+
+/// Our exchange message as plain text (the compact `sn2:` payload) to paste
+/// into email or any chat when nothing else works. Pass their pubkey to
+/// answer text they sent.
+#[tauri::command]
+pub async fn get_exchange_text_payload(
+    their_pubkey: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<String, String> {
+    let transport = LinkTransport { uri: false };
+    send_via(&transport, &state, &app, their_pubkey.as_deref()).await
+}
+
+/// Take an exchange payload pasted from email or chat, verified exactly
+/// like a scanned QR code. The text around it is ignored.
+#[tauri::command]
+pub fn process_exchange_text(
+    text: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<ExchangeUriResult, ScanError> {
+    begin_exchange_timer(&state, &app, Transport::Link);
+
+    let msg = payload_from_text(&text)
+        .and_then(|payload| LinkTransport { uri: false }.receive(&state, &app, &payload))
+        .inspect_err(|e| {
+            update_stats(&app, |s| s.record_failure(Transport::Link, "paste", e.code()));
+        })?;
+    Ok(msg.into())
+}
+
+/// Open an exchange link, verified exactly like a scanned QR code
+#[tauri::command]
+pub fn process_exchange_uri(
+    uri: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<ExchangeUriResult, ScanError> {
+    begin_exchange_timer(&state, &app, Transport::Link);
+
+    let msg = payload_from_uri(&uri)
+        .and_then(|payload| LinkTransport { uri: false }.receive(&state, &app, &payload))
+        .inspect_err(|e| {
+            update_stats(&app, |s| s.record_failure(Transport::Link, "open", e.code()));
+        })?;
+    Ok(msg.into())
+}
+
+/// Upload our exchange message, encrypted, to `relay` (or the first relay
+/// watched for remote exchanges) and return a one-time web link to it on the
+/// page set as `exchange.webLinkBase`. With no page set, our plain
+/// `sneakernet://` link is returned instead and nothing is uploaded.
+#[tauri::command]
+pub async fn create_web_link(
+    relay: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<String, String> {
+    let Some(base) = load_settings_from_store(&app).exchange.web_link_base else {
+        return send_via(&LinkTransport { uri: true }, &state, &app, None).await;
+    };
+    let relay = match relay {
+        Some(relay) => remote::parse_relays(&[relay])
+            .map_err(|e| e.to_string())?
+            .remove(0),
+        None => load_remote_exchanges(&app)
+            .relays
+            .into_iter()
+            .next()
+            .ok_or("Add a relay for remote exchanges to upload web links to")?,
+    };
+    begin_exchange_timer(&state, &app, Transport::Link);
+
+    let payload = exchange_payload(&state, &app, None, peer_protocol(&state, None)).await?;
+    let (link, event) = WebLink::seal(&payload, &relay, now_secs()).map_err(|e| e.to_string())?;
+    relay::publish(&relay, &event)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    advance_session(&app, |session| {
+        session.broadcast(Transport::Link, now_secs());
+        Ok(())
+    });
+    Ok(link.to_url(&base))
+}
+
+/// Fetch and decrypt a web link's exchange message, then verify it exactly
+/// like a scanned QR code
+#[tauri::command]
+pub async fn open_web_link(
+    url: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<ExchangeUriResult, String> {
+    begin_exchange_timer(&state, &app, Transport::Link);
+    let link = WebLink::parse(&url).map_err(|e| e.to_string())?;
+    let events = relay::fetch(&link.relay, link.filter())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let msg = link
+        .open(&events, now_secs())
+        .map_err(|e| e.to_string())
+        .and_then(|payload| {
+            LinkTransport { uri: false }
+                .receive(&state, &app, &payload)
+                .map_err(|e| e.to_string())
+        })
+        .inspect_err(|_| {
+            update_stats(&app, |s| s.record_failure(Transport::Link, "web", "error"));
+        })?;
+    Ok(msg.into())
+}
+
+/// Exchange links the app was opened with, not yet handled
+#[tauri::command]
+pub fn take_pending_exchange_uris(state: State<AppState>) -> Vec<String> {
+    std::mem::take(&mut *state.pending_uris.lock().unwrap())
+}
+
+/// Queue links the app was opened with for the frontend, which may not be
+/// listening yet on a cold start
+pub(crate) fn deliver_exchange_uris(app: &AppHandle, uris: Vec<String>) {
+    let uris: Vec<String> = uris
+        .into_iter()
+        .filter(|uri| payload_from_uri(uri).is_ok() || WebLink::parse(uri).is_ok())
+        .collect();
+    if uris.is_empty() {
+        return;
+    }
+    app.state::<AppState>().pending_uris.lock().unwrap().extend(uris);
+    let _ = app.emit(EXCHANGE_URI_EVENT, ());
+}
+
+// ============================================================================
+// Local-Network Exchange Commands
+// ============================================================================
+
+/// Verify a payload received over the LAN and remember the peer. After our
+/// initial message only a response naming us will do.
+fn accept_lan_payload(
+    state: &AppState,
+    app: &AppHandle,
+    payload: &str,
+    expect_response: bool,
+) -> Result<ExchangeMessage, ScanError> {
+    let our_pubkey = identity_pubkey(state, app);
+    let msg = ExchangeMessage::decode(payload.as_bytes())
+        .map_err(|_| ScanError::NotSneakernetPayload)?;
+    if our_pubkey.as_deref() == Some(msg.pubkey.as_str()) {
+        return Err(ScanError::SelfScan);
+    }
+    screen_inbound(state, app, payload)?;
+
+    let tolerance = live_tolerance(app);
+    let verified = match our_pubkey.as_deref() {
+        Some(ours) if expect_response => msg.verify_response(ours, tolerance),
+        ours => msg.verify_with(ours, tolerance),
+    };
+    if let Err(e) = verified {
+        let e = ScanError::from(e);
+        quarantine_forged(state, app, payload, Some(Transport::Lan), &e);
+        return Err(e);
+    }
+    check_session(state, &msg)?;
+    check_replay(state, app, &msg, tolerance)?;
+    track_pending_exchange(state, &msg, Transport::Lan);
+
+    Ok(msg)
+}
+
+/// Host side: send our initial message, take their response, answer it
+async fn lan_host_handshake<S>(
+    state: &AppState,
+    app: &AppHandle,
+    conn: &mut LanConnection<S>,
+) -> Result<String, String>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let initial = exchange_payload(state, app, None, peer_protocol(state, None)).await?;
+    conn.send(&initial).await.map_err(|e| e.to_string())?;
+
+    let payload = conn.receive().await.map_err(|e| e.to_string())?;
+    let msg = accept_lan_payload(state, app, &payload, true).map_err(|e| e.to_string())?;
+    advance_session(app, |session| {
+        session.receive(&msg.pubkey, Transport::Lan, now_secs());
+        Ok(())
+    });
+
+    let protocol = peer_protocol(state, Some(&msg.pubkey));
+    let response = exchange_payload(state, app, Some(&msg.pubkey), protocol).await?;
+    conn.send(&response).await.map_err(|e| e.to_string())?;
+    state.exchange_queue.lock().unwrap().mark_responded(&msg.pubkey);
+    advance_session(app, |session| session.respond(&msg.pubkey, now_secs()));
+
+    Ok(msg.pubkey)
+}
+
+/// Guest side: take the host's initial message, respond, then take the
+/// host's response naming us
+async fn lan_guest_handshake<S>(
+    state: &AppState,
+    app: &AppHandle,
+    conn: &mut LanConnection<S>,
+) -> Result<String, String>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let payload = conn.receive().await.map_err(|e| e.to_string())?;
+    let initial = accept_lan_payload(state, app, &payload, false).map_err(|e| e.to_string())?;
+    if initial.their_pubkey.is_some() {
+        return Err("The host sent a response before its own message".to_string());
+    }
+    let their_pubkey = initial.pubkey;
+    advance_session(app, |session| {
+        session.receive(&their_pubkey, Transport::Lan, now_secs());
+        Ok(())
+    });
+
+    let protocol = peer_protocol(state, Some(&their_pubkey));
+    let response = exchange_payload(state, app, Some(&their_pubkey), protocol).await?;
+    conn.send(&response).await.map_err(|e| e.to_string())?;
+    state.exchange_queue.lock().unwrap().mark_responded(&their_pubkey);
+    advance_session(app, |session| session.respond(&their_pubkey, now_secs()));
+
+    // Their response carries where to reach them
+    let payload = conn.receive().await.map_err(|e| e.to_string())?;
+    let reply = accept_lan_payload(state, app, &payload, true).map_err(|e| e.to_string())?;
+    if reply.pubkey != their_pubkey {
+        return Err(ScanError::WrongRecipient.to_string());
+    }
+
+    Ok(their_pubkey)
+}
+
+/// Host a local-network exchange: listen on an ephemeral port, advertise it
+/// over mDNS (announced on `exchange://lan-host`) and run the handshake with
+/// the first device to connect. Returns their pubkey for `complete_exchange`.
+#[tauri::command]
+pub async fn host_lan_exchange(
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<String, String> {
+    begin_exchange_timer(&state, &app, Transport::Lan);
+
+    let listener = tokio::net::TcpListener::bind((std::net::Ipv4Addr::UNSPECIFIED, 0))
+        .await
+        .map_err(|e| e.to_string())?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+    let advertisement = lan::Advertisement::start(port).map_err(|e| e.to_string())?;
+    let _ = app.emit(LAN_HOST_EVENT, advertisement.info());
+    advance_session(&app, |session| {
+        session.broadcast(Transport::Lan, now_secs());
+        Ok(())
+    });
+
+    let timeout = std::time::Duration::from_secs(lan::HOST_TIMEOUT_SECS);
+    let accepted = tokio::select! {
+        accepted = tokio::time::timeout(timeout, listener.accept()) => accepted,
+        _ = state.lan_cancel.notified() => return Err("Exchange cancelled".to_string()),
+    };
+    // One guest per exchange
+    drop(advertisement);
+    drop(listener);
+
+    let result = match accepted {
+        Ok(Ok((stream, _))) => {
+            let mut conn = LanConnection::new(stream);
+            tokio::select! {
+                result = lan_host_handshake(&state, &app, &mut conn) => result,
+                _ = state.lan_cancel.notified() => Err("Exchange cancelled".to_string()),
+            }
+        }
+        Ok(Err(e)) => Err(LanError::from(e).to_string()),
+        Err(_) => Err(LanError::Timeout.to_string()),
+    };
+    result.inspect_err(|_| {
+        update_stats(&app, |s| s.record_failure(Transport::Lan, "host", "error"));
+    })
+}
+
+/// Browse the local network for devices hosting an exchange
+#[tauri::command]
+pub async fn discover_lan_exchanges() -> Result<Vec<LanPeer>, String> {
+    tokio::task::spawn_blocking(|| {
+        lan::discover(std::time::Duration::from_secs(lan::DISCOVERY_SECS))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// Connect to a host found by `discover_lan_exchanges` and run the handshake.
+/// Returns their pubkey for `complete_exchange`.
+#[tauri::command]
+pub async fn join_lan_exchange(
+    address: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<String, String> {
+    begin_exchange_timer(&state, &app, Transport::Lan);
+    let address: std::net::SocketAddr = address.parse().map_err(|_| "Invalid address")?;
+
+    let result = tokio::select! {
+        result = async {
+            let stream = tokio::net::TcpStream::connect(address)
+                .await
+                .map_err(|e| e.to_string())?;
+            lan_guest_handshake(&state, &app, &mut LanConnection::new(stream)).await
+        } => result,
+        _ = state.lan_cancel.notified() => Err("Exchange cancelled".to_string()),
+    };
+    result.inspect_err(|_| {
+        update_stats(&app, |s| s.record_failure(Transport::Lan, "join", "error"));
+    })
+}
+
+/// Stop hosting or joining a local-network exchange
+#[tauri::command]
+pub fn cancel_lan_exchange(state: State<AppState>) {
+    state.lan_cancel.notify_waiters();
+}
+
+// ============================================================================
+// Remote Exchange Commands
+// ============================================================================
+
+/// What a check of the relays turned up
+#[derive(serde::Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteCheckResult {
+    /// Contacts whose answer to our remote exchange arrived
+    pub completed: Vec<Contact>,
+    /// Remote exchanges others started, waiting for us
+    pub requests: Vec<RemoteRequestInfo>,
+}
+
+/// Remote exchanges in both directions, and the relays watched for requests
+#[derive(serde::Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteExchangeStatus {
+    pub pending: Vec<RemoteExchange>,
+    pub requests: Vec<RemoteRequestInfo>,
+    pub relays: Vec<String>,
+}
+
+fn load_remote_exchanges(app: &AppHandle) -> RemoteExchangeStore {
+    app.store(store_file(app))
+        .ok()
+        .and_then(|store| store.get(REMOTE_EXCHANGES_KEY))
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+fn save_remote_exchanges(app: &AppHandle, remote: &RemoteExchangeStore) -> Result<(), String> {
+    let store = app.store(store_file(app)).map_err(|e| e.to_string())?;
+    store.set(REMOTE_EXCHANGES_KEY, json!(remote));
+    store.save().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Sign our exchange message for `their_pubkey` with our local keys and send
+/// it to `relays`. Succeeds if any relay accepted it.
+async fn send_remote_message(
+    state: &AppState,
+    app: &AppHandle,
+    their_pubkey: &str,
+    relays: &[String],
+) -> Result<(), String> {
+    let keys = our_keys(state)?;
+    let protocol = peer_protocol(state, Some(their_pubkey));
+    let hints = if protocol.has(CAP_NODE_HINTS) {
+        our_node_hints(app, Some(their_pubkey))?
+    } else {
+        NodeHints::default()
+    };
+    let profile = if protocol.has(CAP_PROFILE) {
+        load_settings_from_store(app).profile.exchange_profile()
+    } else {
+        None
+    };
+    let payload = ExchangeMessage::new_signed(&keys, Some(their_pubkey), hints, profile, protocol)
+        .await
+        .map_err(|e| e.to_string())?
+        .downgraded(protocol)
+        .encode(protocol)
+        .map_err(|e| e.to_string())?;
+    record_key_use(app, KeyUse::ResponseSign, Some(their_pubkey.to_string()))?;
+    let event =
+        remote::seal(&keys, their_pubkey, &payload, now_secs()).map_err(|e| e.to_string())?;
+
+    let mut errors = Vec::new();
+    for relay in relays {
+        if let Err(e) = relay::publish(relay, &event).await {
+            errors.push(format!("{relay}: {e}"));
+        }
+    }
+    if errors.len() == relays.len() {
+        return Err(errors.join("; "));
+    }
+    Ok(())
+}
+
+/// Save the sender of a verified remote exchange message: signed, but not
+/// met, so still to be reviewed
+fn store_remote_contact(
+    state: &AppState,
+    app: &AppHandle,
+    msg: &ExchangeMessage,
+    relays: &[String],
+) -> Result<Contact, String> {
+    let peer = PeerDetails {
+        transport: Some(Transport::Nostr),
+        origin: ContactOrigin::Remote,
+        nostr_relays: relays.to_vec(),
+        ..PeerDetails::from_message(msg)
+    };
+    store_exchanged_contact(state, app, &msg.pubkey, peer, true, None)
+}
+
+/// Start a remote exchange with `their_pubkey` (an npub or NIP-05 address)
+/// through `relays`, or the relays we know for them if none are given
+#[tauri::command]
+pub async fn start_remote_exchange(
+    their_pubkey: String,
+    relays: Vec<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<RemoteExchange, String> {
+    let identifier = Identifier::parse(&their_pubkey).map_err(|e| e.to_string())?;
+    let their_pubkey = resolve_identifier(&identifier)
+        .await
+        .map_err(|e| e.to_string())?;
+    if identity_pubkey(&state, &app).as_deref() == Some(their_pubkey.as_str()) {
+        return Err("That's your own identifier".to_string());
+    }
+
+    let relays = if relays.iter().all(|r| r.trim().is_empty()) {
+        load_contacts_from_store(&app)
+            .into_iter()
+            .find(|c| c.nostr_pubkey == their_pubkey)
+            .map(|c| c.nostr_relays)
+            .unwrap_or_default()
+    } else {
+        relays
+    };
+    let relays = remote::parse_relays(&relays).map_err(|e| e.to_string())?;
+
+    update_stats(&app, |s| s.record_start(Transport::Nostr));
+    send_remote_message(&state, &app, &their_pubkey, &relays)
+        .await
+        .inspect_err(|_| {
+            update_stats(&app, |s| s.record_failure(Transport::Nostr, "send", "error"));
+        })?;
+
+    let exchange = RemoteExchange {
+        their_pubkey,
+        relays,
+        started_at: now_secs(),
+    };
+    let mut remote = load_remote_exchanges(&app);
+    remote.add_pending(exchange.clone());
+    save_remote_exchanges(&app, &remote)?;
+    Ok(exchange)
+}
+
+/// Fetch remote exchange messages for us from every relay we're waiting on
+/// or watching. Answers to our exchanges complete them; anything else is
+/// queued as a request.
+#[tauri::command]
+pub async fn check_remote_exchanges(
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<RemoteCheckResult, String> {
+    let keys = our_keys(&state)?;
+    let now = now_secs();
+    let mut remote = load_remote_exchanges(&app);
+    remote.expire(now);
+    let relays = remote.relays_to_check();
+    if relays.is_empty() {
+        return Err(RemoteError::NoRelays.to_string());
+    }
+    let filter = json!({
+        "kinds": [REMOTE_EXCHANGE_KIND],
+        "#p": [keys.public_key().to_hex()],
+        "since": remote.fetch_since(now),
+    });
+
+    // The same message may be on several relays; our answer goes to all of them
+    let mut found: Vec<(ExchangeMessage, Vec<String>)> = Vec::new();
+    let mut reached = false;
+    for relay in &relays {
+        let Ok(events) = relay::fetch(relay, filter.clone()).await else {
+            continue;
+        };
+        reached = true;
+        for msg in events.iter().filter_map(|e| remote::open(&keys, e).ok()) {
+            match found.iter_mut().find(|(m, _)| m.signature == msg.signature) {
+                Some((_, seen_on)) => seen_on.push(relay.clone()),
+                None => found.push((msg, vec![relay.clone()])),
+            }
+        }
+    }
+    if !reached {
+        return Err("Couldn't reach any relay".to_string());
+    }
+
+    // Reload, in case an exchange was started or accepted while fetching
+    let mut remote = load_remote_exchanges(&app);
+    remote.expire(now);
+    let mut completed = Vec::new();
+    for (msg, seen_on) in found {
+        // Already handled on an earlier check
+        if check_replay(&state, &app, &msg, remote::REMOTE_TOLERANCE).is_err() {
+            continue;
+        }
+        match remote.take_pending(&msg.pubkey) {
+            Some(pending) => {
+                let contact = store_remote_contact(&state, &app, &msg, &pending.relays)?;
+                let elapsed =
+                    std::time::Duration::from_secs(now.saturating_sub(pending.started_at));
+                update_stats(&app, |s| s.record_success(Transport::Nostr, elapsed));
+                completed.push(contact);
+            }
+            None => remote.add_request(RemoteRequest {
+                their_pubkey: msg.pubkey.clone(),
+                relays: seen_on,
+                received_at: now,
+                message: msg,
+            }),
+        }
+    }
+    remote.last_checked = now;
+    save_remote_exchanges(&app, &remote)?;
+
+    Ok(RemoteCheckResult {
+        completed,
+        requests: remote.requests.iter().map(RemoteRequestInfo::from).collect(),
+    })
+}
+
+/// Answer a remote exchange request and save its sender as a contact
+#[tauri::command]
+pub async fn accept_remote_exchange(
+    their_pubkey: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Contact, String> {
+    let request = load_remote_exchanges(&app)
+        .requests
+        .into_iter()
+        .find(|r| r.their_pubkey == their_pubkey)
+        .ok_or("No remote exchange request from them")?;
+
+    update_stats(&app, |s| s.record_start(Transport::Nostr));
+    send_remote_message(&state, &app, &their_pubkey, &request.relays)
+        .await
+        .inspect_err(|_| {
+            update_stats(&app, |s| s.record_failure(Transport::Nostr, "answer", "error"));
+        })?;
+
+    let mut remote = load_remote_exchanges(&app);
+    remote.take_request(&their_pubkey);
+    save_remote_exchanges(&app, &remote)?;
+
+    let contact = store_remote_contact(&state, &app, &request.message, &request.relays)?;
+    let elapsed = now_secs().saturating_sub(request.message.timestamp);
+    update_stats(&app, |s| {
+        s.record_success(Transport::Nostr, std::time::Duration::from_secs(elapsed))
+    });
+    Ok(contact)
+}
+
+/// Drop a remote exchange request without answering it
+#[tauri::command]
+pub fn decline_remote_exchange(their_pubkey: String, app: AppHandle) -> Result<(), String> {
+    let mut remote = load_remote_exchanges(&app);
+    remote
+        .take_request(&their_pubkey)
+        .ok_or("No remote exchange request from them")?;
+    save_remote_exchanges(&app, &remote)
+}
+
+/// Stop waiting for an answer to a remote exchange we started
+#[tauri::command]
+pub fn cancel_remote_exchange(their_pubkey: String, app: AppHandle) -> Result<(), String> {
+    let mut remote = load_remote_exchanges(&app);
+    remote
+        .take_pending(&their_pubkey)
+        .ok_or("No remote exchange with them")?;
+    save_remote_exchanges(&app, &remote)
+}
+
+/// Replace the relays watched for remote exchange requests (empty stops
+/// watching)
+#[tauri::command]
+pub fn set_remote_relays(relays: Vec<String>, app: AppHandle) -> Result<Vec<String>, String> {
+    let mut remote = load_remote_exchanges(&app);
+    remote.relays = match remote::parse_relays(&relays) {
+        Ok(relays) => relays,
+        Err(RemoteError::NoRelays) => Vec::new(),
+        Err(e) => return Err(e.to_string()),
+    };
+    save_remote_exchanges(&app, &remote)?;
+    Ok(remote.relays)
+}
+
+#[tauri::command]
+pub fn get_remote_exchanges(app: AppHandle) -> RemoteExchangeStatus {
+    let mut remote = load_remote_exchanges(&app);
+    remote.expire(now_secs());
+    RemoteExchangeStatus {
+        requests: remote.requests.iter().map(RemoteRequestInfo::from).collect(),
+        pending: remote.pending,
+        relays: remote.relays,
+    }
+}
+
+// ============================================================================
+// Mock Transport Commands (`mock-transport` feature, desktop development)
+// ============================================================================
+
+#[cfg(not(feature = "mock-transport"))]
+const MOCK_DISABLED: &str = "Built without the mock-transport feature";
+
+/// Put a QR payload on the mock screen shared with other local instances
+#[tauri::command]
+pub fn mock_show_qr(payload: String) -> Result<(), String> {
+    #[cfg(feature = "mock-transport")]
+    {
+        crate::mock_transport::MockField::global()
+            .show_qr(&payload)
+            .map_err(|e| e.to_string())
+    }
+
+    #[cfg(not(feature = "mock-transport"))]
+    {
+        let _ = payload;
+        Err(MOCK_DISABLED.to_string())
+    }
+}
+
+/// "Scan" the mock screen, returning the payload for `process_scanned_qr`
+#[tauri::command]
+pub fn mock_scan_qr() -> Result<String, String> {
+    #[cfg(feature = "mock-transport")]
+    {
+        crate::mock_transport::MockField::global()
+            .scan_qr()
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "No QR code on the mock screen".to_string())
+    }
+
+    #[cfg(not(feature = "mock-transport"))]
+    {
+        Err(MOCK_DISABLED.to_string())
+    }
+}
+
+/// Node ID the contact's local instance started for us, to pass to
+/// `connect_to_contact`
+#[tauri::command]
+pub fn mock_lookup_node_id(
+    contact_pubkey: String,
+    state: State<AppState>,
+) -> Result<Option<String>, String> {
+    #[cfg(feature = "mock-transport")]
+    {
+        let our_pubkey = state.our_pubkey().ok_or("No keys found")?;
+        crate::mock_transport::MockField::global()
+            .lookup_node(&our_pubkey, &contact_pubkey)
+            .map_err(|e| e.to_string())
+    }
+
+    #[cfg(not(feature = "mock-transport"))]
+    {
+        let _ = (contact_pubkey, state);
+        Err(MOCK_DISABLED.to_string())
+    }
+}
+
+// ============================================================================
+// Exchange Statistics Commands
+// ============================================================================
+
+/// Get local exchange timing and reliability statistics per transport
+#[tauri::command]
+pub fn get_exchange_stats(app: AppHandle) -> ExchangeStatsSummary {
+    load_stats_from_store(&app).into()
+}
+
+/// Clear local exchange statistics
+#[tauri::command]
+pub fn reset_exchange_stats(app: AppHandle) -> Result<(), String> {
+    save_stats_to_store(&app, &ExchangeStats::default())
+}
+
+// ============================================================================
+// Settings Commands
+// ============================================================================
+
+/// Get the current settings (defaults if none are stored)
+#[tauri::command]
+pub fn get_settings(app: AppHandle) -> Settings {
+    load_settings_from_store(&app)
+}
+
+/// Apply a partial settings update and return the validated result
+#[tauri::command]
+pub async fn update_settings(
+    patch: serde_json::Value,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Settings, String> {
+    let settings = load_settings_from_store(&app)
+        .apply_patch(&patch)
+        .map_err(|e| e.to_string())?;
+
+    save_settings_to_store(&app, &settings)?;
+
+    // Content filters and the hybrid handshake option take effect immediately
+    if let Some(manager) = state.chat_manager.write().await.as_mut() {
+        manager.set_filters(default_filters(&settings.filters));
+        manager.set_hybrid_handshake(settings.security.post_quantum_chat);
+    }
+
+    Ok(settings)
+}
+
+/// Get the runtime feature flags
+#[tauri::command]
+pub fn get_feature_flags(app: AppHandle) -> FeatureFlags {
+    load_settings_from_store(&app).features
+}
+
+/// Enable or disable an experimental feature
+#[tauri::command]
+pub fn set_feature_flag(
+    flag: FeatureFlag,
+    enabled: bool,
+    app: AppHandle,
+) -> Result<FeatureFlags, String> {
+    let mut settings = load_settings_from_store(&app);
+    settings.features.set(flag, enabled);
+    save_settings_to_store(&app, &settings)?;
+
+    Ok(settings.features)
+}
+
+// ============================================================================
+// Group Commands
+// ============================================================================
+
+/// Sign a membership change for `group_id`, apply it locally, and return the
+/// record so it can be distributed to the other members
+fn change_group_membership(
+    group_id: &str,
+    action: MembershipAction,
+    state: &AppState,
+    app: &AppHandle,
+) -> Result<MembershipChange, String> {
+    require_feature(app, FeatureFlag::GossipGroups)?;
+    let keys = our_keys(state)?;
+
+    let mut groups = load_groups_from_store(app);
+    let group = groups
+        .iter_mut()
+        .find(|g| g.id == group_id)
+        .ok_or("Group not found")?;
+
+    let change = MembershipChange::new(&keys, group_id, action).map_err(|e| e.to_string())?;
+    group.apply(change.clone()).map_err(|e| e.to_string())?;
+
+    save_groups_to_store(app, &groups)?;
+
+    Ok(change)
+}
+
+/// Create a group with ourselves as the admin
+#[tauri::command]
+pub fn create_group(name: String, state: State<AppState>, app: AppHandle) -> Result<Group, String> {
+    require_feature(&app, FeatureFlag::GossipGroups)?;
+    let keys = our_keys(&state)?;
+
+    let group = Group::new(&keys, &name);
+    let mut groups = load_groups_from_store(&app);
+    groups.push(group.clone());
+    save_groups_to_store(&app, &groups)?;
+
+    Ok(group.redacted())
+}
+
+#[tauri::command]
+pub fn get_groups(app: AppHandle) -> Vec<Group> {
+    load_groups_from_store(&app)
+        .iter()
+        .map(Group::redacted)
+        .collect()
+}
+
+/// Add a member (admins only)
+#[tauri::command]
+pub fn add_group_member(
+    group_id: String,
+    member_pubkey: String,
+    admin: bool,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<MembershipChange, String> {
+    let action = MembershipAction::Add {
+        member: member_pubkey,
+        admin,
+    };
+    change_group_membership(&group_id, action, &state, &app)
+}
+
+/// Remove another member (admins only; the creator can't be removed)
+#[tauri::command]
+pub fn remove_group_member(
+    group_id: String,
+    member_pubkey: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<MembershipChange, String> {
+    let action = MembershipAction::Remove {
+        member: member_pubkey,
+    };
+    change_group_membership(&group_id, action, &state, &app)
+}
+
+/// Leave a group
+#[tauri::command]
+pub fn leave_group(
+    group_id: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<MembershipChange, String> {
+    change_group_membership(&group_id, MembershipAction::Leave, &state, &app)
+}
+
+/// Apply a membership change received from another member
+#[tauri::command]
+pub fn apply_membership_change(change: MembershipChange, app: AppHandle) -> Result<Group, String> {
+    require_feature(&app, FeatureFlag::GossipGroups)?;
+
+    let mut groups = load_groups_from_store(&app);
+    let group = groups
+        .iter_mut()
+        .find(|g| g.id == change.group_id)
+        .ok_or("Group not found")?;
+
+    group.apply(change).map_err(|e| e.to_string())?;
+    let group = group.redacted();
+
+    save_groups_to_store(&app, &groups)?;
+
+    Ok(group)
+}
+
+/// Create a signed invite payload for a group (shown as QR or served over NFC)
+#[tauri::command]
+pub fn create_group_invite(
+    group_id: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<String, String> {
+    require_feature(&app, FeatureFlag::GossipGroups)?;
+    let keys = our_keys(&state)?;
+
+    let groups = load_groups_from_store(&app);
+    let group = groups
+        .iter()
+        .find(|g| g.id == group_id)
+        .ok_or("Group not found")?;
+
+    if !group.is_admin(&keys.public_key().to_hex()) {
+        return Err("Only admins can invite".to_string());
+    }
+
+    GroupInvite::new(&keys, group)
+        .and_then(|invite| invite.to_json())
+        .map_err(|e| e.to_string())
+}
+
+/// Verify a scanned group invite and return the acceptance payload to show the inviter
+#[tauri::command]
+pub fn accept_group_invite(
+    payload: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<String, String> {
+    require_feature(&app, FeatureFlag::GossipGroups)?;
+    let keys = our_keys(&state)?;
+
+    let invite = GroupInvite::from_json(payload.trim()).map_err(|e| e.to_string())?;
+    invite.verify().map_err(|e| e.to_string())?;
+
+    let acceptance = InviteAcceptance::new(&keys, &invite).map_err(|e| e.to_string())?;
+    serde_json::to_string(&acceptance).map_err(|e| e.to_string())
+}
+
+/// Admit an invitee from their acceptance and return the encrypted key delivery for them
+#[tauri::command]
+pub fn admit_group_invitee(
+    payload: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<String, String> {
+    require_feature(&app, FeatureFlag::GossipGroups)?;
+    let keys = our_keys(&state)?;
+
+    let acceptance: InviteAcceptance =
+        serde_json::from_str(payload.trim()).map_err(|e| e.to_string())?;
+    acceptance
+        .verify(&keys.public_key().to_hex())
+        .map_err(|e| e.to_string())?;
+
+    let mut groups = load_groups_from_store(&app);
+    let group = groups
+        .iter_mut()
+        .find(|g| g.id == acceptance.group_id)
+        .ok_or("Group not found")?;
+
+    if !group.is_member(acceptance.invitee()) {
+        let add = MembershipChange::new(
+            &keys,
+            &group.id,
+            MembershipAction::Add {
+                member: acceptance.invitee().to_string(),
+                admin: false,
+            },
+        )
+        .map_err(|e| e.to_string())?;
+        group.apply(add).map_err(|e| e.to_string())?;
+    }
+
+    let delivery =
+        GroupKeyDelivery::new(&keys, group, acceptance.invitee()).map_err(|e| e.to_string())?;
+
+    save_groups_to_store(&app, &groups)?;
+
+    serde_json::to_string(&delivery).map_err(|e| e.to_string())
+}
+
+/// Join a group from a key delivery addressed to us
+#[tauri::command]
+pub fn receive_group_key(
+    payload: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<Group, String> {
+    require_feature(&app, FeatureFlag::GossipGroups)?;
+    let keys = our_keys(&state)?;
+
+    let delivery: GroupKeyDelivery =
+        serde_json::from_str(payload.trim()).map_err(|e| e.to_string())?;
+    let group = delivery.open(&keys).map_err(|e| e.to_string())?;
+
+    let mut groups = load_groups_from_store(&app);
+    groups.retain(|g| g.id != group.id);
+    groups.push(group.clone());
+    save_groups_to_store(&app, &groups)?;
+
+    Ok(group.redacted())
+}
+
+// ============================================================================
+// Broadcast Channel Commands
+// ============================================================================
+
+/// Create a channel that we publish to
+#[tauri::command]
+pub fn create_broadcast_channel(
+    name: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<BroadcastChannel, String> {
+    require_feature(&app, FeatureFlag::GossipGroups)?;
+    let keys = our_keys(&state)?;
+
+    let channel = BroadcastChannel::new_owned(&keys, &name);
+    let mut channels = load_channels_from_store(&app);
+    if channels.iter().any(|c| c.id == channel.id) {
+        return Err("Channel already exists".to_string());
+    }
+    channels.push(channel.clone());
+    save_channels_to_store(&app, &channels)?;
+
+    Ok(channel)
+}
+
+/// Subscribe to a contact's channel
+#[tauri::command]
+pub fn subscribe_broadcast_channel(
+    owner_pubkey: String,
+    name: String,
+    app: AppHandle,
+) -> Result<BroadcastChannel, String> {
+    require_feature(&app, FeatureFlag::GossipGroups)?;
+
+    if !load_contacts_from_store(&app)
+        .iter()
+        .any(|c| c.nostr_pubkey == owner_pubkey)
+    {
+        return Err("Can only subscribe to contacts' channels".to_string());
+    }
+
+    let channel = BroadcastChannel::subscription(&owner_pubkey, &name);
+    let mut channels = load_channels_from_store(&app);
+    if let Some(existing) = channels.iter().find(|c| c.id == channel.id) {
+        return Ok(existing.clone());
+    }
+    channels.push(channel.clone());
+    save_channels_to_store(&app, &channels)?;
+
+    Ok(channel)
+}
+
+/// Unsubscribe from (or delete) a channel
+#[tauri::command]
+pub fn remove_broadcast_channel(channel_id: String, app: AppHandle) -> Result<(), String> {
+    let mut channels = load_channels_from_store(&app);
+    channels.retain(|c| c.id != channel_id);
+    save_channels_to_store(&app, &channels)
+}
+
+#[tauri::command]
+pub fn get_broadcast_channels(app: AppHandle) -> Vec<BroadcastChannel> {
+    load_channels_from_store(&app)
+}
+
+/// Sign a post on one of our channels and return it for distribution on the channel topic
+#[tauri::command]
+pub fn publish_broadcast(
+    channel_id: String,
+    content: String,
+    state: State<AppState>,
+    app: AppHandle,
+) -> Result<BroadcastPost, String> {
+    require_feature(&app, FeatureFlag::GossipGroups)?;
+    let keys = our_keys(&state)?;
+
+    let mut channels = load_channels_from_store(&app);
+    let channel = channels
+        .iter_mut()
+        .find(|c| c.id == channel_id)
+        .ok_or("Channel not found")?;
+
+    let post = channel.publish(&keys, &content).map_err(|e| e.to_string())?;
+    save_channels_to_store(&app, &channels)?;
+
+    Ok(post)
+}
+
+/// Store a post received on a subscribed channel. Returns false for duplicates.
+#[tauri::command]
+pub fn receive_broadcast_post(post: BroadcastPost, app: AppHandle) -> Result<bool, String> {
+    require_feature(&app, FeatureFlag::GossipGroups)?;
+
+    let mut channels = load_channels_from_store(&app);
+    let channel = channels
+        .iter_mut()
+        .find(|c| c.id == post.channel_id)
+        .ok_or("Not subscribed to this channel")?;
+
+    let added = channel.receive(post).map_err(|e| e.to_string())?;
+    if added {
+        save_channels_to_store(&app, &channels)?;
+    }
+
+    Ok(added)
+}
+
+// ============================================================================
+// Iroh Chat Commands
+// ============================================================================
+
+/// A chat manager for `our_pubkey` with our filters and plugins, saving chat
+/// ratchets, and history when persistence is on, to the profile's message
+/// database. If the database can't be opened, chat still works for the
+/// session.
+fn new_chat_manager(
+    state: &AppState,
+    app: &AppHandle,
+    our_pubkey: &str,
+    settings: &Settings,
+) -> ChatManager {
+    let mut manager = ChatManager::new(our_pubkey, settings.persistence.persist_messages);
+    manager.set_filters(default_filters(&settings.filters));
+    manager.set_plugins(state.plugins.clone());
+    manager.set_hybrid_handshake(settings.security.post_quantum_chat);
+    manager.set_groups(&load_groups_from_store(app));
+    if let Ok(store) = open_message_db(state, app, settings) {
+        manager.set_store(store);
+    }
+    manager
+}
+
+/// Open the active profile's message database, dropping messages older than
+/// the retention period
+fn open_message_db(
+    state: &AppState,
+    app: &AppHandle,
+    settings: &Settings,
+) -> Result<MessageStore, String> {
+    let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let file = state.profile.lock().map_err(|e| e.to_string())?.messages_db_file();
+    let store = MessageStore::open(&dir.join(file)).map_err(|e| e.to_string())?;
+
+    if let Some(days) = settings.persistence.retention_days {
+        let cutoff = now_secs().saturating_sub(u64::from(days) * 24 * 60 * 60);
+        store.prune_before(cutoff).map_err(|e| e.to_string())?;
+    }
+    Ok(store)
+}
+
+/// Start Iroh node for a contact
+#[tauri::command]
+pub async fn start_iroh(
+    contact_pubkey: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<IrohStatus, String> {
+    let settings = load_settings_from_store(&app);
+
+    // Get our keys; chat needs the secret key
+    if is_watch_only(&app) {
+        return Err(WATCH_ONLY_ERROR.to_string());
+    }
+    let device = state
+        .with_keys(|stored| {
+            migrate_endpoint_ids(&app, stored)?;
+            with_device_identity(&app, stored, |device, _| {
+                let secret_key_bytes = device.secret_bytes().map_err(|e| e.to_string())?;
+                let keys = restore_keys(device).map_err(|e| e.to_string())?;
+                Ok::<_, String>((secret_key_bytes, device.public_key_hex.clone(), keys))
+            })?
+        })
+        .ok_or("No keys found")?;
+    let (secret_key_bytes, our_pubkey, keys) = device?;
+
+    // Start Iroh node with the configured relays
+    let mut node = state.iroh_node.write().await;
+    node.set_config(IrohConfig {
+        use_relays: settings.relays.use_relays,
+        custom_relay_url: settings.relays.custom_relay_url.clone(),
+    });
+    node.set_extra_alpns(state.plugins.alpns());
+    let _node_id = node
+        .start_for_contact(&secret_key_bytes, &our_pubkey, &contact_pubkey)
+        .await
+        .map_err(|e| e.to_string())?;
+    record_key_use(&app, KeyUse::IrohDerivation, Some(contact_pubkey.clone()))?;
+
+    // Let the contact's local instance find us
+    #[cfg(feature = "mock-transport")]
+    crate::mock_transport::MockField::global()
+        .publish_node(&our_pubkey, &contact_pubkey, &_node_id)
+        .map_err(|e| e.to_string())?;
+
+    // Initialize chat manager if not already, and seed the contact's ratchet
+    // and disappearing message timer
+    {
+        let chat_secret =
+            derive_chat_secret(&secret_key_bytes, &our_pubkey, &contact_pubkey)
+                .map_err(|e| e.to_string())?;
+        let disappear_after = load_contacts_from_store(&app)
+            .into_iter()
+            .find(|c| c.nostr_pubkey == contact_pubkey)
+            .and_then(|c| c.disappear_after_secs);
+        let mut chat_manager = state.chat_manager.write().await;
+        let manager = chat_manager.get_or_insert_with(|| {
+            new_chat_manager(&state, &app, &our_pubkey, &settings)
+        });
+        manager.set_chat_secret(&contact_pubkey, chat_secret);
+        manager.set_disappear_after(&contact_pubkey, disappear_after);
+    }
+
+    // Keep accepting the contact's connections independently of the window
+    if let Some(endpoint) = node.endpoint() {
+        crate::listener::spawn_accept_loop(
+            app.clone(),
+            endpoint.clone(),
+            contact_pubkey.clone(),
+            keys,
+        );
+    }
+
+    Ok(node.status())
+}
+
+/// Stop Iroh node
+#[tauri::command]
+pub async fn stop_iroh(state: State<'_, AppState>) -> Result<(), String> {
+    let mut node = state.iroh_node.write().await;
+    node.stop().await.map_err(|e| e.to_string())
+}
+
+/// Get Iroh status
+#[tauri::command]
+pub async fn get_iroh_status(state: State<'_, AppState>) -> Result<IrohStatus, String> {
+    let node = state.iroh_node.read().await;
+    Ok(node.status())
+}
+
+/// Connect to a contact's Iroh endpoint
+#[tauri::command]
+pub async fn connect_to_contact(
+    contact_pubkey: String,
+    their_node_id: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), String> {
+    connect_and_listen(&state, &app, &their_node_id, &contact_pubkey).await?;
+    Ok(())
+}
+
+/// Connect to a contact, bind the connection to their key, start listening
+/// on it, and record the attempt in a running connection trace
+async fn connect_and_listen(
+    state: &AppState,
+    app: &AppHandle,
+    their_node_id: &str,
+    contact_pubkey: &str,
+) -> Result<Connection, String> {
+    let keys = chat_keys(state, app)?;
+    let mut node = state.iroh_node.write().await;
+    trace::record(
+        state,
+        contact_pubkey,
+        TraceEventKind::DiscoveryLookup,
+        Some(node.discovery_summary(their_node_id)),
+    );
+    trace::record(
+        state,
+        contact_pubkey,
+        TraceEventKind::ConnectStarted,
+        Some(their_node_id.to_string()),
+    );
+
+    let connection = match node.connect_to_contact(their_node_id).await {
+        Ok(connection) => connection,
+        Err(e) => {
+            trace::record(
+                state,
+                contact_pubkey,
+                TraceEventKind::ConnectFailed,
+                Some(e.to_string()),
+            );
+            return Err(e.to_string());
+        }
+    };
+    trace::record(state, contact_pubkey, TraceEventKind::Connected, None);
+
+    // No frames go either way until the peer proves the contact's key
+    let local_node = node.status().node_id.ok_or("Iroh endpoint not started")?;
+    crate::listener::bind_connection(app, &connection, contact_pubkey, keys, &local_node)
+        .await
+        .map_err(|e| e.to_string())?;
+    node.add_connection(contact_pubkey, connection.clone());
+    crate::listener::spawn_message_listener(
+        app.clone(),
+        connection.clone(),
+        contact_pubkey.to_string(),
+    );
+    crate::outbox::spawn_flush(app.clone(), contact_pubkey.to_string());
+
+    let trace_id = state
+        .connection_trace
+        .lock()
+        .unwrap()
+        .as_ref()
+        .filter(|t| t.contact_pubkey == contact_pubkey)
+        .map(|t| t.id.clone());
+    if let Some(trace_id) = trace_id {
+        trace::spawn_path_watcher(app.clone(), trace_id, contact_pubkey.to_string());
+    }
+
+    Ok(connection)
+}
+
+/// Start capturing a connection trace for a contact, replacing any running
+/// trace. Returns the trace ID.
+#[tauri::command]
+pub async fn start_connection_trace(
+    contact_pubkey: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<String, String> {
+    let mut trace = ConnectionTrace::new(&contact_pubkey);
+    {
+        let node = state.iroh_node.read().await;
+        trace.record(TraceEventKind::HomeRelay, node.home_relay());
+        if let Some(connection) = node.get_connection(&contact_pubkey) {
+            trace.record(
+                TraceEventKind::PathChanged,
+                Some(format!("{:?}", node.path_type(connection)).to_lowercase()),
+            );
+        }
+    }
+
+    let trace_id = trace.id.clone();
+    *state.connection_trace.lock().unwrap() = Some(trace);
+    trace::spawn_path_watcher(app, trace_id.clone(), contact_pubkey);
+
+    Ok(trace_id)
+}
+
+/// Stop the running connection trace and return its timeline
+#[tauri::command]
+pub fn stop_connection_trace(state: State<AppState>) -> Result<ConnectionTrace, String> {
+    state
+        .connection_trace
+        .lock()
+        .unwrap()
+        .take()
+        .map(ConnectionTrace::finish)
+        .ok_or_else(|| "No connection trace running".to_string())
+}
+
+/// One-shot reachability check: ping the contact over the existing
+/// connection, or connect first when `their_node_id` is given, and report the
+/// round-trip time and path type
+#[tauri::command]
+pub async fn ping_contact(
+    contact_id: String,
+    their_node_id: Option<String>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<PingResult, String> {
+    let contact_pubkey = load_contacts_from_store(&app)
+        .into_iter()
+        .find(|c| c.id == contact_id)
+        .ok_or("Contact not found")?
+        .nostr_pubkey;
+
+    let existing = state
+        .iroh_node
+        .read()
+        .await
+        .get_connection(&contact_pubkey)
+        .cloned();
+    let connected_now = existing.is_none();
+    let connection = match (existing, their_node_id) {
+        (Some(connection), _) => connection,
+        (None, Some(node_id)) => {
+            connect_and_listen(&state, &app, &node_id, &contact_pubkey).await?
+        }
+        (None, None) => return Err("Not connected to contact".to_string()),
+    };
+
+    trace::record(
+        &state,
+        &contact_pubkey,
+        TraceEventKind::StreamOpened,
+        Some("ping".to_string()),
+    );
+    let rtt = ping(&connection).await.map_err(|e| e.to_string())?;
+    let path = state.iroh_node.read().await.path_type(&connection);
+
+    Ok(PingResult {
+        contact_id,
+        rtt_ms: rtt.as_millis() as u64,
+        path,
+        connected_now,
+    })
+}
+
+/// Send a message to a contact. If they can't be reached it is queued in
+/// the outbox and returned still pending, to go out once they reconnect.
+#[tauri::command]
+pub async fn send_message(
+    contact_pubkey: String,
+    content: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<ChatMessage, String> {
+    let node = state.iroh_node.read().await;
+    let mut chat_manager_guard = state.chat_manager.write().await;
+    let chat_manager = chat_manager_guard
+        .as_mut()
+        .ok_or("Chat manager not initialized")?;
+    let message = chat_manager.compose(&content);
+
+    // Get the connection
+    let Some(connection) = node.get_connection(&contact_pubkey).cloned() else {
+        return crate::outbox::queue(&app, &contact_pubkey, message, "Not connected to contact");
+    };
+    trace::record(
+        &state,
+        &contact_pubkey,
+        TraceEventKind::StreamOpened,
+        Some("chat".to_string()),
+    );
+
+    match chat_manager
+        .send_composed(&connection, &contact_pubkey, message.clone())
+        .await
+    {
+        // The connection went away under us; retried once it is back
+        Err(ChatError::SendFailed(e)) => crate::outbox::queue(&app, &contact_pubkey, message, &e),
+        result => result.map_err(|e| e.to_string()),
+    }
+}
+
+/// Send a text message to every other member of a group we are in. Members
+/// who aren't connected miss it; its per-member delivery is in
+/// `get_message_info`.
+#[tauri::command]
+pub async fn send_group_message(
+    group_id: String,
+    content: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<ChatMessage, String> {
+    require_feature(&app, FeatureFlag::GossipGroups)?;
+    let groups = load_groups_from_store(&app);
+    let group = groups
+        .iter()
+        .find(|g| g.id == group_id)
+        .ok_or("Group not found")?;
+
+    let node = state.iroh_node.read().await;
+    let connections: HashMap<String, Connection> = group
+        .members
+        .iter()
+        .filter_map(|m| {
+            let connection = node.get_connection(&m.pubkey)?.clone();
+            Some((m.pubkey.clone(), connection))
+        })
+        .collect();
+
+    let mut chat_manager_guard = state.chat_manager.write().await;
+    let chat_manager = chat_manager_guard
+        .as_mut()
+        .ok_or("Chat manager not initialized")?;
+    chat_manager.set_groups(&groups);
+
+    chat_manager
+        .send_group_message(&connections, &group_id, &content)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Tell the senders of group messages we have read them. Senders we aren't
+/// connected to, or who don't take read receipts, aren't told.
+#[tauri::command]
+pub async fn mark_group_read(
+    group_id: String,
+    message_ids: Vec<String>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let node = state.iroh_node.read().await;
+    let mut chat_manager_guard = state.chat_manager.write().await;
+    let chat_manager = chat_manager_guard
+        .as_mut()
+        .ok_or("Chat manager not initialized")?;
+
+    for message in chat_manager.get_messages(&group_id) {
+        if !message_ids.contains(&message.id) {
+            continue;
+        }
+        let (Some(frame), Some(connection)) = (
+            chat_manager.read_receipt_frame(&message),
+            node.get_connection(&message.sender_pubkey),
+        ) else {
+            continue;
+        };
+        let _ = write_frame(connection, &frame).await;
+    }
+    Ok(())
+}
+
+/// Messages waiting for their contact to be reachable, oldest first,
+/// optionally for one contact only
+#[tauri::command]
+pub fn get_outbox(contact_pubkey: Option<String>, app: AppHandle) -> Vec<QueuedMessage> {
+    load_outbox_from_store(&app)
+        .into_iter()
+        .filter(|q| {
+            contact_pubkey
+                .as_ref()
+                .map_or(true, |pk| &q.contact_pubkey == pk)
+        })
+        .collect()
+}
+
+/// Try sending a queued message again now
+#[tauri::command]
+pub async fn retry_message(message_id: String, app: AppHandle) -> Result<ChatMessage, String> {
+    crate::outbox::retry(&app, &message_id).await
+}
+
+/// Drop a queued message without sending it
+#[tauri::command]
+pub fn cancel_queued_message(message_id: String, app: AppHandle) -> Result<(), String> {
+    let mut outbox = load_outbox_from_store(&app);
+    let before = outbox.len();
+    outbox.retain(|q| q.message.id != message_id);
+    if outbox.len() == before {
+        return Err(crate::outbox::OutboxError::NotFound.to_string());
     }
+    save_outbox_to_store(&app, &outbox)
 }
 
-/// Receive and process an NFC exchange message (read mode)
-/// Returns their pubkey if successful
+/// Edit a text message we sent a contact, within `chat::EDIT_WINDOW_SECS` of sending it
 #[tauri::command]
-pub async fn start_nfc_receive(
+pub async fn edit_message(
+    contact_pubkey: String,
+    message_id: String,
+    content: String,
     state: State<'_, AppState>,
-    app: AppHandle,
-) -> Result<String, String> {
-    #[cfg(any(target_os = "android", target_os = "ios"))]
-    {
-        use tauri_plugin_nfc::NfcExt;
-        
-        // Get our pubkey for verification
-        let our_pubkey = {
-            let keys = state.keys.lock().unwrap();
-            keys.as_ref().map(|k| k.public_key_hex.clone())
-        };
-        
-        // Scan for NDEF tag with our MIME type
-        let scan_result = app
-            .nfc()
-            .scan(tauri_plugin_nfc::ScanRequest {
-                kind: tauri_plugin_nfc::ScanKind::Ndef {
-                    mime_type: Some(crate::exchange::NDEF_MIME_TYPE.to_string()),
-                    uri: None,
-                    tech_list: None,
-                },
-                keep_session_alive: true,
-            })
-            .map_err(|e| e.to_string())?;
-        
-        // Extract the records from the tag
-        let tag = scan_result.tag;
-        
-        // Find our record
-        for record in tag.records {
-            let payload_str = String::from_utf8(record.payload)
-                .map_err(|e| e.to_string())?;
-            
-            // Try to parse the exchange message
-            if let Ok(msg) = ExchangeMessage::from_json(&payload_str) {
-                // Verify the message
-                // If this is a response (has their_pubkey), verify it matches us
-                msg.verify(our_pubkey.as_deref()).map_err(|e| e.to_string())?;
-                
-                return Ok(msg.pubkey);
-            }
-        }
-        
-        Err("No valid exchange message found".to_string())
-    }
-    
-    #[cfg(not(any(target_os = "android", target_os = "ios")))]
-    {
-        let _ = state;
-        let _ = app;
-        Err("NFC not supported on this platform".to_string())
-    }
+) -> Result<ChatMessage, String> {
+    let node = state.iroh_node.read().await;
+    let connection = node
+        .get_connection(&contact_pubkey)
+        .ok_or("Not connected to contact")?
+        .clone();
+
+    let mut chat_manager_guard = state.chat_manager.write().await;
+    let chat_manager = chat_manager_guard
+        .as_mut()
+        .ok_or("Chat manager not initialized")?;
+
+    chat_manager
+        .edit_message(&connection, &contact_pubkey, &message_id, &content)
+        .await
+        .map_err(|e| e.to_string())
 }
 
-/// Write a response after receiving their pubkey
+/// Delete a message on this device, and with `for_everyone` (messages we
+/// sent only) replace the contact's copy with a placeholder through a signed
+/// tombstone
 #[tauri::command]
-pub async fn write_nfc_response(
-    their_pubkey: String,
+pub async fn delete_message(
+    message_id: String,
+    for_everyone: bool,
     state: State<'_, AppState>,
     app: AppHandle,
 ) -> Result<(), String> {
-    // Get our keys
-    let stored = {
-        let keys = state.keys.lock().unwrap();
-        keys.clone().ok_or("No keys found")?
-    };
-    
-    let our_keys = restore_keys(&stored).map_err(|e| e.to_string())?;
-    
-    // Create signed response that includes their pubkey
-    let msg = ExchangeMessage::new_response(&our_keys, &their_pubkey)
-        .map_err(|e| e.to_string())?;
-    
-    let json = msg.to_json().map_err(|e| e.to_string())?;
-    
-    #[cfg(any(target_os = "android", target_os = "ios"))]
-    {
-        use tauri_plugin_nfc::{NfcRecord, NfcExt, NFCTypeNameFormat};
-        
-        // Write to NFC using Media type for MIME
-        app.nfc()
-            .write(vec![NfcRecord {
-                format: NFCTypeNameFormat::Media,
-                kind: crate::exchange::NDEF_MIME_TYPE.as_bytes().to_vec(),
-                id: vec![],
-                payload: json.into_bytes(),
-            }])
-            .map_err(|e| e.to_string())?;
-        
-        Ok(())
-    }
-    
-    #[cfg(not(any(target_os = "android", target_os = "ios")))]
-    {
-        let _ = app;
-        let _ = json;
-        Err("NFC not supported on this platform".to_string())
+    let node = state.iroh_node.read().await;
+    let mut chat_manager_guard = state.chat_manager.write().await;
+    let chat_manager = chat_manager_guard
+        .as_mut()
+        .ok_or("Chat manager not initialized")?;
+    let contact_pubkey = chat_manager
+        .contact_of(&message_id)
+        .ok_or("Message not found")?;
+
+    if !for_everyone {
+        return chat_manager
+            .delete_message(&contact_pubkey, &message_id)
+            .map_err(|e| e.to_string());
     }
+    let connection = node
+        .get_connection(&contact_pubkey)
+        .ok_or("Not connected to contact")?
+        .clone();
+    let keys = chat_keys(&state, &app)?;
+    let tombstone = Tombstone::new(&keys, &message_id, now_secs()).map_err(|e| e.to_string())?;
+    chat_manager
+        .delete_for_everyone(&connection, &contact_pubkey, tombstone)
+        .await
+        .map_err(|e| e.to_string())
 }
 
-// Legacy command for backward compatibility - now calls start_nfc_receive
+/// Tell a contact whether we are typing. Repeat every `TYPING_REFRESH_SECS`
+/// while typing; notifications are datagrams and may be lost.
 #[tauri::command]
-pub async fn start_nfc_scan(
+pub async fn set_typing(
+    contact_pubkey: String,
+    typing: bool,
     state: State<'_, AppState>,
-    app: AppHandle,
-) -> Result<String, String> {
-    start_nfc_receive(state, app).await
+) -> Result<(), String> {
+    let node = state.iroh_node.read().await;
+    let connection = node
+        .get_connection(&contact_pubkey)
+        .ok_or("Not connected to contact")?;
+    crate::typing::send_typing(connection, typing).map_err(|e| e.to_string())
 }
 
+/// Make new messages with a contact disappear `secs` after they are sent, or
+/// keep them if `None`. The shorter of our timer and the contact's applies;
+/// they learn of ours from the next message we send them.
 #[tauri::command]
-pub async fn complete_exchange(
-    their_pubkey: String,
+pub async fn set_disappearing_messages(
+    contact_id: String,
+    secs: Option<u64>,
     state: State<'_, AppState>,
     app: AppHandle,
 ) -> Result<Contact, String> {
-    // Get our keys
-    let stored = {
-        let keys = state.keys.lock().unwrap();
-        keys.clone().ok_or("No keys found")?
-    };
-    
-    // Derive Iroh endpoint ID
-    let secret_key_bytes = hex::decode(&stored.secret_key_hex)
-        .map_err(|e| e.to_string())?;
-    
-    let iroh_endpoint_id = derive_endpoint_id(
-        &secret_key_bytes,
-        &stored.public_key_hex,
-        &their_pubkey,
-    )
-    .map_err(|e| e.to_string())?;
-    
-    // Create contact
-    let contact = Contact::new(&their_pubkey, &iroh_endpoint_id);
-    
-    // Load existing contacts, add new one, save
+    if let Some(secs) = secs {
+        crate::disappearing::validate_timer(secs).map_err(|e| e.to_string())?;
+    }
     let mut contacts = load_contacts_from_store(&app);
-    
-    // Check if contact already exists (by pubkey)
-    if !contacts.iter().any(|c| c.nostr_pubkey == their_pubkey) {
-        contacts.insert(0, contact.clone()); // Add to front
-        save_contacts_to_store(&app, &contacts)?;
+    let contact = contacts
+        .iter_mut()
+        .find(|c| c.id == contact_id)
+        .ok_or("Contact not found")?;
+
+    contact.disappear_after_secs = secs;
+    let contact = contact.clone();
+    save_contacts_to_store(&app, &contacts)?;
+
+    if let Some(manager) = state.chat_manager.write().await.as_mut() {
+        manager.set_disappear_after(&contact.nostr_pubkey, secs);
     }
-    
     Ok(contact)
 }
 
-// ============================================================================
-// Contact Management Commands
-// ============================================================================
-
+/// Schedule a message to a contact for `send_at` (Unix seconds). It is sent
+/// once due and a connection is available, and stays queued until then.
 #[tauri::command]
-pub fn get_contacts(app: AppHandle) -> Vec<Contact> {
-    load_contacts_from_store(&app)
+pub fn schedule_message(
+    contact_pubkey: String,
+    content: String,
+    send_at: u64,
+    app: AppHandle,
+) -> Result<ScheduledMessage, String> {
+    if !load_contacts_from_store(&app)
+        .iter()
+        .any(|c| c.nostr_pubkey == contact_pubkey)
+    {
+        return Err("Contact not found".to_string());
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let message =
+        ScheduledMessage::new(&contact_pubkey, &content, send_at, now).map_err(|e| e.to_string())?;
+
+    let mut scheduled = load_scheduled_from_store(&app);
+    crate::schedule::insert(&mut scheduled, message.clone()).map_err(|e| e.to_string())?;
+    save_scheduled_to_store(&app, &scheduled)?;
+
+    Ok(message)
 }
 
+/// Pending scheduled messages, earliest first, optionally for one contact
 #[tauri::command]
-pub fn delete_contact(id: String, app: AppHandle) -> Result<(), String> {
-    let mut contacts = load_contacts_from_store(&app);
-    contacts.retain(|c| c.id != id);
-    save_contacts_to_store(&app, &contacts)
+pub fn get_scheduled_messages(
+    contact_pubkey: Option<String>,
+    app: AppHandle,
+) -> Vec<ScheduledMessage> {
+    load_scheduled_from_store(&app)
+        .into_iter()
+        .filter(|m| contact_pubkey.as_ref().map_or(true, |pk| &m.contact_pubkey == pk))
+        .collect()
 }
 
-// ============================================================================
-// QR Exchange Commands
-// ============================================================================
-
-/// Get the exchange payload for QR code generation
+/// Cancel a scheduled message that hasn't been sent yet
 #[tauri::command]
-pub fn get_exchange_qr_payload(
-    their_pubkey: Option<String>,
-    state: State<AppState>,
-) -> Result<String, String> {
-    // Get our keys
-    let stored = {
-        let keys = state.keys.lock().unwrap();
-        keys.clone().ok_or("No keys found")?
-    };
+pub fn cancel_scheduled_message(id: String, app: AppHandle) -> Result<(), String> {
+    let mut scheduled = load_scheduled_from_store(&app);
+    let before = scheduled.len();
+    scheduled.retain(|m| m.id != id);
+    if scheduled.len() == before {
+        return Err("Scheduled message not found".to_string());
+    }
+    save_scheduled_to_store(&app, &scheduled)
+}
 
-    let our_keys = restore_keys(&stored).map_err(|e| e.to_string())?;
+/// Get messages for a contact. Saved history is loaded on first use,
+/// starting the chat manager for it if Iroh isn't running yet.
+#[tauri::command]
+pub async fn get_messages(
+    contact_pubkey: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Vec<ChatMessage>, String> {
+    let mut chat_manager_guard = state.chat_manager.write().await;
+    if chat_manager_guard.is_none() {
+        if let Some(our_pubkey) = identity_pubkey(&state, &app) {
+            let settings = load_settings_from_store(&app);
+            *chat_manager_guard = Some(new_chat_manager(&state, &app, &our_pubkey, &settings));
+        }
+    }
 
-    // Create exchange message
-    let msg = if let Some(ref their_pk) = their_pubkey {
-        ExchangeMessage::new_response(&our_keys, their_pk)
-    } else {
-        ExchangeMessage::new_initial(&our_keys)
+    match chat_manager_guard.as_mut() {
+        Some(manager) => Ok(manager.get_messages(&contact_pubkey)),
+        None => Ok(vec![]),
     }
-    .map_err(|e| e.to_string())?;
+}
+
+/// A message with its delivery timeline: queued, sent, delivered and read
+/// times, the transport used, retries and the last error
+#[tauri::command]
+pub async fn get_message_info(
+    message_id: String,
+    state: State<'_, AppState>,
+) -> Result<MessageInfo, String> {
+    let chat_manager_guard = state.chat_manager.read().await;
 
-    msg.to_json().map_err(|e| e.to_string())
+    chat_manager_guard
+        .as_ref()
+        .and_then(|manager| manager.message_info(&message_id))
+        .ok_or_else(|| "Message not found".to_string())
 }
 
-/// Process a scanned QR code and return the contact's pubkey
+/// Send a typed payload (location, contact card, payment, ...) to a contact
 #[tauri::command]
-pub fn process_scanned_qr(
-    qr_data: String,
-    state: State<AppState>,
-) -> Result<String, String> {
-    // Parse the QR data as an exchange message
-    let msg = ExchangeMessage::from_json(&qr_data).map_err(|e| e.to_string())?;
+pub async fn send_typed_message(
+    contact_pubkey: String,
+    kind: String,
+    payload: serde_json::Value,
+    state: State<'_, AppState>,
+) -> Result<ChatMessage, String> {
+    // Get the connection
+    let node = state.iroh_node.read().await;
+    let connection = node
+        .get_connection(&contact_pubkey)
+        .ok_or("Not connected to contact")?
+        .clone();
+    trace::record(&state, &contact_pubkey, TraceEventKind::StreamOpened, Some(kind.clone()));
 
-    // Get our pubkey to verify if this is a response to us
-    let our_pubkey = {
-        let keys = state.keys.lock().unwrap();
-        keys.as_ref().map(|k| k.public_key_hex.clone())
-    };
+    let mut chat_manager_guard = state.chat_manager.write().await;
+    let chat_manager = chat_manager_guard
+        .as_mut()
+        .ok_or("Chat manager not initialized")?;
 
-    // Verify the message
-    msg.verify(our_pubkey.as_deref()).map_err(|e| e.to_string())?;
+    chat_manager
+        .send_typed_message(&connection, &contact_pubkey, &kind, payload)
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    // Return their pubkey
-    Ok(msg.pubkey)
+/// Message kinds this client can render natively
+#[tauri::command]
+pub async fn get_message_kinds(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    let chat_manager_guard = state.chat_manager.read().await;
+
+    Ok(match chat_manager_guard.as_ref() {
+        Some(manager) => manager.registry().kinds(),
+        None => MessageTypeRegistry::default().kinds(),
+    })
 }
 
-// ============================================================================
-// Iroh Chat Commands
-// ============================================================================
+/// Content shared from another app, waiting for a contact to be picked
+#[tauri::command]
+pub fn get_pending_share() -> Option<PendingShareInfo> {
+    crate::share::pending().map(|share| share.info())
+}
 
-/// Start Iroh node for a contact
+/// Send the pending share to a contact through the chat pipeline
 #[tauri::command]
-pub async fn start_iroh(
+pub async fn share_to_contact(
+    share_id: String,
     contact_pubkey: String,
+    caption: Option<String>,
     state: State<'_, AppState>,
-) -> Result<IrohStatus, String> {
-    // Get our keys
-    let stored = {
-        let keys = state.keys.lock().unwrap();
-        keys.clone().ok_or("No keys found")?
-    };
+) -> Result<ChatMessage, String> {
+    let share = crate::share::pending()
+        .filter(|s| s.id == share_id)
+        .ok_or("Share no longer pending")?;
 
-    let secret_key_bytes = hex::decode(&stored.secret_key_hex).map_err(|e| e.to_string())?;
+    let node = state.iroh_node.read().await;
+    let connection = node
+        .get_connection(&contact_pubkey)
+        .ok_or("Not connected to contact")?
+        .clone();
 
-    // Start Iroh node
-    let mut node = state.iroh_node.write().await;
-    let _node_id = node
-        .start_for_contact(&secret_key_bytes, &stored.public_key_hex, &contact_pubkey)
-        .await
-        .map_err(|e| e.to_string())?;
+    let mut chat_manager_guard = state.chat_manager.write().await;
+    let chat_manager = chat_manager_guard
+        .as_mut()
+        .ok_or("Chat manager not initialized")?;
 
-    // Initialize chat manager if not already
-    {
-        let mut chat_manager = state.chat_manager.write().await;
-        if chat_manager.is_none() {
-            *chat_manager = Some(ChatManager::new(&stored.public_key_hex, false));
+    let message = match &share.content {
+        SharedContent::Text(text) => {
+            let content = match caption.as_deref().map(str::trim) {
+                Some(caption) if !caption.is_empty() => format!("{}\n\n{}", caption, text),
+                _ => text.clone(),
+            };
+            chat_manager
+                .send_message(&connection, &contact_pubkey, &content)
+                .await
+        }
+        SharedContent::Image { .. } => {
+            let payload = share
+                .image_payload(caption.as_deref())
+                .ok_or("Invalid shared image")?;
+            chat_manager
+                .send_typed_message(&connection, &contact_pubkey, share.kind(), payload)
+                .await
         }
     }
+    .map_err(|e| e.to_string())?;
 
-    Ok(node.status())
+    crate::share::clear(&share.id);
+    Ok(message)
 }
 
-/// Stop Iroh node
+/// Drop the pending share without sending it
 #[tauri::command]
-pub async fn stop_iroh(state: State<'_, AppState>) -> Result<(), String> {
-    let mut node = state.iroh_node.write().await;
-    node.stop().await.map_err(|e| e.to_string())
+pub fn discard_pending_share(share_id: String) -> bool {
+    crate::share::clear(&share_id)
 }
 
-/// Get Iroh status
+/// Backend plugins registered in this build
 #[tauri::command]
-pub async fn get_iroh_status(state: State<'_, AppState>) -> Result<IrohStatus, String> {
-    let node = state.iroh_node.read().await;
-    Ok(node.status())
+pub fn get_plugins(state: State<AppState>) -> Vec<PluginInfo> {
+    state.plugins.list()
 }
 
-/// Connect to a contact's Iroh endpoint
+/// Incoming messages held back by a content filter
 #[tauri::command]
-pub async fn connect_to_contact(
-    contact_pubkey: String,
-    their_node_id: String,
+pub async fn get_quarantined_messages(
     state: State<'_, AppState>,
-) -> Result<(), String> {
-    let mut node = state.iroh_node.write().await;
-    node.connect_to_contact(&their_node_id, &contact_pubkey)
-        .await
-        .map_err(|e| e.to_string())
+) -> Result<Vec<QuarantinedMessage>, String> {
+    let chat_manager_guard = state.chat_manager.read().await;
+
+    Ok(chat_manager_guard
+        .as_ref()
+        .map(|m| m.quarantined().to_vec())
+        .unwrap_or_default())
 }
 
-/// Send a message to a contact
+/// Move a quarantined message into its conversation
 #[tauri::command]
-pub async fn send_message(
-    contact_pubkey: String,
-    content: String,
+pub async fn release_quarantined_message(
+    message_id: String,
     state: State<'_, AppState>,
 ) -> Result<ChatMessage, String> {
-    // Get the connection
-    let node = state.iroh_node.read().await;
-    let connection = node
-        .get_connection(&contact_pubkey)
-        .ok_or("Not connected to contact")?
-        .clone();
-
-    // Send via chat manager
     let mut chat_manager_guard = state.chat_manager.write().await;
     let chat_manager = chat_manager_guard
         .as_mut()
         .ok_or("Chat manager not initialized")?;
 
     chat_manager
-        .send_message(&connection, &contact_pubkey, &content)
-        .await
-        .map_err(|e| e.to_string())
+        .release_quarantined(&message_id)
+        .ok_or_else(|| "Message not found".to_string())
 }
 
-/// Get messages for a contact
 #[tauri::command]
-pub async fn get_messages(
-    contact_pubkey: String,
+pub async fn discard_quarantined_message(
+    message_id: String,
     state: State<'_, AppState>,
-) -> Result<Vec<ChatMessage>, String> {
-    let chat_manager_guard = state.chat_manager.read().await;
+) -> Result<(), String> {
+    let mut chat_manager_guard = state.chat_manager.write().await;
+    let chat_manager = chat_manager_guard
+        .as_mut()
+        .ok_or("Chat manager not initialized")?;
 
-    match chat_manager_guard.as_ref() {
-        Some(manager) => Ok(manager.get_messages(&contact_pubkey)),
-        None => Ok(vec![]),
+    if chat_manager.discard_quarantined(&message_id) {
+        Ok(())
+    } else {
+        Err("Message not found".to_string())
     }
 }
+
+/// Conversation list with resolved contact names, most recently active first
+#[tauri::command]
+pub async fn get_conversations(
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<Vec<ConversationSummary>, String> {
+    let contacts = resolve_all(load_contacts_from_store(&app));
+    let chat_manager_guard = state.chat_manager.read().await;
+
+    Ok(conversation_summaries(chat_manager_guard.as_ref(), contacts))
+}
@@ -0,0 +1,272 @@
+//! Conference mode
+//!
+//! Tapping forty people one by one at a meetup is impractical. In conference
+//! mode we host a local-network exchange (see `lan`) for a time window and
+//! run the handshake with everyone who joins, one after another. Unlike the
+//! beacon, nobody is saved on arrival: each verified peer waits here until
+//! the user approves or dismisses them in a batch, after the window closes
+//! or while it is still open.
+
+use crate::lan::LanHostInfo;
+use crate::queue::PendingExchange;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Window used when the user doesn't pick one
+pub const DEFAULT_CONFERENCE_WINDOW_SECS: u64 = 30 * 60;
+
+/// Longest a conference may advertise us for
+pub const MAX_CONFERENCE_WINDOW_SECS: u64 = 4 * 60 * 60;
+
+/// Most attendees held for approval; later ones are turned away
+pub const MAX_CONFERENCE_ATTENDEES: usize = 250;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ConferenceError {
+    #[error("The conference window has closed")]
+    Closed,
+    #[error("Too many attendees are waiting for approval")]
+    Full,
+}
+
+/// A verified peer waiting for approval
+#[derive(Clone, Debug)]
+struct Attendee {
+    pending: PendingExchange,
+    /// Unix time their handshake finished
+    joined_at: u64,
+}
+
+/// Attendee as shown to the frontend
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConferenceAttendee {
+    pub their_pubkey: String,
+    pub display_name: Option<String>,
+    pub nip05: Option<String>,
+    pub master_pubkey: Option<String>,
+    pub joined_at: u64,
+}
+
+/// Conference state returned to the frontend
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConferenceStatus {
+    /// Name guests pick on their screen
+    pub host: LanHostInfo,
+    /// Still accepting guests
+    pub open: bool,
+    /// Seconds until the window closes
+    pub remaining_secs: u64,
+    /// Waiting for approval, in arrival order
+    pub attendees: Vec<ConferenceAttendee>,
+}
+
+/// A time-boxed run of local-network exchanges and the peers it collected
+#[derive(Debug)]
+pub struct Conference {
+    host: LanHostInfo,
+    started: Instant,
+    window: Duration,
+    closed: bool,
+    attendees: Vec<Attendee>,
+}
+
+impl Conference {
+    /// Open a window of `window_secs` (clamped to the maximum)
+    pub fn new(host: LanHostInfo, window_secs: u64) -> Self {
+        Self {
+            host,
+            started: Instant::now(),
+            window: Duration::from_secs(window_secs.clamp(1, MAX_CONFERENCE_WINDOW_SECS)),
+            closed: false,
+            attendees: Vec::new(),
+        }
+    }
+
+    /// Open a fresh window under a new host name. Attendees an earlier
+    /// window left waiting stay in line.
+    pub fn reopen(&mut self, host: LanHostInfo, window_secs: u64) {
+        let attendees = std::mem::take(&mut self.attendees);
+        *self = Self {
+            attendees,
+            ..Self::new(host, window_secs)
+        };
+    }
+
+    pub fn is_open(&self) -> bool {
+        !self.closed && self.started.elapsed() < self.window
+    }
+
+    /// Time left in the window
+    pub fn remaining(&self) -> Duration {
+        if self.closed {
+            return Duration::ZERO;
+        }
+        self.window.saturating_sub(self.started.elapsed())
+    }
+
+    /// Stop accepting guests; those already here keep waiting for approval
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+
+    /// Queue a peer whose handshake finished. Someone joining twice keeps
+    /// their place with their latest details.
+    pub fn admit(&mut self, pending: PendingExchange, now: u64) -> Result<(), ConferenceError> {
+        if !self.is_open() {
+            return Err(ConferenceError::Closed);
+        }
+        let attendee = Attendee {
+            pending,
+            joined_at: now,
+        };
+        match self
+            .attendees
+            .iter_mut()
+            .find(|a| a.pending.their_pubkey == attendee.pending.their_pubkey)
+        {
+            Some(existing) => *existing = attendee,
+            None if self.is_full() => return Err(ConferenceError::Full),
+            None => self.attendees.push(attendee),
+        }
+        Ok(())
+    }
+
+    /// No room for anyone new
+    pub fn is_full(&self) -> bool {
+        self.attendees.len() >= MAX_CONFERENCE_ATTENDEES
+    }
+
+    /// The attendees with these pubkeys, in arrival order, left in line
+    pub fn waiting(&self, pubkeys: &[String]) -> Vec<PendingExchange> {
+        self.attendees
+            .iter()
+            .filter(|a| pubkeys.contains(&a.pending.their_pubkey))
+            .map(|a| a.pending.clone())
+            .collect()
+    }
+
+    /// Remove and return the attendees with these pubkeys, in arrival order
+    pub fn take(&mut self, pubkeys: &[String]) -> Vec<PendingExchange> {
+        let (taken, kept): (Vec<Attendee>, Vec<Attendee>) = std::mem::take(&mut self.attendees)
+            .into_iter()
+            .partition(|a| pubkeys.contains(&a.pending.their_pubkey));
+        self.attendees = kept;
+        taken.into_iter().map(|a| a.pending).collect()
+    }
+
+    /// Closed with nobody left to approve
+    pub fn is_finished(&self) -> bool {
+        !self.is_open() && self.attendees.is_empty()
+    }
+
+    pub fn status(&self) -> ConferenceStatus {
+        ConferenceStatus {
+            host: self.host.clone(),
+            open: self.is_open(),
+            remaining_secs: self.remaining().as_secs(),
+            attendees: self.attendees.iter().map(Attendee::info).collect(),
+        }
+    }
+}
+
+impl Attendee {
+    fn info(&self) -> ConferenceAttendee {
+        let profile = self.pending.profile.clone().unwrap_or_default();
+        ConferenceAttendee {
+            their_pubkey: self.pending.their_pubkey.clone(),
+            display_name: profile.display_name,
+            nip05: profile.nip05,
+            master_pubkey: self.pending.master_pubkey.clone(),
+            joined_at: self.joined_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::ExchangeQueue;
+    use crate::stats::Transport;
+
+    fn host() -> LanHostInfo {
+        LanHostInfo {
+            name: "SneakerNet-1A2B".to_string(),
+            port: 4000,
+        }
+    }
+
+    fn pending(pubkey: &str) -> PendingExchange {
+        let mut queue = ExchangeQueue::default();
+        queue.track(pubkey, Transport::Lan);
+        queue.complete(pubkey).unwrap()
+    }
+
+    #[test]
+    fn test_attendees_wait_for_batch_approval() {
+        let mut conference = Conference::new(host(), DEFAULT_CONFERENCE_WINDOW_SECS);
+        conference.admit(pending("alice"), 1).unwrap();
+        conference.admit(pending("bob"), 2).unwrap();
+        conference.admit(pending("carol"), 3).unwrap();
+        // Joining again keeps their place
+        conference.admit(pending("alice"), 4).unwrap();
+
+        let status = conference.status();
+        assert!(status.open);
+        let order: Vec<_> = status
+            .attendees
+            .iter()
+            .map(|a| a.their_pubkey.as_str())
+            .collect();
+        assert_eq!(order, ["alice", "bob", "carol"]);
+        assert_eq!(status.attendees[0].joined_at, 4);
+
+        let approved = conference.take(&["carol".to_string(), "alice".to_string()]);
+        let approved: Vec<_> = approved.iter().map(|p| p.their_pubkey.as_str()).collect();
+        assert_eq!(approved, ["alice", "carol"]);
+        assert_eq!(conference.status().attendees.len(), 1);
+        assert!(conference.take(&["alice".to_string()]).is_empty());
+    }
+
+    #[test]
+    fn test_closed_window_keeps_attendees() {
+        let mut conference = Conference::new(host(), MAX_CONFERENCE_WINDOW_SECS * 2);
+        assert!(conference.remaining() <= Duration::from_secs(MAX_CONFERENCE_WINDOW_SECS));
+        conference.admit(pending("alice"), 1).unwrap();
+
+        conference.close();
+        assert!(!conference.is_open());
+        assert_eq!(conference.remaining(), Duration::ZERO);
+        assert_eq!(
+            conference.admit(pending("bob"), 2),
+            Err(ConferenceError::Closed)
+        );
+        assert!(!conference.is_finished());
+
+        // A new window keeps them in line
+        conference.reopen(host(), DEFAULT_CONFERENCE_WINDOW_SECS);
+        assert!(conference.is_open());
+        assert_eq!(conference.waiting(&["alice".to_string()]).len(), 1);
+
+        conference.close();
+        conference.take(&["alice".to_string()]);
+        assert!(conference.is_finished());
+    }
+
+    #[test]
+    fn test_full_conference_turns_guests_away() {
+        let mut conference = Conference::new(host(), DEFAULT_CONFERENCE_WINDOW_SECS);
+        for i in 0..MAX_CONFERENCE_ATTENDEES {
+            conference.admit(pending(&i.to_string()), 1).unwrap();
+        }
+        assert!(conference.is_full());
+        assert_eq!(
+            conference.admit(pending("late"), 2),
+            Err(ConferenceError::Full)
+        );
+        // Someone already waiting can still rejoin
+        conference.admit(pending("0"), 2).unwrap();
+    }
+}
@@ -0,0 +1,303 @@
+//! SQLite-backed contact store
+//!
+//! Contacts used to live as one JSON array in the key-value store, which had
+//! to be parsed whole for every lookup. Here each contact is a row holding
+//! its JSON, with indexed columns for the fields we query on (pubkey, tags,
+//! exchange time), so lookups and paginated listings don't scale with the
+//! address book. The full list is still cached in `AppState` for the
+//! commands that need all of it.
+
+use crate::exchange::Contact;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use thiserror::Error;
+
+/// Schema version stored in `PRAGMA user_version`
+const SCHEMA_VERSION: i32 = 1;
+
+/// Largest page `query` returns
+pub const MAX_PAGE_SIZE: u32 = 500;
+
+#[derive(Error, Debug)]
+pub enum ContactStoreError {
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("Contact database is from a newer version ({0})")]
+    UnsupportedVersion(i32),
+}
+
+/// Filters and paging for a contact listing
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ContactQuery {
+    pub pubkey: Option<String>,
+    pub tag: Option<String>,
+    pub offset: u32,
+    /// Page size (None or 0 = `MAX_PAGE_SIZE`)
+    pub limit: Option<u32>,
+}
+
+/// One page of contacts, newest exchange first
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactPage<T> {
+    pub contacts: Vec<T>,
+    /// Number of contacts matching the query across all pages
+    pub total: u32,
+}
+
+pub struct ContactStore {
+    conn: Connection,
+}
+
+impl ContactStore {
+    pub fn open(path: &Path) -> Result<Self, ContactStoreError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, ContactStoreError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, ContactStoreError> {
+        conn.pragma_update(None, "foreign_keys", true)?;
+
+        let version: i32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(ContactStoreError::UnsupportedVersion(version));
+        }
+        if version < 1 {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS contacts (
+                    id TEXT PRIMARY KEY,
+                    nostr_pubkey TEXT NOT NULL,
+                    exchanged_at INTEGER NOT NULL,
+                    data TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS contacts_pubkey ON contacts(nostr_pubkey);
+                CREATE INDEX IF NOT EXISTS contacts_exchanged_at ON contacts(exchanged_at);
+                CREATE TABLE IF NOT EXISTS contact_tags (
+                    contact_id TEXT NOT NULL REFERENCES contacts(id) ON DELETE CASCADE,
+                    tag TEXT NOT NULL,
+                    PRIMARY KEY (contact_id, tag)
+                );
+                CREATE INDEX IF NOT EXISTS contact_tags_tag ON contact_tags(tag);",
+            )?;
+        }
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+
+        Ok(Self { conn })
+    }
+
+    pub fn is_empty(&self) -> Result<bool, ContactStoreError> {
+        let count: u32 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM contacts", [], |row| row.get(0))?;
+        Ok(count == 0)
+    }
+
+    fn insert(conn: &Connection, contact: &Contact) -> Result<(), ContactStoreError> {
+        conn.execute(
+            "INSERT INTO contacts (id, nostr_pubkey, exchanged_at, data) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(id) DO UPDATE SET
+                nostr_pubkey = excluded.nostr_pubkey,
+                exchanged_at = excluded.exchanged_at,
+                data = excluded.data",
+            params![
+                contact.id,
+                contact.nostr_pubkey,
+                contact.exchanged_at as i64,
+                serde_json::to_string(contact)?
+            ],
+        )?;
+
+        conn.execute(
+            "DELETE FROM contact_tags WHERE contact_id = ?1",
+            params![contact.id],
+        )?;
+        for tag in &contact.tags {
+            conn.execute(
+                "INSERT OR IGNORE INTO contact_tags (contact_id, tag) VALUES (?1, ?2)",
+                params![contact.id, tag.to_lowercase()],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Insert or update a single contact
+    pub fn upsert(&mut self, contact: &Contact) -> Result<(), ContactStoreError> {
+        let tx = self.conn.transaction()?;
+        Self::insert(&tx, contact)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Make the store hold exactly `contacts`
+    pub fn replace_all(&mut self, contacts: &[Contact]) -> Result<(), ContactStoreError> {
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM contacts", [])?;
+        for contact in contacts {
+            Self::insert(&tx, contact)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Delete a contact, returning whether it existed
+    pub fn delete(&self, id: &str) -> Result<bool, ContactStoreError> {
+        Ok(self
+            .conn
+            .execute("DELETE FROM contacts WHERE id = ?1", params![id])?
+            > 0)
+    }
+
+    /// All contacts, in the order they were exchanged
+    pub fn all(&self) -> Result<Vec<Contact>, ContactStoreError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT data FROM contacts ORDER BY exchanged_at, rowid")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+
+        let mut contacts = Vec::new();
+        for data in rows {
+            contacts.push(serde_json::from_str(&data?)?);
+        }
+        Ok(contacts)
+    }
+
+    pub fn by_pubkey(&self, nostr_pubkey: &str) -> Result<Option<Contact>, ContactStoreError> {
+        let data: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT data FROM contacts WHERE nostr_pubkey = ?1 ORDER BY exchanged_at LIMIT 1",
+                params![nostr_pubkey],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(data.map(|d| serde_json::from_str(&d)).transpose()?)
+    }
+
+    /// One page of contacts matching `query`, most recently exchanged first
+    pub fn query(&self, query: &ContactQuery) -> Result<ContactPage<Contact>, ContactStoreError> {
+        let limit = match query.limit {
+            Some(limit) if limit > 0 => limit.min(MAX_PAGE_SIZE),
+            _ => MAX_PAGE_SIZE,
+        };
+        let tag = query.tag.as_ref().map(|t| t.to_lowercase());
+
+        let filter = "FROM contacts c
+             WHERE (?1 IS NULL OR c.nostr_pubkey = ?1)
+               AND (?2 IS NULL OR EXISTS (
+                    SELECT 1 FROM contact_tags t WHERE t.contact_id = c.id AND t.tag = ?2))";
+
+        let total: u32 = self.conn.query_row(
+            &format!("SELECT COUNT(*) {}", filter),
+            params![query.pubkey, tag],
+            |row| row.get(0),
+        )?;
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT c.data {} ORDER BY c.exchanged_at DESC, c.rowid DESC LIMIT ?3 OFFSET ?4",
+            filter
+        ))?;
+        let rows = stmt.query_map(params![query.pubkey, tag, limit, query.offset], |row| {
+            row.get::<_, String>(0)
+        })?;
+
+        let mut contacts = Vec::new();
+        for data in rows {
+            contacts.push(serde_json::from_str(&data?)?);
+        }
+
+        Ok(ContactPage { contacts, total })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(pubkey: &str, exchanged_at: u64, tags: &[&str]) -> Contact {
+        let mut contact = Contact::new(pubkey, "endpoint");
+        contact.exchanged_at = exchanged_at;
+        contact.tags = tags.iter().map(|t| t.to_string()).collect();
+        contact
+    }
+
+    #[test]
+    fn test_replace_and_lookup() {
+        let mut store = ContactStore::open_in_memory().unwrap();
+        assert!(store.is_empty().unwrap());
+
+        let alice = contact("alice", 1, &["Work"]);
+        let bob = contact("bob", 2, &[]);
+        store.replace_all(&[alice.clone(), bob.clone()]).unwrap();
+
+        let all = store.all().unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].id, alice.id);
+        assert_eq!(store.by_pubkey("bob").unwrap().unwrap().id, bob.id);
+
+        assert!(store.delete(&alice.id).unwrap());
+        assert!(!store.delete(&alice.id).unwrap());
+        assert!(store.by_pubkey("alice").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_query_by_tag_with_pages() {
+        let mut store = ContactStore::open_in_memory().unwrap();
+        let contacts: Vec<Contact> = (0..5)
+            .map(|i| {
+                contact(
+                    &format!("pk{}", i),
+                    i,
+                    if i % 2 == 0 { &["conf"] } else { &[] },
+                )
+            })
+            .collect();
+        store.replace_all(&contacts).unwrap();
+
+        let page = store
+            .query(&ContactQuery {
+                tag: Some("CONF".to_string()),
+                limit: Some(2),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(page.total, 3);
+        // Newest first
+        let pubkeys: Vec<_> = page
+            .contacts
+            .iter()
+            .map(|c| c.nostr_pubkey.as_str())
+            .collect();
+        assert_eq!(pubkeys, vec!["pk4", "pk2"]);
+
+        let next = store
+            .query(&ContactQuery {
+                tag: Some("conf".to_string()),
+                offset: 2,
+                limit: Some(2),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(next.contacts.len(), 1);
+        assert_eq!(next.contacts[0].nostr_pubkey, "pk0");
+
+        // Retagging replaces the old tags
+        let mut retagged = contacts[4].clone();
+        retagged.tags = vec!["friends".to_string()];
+        store.upsert(&retagged).unwrap();
+        let conf = store
+            .query(&ContactQuery {
+                tag: Some("conf".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(conf.total, 2);
+    }
+}
@@ -0,0 +1,269 @@
+//! Per-message delivery tracking
+//!
+//! Every outgoing message gets a `DeliveryInfo` when it is queued, which is
+//! updated as it is written to the connection and, once the peer
+//! acknowledges it, delivered and read. The log lives next to the messages
+//! in the chat manager and is what `get_message_info` returns; the message
+//! itself carries the coarser `MessageStatus` the UI shows as ticks.
+//!
+//! A message to a group gets a `GroupDelivery` instead, with one timeline
+//! per member, so the sender can see how many members it reached and how
+//! many read it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Transport label for messages sent over an Iroh QUIC connection
+pub const TRANSPORT_IROH: &str = "iroh";
+
+/// Furthest point an outgoing message has reached
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Queued,
+    Sent,
+    Delivered,
+    Read,
+    Failed,
+}
+
+/// Status shown on an outgoing message
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageStatus {
+    Pending,
+    Sent,
+    Delivered,
+    Failed,
+}
+
+impl From<DeliveryStatus> for MessageStatus {
+    fn from(status: DeliveryStatus) -> Self {
+        match status {
+            DeliveryStatus::Queued => MessageStatus::Pending,
+            DeliveryStatus::Sent => MessageStatus::Sent,
+            DeliveryStatus::Delivered | DeliveryStatus::Read => MessageStatus::Delivered,
+            DeliveryStatus::Failed => MessageStatus::Failed,
+        }
+    }
+}
+
+/// Delivery timeline of one outgoing message (Unix timestamps)
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryInfo {
+    pub message_id: String,
+    pub contact_pubkey: String,
+    pub status: DeliveryStatus,
+    pub queued_at: u64,
+    pub sent_at: Option<u64>,
+    pub delivered_at: Option<u64>,
+    pub read_at: Option<u64>,
+    /// Transport the message went out on, once sent
+    pub transport: Option<String>,
+    /// Attempts that failed before the one that succeeded
+    pub retries: u32,
+    /// Error from the last failed attempt
+    pub error: Option<String>,
+}
+
+impl DeliveryInfo {
+    pub fn new(message_id: &str, contact_pubkey: &str, now: u64) -> Self {
+        Self {
+            message_id: message_id.to_string(),
+            contact_pubkey: contact_pubkey.to_string(),
+            status: DeliveryStatus::Queued,
+            queued_at: now,
+            sent_at: None,
+            delivered_at: None,
+            read_at: None,
+            transport: None,
+            retries: 0,
+            error: None,
+        }
+    }
+
+    pub fn mark_sent(&mut self, transport: &str, now: u64) {
+        self.status = DeliveryStatus::Sent;
+        self.sent_at = Some(now);
+        self.transport = Some(transport.to_string());
+    }
+
+    pub fn mark_failed(&mut self, error: &str) {
+        self.status = DeliveryStatus::Failed;
+        self.error = Some(error.to_string());
+    }
+
+    /// The peer acknowledged receipt. Never moves a read message back.
+    pub fn mark_delivered(&mut self, now: u64) {
+        self.delivered_at.get_or_insert(now);
+        if self.status != DeliveryStatus::Read {
+            self.status = DeliveryStatus::Delivered;
+        }
+    }
+
+    pub fn mark_read(&mut self, now: u64) {
+        self.delivered_at.get_or_insert(now);
+        self.read_at.get_or_insert(now);
+        self.status = DeliveryStatus::Read;
+    }
+}
+
+/// What a member's receipt for a group message says
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReceiptKind {
+    Delivered,
+    Read,
+}
+
+/// Delivery of one outgoing group message to each member
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupDelivery {
+    pub message_id: String,
+    pub group_id: String,
+    /// One timeline per member, keyed by their pubkey in `contact_pubkey`
+    pub members: Vec<DeliveryInfo>,
+    /// How many members acknowledged it
+    pub delivered: usize,
+    /// How many members read it
+    pub read: usize,
+}
+
+impl GroupDelivery {
+    pub fn new(message_id: &str, group_id: &str, members: &[String], now: u64) -> Self {
+        Self {
+            message_id: message_id.to_string(),
+            group_id: group_id.to_string(),
+            members: members
+                .iter()
+                .map(|member| DeliveryInfo::new(message_id, member, now))
+                .collect(),
+            delivered: 0,
+            read: 0,
+        }
+    }
+
+    /// Timeline of one member, if the message went to them
+    pub fn member_mut(&mut self, member_pubkey: &str) -> Option<&mut DeliveryInfo> {
+        self.members
+            .iter_mut()
+            .find(|info| info.contact_pubkey == member_pubkey)
+    }
+
+    /// Count a member's receipt. Receipts from anyone the message didn't go
+    /// to are ignored; returns whether it counted.
+    pub fn apply(&mut self, member_pubkey: &str, kind: ReceiptKind, now: u64) -> bool {
+        let Some(info) = self.member_mut(member_pubkey) else {
+            return false;
+        };
+        match kind {
+            ReceiptKind::Delivered => info.mark_delivered(now),
+            ReceiptKind::Read => info.mark_read(now),
+        }
+        self.delivered = self
+            .members
+            .iter()
+            .filter(|info| info.delivered_at.is_some())
+            .count();
+        self.read = self
+            .members
+            .iter()
+            .filter(|info| info.read_at.is_some())
+            .count();
+        true
+    }
+
+    /// Whether every member acknowledged it
+    pub fn fully_delivered(&self) -> bool {
+        self.delivered == self.members.len()
+    }
+}
+
+/// Delivery records keyed by message ID
+#[derive(Debug, Default)]
+pub struct DeliveryLog {
+    entries: HashMap<String, DeliveryInfo>,
+}
+
+impl DeliveryLog {
+    pub fn insert(&mut self, info: DeliveryInfo) {
+        self.entries.insert(info.message_id.clone(), info);
+    }
+
+    pub fn get(&self, message_id: &str) -> Option<&DeliveryInfo> {
+        self.entries.get(message_id)
+    }
+
+    pub fn get_mut(&mut self, message_id: &str) -> Option<&mut DeliveryInfo> {
+        self.entries.get_mut(message_id)
+    }
+
+    pub fn remove(&mut self, message_id: &str) -> Option<DeliveryInfo> {
+        self.entries.remove(message_id)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeline() {
+        let mut info = DeliveryInfo::new("m1", "pk", 100);
+        assert_eq!(info.status, DeliveryStatus::Queued);
+
+        info.mark_sent(TRANSPORT_IROH, 101);
+        info.mark_read(105);
+        // A late delivery ack doesn't move a read message back
+        info.mark_delivered(106);
+
+        assert_eq!(info.status, DeliveryStatus::Read);
+        assert_eq!(info.sent_at, Some(101));
+        assert_eq!(info.delivered_at, Some(105));
+        assert_eq!(info.read_at, Some(105));
+        assert_eq!(info.transport.as_deref(), Some(TRANSPORT_IROH));
+        assert_eq!(MessageStatus::from(info.status), MessageStatus::Delivered);
+    }
+
+    #[test]
+    fn test_log() {
+        let mut log = DeliveryLog::default();
+        log.insert(DeliveryInfo::new("m1", "alice", 1));
+
+        log.get_mut("m1").unwrap().mark_failed("closed");
+        let info = log.get("m1").unwrap();
+        assert_eq!(info.status, DeliveryStatus::Failed);
+        assert_eq!(info.error.as_deref(), Some("closed"));
+
+        log.clear();
+        assert!(log.get("m1").is_none());
+    }
+
+    #[test]
+    fn test_group_receipts() {
+        let members = ["bob", "carol", "dave"].map(String::from);
+        let mut group = GroupDelivery::new("m1", "g1", &members, 1);
+
+        assert!(group.apply("bob", ReceiptKind::Delivered, 2));
+        assert!(group.apply("carol", ReceiptKind::Read, 3));
+        // Repeated receipts count once, and a read member stays read
+        assert!(group.apply("carol", ReceiptKind::Delivered, 4));
+        // Someone the message didn't go to can't add to the count
+        assert!(!group.apply("mallory", ReceiptKind::Read, 5));
+
+        assert_eq!((group.delivered, group.read), (2, 1));
+        assert!(!group.fully_delivered());
+        let carol = group.member_mut("carol").unwrap();
+        assert_eq!(carol.status, DeliveryStatus::Read);
+        assert_eq!(carol.delivered_at, Some(3));
+
+        assert!(group.apply("dave", ReceiptKind::Delivered, 6));
+        assert!(group.fully_delivered());
+    }
+}
@@ -0,0 +1,190 @@
+//! Per-device subkeys under one master identity
+//!
+//! Someone running SneakerNet on a phone and a tablet keeps one npub (the
+//! master identity) but gives each device a subkey of its own, derived from
+//! the master secret and a device index with HKDF. The device signs exchange
+//! messages with its subkey, and its Iroh identities are derived from the
+//! subkey too, so two devices never share an endpoint.
+//!
+//! A `DeviceCertificate` signed by the master key binds the subkey to the
+//! master identity. It travels with exchange messages, so a peer can see
+//! which identity the device belongs to. The subkey itself is never stored:
+//! it is derived again from the master key whenever it is needed.
+
+use crate::keys::{restore_keys, sign_content, verify_content, StoredKeys};
+use hkdf::Hkdf;
+use nostr::{Keys, SecretKey};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// HKDF salt for device subkeys
+const DEVICE_KEY_SALT: &[u8] = b"sneakernet-device-v1";
+
+/// `type` field of a device certificate
+pub const DEVICE_CERT_TYPE: &str = "sneakernet-device-cert";
+
+/// Longest device name accepted, in characters
+pub const MAX_DEVICE_NAME_LEN: usize = 64;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum DeviceError {
+    #[error("Key derivation failed: {0}")]
+    DerivationFailed(String),
+    #[error("Invalid device certificate: {0}")]
+    InvalidCertificate(String),
+    #[error("Device certificate signature is invalid")]
+    InvalidSignature,
+    #[error("Signing error: {0}")]
+    SigningError(String),
+}
+
+/// The subkey of device `index` under `master`
+pub fn derive_device_keys(master: &StoredKeys, index: u32) -> Result<StoredKeys, DeviceError> {
+    let master_secret = master
+        .secret_bytes()
+        .map_err(|e| DeviceError::DerivationFailed(e.to_string()))?;
+    let hk = Hkdf::<Sha256>::new(Some(DEVICE_KEY_SALT), &master_secret);
+
+    // A few outputs aren't valid secp256k1 keys; try the next counter then
+    for counter in 0u8..=u8::MAX {
+        let mut info = index.to_be_bytes().to_vec();
+        info.push(counter);
+
+        let mut seed = Zeroizing::new([0u8; 32]);
+        hk.expand(&info, seed.as_mut_slice())
+            .map_err(|e| DeviceError::DerivationFailed(e.to_string()))?;
+
+        if let Ok(secret_key) = SecretKey::from_slice(seed.as_slice()) {
+            let keys = Keys::new(secret_key);
+            return Ok(StoredKeys {
+                secret_key_hex: keys.secret_key().to_secret_hex().into(),
+                public_key_hex: keys.public_key().to_hex(),
+            });
+        }
+    }
+
+    Err(DeviceError::DerivationFailed(
+        "no valid key for this index".to_string(),
+    ))
+}
+
+/// A device subkey vouched for by the master key
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceCertificate {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    /// The identity the device belongs to (hex)
+    pub master_pubkey: String,
+    /// The device's subkey (hex)
+    pub device_pubkey: String,
+    pub device_index: u32,
+    pub device_name: String,
+    pub issued_at: u64,
+    /// Master key's signature (hex)
+    pub signature: String,
+}
+
+impl DeviceCertificate {
+    /// Derive device `index`'s subkey and certify it with `master`
+    pub fn issue(
+        master: &StoredKeys,
+        index: u32,
+        name: &str,
+        now: u64,
+    ) -> Result<(Self, StoredKeys), DeviceError> {
+        let device = derive_device_keys(master, index)?;
+        let master_keys =
+            restore_keys(master).map_err(|e| DeviceError::SigningError(e.to_string()))?;
+
+        let mut cert = Self {
+            msg_type: DEVICE_CERT_TYPE.to_string(),
+            master_pubkey: master.public_key_hex.clone(),
+            device_pubkey: device.public_key_hex.clone(),
+            device_index: index,
+            device_name: name.trim().chars().take(MAX_DEVICE_NAME_LEN).collect(),
+            issued_at: now,
+            signature: String::new(),
+        };
+        cert.signature = sign_content(&master_keys, &cert.signing_content())
+            .map_err(|e| DeviceError::SigningError(e.to_string()))?;
+
+        Ok((cert, device))
+    }
+
+    fn signing_content(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{}:{}",
+            DEVICE_CERT_TYPE,
+            self.master_pubkey,
+            self.device_pubkey,
+            self.device_index,
+            self.device_name,
+            self.issued_at
+        )
+    }
+
+    /// Check the master key signed this certificate
+    pub fn verify(&self) -> Result<(), DeviceError> {
+        if self.msg_type != DEVICE_CERT_TYPE {
+            return Err(DeviceError::InvalidCertificate(format!(
+                "unexpected type {}",
+                self.msg_type
+            )));
+        }
+        if self.master_pubkey == self.device_pubkey {
+            return Err(DeviceError::InvalidCertificate(
+                "device key is the master key".to_string(),
+            ));
+        }
+
+        verify_content(
+            &self.master_pubkey,
+            &self.signing_content(),
+            &self.signature,
+        )
+        .map_err(|_| DeviceError::InvalidSignature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::generate_keypair;
+
+    #[test]
+    fn test_device_keys_are_distinct_and_stable() {
+        let (_, master) = generate_keypair().unwrap();
+
+        let phone = derive_device_keys(&master, 0).unwrap();
+        let tablet = derive_device_keys(&master, 1).unwrap();
+        assert_ne!(phone.public_key_hex, tablet.public_key_hex);
+        assert_ne!(phone.public_key_hex, master.public_key_hex);
+        assert_eq!(
+            derive_device_keys(&master, 1).unwrap().public_key_hex,
+            tablet.public_key_hex
+        );
+    }
+
+    #[test]
+    fn test_device_certificate() {
+        let (_, master) = generate_keypair().unwrap();
+        let (cert, device) =
+            DeviceCertificate::issue(&master, 1, " Tablet ", 1_700_000_000).unwrap();
+        assert_eq!(cert.device_pubkey, device.public_key_hex);
+        assert_eq!(cert.device_name, "Tablet");
+        cert.verify().unwrap();
+
+        let json = serde_json::to_string(&cert).unwrap();
+        let parsed: DeviceCertificate = serde_json::from_str(&json).unwrap();
+        parsed.verify().unwrap();
+
+        // Claiming a key the master didn't certify
+        let (_, other) = generate_keypair().unwrap();
+        let mut forged = cert.clone();
+        forged.device_pubkey = other.public_key_hex;
+        assert_eq!(forged.verify(), Err(DeviceError::InvalidSignature));
+    }
+}
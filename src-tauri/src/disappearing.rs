@@ -0,0 +1,116 @@
+//! Disappearing messages
+//!
+//! Each contact can be given a timer for new messages. Both sides advertise
+//! their timer in every chat frame, next to their chat capabilities, and the
+//! shorter of the two applies in both directions: either side can make a
+//! conversation disappear sooner, but neither can keep it longer than the
+//! other wants. The sender stamps each message with its expiry and the
+//! receiver keeps the earlier of that and its own, so a peer with a wrong
+//! clock can't stretch it. A sweeper on each end purges expired messages
+//! from memory and the message database every `SWEEP_INTERVAL_SECS`.
+
+use crate::state::AppState;
+use serde::Serialize;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use thiserror::Error;
+
+/// How often expired messages are purged
+pub const SWEEP_INTERVAL_SECS: u64 = 10;
+
+/// Shortest timer a conversation can have
+pub const MIN_DISAPPEAR_SECS: u64 = 30;
+
+/// Longest timer a conversation can have (four weeks)
+pub const MAX_DISAPPEAR_SECS: u64 = 28 * 24 * 60 * 60;
+
+/// Event emitted with the messages each sweep purged
+pub const EXPIRED_EVENT: &str = "chat://expired";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum DisappearError {
+    #[error("Timer must be between {MIN_DISAPPEAR_SECS}s and {MAX_DISAPPEAR_SECS}s")]
+    OutOfRange,
+}
+
+/// A message purged by the sweeper
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpiredMessage {
+    pub contact_pubkey: String,
+    pub message_id: String,
+}
+
+pub fn validate_timer(secs: u64) -> Result<(), DisappearError> {
+    if (MIN_DISAPPEAR_SECS..=MAX_DISAPPEAR_SECS).contains(&secs) {
+        Ok(())
+    } else {
+        Err(DisappearError::OutOfRange)
+    }
+}
+
+/// The sooner of two timers or expiries, ignoring unset ones
+pub fn sooner(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Expiry of a message sent or received at `at` under `timer`
+pub fn expiry(at: u64, timer: Option<u64>) -> Option<u64> {
+    timer.map(|secs| at.saturating_add(secs))
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Purge expired messages every `SWEEP_INTERVAL_SECS` for the life of the app
+pub fn spawn_sweeper(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(SWEEP_INTERVAL_SECS));
+        loop {
+            tick.tick().await;
+            let state = app.state::<AppState>();
+            let expired = match state.chat_manager.write().await.as_mut() {
+                Some(manager) => manager.purge_expired(now_secs()),
+                None => continue,
+            };
+            if !expired.is_empty() {
+                let _ = app.emit(EXPIRED_EVENT, expired);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shorter_timer_wins() {
+        assert_eq!(sooner(Some(3600), Some(60)), Some(60));
+        assert_eq!(sooner(None, Some(60)), Some(60));
+        assert_eq!(sooner(Some(3600), None), Some(3600));
+        assert_eq!(sooner(None, None), None);
+
+        assert_eq!(expiry(1000, Some(60)), Some(1060));
+        assert_eq!(expiry(1000, None), None);
+        assert_eq!(expiry(u64::MAX, Some(60)), Some(u64::MAX));
+    }
+
+    #[test]
+    fn test_timer_range() {
+        assert!(validate_timer(MIN_DISAPPEAR_SECS).is_ok());
+        assert!(validate_timer(MAX_DISAPPEAR_SECS).is_ok());
+        assert_eq!(validate_timer(0), Err(DisappearError::OutOfRange));
+        assert_eq!(
+            validate_timer(MAX_DISAPPEAR_SECS + 1),
+            Err(DisappearError::OutOfRange)
+        );
+    }
+}
@@ -1,18 +1,165 @@
 //! NFC exchange protocol - message format, signing, and verification
 
+use crate::device::DeviceCertificate;
+use crate::identifier::Identifier;
+use crate::keys::{sign_content, verify_content_as, KeyError, SigScheme, Signer};
+use crate::queue::PENDING_EXCHANGE_TTL_SECS;
+use crate::settings::MAX_DISPLAY_NAME_CHARS;
+use crate::stats::Transport;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ciborium::Value;
+use nostr::nips::nip44;
 use nostr::prelude::*;
-use nostr::secp256k1::{self, Message as Secp256k1Message, Secp256k1, XOnlyPublicKey};
-use serde::{Deserialize, Serialize};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
 use sha2::{Digest, Sha256};
 use thiserror::Error;
 use uuid::Uuid;
 
-/// Protocol version
-pub const PROTOCOL_VERSION: u32 = 1;
+/// Protocol version. Version 2 adds the compact CBOR encoding; the signed
+/// content is the same in both.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest protocol version still accepted (JSON only)
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Prefix of the text form of a CBOR message, as put in QR codes and NFC records
+pub const COMPACT_PREFIX: &str = "sn2:";
+
+/// Prefix of a response encrypted to the initiator: a throwaway pubkey,
+/// then the NIP-44 payload of the compact message
+pub const SEALED_PREFIX: &str = "sn2s:";
+
+/// Prefix of a message shared as a link; the compact form's data follows
+pub const EXCHANGE_URI_PREFIX: &str = "sneakernet://exchange/";
+
+/// Capability flag: reads the compact CBOR encoding
+pub const CAP_COMPACT_ENCODING: u32 = 1 << 0;
+
+/// Capability flag: understands device certificates
+pub const CAP_DEVICE_CERT: u32 = 1 << 1;
+
+/// Capability flag: verifies signed Iroh node hints (`nodeId`, `relayUrl`)
+pub const CAP_NODE_HINTS: u32 = 1 << 2;
+
+/// Capability flag: honours the signed `staticTag` marker
+pub const CAP_STATIC_TAG: u32 = 1 << 3;
+
+/// Capability flag: verifies signed profile metadata (`profile`)
+pub const CAP_PROFILE: u32 = 1 << 4;
+
+/// Capability flag: can open a response sealed to its key (`sn2s:`). Not
+/// advertised when the key is held by a remote signer.
+pub const CAP_SEALED_RESPONSE: u32 = 1 << 5;
+
+/// Capability flag: reads compact messages whose CBOR is zlib-compressed
+pub const CAP_COMPRESSION: u32 = 1 << 6;
+
+/// Capability flag: binds a response to the initial message it answers
+/// with a signed session ID (`sessionId`)
+pub const CAP_SESSION_BINDING: u32 = 1 << 7;
+
+/// Domain separation for the session ID hash
+const SESSION_CONTEXT: &[u8] = b"sneakernet-session-v1";
+
+/// Largest CBOR a compressed message may inflate to
+const MAX_INFLATED_LEN: usize = 16 * 1024;
+
+/// First byte of a zlib stream (deflate, 32 KiB window). A CBOR message is
+/// an array, which never starts with it, so the two can't be confused.
+const ZLIB_HEADER: u8 = 0x78;
+
+/// Everything this version of the app understands
+pub const OUR_CAPABILITIES: u32 = CAP_COMPACT_ENCODING
+    | CAP_DEVICE_CERT
+    | CAP_NODE_HINTS
+    | CAP_STATIC_TAG
+    | CAP_PROFILE
+    | CAP_SEALED_RESPONSE
+    | CAP_COMPRESSION
+    | CAP_SESSION_BINDING;
+
+/// Longest NIP-05 identifier in an exchange message
+pub const MAX_NIP05_LEN: usize = 128;
+
+/// Protocol version and capabilities to use with a peer, settled from the
+/// first message we received from them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Negotiated {
+    pub version: u32,
+    pub capabilities: u32,
+}
+
+impl Negotiated {
+    /// For a peer we haven't heard from yet
+    pub const OURS: Self = Self {
+        version: PROTOCOL_VERSION,
+        capabilities: OUR_CAPABILITIES,
+    };
+
+    /// For a version 1 app, which only reads JSON and only accepts version 1
+    pub const V1: Self = Self {
+        version: 1,
+        capabilities: 0,
+    };
+
+    pub fn has(&self, capability: u32) -> bool {
+        self.capabilities & capability != 0
+    }
+}
 
 /// MIME type for NDEF records
 pub const NDEF_MIME_TYPE: &str = "application/x-sneakernet";
 
+/// Longest context note accepted at exchange time, in characters
+pub const MAX_CONTEXT_NOTE_LEN: usize = 280;
+
+/// How far an exchange message's timestamp may be from our clock
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimestampTolerance {
+    /// Oldest accepted message, in seconds
+    pub max_age_secs: u64,
+    /// How far ahead of our clock a message may be dated, in seconds
+    pub max_future_secs: u64,
+}
+
+impl TimestampTolerance {
+    /// Live exchanges (NFC tap, QR on screen), where the message was just made
+    pub const LIVE: Self = Self {
+        max_age_secs: 300,
+        max_future_secs: 120,
+    };
+
+    /// Messages written ahead of time, e.g. to a static NFC tag
+    pub const STATIC_TAG: Self = Self {
+        max_age_secs: 30 * 24 * 60 * 60,
+        max_future_secs: 120,
+    };
+
+    /// Check `timestamp` against `now`
+    pub fn check(&self, timestamp: u64, now: u64) -> Result<(), ExchangeError> {
+        if timestamp > now {
+            let ahead_secs = timestamp - now;
+            if ahead_secs > self.max_future_secs {
+                return Err(ExchangeError::MessageFromFuture { ahead_secs });
+            }
+        } else {
+            let age_secs = now - timestamp;
+            if age_secs > self.max_age_secs {
+                return Err(ExchangeError::MessageExpired { age_secs });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for TimestampTolerance {
+    fn default() -> Self {
+        Self::LIVE
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum ExchangeError {
     #[error("Invalid message format: {0}")]
@@ -25,12 +172,118 @@ pub enum ExchangeError {
     InvalidPubkey,
     #[error("Their pubkey doesn't match expected")]
     PubkeyMismatch,
-    #[error("Message too old (timestamp check failed)")]
-    MessageExpired,
+    #[error("Response doesn't name its recipient")]
+    MissingTheirPubkey,
+    #[error("Message too old ({age_secs}s)")]
+    MessageExpired { age_secs: u64 },
+    #[error("Message timestamp is {ahead_secs}s in the future")]
+    MessageFromFuture { ahead_secs: u64 },
     #[error("Serialization error: {0}")]
     SerializationError(String),
     #[error("Signing error: {0}")]
     SigningError(String),
+    #[error("Invalid device certificate: {0}")]
+    InvalidDeviceCertificate(String),
+    #[error("Message was already used")]
+    Replayed,
+    #[error("Response belongs to another exchange session")]
+    SessionMismatch,
+    #[error("Exchange can't go from {from:?} to {to:?}")]
+    InvalidTransition {
+        from: SessionStage,
+        to: SessionStage,
+    },
+}
+
+/// Why a scanned exchange payload was rejected, returned to the UI as `{ code, message }`
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ScanError {
+    #[error("This isn't a SneakerNet code")]
+    NotSneakernetPayload,
+    #[error("This code uses protocol version {got}, expected {expected}")]
+    WrongVersion { expected: u32, got: u32 },
+    #[error("This code has expired, ask them to show a fresh one")]
+    Expired { age_secs: u64 },
+    #[error("This code is dated {ahead_secs}s in the future, check that both devices' clocks are set correctly")]
+    ClockSkew { ahead_secs: u64 },
+    #[error("The code's signature is invalid")]
+    BadSignature,
+    #[error("This code was made for someone else")]
+    WrongRecipient,
+    #[error("You scanned your own code")]
+    SelfScan,
+    #[error("You already exchanged keys with this contact")]
+    AlreadyContact,
+    #[error("This code was already used, ask them to show a fresh one")]
+    Replayed,
+    #[error("This code answers a different exchange, start again")]
+    WrongSession,
+    #[error("Too many codes at once, wait a minute and try again")]
+    RateLimited,
+}
+
+impl ScanError {
+    /// Stable error code for the frontend
+    pub fn code(&self) -> &'static str {
+        match self {
+            ScanError::NotSneakernetPayload => "notSneakernetPayload",
+            ScanError::WrongVersion { .. } => "wrongVersion",
+            ScanError::Expired { .. } => "expired",
+            ScanError::ClockSkew { .. } => "clockSkew",
+            ScanError::BadSignature => "badSignature",
+            ScanError::WrongRecipient => "wrongRecipient",
+            ScanError::SelfScan => "selfScan",
+            ScanError::AlreadyContact => "alreadyContact",
+            ScanError::Replayed => "replayed",
+            ScanError::WrongSession => "wrongSession",
+            ScanError::RateLimited => "rateLimited",
+        }
+    }
+
+    /// The payload's timestamp minus our clock, for timestamp failures
+    pub fn skew_secs(&self) -> Option<i64> {
+        match self {
+            ScanError::Expired { age_secs } => Some(-(*age_secs as i64)),
+            ScanError::ClockSkew { ahead_secs } => Some(*ahead_secs as i64),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for ScanError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("ScanError", 3)?;
+        s.serialize_field("code", self.code())?;
+        s.serialize_field("message", &self.to_string())?;
+        s.serialize_field("skewSecs", &self.skew_secs())?;
+        s.end()
+    }
+}
+
+impl From<ExchangeError> for ScanError {
+    fn from(e: ExchangeError) -> Self {
+        match e {
+            ExchangeError::VersionMismatch { expected, got } => {
+                ScanError::WrongVersion { expected, got }
+            }
+            ExchangeError::MessageExpired { age_secs } => ScanError::Expired { age_secs },
+            ExchangeError::MessageFromFuture { ahead_secs } => {
+                ScanError::ClockSkew { ahead_secs }
+            }
+            ExchangeError::SignatureVerificationFailed
+            | ExchangeError::InvalidPubkey
+            | ExchangeError::SigningError(_)
+            | ExchangeError::InvalidDeviceCertificate(_) => ScanError::BadSignature,
+            ExchangeError::PubkeyMismatch | ExchangeError::MissingTheirPubkey => {
+                ScanError::WrongRecipient
+            }
+            ExchangeError::Replayed => ScanError::Replayed,
+            ExchangeError::SessionMismatch => ScanError::WrongSession,
+            ExchangeError::InvalidFormat(_)
+            | ExchangeError::SerializationError(_)
+            | ExchangeError::InvalidTransition { .. } => ScanError::NotSneakernetPayload,
+        }
+    }
 }
 
 /// Exchange message sent over NFC
@@ -45,6 +298,152 @@ pub struct ExchangeMessage {
     pub timestamp: u64,
     pub nonce: String,     // Random nonce (hex)
     pub signature: String, // Schnorr signature (hex)
+    /// Present when `pubkey` is a device subkey, naming its master identity
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_cert: Option<DeviceCertificate>,
+    /// Oldest version the sender can speak; absent from version 1 messages.
+    /// Signed along with `capabilities`, so neither can be stripped on the
+    /// way to turn off a protection both apps support.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_version: Option<u32>,
+    /// `CAP_*` flags of the sender; 0 for version 1 messages
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub capabilities: u32,
+    /// The sender's Iroh node ID for the recipient. Only responses carry
+    /// one, as the node is derived per contact. Signed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    /// Relay the sender's node can be reached through. Signed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay_url: Option<String>,
+    /// Written to a sticker or card to be read long after, so accepted with
+    /// the static tag tolerance. Only initial messages may be static. Signed.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub static_tag: bool,
+    /// What the sender calls themselves, so the contact has a name before
+    /// the user gives one. Signed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<ExchangeProfile>,
+    /// The exchange this response belongs to (`session_id` of the nonce it
+    /// answers and its own), so it can't be passed off as the answer to
+    /// someone else's initial message. Signed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// How `signature` was made: over the content's digest, or as a content
+    /// event by a remote signer. Signed, and the only way it is checked.
+    #[serde(default, skip_serializing_if = "SigScheme::is_digest")]
+    pub sig_scheme: SigScheme,
+}
+
+/// ID of the exchange made of the initial message with `initial_nonce` and
+/// the response with `response_nonce`, both hex. Both sides derive it; the
+/// short code on screen is made from it (`safety::session_code`).
+pub fn session_id(initial_nonce: &str, response_nonce: &str) -> String {
+    let hash = Sha256::new()
+        .chain_update(SESSION_CONTEXT)
+        .chain_update(initial_nonce.to_ascii_lowercase().as_bytes())
+        .chain_update(response_nonce.to_ascii_lowercase().as_bytes())
+        .finalize();
+    hex::encode(&hash[..16])
+}
+
+/// Profile metadata the sender attaches to an exchange message
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExchangeProfile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// SHA-256 of their avatar image (hex), to match it once fetched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar_hash: Option<String>,
+    /// NIP-05 identifier they claim; not checked against the domain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nip05: Option<String>,
+}
+
+impl ExchangeProfile {
+    /// None when there is nothing to send
+    pub fn non_empty(self) -> Option<Self> {
+        (self != Self::default()).then_some(self)
+    }
+
+    fn validate(&self) -> Result<(), ExchangeError> {
+        let invalid = |what: &str| Err(ExchangeError::InvalidFormat(what.to_string()));
+        if let Some(name) = &self.display_name {
+            let too_long = name.chars().count() > MAX_DISPLAY_NAME_CHARS;
+            if too_long || name.chars().any(char::is_control) {
+                return invalid("bad display name");
+            }
+        }
+        if let Some(hash) = &self.avatar_hash {
+            if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return invalid("bad avatar hash");
+            }
+        }
+        if let Some(nip05) = &self.nip05 {
+            let parsed = Identifier::parse(nip05);
+            if nip05.len() > MAX_NIP05_LEN || !matches!(parsed, Ok(Identifier::Nip05 { .. })) {
+                return invalid("bad NIP-05 identifier");
+            }
+        }
+        Ok(())
+    }
+
+    /// As a CBOR array of the three fields, the hash as 32 bytes
+    fn to_value(&self) -> Result<Value, ExchangeError> {
+        let text = |value: &Option<String>| value.clone().map_or(Value::Null, Value::Text);
+        Ok(Value::Array(vec![
+            text(&self.display_name),
+            match &self.avatar_hash {
+                Some(hash) => fixed_width(hash, 32)?,
+                None => Value::Null,
+            },
+            text(&self.nip05),
+        ]))
+    }
+
+    fn from_value(value: &Value) -> Result<Self, ExchangeError> {
+        let invalid = || ExchangeError::InvalidFormat("bad profile".to_string());
+        let fields = value.as_array().ok_or_else(invalid)?;
+        let [display_name, avatar_hash, nip05] = fields.as_slice() else {
+            return Err(invalid());
+        };
+        let text = |value: &Value| match value {
+            Value::Null => Ok(None),
+            Value::Text(text) => Ok(Some(text.clone())),
+            _ => Err(invalid()),
+        };
+        Ok(Self {
+            display_name: text(display_name)?,
+            avatar_hash: match avatar_hash {
+                Value::Null => None,
+                value => Some(from_fixed_width(value, 32)?),
+            },
+            nip05: text(nip05)?,
+        })
+    }
+}
+
+/// Where the sender of an exchange message can be dialed over Iroh
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeHints {
+    pub node_id: Option<String>,
+    pub relay_url: Option<String>,
+}
+
+impl NodeHints {
+    pub fn is_empty(&self) -> bool {
+        self.node_id.is_none() && self.relay_url.is_none()
+    }
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
 }
 
 /// Contact stored after successful exchange
@@ -53,19 +452,94 @@ pub struct ExchangeMessage {
 pub struct Contact {
     pub id: String,
     pub nostr_pubkey: String,     // Their Nostr pubkey (hex)
-    pub iroh_endpoint_id: String, // Derived Iroh endpoint ID
+    pub iroh_endpoint_id: String, // Their Iroh node ID for us ("" until known)
     pub exchanged_at: u64,        // Unix timestamp
     pub nickname: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Name given by the contact who introduced them to us
+    #[serde(default)]
+    pub introduced_as: Option<String>,
+    /// Pubkey of the contact who introduced them to us
+    #[serde(default)]
+    pub introduced_by: Option<String>,
+    /// Name from their own profile metadata
+    #[serde(default)]
+    pub profile_name: Option<String>,
+    /// Saved automatically (e.g. by beacon mode) and not yet confirmed by the user
+    #[serde(default)]
+    pub needs_review: bool,
+    /// Messages from this contact are dropped
+    #[serde(default)]
+    pub blocked: bool,
+    /// Keys they rotated away from, oldest first
+    #[serde(default)]
+    pub previous_pubkeys: Vec<String>,
+    /// Safety number compared out of band for their current key
+    #[serde(default)]
+    pub verified: bool,
+    /// Identity their device key is certified for, if it is a device subkey
+    #[serde(default)]
+    pub master_pubkey: Option<String>,
+    /// Relay their node said it can be reached through
+    #[serde(default)]
+    pub relay_url: Option<String>,
+    /// How we first learned of them
+    #[serde(default)]
+    pub origin: ContactOrigin,
+    /// Nostr relays they publish to, from an nprofile
+    #[serde(default)]
+    pub nostr_relays: Vec<String>,
+    /// SHA-256 of the avatar from their exchange profile (hex)
+    #[serde(default)]
+    pub avatar_hash: Option<String>,
+    /// NIP-05 identifier from their exchange profile, as they claimed it
+    #[serde(default)]
+    pub nip05: Option<String>,
+    /// Transport of the exchange that verified them; None if it isn't known
+    /// or they were added without one
+    #[serde(default)]
+    pub exchange_method: Option<Transport>,
+    /// Signature of their exchange message (hex), kept as a record of it
+    #[serde(default)]
+    pub exchange_signature: Option<String>,
+    /// When they revoked their current key as compromised. Messages from it
+    /// are dropped until a new exchange moves them to another key.
+    #[serde(default)]
+    pub revoked_at: Option<u64>,
+    /// Our disappearing message timer for them, in seconds (see
+    /// `disappearing`)
+    #[serde(default)]
+    pub disappear_after_secs: Option<u64>,
+    /// The node ID they signed into their last exchange differs from the one
+    /// we derive for them. Only the derived one is kept; this flags the rest.
+    #[serde(default)]
+    pub node_hint_mismatch: bool,
 }
 
-/// Hash content for signing using SHA256
-fn hash_content(content: &[u8]) -> [u8; 32] {
-    let mut hasher = Sha256::new();
-    hasher.update(content);
-    let result = hasher.finalize();
-    let mut hash = [0u8; 32];
-    hash.copy_from_slice(&result);
-    hash
+/// How we first learned of a contact
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ContactOrigin {
+    /// A signed SneakerNet exchange, or added before origins were recorded
+    #[default]
+    Exchange,
+    /// A bare npub or nprofile shown by another Nostr app; nothing was signed
+    Nip19,
+    /// Vouched for by one of our contacts in a signed card, not met yet
+    Introduction,
+    /// Signed exchange messages swapped over Nostr DMs, without meeting
+    Remote,
+}
+
+/// Contacts that share the same Nostr pubkey
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateGroup {
+    pub nostr_pubkey: String,
+    pub contact_ids: Vec<String>,
 }
 
 impl ExchangeMessage {
@@ -80,6 +554,104 @@ impl ExchangeMessage {
     }
 
     fn new(keys: &Keys, their_pubkey: Option<String>) -> Result<Self, ExchangeError> {
+        let hints = NodeHints::default();
+        let mut msg = Self::unsigned(keys.public_key().to_hex(), their_pubkey, hints)?;
+        msg.signature = sign_content(keys, &msg.signing_content())
+            .map_err(|e| ExchangeError::SigningError(e.to_string()))?;
+        Ok(msg)
+    }
+
+    /// Create an exchange message signed by `signer`, which may be a remote
+    /// signer that asks its user first. `offer` is the version and
+    /// capabilities to sign in.
+    pub async fn new_signed(
+        signer: &dyn Signer,
+        their_pubkey: Option<&str>,
+        hints: NodeHints,
+        profile: Option<ExchangeProfile>,
+        offer: Negotiated,
+    ) -> Result<Self, ExchangeError> {
+        let mut msg = Self::unsigned(
+            signer.public_key_hex(),
+            their_pubkey.map(str::to_string),
+            hints,
+        )?;
+        msg.profile = profile;
+        msg.offer(offer);
+        msg.signed_by(signer).await
+    }
+
+    /// Create a response to the initial message with `initial_nonce`, bound
+    /// to it by a signed session ID
+    pub async fn new_bound_response(
+        signer: &dyn Signer,
+        their_pubkey: &str,
+        initial_nonce: &str,
+        hints: NodeHints,
+        profile: Option<ExchangeProfile>,
+        offer: Negotiated,
+    ) -> Result<Self, ExchangeError> {
+        let mut msg = Self::unsigned(
+            signer.public_key_hex(),
+            Some(their_pubkey.to_string()),
+            hints,
+        )?;
+        msg.profile = profile;
+        msg.offer(offer);
+        msg.session_id = Some(session_id(initial_nonce, &msg.nonce));
+        msg.signed_by(signer).await
+    }
+
+    /// Create a response signed with local `keys` without awaiting, for a
+    /// tap that can't wait on the runtime. Bound to `initial_nonce`, if given,
+    /// as by [`ExchangeMessage::new_bound_response`].
+    pub fn new_local_response(
+        keys: &Keys,
+        their_pubkey: &str,
+        initial_nonce: Option<&str>,
+        hints: NodeHints,
+        profile: Option<ExchangeProfile>,
+        offer: Negotiated,
+    ) -> Result<Self, ExchangeError> {
+        let mut msg = Self::unsigned(
+            keys.public_key().to_hex(),
+            Some(their_pubkey.to_string()),
+            hints,
+        )?;
+        msg.profile = profile;
+        msg.offer(offer);
+        msg.session_id = initial_nonce.map(|nonce| session_id(nonce, &msg.nonce));
+        msg.signature = sign_content(keys, &msg.signing_content())
+            .map_err(|e| ExchangeError::SigningError(e.to_string()))?;
+        Ok(msg)
+    }
+
+    /// Create a long-lived initial message for a sticker or business card
+    pub async fn new_static(
+        signer: &dyn Signer,
+        hints: NodeHints,
+        profile: Option<ExchangeProfile>,
+    ) -> Result<Self, ExchangeError> {
+        let mut msg = Self::unsigned(signer.public_key_hex(), None, hints)?;
+        msg.static_tag = true;
+        msg.profile = profile;
+        msg.signed_by(signer).await
+    }
+
+    async fn signed_by(mut self, signer: &dyn Signer) -> Result<Self, ExchangeError> {
+        self.sig_scheme = signer.sig_scheme();
+        self.signature = signer
+            .sign_content(&self.signing_content())
+            .await
+            .map_err(|e| ExchangeError::SigningError(e.to_string()))?;
+        Ok(self)
+    }
+
+    fn unsigned(
+        pubkey: String,
+        their_pubkey: Option<String>,
+        hints: NodeHints,
+    ) -> Result<Self, ExchangeError> {
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
@@ -91,34 +663,6 @@ impl ExchangeMessage {
             .map_err(|e| ExchangeError::SerializationError(e.to_string()))?;
         let nonce = hex::encode(nonce_bytes);
 
-        let pubkey = keys.public_key().to_hex();
-
-        // Create the content to sign
-        let content = format!(
-            "sneakernet:{}:{}:{}:{}",
-            pubkey,
-            their_pubkey.as_deref().unwrap_or(""),
-            timestamp,
-            nonce
-        );
-
-        // Hash the content to create a message for signing
-        let hash = hash_content(content.as_bytes());
-        let message = Secp256k1Message::from_digest(hash);
-
-        // Sign the message using the secret key
-        let secp = Secp256k1::new();
-        let secret_key = keys.secret_key();
-
-        // Get the raw secp256k1 keypair
-        let sk_bytes = hex::decode(secret_key.to_secret_hex())
-            .map_err(|e| ExchangeError::SigningError(e.to_string()))?;
-        let sk = secp256k1::SecretKey::from_slice(&sk_bytes)
-            .map_err(|e| ExchangeError::SigningError(e.to_string()))?;
-        let keypair = secp256k1::Keypair::from_secret_key(&secp, &sk);
-
-        let signature = secp.sign_schnorr(&message, &keypair);
-
         Ok(Self {
             version: PROTOCOL_VERSION,
             msg_type: "sneakernet-exchange".to_string(),
@@ -126,10 +670,127 @@ impl ExchangeMessage {
             their_pubkey,
             timestamp,
             nonce,
-            signature: hex::encode(signature.serialize()),
+            signature: String::new(),
+            device_cert: None,
+            min_version: Some(MIN_PROTOCOL_VERSION),
+            capabilities: OUR_CAPABILITIES,
+            node_id: hints.node_id,
+            relay_url: hints.relay_url,
+            static_tag: false,
+            profile: None,
+            session_id: None,
+            sig_scheme: SigScheme::Digest,
         })
     }
 
+    /// Where the sender said it can be dialed
+    pub fn node_hints(&self) -> NodeHints {
+        NodeHints {
+            node_id: self.node_id.clone(),
+            relay_url: self.relay_url.clone(),
+        }
+    }
+
+    /// Advertise `offer` rather than everything we support. Both are
+    /// signed, so this must come before signing; a version 1 peer gets no
+    /// range and no flags, and signs the same as before.
+    fn offer(&mut self, offer: Negotiated) {
+        self.version = offer.version;
+        if offer.version < PROTOCOL_VERSION {
+            self.min_version = None;
+        }
+        self.capabilities = offer.capabilities;
+    }
+
+    /// The version and capabilities to answer this message with
+    pub fn negotiate(&self) -> Negotiated {
+        Negotiated {
+            version: self.version.min(PROTOCOL_VERSION),
+            capabilities: self.capabilities & OUR_CAPABILITIES,
+        }
+    }
+
+    /// Adjust to what a peer understands: an older version number, and no
+    /// fields it can't use. The signed version range and capabilities are
+    /// settled by the `offer` the message was signed with.
+    pub fn downgraded(mut self, negotiated: Negotiated) -> Self {
+        self.version = negotiated.version;
+        if !negotiated.has(CAP_DEVICE_CERT) {
+            self.device_cert = None;
+        }
+        self
+    }
+
+    /// Encode in the most compact form the peer reads
+    pub fn encode(&self, negotiated: Negotiated) -> Result<String, ExchangeError> {
+        if negotiated.has(CAP_COMPRESSION) {
+            self.to_compressed()
+        } else if negotiated.has(CAP_COMPACT_ENCODING) {
+            self.to_compact()
+        } else {
+            self.to_json()
+        }
+    }
+
+    /// Attach the certificate for the device subkey that signed this
+    pub fn with_device_cert(mut self, cert: Option<DeviceCertificate>) -> Self {
+        self.device_cert = cert;
+        self
+    }
+
+    /// The identity behind the message: the master key if it was signed by a
+    /// certified device subkey, otherwise the signing key
+    pub fn identity_pubkey(&self) -> &str {
+        match &self.device_cert {
+            Some(cert) => &cert.master_pubkey,
+            None => &self.pubkey,
+        }
+    }
+
+    /// The content the signature covers. Node hints, the static marker, the
+    /// profile (as JSON), the session ID, the version range and
+    /// capabilities and a non-digest signature scheme are appended only when
+    /// present, so messages without them sign the same as before.
+    fn signing_content(&self) -> String {
+        let mut content = format!(
+            "sneakernet:{}:{}:{}:{}",
+            self.pubkey,
+            self.their_pubkey.as_deref().unwrap_or(""),
+            self.timestamp,
+            self.nonce
+        );
+        if self.node_id.is_some() || self.relay_url.is_some() {
+            content.push_str(&format!(
+                ":{}:{}",
+                self.node_id.as_deref().unwrap_or(""),
+                self.relay_url.as_deref().unwrap_or("")
+            ));
+        }
+        if self.static_tag {
+            content.push_str(":static");
+        }
+        if let Some(profile) = &self.profile {
+            // Field order is fixed by the struct, so both sides serialize alike
+            let json = serde_json::to_string(profile).unwrap_or_default();
+            content.push_str(&format!(":profile:{}", json));
+        }
+        if let Some(session_id) = &self.session_id {
+            content.push_str(&format!(":session:{}", session_id));
+        }
+        if self.min_version.is_some() || self.capabilities != 0 {
+            // The compact form always carries a minimum, defaulting to the version
+            content.push_str(&format!(
+                ":caps:{}:{}",
+                self.min_version.unwrap_or(self.version),
+                self.capabilities
+            ));
+        }
+        if !self.sig_scheme.is_digest() {
+            content.push_str(&format!(":scheme:{}", self.sig_scheme.as_str()));
+        }
+        content
+    }
+
     /// Serialize to JSON for NFC transmission
     pub fn to_json(&self) -> Result<String, ExchangeError> {
         serde_json::to_string(self).map_err(|e| ExchangeError::SerializationError(e.to_string()))
@@ -140,10 +801,239 @@ impl ExchangeMessage {
         serde_json::from_str(json).map_err(|e| ExchangeError::InvalidFormat(e.to_string()))
     }
 
-    /// Verify the message signature and optionally check their_pubkey
+    /// Encode as a CBOR array with the keys, nonce and signature as
+    /// fixed-width byte strings: about half the size of the JSON
+    pub fn to_binary(&self) -> Result<Vec<u8>, ExchangeError> {
+        let mut fields = vec![
+            Value::Integer(self.version.into()),
+            fixed_width(&self.pubkey, 32)?,
+            match &self.their_pubkey {
+                Some(their_pubkey) => fixed_width(their_pubkey, 32)?,
+                None => Value::Null,
+            },
+            Value::Integer(self.timestamp.into()),
+            fixed_width(&self.nonce, 16)?,
+            fixed_width(&self.signature, 64)?,
+            Value::Integer(self.min_version.unwrap_or(self.version).into()),
+            Value::Integer(self.capabilities.into()),
+        ];
+        let cert = match &self.device_cert {
+            Some(cert) => Value::serialized(cert)
+                .map_err(|e| ExchangeError::SerializationError(e.to_string()))?,
+            None => Value::Null,
+        };
+        let text = |value: &Option<String>| value.clone().map_or(Value::Null, Value::Text);
+        let profile = match &self.profile {
+            Some(profile) => profile.to_value()?,
+            None => Value::Null,
+        };
+        let session = match &self.session_id {
+            Some(session_id) => fixed_width(session_id, 16)?,
+            None => Value::Null,
+        };
+        let scheme = match self.sig_scheme {
+            SigScheme::Digest => Value::Null,
+            scheme => Value::Text(scheme.as_str().to_string()),
+        };
+        // Optional fields in order, dropping unset ones from the end so older
+        // messages encode as they always have
+        let mut optional = vec![
+            cert,
+            text(&self.node_id),
+            text(&self.relay_url),
+            Value::Bool(self.static_tag),
+            profile,
+            session,
+            scheme,
+        ];
+        while matches!(optional.last(), Some(Value::Null | Value::Bool(false))) {
+            optional.pop();
+        }
+        fields.extend(optional);
+
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&Value::Array(fields), &mut bytes)
+            .map_err(|e| ExchangeError::SerializationError(e.to_string()))?;
+        Ok(bytes)
+    }
+
+    /// Decode the CBOR form made by `to_binary`
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, ExchangeError> {
+        let invalid = |what: &str| ExchangeError::InvalidFormat(what.to_string());
+        let value: Value =
+            ciborium::from_reader(bytes).map_err(|e| ExchangeError::InvalidFormat(e.to_string()))?;
+        let fields = value.into_array().map_err(|_| invalid("not a CBOR array"))?;
+        let [
+            version,
+            pubkey,
+            their_pubkey,
+            timestamp,
+            nonce,
+            signature,
+            min_version,
+            capabilities,
+            rest @ ..,
+        ] = fields.as_slice()
+        else {
+            return Err(invalid("missing fields"));
+        };
+
+        if rest.len() > 7 {
+            return Err(invalid("unexpected trailing fields"));
+        }
+        let optional = |index: usize| rest.get(index).unwrap_or(&Value::Null);
+        let (cert, node_id, relay_url) = (optional(0), optional(1), optional(2));
+        let static_tag = match optional(3) {
+            Value::Null => false,
+            marker => marker
+                .as_bool()
+                .ok_or_else(|| invalid("bad static marker"))?,
+        };
+        let profile = match optional(4) {
+            Value::Null => None,
+            value => Some(ExchangeProfile::from_value(value)?),
+        };
+        let session_id = match optional(5) {
+            Value::Null => None,
+            value => Some(from_fixed_width(value, 16)?),
+        };
+        let sig_scheme = match optional(6) {
+            Value::Null => SigScheme::Digest,
+            value => value
+                .as_text()
+                .and_then(SigScheme::from_name)
+                .ok_or_else(|| invalid("bad signature scheme"))?,
+        };
+        let device_cert = match cert {
+            Value::Null => None,
+            cert => Some(
+                cert.deserialized()
+                    .map_err(|e| ExchangeError::InvalidFormat(e.to_string()))?,
+            ),
+        };
+        let text = |value: &Value, what: &str| match value {
+            Value::Null => Ok(None),
+            Value::Text(text) => Ok(Some(text.clone())),
+            _ => Err(invalid(what)),
+        };
+
+        Ok(Self {
+            version: version
+                .as_integer()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| invalid("bad version"))?,
+            msg_type: "sneakernet-exchange".to_string(),
+            pubkey: from_fixed_width(pubkey, 32)?,
+            their_pubkey: match their_pubkey {
+                Value::Null => None,
+                value => Some(from_fixed_width(value, 32)?),
+            },
+            timestamp: timestamp
+                .as_integer()
+                .and_then(|t| u64::try_from(t).ok())
+                .ok_or_else(|| invalid("bad timestamp"))?,
+            nonce: from_fixed_width(nonce, 16)?,
+            signature: from_fixed_width(signature, 64)?,
+            device_cert,
+            min_version: Some(
+                min_version
+                    .as_integer()
+                    .and_then(|v| u32::try_from(v).ok())
+                    .ok_or_else(|| invalid("bad minimum version"))?,
+            ),
+            capabilities: capabilities
+                .as_integer()
+                .and_then(|c| u32::try_from(c).ok())
+                .ok_or_else(|| invalid("bad capabilities"))?,
+            node_id: text(node_id, "bad node ID")?,
+            relay_url: text(relay_url, "bad relay URL")?,
+            static_tag,
+            profile,
+            session_id,
+            sig_scheme,
+        })
+    }
+
+    /// The CBOR form as text for a QR code or NFC record
+    pub fn to_compact(&self) -> Result<String, ExchangeError> {
+        Ok(format!(
+            "{}{}",
+            COMPACT_PREFIX,
+            URL_SAFE_NO_PAD.encode(self.to_binary()?)
+        ))
+    }
+
+    /// The compact text form with the CBOR zlib-compressed, when that comes
+    /// out shorter. Mostly keys and signatures, a message only shrinks when
+    /// it carries text such as relay URLs or a profile; otherwise this is
+    /// the same as `to_compact`.
+    pub fn to_compressed(&self) -> Result<String, ExchangeError> {
+        let bytes = self.to_binary()?;
+        let compressed = miniz_oxide::deflate::compress_to_vec_zlib(&bytes, 10);
+        let shortest = if compressed.len() < bytes.len() {
+            compressed
+        } else {
+            bytes
+        };
+        Ok(format!(
+            "{}{}",
+            COMPACT_PREFIX,
+            URL_SAFE_NO_PAD.encode(shortest)
+        ))
+    }
+
+    /// Decode a payload in any supported form: JSON (v1 or v2), the compact
+    /// text form, or raw CBOR bytes, either compressed or not
+    pub fn decode(payload: &[u8]) -> Result<Self, ExchangeError> {
+        let text = std::str::from_utf8(payload).map(str::trim).unwrap_or_default();
+        if text.starts_with('{') {
+            return Self::from_json(text);
+        }
+        let bytes = match text.strip_prefix(COMPACT_PREFIX) {
+            Some(data) => URL_SAFE_NO_PAD
+                .decode(data)
+                .map_err(|e| ExchangeError::InvalidFormat(e.to_string()))?,
+            None => payload.to_vec(),
+        };
+        if bytes.first() == Some(&ZLIB_HEADER) {
+            let inflated =
+                miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&bytes, MAX_INFLATED_LEN)
+                    .map_err(|_| ExchangeError::InvalidFormat("bad compressed data".to_string()))?;
+            return Self::from_binary(&inflated);
+        }
+        Self::from_binary(&bytes)
+    }
+
+    /// Verify the message signature and optionally check their_pubkey,
+    /// with the tolerance for a live exchange
     pub fn verify(&self, expected_our_pubkey: Option<&str>) -> Result<(), ExchangeError> {
-        // Check version
-        if self.version != PROTOCOL_VERSION {
+        self.verify_with(expected_our_pubkey, TimestampTolerance::LIVE)
+    }
+
+    /// Verify a message that must be a response to us. Unlike `verify`, a
+    /// message without their_pubkey is rejected, so someone's initial
+    /// broadcast can't stand in for their answer to ours.
+    pub fn verify_response(
+        &self,
+        our_pubkey: &str,
+        tolerance: TimestampTolerance,
+    ) -> Result<(), ExchangeError> {
+        if self.their_pubkey.is_none() {
+            return Err(ExchangeError::MissingTheirPubkey);
+        }
+        self.verify_with(Some(our_pubkey), tolerance)
+    }
+
+    /// Verify the message, accepting timestamps within `tolerance` of our clock
+    pub fn verify_with(
+        &self,
+        expected_our_pubkey: Option<&str>,
+        tolerance: TimestampTolerance,
+    ) -> Result<(), ExchangeError> {
+        // Check the sender's version range overlaps ours; a newer app that
+        // can still speak our version is fine
+        let their_min = self.min_version.unwrap_or(self.version).min(self.version);
+        if self.version < MIN_PROTOCOL_VERSION || their_min > PROTOCOL_VERSION {
             return Err(ExchangeError::VersionMismatch {
                 expected: PROTOCOL_VERSION,
                 got: self.version,
@@ -157,38 +1047,47 @@ impl ExchangeMessage {
             ));
         }
 
-        // Parse the sender's public key
-        let sender_pubkey =
-            PublicKey::from_hex(&self.pubkey).map_err(|_| ExchangeError::InvalidPubkey)?;
-
-        // Reconstruct the signed content
-        let content = format!(
-            "sneakernet:{}:{}:{}:{}",
-            self.pubkey,
-            self.their_pubkey.as_deref().unwrap_or(""),
-            self.timestamp,
-            self.nonce
-        );
+        // Check the sender's public key parses
+        PublicKey::from_hex(&self.pubkey).map_err(|_| ExchangeError::InvalidPubkey)?;
 
-        // Hash the content
-        let hash = hash_content(content.as_bytes());
-        let message = Secp256k1Message::from_digest(hash);
+        // A response is made for one tap; it must not live on a sticker
+        if self.static_tag && self.their_pubkey.is_some() {
+            return Err(ExchangeError::InvalidFormat(
+                "Static messages can't be responses".to_string(),
+            ));
+        }
+        if self.session_id.is_some() && self.their_pubkey.is_none() {
+            return Err(ExchangeError::InvalidFormat(
+                "Only responses belong to a session".to_string(),
+            ));
+        }
 
-        // Parse signature from hex
-        let sig_bytes =
-            hex::decode(&self.signature).map_err(|_| ExchangeError::SignatureVerificationFailed)?;
-        let signature = secp256k1::schnorr::Signature::from_slice(&sig_bytes)
-            .map_err(|_| ExchangeError::SignatureVerificationFailed)?;
+        if let Some(profile) = &self.profile {
+            profile.validate()?;
+        }
 
-        // Get the x-only pubkey for verification
-        let xonly_pubkey = sender_pubkey.to_bytes();
-        let xonly =
-            XOnlyPublicKey::from_slice(&xonly_pubkey).map_err(|_| ExchangeError::InvalidPubkey)?;
+        // Verify the signature over the reconstructed content
+        verify_content_as(
+            &self.pubkey,
+            &self.signing_content(),
+            &self.signature,
+            self.sig_scheme,
+        )
+        .map_err(|e| match e {
+            KeyError::ParseError(_) => ExchangeError::InvalidPubkey,
+            _ => ExchangeError::SignatureVerificationFailed,
+        })?;
 
-        // Verify signature
-        let secp = Secp256k1::verification_only();
-        secp.verify_schnorr(&signature, &message, &xonly)
-            .map_err(|_| ExchangeError::SignatureVerificationFailed)?;
+        // A device certificate must be valid and for the signing key
+        if let Some(cert) = &self.device_cert {
+            cert.verify()
+                .map_err(|e| ExchangeError::InvalidDeviceCertificate(e.to_string()))?;
+            if cert.device_pubkey != self.pubkey {
+                return Err(ExchangeError::InvalidDeviceCertificate(
+                    "certificate is for another key".to_string(),
+                ));
+            }
+        }
 
         // If we expect our pubkey to be in their message, verify it
         if let Some(our_pubkey) = expected_our_pubkey {
@@ -199,17 +1098,343 @@ impl ExchangeMessage {
             }
         }
 
-        // Optional: Check timestamp isn't too old (e.g., 5 minutes)
+        // Check the timestamp is neither stale nor from the future
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs();
 
-        if now > self.timestamp && now - self.timestamp > 300 {
-            return Err(ExchangeError::MessageExpired);
+        tolerance.check(self.timestamp, now)
+    }
+
+    /// Check a verified response answers one of our messages, given by
+    /// nonce. A sender that binds its responses must have bound this one;
+    /// older apps' responses carry no session ID and pass.
+    pub fn verify_session(&self, our_nonces: &[&str]) -> Result<(), ExchangeError> {
+        match &self.session_id {
+            Some(id) if our_nonces.iter().any(|n| *id == session_id(n, &self.nonce)) => Ok(()),
+            None if self.capabilities & CAP_SESSION_BINDING == 0 => Ok(()),
+            _ => Err(ExchangeError::SessionMismatch),
         }
+    }
+}
 
-        Ok(())
+/// A hex field as a CBOR byte string of exactly `len` bytes
+fn fixed_width(hex_str: &str, len: usize) -> Result<Value, ExchangeError> {
+    let bytes = hex::decode(hex_str).map_err(|e| ExchangeError::SerializationError(e.to_string()))?;
+    if bytes.len() != len {
+        return Err(ExchangeError::SerializationError(format!(
+            "expected {} bytes, got {}",
+            len,
+            bytes.len()
+        )));
+    }
+    Ok(Value::Bytes(bytes))
+}
+
+/// A fixed-width CBOR byte string back as hex
+fn from_fixed_width(value: &Value, len: usize) -> Result<String, ExchangeError> {
+    match value.as_bytes() {
+        Some(bytes) if bytes.len() == len => Ok(hex::encode(bytes)),
+        _ => Err(ExchangeError::InvalidFormat(format!(
+            "expected a {}-byte field",
+            len
+        ))),
+    }
+}
+
+/// Parse and verify a scanned exchange payload.
+///
+/// `our_pubkey` is used both to detect scanning our own code and to check
+/// that a response was made for us.
+pub fn verify_scanned_payload(
+    data: &str,
+    our_pubkey: Option<&str>,
+    tolerance: TimestampTolerance,
+) -> Result<ExchangeMessage, ScanError> {
+    let msg = ExchangeMessage::decode(data.as_bytes())
+        .map_err(|_| ScanError::NotSneakernetPayload)?;
+
+    if our_pubkey == Some(msg.pubkey.as_str()) {
+        return Err(ScanError::SelfScan);
+    }
+
+    msg.verify_with(our_pubkey, tolerance)?;
+
+    Ok(msg)
+}
+
+/// The link form of a compact payload, to paste into any messenger. `None`
+/// for a JSON payload, which is only made for apps too old to open links.
+pub fn exchange_uri(payload: &str) -> Option<String> {
+    let data = payload.strip_prefix(COMPACT_PREFIX)?;
+    Some(format!("{}{}", EXCHANGE_URI_PREFIX, data))
+}
+
+/// The compact payload in an exchange link. Messengers may append a
+/// trailing slash or tracking parameters, which are ignored.
+pub fn payload_from_uri(uri: &str) -> Result<String, ScanError> {
+    let uri = uri.trim();
+    let data = uri
+        .get(..EXCHANGE_URI_PREFIX.len())
+        .filter(|scheme| scheme.eq_ignore_ascii_case(EXCHANGE_URI_PREFIX))
+        .map(|_| &uri[EXCHANGE_URI_PREFIX.len()..])
+        .ok_or(ScanError::NotSneakernetPayload)?;
+    let data = data.split(['?', '#']).next().unwrap_or_default();
+    let data = data.trim_end_matches('/');
+
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if data.is_empty() || !data.chars().all(valid) {
+        return Err(ScanError::NotSneakernetPayload);
+    }
+    Ok(format!("{}{}", COMPACT_PREFIX, data))
+}
+
+/// The payload in text pasted from an email or chat: an exchange link or
+/// `sn2:` data wherever it appears, rejoined if a mail client wrapped it
+/// over several lines
+pub fn payload_from_text(text: &str) -> Result<String, ScanError> {
+    let text = text.trim();
+    // A version 1 app's JSON
+    if text.starts_with('{') {
+        return Ok(text.to_string());
+    }
+    let start = text
+        .find(COMPACT_PREFIX)
+        .map(|i| i + COMPACT_PREFIX.len())
+        .or_else(|| {
+            let lower = text.to_ascii_lowercase();
+            lower
+                .find(EXCHANGE_URI_PREFIX)
+                .map(|i| i + EXCHANGE_URI_PREFIX.len())
+        })
+        .ok_or(ScanError::NotSneakernetPayload)?;
+
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    let mut lines = text[start..].lines();
+    let first = lines.next().unwrap_or_default();
+    let data: String = first.chars().take_while(|c| valid(*c)).collect();
+    let mut pieces = vec![data];
+    if pieces[0].len() == first.len() {
+        pieces.extend(
+            lines
+                .map(str::trim)
+                .take_while(|line| !line.is_empty() && line.chars().all(valid))
+                .map(str::to_string),
+        );
+    }
+
+    // Text after the payload can look like more of it, so stop at the
+    // fewest lines that make a whole message
+    (1..=pieces.len())
+        .map(|count| format!("{}{}", COMPACT_PREFIX, pieces[..count].concat()))
+        .find(|payload| ExchangeMessage::decode(payload.as_bytes()).is_ok())
+        .ok_or(ScanError::NotSneakernetPayload)
+}
+
+/// Encrypt a response payload to `their_pubkey` from a throwaway key, so a
+/// camera or onlooker catching the code on screen learns nothing from it
+pub fn seal_payload(payload: &str, their_pubkey: &str) -> Result<String, ExchangeError> {
+    let recipient = PublicKey::from_hex(their_pubkey).map_err(|_| ExchangeError::InvalidPubkey)?;
+    let ephemeral = Keys::generate();
+    let sealed = nip44::encrypt(
+        ephemeral.secret_key(),
+        &recipient,
+        payload,
+        nip44::Version::V2,
+    )
+    .map_err(|e| ExchangeError::SerializationError(e.to_string()))?;
+    Ok(format!(
+        "{}{}:{}",
+        SEALED_PREFIX,
+        ephemeral.public_key().to_hex(),
+        sealed
+    ))
+}
+
+/// The payload inside a sealed response, opened with `keys`. Anything not
+/// sealed is returned as it is.
+pub fn open_sealed_payload(data: &str, keys: Option<&Keys>) -> Result<String, ScanError> {
+    let Some(sealed) = data.trim().strip_prefix(SEALED_PREFIX) else {
+        return Ok(data.to_string());
+    };
+    let (sender, content) = sealed
+        .split_once(':')
+        .ok_or(ScanError::NotSneakernetPayload)?;
+    let sender = PublicKey::from_hex(sender).map_err(|_| ScanError::NotSneakernetPayload)?;
+    let keys = keys.ok_or(ScanError::WrongRecipient)?;
+    nip44::decrypt(keys.secret_key(), &sender, content).map_err(|_| ScanError::WrongRecipient)
+}
+
+/// Where a single exchange operation has got to, for a live status line.
+/// Finer than `SessionStage` and not persisted.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ExchangePhase {
+    /// Armed and waiting for the other device or a tag
+    WaitingForTap,
+    /// A tag or device came into range
+    TagDetected,
+    /// Their payload was read and decoded
+    PayloadRead,
+    /// Their message checked out
+    Verified,
+    /// Our initial message is out; their response comes next
+    AwaitingResponse,
+    /// Our response was delivered
+    Responded,
+    /// The operation failed; the command returns why
+    Failed,
+}
+
+/// An `ExchangePhase` as announced on `exchange://state`
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExchangeProgress {
+    pub phase: ExchangePhase,
+    pub transport: Transport,
+    /// Their pubkey, once their message has been verified
+    pub their_pubkey: Option<String>,
+    pub at: u64,
+}
+
+/// Step of the exchange handshake
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SessionStage {
+    /// No exchange in progress
+    #[default]
+    Idle,
+    /// Our initial message is out, waiting for their response
+    Broadcast,
+    /// Their verified message arrived, ours still to be delivered
+    Received,
+    /// Both messages delivered, waiting for the contact to be saved
+    Responded,
+    /// Contact saved
+    Complete,
+}
+
+/// The exchange in the foreground, persisted so it can be picked up again
+/// after the app is suspended mid-handshake:
+///
+/// ```text
+/// Idle -broadcast-> Broadcast -receive-> Received -respond-> Responded -complete-> Complete
+///   |                                    ^    |                                    ^
+///   +---------------receive--------------+    +--------------complete--------------+
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExchangeSession {
+    pub stage: SessionStage,
+    pub transport: Option<Transport>,
+    /// Peer of this exchange, once their message has arrived
+    pub their_pubkey: Option<String>,
+    pub started_at: u64,
+    /// Time of the last transition
+    pub updated_at: u64,
+}
+
+impl ExchangeSession {
+    /// Whether an unfinished exchange was left alone for too long
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.in_progress() && self.updated_at.saturating_add(PENDING_EXCHANGE_TTL_SECS) < now
+    }
+
+    fn in_progress(&self) -> bool {
+        !matches!(self.stage, SessionStage::Idle | SessionStage::Complete)
+    }
+
+    /// The session to resume after a suspend; an expired one starts over
+    pub fn resumed(self, now: u64) -> Self {
+        if self.is_expired(now) {
+            Self::default()
+        } else {
+            self
+        }
+    }
+
+    fn is_with(&self, their_pubkey: &str) -> bool {
+        self.their_pubkey.as_deref() == Some(their_pubkey)
+    }
+
+    fn enter(&mut self, stage: SessionStage, now: u64) {
+        self.stage = stage;
+        self.updated_at = now;
+    }
+
+    fn invalid(&self, to: SessionStage) -> ExchangeError {
+        ExchangeError::InvalidTransition {
+            from: self.stage,
+            to,
+        }
+    }
+
+    /// Our initial message went out; any earlier exchange is left behind
+    pub fn broadcast(&mut self, transport: Transport, now: u64) {
+        *self = Self {
+            stage: SessionStage::Broadcast,
+            transport: Some(transport),
+            their_pubkey: None,
+            started_at: now,
+            updated_at: now,
+        };
+    }
+
+    /// A verified message from `their_pubkey` arrived. A message from someone
+    /// new starts a fresh session; the exchange it interrupts stays queued.
+    pub fn receive(&mut self, their_pubkey: &str, transport: Transport, now: u64) {
+        match self.stage {
+            SessionStage::Broadcast => {
+                self.their_pubkey = Some(their_pubkey.to_string());
+                self.transport = Some(transport);
+            }
+            // A repeated tap or scan of the same peer
+            SessionStage::Received | SessionStage::Responded if self.is_with(their_pubkey) => {
+                return;
+            }
+            _ => {
+                *self = Self {
+                    transport: Some(transport),
+                    their_pubkey: Some(their_pubkey.to_string()),
+                    started_at: now,
+                    ..Self::default()
+                };
+            }
+        }
+        self.enter(SessionStage::Received, now);
+    }
+
+    /// Our message for `their_pubkey` was delivered
+    pub fn respond(&mut self, their_pubkey: &str, now: u64) -> Result<(), ExchangeError> {
+        match self.stage {
+            SessionStage::Received | SessionStage::Responded if self.is_with(their_pubkey) => {
+                self.enter(SessionStage::Responded, now);
+                Ok(())
+            }
+            _ => Err(self.invalid(SessionStage::Responded)),
+        }
+    }
+
+    /// `their_pubkey` was saved as a contact
+    pub fn complete(&mut self, their_pubkey: &str, now: u64) -> Result<(), ExchangeError> {
+        match self.stage {
+            SessionStage::Received | SessionStage::Responded if self.is_with(their_pubkey) => {
+                self.enter(SessionStage::Complete, now);
+                Ok(())
+            }
+            _ => Err(self.invalid(SessionStage::Complete)),
+        }
+    }
+
+    /// The exchange with `their_pubkey` was abandoned
+    pub fn cancel(&mut self, their_pubkey: &str) -> Result<(), ExchangeError> {
+        if self.in_progress() && self.is_with(their_pubkey) {
+            *self = Self::default();
+            Ok(())
+        } else {
+            Err(self.invalid(SessionStage::Idle))
+        }
     }
 }
 
@@ -227,8 +1452,184 @@ impl Contact {
             iroh_endpoint_id: iroh_endpoint_id.to_string(),
             exchanged_at: timestamp,
             nickname: None,
+            notes: None,
+            tags: Vec::new(),
+            introduced_as: None,
+            introduced_by: None,
+            profile_name: None,
+            needs_review: false,
+            blocked: false,
+            previous_pubkeys: Vec::new(),
+            verified: false,
+            master_pubkey: None,
+            relay_url: None,
+            origin: ContactOrigin::Exchange,
+            nostr_relays: Vec::new(),
+            avatar_hash: None,
+            nip05: None,
+            exchange_method: None,
+            exchange_signature: None,
+            revoked_at: None,
+            disappear_after_secs: None,
+            node_hint_mismatch: false,
+        }
+    }
+
+    /// Switch to the key they rotated to, keeping the old one in history.
+    /// `iroh_endpoint_id` is re-derived for the new key by the caller.
+    pub fn apply_rotation(&mut self, new_pubkey: &str, iroh_endpoint_id: &str) {
+        if self.nostr_pubkey == new_pubkey {
+            return;
+        }
+
+        let old = std::mem::replace(&mut self.nostr_pubkey, new_pubkey.to_string());
+        self.previous_pubkeys.push(old);
+        self.iroh_endpoint_id = iroh_endpoint_id.to_string();
+        // The new key has a new safety number
+        self.verified = false;
+        self.revoked_at = None;
+    }
+
+    /// Their current key was revoked at `revoked_at`. The first revocation
+    /// counts; nothing done with the key since can be trusted.
+    pub fn apply_revocation(&mut self, revoked_at: u64) {
+        self.revoked_at.get_or_insert(revoked_at);
+        self.verified = false;
+    }
+
+    /// A fresh signed exchange with this contact, as `their_pubkey` now: a new
+    /// key is taken as a rotation, the endpoint is re-derived for it and the
+    /// contact counts as exchanged in person again. A confirmed short
+    /// authentication string also marks the key verified.
+    pub fn apply_reverification(
+        &mut self,
+        their_pubkey: &str,
+        iroh_endpoint_id: &str,
+        exchanged_at: u64,
+        sas_confirmed: bool,
+    ) {
+        self.apply_rotation(their_pubkey, iroh_endpoint_id);
+        self.iroh_endpoint_id = iroh_endpoint_id.to_string();
+        self.exchanged_at = exchanged_at;
+        self.needs_review = false;
+        self.origin = ContactOrigin::Exchange;
+        self.verified |= sas_confirmed;
+    }
+
+    /// Attach a context note ("Bitcoin meetup Nov 2025"), appending to any
+    /// existing notes unless it is already there. Blank notes are ignored and
+    /// long ones are truncated to `MAX_CONTEXT_NOTE_LEN` characters.
+    pub fn add_note(&mut self, note: &str) {
+        let note: String = note.trim().chars().take(MAX_CONTEXT_NOTE_LEN).collect();
+        if note.is_empty() {
+            return;
+        }
+
+        self.notes = match self.notes.take() {
+            Some(existing) if existing.lines().any(|l| l == note) => Some(existing),
+            Some(existing) => Some(format!("{}\n{}", existing, note)),
+            None => Some(note),
+        };
+    }
+
+    /// Fold another record for the same person into this one.
+    ///
+    /// Keeps our nickname if set, concatenates distinct notes, unions tags,
+    /// and takes the endpoint and profile name from whichever exchange
+    /// happened last.
+    pub fn merge_from(&mut self, other: Contact) {
+        if self.nickname.is_none() {
+            self.nickname = other.nickname;
+        }
+        if self.introduced_as.is_none() {
+            self.introduced_as = other.introduced_as;
+        }
+        if self.introduced_by.is_none() {
+            self.introduced_by = other.introduced_by;
+        }
+        if self.master_pubkey.is_none() {
+            self.master_pubkey = other.master_pubkey;
+        }
+
+        self.notes = match (self.notes.take(), other.notes) {
+            (Some(a), Some(b)) if a != b => Some(format!("{}\n{}", a, b)),
+            (a, b) => a.or(b),
+        };
+
+        self.needs_review &= other.needs_review;
+        self.blocked |= other.blocked;
+        self.disappear_after_secs =
+            crate::disappearing::sooner(self.disappear_after_secs, other.disappear_after_secs);
+        self.verified |= other.verified;
+
+        for pubkey in other.previous_pubkeys {
+            if !self.previous_pubkeys.contains(&pubkey) {
+                self.previous_pubkeys.push(pubkey);
+            }
+        }
+
+        for tag in other.tags {
+            if !self.tags.contains(&tag) {
+                self.tags.push(tag);
+            }
+        }
+
+        if other.exchanged_at > self.exchanged_at {
+            self.exchanged_at = other.exchanged_at;
+            self.iroh_endpoint_id = other.iroh_endpoint_id;
+            self.relay_url = other.relay_url;
+            self.profile_name = other.profile_name.or(self.profile_name.take());
+            // The earlier exchange is the one that created the contact
+            self.exchange_method = self.exchange_method.or(other.exchange_method);
+            self.exchange_signature = self.exchange_signature.take().or(other.exchange_signature);
+            self.avatar_hash = other.avatar_hash.or(self.avatar_hash.take());
+            self.nip05 = other.nip05.or(self.nip05.take());
+        } else {
+            self.profile_name = self.profile_name.take().or(other.profile_name);
+            self.exchange_method = other.exchange_method.or(self.exchange_method);
+            self.exchange_signature = other.exchange_signature.or(self.exchange_signature.take());
+            self.avatar_hash = self.avatar_hash.take().or(other.avatar_hash);
+            self.nip05 = self.nip05.take().or(other.nip05);
+        }
+    }
+
+    /// Take on the profile from a signed exchange message, keeping what we
+    /// knew for fields it leaves out. Returns whether anything changed.
+    pub fn apply_profile(&mut self, profile: &ExchangeProfile) -> bool {
+        let mut changed = false;
+        for (field, value) in [
+            (&mut self.profile_name, &profile.display_name),
+            (&mut self.avatar_hash, &profile.avatar_hash),
+            (&mut self.nip05, &profile.nip05),
+        ] {
+            if value.is_some() && field != value {
+                field.clone_from(value);
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// Find contacts that were stored more than once under the same pubkey
+pub fn find_duplicates(contacts: &[Contact]) -> Vec<DuplicateGroup> {
+    let mut groups: Vec<DuplicateGroup> = Vec::new();
+
+    for contact in contacts {
+        match groups
+            .iter_mut()
+            .find(|g| g.nostr_pubkey == contact.nostr_pubkey)
+        {
+            Some(group) => group.contact_ids.push(contact.id.clone()),
+            None => groups.push(DuplicateGroup {
+                nostr_pubkey: contact.nostr_pubkey.clone(),
+                contact_ids: vec![contact.id.clone()],
+            }),
         }
     }
+
+    groups.retain(|g| g.contact_ids.len() > 1);
+    groups
 }
 
 #[cfg(test)]
@@ -279,6 +1680,28 @@ mod tests {
 
         // Verify it includes our pubkey correctly
         msg.verify(Some(&our_pubkey)).unwrap();
+        msg.verify_response(&our_pubkey, TimestampTolerance::LIVE).unwrap();
+    }
+
+    #[test]
+    fn test_verify_response_requires_their_pubkey() {
+        let their_keys = Keys::generate();
+        let our_pubkey = Keys::generate().public_key().to_hex();
+
+        // An initial broadcast passes the lenient check but is not a response
+        let msg = ExchangeMessage::new_initial(&their_keys).unwrap();
+        msg.verify(Some(&our_pubkey)).unwrap();
+        assert!(matches!(
+            msg.verify_response(&our_pubkey, TimestampTolerance::LIVE),
+            Err(ExchangeError::MissingTheirPubkey)
+        ));
+
+        let other =
+            ExchangeMessage::new_response(&their_keys, &their_keys.public_key().to_hex()).unwrap();
+        assert!(matches!(
+            other.verify_response(&our_pubkey, TimestampTolerance::LIVE),
+            Err(ExchangeError::PubkeyMismatch)
+        ));
     }
 
     #[test]
@@ -297,6 +1720,36 @@ mod tests {
         assert!(matches!(result, Err(ExchangeError::PubkeyMismatch)));
     }
 
+    #[test]
+    fn test_timestamp_tolerance() {
+        let now = 1_700_000_000;
+        let live = TimestampTolerance::LIVE;
+
+        live.check(now - 60, now).unwrap();
+        live.check(now + 60, now).unwrap();
+        assert!(matches!(
+            live.check(now - 600, now),
+            Err(ExchangeError::MessageExpired { age_secs: 600 })
+        ));
+        assert!(matches!(
+            live.check(now + 600, now),
+            Err(ExchangeError::MessageFromFuture { ahead_secs: 600 })
+        ));
+
+        // A tag written last week is still fine
+        TimestampTolerance::STATIC_TAG
+            .check(now - 7 * 24 * 60 * 60, now)
+            .unwrap();
+
+        let err = ScanError::from(live.check(now + 600, now).unwrap_err());
+        assert_eq!(err.code(), "clockSkew");
+        assert_eq!(err.skew_secs(), Some(600));
+        assert_eq!(
+            ScanError::Expired { age_secs: 600 }.skew_secs(),
+            Some(-600)
+        );
+    }
+
     #[test]
     fn test_json_roundtrip() {
         let keys = Keys::generate();
@@ -309,6 +1762,648 @@ mod tests {
         assert_eq!(msg.signature, restored.signature);
     }
 
+    #[test]
+    fn test_compact_roundtrip() {
+        let keys = Keys::generate();
+        let their_pubkey = Keys::generate().public_key().to_hex();
+        let msg = ExchangeMessage::new_response(&keys, &their_pubkey).unwrap();
+
+        let compact = msg.to_compact().unwrap();
+        assert!(compact.starts_with(COMPACT_PREFIX));
+        assert!(compact.len() * 3 < msg.to_json().unwrap().len() * 2);
+
+        let restored = ExchangeMessage::decode(compact.as_bytes()).unwrap();
+        assert_eq!(restored.their_pubkey.as_deref(), Some(their_pubkey.as_str()));
+        assert_eq!(restored.signature, msg.signature);
+        restored.verify(Some(&their_pubkey)).unwrap();
+
+        let raw = ExchangeMessage::decode(&msg.to_binary().unwrap()).unwrap();
+        assert_eq!(raw.nonce, msg.nonce);
+
+        // Version 1 JSON from older apps is still accepted
+        let mut v1 = ExchangeMessage::new_initial(&keys).unwrap();
+        v1.version = 1;
+        let restored = ExchangeMessage::decode(v1.to_json().unwrap().as_bytes()).unwrap();
+        restored.verify(None).unwrap();
+    }
+
+    #[test]
+    fn test_compressed_roundtrip() {
+        let keys = Keys::generate();
+        let mut msg = ExchangeMessage::new_initial(&keys).unwrap();
+        msg.relay_url = Some("https://relay.example.com/".repeat(4));
+        msg.profile = Some(ExchangeProfile {
+            display_name: Some("Alice Alice Alice".to_string()),
+            ..Default::default()
+        });
+
+        let compressed = msg.to_compressed().unwrap();
+        assert!(compressed.len() < msg.to_compact().unwrap().len());
+        assert_eq!(msg.encode(Negotiated::OURS).unwrap(), compressed);
+        let restored = ExchangeMessage::decode(compressed.as_bytes()).unwrap();
+        assert_eq!(restored.relay_url, msg.relay_url);
+        assert_eq!(restored.profile, msg.profile);
+
+        // Nothing to gain without text: the plain compact form is kept
+        let plain = ExchangeMessage::new_initial(&keys).unwrap();
+        assert_eq!(plain.to_compressed().unwrap(), plain.to_compact().unwrap());
+
+        // A peer without the capability gets the plain form
+        let older = Negotiated {
+            capabilities: OUR_CAPABILITIES & !CAP_COMPRESSION,
+            ..Negotiated::OURS
+        };
+        assert_eq!(msg.encode(older).unwrap(), msg.to_compact().unwrap());
+    }
+
+    #[test]
+    fn test_payload_from_pasted_text() {
+        let keys = Keys::generate();
+        let compact = ExchangeMessage::new_initial(&keys)
+            .unwrap()
+            .to_compact()
+            .unwrap();
+
+        // Wrapped by a mail client, with a greeting either side
+        let data = compact.strip_prefix(COMPACT_PREFIX).unwrap();
+        let wrapped: Vec<&str> = data
+            .as_bytes()
+            .chunks(40)
+            .map(|c| std::str::from_utf8(c).unwrap())
+            .collect();
+        let email = format!(
+            "Hi, here's my code: {}{}\nThanks",
+            COMPACT_PREFIX,
+            wrapped.join("\n")
+        );
+        assert_eq!(payload_from_text(&email).unwrap(), compact);
+
+        let uri = exchange_uri(&compact).unwrap();
+        assert_eq!(
+            payload_from_text(&format!("Open {} please", uri)).unwrap(),
+            compact
+        );
+        assert!(payload_from_text("no code here").is_err());
+    }
+
+    #[test]
+    fn test_sealed_response_roundtrip() {
+        let (alice, bob) = (Keys::generate(), Keys::generate());
+        let alice_pk = alice.public_key().to_hex();
+        let response = ExchangeMessage::new_response(&bob, &alice_pk)
+            .unwrap()
+            .to_compact()
+            .unwrap();
+
+        let sealed = seal_payload(&response, &alice_pk).unwrap();
+        assert!(sealed.starts_with(SEALED_PREFIX));
+        assert!(!sealed.contains(&bob.public_key().to_hex()));
+        assert_eq!(
+            open_sealed_payload(&sealed, Some(&alice)).unwrap(),
+            response
+        );
+
+        // Only Alice can open it; plain payloads pass through
+        assert!(matches!(
+            open_sealed_payload(&sealed, Some(&Keys::generate())),
+            Err(ScanError::WrongRecipient)
+        ));
+        assert_eq!(open_sealed_payload(&response, None).unwrap(), response);
+    }
+
+    #[test]
+    fn test_exchange_uri_roundtrip() {
+        let keys = Keys::generate();
+        let msg = ExchangeMessage::new_initial(&keys).unwrap();
+        let compact = msg.to_compact().unwrap();
+
+        let uri = exchange_uri(&compact).unwrap();
+        assert!(uri.starts_with(EXCHANGE_URI_PREFIX));
+        assert_eq!(payload_from_uri(&uri).unwrap(), compact);
+
+        // What messengers do to links doesn't matter
+        let mangled = format!(
+            " {}/?utm_source=chat#top ",
+            uri.replacen(EXCHANGE_URI_PREFIX, "SneakerNet://Exchange/", 1)
+        );
+        let restored = payload_from_uri(&mangled).unwrap();
+        verify_scanned_payload(&restored, None, TimestampTolerance::LIVE).unwrap();
+
+        assert!(exchange_uri(&msg.to_json().unwrap()).is_none());
+        assert!(matches!(
+            payload_from_uri("https://example.com/exchange/abc"),
+            Err(ScanError::NotSneakernetPayload)
+        ));
+        assert!(payload_from_uri("sneakernet://exchange/not base64!").is_err());
+    }
+
+    #[test]
+    fn test_node_hints_are_signed() {
+        let keys = Keys::generate();
+        let their_pubkey = Keys::generate().public_key().to_hex();
+        let hints = NodeHints {
+            node_id: Some("node-for-them".to_string()),
+            relay_url: Some("https://relay.example.com/".to_string()),
+        };
+        let mut msg = ExchangeMessage::unsigned(
+            keys.public_key().to_hex(),
+            Some(their_pubkey.clone()),
+            hints.clone(),
+        )
+        .unwrap();
+        msg.signature = sign_content(&keys, &msg.signing_content()).unwrap();
+
+        for encoded in [msg.to_compact().unwrap(), msg.to_json().unwrap()] {
+            let restored = ExchangeMessage::decode(encoded.as_bytes()).unwrap();
+            assert_eq!(restored.node_hints(), hints);
+            restored.verify(Some(&their_pubkey)).unwrap();
+        }
+
+        // Redirecting them to another node breaks the signature
+        let mut forged = msg.clone();
+        forged.node_id = Some("attacker-node".to_string());
+        assert!(matches!(
+            forged.verify(Some(&their_pubkey)),
+            Err(ExchangeError::SignatureVerificationFailed)
+        ));
+    }
+
+    #[test]
+    fn test_static_marker_is_signed() {
+        let keys = Keys::generate();
+        let mut msg =
+            ExchangeMessage::unsigned(keys.public_key().to_hex(), None, NodeHints::default())
+                .unwrap();
+        msg.static_tag = true;
+        msg.signature = sign_content(&keys, &msg.signing_content()).unwrap();
+
+        for encoded in [msg.to_compact().unwrap(), msg.to_json().unwrap()] {
+            let restored = ExchangeMessage::decode(encoded.as_bytes()).unwrap();
+            assert!(restored.static_tag);
+            restored.verify(None).unwrap();
+        }
+
+        // Passing a live message off as long-lived breaks the signature
+        let mut live = ExchangeMessage::new_initial(&keys).unwrap();
+        live.static_tag = true;
+        assert!(matches!(
+            live.verify(None),
+            Err(ExchangeError::SignatureVerificationFailed)
+        ));
+
+        // Responses can't be static
+        let mut response =
+            ExchangeMessage::new_response(&keys, &Keys::generate().public_key().to_hex()).unwrap();
+        response.static_tag = true;
+        assert!(matches!(
+            response.verify(None),
+            Err(ExchangeError::InvalidFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_profile_is_signed() {
+        let keys = Keys::generate();
+        let mut msg =
+            ExchangeMessage::unsigned(keys.public_key().to_hex(), None, NodeHints::default())
+                .unwrap();
+        msg.profile = Some(ExchangeProfile {
+            display_name: Some("Alice: at the meetup".to_string()),
+            avatar_hash: Some("ab".repeat(32)),
+            nip05: Some("alice@example.com".to_string()),
+        });
+        msg.signature = sign_content(&keys, &msg.signing_content()).unwrap();
+
+        for encoded in [msg.to_compact().unwrap(), msg.to_json().unwrap()] {
+            let restored = ExchangeMessage::decode(encoded.as_bytes()).unwrap();
+            assert_eq!(restored.profile, msg.profile);
+            assert!(!restored.static_tag);
+            restored.verify(None).unwrap();
+        }
+
+        // A different name breaks the signature
+        let mut renamed = msg.clone();
+        renamed.profile.as_mut().unwrap().display_name = Some("Mallory".to_string());
+        assert!(matches!(
+            renamed.verify(None),
+            Err(ExchangeError::SignatureVerificationFailed)
+        ));
+
+        // Without a profile the encoding is unchanged
+        let plain = ExchangeMessage::new_initial(&keys).unwrap();
+        let fields: Value = ciborium::from_reader(plain.to_binary().unwrap().as_slice()).unwrap();
+        assert_eq!(fields.as_array().unwrap().len(), 8);
+    }
+
+    #[test]
+    fn test_session_binding() {
+        let alice = Keys::generate();
+        let bob = Keys::generate();
+        let initial = ExchangeMessage::new_initial(&alice).unwrap();
+
+        let mut response = ExchangeMessage::unsigned(
+            bob.public_key().to_hex(),
+            Some(alice.public_key().to_hex()),
+            NodeHints::default(),
+        )
+        .unwrap();
+        response.session_id = Some(session_id(&initial.nonce, &response.nonce));
+        response.signature = sign_content(&bob, &response.signing_content()).unwrap();
+
+        for encoded in [response.to_compact().unwrap(), response.to_json().unwrap()] {
+            let restored = ExchangeMessage::decode(encoded.as_bytes()).unwrap();
+            assert_eq!(restored.session_id, response.session_id);
+            restored.verify(Some(&alice.public_key().to_hex())).unwrap();
+            restored.verify_session(&[&initial.nonce]).unwrap();
+        }
+
+        // Relayed to answer someone else's initial message, it doesn't fit
+        let other = ExchangeMessage::new_initial(&alice).unwrap();
+        assert!(matches!(
+            response.verify_session(&[&other.nonce]),
+            Err(ExchangeError::SessionMismatch)
+        ));
+
+        // The session ID is signed, so it can't be dropped or swapped
+        let mut swapped = response.clone();
+        swapped.session_id = Some(session_id(&other.nonce, &response.nonce));
+        assert!(matches!(
+            swapped.verify(None),
+            Err(ExchangeError::SignatureVerificationFailed)
+        ));
+
+        // An unbound response only passes from an app that can't bind
+        let mut old = ExchangeMessage::new_response(&bob, &alice.public_key().to_hex()).unwrap();
+        assert!(old.verify_session(&[&initial.nonce]).is_err());
+        old.capabilities &= !CAP_SESSION_BINDING;
+        old.verify_session(&[&initial.nonce]).unwrap();
+    }
+
+    #[test]
+    fn test_one_tap_reply_checks() {
+        use crate::replay::NonceCache;
+
+        // The card phone's initial message, our response signed mid-tap, and
+        // the card's reply to that
+        let reader = Keys::generate();
+        let card = Keys::generate();
+        let (ours, theirs) = (reader.public_key().to_hex(), card.public_key().to_hex());
+        let initial = ExchangeMessage::new_initial(&card).unwrap();
+        let response = ExchangeMessage::new_local_response(
+            &reader,
+            &theirs,
+            Some(&initial.nonce),
+            NodeHints::default(),
+            None,
+            initial.negotiate(),
+        )
+        .unwrap();
+        response.verify(Some(&theirs)).unwrap();
+        response.verify_session(&[&initial.nonce]).unwrap();
+
+        let reply = |answering: &str| {
+            ExchangeMessage::new_local_response(
+                &card,
+                &ours,
+                Some(answering),
+                NodeHints::default(),
+                None,
+                Negotiated::OURS,
+            )
+            .unwrap()
+        };
+        let mut cache = NonceCache::default();
+        let genuine = reply(&response.nonce);
+        genuine.verify_session(&[&response.nonce]).unwrap();
+        cache.check(&theirs, &genuine.nonce, Some(&initial.nonce)).unwrap();
+        let (sent_at, live) = (genuine.timestamp, TimestampTolerance::LIVE);
+        cache.insert(&theirs, &genuine.nonce, sent_at, live, sent_at);
+
+        // A reply relayed from the card's exchange with someone else
+        let relayed = reply(&initial.nonce);
+        assert!(matches!(
+            relayed.verify_session(&[&response.nonce]),
+            Err(ExchangeError::SessionMismatch)
+        ));
+
+        // The same reply played back in a later tap
+        assert!(matches!(
+            cache.check(&theirs, &genuine.nonce, Some(&initial.nonce)),
+            Err(ScanError::Replayed)
+        ));
+    }
+
+    #[test]
+    fn test_version_negotiation() {
+        // A version 1 app's message: no range, no capabilities
+        let v1_keys = Keys::generate();
+        let mut v1 = ExchangeMessage::new_initial(&v1_keys).unwrap();
+        v1.version = 1;
+        v1.min_version = None;
+        v1.capabilities = 0;
+        let json = serde_json::to_value(&v1).unwrap();
+        assert!(json.get("minVersion").is_none() && json.get("capabilities").is_none());
+
+        // We answer in version 1 JSON, which it can read
+        let negotiated = v1.negotiate();
+        assert_eq!(negotiated, Negotiated::V1);
+        let bob = Keys::generate();
+        let mut response = ExchangeMessage::new_response(&bob, &v1.pubkey).unwrap();
+        response.offer(negotiated);
+        response.signature = sign_content(&bob, &response.signing_content()).unwrap();
+        let payload = response.downgraded(negotiated).encode(negotiated).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(parsed["version"], 1);
+        assert!(parsed.get("minVersion").is_none() && parsed.get("capabilities").is_none());
+        ExchangeMessage::from_json(&payload)
+            .unwrap()
+            .verify(Some(&v1.pubkey))
+            .unwrap();
+
+        // A newer app that can still speak our version
+        let newer_keys = Keys::generate();
+        let mut newer = ExchangeMessage::new_initial(&newer_keys).unwrap();
+        newer.version = PROTOCOL_VERSION + 1;
+        newer.capabilities |= 1 << 10;
+        newer.signature = sign_content(&newer_keys, &newer.signing_content()).unwrap();
+        newer.verify(None).unwrap();
+        assert_eq!(newer.negotiate(), Negotiated::OURS);
+
+        // One that can't
+        newer.min_version = Some(PROTOCOL_VERSION + 1);
+        assert!(matches!(
+            newer.verify(None),
+            Err(ExchangeError::VersionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_capabilities_are_signed() {
+        let keys = Keys::generate();
+        let msg = ExchangeMessage::new_initial(&keys).unwrap();
+        msg.verify(None).unwrap();
+
+        // Clearing a flag on the way would turn off the protection it asks for
+        for flag in [CAP_SESSION_BINDING, CAP_SEALED_RESPONSE] {
+            let mut stripped = msg.clone();
+            stripped.capabilities &= !flag;
+            assert!(matches!(
+                stripped.verify(None),
+                Err(ExchangeError::SignatureVerificationFailed)
+            ));
+        }
+
+        let mut raised = msg.clone();
+        raised.min_version = Some(PROTOCOL_VERSION);
+        assert!(matches!(
+            raised.verify(None),
+            Err(ExchangeError::SignatureVerificationFailed)
+        ));
+
+        // Also through the compact form, which always carries a minimum
+        let compact = ExchangeMessage::decode(msg.to_compact().unwrap().as_bytes()).unwrap();
+        compact.verify(None).unwrap();
+    }
+
+    /// A remote signer's stand-in: signs events only
+    struct EventOnlySigner(Keys);
+
+    impl Signer for EventOnlySigner {
+        fn public_key_hex(&self) -> String {
+            self.0.public_key().to_hex()
+        }
+
+        fn sign_event<'a>(
+            &'a self,
+            event: &'a crate::keys::EventTemplate,
+        ) -> crate::keys::SignerFuture<'a, crate::keys::SignedEvent> {
+            Box::pin(async move { Ok(crate::keys::sign_event(&self.0, event)) })
+        }
+    }
+
+    #[test]
+    fn test_signature_scheme_is_signed() {
+        let signer = EventOnlySigner(Keys::generate());
+        let msg = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(ExchangeMessage::new_signed(
+                &signer,
+                None,
+                NodeHints::default(),
+                None,
+                Negotiated::OURS,
+            ))
+            .unwrap();
+        assert_eq!(msg.sig_scheme, SigScheme::Nip01);
+        msg.verify(None).unwrap();
+        let compact = ExchangeMessage::decode(msg.to_compact().unwrap().as_bytes()).unwrap();
+        compact.verify(None).unwrap();
+
+        // Claiming the other scheme changes what was signed
+        let mut relabelled = msg.clone();
+        relabelled.sig_scheme = SigScheme::Digest;
+        assert!(matches!(
+            relabelled.verify(None),
+            Err(ExchangeError::SignatureVerificationFailed)
+        ));
+
+        // A digest-signed message isn't accepted as a content event either
+        let mut plain = ExchangeMessage::new_initial(&signer.0).unwrap();
+        plain.sig_scheme = SigScheme::Nip01;
+        assert!(plain.verify(None).is_err());
+    }
+
+    #[test]
+    fn test_device_certificate_in_message() {
+        let (_, master) = crate::keys::generate_keypair().unwrap();
+        let (cert, device) = DeviceCertificate::issue(&master, 1, "Tablet", 0).unwrap();
+        let device_keys = crate::keys::restore_keys(&device).unwrap();
+
+        let msg = ExchangeMessage::new_initial(&device_keys)
+            .unwrap()
+            .with_device_cert(Some(cert.clone()));
+        let msg = ExchangeMessage::decode(msg.to_compact().unwrap().as_bytes()).unwrap();
+        msg.verify(None).unwrap();
+        assert_eq!(msg.identity_pubkey(), master.public_key_hex);
+
+        // A certificate for another device's key
+        let other = ExchangeMessage::new_initial(&Keys::generate())
+            .unwrap()
+            .with_device_cert(Some(cert));
+        assert!(matches!(
+            other.verify(None),
+            Err(ExchangeError::InvalidDeviceCertificate(_))
+        ));
+    }
+
+    #[test]
+    fn test_scan_errors() {
+        let our_keys = Keys::generate();
+        let our_pubkey = our_keys.public_key().to_hex();
+
+        // Not JSON at all
+        let result = verify_scanned_payload(
+            "https://example.com",
+            Some(&our_pubkey),
+            TimestampTolerance::LIVE,
+        );
+        assert_eq!(result.unwrap_err(), ScanError::NotSneakernetPayload);
+
+        // Our own code
+        let own = ExchangeMessage::new_initial(&our_keys).unwrap().to_json().unwrap();
+        let result = verify_scanned_payload(&own, Some(&our_pubkey), TimestampTolerance::LIVE);
+        assert_eq!(result.unwrap_err(), ScanError::SelfScan);
+
+        // Tampered signature
+        let mut msg = ExchangeMessage::new_initial(&Keys::generate()).unwrap();
+        msg.nonce = "00".repeat(16);
+        let result = verify_scanned_payload(&msg.to_json().unwrap(), Some(&our_pubkey), TimestampTolerance::LIVE);
+        assert_eq!(result.unwrap_err(), ScanError::BadSignature);
+
+        // A valid code from someone else
+        let theirs = ExchangeMessage::new_initial(&Keys::generate()).unwrap().to_json().unwrap();
+        assert!(verify_scanned_payload(&theirs, Some(&our_pubkey), TimestampTolerance::LIVE).is_ok());
+    }
+
+    #[test]
+    fn test_session_transitions() {
+        let alice = Keys::generate().public_key().to_hex();
+        let bob = Keys::generate().public_key().to_hex();
+
+        // Broadcaster: broadcast, receive their response, complete
+        let mut session = ExchangeSession::default();
+        session.broadcast(Transport::Nfc, 100);
+        session.receive(&alice, Transport::Nfc, 110);
+        assert_eq!(session.stage, SessionStage::Received);
+        assert_eq!(session.started_at, 100);
+        assert!(matches!(
+            session.complete(&bob, 120),
+            Err(ExchangeError::InvalidTransition { .. })
+        ));
+        session.complete(&alice, 120).unwrap();
+        assert_eq!(session.stage, SessionStage::Complete);
+
+        // Receiver: receive, respond (twice on a retried write), complete
+        let mut session = ExchangeSession::default();
+        assert!(session.respond(&alice, 100).is_err());
+        session.receive(&alice, Transport::Qr, 100);
+        session.respond(&alice, 105).unwrap();
+        session.respond(&alice, 106).unwrap();
+        session.receive(&alice, Transport::Qr, 107);
+        assert_eq!(session.stage, SessionStage::Responded);
+
+        // Someone new takes over the foreground
+        session.receive(&bob, Transport::Qr, 108);
+        assert_eq!(session.their_pubkey.as_deref(), Some(bob.as_str()));
+        assert_eq!(session.started_at, 108);
+    }
+
+    #[test]
+    fn test_session_resume() {
+        let mut session = ExchangeSession::default();
+        session.receive("alice", Transport::Nfc, 1_000);
+
+        let json = serde_json::to_string(&session).unwrap();
+        let restored: ExchangeSession = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.clone().resumed(1_100), session);
+
+        let late = 1_000 + PENDING_EXCHANGE_TTL_SECS + 1;
+        assert_eq!(restored.resumed(late), ExchangeSession::default());
+
+        session.complete("alice", 1_010).unwrap();
+        assert!(!session.is_expired(late));
+        assert!(session.cancel("alice").is_err());
+    }
+
+    #[test]
+    fn test_merge_contacts() {
+        let mut keep = Contact::new("abcd1234", "old-endpoint");
+        keep.tags = vec!["meetup".to_string()];
+        keep.notes = Some("Met at the bar".to_string());
+
+        let mut dup = Contact::new("abcd1234", "new-endpoint");
+        dup.exchanged_at = keep.exchanged_at + 10;
+        dup.nickname = Some("Alice".to_string());
+        dup.tags = vec!["meetup".to_string(), "bitcoin".to_string()];
+        dup.notes = Some("Runs a node".to_string());
+        dup.exchange_method = Some(Transport::Qr);
+        keep.exchange_method = Some(Transport::Nfc);
+
+        assert_eq!(find_duplicates(&[keep.clone(), dup.clone()]).len(), 1);
+
+        keep.merge_from(dup);
+        assert_eq!(keep.nickname.as_deref(), Some("Alice"));
+        assert_eq!(keep.tags, vec!["meetup", "bitcoin"]);
+        assert_eq!(keep.notes.as_deref(), Some("Met at the bar\nRuns a node"));
+        assert_eq!(keep.iroh_endpoint_id, "new-endpoint");
+        assert_eq!(keep.exchange_method, Some(Transport::Nfc));
+    }
+
+    #[test]
+    fn test_apply_rotation() {
+        let mut contact = Contact::new("old-key", "old-endpoint");
+        contact.verified = true;
+        contact.apply_rotation("new-key", "new-endpoint");
+        contact.apply_rotation("new-key", "ignored");
+
+        assert_eq!(contact.nostr_pubkey, "new-key");
+        assert_eq!(contact.iroh_endpoint_id, "new-endpoint");
+        assert_eq!(contact.previous_pubkeys, vec!["old-key"]);
+        assert!(!contact.verified);
+    }
+
+    #[test]
+    fn test_apply_revocation() {
+        let mut contact = Contact::new("old-key", "old-endpoint");
+        contact.verified = true;
+        contact.apply_revocation(1_000);
+        contact.apply_revocation(2_000);
+        assert_eq!(contact.revoked_at, Some(1_000));
+        assert!(!contact.verified);
+
+        // Meeting again on a new key clears it
+        contact.apply_rotation("new-key", "new-endpoint");
+        assert_eq!(contact.revoked_at, None);
+    }
+
+    #[test]
+    fn test_apply_reverification() {
+        let mut contact = Contact::new("old-key", "old-endpoint");
+        contact.needs_review = true;
+        contact.origin = ContactOrigin::Nip19;
+
+        contact.apply_reverification("old-key", "new-endpoint", 2_000, false);
+        assert_eq!(contact.nostr_pubkey, "old-key");
+        assert_eq!(contact.iroh_endpoint_id, "new-endpoint");
+        assert_eq!(contact.exchanged_at, 2_000);
+        assert!(!contact.needs_review);
+        assert_eq!(contact.origin, ContactOrigin::Exchange);
+        assert!(!contact.verified);
+
+        // They rotated since: the old key is kept and the new one verified
+        contact.apply_reverification("new-key", "rotated-endpoint", 3_000, true);
+        assert_eq!(contact.nostr_pubkey, "new-key");
+        assert_eq!(contact.previous_pubkeys, vec!["old-key"]);
+        assert_eq!(contact.iroh_endpoint_id, "rotated-endpoint");
+        assert!(contact.verified);
+    }
+
+    #[test]
+    fn test_add_note() {
+        let mut contact = Contact::new("abcd1234", "endpoint-id-here");
+        contact.add_note("   ");
+        assert!(contact.notes.is_none());
+
+        contact.add_note(" Bitcoin meetup Nov 2025 ");
+        contact.add_note("Bitcoin meetup Nov 2025");
+        assert_eq!(contact.notes.as_deref(), Some("Bitcoin meetup Nov 2025"));
+
+        contact.add_note(&"x".repeat(MAX_CONTEXT_NOTE_LEN + 10));
+        let notes = contact.notes.unwrap();
+        assert_eq!(notes.lines().nth(1).unwrap().len(), MAX_CONTEXT_NOTE_LEN);
+    }
+
     #[test]
     fn test_contact_creation() {
         let contact = Contact::new("abcd1234", "endpoint-id-here");
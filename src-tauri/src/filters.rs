@@ -0,0 +1,135 @@
+//! Pluggable content filters for incoming messages
+//!
+//! Filters run in registration order inside `ChatManager` after a message is
+//! parsed and before it is stored or surfaced. Each one can let the message
+//! through, mark it with a label the UI can show, hold it in quarantine for
+//! the user to release, or drop it outright. The first quarantine or drop wins.
+
+use crate::chat::ChatMessage;
+use crate::moderation::FilterReason;
+use crate::settings::FilterSettings;
+
+/// What a filter decided about a message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FilterVerdict {
+    Accept,
+    /// Keep the message but attach a label
+    Mark(String),
+    /// Hold the message until the user releases or discards it
+    Quarantine(FilterReason),
+    /// Discard the message
+    Drop(FilterReason),
+}
+
+/// A stage in the incoming message pipeline
+pub trait MessageFilter: Send + Sync {
+    /// Short identifier, used in logs and labels
+    fn name(&self) -> &str;
+
+    fn check(&self, message: &ChatMessage) -> FilterVerdict;
+}
+
+/// Drops messages containing any of a list of keywords (case-insensitive)
+pub struct KeywordFilter {
+    keywords: Vec<String>,
+}
+
+impl KeywordFilter {
+    pub fn new(keywords: &[String]) -> Self {
+        Self {
+            keywords: keywords
+                .iter()
+                .map(|k| k.trim().to_lowercase())
+                .filter(|k| !k.is_empty())
+                .collect(),
+        }
+    }
+}
+
+impl MessageFilter for KeywordFilter {
+    fn name(&self) -> &str {
+        "keyword"
+    }
+
+    fn check(&self, message: &ChatMessage) -> FilterVerdict {
+        let content = message.content.to_lowercase();
+        match self.keywords.iter().find(|k| content.contains(k.as_str())) {
+            Some(keyword) => FilterVerdict::Drop(FilterReason::Keyword {
+                keyword: keyword.clone(),
+            }),
+            None => FilterVerdict::Accept,
+        }
+    }
+}
+
+/// Quarantines messages longer than a limit
+pub struct SizeFilter {
+    max_chars: usize,
+}
+
+impl SizeFilter {
+    pub fn new(max_chars: usize) -> Self {
+        Self { max_chars }
+    }
+}
+
+impl MessageFilter for SizeFilter {
+    fn name(&self) -> &str {
+        "size"
+    }
+
+    fn check(&self, message: &ChatMessage) -> FilterVerdict {
+        if message.content.chars().count() > self.max_chars {
+            FilterVerdict::Quarantine(FilterReason::TooLong {
+                max_chars: self.max_chars,
+            })
+        } else {
+            FilterVerdict::Accept
+        }
+    }
+}
+
+/// The filters configured in settings
+pub fn default_filters(settings: &FilterSettings) -> Vec<Box<dyn MessageFilter>> {
+    let mut filters: Vec<Box<dyn MessageFilter>> = Vec::new();
+
+    if !settings.blocked_keywords.is_empty() {
+        filters.push(Box::new(KeywordFilter::new(&settings.blocked_keywords)));
+    }
+    if let Some(max_chars) = settings.max_message_chars {
+        filters.push(Box::new(SizeFilter::new(max_chars as usize)));
+    }
+
+    filters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyword_filter() {
+        let filter = KeywordFilter::new(&["Airdrop".to_string(), " ".to_string()]);
+
+        let spam = ChatMessage::new_outgoing("Free AIRDROP today", "them");
+        assert!(matches!(filter.check(&spam), FilterVerdict::Drop(_)));
+
+        let fine = ChatMessage::new_outgoing("See you at 3", "them");
+        assert_eq!(filter.check(&fine), FilterVerdict::Accept);
+    }
+
+    #[test]
+    fn test_size_filter() {
+        let filter = SizeFilter::new(5);
+
+        let long = ChatMessage::new_outgoing("too long", "them");
+        assert_eq!(
+            filter.check(&long),
+            FilterVerdict::Quarantine(FilterReason::TooLong { max_chars: 5 })
+        );
+        assert_eq!(
+            filter.check(&ChatMessage::new_outgoing("ok", "them")),
+            FilterVerdict::Accept
+        );
+    }
+}
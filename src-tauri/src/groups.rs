@@ -0,0 +1,622 @@
+//! Group rosters built from signed membership-change records
+//!
+//! A group's roster is never sent directly. Every add/remove/leave is a
+//! `MembershipChange` signed by its author, and each device replays the full
+//! set of changes in a deterministic order (timestamp, then record id). Any two
+//! members holding the same records therefore compute the same roster, no
+//! matter what order the records arrived in.
+//!
+//! Invites are shown as a QR code (or served over NFC) and carry a standard
+//! signed `ExchangeMessage` from the inviter, so accepting one goes through the
+//! same verification as a contact exchange. Once the inviter admits the
+//! invitee, the group key is delivered NIP-44 encrypted to them.
+
+use crate::exchange::ExchangeMessage;
+use crate::keys::{sign_content, verify_content};
+use nostr::nips::nip44;
+use nostr::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum GroupError {
+    #[error("Group not found")]
+    UnknownGroup,
+    #[error("Only admins can do that")]
+    NotAdmin,
+    #[error("Not a member of this group")]
+    NotMember,
+    #[error("Already a member of this group")]
+    AlreadyMember,
+    #[error("The group creator can't be removed")]
+    CannotRemoveCreator,
+    #[error("Membership record signature is invalid")]
+    InvalidSignature,
+    #[error("Membership record is for a different group")]
+    WrongGroup,
+    #[error("Signing error: {0}")]
+    SigningError(String),
+    #[error("Invalid invite: {0}")]
+    InvalidInvite(String),
+    #[error("Group key unavailable")]
+    MissingKey,
+    #[error("Encryption error: {0}")]
+    EncryptionError(String),
+}
+
+/// Message type of a group invite payload
+pub const INVITE_TYPE: &str = "sneakernet-group-invite";
+/// Message type of an invite acceptance payload
+pub const ACCEPTANCE_TYPE: &str = "sneakernet-group-accept";
+
+/// What a membership change does
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "action")]
+pub enum MembershipAction {
+    Add { member: String, admin: bool },
+    Remove { member: String },
+    Leave,
+}
+
+/// Signed record of a single roster change
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct MembershipChange {
+    pub id: String,
+    pub group_id: String,
+    #[serde(flatten)]
+    pub action: MembershipAction,
+    /// Pubkey (hex) of whoever made the change
+    pub author: String,
+    pub timestamp: u64,
+    pub signature: String,
+}
+
+impl MembershipChange {
+    pub fn new(keys: &Keys, group_id: &str, action: MembershipAction) -> Result<Self, GroupError> {
+        let mut change = Self {
+            id: Uuid::new_v4().to_string(),
+            group_id: group_id.to_string(),
+            action,
+            author: keys.public_key().to_hex(),
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            signature: String::new(),
+        };
+
+        change.signature = sign_content(keys, &change.signing_content())
+            .map_err(|e| GroupError::SigningError(e.to_string()))?;
+
+        Ok(change)
+    }
+
+    fn signing_content(&self) -> String {
+        let action = match &self.action {
+            MembershipAction::Add { member, admin } => format!("add:{}:{}", member, admin),
+            MembershipAction::Remove { member } => format!("remove:{}", member),
+            MembershipAction::Leave => "leave".to_string(),
+        };
+
+        format!(
+            "sneakernet-group:{}:{}:{}:{}:{}",
+            self.group_id, self.id, action, self.author, self.timestamp
+        )
+    }
+
+    /// Check the author's signature
+    pub fn verify(&self) -> Result<(), GroupError> {
+        verify_content(&self.author, &self.signing_content(), &self.signature)
+            .map_err(|_| GroupError::InvalidSignature)
+    }
+}
+
+/// A member on the roster
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupMember {
+    pub pubkey: String,
+    pub admin: bool,
+}
+
+/// A group and the membership log its roster is derived from
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Group {
+    pub id: String,
+    pub name: String,
+    /// Pubkey (hex) of the creator, always an admin
+    pub creator: String,
+    pub created_at: u64,
+    pub members: Vec<GroupMember>,
+    pub changes: Vec<MembershipChange>,
+    /// Shared group key (hex), None until it has been delivered to us
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+/// Public description of a group, carried in invites
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupDescriptor {
+    pub id: String,
+    pub name: String,
+    pub creator: String,
+}
+
+impl Group {
+    /// Create a new group with ourselves as the only (admin) member
+    pub fn new(keys: &Keys, name: &str) -> Self {
+        let creator = keys.public_key().to_hex();
+
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            members: vec![GroupMember {
+                pubkey: creator.clone(),
+                admin: true,
+            }],
+            creator,
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            changes: Vec::new(),
+            key: Some(random_key_hex()),
+        }
+    }
+
+    /// Build our copy of a group from a verified key delivery
+    fn from_delivery(descriptor: GroupDescriptor, changes: Vec<MembershipChange>, key: String) -> Self {
+        let mut group = Self {
+            id: descriptor.id,
+            name: descriptor.name,
+            creator: descriptor.creator,
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            members: Vec::new(),
+            changes: Vec::new(),
+            key: Some(key),
+        };
+
+        group.changes = changes
+            .into_iter()
+            .filter(|c| c.group_id == group.id && c.verify().is_ok())
+            .collect();
+        group.rebuild_roster();
+
+        group
+    }
+
+    pub fn descriptor(&self) -> GroupDescriptor {
+        GroupDescriptor {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            creator: self.creator.clone(),
+        }
+    }
+
+    /// Copy without the group key, for handing to the frontend
+    pub fn redacted(&self) -> Self {
+        Self {
+            key: None,
+            ..self.clone()
+        }
+    }
+
+    pub fn is_member(&self, pubkey: &str) -> bool {
+        self.members.iter().any(|m| m.pubkey == pubkey)
+    }
+
+    pub fn is_admin(&self, pubkey: &str) -> bool {
+        pubkey == self.creator || self.members.iter().any(|m| m.pubkey == pubkey && m.admin)
+    }
+
+    /// Verify a change and add it to the log, then recompute the roster
+    pub fn apply(&mut self, change: MembershipChange) -> Result<(), GroupError> {
+        if change.group_id != self.id {
+            return Err(GroupError::WrongGroup);
+        }
+        change.verify()?;
+
+        // Already have it (records are gossiped and may arrive more than once)
+        if self.changes.iter().any(|c| c.id == change.id) {
+            return Ok(());
+        }
+
+        Self::check_authority(&self.creator, &self.members, &change)?;
+
+        self.changes.push(change);
+        self.rebuild_roster();
+
+        Ok(())
+    }
+
+    /// Whether `change` is allowed against `members`
+    fn check_authority(
+        creator: &str,
+        members: &[GroupMember],
+        change: &MembershipChange,
+    ) -> Result<(), GroupError> {
+        let is_member = |pk: &str| members.iter().any(|m| m.pubkey == pk);
+        let is_admin =
+            |pk: &str| pk == creator || members.iter().any(|m| m.pubkey == pk && m.admin);
+
+        match &change.action {
+            MembershipAction::Add { member, .. } => {
+                if !is_admin(change.author.as_str()) {
+                    return Err(GroupError::NotAdmin);
+                }
+                if is_member(member.as_str()) {
+                    return Err(GroupError::AlreadyMember);
+                }
+            }
+            MembershipAction::Remove { member } => {
+                if !is_admin(change.author.as_str()) {
+                    return Err(GroupError::NotAdmin);
+                }
+                if member == creator {
+                    return Err(GroupError::CannotRemoveCreator);
+                }
+                if !is_member(member.as_str()) {
+                    return Err(GroupError::NotMember);
+                }
+            }
+            MembershipAction::Leave => {
+                if !is_member(change.author.as_str()) {
+                    return Err(GroupError::NotMember);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replay the membership log in canonical order
+    fn rebuild_roster(&mut self) {
+        self.changes
+            .sort_by(|a, b| (a.timestamp, &a.id).cmp(&(b.timestamp, &b.id)));
+
+        let mut members = vec![GroupMember {
+            pubkey: self.creator.clone(),
+            admin: true,
+        }];
+
+        for change in &self.changes {
+            // Changes that were valid when received but not in canonical order are skipped
+            if Self::check_authority(&self.creator, &members, change).is_err() {
+                continue;
+            }
+
+            match &change.action {
+                MembershipAction::Add { member, admin } => members.push(GroupMember {
+                    pubkey: member.clone(),
+                    admin: *admin,
+                }),
+                MembershipAction::Remove { member } => members.retain(|m| &m.pubkey != member),
+                MembershipAction::Leave => members.retain(|m| m.pubkey != change.author),
+            }
+        }
+
+        self.members = members;
+    }
+}
+
+/// Generate a random 32-byte group key
+fn random_key_hex() -> String {
+    let mut key = [0u8; 32];
+    getrandom::getrandom(&mut key).expect("OS randomness unavailable");
+    hex::encode(key)
+}
+
+/// Signed invitation to join a group
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupInvite {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub invite_id: String,
+    pub group: GroupDescriptor,
+    /// Standard exchange message proving the inviter holds their key
+    pub inviter: ExchangeMessage,
+    /// Inviter's signature binding the group to the exchange message
+    pub signature: String,
+}
+
+impl GroupInvite {
+    pub fn new(keys: &Keys, group: &Group) -> Result<Self, GroupError> {
+        let inviter = ExchangeMessage::new_initial(keys)
+            .map_err(|e| GroupError::SigningError(e.to_string()))?;
+
+        let mut invite = Self {
+            msg_type: INVITE_TYPE.to_string(),
+            invite_id: Uuid::new_v4().to_string(),
+            group: group.descriptor(),
+            inviter,
+            signature: String::new(),
+        };
+        invite.signature = sign_content(keys, &invite.signing_content())
+            .map_err(|e| GroupError::SigningError(e.to_string()))?;
+
+        Ok(invite)
+    }
+
+    fn signing_content(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{}:{}",
+            INVITE_TYPE,
+            self.invite_id,
+            self.group.id,
+            self.group.name,
+            self.group.creator,
+            self.inviter.nonce
+        )
+    }
+
+    /// Verify the inviter's exchange message and the binding signature
+    pub fn verify(&self) -> Result<(), GroupError> {
+        if self.msg_type != INVITE_TYPE {
+            return Err(GroupError::InvalidInvite("not a group invite".to_string()));
+        }
+
+        self.inviter
+            .verify(None)
+            .map_err(|e| GroupError::InvalidInvite(e.to_string()))?;
+
+        verify_content(&self.inviter.pubkey, &self.signing_content(), &self.signature)
+            .map_err(|_| GroupError::InvalidSignature)
+    }
+
+    pub fn to_json(&self) -> Result<String, GroupError> {
+        serde_json::to_string(self).map_err(|e| GroupError::InvalidInvite(e.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, GroupError> {
+        serde_json::from_str(json).map_err(|e| GroupError::InvalidInvite(e.to_string()))
+    }
+}
+
+/// The invitee's answer, shown back to the inviter
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteAcceptance {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub invite_id: String,
+    pub group_id: String,
+    /// Exchange response from the invitee, naming the inviter
+    pub response: ExchangeMessage,
+    pub signature: String,
+}
+
+impl InviteAcceptance {
+    /// Accept a verified invite
+    pub fn new(keys: &Keys, invite: &GroupInvite) -> Result<Self, GroupError> {
+        let response = ExchangeMessage::new_response(keys, &invite.inviter.pubkey)
+            .map_err(|e| GroupError::SigningError(e.to_string()))?;
+
+        let mut acceptance = Self {
+            msg_type: ACCEPTANCE_TYPE.to_string(),
+            invite_id: invite.invite_id.clone(),
+            group_id: invite.group.id.clone(),
+            response,
+            signature: String::new(),
+        };
+        acceptance.signature = sign_content(keys, &acceptance.signing_content())
+            .map_err(|e| GroupError::SigningError(e.to_string()))?;
+
+        Ok(acceptance)
+    }
+
+    fn signing_content(&self) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            ACCEPTANCE_TYPE, self.invite_id, self.group_id, self.response.nonce
+        )
+    }
+
+    /// Verify the response was made for us (the inviter) and binds this group
+    pub fn verify(&self, inviter_pubkey: &str) -> Result<(), GroupError> {
+        if self.msg_type != ACCEPTANCE_TYPE {
+            return Err(GroupError::InvalidInvite("not an invite acceptance".to_string()));
+        }
+
+        if self.response.their_pubkey.as_deref() != Some(inviter_pubkey) {
+            return Err(GroupError::InvalidInvite("acceptance is for someone else".to_string()));
+        }
+
+        self.response
+            .verify(Some(inviter_pubkey))
+            .map_err(|e| GroupError::InvalidInvite(e.to_string()))?;
+
+        verify_content(&self.response.pubkey, &self.signing_content(), &self.signature)
+            .map_err(|_| GroupError::InvalidSignature)
+    }
+
+    /// Pubkey (hex) of the invitee
+    pub fn invitee(&self) -> &str {
+        &self.response.pubkey
+    }
+}
+
+/// Group key and membership log, encrypted to a newly admitted member
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupKeyDelivery {
+    pub group: GroupDescriptor,
+    pub changes: Vec<MembershipChange>,
+    pub sender: String,
+    pub recipient: String,
+    /// NIP-44 ciphertext of the hex group key
+    pub encrypted_key: String,
+}
+
+impl GroupKeyDelivery {
+    pub fn new(keys: &Keys, group: &Group, recipient: &str) -> Result<Self, GroupError> {
+        let key = group.key.as_ref().ok_or(GroupError::MissingKey)?;
+        let recipient_pk = PublicKey::from_hex(recipient)
+            .map_err(|e| GroupError::EncryptionError(e.to_string()))?;
+
+        let encrypted_key = nip44::encrypt(keys.secret_key(), &recipient_pk, key, nip44::Version::V2)
+            .map_err(|e| GroupError::EncryptionError(e.to_string()))?;
+
+        Ok(Self {
+            group: group.descriptor(),
+            changes: group.changes.clone(),
+            sender: keys.public_key().to_hex(),
+            recipient: recipient.to_string(),
+            encrypted_key,
+        })
+    }
+
+    /// Decrypt the key and build our copy of the group
+    pub fn open(self, keys: &Keys) -> Result<Group, GroupError> {
+        let sender_pk = PublicKey::from_hex(&self.sender)
+            .map_err(|e| GroupError::EncryptionError(e.to_string()))?;
+
+        let key = nip44::decrypt(keys.secret_key(), &sender_pk, &self.encrypted_key)
+            .map_err(|e| GroupError::EncryptionError(e.to_string()))?;
+
+        let group = Group::from_delivery(self.group, self.changes, key);
+
+        // Only trust the delivery if the sender is an admin and we were admitted
+        if !group.is_admin(&self.sender) {
+            return Err(GroupError::NotAdmin);
+        }
+        if !group.is_member(&keys.public_key().to_hex()) {
+            return Err(GroupError::NotMember);
+        }
+
+        Ok(group)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_and_remove_member() {
+        let creator = Keys::generate();
+        let alice = Keys::generate();
+        let alice_pk = alice.public_key().to_hex();
+        let mut group = Group::new(&creator, "Meetup");
+
+        let add = MembershipChange::new(
+            &creator,
+            &group.id,
+            MembershipAction::Add {
+                member: alice_pk.clone(),
+                admin: false,
+            },
+        )
+        .unwrap();
+        group.apply(add).unwrap();
+        assert!(group.is_member(&alice_pk));
+
+        // Non-admins can't remove others
+        let bob_pk = Keys::generate().public_key().to_hex();
+        let remove_other = MembershipChange::new(
+            &alice,
+            &group.id,
+            MembershipAction::Remove { member: bob_pk },
+        )
+        .unwrap();
+        assert!(matches!(group.apply(remove_other), Err(GroupError::NotAdmin)));
+
+        // Members can leave
+        let leave = MembershipChange::new(&alice, &group.id, MembershipAction::Leave).unwrap();
+        group.apply(leave).unwrap();
+        assert!(!group.is_member(&alice_pk));
+    }
+
+    #[test]
+    fn test_rosters_converge() {
+        let creator = Keys::generate();
+        let mut group_a = Group::new(&creator, "Meetup");
+        let mut group_b = group_a.clone();
+
+        let changes: Vec<_> = (0..3)
+            .map(|_| {
+                MembershipChange::new(
+                    &creator,
+                    &group_a.id,
+                    MembershipAction::Add {
+                        member: Keys::generate().public_key().to_hex(),
+                        admin: false,
+                    },
+                )
+                .unwrap()
+            })
+            .collect();
+
+        for change in changes.iter().cloned() {
+            group_a.apply(change).unwrap();
+        }
+        for change in changes.into_iter().rev() {
+            group_b.apply(change).unwrap();
+        }
+
+        assert_eq!(group_a.members, group_b.members);
+    }
+
+    #[test]
+    fn test_invite_flow() {
+        let creator = Keys::generate();
+        let invitee = Keys::generate();
+        let invitee_pk = invitee.public_key().to_hex();
+        let mut group = Group::new(&creator, "Meetup");
+
+        // Inviter shows the invite, invitee verifies and accepts
+        let invite = GroupInvite::from_json(&GroupInvite::new(&creator, &group).unwrap().to_json().unwrap()).unwrap();
+        invite.verify().unwrap();
+        let acceptance = InviteAcceptance::new(&invitee, &invite).unwrap();
+
+        // Inviter verifies the acceptance, admits, and delivers the key
+        let creator_pk = creator.public_key().to_hex();
+        acceptance.verify(&creator_pk).unwrap();
+        let add = MembershipChange::new(
+            &creator,
+            &group.id,
+            MembershipAction::Add {
+                member: acceptance.invitee().to_string(),
+                admin: false,
+            },
+        )
+        .unwrap();
+        group.apply(add).unwrap();
+
+        let delivery = GroupKeyDelivery::new(&creator, &group, &invitee_pk).unwrap();
+        let joined = delivery.open(&invitee).unwrap();
+
+        assert_eq!(joined.key, group.key);
+        assert_eq!(joined.members, group.members);
+        assert!(joined.is_member(&invitee_pk));
+    }
+
+    #[test]
+    fn test_rejects_tampered_change() {
+        let creator = Keys::generate();
+        let mut group = Group::new(&creator, "Meetup");
+
+        let mut change = MembershipChange::new(
+            &creator,
+            &group.id,
+            MembershipAction::Add {
+                member: Keys::generate().public_key().to_hex(),
+                admin: false,
+            },
+        )
+        .unwrap();
+        change.action = MembershipAction::Add {
+            member: Keys::generate().public_key().to_hex(),
+            admin: true,
+        };
+
+        assert!(matches!(group.apply(change), Err(GroupError::InvalidSignature)));
+    }
+}
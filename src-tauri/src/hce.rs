@@ -0,0 +1,693 @@
+//! NFC Host Card Emulation (HCE) responder
+//!
+//! Android phones can't be written to as passive tags, so on Android the
+//! broadcast leg of the exchange emulates an NFC Forum Type 4 Tag instead.
+//! Any reader that selects the NDEF application gets our signed
+//! `ExchangeMessage` as a single MIME record.
+//!
+//! Between two phones the tag dance takes two taps, so the service also
+//! answers a proprietary SneakerNet AID that completes the whole exchange in
+//! one. The reader (`pcsc_nfc::tap_exchange`) drives it:
+//!
+//! ```text
+//! SELECT EXCHANGE_AID
+//! GET_INITIAL     card  -> reader  initial message
+//! PUT_RESPONSE    reader -> card   response naming the card
+//! GET_REPLY       card  -> reader  response naming the reader (polled)
+//! ```
+//!
+//! Messages travel as a 2-byte length followed by the payload, in chunks
+//! addressed by offset in P1-P2. The card's reply is signed by the app once
+//! the reader's response has been verified, so GET_REPLY answers
+//! `SW_NOT_READY` until then. Readers that only know the NDEF application
+//! still get the initial message from the same service.
+//!
+//! The APDU handling lives here so it can be tested on desktop. The Android
+//! `HostApduService` (`net.sneaker.app.HceService`) forwards each command APDU
+//! to `Java_net_sneaker_app_HceService_processApdu` below; in reader mode the
+//! app hands the tapped `IsoDep` to `Java_net_sneaker_app_ExchangeReader_onTag`.
+//!
+//! Those Kotlin classes and their manifest entries aren't in this repository
+//! yet, so the entry points are only built with the `android-hce` feature and
+//! [`AVAILABLE`] is false otherwise.
+
+use crate::exchange::NDEF_MIME_TYPE;
+use crate::pcsc_nfc::CardChannel;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// NDEF Tag Application AID (NFC Forum Type 4 Tag)
+pub const NDEF_AID: [u8; 7] = [0xD2, 0x76, 0x00, 0x00, 0x85, 0x01, 0x01];
+
+pub(crate) const CC_FILE_ID: [u8; 2] = [0xE1, 0x03];
+pub(crate) const NDEF_FILE_ID: [u8; 2] = [0xE1, 0x04];
+
+/// Proprietary SneakerNet exchange AID (`F0` + "SNEXCH")
+pub const EXCHANGE_AID: [u8; 7] = [0xF0, 0x53, 0x4E, 0x45, 0x58, 0x43, 0x48];
+
+pub(crate) const CLA_PROPRIETARY: u8 = 0x80;
+pub(crate) const INS_GET_INITIAL: u8 = 0x10;
+pub(crate) const INS_PUT_RESPONSE: u8 = 0x20;
+pub(crate) const INS_GET_REPLY: u8 = 0x30;
+
+/// Whether this build can emulate a card and tap one in reader mode
+pub const AVAILABLE: bool = cfg!(all(target_os = "android", feature = "android-hce"));
+
+/// Longest message the exchange service accepts from a reader
+pub const MAX_EXCHANGE_LEN: usize = 8 * 1024;
+
+/// Maximum R-APDU data size advertised in the capability container
+const MAX_READ: u16 = 0xFF;
+
+const SW_OK: [u8; 2] = [0x90, 0x00];
+const SW_NOT_FOUND: [u8; 2] = [0x6A, 0x82];
+const SW_WRONG_PARAMS: [u8; 2] = [0x6B, 0x00];
+const SW_INS_NOT_SUPPORTED: [u8; 2] = [0x6D, 0x00];
+const SW_WRONG_LENGTH: [u8; 2] = [0x67, 0x00];
+/// Reply not signed yet; the reader polls again
+pub(crate) const SW_NOT_READY: [u8; 2] = [0x69, 0x85];
+/// The reader's response failed verification; no reply will follow
+pub(crate) const SW_REJECTED: [u8; 2] = [0x69, 0x84];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SelectedFile {
+    None,
+    CapabilityContainer,
+    Ndef,
+}
+
+/// Type 4 Tag emulator serving a single read-only NDEF message
+pub struct HceResponder {
+    cc_file: Vec<u8>,
+    ndef_file: Vec<u8>,
+    app_selected: bool,
+    selected: SelectedFile,
+    /// Number of times a reader read the complete NDEF file
+    reads: u32,
+}
+
+impl HceResponder {
+    /// Create a responder serving `payload` as an `application/x-sneakernet` record
+    pub fn new(payload: &[u8]) -> Self {
+        let message = build_mime_record(NDEF_MIME_TYPE.as_bytes(), payload);
+
+        // NDEF file: 2-byte NLEN followed by the message
+        let mut ndef_file = Vec::with_capacity(message.len() + 2);
+        ndef_file.extend_from_slice(&(message.len() as u16).to_be_bytes());
+        ndef_file.extend_from_slice(&message);
+
+        let max_ndef = ndef_file.len() as u16;
+        let cc_file = vec![
+            0x00, 0x0F, // CCLEN
+            0x20, // Mapping version 2.0
+            (MAX_READ >> 8) as u8,
+            MAX_READ as u8, // MLe
+            0x00,
+            0xFF, // MLc
+            0x04, // NDEF File Control TLV
+            0x06,
+            NDEF_FILE_ID[0],
+            NDEF_FILE_ID[1],
+            (max_ndef >> 8) as u8,
+            max_ndef as u8,
+            0x00, // Read access granted
+            0xFF, // Write access denied
+        ];
+
+        Self {
+            cc_file,
+            ndef_file,
+            app_selected: false,
+            selected: SelectedFile::None,
+            reads: 0,
+        }
+    }
+
+    /// Number of complete NDEF reads served so far
+    pub fn reads(&self) -> u32 {
+        self.reads
+    }
+
+    /// Handle a command APDU and return the response APDU
+    pub fn process_apdu(&mut self, apdu: &[u8]) -> Vec<u8> {
+        if apdu.len() < 4 {
+            return SW_WRONG_LENGTH.to_vec();
+        }
+
+        let (cla, ins, p1, p2) = (apdu[0], apdu[1], apdu[2], apdu[3]);
+        if cla != 0x00 {
+            return SW_INS_NOT_SUPPORTED.to_vec();
+        }
+
+        match ins {
+            0xA4 => self.select(p1, &apdu[4..]),
+            0xB0 => self.read_binary(p1, p2, &apdu[4..]),
+            _ => SW_INS_NOT_SUPPORTED.to_vec(),
+        }
+    }
+
+    fn select(&mut self, p1: u8, body: &[u8]) -> Vec<u8> {
+        let data = match body.split_first() {
+            Some((&lc, rest)) if rest.len() >= lc as usize => &rest[..lc as usize],
+            _ => return SW_WRONG_LENGTH.to_vec(),
+        };
+
+        match p1 {
+            // Select by AID
+            0x04 => {
+                if data == NDEF_AID {
+                    self.app_selected = true;
+                    self.selected = SelectedFile::None;
+                    SW_OK.to_vec()
+                } else {
+                    self.app_selected = false;
+                    SW_NOT_FOUND.to_vec()
+                }
+            }
+            // Select by file ID
+            0x00 if self.app_selected => {
+                if data == CC_FILE_ID {
+                    self.selected = SelectedFile::CapabilityContainer;
+                    SW_OK.to_vec()
+                } else if data == NDEF_FILE_ID {
+                    self.selected = SelectedFile::Ndef;
+                    SW_OK.to_vec()
+                } else {
+                    SW_NOT_FOUND.to_vec()
+                }
+            }
+            _ => SW_NOT_FOUND.to_vec(),
+        }
+    }
+
+    fn read_binary(&mut self, p1: u8, p2: u8, body: &[u8]) -> Vec<u8> {
+        let file = match self.selected {
+            SelectedFile::CapabilityContainer => &self.cc_file,
+            SelectedFile::Ndef => &self.ndef_file,
+            SelectedFile::None => return SW_NOT_FOUND.to_vec(),
+        };
+
+        let offset = u16::from_be_bytes([p1, p2]) as usize;
+        let response = read_chunk(file, offset, body);
+
+        let read_to_end =
+            response.ends_with(&SW_OK) && offset + response.len() - SW_OK.len() == file.len();
+        if self.selected == SelectedFile::Ndef && offset > 0 && read_to_end {
+            self.reads += 1;
+        }
+
+        response
+    }
+}
+
+/// READ BINARY-style chunk of `file` at `offset`, with the status word
+fn read_chunk(file: &[u8], offset: usize, body: &[u8]) -> Vec<u8> {
+    if offset > file.len() {
+        return SW_WRONG_PARAMS.to_vec();
+    }
+
+    // Le of 0 means 256 in short APDUs
+    let le = match body.first() {
+        Some(0) | None => 256,
+        Some(&le) => le as usize,
+    };
+
+    let end = (offset + le).min(file.len());
+    let mut response = file[offset..end].to_vec();
+    response.extend_from_slice(&SW_OK);
+    response
+}
+
+/// A message framed for the exchange service: 2-byte length, then payload
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(payload.len() + 2);
+    framed.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    framed.extend_from_slice(payload);
+    framed
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Reply {
+    Waiting,
+    Ready(Vec<u8>),
+    Rejected,
+}
+
+/// Two-way exchange service: serves our initial message, takes the reader's
+/// response and, once the app answers, serves our reply. Anything else is
+/// passed to an NDEF responder serving the initial message.
+pub struct ExchangeResponder {
+    ndef: HceResponder,
+    selected: bool,
+    initial: Vec<u8>,
+    /// Reader's framed response as received so far
+    incoming: Vec<u8>,
+    /// Complete response not yet handed to the app
+    received: Option<Vec<u8>>,
+    reply: Reply,
+}
+
+impl ExchangeResponder {
+    pub fn new(initial: &[u8]) -> Self {
+        Self {
+            ndef: HceResponder::new(initial),
+            selected: false,
+            initial: frame(initial),
+            incoming: Vec::new(),
+            received: None,
+            reply: Reply::Waiting,
+        }
+    }
+
+    /// The reader's complete response, once, for the app to verify
+    pub fn take_received(&mut self) -> Option<Vec<u8>> {
+        self.received.take()
+    }
+
+    /// Serve `payload` as our reply to the reader
+    pub fn answer(&mut self, payload: &[u8]) {
+        self.reply = Reply::Ready(frame(payload));
+    }
+
+    /// Tell the reader its response was refused
+    pub fn reject(&mut self) {
+        self.reply = Reply::Rejected;
+    }
+
+    /// Handle a command APDU and return the response APDU
+    pub fn process_apdu(&mut self, apdu: &[u8]) -> Vec<u8> {
+        if apdu.len() < 4 {
+            return SW_WRONG_LENGTH.to_vec();
+        }
+
+        let (cla, ins, p1, p2) = (apdu[0], apdu[1], apdu[2], apdu[3]);
+        if cla == 0x00 && ins == 0xA4 && p1 == 0x04 {
+            self.selected = apdu.get(5..).and_then(|aid| aid.get(..apdu[4] as usize))
+                == Some(&EXCHANGE_AID[..]);
+            if self.selected {
+                return SW_OK.to_vec();
+            }
+        }
+        if !self.selected {
+            return self.ndef.process_apdu(apdu);
+        }
+        if cla != CLA_PROPRIETARY {
+            return SW_INS_NOT_SUPPORTED.to_vec();
+        }
+
+        let offset = u16::from_be_bytes([p1, p2]) as usize;
+        match ins {
+            INS_GET_INITIAL => read_chunk(&self.initial, offset, &apdu[4..]),
+            INS_PUT_RESPONSE => self.put_response(offset, &apdu[4..]),
+            INS_GET_REPLY => match &self.reply {
+                Reply::Ready(reply) => read_chunk(reply, offset, &apdu[4..]),
+                Reply::Waiting => SW_NOT_READY.to_vec(),
+                Reply::Rejected => SW_REJECTED.to_vec(),
+            },
+            _ => SW_INS_NOT_SUPPORTED.to_vec(),
+        }
+    }
+
+    fn put_response(&mut self, offset: usize, body: &[u8]) -> Vec<u8> {
+        let data = match body.split_first() {
+            Some((&lc, rest)) if rest.len() >= lc as usize => &rest[..lc as usize],
+            _ => return SW_WRONG_LENGTH.to_vec(),
+        };
+        // One response per tap, sent in order
+        if self.reply != Reply::Waiting || self.is_complete() || offset != self.incoming.len() {
+            return SW_WRONG_PARAMS.to_vec();
+        }
+
+        self.incoming.extend_from_slice(data);
+        if self.incoming.len() < 2 {
+            return SW_OK.to_vec();
+        }
+        let len = u16::from_be_bytes([self.incoming[0], self.incoming[1]]) as usize;
+        if len > MAX_EXCHANGE_LEN || self.incoming.len() > len + 2 {
+            self.incoming.clear();
+            return SW_WRONG_LENGTH.to_vec();
+        }
+        if self.is_complete() {
+            self.received = Some(self.incoming[2..].to_vec());
+        }
+        SW_OK.to_vec()
+    }
+
+    fn is_complete(&self) -> bool {
+        self.incoming.len() >= 2
+            && self.incoming.len() - 2
+                == u16::from_be_bytes([self.incoming[0], self.incoming[1]]) as usize
+    }
+}
+
+/// Build a single-record NDEF message with a MIME media type
+pub(crate) fn build_mime_record(mime_type: &[u8], payload: &[u8]) -> Vec<u8> {
+    build_mime_message(mime_type, &[payload.to_vec()])
+}
+
+/// An NDEF message of one MIME record per payload, all of `mime_type`
+pub(crate) fn build_mime_message(mime_type: &[u8], payloads: &[Vec<u8>]) -> Vec<u8> {
+    let mut message = Vec::new();
+    for (i, payload) in payloads.iter().enumerate() {
+        // TNF=0x02 (media type), MB on the first record and ME on the last,
+        // plus SR for short payloads
+        let short = payload.len() < 256;
+        let mut header = 0x02 | if short { 0x10 } else { 0x00 };
+        if i == 0 {
+            header |= 0x80;
+        }
+        if i + 1 == payloads.len() {
+            header |= 0x40;
+        }
+
+        message.extend_from_slice(&[header, mime_type.len() as u8]);
+        if short {
+            message.push(payload.len() as u8);
+        } else {
+            message.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        }
+        message.extend_from_slice(mime_type);
+        message.extend_from_slice(payload);
+    }
+    message
+}
+
+/// Called with the reader's response once a two-way exchange receives it
+type ResponseHook = Arc<dyn Fn(Vec<u8>) + Send + Sync>;
+
+/// What the service currently answers readers with
+enum Service {
+    Ndef(HceResponder),
+    Exchange(ExchangeResponder, ResponseHook),
+}
+
+impl Service {
+    fn reads(&self) -> u32 {
+        match self {
+            Service::Ndef(responder) => responder.reads(),
+            Service::Exchange(responder, _) => responder.ndef.reads(),
+        }
+    }
+}
+
+/// The service currently offered to readers, shared with the Android service
+fn active_responder() -> &'static Mutex<Option<Service>> {
+    static RESPONDER: OnceLock<Mutex<Option<Service>>> = OnceLock::new();
+    RESPONDER.get_or_init(|| Mutex::new(None))
+}
+
+/// Start serving `payload` to any reader that taps us
+pub fn arm(payload: &[u8]) {
+    *active_responder().lock().unwrap() = Some(Service::Ndef(HceResponder::new(payload)));
+}
+
+/// Offer a one-tap two-way exchange starting with `initial`. `on_response`
+/// gets the reader's response and should end in `answer_exchange` or
+/// `reject_exchange`.
+pub fn arm_exchange(initial: &[u8], on_response: impl Fn(Vec<u8>) + Send + Sync + 'static) {
+    *active_responder().lock().unwrap() = Some(Service::Exchange(
+        ExchangeResponder::new(initial),
+        Arc::new(on_response),
+    ));
+}
+
+/// Serve our reply to the reader of the current two-way exchange
+pub fn answer_exchange(payload: &[u8]) {
+    if let Some(Service::Exchange(responder, _)) = active_responder().lock().unwrap().as_mut() {
+        responder.answer(payload);
+    }
+}
+
+/// Refuse the reader's response in the current two-way exchange
+pub fn reject_exchange() {
+    if let Some(Service::Exchange(responder, _)) = active_responder().lock().unwrap().as_mut() {
+        responder.reject();
+    }
+}
+
+/// Stop serving; readers get "file not found" until armed again
+pub fn disarm() {
+    *active_responder().lock().unwrap() = None;
+}
+
+/// Whether a payload is currently being served
+pub fn is_armed() -> bool {
+    active_responder().lock().unwrap().is_some()
+}
+
+/// Number of complete reads of the current payload
+pub fn served_reads() -> u32 {
+    active_responder()
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(Service::reads)
+        .unwrap_or(0)
+}
+
+/// Route a command APDU to the active responder
+pub fn process_apdu(apdu: &[u8]) -> Vec<u8> {
+    let (response, received) = match active_responder().lock().unwrap().as_mut() {
+        Some(Service::Ndef(responder)) => (responder.process_apdu(apdu), None),
+        Some(Service::Exchange(responder, hook)) => {
+            let response = responder.process_apdu(apdu);
+            let received = responder
+                .take_received()
+                .map(|payload| (payload, hook.clone()));
+            (response, received)
+        }
+        None => (SW_NOT_FOUND.to_vec(), None),
+    };
+
+    // Outside the lock: the hook answers through `answer_exchange`
+    if let Some((payload, hook)) = received {
+        hook(payload);
+    }
+    response
+}
+
+/// Run on the next phone tapped in reader mode
+type PendingTap = Box<dyn FnOnce(&mut dyn CardChannel) + Send>;
+
+fn pending_tap() -> &'static Mutex<Option<PendingTap>> {
+    static TAP: OnceLock<Mutex<Option<PendingTap>>> = OnceLock::new();
+    TAP.get_or_init(|| Mutex::new(None))
+}
+
+/// Run `tap` against the next phone the reader-mode activity reports
+pub fn await_tap(tap: impl FnOnce(&mut dyn CardChannel) + Send + 'static) {
+    *pending_tap().lock().unwrap() = Some(Box::new(tap));
+}
+
+/// Forget a tap that hasn't happened yet
+pub fn cancel_tap() {
+    pending_tap().lock().unwrap().take();
+}
+
+/// JNI entry point for `HceService.processApdu(ByteArray): ByteArray`
+#[cfg(all(target_os = "android", feature = "android-hce"))]
+#[no_mangle]
+pub extern "system" fn Java_net_sneaker_app_HceService_processApdu<'local>(
+    env: jni::JNIEnv<'local>,
+    _class: jni::objects::JClass<'local>,
+    apdu: jni::objects::JByteArray<'local>,
+) -> jni::sys::jbyteArray {
+    let command = env.convert_byte_array(&apdu).unwrap_or_default();
+    let response = process_apdu(&command);
+
+    env.byte_array_from_slice(&response)
+        .map(|array| array.into_raw())
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// `android.nfc.tech.IsoDep` of the phone we tapped in reader mode
+#[cfg(all(target_os = "android", feature = "android-hce"))]
+struct IsoDepChannel<'a, 'local> {
+    env: &'a mut jni::JNIEnv<'local>,
+    iso_dep: &'a jni::objects::JObject<'local>,
+}
+
+#[cfg(all(target_os = "android", feature = "android-hce"))]
+impl CardChannel for IsoDepChannel<'_, '_> {
+    fn transmit(&mut self, apdu: &[u8]) -> Result<Vec<u8>, crate::nfc::NfcError> {
+        use crate::nfc::NfcError;
+
+        let command = self
+            .env
+            .byte_array_from_slice(apdu)
+            .map_err(|e| NfcError::Transceive(e.to_string()))?;
+        let response = self
+            .env
+            .call_method(self.iso_dep, "transceive", "([B)[B", &[(&command).into()])
+            .and_then(|value| value.l());
+        if self.env.exception_check().unwrap_or(false) {
+            // IOException / TagLostException: the phones moved apart
+            let _ = self.env.exception_clear();
+            return Err(NfcError::TagLost);
+        }
+        let response = jni::objects::JByteArray::from(
+            response.map_err(|e| NfcError::Transceive(e.to_string()))?,
+        );
+        self.env
+            .convert_byte_array(&response)
+            .map_err(|e| NfcError::Transceive(e.to_string()))
+    }
+}
+
+/// JNI entry point for `ExchangeReader.onTag(IsoDep): Boolean`, called from
+/// the reader-mode callback with the tag already connected. Returns whether a
+/// tap was waiting for it.
+#[cfg(all(target_os = "android", feature = "android-hce"))]
+#[no_mangle]
+pub extern "system" fn Java_net_sneaker_app_ExchangeReader_onTag<'local>(
+    mut env: jni::JNIEnv<'local>,
+    _class: jni::objects::JClass<'local>,
+    iso_dep: jni::objects::JObject<'local>,
+) -> jni::sys::jboolean {
+    let Some(tap) = pending_tap().lock().unwrap().take() else {
+        return jni::sys::JNI_FALSE;
+    };
+    tap(&mut IsoDepChannel {
+        env: &mut env,
+        iso_dep: &iso_dep,
+    });
+    jni::sys::JNI_TRUE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select_apdu(p1: u8, data: &[u8]) -> Vec<u8> {
+        let mut apdu = vec![0x00, 0xA4, p1, 0x0C, data.len() as u8];
+        apdu.extend_from_slice(data);
+        apdu
+    }
+
+    fn read_apdu(offset: u16, le: u8) -> Vec<u8> {
+        let [hi, lo] = offset.to_be_bytes();
+        vec![0x00, 0xB0, hi, lo, le]
+    }
+
+    #[test]
+    fn test_full_ndef_read() {
+        let payload = br#"{"version":1}"#;
+        let mut responder = HceResponder::new(payload);
+
+        assert_eq!(responder.process_apdu(&select_apdu(0x04, &NDEF_AID)), SW_OK);
+        assert_eq!(responder.process_apdu(&select_apdu(0x00, &CC_FILE_ID)), SW_OK);
+
+        let cc = responder.process_apdu(&read_apdu(0, 15));
+        assert_eq!(cc.len(), 17);
+        assert_eq!(&cc[9..11], &NDEF_FILE_ID);
+
+        assert_eq!(responder.process_apdu(&select_apdu(0x00, &NDEF_FILE_ID)), SW_OK);
+        let nlen = responder.process_apdu(&read_apdu(0, 2));
+        let len = u16::from_be_bytes([nlen[0], nlen[1]]);
+
+        let body = responder.process_apdu(&read_apdu(2, len as u8));
+        let message = &body[..body.len() - 2];
+        assert_eq!(message, build_mime_record(NDEF_MIME_TYPE.as_bytes(), payload));
+        assert!(message.ends_with(payload));
+        assert_eq!(responder.reads(), 1);
+    }
+
+    #[test]
+    fn test_rejects_without_app_select() {
+        let mut responder = HceResponder::new(b"x");
+
+        assert_eq!(
+            responder.process_apdu(&select_apdu(0x00, &NDEF_FILE_ID)),
+            SW_NOT_FOUND
+        );
+        assert_eq!(responder.process_apdu(&select_apdu(0x04, &[0xA0, 0x00])), SW_NOT_FOUND);
+        assert_eq!(responder.process_apdu(&[0x00, 0xD6, 0x00, 0x00]), SW_INS_NOT_SUPPORTED);
+    }
+
+    fn exchange_apdu(ins: u8, offset: u16, data: &[u8]) -> Vec<u8> {
+        let [hi, lo] = offset.to_be_bytes();
+        let mut apdu = vec![CLA_PROPRIETARY, ins, hi, lo, data.len() as u8];
+        apdu.extend_from_slice(data);
+        apdu
+    }
+
+    #[test]
+    fn test_exchange_service() {
+        let mut card = ExchangeResponder::new(b"initial");
+
+        // Old readers still find the initial message as an NDEF record
+        assert_eq!(card.process_apdu(&select_apdu(0x04, &NDEF_AID)), SW_OK);
+        assert_eq!(
+            card.process_apdu(&exchange_apdu(INS_GET_INITIAL, 0, &[])),
+            SW_INS_NOT_SUPPORTED
+        );
+
+        assert_eq!(card.process_apdu(&select_apdu(0x04, &EXCHANGE_AID)), SW_OK);
+        let initial = card.process_apdu(&[CLA_PROPRIETARY, INS_GET_INITIAL, 0, 0, 0]);
+        assert_eq!(initial, [&frame(b"initial")[..], &SW_OK].concat());
+
+        // Nothing to reply to yet
+        assert_eq!(
+            card.process_apdu(&exchange_apdu(INS_GET_REPLY, 0, &[])),
+            SW_NOT_READY
+        );
+
+        let framed = frame(b"response");
+        assert_eq!(
+            card.process_apdu(&exchange_apdu(INS_PUT_RESPONSE, 0, &framed[..4])),
+            SW_OK
+        );
+        assert_eq!(card.take_received(), None);
+        // Chunks must arrive in order
+        assert_eq!(
+            card.process_apdu(&exchange_apdu(INS_PUT_RESPONSE, 0, &framed[4..])),
+            SW_WRONG_PARAMS
+        );
+        assert_eq!(
+            card.process_apdu(&exchange_apdu(INS_PUT_RESPONSE, 4, &framed[4..])),
+            SW_OK
+        );
+        assert_eq!(card.take_received(), Some(b"response".to_vec()));
+        assert_eq!(card.take_received(), None);
+
+        card.answer(b"reply");
+        let reply = card.process_apdu(&[CLA_PROPRIETARY, INS_GET_REPLY, 0, 0, 0]);
+        assert_eq!(reply, [&frame(b"reply")[..], &SW_OK].concat());
+
+        // A second response in the same tap is refused
+        assert_eq!(
+            card.process_apdu(&exchange_apdu(INS_PUT_RESPONSE, 0, &framed)),
+            SW_WRONG_PARAMS
+        );
+    }
+
+    #[test]
+    fn test_exchange_service_rejects() {
+        let mut card = ExchangeResponder::new(b"initial");
+        card.process_apdu(&select_apdu(0x04, &EXCHANGE_AID));
+
+        // Longer than its declared length
+        let mut framed = frame(b"response");
+        framed.push(b'!');
+        assert_eq!(
+            card.process_apdu(&exchange_apdu(INS_PUT_RESPONSE, 0, &framed)),
+            SW_WRONG_LENGTH
+        );
+
+        card.process_apdu(&exchange_apdu(INS_PUT_RESPONSE, 0, &frame(b"response")));
+        card.reject();
+        assert_eq!(
+            card.process_apdu(&exchange_apdu(INS_GET_REPLY, 0, &[])),
+            SW_REJECTED
+        );
+    }
+
+    #[test]
+    fn test_long_payload_record() {
+        let payload = vec![b'a'; 300];
+        let record = build_mime_record(b"application/x-sneakernet", &payload);
+
+        // Not a short record: 4-byte payload length
+        assert_eq!(record[0] & 0x10, 0);
+        assert_eq!(&record[2..6], &300u32.to_be_bytes());
+    }
+}
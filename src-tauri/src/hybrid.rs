@@ -0,0 +1,249 @@
+//! Post-quantum hybrid chat handshake
+//!
+//! The chat ratchet (see `ratchet`) rests on secp256k1 ECDH, which a large
+//! enough quantum computer could undo for traffic recorded today. Contacts
+//! who both support it can agree on an extra secret from X25519 and ML-KEM
+//! (Kyber) together: one side sends an `Offer` with a throwaway X25519 key
+//! and an ML-KEM encapsulation key, the other answers with an `Accept`
+//! holding its own X25519 key and an ML-KEM ciphertext. The secret needs
+//! both halves, so it holds as long as either one does, and it salts every
+//! ratchet chain started after the handshake.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hkdf::Hkdf;
+use ml_kem::kem::{Decapsulate, DecapsulationKey, Encapsulate, EncapsulationKey};
+use ml_kem::{Ciphertext, Encoded, EncodedSizeUser, KemCore, MlKem768, MlKem768Params};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use x25519_dalek::{EphemeralSecret, PublicKey as X25519PublicKey};
+use zeroize::Zeroizing;
+
+/// HKDF info string of the hybrid secret; bumped whenever the scheme changes
+pub const HYBRID_CONTEXT: &[u8] = b"sneakernet-chat-hybrid-v1";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum HybridError {
+    #[error("Invalid handshake key: {0}")]
+    InvalidKey(&'static str),
+    #[error("Handshake answers an offer we didn't make")]
+    UnknownOffer,
+    #[error("Handshake key agreement failed")]
+    Agreement,
+}
+
+/// Handshake frame, sent on its own outside the ratchet. Keys and the
+/// ciphertext are base64.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Handshake {
+    #[serde(rename_all = "camelCase")]
+    Offer { x25519: String, ml_kem: String },
+    #[serde(rename_all = "camelCase")]
+    Accept {
+        /// ID of the offer it answers
+        offer: String,
+        x25519: String,
+        ml_kem: String,
+    },
+}
+
+/// A secret agreed in a handshake, named by the offer's ID
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HybridSecret {
+    pub id: String,
+    pub secret: Zeroizing<[u8; 32]>,
+}
+
+/// Our side of an offer, kept until it is accepted
+pub struct PendingOffer {
+    id: String,
+    x25519: EphemeralSecret,
+    ml_kem: DecapsulationKey<MlKem768Params>,
+}
+
+/// ID of an offer: a short hash of its two keys
+pub fn offer_id(x25519: &str, ml_kem: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(x25519.as_bytes());
+    hasher.update(ml_kem.as_bytes());
+    hex::encode(&hasher.finalize()[..8])
+}
+
+fn decode_x25519(key: &str) -> Result<X25519PublicKey, HybridError> {
+    let bytes: [u8; 32] = STANDARD
+        .decode(key)
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or(HybridError::InvalidKey("x25519"))?;
+    Ok(X25519PublicKey::from(bytes))
+}
+
+/// The hybrid secret from both key agreements
+fn combine(id: &str, x25519: &[u8], ml_kem: &[u8]) -> HybridSecret {
+    let hk = Hkdf::<Sha256>::new(Some(id.as_bytes()), &[x25519, ml_kem].concat());
+    let mut secret = Zeroizing::new([0u8; 32]);
+    hk.expand(HYBRID_CONTEXT, secret.as_mut_slice())
+        .expect("32 bytes is a valid HKDF length");
+    HybridSecret {
+        id: id.to_string(),
+        secret,
+    }
+}
+
+/// Start a handshake
+pub fn offer() -> (PendingOffer, Handshake) {
+    let mut rng = rand::thread_rng();
+    let x25519 = EphemeralSecret::random_from_rng(&mut rng);
+    let (ml_kem, encapsulation_key) = MlKem768::generate(&mut rng);
+
+    let x25519_public = STANDARD.encode(X25519PublicKey::from(&x25519).as_bytes());
+    let ml_kem_public = STANDARD.encode(encapsulation_key.as_bytes());
+    let pending = PendingOffer {
+        id: offer_id(&x25519_public, &ml_kem_public),
+        x25519,
+        ml_kem,
+    };
+    let handshake = Handshake::Offer {
+        x25519: x25519_public,
+        ml_kem: ml_kem_public,
+    };
+    (pending, handshake)
+}
+
+/// Answer an offer, returning the secret and the `Accept` to send back
+pub fn accept(x25519: &str, ml_kem: &str) -> Result<(HybridSecret, Handshake), HybridError> {
+    let their_x25519 = decode_x25519(x25519)?;
+    let encoded = STANDARD
+        .decode(ml_kem)
+        .ok()
+        .and_then(|b| Encoded::<EncapsulationKey<MlKem768Params>>::try_from(&b[..]).ok())
+        .ok_or(HybridError::InvalidKey("ml-kem"))?;
+    let encapsulation_key = EncapsulationKey::<MlKem768Params>::from_bytes(&encoded);
+
+    let mut rng = rand::thread_rng();
+    let ours = EphemeralSecret::random_from_rng(&mut rng);
+    let our_public = X25519PublicKey::from(&ours);
+    let dh = ours.diffie_hellman(&their_x25519);
+    if !dh.was_contributory() {
+        return Err(HybridError::InvalidKey("x25519"));
+    }
+    let (ciphertext, shared) = encapsulation_key
+        .encapsulate(&mut rng)
+        .map_err(|_| HybridError::Agreement)?;
+
+    let id = offer_id(x25519, ml_kem);
+    let secret = combine(&id, dh.as_bytes(), shared.as_slice());
+    let handshake = Handshake::Accept {
+        offer: id,
+        x25519: STANDARD.encode(our_public.as_bytes()),
+        ml_kem: STANDARD.encode(ciphertext.as_slice()),
+    };
+    Ok((secret, handshake))
+}
+
+impl PendingOffer {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Finish the handshake with the contact's `Accept`
+    pub fn complete(
+        self,
+        offer: &str,
+        x25519: &str,
+        ml_kem: &str,
+    ) -> Result<HybridSecret, HybridError> {
+        if offer != self.id {
+            return Err(HybridError::UnknownOffer);
+        }
+        let their_x25519 = decode_x25519(x25519)?;
+        let ciphertext = STANDARD
+            .decode(ml_kem)
+            .ok()
+            .and_then(|b| Ciphertext::<MlKem768>::try_from(&b[..]).ok())
+            .ok_or(HybridError::InvalidKey("ml-kem"))?;
+
+        let dh = self.x25519.diffie_hellman(&their_x25519);
+        if !dh.was_contributory() {
+            return Err(HybridError::InvalidKey("x25519"));
+        }
+        let shared = self
+            .ml_kem
+            .decapsulate(&ciphertext)
+            .map_err(|_| HybridError::Agreement)?;
+
+        Ok(combine(&self.id, dh.as_bytes(), shared.as_slice()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept_fields(handshake: &Handshake) -> (&str, &str, &str) {
+        match handshake {
+            Handshake::Accept {
+                offer,
+                x25519,
+                ml_kem,
+            } => (offer, x25519, ml_kem),
+            Handshake::Offer { .. } => panic!("expected an accept"),
+        }
+    }
+
+    #[test]
+    fn test_both_sides_agree() {
+        let (pending, offer_frame) = offer();
+        let Handshake::Offer { x25519, ml_kem } = &offer_frame else {
+            panic!("expected an offer");
+        };
+
+        let (theirs, accept_frame) = accept(x25519, ml_kem).unwrap();
+        assert_eq!(theirs.id, pending.id());
+        let (offer_id, x25519, ml_kem) = accept_fields(&accept_frame);
+        let ours = pending.complete(offer_id, x25519, ml_kem).unwrap();
+        assert_eq!(ours, theirs);
+
+        // Frames round-trip through JSON
+        let json = serde_json::to_string(&offer_frame).unwrap();
+        assert!(json.contains(r#""type":"offer""#));
+        assert_eq!(
+            serde_json::from_str::<Handshake>(&json).unwrap(),
+            offer_frame
+        );
+    }
+
+    #[test]
+    fn test_accept_must_match_the_offer() {
+        let (pending, _) = offer();
+        let (_, other_offer) = offer();
+        let Handshake::Offer { x25519, ml_kem } = &other_offer else {
+            panic!("expected an offer");
+        };
+        let (_, accept_frame) = accept(x25519, ml_kem).unwrap();
+        let (offer_id, x25519, ml_kem) = accept_fields(&accept_frame);
+
+        assert_eq!(
+            pending.complete(offer_id, x25519, ml_kem).err(),
+            Some(HybridError::UnknownOffer)
+        );
+    }
+
+    #[test]
+    fn test_malformed_keys_are_refused() {
+        let (_, offer_frame) = offer();
+        let Handshake::Offer { x25519, ml_kem } = &offer_frame else {
+            panic!("expected an offer");
+        };
+        assert_eq!(
+            accept("not base64!", ml_kem).err(),
+            Some(HybridError::InvalidKey("x25519"))
+        );
+        assert_eq!(
+            accept(x25519, &STANDARD.encode([0u8; 16])).err(),
+            Some(HybridError::InvalidKey("ml-kem"))
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Resolve npubs and NIP-05 identifiers to pubkeys
+//!
+//! Used when adding a contact by hand instead of in person. An npub decodes
+//! locally; a NIP-05 address (`bob@example.com`) is looked up at
+//! `https://example.com/.well-known/nostr.json?name=bob`. Redirects are not
+//! followed, as NIP-05 requires.
+
+use nostr::nips::nip19::FromBech32;
+use nostr::PublicKey;
+use serde_json::Value;
+use std::time::Duration;
+use thiserror::Error;
+
+/// How long a NIP-05 lookup may take
+pub const NIP05_TIMEOUT_SECS: u64 = 10;
+
+/// URI scheme other Nostr apps use for a bare npub
+pub(crate) const NOSTR_URI_PREFIX: &str = "nostr:";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum IdentifierError {
+    #[error("Not an npub or NIP-05 address")]
+    Invalid,
+    #[error("{0} is not listed by its domain")]
+    NotFound(String),
+    #[error("NIP-05 lookup failed: {0}")]
+    Lookup(String),
+}
+
+/// A contact identifier typed or pasted by the user
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Identifier {
+    /// Hex pubkey, decoded from an npub
+    Pubkey(String),
+    Nip05 {
+        name: String,
+        domain: String,
+    },
+}
+
+impl Identifier {
+    /// Parse `npub1…`, `nostr:npub1…`, `name@domain` or a bare domain (`_@domain`)
+    pub fn parse(input: &str) -> Result<Self, IdentifierError> {
+        let input = input.trim();
+        let bech32 = input.strip_prefix(NOSTR_URI_PREFIX).unwrap_or(input);
+        if bech32.starts_with("npub1") {
+            let pubkey = PublicKey::from_bech32(bech32).map_err(|_| IdentifierError::Invalid)?;
+            return Ok(Identifier::Pubkey(pubkey.to_hex()));
+        }
+
+        let (name, domain) = input.split_once('@').unwrap_or(("_", input));
+        let name = name.to_lowercase();
+        let domain = domain.to_lowercase();
+
+        let valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        let valid_domain = domain.contains('.')
+            && !domain.starts_with('.')
+            && !domain.ends_with('.')
+            && domain
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.'));
+        if !valid_name || !valid_domain {
+            return Err(IdentifierError::Invalid);
+        }
+
+        Ok(Identifier::Nip05 { name, domain })
+    }
+
+    /// The address as users write it (`_@domain` is shown as the domain)
+    pub fn nip05_address(&self) -> Option<String> {
+        match self {
+            Identifier::Pubkey(_) => None,
+            Identifier::Nip05 { name, domain } if name == "_" => Some(domain.clone()),
+            Identifier::Nip05 { name, domain } => Some(format!("{}@{}", name, domain)),
+        }
+    }
+}
+
+/// URL of the NIP-05 document listing `name`
+pub fn nip05_url(name: &str, domain: &str) -> String {
+    format!("https://{}/.well-known/nostr.json?name={}", domain, name)
+}
+
+/// Pubkey for `name` in a NIP-05 `nostr.json` document
+pub fn pubkey_from_nip05_json(json: &Value, name: &str) -> Option<String> {
+    let hex = json.get("names")?.get(name)?.as_str()?;
+    PublicKey::from_hex(hex).ok().map(|pk| pk.to_hex())
+}
+
+/// Resolve an identifier to a hex pubkey, looking NIP-05 addresses up over HTTPS
+pub async fn resolve(identifier: &Identifier) -> Result<String, IdentifierError> {
+    let (name, domain) = match identifier {
+        Identifier::Pubkey(pubkey) => return Ok(pubkey.clone()),
+        Identifier::Nip05 { name, domain } => (name, domain),
+    };
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(NIP05_TIMEOUT_SECS))
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .map_err(|e| IdentifierError::Lookup(e.to_string()))?;
+
+    let response = client
+        .get(nip05_url(name, domain))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| IdentifierError::Lookup(e.to_string()))?;
+    let json: Value = response
+        .json()
+        .await
+        .map_err(|e| IdentifierError::Lookup(e.to_string()))?;
+
+    pubkey_from_nip05_json(&json, name)
+        .ok_or_else(|| IdentifierError::NotFound(identifier.nip05_address().unwrap_or_default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::nips::nip19::ToBech32;
+    use nostr::Keys;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse() {
+        let pubkey = Keys::generate().public_key();
+        let npub = pubkey.to_bech32().unwrap();
+
+        assert_eq!(
+            Identifier::parse(&format!("nostr:{}", npub)).unwrap(),
+            Identifier::Pubkey(pubkey.to_hex())
+        );
+        assert_eq!(
+            Identifier::parse(" Bob@Example.com ").unwrap(),
+            Identifier::Nip05 {
+                name: "bob".to_string(),
+                domain: "example.com".to_string()
+            }
+        );
+
+        let root = Identifier::parse("example.com").unwrap();
+        assert_eq!(root.nip05_address().as_deref(), Some("example.com"));
+
+        assert_eq!(
+            Identifier::parse("npub1garbage").unwrap_err(),
+            IdentifierError::Invalid
+        );
+        assert_eq!(
+            Identifier::parse("bob@localhost").unwrap_err(),
+            IdentifierError::Invalid
+        );
+    }
+
+    #[test]
+    fn test_nip05_json() {
+        let pubkey = Keys::generate().public_key().to_hex();
+        let doc = json!({ "names": { "bob": pubkey, "eve": "not-a-key" } });
+
+        assert_eq!(pubkey_from_nip05_json(&doc, "bob"), Some(pubkey));
+        assert_eq!(pubkey_from_nip05_json(&doc, "eve"), None);
+        assert_eq!(pubkey_from_nip05_json(&doc, "carol"), None);
+        assert_eq!(
+            nip05_url("bob", "example.com"),
+            "https://example.com/.well-known/nostr.json?name=bob"
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Inbound exchange payload limits
+//!
+//! Beacon and conference modes take payloads from anyone in range, and a
+//! flood of them would tie up verification and the exchange queue. Before a
+//! payload is verified, `InboundLimiter` caps how many different senders are
+//! processed per minute and how many messages one sender may send; the same
+//! message seen again is deduplicated and counts once. A payload whose
+//! signature fails is held in `PayloadQuarantine` for the user to inspect
+//! instead of vanishing.
+
+use crate::exchange::ScanError;
+use crate::settings::ExchangeSettings;
+use crate::stats::Transport;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use thiserror::Error;
+
+/// Span the inbound limits are counted over
+pub const RATE_WINDOW_SECS: u64 = 60;
+
+/// Most payloads held in quarantine; the oldest is dropped to make room
+pub const MAX_QUARANTINED_PAYLOADS: usize = 50;
+
+/// Longest stretch of a quarantined payload kept, in characters
+pub const MAX_QUARANTINED_CHARS: usize = 4096;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum LimitError {
+    #[error("Too many people are exchanging at once")]
+    TooManySenders,
+    #[error("This sender sent too many codes")]
+    TooManyFromSender,
+}
+
+impl From<LimitError> for ScanError {
+    fn from(_: LimitError) -> Self {
+        ScanError::RateLimited
+    }
+}
+
+/// Messages processed in the last `RATE_WINDOW_SECS`, oldest first
+#[derive(Debug, Default)]
+pub struct InboundLimiter {
+    seen: VecDeque<(String, String, u64)>,
+}
+
+impl InboundLimiter {
+    /// Count a message from `pubkey` with `nonce` received at `now`, or
+    /// refuse it if it would exceed the limits. A message already counted
+    /// passes without counting again.
+    pub fn admit(
+        &mut self,
+        pubkey: &str,
+        nonce: &str,
+        limits: &ExchangeSettings,
+        now: u64,
+    ) -> Result<(), LimitError> {
+        while self
+            .seen
+            .front()
+            .is_some_and(|(_, _, at)| now.saturating_sub(*at) >= RATE_WINDOW_SECS)
+        {
+            self.seen.pop_front();
+        }
+
+        let from_sender = self.seen.iter().filter(|(pk, _, _)| pk == pubkey);
+        if from_sender.clone().any(|(_, n, _)| n == nonce) {
+            return Ok(());
+        }
+        let sent = from_sender.count();
+        if sent == 0 && limits.max_senders_per_minute > 0 {
+            let mut senders: Vec<&str> = self.seen.iter().map(|(pk, _, _)| pk.as_str()).collect();
+            senders.sort_unstable();
+            senders.dedup();
+            if senders.len() >= limits.max_senders_per_minute as usize {
+                return Err(LimitError::TooManySenders);
+            }
+        }
+        if limits.max_payloads_per_sender > 0 && sent >= limits.max_payloads_per_sender as usize {
+            return Err(LimitError::TooManyFromSender);
+        }
+
+        self.seen
+            .push_back((pubkey.to_string(), nonce.to_string(), now));
+        Ok(())
+    }
+}
+
+/// A payload that failed its signature check
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct QuarantinedPayload {
+    /// Hash of the payload, so the same one is held once
+    pub id: String,
+    /// Key it claims to be signed by, if it decoded that far
+    pub pubkey: Option<String>,
+    /// How it arrived, if known
+    pub transport: Option<Transport>,
+    pub received_at: u64,
+    /// The payload, cut to `MAX_QUARANTINED_CHARS`
+    pub payload: String,
+}
+
+/// Forged payloads held for review, oldest first
+#[derive(Debug, Default)]
+pub struct PayloadQuarantine {
+    held: VecDeque<QuarantinedPayload>,
+}
+
+impl PayloadQuarantine {
+    /// Hold a payload. Returns the entry, or `None` if it is already held.
+    pub fn hold(
+        &mut self,
+        payload: &str,
+        pubkey: Option<&str>,
+        transport: Option<Transport>,
+        now: u64,
+    ) -> Option<QuarantinedPayload> {
+        let id = hex::encode(&Sha256::digest(payload.as_bytes())[..8]);
+        if self.held.iter().any(|q| q.id == id) {
+            return None;
+        }
+        if self.held.len() >= MAX_QUARANTINED_PAYLOADS {
+            self.held.pop_front();
+        }
+
+        let entry = QuarantinedPayload {
+            id,
+            pubkey: pubkey.map(str::to_string),
+            transport,
+            received_at: now,
+            payload: payload.chars().take(MAX_QUARANTINED_CHARS).collect(),
+        };
+        self.held.push_back(entry.clone());
+        Some(entry)
+    }
+
+    pub fn list(&self) -> Vec<QuarantinedPayload> {
+        self.held.iter().cloned().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.held.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_senders: u32, max_per_sender: u32) -> ExchangeSettings {
+        ExchangeSettings {
+            max_senders_per_minute: max_senders,
+            max_payloads_per_sender: max_per_sender,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_senders_are_capped_per_minute() {
+        let limits = limits(2, 0);
+        let mut limiter = InboundLimiter::default();
+        limiter.admit("alice", "a1", &limits, 100).unwrap();
+        limiter.admit("bob", "b1", &limits, 110).unwrap();
+        assert_eq!(
+            limiter.admit("carol", "c1", &limits, 120),
+            Err(LimitError::TooManySenders)
+        );
+        // Someone already counted isn't a new sender
+        limiter.admit("alice", "a2", &limits, 120).unwrap();
+
+        // Alice's first message ages out, but her second keeps her counted
+        assert!(limiter.admit("carol", "c1", &limits, 165).is_err());
+        limiter.admit("carol", "c1", &limits, 171).unwrap();
+    }
+
+    #[test]
+    fn test_repeats_from_a_sender() {
+        let limits = limits(0, 2);
+        let mut limiter = InboundLimiter::default();
+        limiter.admit("alice", "a1", &limits, 100).unwrap();
+        limiter.admit("alice", "a2", &limits, 100).unwrap();
+        // The same message again is deduplicated
+        limiter.admit("alice", "a1", &limits, 101).unwrap();
+        assert_eq!(
+            limiter.admit("alice", "a3", &limits, 101),
+            Err(LimitError::TooManyFromSender)
+        );
+        limiter.admit("bob", "b1", &limits, 101).unwrap();
+    }
+
+    #[test]
+    fn test_quarantine_holds_each_payload_once() {
+        let mut quarantine = PayloadQuarantine::default();
+        let entry = quarantine
+            .hold("sn2:forged", Some("mallory"), Some(Transport::Qr), 100)
+            .unwrap();
+        assert_eq!(entry.pubkey.as_deref(), Some("mallory"));
+        assert!(quarantine.hold("sn2:forged", None, None, 101).is_none());
+
+        for i in 0..MAX_QUARANTINED_PAYLOADS {
+            quarantine.hold(&format!("sn2:{}", i), None, Some(Transport::Lan), 102);
+        }
+        let held = quarantine.list();
+        assert_eq!(held.len(), MAX_QUARANTINED_PAYLOADS);
+        assert!(held.iter().all(|q| q.id != entry.id));
+
+        quarantine.clear();
+        assert!(quarantine.list().is_empty());
+    }
+}
@@ -2,8 +2,16 @@
 
 use hkdf::Hkdf;
 use iroh_base::key::{PublicKey as IrohPublicKey, SecretKey as IrohSecretKey};
-use sha2::Sha256;
+use nostr::secp256k1::{ecdh, Parity, SecretKey, XOnlyPublicKey};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// HKDF info string; bumped whenever the derivation changes
+pub const DERIVATION_CONTEXT: &[u8] = b"sneakernet-iroh-v2";
+
+/// HKDF info string of the chat relationship secret
+pub const CHAT_CONTEXT: &[u8] = b"sneakernet-chat-v1";
 
 #[derive(Error, Debug)]
 pub enum DeriveError {
@@ -15,67 +23,108 @@ pub enum DeriveError {
     HkdfExpansionFailed,
 }
 
-/// Derive an Iroh keypair from a Nostr secret key and exchange context.
-///
-/// The derivation uses HKDF-SHA256 with:
-/// - IKM (Input Key Material): Nostr secret key bytes
-/// - Salt: SHA256 hash of sorted pubkeys (ensures same result regardless of who initiates)
-/// - Info: "sneakernet-iroh-v1" context string
-///
-/// This ensures:
-/// 1. Deterministic: Same inputs always produce same Iroh key
-/// 2. Unique per relationship: Different contact = different Iroh identity
-/// 3. Secure: HKDF is a standard, secure key derivation function
-pub fn derive_iroh_keypair(
+fn parse_pubkey(pubkey_hex: &str) -> Result<XOnlyPublicKey, DeriveError> {
+    let bytes =
+        hex::decode(pubkey_hex).map_err(|e| DeriveError::InvalidPublicKey(e.to_string()))?;
+    XOnlyPublicKey::from_slice(&bytes).map_err(|e| DeriveError::InvalidPublicKey(e.to_string()))
+}
+
+/// ECDH shared secret of two Nostr keys: the x coordinate of
+/// `secret * their_pubkey`, as in NIP-04. Both sides compute the same value;
+/// nobody who only knows the pubkeys can.
+pub(crate) fn shared_secret(
     nostr_secret_key: &[u8],
-    my_pubkey_hex: &str,
     their_pubkey_hex: &str,
-) -> Result<(IrohSecretKey, IrohPublicKey), DeriveError> {
-    // Validate input
+) -> Result<Zeroizing<[u8; 32]>, DeriveError> {
     if nostr_secret_key.len() != 32 {
         return Err(DeriveError::InvalidSecretKeyLength);
     }
+    let secret =
+        SecretKey::from_slice(nostr_secret_key).map_err(|_| DeriveError::InvalidSecretKeyLength)?;
+    let their_pubkey = parse_pubkey(their_pubkey_hex)?.public_key(Parity::Even);
+
+    let point = Zeroizing::new(ecdh::shared_secret_point(&their_pubkey, &secret));
+    let mut x = Zeroizing::new([0u8; 32]);
+    x.copy_from_slice(&point[..32]);
+    Ok(x)
+}
+
+/// The Iroh keypair `owner` runs for `peer`, from their shared secret.
+///
+/// The derivation uses HKDF-SHA256 with:
+/// - IKM (Input Key Material): ECDH shared secret of the two Nostr keys
+/// - Salt: SHA256 of owner pubkey || peer pubkey (so each direction differs)
+/// - Info: `DERIVATION_CONTEXT`
+fn derive_pair_keypair(
+    shared: &[u8; 32],
+    owner_pubkey_hex: &str,
+    peer_pubkey_hex: &str,
+) -> Result<(IrohSecretKey, IrohPublicKey), DeriveError> {
+    let owner = parse_pubkey(owner_pubkey_hex)?;
+    let peer = parse_pubkey(peer_pubkey_hex)?;
 
-    // Decode pubkeys from hex
-    let my_pubkey_bytes =
-        hex::decode(my_pubkey_hex).map_err(|e| DeriveError::InvalidPublicKey(e.to_string()))?;
-    let their_pubkey_bytes =
-        hex::decode(their_pubkey_hex).map_err(|e| DeriveError::InvalidPublicKey(e.to_string()))?;
-
-    // Sort pubkeys to ensure same salt regardless of who initiates
-    let (first, second) = if my_pubkey_bytes < their_pubkey_bytes {
-        (&my_pubkey_bytes, &their_pubkey_bytes)
-    } else {
-        (&their_pubkey_bytes, &my_pubkey_bytes)
-    };
-
-    // Create salt from sorted pubkeys
-    use sha2::Digest;
     let mut hasher = Sha256::new();
-    hasher.update(first);
-    hasher.update(second);
+    hasher.update(owner.serialize());
+    hasher.update(peer.serialize());
     let salt = hasher.finalize();
 
-    // HKDF-SHA256 key derivation
-    let hk = Hkdf::<Sha256>::new(Some(&salt), nostr_secret_key);
-
-    let mut iroh_seed = [0u8; 32];
-    hk.expand(b"sneakernet-iroh-v1", &mut iroh_seed)
+    let hk = Hkdf::<Sha256>::new(Some(&salt), shared);
+    let mut iroh_seed = Zeroizing::new([0u8; 32]);
+    hk.expand(DERIVATION_CONTEXT, iroh_seed.as_mut_slice())
         .map_err(|_| DeriveError::HkdfExpansionFailed)?;
 
-    // Create Iroh keypair from seed
     let secret_key = IrohSecretKey::from_bytes(&iroh_seed);
     let public_key = secret_key.public();
-
     Ok((secret_key, public_key))
 }
 
+/// Derive the Iroh keypair we run for one contact.
+///
+/// This ensures:
+/// 1. Deterministic: Same inputs always produce same Iroh key
+/// 2. Unique per relationship and direction: our node for them differs
+///    from theirs for us, and from our node for anyone else
+/// 3. Mutual: they can compute this node's ID from their own secret key
+///    (see `derive_peer_endpoint_id`), but third parties who only know the
+///    two pubkeys cannot
+pub fn derive_iroh_keypair(
+    nostr_secret_key: &[u8],
+    my_pubkey_hex: &str,
+    their_pubkey_hex: &str,
+) -> Result<(IrohSecretKey, IrohPublicKey), DeriveError> {
+    let shared = shared_secret(nostr_secret_key, their_pubkey_hex)?;
+    derive_pair_keypair(&shared, my_pubkey_hex, their_pubkey_hex)
+}
+
+/// The secret a contact and we share for chat encryption (see `ratchet`).
+/// Unlike the Iroh keys it is the same from both sides: the salt is the
+/// SHA256 of the two pubkeys in sorted order.
+pub fn derive_chat_secret(
+    nostr_secret_key: &[u8],
+    my_pubkey_hex: &str,
+    their_pubkey_hex: &str,
+) -> Result<Zeroizing<[u8; 32]>, DeriveError> {
+    let shared = shared_secret(nostr_secret_key, their_pubkey_hex)?;
+    let mut pubkeys = [
+        parse_pubkey(my_pubkey_hex)?.serialize(),
+        parse_pubkey(their_pubkey_hex)?.serialize(),
+    ];
+    pubkeys.sort();
+    let salt = Sha256::digest(pubkeys.concat());
+
+    let hk = Hkdf::<Sha256>::new(Some(&salt), shared.as_slice());
+    let mut secret = Zeroizing::new([0u8; 32]);
+    hk.expand(CHAT_CONTEXT, secret.as_mut_slice())
+        .map_err(|_| DeriveError::HkdfExpansionFailed)?;
+    Ok(secret)
+}
+
 /// Get the Iroh endpoint ID (public key in base32) from derived keys
 pub fn get_endpoint_id(public_key: &IrohPublicKey) -> String {
     public_key.to_string()
 }
 
-/// Derive and return just the endpoint ID (convenience function)
+/// Derive and return just the endpoint ID of our node for a contact
 pub fn derive_endpoint_id(
     nostr_secret_key: &[u8],
     my_pubkey_hex: &str,
@@ -85,17 +134,37 @@ pub fn derive_endpoint_id(
     Ok(get_endpoint_id(&public_key))
 }
 
+/// Derive the endpoint ID of the node a contact runs for us, to dial them
+pub fn derive_peer_endpoint_id(
+    nostr_secret_key: &[u8],
+    my_pubkey_hex: &str,
+    their_pubkey_hex: &str,
+) -> Result<String, DeriveError> {
+    let shared = shared_secret(nostr_secret_key, their_pubkey_hex)?;
+    let (_, public_key) = derive_pair_keypair(&shared, their_pubkey_hex, my_pubkey_hex)?;
+    Ok(get_endpoint_id(&public_key))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use nostr::Keys;
+
+    /// Secret bytes and hex pubkey of a fresh Nostr key
+    fn nostr_key() -> (Vec<u8>, String) {
+        let keys = Keys::generate();
+        (
+            keys.secret_key().secret_bytes().to_vec(),
+            keys.public_key().to_hex(),
+        )
+    }
 
     #[test]
     fn test_derive_keypair() {
-        let nostr_secret = [0x42u8; 32]; // Test secret key
-        let my_pubkey = "a".repeat(64);
-        let their_pubkey = "b".repeat(64);
+        let (secret, my_pubkey) = nostr_key();
+        let (_, their_pubkey) = nostr_key();
 
-        let result = derive_iroh_keypair(&nostr_secret, &my_pubkey, &their_pubkey);
+        let result = derive_iroh_keypair(&secret, &my_pubkey, &their_pubkey);
         assert!(result.is_ok());
 
         let (secret, public) = result.unwrap();
@@ -104,51 +173,70 @@ mod tests {
 
     #[test]
     fn test_deterministic_derivation() {
-        let nostr_secret = [0x42u8; 32];
-        let my_pubkey = "a".repeat(64);
-        let their_pubkey = "b".repeat(64);
+        let (secret, my_pubkey) = nostr_key();
+        let (_, their_pubkey) = nostr_key();
 
-        let (_, public1) = derive_iroh_keypair(&nostr_secret, &my_pubkey, &their_pubkey).unwrap();
-        let (_, public2) = derive_iroh_keypair(&nostr_secret, &my_pubkey, &their_pubkey).unwrap();
+        let (_, public1) = derive_iroh_keypair(&secret, &my_pubkey, &their_pubkey).unwrap();
+        let (_, public2) = derive_iroh_keypair(&secret, &my_pubkey, &their_pubkey).unwrap();
 
         assert_eq!(public1, public2);
     }
 
     #[test]
-    fn test_order_independent() {
-        // The result should be the same regardless of who initiates
-        let nostr_secret = [0x42u8; 32];
-        let pubkey_a = "a".repeat(64);
-        let pubkey_b = "b".repeat(64);
-
-        let (_, public1) = derive_iroh_keypair(&nostr_secret, &pubkey_a, &pubkey_b).unwrap();
-        let (_, public2) = derive_iroh_keypair(&nostr_secret, &pubkey_b, &pubkey_a).unwrap();
+    fn test_each_side_computes_the_others_node() {
+        let (alice_secret, alice) = nostr_key();
+        let (bob_secret, bob) = nostr_key();
+
+        let alice_node = derive_endpoint_id(&alice_secret, &alice, &bob).unwrap();
+        let bob_node = derive_endpoint_id(&bob_secret, &bob, &alice).unwrap();
+        assert_ne!(alice_node, bob_node);
+
+        assert_eq!(
+            derive_peer_endpoint_id(&bob_secret, &bob, &alice).unwrap(),
+            alice_node
+        );
+        assert_eq!(
+            derive_peer_endpoint_id(&alice_secret, &alice, &bob).unwrap(),
+            bob_node
+        );
+
+        // A third party's secret gives something else
+        let (eve_secret, _) = nostr_key();
+        assert_ne!(
+            derive_peer_endpoint_id(&eve_secret, &bob, &alice).unwrap(),
+            alice_node
+        );
+    }
 
-        assert_eq!(public1, public2);
+    #[test]
+    fn test_chat_secret_is_shared() {
+        let (alice_secret, alice) = nostr_key();
+        let (bob_secret, bob) = nostr_key();
+        let (eve_secret, _) = nostr_key();
+
+        let ours = derive_chat_secret(&alice_secret, &alice, &bob).unwrap();
+        assert_eq!(ours, derive_chat_secret(&bob_secret, &bob, &alice).unwrap());
+        assert_ne!(ours, derive_chat_secret(&eve_secret, &alice, &bob).unwrap());
     }
 
     #[test]
     fn test_different_contacts_different_keys() {
-        let nostr_secret = [0x42u8; 32];
-        let my_pubkey = "a".repeat(64);
-        let contact1_pubkey = "b".repeat(64);
-        let contact2_pubkey = "c".repeat(64);
+        let (secret, my_pubkey) = nostr_key();
+        let (_, contact1_pubkey) = nostr_key();
+        let (_, contact2_pubkey) = nostr_key();
 
-        let (_, public1) =
-            derive_iroh_keypair(&nostr_secret, &my_pubkey, &contact1_pubkey).unwrap();
-        let (_, public2) =
-            derive_iroh_keypair(&nostr_secret, &my_pubkey, &contact2_pubkey).unwrap();
+        let (_, public1) = derive_iroh_keypair(&secret, &my_pubkey, &contact1_pubkey).unwrap();
+        let (_, public2) = derive_iroh_keypair(&secret, &my_pubkey, &contact2_pubkey).unwrap();
 
         assert_ne!(public1, public2);
     }
 
     #[test]
     fn test_endpoint_id_format() {
-        let nostr_secret = [0x42u8; 32];
-        let my_pubkey = "a".repeat(64);
-        let their_pubkey = "b".repeat(64);
+        let (secret, my_pubkey) = nostr_key();
+        let (_, their_pubkey) = nostr_key();
 
-        let endpoint_id = derive_endpoint_id(&nostr_secret, &my_pubkey, &their_pubkey).unwrap();
+        let endpoint_id = derive_endpoint_id(&secret, &my_pubkey, &their_pubkey).unwrap();
 
         // Iroh endpoint IDs are base32 encoded
         assert!(!endpoint_id.is_empty());
@@ -159,8 +247,8 @@ mod tests {
     #[test]
     fn test_invalid_secret_key_length() {
         let short_secret = [0x42u8; 16]; // Too short
-        let my_pubkey = "a".repeat(64);
-        let their_pubkey = "b".repeat(64);
+        let (_, my_pubkey) = nostr_key();
+        let (_, their_pubkey) = nostr_key();
 
         let result = derive_iroh_keypair(&short_secret, &my_pubkey, &their_pubkey);
         assert!(matches!(result, Err(DeriveError::InvalidSecretKeyLength)));
@@ -168,11 +256,9 @@ mod tests {
 
     #[test]
     fn test_invalid_pubkey_format() {
-        let nostr_secret = [0x42u8; 32];
-        let invalid_pubkey = "not-hex!";
-        let their_pubkey = "b".repeat(64);
+        let (secret, my_pubkey) = nostr_key();
 
-        let result = derive_iroh_keypair(&nostr_secret, invalid_pubkey, &their_pubkey);
+        let result = derive_iroh_keypair(&secret, &my_pubkey, "not-hex!");
         assert!(matches!(result, Err(DeriveError::InvalidPublicKey(_))));
     }
 }
@@ -3,12 +3,12 @@
 //! This module manages the Iroh endpoint lifecycle, supporting both
 //! on-demand (start for specific chat) and background modes.
 
-use crate::iroh_derive::derive_iroh_keypair;
+use crate::iroh_derive::{derive_iroh_keypair, derive_peer_endpoint_id};
 use iroh_base::key::NodeId;
 #[allow(deprecated)]
-use iroh_net::endpoint::Endpoint;
+use iroh_net::endpoint::{get_remote_node_id, ConnectionType, Endpoint};
 #[allow(deprecated)]
-use iroh_net::relay::RelayMode;
+use iroh_net::relay::{RelayMap, RelayMode, RelayUrl};
 use iroh_quinn::Connection;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -32,6 +32,8 @@ pub enum IrohError {
     KeyDerivation(String),
     #[error("Invalid node ID: {0}")]
     InvalidNodeId(String),
+    #[error("Node {0} isn't the one derived for this contact")]
+    UnexpectedPeer(String),
 }
 
 /// Iroh endpoint status
@@ -44,6 +46,17 @@ pub struct IrohStatus {
     pub connected_contacts: Vec<String>,
 }
 
+/// How packets currently reach a peer
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PathType {
+    Direct,
+    Relay,
+    /// Holepunching in progress; both a direct address and the relay are in use
+    Mixed,
+    Unknown,
+}
+
 /// Configuration for the Iroh node
 #[derive(Clone, Debug)]
 pub struct IrohConfig {
@@ -62,14 +75,23 @@ impl Default for IrohConfig {
     }
 }
 
+/// Node ID of the peer on `connection`
+pub fn remote_node_id(connection: &Connection) -> Option<String> {
+    get_remote_node_id(connection).ok().map(|id| id.to_string())
+}
+
 /// Managed Iroh node state
 pub struct IrohNode {
     endpoint: Option<Endpoint>,
     config: IrohConfig,
     /// Current contact we're connected with (their nostr pubkey)
     current_contact: Option<String>,
+    /// Node ID derived for the current contact's end of the relationship
+    expected_peer: Option<String>,
     /// Active connections keyed by contact pubkey
     connections: std::collections::HashMap<String, Connection>,
+    /// ALPNs registered by plugins, advertised alongside chat
+    extra_alpns: Vec<Vec<u8>>,
 }
 
 impl IrohNode {
@@ -78,10 +100,22 @@ impl IrohNode {
             endpoint: None,
             config,
             current_contact: None,
+            expected_peer: None,
             connections: std::collections::HashMap::new(),
+            extra_alpns: Vec::new(),
         }
     }
 
+    /// Replace the configuration used the next time the endpoint starts
+    pub fn set_config(&mut self, config: IrohConfig) {
+        self.config = config;
+    }
+
+    /// Set the plugin ALPNs advertised the next time the endpoint starts
+    pub fn set_extra_alpns(&mut self, alpns: Vec<Vec<u8>>) {
+        self.extra_alpns = alpns;
+    }
+
     /// Start the Iroh endpoint for a specific contact
     pub async fn start_for_contact(
         &mut self,
@@ -96,18 +130,29 @@ impl IrohNode {
         // Derive Iroh keypair for this contact relationship
         let (secret_key, _) = derive_iroh_keypair(nostr_secret_key, my_pubkey_hex, their_pubkey_hex)
             .map_err(|e| IrohError::KeyDerivation(e.to_string()))?;
+        // The only node this endpoint should ever talk to
+        let expected_peer =
+            derive_peer_endpoint_id(nostr_secret_key, my_pubkey_hex, their_pubkey_hex)
+                .map_err(|e| IrohError::KeyDerivation(e.to_string()))?;
 
         // Determine relay mode
-        let relay_mode = if self.config.use_relays {
-            RelayMode::Default
-        } else {
-            RelayMode::Disabled
+        let relay_mode = match (&self.config.custom_relay_url, self.config.use_relays) {
+            (_, false) => RelayMode::Disabled,
+            (Some(url), true) => {
+                let url: RelayUrl = url
+                    .parse()
+                    .map_err(|e| IrohError::EndpointCreation(format!("Invalid relay URL: {}", e)))?;
+                RelayMode::Custom(RelayMap::from_url(url))
+            }
+            (None, true) => RelayMode::Default,
         };
 
         // Create the endpoint
+        let mut alpns = vec![CHAT_ALPN.to_vec()];
+        alpns.extend(self.extra_alpns.iter().cloned());
         let endpoint = Endpoint::builder()
             .secret_key(secret_key)
-            .alpns(vec![CHAT_ALPN.to_vec()])
+            .alpns(alpns)
             .relay_mode(relay_mode)
             .bind()
             .await
@@ -116,6 +161,7 @@ impl IrohNode {
         let node_id = endpoint.node_id().to_string();
         self.endpoint = Some(endpoint);
         self.current_contact = Some(their_pubkey_hex.to_string());
+        self.expected_peer = Some(expected_peer);
 
         Ok(node_id)
     }
@@ -130,6 +176,7 @@ impl IrohNode {
             let _ = endpoint.close(iroh_quinn::VarInt::from_u32(0), b"shutdown").await;
             
             self.current_contact = None;
+            self.expected_peer = None;
         }
         Ok(())
     }
@@ -144,28 +191,143 @@ impl IrohNode {
         }
     }
 
-    /// Connect to a contact's Iroh endpoint
-    pub async fn connect_to_contact(
-        &mut self,
-        their_node_id: &str,
-        contact_pubkey: &str,
-    ) -> Result<(), IrohError> {
+    /// Connect to a contact's Iroh endpoint. The connection isn't tracked
+    /// until the caller has bound it to the contact (see `binding`) and
+    /// passed it to `add_connection`.
+    pub async fn connect_to_contact(&self, their_node_id: &str) -> Result<Connection, IrohError> {
         let endpoint = self.endpoint.as_ref().ok_or(IrohError::NotStarted)?;
 
         // Parse their node ID (it's a public key in base32)
         let node_id: NodeId = their_node_id
             .parse()
             .map_err(|e: iroh_base::key::KeyParsingError| IrohError::InvalidNodeId(e.to_string()))?;
+        self.check_peer(&node_id.to_string())?;
 
         // Connect using just the node ID - Iroh will use relays if needed
-        let conn = endpoint
+        let connection = endpoint
             .connect(node_id, CHAT_ALPN)
             .await
             .map_err(|e| IrohError::ConnectionFailed(e.to_string()))?;
+        self.verify_peer(&connection)?;
+        Ok(connection)
+    }
+
+    /// Open a connection speaking a plugin's ALPN. The caller owns it; it
+    /// isn't tracked with the chat connections.
+    pub async fn connect_with_alpn(
+        &self,
+        their_node_id: &str,
+        alpn: &[u8],
+    ) -> Result<Connection, IrohError> {
+        let endpoint = self.endpoint.as_ref().ok_or(IrohError::NotStarted)?;
+
+        let node_id: NodeId = their_node_id
+            .parse()
+            .map_err(|e: iroh_base::key::KeyParsingError| IrohError::InvalidNodeId(e.to_string()))?;
+        self.check_peer(&node_id.to_string())?;
+
+        let connection = endpoint
+            .connect(node_id, alpn)
+            .await
+            .map_err(|e| IrohError::ConnectionFailed(e.to_string()))?;
+        self.verify_peer(&connection)?;
+        Ok(connection)
+    }
 
-        self.connections.insert(contact_pubkey.to_string(), conn);
+    /// Refuse `node_id` unless it is the node derived for the current contact
+    fn check_peer(&self, node_id: &str) -> Result<(), IrohError> {
+        match &self.expected_peer {
+            Some(expected) if expected != node_id => {
+                Err(IrohError::UnexpectedPeer(node_id.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
 
-        Ok(())
+    /// Refuse a connection unless its peer is the node derived for the
+    /// current contact, so a stale or mistyped node ID can't reach anyone else
+    pub fn verify_peer(&self, connection: &Connection) -> Result<(), IrohError> {
+        let node_id = remote_node_id(connection)
+            .ok_or_else(|| IrohError::UnexpectedPeer("unknown".to_string()))?;
+        self.check_peer(&node_id)
+    }
+
+    /// Track a connection the peer opened to us
+    pub fn add_connection(&mut self, contact_pubkey: &str, connection: Connection) {
+        self.connections.insert(contact_pubkey.to_string(), connection);
+    }
+
+    /// Forget a connection that has closed, unless it was already replaced
+    pub fn remove_connection(&mut self, contact_pubkey: &str, connection: &Connection) {
+        if self
+            .connections
+            .get(contact_pubkey)
+            .is_some_and(|c| c.stable_id() == connection.stable_id())
+        {
+            self.connections.remove(contact_pubkey);
+        }
+    }
+
+    /// Current path to the peer on `connection`
+    pub fn path_type(&self, connection: &Connection) -> PathType {
+        let (Some(endpoint), Ok(node_id)) = (self.endpoint.as_ref(), get_remote_node_id(connection))
+        else {
+            return PathType::Unknown;
+        };
+
+        match endpoint.remote_info(node_id).map(|info| info.conn_type) {
+            Some(ConnectionType::Direct(_)) => PathType::Direct,
+            Some(ConnectionType::Relay(_)) => PathType::Relay,
+            Some(ConnectionType::Mixed(..)) => PathType::Mixed,
+            _ => PathType::Unknown,
+        }
+    }
+
+    /// Stream of path changes to the peer on `connection`
+    pub fn path_changes(
+        &self,
+        connection: &Connection,
+    ) -> Option<impl futures_lite::Stream<Item = PathType> + Send + Unpin + 'static> {
+        let endpoint = self.endpoint.as_ref()?;
+        let node_id = get_remote_node_id(connection).ok()?;
+        let stream = endpoint.conn_type_stream(node_id).ok()?;
+
+        Some(futures_lite::StreamExt::map(stream, |conn_type| match conn_type {
+            ConnectionType::Direct(_) => PathType::Direct,
+            ConnectionType::Relay(_) => PathType::Relay,
+            ConnectionType::Mixed(..) => PathType::Mixed,
+            _ => PathType::Unknown,
+        }))
+    }
+
+    /// Relay our endpoint is registered with
+    pub fn home_relay(&self) -> Option<String> {
+        self.endpoint.as_ref()?.home_relay().map(|url| url.to_string())
+    }
+
+    /// What the endpoint already knows about how to reach `their_node_id`
+    pub fn discovery_summary(&self, their_node_id: &str) -> String {
+        let info = self
+            .endpoint
+            .as_ref()
+            .zip(their_node_id.parse::<NodeId>().ok())
+            .and_then(|(endpoint, node_id)| endpoint.remote_info(node_id));
+
+        match info {
+            Some(info) => format!(
+                "{} direct address(es), relay {}",
+                info.addrs.len(),
+                info.relay_url
+                    .map(|r| r.relay_url.to_string())
+                    .unwrap_or_else(|| "unknown".to_string())
+            ),
+            None => "no addresses known, relying on discovery".to_string(),
+        }
+    }
+
+    /// Contact the running endpoint was started for
+    pub fn current_contact(&self) -> Option<&str> {
+        self.current_contact.as_deref()
     }
 
     /// Get a connection for a contact
@@ -173,6 +335,11 @@ impl IrohNode {
         self.connections.get(contact_pubkey)
     }
 
+    /// Contacts with an open connection
+    pub fn connections(&self) -> impl Iterator<Item = (&str, &Connection)> {
+        self.connections.iter().map(|(pubkey, c)| (pubkey.as_str(), c))
+    }
+
     /// Get mutable connection for a contact
     pub fn get_connection_mut(&mut self, contact_pubkey: &str) -> Option<&mut Connection> {
         self.connections.get_mut(contact_pubkey)
@@ -210,4 +377,16 @@ mod tests {
         assert!(!status.running);
         assert!(status.node_id.is_none());
     }
+
+    #[test]
+    fn test_only_the_derived_peer_is_accepted() {
+        let mut node = IrohNode::new(IrohConfig::default());
+        node.expected_peer = Some("expected".to_string());
+
+        assert!(node.check_peer("expected").is_ok());
+        assert!(matches!(
+            node.check_peer("someone-else"),
+            Err(IrohError::UnexpectedPeer(id)) if id == "someone-else"
+        ));
+    }
 }
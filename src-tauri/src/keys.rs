@@ -1,8 +1,16 @@
 //! Nostr key generation and management
 
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key as CipherKey, XChaCha20Poly1305, XNonce};
 use nostr::prelude::*;
+use nostr::secp256k1::{self, Message as Secp256k1Message, Secp256k1, XOnlyPublicKey};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::pin::Pin;
 use thiserror::Error;
+use zeroize::Zeroizing;
 
 #[derive(Error, Debug)]
 pub enum KeyError {
@@ -12,17 +20,364 @@ pub enum KeyError {
     ParseError(String),
     #[error("No keys found")]
     NoKeysFound,
+    #[error("Signing failed: {0}")]
+    SigningError(String),
+    #[error("Signature verification failed")]
+    InvalidSignature,
+    #[error("Passphrase must be at least {MIN_PASSPHRASE_LEN} characters")]
+    WeakPassphrase,
+    #[error("Wrong passphrase")]
+    WrongPassphrase,
+    #[error("Encryption failed: {0}")]
+    EncryptionError(String),
+    #[error("Key storage error: {0}")]
+    StorageError(String),
+    #[error("Stored keys are corrupt: {0}")]
+    CorruptKeys(String),
 }
 
-/// Serializable key data for storage
-#[derive(Serialize, Deserialize, Clone)]
+/// How long the UI has to confirm a secret key export
+pub const EXPORT_CONFIRMATION_TTL_SECS: u64 = 60;
+
+/// Shortest passphrase accepted for sealing keys
+pub const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// Sealed key format version
+pub const SEALED_KEYS_VERSION: u32 = 1;
+
+/// Serializable key data for storage. The secret is wiped from memory when
+/// the value is dropped. It isn't `Clone`: the app state lends it out (see
+/// `AppState::with_keys`) rather than handing out copies.
+#[derive(Serialize, Deserialize)]
 pub struct StoredKeys {
     /// Secret key in hex format
-    pub secret_key_hex: String,
+    pub secret_key_hex: Zeroizing<String>,
     /// Public key in hex format  
     pub public_key_hex: String,
 }
 
+impl StoredKeys {
+    /// The raw secret key, for derivations that take bytes
+    pub fn secret_bytes(&self) -> Result<Zeroizing<Vec<u8>>, KeyError> {
+        hex::decode(self.secret_key_hex.as_str())
+            .map(Zeroizing::new)
+            .map_err(|e| KeyError::ParseError(e.to_string()))
+    }
+
+    /// Check the secret is a valid 32-byte key whose public key is
+    /// `public_key_hex`, so broken keys are caught on load rather than
+    /// deep inside signing
+    pub fn validate(&self) -> Result<(), KeyError> {
+        let secret = self
+            .secret_bytes()
+            .map_err(|_| KeyError::CorruptKeys("secret key is not hex".to_string()))?;
+        if secret.len() != 32 {
+            return Err(KeyError::CorruptKeys(format!(
+                "secret key is {} bytes, expected 32",
+                secret.len()
+            )));
+        }
+        let secret_key = SecretKey::from_slice(&secret)
+            .map_err(|_| KeyError::CorruptKeys("secret key is out of range".to_string()))?;
+        let public_key = Keys::new(secret_key).public_key().to_hex();
+        if !public_key.eq_ignore_ascii_case(&self.public_key_hex) {
+            return Err(KeyError::CorruptKeys(
+                "public key does not match the secret key".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Argon2id cost parameters and salt used to seal keys
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct KdfParams {
+    /// Memory in KiB
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+    pub salt_hex: String,
+}
+
+impl KdfParams {
+    /// 64 MiB, 3 passes: about half a second on a mid-range phone
+    pub fn new_random() -> Self {
+        Self {
+            m_cost: 64 * 1024,
+            t_cost: 3,
+            p_cost: 1,
+            salt_hex: hex::encode(rand::random::<[u8; 16]>()),
+        }
+    }
+
+    /// Light parameters for a passphrase that is itself a random 256-bit
+    /// key, which needs no stretching
+    pub fn for_random_secret() -> Self {
+        Self {
+            m_cost: 1024,
+            t_cost: 1,
+            p_cost: 1,
+            salt_hex: hex::encode(rand::random::<[u8; 16]>()),
+        }
+    }
+
+    /// The same costs with a fresh salt
+    pub fn with_new_salt(&self) -> Self {
+        Self {
+            salt_hex: hex::encode(rand::random::<[u8; 16]>()),
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn derive_key(&self, passphrase: &str) -> Result<Zeroizing<[u8; 32]>, KeyError> {
+        let salt = hex::decode(&self.salt_hex).map_err(|e| KeyError::ParseError(e.to_string()))?;
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+            .map_err(|e| KeyError::EncryptionError(e.to_string()))?;
+
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, key.as_mut_slice())
+            .map_err(|e| KeyError::EncryptionError(e.to_string()))?;
+        Ok(key)
+    }
+}
+
+/// Secret key encrypted with a passphrase (Argon2id + XChaCha20-Poly1305).
+/// The public key stays readable and is bound to the ciphertext.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SealedKeys {
+    pub version: u32,
+    pub public_key_hex: String,
+    pub kdf: KdfParams,
+    pub nonce_hex: String,
+    pub ciphertext_hex: String,
+}
+
+impl SealedKeys {
+    pub fn seal(stored: &StoredKeys, passphrase: &str) -> Result<Self, KeyError> {
+        Self::seal_with(stored, passphrase, KdfParams::new_random())
+    }
+
+    pub fn seal_with(
+        stored: &StoredKeys,
+        passphrase: &str,
+        kdf: KdfParams,
+    ) -> Result<Self, KeyError> {
+        if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+            return Err(KeyError::WeakPassphrase);
+        }
+
+        let secret = stored.secret_bytes()?;
+        let key = kdf.derive_key(passphrase)?;
+        let cipher = XChaCha20Poly1305::new(CipherKey::from_slice(key.as_slice()));
+        let nonce = rand::random::<[u8; 24]>();
+        let ciphertext = cipher
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &secret,
+                    aad: stored.public_key_hex.as_bytes(),
+                },
+            )
+            .map_err(|e| KeyError::EncryptionError(e.to_string()))?;
+
+        Ok(Self {
+            version: SEALED_KEYS_VERSION,
+            public_key_hex: stored.public_key_hex.clone(),
+            kdf,
+            nonce_hex: hex::encode(nonce),
+            ciphertext_hex: hex::encode(ciphertext),
+        })
+    }
+
+    pub fn unseal(&self, passphrase: &str) -> Result<StoredKeys, KeyError> {
+        let nonce = hex::decode(&self.nonce_hex).map_err(|e| KeyError::ParseError(e.to_string()))?;
+        let ciphertext =
+            hex::decode(&self.ciphertext_hex).map_err(|e| KeyError::ParseError(e.to_string()))?;
+        if nonce.len() != 24 {
+            return Err(KeyError::ParseError("bad nonce length".to_string()));
+        }
+
+        let key = self.kdf.derive_key(passphrase)?;
+        let cipher = XChaCha20Poly1305::new(CipherKey::from_slice(key.as_slice()));
+        let secret = cipher
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: self.public_key_hex.as_bytes(),
+                },
+            )
+            .map_err(|_| KeyError::WrongPassphrase)?;
+        let secret = Zeroizing::new(secret);
+
+        let stored = StoredKeys {
+            secret_key_hex: hex::encode(secret.as_slice()).into(),
+            public_key_hex: self.public_key_hex.clone(),
+        };
+        if restore_keys(&stored)?.public_key().to_hex() != self.public_key_hex {
+            return Err(KeyError::WrongPassphrase);
+        }
+
+        Ok(stored)
+    }
+}
+
+/// Secret key wrapped by a platform keystore key that never leaves it
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WrappedKeys {
+    pub public_key_hex: String,
+    /// Keystore that holds the wrapping key, e.g. "androidKeystore"
+    pub backend: String,
+    pub wrapped_hex: String,
+}
+
+impl WrappedKeys {
+    pub fn new(
+        stored: &StoredKeys,
+        backend: &str,
+        wrap: impl FnOnce(&[u8]) -> Result<Vec<u8>, KeyError>,
+    ) -> Result<Self, KeyError> {
+        let secret = stored.secret_bytes()?;
+
+        Ok(Self {
+            public_key_hex: stored.public_key_hex.clone(),
+            backend: backend.to_string(),
+            wrapped_hex: hex::encode(wrap(&secret)?),
+        })
+    }
+
+    /// Unwrap the secret and check it still belongs to our public key
+    pub fn open(
+        &self,
+        unwrap: impl FnOnce(&[u8]) -> Result<Vec<u8>, KeyError>,
+    ) -> Result<StoredKeys, KeyError> {
+        let wrapped =
+            hex::decode(&self.wrapped_hex).map_err(|e| KeyError::ParseError(e.to_string()))?;
+        let secret = Zeroizing::new(unwrap(&wrapped)?);
+        let stored = StoredKeys {
+            secret_key_hex: hex::encode(secret.as_slice()).into(),
+            public_key_hex: self.public_key_hex.clone(),
+        };
+        if restore_keys(&stored)?.public_key().to_hex() != self.public_key_hex {
+            return Err(KeyError::StorageError(
+                "unwrapped key does not match the public key".to_string(),
+            ));
+        }
+
+        Ok(stored)
+    }
+}
+
+/// Only the public key, for watching an identity whose secret key lives on
+/// another device. Nothing can be signed with it.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchOnlyKeys {
+    pub public_key_hex: String,
+}
+
+impl WatchOnlyKeys {
+    /// From an npub or a hex pubkey
+    pub fn parse(pubkey: &str) -> Result<Self, KeyError> {
+        let pubkey = pubkey.trim();
+        let public_key = PublicKey::from_bech32(pubkey)
+            .or_else(|_| PublicKey::from_hex(pubkey))
+            .map_err(|e| KeyError::ParseError(e.to_string()))?;
+
+        Ok(Self {
+            public_key_hex: public_key.to_hex(),
+        })
+    }
+}
+
+/// Keys as written to the store: sealed with a passphrase, wrapped by a
+/// platform keystore, plaintext, or only the public key
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PersistedKeys {
+    // Sealed first: its fields are a superset of the wrapped ones
+    Sealed(SealedKeys),
+    Wrapped(WrappedKeys),
+    Plain(StoredKeys),
+    // Last: every other form has its one field too
+    WatchOnly(WatchOnlyKeys),
+}
+
+impl PersistedKeys {
+    pub fn public_key_hex(&self) -> &str {
+        match self {
+            PersistedKeys::Sealed(sealed) => &sealed.public_key_hex,
+            PersistedKeys::Wrapped(wrapped) => &wrapped.public_key_hex,
+            PersistedKeys::Plain(stored) => &stored.public_key_hex,
+            PersistedKeys::WatchOnly(watch_only) => &watch_only.public_key_hex,
+        }
+    }
+}
+
+/// Where the secret key is kept at rest when it isn't passphrase-sealed
+pub trait KeyStorage: Send + Sync {
+    /// What to write to the store for `stored`
+    fn persist(&self, stored: &StoredKeys) -> Result<PersistedKeys, KeyError>;
+
+    /// Recover keys from the store; `None` while they are sealed, or if
+    /// there is no secret key (watch-only)
+    fn load(&self, persisted: PersistedKeys) -> Result<Option<StoredKeys>, KeyError>;
+}
+
+/// Plaintext (or passphrase-sealed) in the app store; used where no keystore
+/// is available, which includes Android unless built with `android-keystore`
+pub struct PlainKeyStorage;
+
+impl KeyStorage for PlainKeyStorage {
+    fn persist(&self, stored: &StoredKeys) -> Result<PersistedKeys, KeyError> {
+        // Written out as is, so this is the one copy of the secret we make
+        Ok(PersistedKeys::Plain(StoredKeys {
+            secret_key_hex: stored.secret_key_hex.clone(),
+            public_key_hex: stored.public_key_hex.clone(),
+        }))
+    }
+
+    fn load(&self, persisted: PersistedKeys) -> Result<Option<StoredKeys>, KeyError> {
+        match persisted {
+            PersistedKeys::Plain(stored) => Ok(Some(stored)),
+            PersistedKeys::Sealed(_) | PersistedKeys::WatchOnly(_) => Ok(None),
+            PersistedKeys::Wrapped(wrapped) => Err(KeyError::StorageError(format!(
+                "key is held by {}, which is not available",
+                wrapped.backend
+            ))),
+        }
+    }
+}
+
+/// The Android Keystore once the app has registered its bridge (builds with
+/// `android-keystore` only), the app store everywhere else
+pub fn platform_key_storage() -> Box<dyn KeyStorage> {
+    #[cfg(all(target_os = "android", feature = "android-keystore"))]
+    if let Some(keystore) = crate::android_keystore::AndroidKeystore::registered() {
+        return Box::new(keystore);
+    }
+
+    Box::new(PlainKeyStorage)
+}
+
+/// Whether the identity is passphrase-protected and currently unlocked
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyProtection {
+    pub encrypted: bool,
+    pub unlocked: bool,
+    /// Wrapped by a hardware-backed platform keystore
+    pub hardware_backed: bool,
+    /// Only the public key is here
+    pub watch_only: bool,
+    /// Why the stored keys failed the integrity check, if they did
+    pub corrupt: Option<String>,
+}
+
 /// Public key info returned to frontend
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -36,7 +391,30 @@ pub fn generate_keypair() -> Result<(Keys, StoredKeys), KeyError> {
     let keys = Keys::generate();
 
     let stored = StoredKeys {
-        secret_key_hex: keys.secret_key().to_secret_hex(),
+        secret_key_hex: keys.secret_key().to_secret_hex().into(),
+        public_key_hex: keys.public_key().to_hex(),
+    };
+
+    Ok((keys, stored))
+}
+
+/// Import an existing Nostr secret key given as `nsec1…` or 64 hex characters
+pub fn import_keypair(secret: &str) -> Result<(Keys, StoredKeys), KeyError> {
+    let secret = secret.trim();
+
+    let secret_key = if secret.starts_with("nsec1") {
+        SecretKey::from_bech32(secret).map_err(|e| KeyError::ParseError(e.to_string()))?
+    } else if secret.len() == 64 && secret.chars().all(|c| c.is_ascii_hexdigit()) {
+        SecretKey::from_hex(secret).map_err(|e| KeyError::ParseError(e.to_string()))?
+    } else {
+        return Err(KeyError::ParseError(
+            "expected an nsec or a 64-character hex secret key".to_string(),
+        ));
+    };
+    let keys = Keys::new(secret_key);
+
+    let stored = StoredKeys {
+        secret_key_hex: keys.secret_key().to_secret_hex().into(),
         public_key_hex: keys.public_key().to_hex(),
     };
 
@@ -45,12 +423,198 @@ pub fn generate_keypair() -> Result<(Keys, StoredKeys), KeyError> {
 
 /// Restore keys from stored data
 pub fn restore_keys(stored: &StoredKeys) -> Result<Keys, KeyError> {
-    let secret_key = SecretKey::from_hex(&stored.secret_key_hex)
+    let secret_key = SecretKey::from_hex(stored.secret_key_hex.as_str())
         .map_err(|e| KeyError::ParseError(e.to_string()))?;
 
     Ok(Keys::new(secret_key))
 }
 
+/// Our secret key as an nsec, for backing up the identity
+pub fn secret_key_nsec(stored: &StoredKeys) -> Result<String, KeyError> {
+    restore_keys(stored)?
+        .secret_key()
+        .to_bech32()
+        .map_err(|e| KeyError::ParseError(e.to_string()))
+}
+
+/// One-time token the UI passes back, after the user confirmed, to export
+/// the secret key
+#[derive(Clone, Debug)]
+pub struct ExportConfirmation {
+    pub token: String,
+    pub expires_at: u64,
+}
+
+impl ExportConfirmation {
+    pub fn new(now: u64) -> Self {
+        Self {
+            token: hex::encode(rand::random::<[u8; 16]>()),
+            expires_at: now + EXPORT_CONFIRMATION_TTL_SECS,
+        }
+    }
+
+    pub fn accepts(&self, token: &str, now: u64) -> bool {
+        now <= self.expires_at && self.token == token
+    }
+}
+
+/// `type` field of a key rotation statement
+pub const ROTATION_TYPE: &str = "sneakernet-key-rotation";
+
+/// Announcement that `old_pubkey` has been replaced by `new_pubkey`.
+///
+/// The old key signs the new one so contacts know the change comes from the
+/// identity they exchanged with; the new key countersigns to prove it is
+/// held by the same person.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RotationStatement {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub version: u32,
+    pub old_pubkey: String,
+    pub new_pubkey: String,
+    pub rotated_at: u64,
+    pub old_signature: String,
+    pub new_signature: String,
+}
+
+impl RotationStatement {
+    pub fn new(old_keys: &Keys, new_keys: &Keys, now: u64) -> Result<Self, KeyError> {
+        let mut statement = Self {
+            msg_type: ROTATION_TYPE.to_string(),
+            version: 1,
+            old_pubkey: old_keys.public_key().to_hex(),
+            new_pubkey: new_keys.public_key().to_hex(),
+            rotated_at: now,
+            old_signature: String::new(),
+            new_signature: String::new(),
+        };
+        let content = statement.signing_content();
+        statement.old_signature = sign_content(old_keys, &content)?;
+        statement.new_signature = sign_content(new_keys, &content)?;
+
+        Ok(statement)
+    }
+
+    fn signing_content(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{}",
+            ROTATION_TYPE, self.version, self.old_pubkey, self.new_pubkey, self.rotated_at
+        )
+    }
+
+    pub fn verify(&self) -> Result<(), KeyError> {
+        if self.msg_type != ROTATION_TYPE || self.version != 1 {
+            return Err(KeyError::ParseError("not a key rotation statement".to_string()));
+        }
+        if self.old_pubkey == self.new_pubkey {
+            return Err(KeyError::ParseError("old and new key are the same".to_string()));
+        }
+
+        let content = self.signing_content();
+        verify_content(&self.old_pubkey, &content, &self.old_signature)?;
+        verify_content(&self.new_pubkey, &content, &self.new_signature)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, KeyError> {
+        serde_json::from_str(json.trim()).map_err(|e| KeyError::ParseError(e.to_string()))
+    }
+}
+
+/// `type` field of a key revocation statement
+pub const REVOCATION_TYPE: &str = "sneakernet-key-revocation";
+
+/// Nostr event kind a revocation statement is published to relays as
+pub const REVOCATION_EVENT_KIND: u16 = 4_735;
+
+/// Longest reason a revocation statement may give
+pub const MAX_REVOCATION_REASON_LEN: usize = 280;
+
+/// Announcement that `pubkey` is compromised and must no longer be trusted.
+///
+/// Signed by the revoked key itself. Whoever else holds it can only use that
+/// to take the key out of service, which is what its owner wants once it has
+/// leaked. Unlike a rotation there is no successor: contacts meet again to
+/// exchange a new key.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RevocationStatement {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub version: u32,
+    pub pubkey: String,
+    pub revoked_at: u64,
+    pub reason: Option<String>,
+    pub signature: String,
+}
+
+impl RevocationStatement {
+    pub fn new(keys: &Keys, reason: Option<String>, now: u64) -> Result<Self, KeyError> {
+        let mut statement = Self {
+            msg_type: REVOCATION_TYPE.to_string(),
+            version: 1,
+            pubkey: keys.public_key().to_hex(),
+            revoked_at: now,
+            reason: reason.filter(|r| !r.trim().is_empty()),
+            signature: String::new(),
+        };
+        statement.check_reason()?;
+        statement.signature = sign_content(keys, &statement.signing_content())?;
+
+        Ok(statement)
+    }
+
+    fn signing_content(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{}",
+            REVOCATION_TYPE,
+            self.version,
+            self.pubkey,
+            self.revoked_at,
+            self.reason.as_deref().unwrap_or("")
+        )
+    }
+
+    fn check_reason(&self) -> Result<(), KeyError> {
+        match &self.reason {
+            Some(reason) if reason.len() > MAX_REVOCATION_REASON_LEN => Err(KeyError::ParseError(
+                "revocation reason is too long".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn verify(&self) -> Result<(), KeyError> {
+        if self.msg_type != REVOCATION_TYPE || self.version != 1 {
+            return Err(KeyError::ParseError(
+                "not a key revocation statement".to_string(),
+            ));
+        }
+        self.check_reason()?;
+        verify_content(&self.pubkey, &self.signing_content(), &self.signature)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, KeyError> {
+        serde_json::from_str(json.trim()).map_err(|e| KeyError::ParseError(e.to_string()))
+    }
+
+    /// The statement as a Nostr event from the revoked key, for relays
+    pub fn to_event(&self, keys: &Keys) -> Result<SignedEvent, KeyError> {
+        let content =
+            serde_json::to_string(self).map_err(|e| KeyError::ParseError(e.to_string()))?;
+        Ok(sign_event(
+            keys,
+            &EventTemplate {
+                created_at: self.revoked_at,
+                kind: REVOCATION_EVENT_KIND,
+                tags: vec![vec!["t".to_string(), REVOCATION_TYPE.to_string()]],
+                content,
+            },
+        ))
+    }
+}
+
 /// Get public key info from keys
 pub fn get_public_key_info(keys: &Keys) -> Result<NostrKeysInfo, KeyError> {
     let public_key = keys.public_key();
@@ -63,12 +627,252 @@ pub fn get_public_key_info(keys: &Keys) -> Result<NostrKeysInfo, KeyError> {
     })
 }
 
+/// Get public key info from a hex pubkey alone
+pub fn get_public_key_info_from_hex(pubkey_hex: &str) -> Result<NostrKeysInfo, KeyError> {
+    let public_key =
+        PublicKey::from_hex(pubkey_hex).map_err(|e| KeyError::ParseError(e.to_string()))?;
+
+    Ok(NostrKeysInfo {
+        public_key: public_key.to_hex(),
+        public_key_bech32: public_key
+            .to_bech32()
+            .map_err(|e| KeyError::ParseError(e.to_string()))?,
+    })
+}
+
 /// Get public key info from stored keys
 pub fn get_public_key_info_from_stored(stored: &StoredKeys) -> Result<NostrKeysInfo, KeyError> {
     let keys = restore_keys(stored)?;
     get_public_key_info(&keys)
 }
 
+/// Sign arbitrary content with a Schnorr signature over its SHA256 hash.
+///
+/// Used for signed records outside the exchange handshake (group membership,
+/// introductions, ...). Returns the signature as hex.
+pub fn sign_content(keys: &Keys, content: &str) -> Result<String, KeyError> {
+    let hash: [u8; 32] = Sha256::digest(content.as_bytes()).into();
+    Ok(sign_digest(keys, hash))
+}
+
+fn sign_digest(keys: &Keys, digest: [u8; 32]) -> String {
+    let message = Secp256k1Message::from_digest(digest);
+
+    let secp = Secp256k1::new();
+    let keypair = secp256k1::Keypair::from_secret_key(&secp, keys.secret_key());
+
+    hex::encode(secp.sign_schnorr(&message, &keypair).serialize())
+}
+
+/// Verify a signature produced by [`sign_content`]
+pub fn verify_content(pubkey_hex: &str, content: &str, signature_hex: &str) -> Result<(), KeyError> {
+    verify_content_as(pubkey_hex, content, signature_hex, SigScheme::Digest)
+}
+
+/// Verify a signature produced by [`Signer::sign_content`], made the way
+/// the record says. Only that scheme is tried.
+pub fn verify_content_as(
+    pubkey_hex: &str,
+    content: &str,
+    signature_hex: &str,
+    scheme: SigScheme,
+) -> Result<(), KeyError> {
+    let digest = match scheme {
+        SigScheme::Digest => Sha256::digest(content.as_bytes()).into(),
+        SigScheme::Nip01 => EventTemplate::for_content(content).id(pubkey_hex),
+    };
+    verify_digest(pubkey_hex, digest, signature_hex)
+}
+
+fn verify_digest(pubkey_hex: &str, digest: [u8; 32], signature_hex: &str) -> Result<(), KeyError> {
+    let message = Secp256k1Message::from_digest(digest);
+
+    let pubkey_bytes = hex::decode(pubkey_hex).map_err(|e| KeyError::ParseError(e.to_string()))?;
+    let xonly = XOnlyPublicKey::from_slice(&pubkey_bytes)
+        .map_err(|e| KeyError::ParseError(e.to_string()))?;
+
+    let sig_bytes = hex::decode(signature_hex).map_err(|_| KeyError::InvalidSignature)?;
+    let signature = secp256k1::schnorr::Signature::from_slice(&sig_bytes)
+        .map_err(|_| KeyError::InvalidSignature)?;
+
+    Secp256k1::verification_only()
+        .verify_schnorr(&signature, &message, &xonly)
+        .map_err(|_| KeyError::InvalidSignature)
+}
+
+// ============================================================================
+// Signers
+// ============================================================================
+
+/// Kind of the event an event-only signer (NIP-46) signs in place of raw
+/// content. It is dated 0 and has no tags, so it means nothing on a relay.
+pub const CONTENT_EVENT_KIND: u16 = 27_333;
+
+/// Start of a content event's content, so an event of the same kind signed
+/// for another app can't pass as one of our records
+pub const CONTENT_EVENT_DOMAIN: &str = "sneakernet-signed-record:";
+
+/// How a record's signature was made. Records that a remote signer may sign
+/// carry it, covered by the signature, and are verified that way only.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SigScheme {
+    /// Schnorr signature over the SHA-256 of the content ([`sign_content`])
+    #[default]
+    Digest,
+    /// Schnorr signature over the id of the content event, from a signer
+    /// that only signs events ([`EventTemplate::for_content`])
+    Nip01,
+}
+
+impl SigScheme {
+    pub fn is_digest(&self) -> bool {
+        *self == SigScheme::Digest
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SigScheme::Digest => "digest",
+            SigScheme::Nip01 => "nip01",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "digest" => Some(SigScheme::Digest),
+            "nip01" => Some(SigScheme::Nip01),
+            _ => None,
+        }
+    }
+}
+
+/// A Nostr event before signing; the signer supplies the pubkey
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EventTemplate {
+    pub created_at: u64,
+    pub kind: u16,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+}
+
+impl EventTemplate {
+    /// The event standing in for `content` in [`Signer::sign_content`]
+    pub fn for_content(content: &str) -> Self {
+        Self {
+            created_at: 0,
+            kind: CONTENT_EVENT_KIND,
+            tags: Vec::new(),
+            content: format!("{}{}", CONTENT_EVENT_DOMAIN, content),
+        }
+    }
+
+    /// NIP-01 event id once signed by `pubkey_hex`
+    pub fn id(&self, pubkey_hex: &str) -> [u8; 32] {
+        let serialized = serde_json::json!([
+            0,
+            pubkey_hex,
+            self.created_at,
+            self.kind,
+            self.tags,
+            self.content
+        ]);
+        Sha256::digest(serialized.to_string().as_bytes()).into()
+    }
+}
+
+/// A signed Nostr event
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SignedEvent {
+    pub id: String,
+    pub pubkey: String,
+    pub created_at: u64,
+    pub kind: u16,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+    pub sig: String,
+}
+
+impl SignedEvent {
+    pub fn template(&self) -> EventTemplate {
+        EventTemplate {
+            created_at: self.created_at,
+            kind: self.kind,
+            tags: self.tags.clone(),
+            content: self.content.clone(),
+        }
+    }
+
+    /// Check the id matches the event and the signature matches the id
+    pub fn verify(&self) -> Result<(), KeyError> {
+        let id = self.template().id(&self.pubkey);
+        if hex::encode(id) != self.id {
+            return Err(KeyError::InvalidSignature);
+        }
+        verify_digest(&self.pubkey, id, &self.sig)
+    }
+}
+
+/// Sign `event` with local keys
+pub fn sign_event(keys: &Keys, event: &EventTemplate) -> SignedEvent {
+    let pubkey = keys.public_key().to_hex();
+    let id = event.id(&pubkey);
+    SignedEvent {
+        id: hex::encode(id),
+        sig: sign_digest(keys, id),
+        pubkey,
+        created_at: event.created_at,
+        kind: event.kind,
+        tags: event.tags.clone(),
+        content: event.content.clone(),
+    }
+}
+
+/// Future returned by [`Signer`] methods
+pub type SignerFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, KeyError>> + Send + 'a>>;
+
+/// Signs for our identity: local `Keys`, or a remote signer that holds the
+/// secret key elsewhere (see `nip46`)
+pub trait Signer: Send + Sync {
+    fn public_key_hex(&self) -> String;
+
+    fn sign_event<'a>(&'a self, event: &'a EventTemplate) -> SignerFuture<'a, SignedEvent>;
+
+    /// How [`Signer::sign_content`] signs. A record must include it in
+    /// what it signs, then be verified with [`verify_content_as`].
+    fn sig_scheme(&self) -> SigScheme {
+        SigScheme::Nip01
+    }
+
+    /// Sign a record as [`sign_content`] does. Signers that only sign events
+    /// sign it wrapped in a content event instead (see [`SigScheme::Nip01`]).
+    fn sign_content<'a>(&'a self, content: &'a str) -> SignerFuture<'a, String> {
+        Box::pin(async move {
+            let event = self
+                .sign_event(&EventTemplate::for_content(content))
+                .await?;
+            Ok(event.sig)
+        })
+    }
+}
+
+impl Signer for Keys {
+    fn public_key_hex(&self) -> String {
+        self.public_key().to_hex()
+    }
+
+    fn sign_event<'a>(&'a self, event: &'a EventTemplate) -> SignerFuture<'a, SignedEvent> {
+        Box::pin(async move { Ok(sign_event(self, event)) })
+    }
+
+    fn sig_scheme(&self) -> SigScheme {
+        SigScheme::Digest
+    }
+
+    fn sign_content<'a>(&'a self, content: &'a str) -> SignerFuture<'a, String> {
+        Box::pin(async move { sign_content(self, content) })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -92,6 +896,239 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_validate_stored_keys() {
+        let (_, stored) = generate_keypair().unwrap();
+        stored.validate().unwrap();
+
+        let (_, other) = generate_keypair().unwrap();
+        let mismatched = StoredKeys {
+            secret_key_hex: stored.secret_key_hex.clone(),
+            public_key_hex: other.public_key_hex,
+        };
+        assert!(matches!(mismatched.validate(), Err(KeyError::CorruptKeys(_))));
+
+        let truncated = StoredKeys {
+            secret_key_hex: stored.secret_key_hex[..62].to_string().into(),
+            public_key_hex: stored.public_key_hex.clone(),
+        };
+        assert!(matches!(truncated.validate(), Err(KeyError::CorruptKeys(_))));
+    }
+
+    #[test]
+    fn test_sign_and_verify_content() {
+        let (keys, stored) = generate_keypair().unwrap();
+        let signature = sign_content(&keys, "hello").unwrap();
+
+        verify_content(&stored.public_key_hex, "hello", &signature).unwrap();
+        assert!(matches!(
+            verify_content(&stored.public_key_hex, "tampered", &signature),
+            Err(KeyError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_content_event_signature() {
+        let (keys, stored) = generate_keypair().unwrap();
+
+        // What an event-only signer returns for sign_content
+        let event = sign_event(&keys, &EventTemplate::for_content("hello"));
+        event.verify().unwrap();
+        assert!(event.content.starts_with(CONTENT_EVENT_DOMAIN));
+        let pubkey = &stored.public_key_hex;
+        verify_content_as(pubkey, "hello", &event.sig, SigScheme::Nip01).unwrap();
+        assert!(verify_content_as(pubkey, "tampered", &event.sig, SigScheme::Nip01).is_err());
+
+        // Only under the scheme the record names
+        assert!(verify_content(pubkey, "hello", &event.sig).is_err());
+        let digest = sign_content(&keys, "hello").unwrap();
+        assert!(verify_content_as(pubkey, "hello", &digest, SigScheme::Nip01).is_err());
+
+        // Nor is any other event of the kind a record
+        let other = sign_event(
+            &keys,
+            &EventTemplate {
+                content: "hello".to_string(),
+                ..EventTemplate::for_content("")
+            },
+        );
+        assert!(verify_content_as(pubkey, "hello", &other.sig, SigScheme::Nip01).is_err());
+
+        let mut forged = event.clone();
+        forged.content = "tampered".to_string();
+        assert!(forged.verify().is_err());
+    }
+
+    #[test]
+    fn test_import_keypair() {
+        let (keys, stored) = generate_keypair().unwrap();
+        let nsec = keys.secret_key().to_bech32().unwrap();
+
+        let (_, from_nsec) = import_keypair(&nsec).unwrap();
+        assert_eq!(from_nsec.public_key_hex, stored.public_key_hex);
+
+        let padded = format!(" {} ", stored.secret_key_hex.to_uppercase());
+        let (_, from_hex) = import_keypair(&padded).unwrap();
+        assert_eq!(from_hex.public_key_hex, stored.public_key_hex);
+        assert_eq!(from_hex.secret_key_hex, stored.secret_key_hex);
+
+        assert!(import_keypair(&stored.public_key_hex[..60]).is_err());
+        assert!(import_keypair("nsec1garbage").is_err());
+        assert!(import_keypair(&"0".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn test_export_confirmation() {
+        let (keys, stored) = generate_keypair().unwrap();
+        let nsec = secret_key_nsec(&stored).unwrap();
+        assert!(nsec.starts_with("nsec1"));
+        assert_eq!(import_keypair(&nsec).unwrap().0.public_key(), keys.public_key());
+
+        let confirmation = ExportConfirmation::new(1_000);
+        assert!(confirmation.accepts(&confirmation.token, 1_000 + EXPORT_CONFIRMATION_TTL_SECS));
+        assert!(!confirmation.accepts(&confirmation.token, 1_001 + EXPORT_CONFIRMATION_TTL_SECS));
+        assert!(!confirmation.accepts("guess", 1_000));
+    }
+
+    /// Cheap parameters so tests don't spend seconds in Argon2
+    fn test_kdf() -> KdfParams {
+        KdfParams {
+            m_cost: 64,
+            t_cost: 1,
+            p_cost: 1,
+            salt_hex: hex::encode([7u8; 16]),
+        }
+    }
+
+    #[test]
+    fn test_seal_unseal() {
+        let (_, stored) = generate_keypair().unwrap();
+        let sealed = SealedKeys::seal_with(&stored, "correct horse", test_kdf()).unwrap();
+        assert!(!sealed.ciphertext_hex.contains(stored.secret_key_hex.as_str()));
+
+        let unsealed = sealed.unseal("correct horse").unwrap();
+        assert_eq!(unsealed.secret_key_hex, stored.secret_key_hex);
+        assert!(matches!(sealed.unseal("wrong horse"), Err(KeyError::WrongPassphrase)));
+
+        // The public key is bound to the ciphertext
+        let mut swapped = sealed.clone();
+        swapped.public_key_hex = generate_keypair().unwrap().1.public_key_hex;
+        assert!(matches!(swapped.unseal("correct horse"), Err(KeyError::WrongPassphrase)));
+
+        assert!(matches!(
+            SealedKeys::seal_with(&stored, "short", test_kdf()),
+            Err(KeyError::WeakPassphrase)
+        ));
+    }
+
+    #[test]
+    fn test_persisted_keys_format() {
+        let (_, stored) = generate_keypair().unwrap();
+
+        let plain: PersistedKeys = serde_json::from_value(serde_json::json!(stored)).unwrap();
+        assert!(matches!(plain, PersistedKeys::Plain(_)));
+
+        let sealed = SealedKeys::seal_with(&stored, "correct horse", test_kdf()).unwrap();
+        let json = serde_json::to_value(PersistedKeys::Sealed(sealed)).unwrap();
+        assert!(json.get("secret_key_hex").is_none());
+        let parsed: PersistedKeys = serde_json::from_value(json).unwrap();
+        assert!(matches!(parsed, PersistedKeys::Sealed(_)));
+        assert_eq!(parsed.public_key_hex(), stored.public_key_hex);
+
+        let npub = get_public_key_info_from_stored(&stored).unwrap().public_key_bech32;
+        let watch_only = WatchOnlyKeys::parse(&npub).unwrap();
+        let json = serde_json::to_value(PersistedKeys::WatchOnly(watch_only)).unwrap();
+        let parsed: PersistedKeys = serde_json::from_value(json).unwrap();
+        assert!(matches!(parsed, PersistedKeys::WatchOnly(_)));
+        assert_eq!(parsed.public_key_hex(), stored.public_key_hex);
+        assert!(PlainKeyStorage.load(parsed).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_wrapped_keys() {
+        let (_, stored) = generate_keypair().unwrap();
+        let xor = |bytes: &[u8]| -> Result<Vec<u8>, KeyError> {
+            Ok(bytes.iter().map(|b| b ^ 0x5A).collect())
+        };
+
+        let wrapped = WrappedKeys::new(&stored, "test", xor).unwrap();
+        let json = serde_json::to_value(PersistedKeys::Wrapped(wrapped)).unwrap();
+        let persisted: PersistedKeys = serde_json::from_value(json).unwrap();
+        let PersistedKeys::Wrapped(wrapped) = &persisted else {
+            panic!("expected wrapped keys");
+        };
+        assert_eq!(wrapped.open(xor).unwrap().secret_key_hex, stored.secret_key_hex);
+        assert!(wrapped.open(|bytes| Ok(bytes.to_vec())).is_err());
+
+        // Without the keystore the key can't be recovered
+        assert!(PlainKeyStorage.load(persisted).is_err());
+        let plain = PlainKeyStorage.persist(&stored).unwrap();
+        assert!(PlainKeyStorage.load(plain).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_rotation_statement() {
+        let (old, _) = generate_keypair().unwrap();
+        let (new, _) = generate_keypair().unwrap();
+        let statement = RotationStatement::new(&old, &new, 1_000).unwrap();
+        statement.verify().unwrap();
+
+        let json = serde_json::to_string(&statement).unwrap();
+        assert_eq!(RotationStatement::from_json(&json).unwrap(), statement);
+
+        // Only the old key can announce its successor
+        let (mallory, _) = generate_keypair().unwrap();
+        let mut forged = RotationStatement::new(&mallory, &new, 1_000).unwrap();
+        forged.old_pubkey = statement.old_pubkey.clone();
+        assert!(matches!(forged.verify(), Err(KeyError::InvalidSignature)));
+
+        // ...and the new key must countersign
+        let mut hijacked = statement.clone();
+        hijacked.new_pubkey = mallory.public_key().to_hex();
+        assert!(hijacked.verify().is_err());
+    }
+
+    #[test]
+    fn test_revocation_statement() {
+        let (keys, _) = generate_keypair().unwrap();
+        let statement =
+            RevocationStatement::new(&keys, Some("phone stolen".to_string()), 1_000).unwrap();
+        statement.verify().unwrap();
+
+        let json = serde_json::to_string(&statement).unwrap();
+        assert_eq!(RevocationStatement::from_json(&json).unwrap(), statement);
+
+        // Only the key itself can revoke it
+        let (mallory, _) = generate_keypair().unwrap();
+        let mut forged = RevocationStatement::new(&mallory, None, 1_000).unwrap();
+        forged.pubkey = statement.pubkey.clone();
+        assert!(matches!(forged.verify(), Err(KeyError::InvalidSignature)));
+
+        // The reason is signed too
+        let mut reworded = statement.clone();
+        reworded.reason = Some("never mind".to_string());
+        assert!(reworded.verify().is_err());
+
+        let event = statement.to_event(&keys).unwrap();
+        event.verify().unwrap();
+        assert_eq!(event.pubkey, statement.pubkey);
+        assert_eq!(event.kind, REVOCATION_EVENT_KIND);
+    }
+
+    #[test]
+    fn test_event_kinds_are_distinct() {
+        // A relay filter for one of our kinds must never return another
+        let kinds = [
+            REVOCATION_EVENT_KIND,
+            CONTENT_EVENT_KIND,
+            crate::nip46::NOSTR_CONNECT_KIND,
+            crate::remote::REMOTE_EXCHANGE_KIND,
+            crate::weblink::WEB_LINK_KIND,
+        ];
+        let unique: std::collections::HashSet<_> = kinds.iter().collect();
+        assert_eq!(unique.len(), kinds.len());
+    }
+
     #[test]
     fn test_public_key_info() {
         let (keys, _) = generate_keypair().unwrap();
@@ -0,0 +1,275 @@
+//! Local-network exchange
+//!
+//! For tablets and laptops without NFC. One device hosts a short-lived TCP
+//! listener and advertises it over mDNS as `_sneakernet._tcp`; the other
+//! browses for it and connects. The handshake is the usual signed
+//! `ExchangeMessage` exchange, one encoded payload per line:
+//!
+//! ```text
+//! host  -> guest  initial message
+//! guest -> host   response naming the host
+//! host  -> guest  response naming the guest
+//! ```
+//!
+//! Being on the same network proves nothing: every payload is verified like
+//! a scanned one, and the user still confirms the contact. The advertisement
+//! carries a random instance name only, never a pubkey.
+
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// mDNS service type hosts advertise
+pub const SERVICE_TYPE: &str = "_sneakernet._tcp.local.";
+
+/// How long a host waits for someone to connect
+pub const HOST_TIMEOUT_SECS: u64 = 120;
+
+/// How long a guest browses for hosts
+pub const DISCOVERY_SECS: u64 = 3;
+
+/// How long either side waits for the other's next payload
+pub const STEP_TIMEOUT_SECS: u64 = 15;
+
+/// Longest payload line accepted; exchange messages are well under this
+pub const MAX_PAYLOAD_LEN: usize = 16 * 1024;
+
+#[derive(Error, Debug)]
+pub enum LanError {
+    #[error("mDNS error: {0}")]
+    Mdns(String),
+    #[error("Network error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The other device took too long to answer")]
+    Timeout,
+    #[error("The other device closed the connection")]
+    Closed,
+    #[error("Payload too large")]
+    PayloadTooLarge,
+    #[error("Payload is not a single line")]
+    InvalidPayload,
+}
+
+impl From<mdns_sd::Error> for LanError {
+    fn from(e: mdns_sd::Error) -> Self {
+        LanError::Mdns(e.to_string())
+    }
+}
+
+/// A host found on the local network
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct LanPeer {
+    /// Random instance name the host shows on screen, e.g. `SneakerNet-1A2B`
+    pub name: String,
+    /// `ip:port` to pass to `join_lan_exchange`
+    pub address: String,
+}
+
+/// What a host is advertising, so its screen can show the name to pick
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LanHostInfo {
+    pub name: String,
+    pub port: u16,
+}
+
+/// A random, human-checkable instance name
+pub fn instance_name() -> String {
+    format!("SneakerNet-{:04X}", rand::random::<u16>())
+}
+
+/// Our mDNS advertisement, withdrawn when dropped
+pub struct Advertisement {
+    daemon: ServiceDaemon,
+    fullname: String,
+    info: LanHostInfo,
+}
+
+impl Advertisement {
+    /// Advertise a listener on `port` under a fresh instance name
+    pub fn start(port: u16) -> Result<Self, LanError> {
+        let name = instance_name();
+        let daemon = ServiceDaemon::new()?;
+        let service = ServiceInfo::new(
+            SERVICE_TYPE,
+            &name,
+            &format!("{}.local.", name),
+            "",
+            port,
+            HashMap::<String, String>::new(),
+        )?
+        .enable_addr_auto();
+        let fullname = service.get_fullname().to_string();
+        daemon.register(service)?;
+
+        Ok(Self {
+            daemon,
+            fullname,
+            info: LanHostInfo { name, port },
+        })
+    }
+
+    pub fn info(&self) -> &LanHostInfo {
+        &self.info
+    }
+}
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        let _ = self.daemon.unregister(&self.fullname);
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// Browse for hosts for `timeout`. Blocks; run it off the async runtime.
+pub fn discover(timeout: Duration) -> Result<Vec<LanPeer>, LanError> {
+    let daemon = ServiceDaemon::new()?;
+    let events = daemon.browse(SERVICE_TYPE)?;
+    let deadline = Instant::now() + timeout;
+
+    let mut peers: Vec<LanPeer> = Vec::new();
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        let Ok(event) = events.recv_timeout(remaining) else {
+            break;
+        };
+        let ServiceEvent::ServiceResolved(info) = event else {
+            continue;
+        };
+        // Prefer IPv4, which every LAN routes
+        let mut addresses: Vec<_> = info.get_addresses().iter().copied().collect();
+        addresses.sort_by_key(|ip| !ip.is_ipv4());
+        let Some(ip) = addresses.first() else {
+            continue;
+        };
+
+        let name = info
+            .get_fullname()
+            .trim_end_matches(SERVICE_TYPE)
+            .trim_end_matches('.')
+            .to_string();
+        if peers.iter().all(|p| p.name != name) {
+            peers.push(LanPeer {
+                name,
+                address: SocketAddr::new(*ip, info.get_port()).to_string(),
+            });
+        }
+    }
+
+    let _ = daemon.stop_browse(SERVICE_TYPE);
+    let _ = daemon.shutdown();
+    Ok(peers)
+}
+
+/// One end of the handshake: newline-terminated payloads over a stream
+pub struct LanConnection<S> {
+    stream: BufReader<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> LanConnection<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    pub async fn send(&mut self, payload: &str) -> Result<(), LanError> {
+        if payload.contains('\n') {
+            return Err(LanError::InvalidPayload);
+        }
+        if payload.len() > MAX_PAYLOAD_LEN {
+            return Err(LanError::PayloadTooLarge);
+        }
+
+        let write = async {
+            let stream = self.stream.get_mut();
+            stream.write_all(payload.as_bytes()).await?;
+            stream.write_all(b"\n").await?;
+            stream.flush().await
+        };
+        tokio::time::timeout(Duration::from_secs(STEP_TIMEOUT_SECS), write)
+            .await
+            .map_err(|_| LanError::Timeout)??;
+        Ok(())
+    }
+
+    pub async fn receive(&mut self) -> Result<String, LanError> {
+        let mut line = Vec::new();
+        let mut limited = (&mut self.stream).take(MAX_PAYLOAD_LEN as u64 + 1);
+        tokio::time::timeout(
+            Duration::from_secs(STEP_TIMEOUT_SECS),
+            limited.read_until(b'\n', &mut line),
+        )
+        .await
+        .map_err(|_| LanError::Timeout)??;
+
+        match line.pop() {
+            Some(b'\n') => {}
+            _ if line.len() >= MAX_PAYLOAD_LEN => return Err(LanError::PayloadTooLarge),
+            _ => return Err(LanError::Closed),
+        }
+        String::from_utf8(line).map_err(|_| LanError::InvalidPayload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn test_payloads_round_trip() {
+        block_on(async {
+            let (a, b) = tokio::io::duplex(4096);
+            let mut host = LanConnection::new(a);
+            let mut guest = LanConnection::new(b);
+
+            host.send("initial").await.unwrap();
+            assert_eq!(guest.receive().await.unwrap(), "initial");
+            guest.send("response").await.unwrap();
+            assert_eq!(host.receive().await.unwrap(), "response");
+
+            drop(guest);
+            assert!(matches!(host.receive().await, Err(LanError::Closed)));
+        });
+    }
+
+    #[test]
+    fn test_oversized_payloads_are_refused() {
+        block_on(async {
+            let (a, b) = tokio::io::duplex(64 * 1024);
+            let mut host = LanConnection::new(a);
+            let guest = LanConnection::new(b);
+
+            let huge = "x".repeat(MAX_PAYLOAD_LEN + 1);
+            assert!(matches!(
+                host.send(&huge).await,
+                Err(LanError::PayloadTooLarge)
+            ));
+            assert!(matches!(
+                host.send("two\nlines").await,
+                Err(LanError::InvalidPayload)
+            ));
+
+            // A peer ignoring the limit is cut off rather than buffered
+            let mut raw = guest.stream.into_inner();
+            raw.write_all(huge.as_bytes()).await.unwrap();
+            raw.write_all(b"\n").await.unwrap();
+            assert!(matches!(
+                host.receive().await,
+                Err(LanError::PayloadTooLarge)
+            ));
+        });
+    }
+}
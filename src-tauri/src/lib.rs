@@ -4,53 +4,320 @@
 //! It handles Nostr key management, NFC/QR exchange protocol, Iroh key derivation,
 //! and p2p chat functionality.
 
+#[cfg(all(target_os = "android", feature = "android-keystore"))]
+pub mod android_keystore;
+pub mod audio;
+pub mod audit;
+pub mod backup;
+pub mod beacon;
+pub mod binding;
+pub mod broadcast;
+pub mod card;
 pub mod chat;
 pub mod commands;
+pub mod conference;
+pub mod contact_store;
+pub mod delivery;
+pub mod device;
+pub mod disappearing;
 pub mod exchange;
+pub mod filters;
+pub mod groups;
+pub mod hce;
+pub mod hybrid;
+pub mod identifier;
+pub mod intake;
 pub mod iroh_derive;
 pub mod iroh_node;
 pub mod keys;
+pub mod lan;
+pub mod listener;
+pub mod message_store;
+pub mod message_types;
+pub mod migration;
+#[cfg(feature = "mock-transport")]
+pub mod mock_transport;
+pub mod moderation;
+pub mod multipart;
+pub mod nfc;
+pub mod nip46;
+pub mod outbox;
+pub mod paper;
+pub mod pcsc_nfc;
+pub mod petname;
+pub mod ping;
+pub mod plugins;
+pub mod profile;
+pub mod queue;
+pub mod ratchet;
+pub mod recovery;
+pub mod relay;
+pub mod remote;
+pub mod replay;
+pub mod roster;
+pub mod safety;
+pub mod schedule;
+pub mod settings;
+pub mod share;
+pub mod state;
+pub mod stats;
+pub mod trace;
+#[cfg(desktop)]
+pub mod tray;
+pub mod trust;
+pub mod typing;
+pub mod weblink;
+pub mod wipe;
 
-use commands::AppState;
+use state::AppState;
+use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_store::Builder::default().build())
+        .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_deep_link::init())
         .setup(|_app| {
+            if let Ok(dir) = _app.path().app_data_dir() {
+                wipe::finish_interrupted(&dir);
+            }
+            // sneakernet://exchange/... links, at launch or while running
+            {
+                use tauri_plugin_deep_link::DeepLinkExt;
+                let handle = _app.handle().clone();
+                _app.deep_link().on_open_url(move |event| {
+                    let uris = event.urls().iter().map(ToString::to_string).collect();
+                    commands::deliver_exchange_uris(&handle, uris);
+                });
+                if let Ok(Some(urls)) = _app.deep_link().get_current() {
+                    let uris = urls.iter().map(ToString::to_string).collect();
+                    commands::deliver_exchange_uris(_app.handle(), uris);
+                }
+                #[cfg(any(target_os = "linux", all(debug_assertions, windows)))]
+                _app.deep_link().register_all()?;
+            }
             #[cfg(mobile)]
             {
                 _app.handle().plugin(tauri_plugin_nfc::init())?;
                 _app.handle().plugin(tauri_plugin_barcode_scanner::init())?;
             }
+            #[cfg(desktop)]
+            tray::setup(_app.handle())?;
+            schedule::spawn_scheduler(_app.handle().clone());
+            disappearing::spawn_sweeper(_app.handle().clone());
+            commands::spawn_pending_expiry(_app.handle().clone());
             Ok(())
         })
+        .on_window_event(|_window, _event| {
+            #[cfg(desktop)]
+            tray::on_window_event(_window, _event);
+        })
         .manage(AppState::default())
         .invoke_handler(tauri::generate_handler![
             // Key management
             commands::has_keys,
             commands::generate_keys,
+            commands::import_keys,
+            commands::init_watch_only,
+            commands::split_key_shares,
+            commands::recover_from_shares,
+            commands::distribute_recovery_shares,
+            commands::get_recovery_guardians,
+            commands::get_held_recovery_shares,
+            commands::get_recovery_requests,
+            commands::approve_recovery_request,
+            commands::decline_recovery_request,
+            commands::start_social_recovery,
+            commands::get_social_recovery_status,
+            commands::cancel_social_recovery,
+            commands::complete_social_recovery,
+            commands::request_secret_key_export,
+            commands::export_secret_key,
+            commands::get_key_audit_log,
+            commands::get_key_protection,
+            commands::set_key_passphrase,
+            commands::unlock_keys,
+            commands::lock_keys,
+            commands::remove_key_passphrase,
+            commands::connect_remote_signer,
+            commands::get_remote_signer,
+            commands::disconnect_remote_signer,
+            commands::enable_device_subkey,
+            commands::get_device_certificate,
+            commands::disable_device_subkey,
+            commands::export_identity_qr,
+            commands::scan_identity_qr,
+            commands::import_identity_qr,
+            commands::has_app_pin,
+            commands::set_app_pin,
+            commands::set_duress_pin,
+            commands::unlock_with_pin,
+            commands::rotate_keys,
+            commands::wipe_all_data,
+            commands::get_rotation_statements,
+            commands::import_rotation_statement,
+            commands::revoke_key,
+            commands::import_revocation_statement,
             commands::get_public_key,
             // NFC exchange
             commands::is_nfc_available,
             commands::start_nfc_broadcast,
+            commands::stop_nfc_broadcast,
             commands::start_nfc_receive,
             commands::start_nfc_scan, // Legacy alias for start_nfc_receive
             commands::write_nfc_response,
+            commands::retry_nfc_write,
+            commands::start_hce_exchange,
+            commands::tap_hce_exchange,
+            commands::get_one_tap_support,
+            commands::write_contact_tag,
+            commands::get_nfc_exchange_state,
+            commands::reset_nfc_exchange,
+            commands::get_exchange_sas,
+            commands::get_session_code,
+            commands::confirm_exchange_sas,
             commands::complete_exchange,
+            commands::start_reverify,
+            commands::cancel_reverify,
+            commands::reverify_contact,
+            commands::get_pending_exchanges,
+            commands::cancel_pending_exchange,
+            commands::get_exchange_session,
+            commands::reset_exchange_session,
+            // Beacon mode
+            commands::start_beacon_mode,
+            commands::get_beacon_status,
+            commands::stop_beacon_mode,
+            commands::beacon_ingest,
+            commands::get_quarantined_payloads,
+            commands::clear_quarantined_payloads,
+            commands::start_conference_mode,
+            commands::get_conference_status,
+            commands::stop_conference_mode,
+            commands::approve_conference_attendees,
+            commands::dismiss_conference_attendees,
             // QR exchange
             commands::get_exchange_qr_payload,
             commands::process_scanned_qr,
+            // Group exchange
+            commands::start_group_exchange,
+            commands::get_group_exchange_payload,
+            commands::scan_group_exchange,
+            commands::end_group_exchange,
+            // Link exchange
+            commands::get_exchange_uri,
+            commands::process_exchange_uri,
+            commands::get_exchange_text_payload,
+            commands::process_exchange_text,
+            commands::create_web_link,
+            commands::open_web_link,
+            commands::take_pending_exchange_uris,
+            // Audio exchange
+            commands::start_audio_broadcast,
+            commands::start_audio_receive,
+            commands::push_audio_samples,
+            commands::stop_audio_receive,
+            // Local-network exchange
+            commands::host_lan_exchange,
+            commands::discover_lan_exchanges,
+            commands::join_lan_exchange,
+            commands::cancel_lan_exchange,
+            // Remote exchange over Nostr DMs
+            commands::start_remote_exchange,
+            commands::check_remote_exchanges,
+            commands::accept_remote_exchange,
+            commands::decline_remote_exchange,
+            commands::cancel_remote_exchange,
+            commands::set_remote_relays,
+            commands::get_remote_exchanges,
+            // Mock transport (desktop development)
+            commands::mock_show_qr,
+            commands::mock_scan_qr,
+            commands::mock_lookup_node_id,
+            // Exchange statistics
+            commands::get_exchange_stats,
+            commands::reset_exchange_stats,
             // Contact management
             commands::get_contacts,
             commands::delete_contact,
+            commands::query_contacts,
+            commands::update_contact,
+            commands::mark_contact_reviewed,
+            commands::get_safety_number,
+            commands::mark_contact_verified,
+            commands::report_contact,
+            commands::get_reports,
+            commands::set_contact_blocked,
+            commands::export_contact_card,
+            commands::import_contact_card,
+            commands::introduce_contact,
+            commands::get_contact_share_payload,
+            commands::add_contact_by_identifier,
+            commands::get_my_profile_card,
+            commands::get_paper_card,
+            commands::import_profile_card,
+            commands::get_contact_trust,
+            commands::get_trust_scores,
+            commands::find_duplicate_contacts,
+            commands::merge_contacts,
+            // Settings
+            commands::get_settings,
+            commands::update_settings,
+            commands::get_feature_flags,
+            commands::set_feature_flag,
+            // Groups
+            commands::create_group,
+            commands::get_groups,
+            commands::add_group_member,
+            commands::remove_group_member,
+            commands::leave_group,
+            commands::apply_membership_change,
+            commands::create_group_invite,
+            commands::accept_group_invite,
+            commands::admit_group_invitee,
+            commands::receive_group_key,
+            // Broadcast channels
+            commands::create_broadcast_channel,
+            commands::subscribe_broadcast_channel,
+            commands::remove_broadcast_channel,
+            commands::get_broadcast_channels,
+            commands::publish_broadcast,
+            commands::receive_broadcast_post,
             // Iroh chat
             commands::start_iroh,
             commands::stop_iroh,
             commands::get_iroh_status,
             commands::connect_to_contact,
+            commands::ping_contact,
+            commands::start_connection_trace,
+            commands::stop_connection_trace,
             commands::send_message,
+            commands::send_group_message,
+            commands::mark_group_read,
+            commands::get_outbox,
+            commands::retry_message,
+            commands::cancel_queued_message,
+            commands::edit_message,
+            commands::delete_message,
+            commands::set_typing,
+            commands::set_disappearing_messages,
+            commands::send_typed_message,
             commands::get_messages,
+            commands::get_message_info,
+            commands::get_message_kinds,
+            commands::get_plugins,
+            commands::get_conversations,
+            commands::get_quarantined_messages,
+            commands::release_quarantined_message,
+            commands::discard_quarantined_message,
+            commands::schedule_message,
+            commands::get_scheduled_messages,
+            commands::cancel_scheduled_message,
+            // Share sheet
+            commands::get_pending_share,
+            commands::share_to_contact,
+            commands::discard_pending_share,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,357 @@
+//! Background receive path for the Iroh node
+//!
+//! The node and chat manager live in `AppState`, not in a window, so these
+//! tasks keep accepting connections and messages while the window is hidden
+//! (desktop tray mode) or not yet open. Each accepted message goes through
+//! the same policy and filters as everywhere else, is announced to the UI
+//! with a `chat://message` event, and raises a notification when the window
+//! isn't in front. A chat connection is only listened on once the peer has
+//! proven it holds the contact's key (see `binding`).
+
+use crate::binding::{challenge, BindError, Prover};
+use crate::chat::{read_frame, write_frame, ChatError, ChatMessage, Incoming};
+use crate::commands::{
+    apply_contact_rotation, handle_introduction, handle_key_revocation, handle_recovery_request,
+    handle_recovery_share, load_contacts_from_store, load_groups_from_store,
+    load_settings_from_store,
+};
+use crate::iroh_node::{remote_node_id, CHAT_ALPN};
+use crate::keys::RotationStatement;
+use crate::message_types::{
+    KIND_INTRODUCTION, KIND_KEY_REVOCATION, KIND_KEY_ROTATION, KIND_RECOVERY_REQUEST,
+    KIND_RECOVERY_SHARE,
+};
+use crate::moderation::InboundPolicy;
+use crate::petname::resolve;
+use crate::ping::spawn_responder as spawn_ping_responder;
+use crate::state::AppState;
+use crate::trace::{record as trace_event, TraceEventKind};
+use crate::typing::{decode_typing, TypingEvent};
+#[allow(deprecated)]
+use iroh_net::endpoint::Endpoint;
+use iroh_quinn::{Connection, VarInt};
+use nostr::Keys;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_notification::NotificationExt;
+
+/// Event emitted for every accepted incoming message
+pub const MESSAGE_EVENT: &str = "chat://message";
+
+/// Event emitted with an outgoing message whose status changed
+pub const STATUS_EVENT: &str = "chat://status";
+
+/// Event emitted with a message the contact edited, holding its new content
+pub const EDIT_EVENT: &str = "chat://edited";
+
+/// Event emitted with the placeholder of a message the contact deleted
+pub const DELETE_EVENT: &str = "chat://deleted";
+
+/// Event emitted with a `GroupDelivery` when a member's receipt for one of
+/// our group messages counts
+pub const GROUP_RECEIPT_EVENT: &str = "chat://group-receipt";
+
+/// Event emitted with a `TypingEvent` when a contact starts or stops typing
+pub const TYPING_EVENT: &str = "chat://typing";
+
+/// Label of the main window
+pub const MAIN_WINDOW: &str = "main";
+
+/// Close code for a peer that didn't prove the contact's key
+const UNBOUND_CLOSE_CODE: u32 = 2;
+
+/// Close code for a peer other than the node derived for the contact
+const UNEXPECTED_PEER_CLOSE_CODE: u32 = 3;
+
+/// Accept incoming connections on `endpoint` until it closes.
+///
+/// The endpoint is derived per contact, and peers other than the node
+/// derived for `contact_pubkey` are turned away. Chat connections still
+/// have to prove the contact's key, with `keys` proving us in turn, before
+/// they get a message listener. Plugin ALPNs are handed to their handler.
+#[allow(deprecated)]
+pub fn spawn_accept_loop(app: AppHandle, endpoint: Endpoint, contact_pubkey: String, keys: Keys) {
+    let local_node = endpoint.node_id().to_string();
+    tauri::async_runtime::spawn(async move {
+        while let Some(incoming) = endpoint.accept().await {
+            let mut connecting = match incoming.accept() {
+                Ok(connecting) => connecting,
+                Err(_) => continue,
+            };
+            let alpn = match connecting.alpn().await {
+                Ok(alpn) => alpn,
+                Err(_) => continue,
+            };
+            let connection = match connecting.await {
+                Ok(connection) => connection,
+                Err(_) => continue,
+            };
+
+            let state = app.state::<AppState>();
+            if let Err(e) = state.iroh_node.read().await.verify_peer(&connection) {
+                trace_event(
+                    &state,
+                    &contact_pubkey,
+                    TraceEventKind::ConnectFailed,
+                    Some(e.to_string()),
+                );
+                connection.close(
+                    VarInt::from_u32(UNEXPECTED_PEER_CLOSE_CODE),
+                    b"unexpected peer",
+                );
+                continue;
+            }
+            trace_event(
+                &state,
+                &contact_pubkey,
+                TraceEventKind::Connected,
+                Some("incoming".to_string()),
+            );
+            if alpn == CHAT_ALPN {
+                // Binding takes a round trip; keep accepting meanwhile
+                let (app, contact_pubkey) = (app.clone(), contact_pubkey.clone());
+                let (keys, local_node) = (keys.clone(), local_node.clone());
+                tauri::async_runtime::spawn(async move {
+                    if bind_connection(&app, &connection, &contact_pubkey, keys, &local_node)
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                    app.state::<AppState>()
+                        .iroh_node
+                        .write()
+                        .await
+                        .add_connection(&contact_pubkey, connection.clone());
+                    spawn_message_listener(app.clone(), connection, contact_pubkey.clone());
+                    crate::outbox::spawn_flush(app, contact_pubkey);
+                });
+            } else if let Some(handler) =
+                state
+                    .plugins
+                    .dispatch(&alpn, connection, Some(contact_pubkey.clone()))
+            {
+                tauri::async_runtime::spawn(handler);
+            }
+        }
+    });
+}
+
+/// Answer the peer's pings and identity challenges on `connection` with
+/// `keys`, and challenge it to prove it holds `contact_pubkey`. A peer that
+/// fails is disconnected; one that succeeds is marked bound in the chat
+/// manager.
+pub async fn bind_connection(
+    app: &AppHandle,
+    connection: &Connection,
+    contact_pubkey: &str,
+    keys: Keys,
+    local_node: &str,
+) -> Result<(), BindError> {
+    let state = app.state::<AppState>();
+    let result = match remote_node_id(connection) {
+        Some(remote_node) => {
+            let prover = Prover::new(keys, local_node.to_string(), remote_node.clone());
+            spawn_ping_responder(connection.clone(), prover);
+            challenge(connection, local_node, &remote_node, contact_pubkey).await
+        }
+        None => Err(BindError::Stream("Unknown peer node".to_string())),
+    };
+
+    match &result {
+        Ok(()) => {
+            trace_event(&state, contact_pubkey, TraceEventKind::IdentityBound, None);
+            if let Some(manager) = state.chat_manager.write().await.as_mut() {
+                manager.mark_bound(contact_pubkey, connection);
+            }
+        }
+        Err(e) => {
+            trace_event(
+                &state,
+                contact_pubkey,
+                TraceEventKind::IdentityRejected,
+                Some(e.to_string()),
+            );
+            connection.close(VarInt::from_u32(UNBOUND_CLOSE_CODE), b"identity not proven");
+        }
+    }
+    result
+}
+
+/// Receive messages on a connection bound by `bind_connection` until it
+/// closes, and typing notifications alongside
+pub fn spawn_message_listener(app: AppHandle, connection: Connection, contact_pubkey: String) {
+    spawn_typing_listener(app.clone(), connection.clone(), contact_pubkey.clone());
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let data = match read_frame(&connection).await {
+                Ok(data) => data,
+                // A bad frame doesn't end the conversation; a closed connection does
+                Err(ChatError::ReceiveFailed(_)) if connection.close_reason().is_some() => break,
+                Err(_) => continue,
+            };
+
+            let settings = load_settings_from_store(&app);
+            let policy = InboundPolicy::from_contacts(
+                &load_contacts_from_store(&app),
+                settings.filters.clone(),
+            );
+            let groups = load_groups_from_store(&app);
+
+            let state = app.state::<AppState>();
+            let (accepted, ack) = {
+                let mut chat_manager = state.chat_manager.write().await;
+                let Some(manager) = chat_manager.as_mut() else {
+                    break;
+                };
+                manager.set_groups(&groups);
+                let message = match manager.open_frame(&data, &contact_pubkey) {
+                    Ok(Incoming::Message(message)) => message,
+                    // A handshake step is answered without holding the lock
+                    Ok(Incoming::Handshake(answer)) => {
+                        drop(chat_manager);
+                        if let Some(answer) = answer {
+                            let _ = write_frame(&connection, &answer).await;
+                        }
+                        continue;
+                    }
+                    Ok(Incoming::Ack(delivered)) => {
+                        if let Some(message) = delivered {
+                            let _ = app.emit(STATUS_EVENT, &message);
+                        }
+                        continue;
+                    }
+                    Ok(Incoming::GroupReceipt(receipts)) => {
+                        if let Some(receipts) = receipts {
+                            let _ = app.emit(GROUP_RECEIPT_EVENT, &receipts);
+                        }
+                        continue;
+                    }
+                    // Frames that don't parse or open are skipped like bad frames
+                    Err(_) => continue,
+                };
+                trace_event(
+                    &state,
+                    &contact_pubkey,
+                    TraceEventKind::StreamAccepted,
+                    Some(message.id.clone()),
+                );
+                let accepted = manager.process_incoming(message, &policy);
+                let ack = accepted
+                    .as_ref()
+                    .ok()
+                    .and_then(|message| manager.ack_frame(&contact_pubkey, message));
+                (accepted, ack)
+            };
+            if let Some(ack) = ack {
+                let _ = write_frame(&connection, &ack).await;
+            }
+
+            if let Ok(message) = accepted {
+                // Tombstones and edits come back as the message they changed
+                if message.deleted_at.is_some() {
+                    let _ = app.emit(DELETE_EVENT, &message);
+                    continue;
+                }
+                if message.edited_at.is_some() {
+                    let _ = app.emit(EDIT_EVENT, &message);
+                    continue;
+                }
+                match message.kind.as_str() {
+                    KIND_KEY_ROTATION => apply_rotation_message(&app, &message).await,
+                    KIND_KEY_REVOCATION => {
+                        let _ = handle_key_revocation(&app, &message);
+                    }
+                    KIND_RECOVERY_SHARE => {
+                        let _ = handle_recovery_share(&app, &message);
+                    }
+                    KIND_RECOVERY_REQUEST => {
+                        let _ = handle_recovery_request(&app, &message);
+                    }
+                    KIND_INTRODUCTION => {
+                        let _ = handle_introduction(&app, &message);
+                    }
+                    _ => {}
+                }
+                let _ = app.emit(MESSAGE_EVENT, &message);
+                notify(&app, &message);
+            }
+        }
+
+        let state = app.state::<AppState>();
+        trace_event(
+            &state,
+            &contact_pubkey,
+            TraceEventKind::ConnectionClosed,
+            connection.close_reason().map(|reason| reason.to_string()),
+        );
+        state
+            .iroh_node
+            .write()
+            .await
+            .remove_connection(&contact_pubkey, &connection);
+    });
+}
+
+/// Announce the contact's typing notifications until `connection` closes.
+/// Blocked and revoked contacts are ignored like their messages.
+fn spawn_typing_listener(app: AppHandle, connection: Connection, contact_pubkey: String) {
+    tauri::async_runtime::spawn(async move {
+        while let Ok(datagram) = connection.read_datagram().await {
+            let Some(typing) = decode_typing(&datagram) else {
+                continue;
+            };
+            let policy =
+                InboundPolicy::from_contacts(&load_contacts_from_store(&app), Default::default());
+            if policy.check(&contact_pubkey, "").is_ok() {
+                let _ = app.emit(TYPING_EVENT, TypingEvent::new(&contact_pubkey, typing));
+            }
+        }
+    });
+}
+
+/// Move the sender to the new key in their (already verified) rotation
+/// statement. Only the key being rotated away from may announce it.
+async fn apply_rotation_message(app: &AppHandle, message: &ChatMessage) {
+    let Some(statement) = message
+        .payload
+        .clone()
+        .and_then(|payload| serde_json::from_value::<RotationStatement>(payload).ok())
+    else {
+        return;
+    };
+    if statement.old_pubkey == message.sender_pubkey {
+        let _ = apply_contact_rotation(app, &statement).await;
+    }
+}
+
+/// Whether the main window is visible and focused
+fn window_in_front(app: &AppHandle) -> bool {
+    app.get_webview_window(MAIN_WINDOW).is_some_and(|window| {
+        window.is_visible().unwrap_or(false) && window.is_focused().unwrap_or(false)
+    })
+}
+
+/// Raise a notification for `message` unless the user is already looking at the app
+fn notify(app: &AppHandle, message: &ChatMessage) {
+    let settings = load_settings_from_store(app).notifications;
+    if !settings.enabled || window_in_front(app) {
+        return;
+    }
+
+    let title = load_contacts_from_store(app)
+        .iter()
+        .find(|c| c.nostr_pubkey == message.sender_pubkey)
+        .map(|contact| resolve(contact).0)
+        .unwrap_or_else(|| "SneakerNet".to_string());
+    let body = if settings.show_preview {
+        message.content.clone()
+    } else {
+        "New message".to_string()
+    };
+
+    let mut builder = app.notification().builder().title(title).body(body);
+    if settings.sound {
+        builder = builder.sound("default");
+    }
+    let _ = builder.show();
+}
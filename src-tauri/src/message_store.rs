@@ -0,0 +1,334 @@
+//! SQLite-backed message history
+//!
+//! `ChatSession` keeps a conversation in memory, so history used to vanish
+//! when the app restarted. When message persistence is on, `ChatManager`
+//! also writes every message it stores here, one row per message holding
+//! its JSON, and loads a conversation the first time it is asked for.
+//!
+//! Disappearing messages keep their expiry in a column of their own, so the
+//! sweeper (see `disappearing`) can purge them without parsing every row.
+//!
+//! The chat ratchet of each contact (see `ratchet`) is saved here too, even
+//! when history isn't, since messages sealed to a lost state can't be read.
+
+use crate::chat::ChatMessage;
+use crate::ratchet::Ratchet;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use thiserror::Error;
+
+/// Schema version stored in `PRAGMA user_version`
+const SCHEMA_VERSION: i32 = 3;
+
+#[derive(Error, Debug)]
+pub enum MessageStoreError {
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("Message database is from a newer version ({0})")]
+    UnsupportedVersion(i32),
+}
+
+pub struct MessageStore {
+    conn: Connection,
+}
+
+impl MessageStore {
+    pub fn open(path: &Path) -> Result<Self, MessageStoreError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, MessageStoreError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, MessageStoreError> {
+        let version: i32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(MessageStoreError::UnsupportedVersion(version));
+        }
+        if version < 1 {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS messages (
+                    id TEXT PRIMARY KEY,
+                    contact_pubkey TEXT NOT NULL,
+                    timestamp INTEGER NOT NULL,
+                    data TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS messages_conversation
+                    ON messages(contact_pubkey, timestamp);",
+            )?;
+        }
+        if version < 2 {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS ratchets (
+                    contact_pubkey TEXT PRIMARY KEY,
+                    data TEXT NOT NULL
+                );",
+            )?;
+        }
+        if version < 3 {
+            conn.execute_batch(
+                "ALTER TABLE messages ADD COLUMN expires_at INTEGER;
+                CREATE INDEX IF NOT EXISTS messages_expiry
+                    ON messages(expires_at) WHERE expires_at IS NOT NULL;",
+            )?;
+        }
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+
+        Ok(Self { conn })
+    }
+
+    /// Save a message in the conversation with `contact_pubkey`, replacing
+    /// any earlier copy with the same ID
+    pub fn insert(
+        &self,
+        contact_pubkey: &str,
+        message: &ChatMessage,
+    ) -> Result<(), MessageStoreError> {
+        self.conn.execute(
+            "INSERT INTO messages (id, contact_pubkey, timestamp, data, expires_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(id) DO UPDATE SET
+                contact_pubkey = excluded.contact_pubkey,
+                timestamp = excluded.timestamp,
+                data = excluded.data,
+                expires_at = excluded.expires_at",
+            params![
+                message.id,
+                contact_pubkey,
+                message.timestamp as i64,
+                serde_json::to_string(message)?,
+                message.expires_at.map(|at| at as i64)
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The conversation with `contact_pubkey`, oldest first
+    pub fn conversation(
+        &self,
+        contact_pubkey: &str,
+    ) -> Result<Vec<ChatMessage>, MessageStoreError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT data FROM messages WHERE contact_pubkey = ?1 ORDER BY timestamp, rowid",
+        )?;
+        let rows = stmt.query_map(params![contact_pubkey], |row| row.get::<_, String>(0))?;
+
+        let mut messages = Vec::new();
+        for data in rows {
+            messages.push(serde_json::from_str(&data?)?);
+        }
+        Ok(messages)
+    }
+
+    /// The latest message with `contact_pubkey` and how many there are
+    pub fn summary(
+        &self,
+        contact_pubkey: &str,
+    ) -> Result<(Option<ChatMessage>, usize), MessageStoreError> {
+        let count: u32 = self.conn.query_row(
+            "SELECT COUNT(*) FROM messages WHERE contact_pubkey = ?1",
+            params![contact_pubkey],
+            |row| row.get(0),
+        )?;
+        let last: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT data FROM messages WHERE contact_pubkey = ?1
+                 ORDER BY timestamp DESC, rowid DESC LIMIT 1",
+                params![contact_pubkey],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok((
+            last.map(|d| serde_json::from_str(&d)).transpose()?,
+            count as usize,
+        ))
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<ChatMessage>, MessageStoreError> {
+        let data: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT data FROM messages WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(data.map(|d| serde_json::from_str(&d)).transpose()?)
+    }
+
+    /// Contact whose conversation holds message `id`
+    pub fn contact_of(&self, id: &str) -> Result<Option<String>, MessageStoreError> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT contact_pubkey FROM messages WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Delete one message. Returns whether it was there.
+    pub fn delete(&self, id: &str) -> Result<bool, MessageStoreError> {
+        Ok(self
+            .conn
+            .execute("DELETE FROM messages WHERE id = ?1", params![id])?
+            > 0)
+    }
+
+    /// Delete messages whose expiry is at or before `now` (Unix seconds).
+    /// Returns the contact and ID of each one deleted.
+    pub fn delete_expired(&self, now: u64) -> Result<Vec<(String, String)>, MessageStoreError> {
+        let mut stmt = self
+            .conn
+            .prepare("DELETE FROM messages WHERE expires_at <= ?1 RETURNING contact_pubkey, id")?;
+        let rows = stmt.query_map(params![now as i64], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Move a conversation to another pubkey after a key rotation. Returns
+    /// the number of messages moved.
+    pub fn reassign(
+        &self,
+        from_pubkey: &str,
+        into_pubkey: &str,
+    ) -> Result<usize, MessageStoreError> {
+        Ok(self.conn.execute(
+            "UPDATE messages SET contact_pubkey = ?2 WHERE contact_pubkey = ?1",
+            params![from_pubkey, into_pubkey],
+        )?)
+    }
+
+    pub fn load_ratchet(&self, contact_pubkey: &str) -> Result<Option<Ratchet>, MessageStoreError> {
+        let data: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT data FROM ratchets WHERE contact_pubkey = ?1",
+                params![contact_pubkey],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(data.map(|d| serde_json::from_str(&d)).transpose()?)
+    }
+
+    pub fn save_ratchet(
+        &self,
+        contact_pubkey: &str,
+        ratchet: &Ratchet,
+    ) -> Result<(), MessageStoreError> {
+        self.conn.execute(
+            "INSERT INTO ratchets (contact_pubkey, data) VALUES (?1, ?2)
+             ON CONFLICT(contact_pubkey) DO UPDATE SET data = excluded.data",
+            params![contact_pubkey, serde_json::to_string(ratchet)?],
+        )?;
+        Ok(())
+    }
+
+    /// Delete messages sent before `cutoff` (Unix seconds). Returns the
+    /// number deleted.
+    pub fn prune_before(&self, cutoff: u64) -> Result<usize, MessageStoreError> {
+        Ok(self.conn.execute(
+            "DELETE FROM messages WHERE timestamp < ?1",
+            params![cutoff as i64],
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(content: &str, timestamp: u64) -> ChatMessage {
+        ChatMessage {
+            timestamp,
+            ..ChatMessage::new_outgoing(content, "me")
+        }
+    }
+
+    #[test]
+    fn test_conversations_round_trip() {
+        let store = MessageStore::open_in_memory().unwrap();
+        let later = message("later", 200);
+        store.insert("alice", &later).unwrap();
+        store.insert("alice", &message("earlier", 100)).unwrap();
+        store.insert("bob", &message("hi bob", 150)).unwrap();
+        // Saving again doesn't duplicate
+        store.insert("alice", &later).unwrap();
+
+        let alice: Vec<_> = store
+            .conversation("alice")
+            .unwrap()
+            .into_iter()
+            .map(|m| m.content)
+            .collect();
+        assert_eq!(alice, ["earlier", "later"]);
+
+        let (last, count) = store.summary("alice").unwrap();
+        assert_eq!(last.unwrap().content, "later");
+        assert_eq!(count, 2);
+        assert_eq!(store.get(&later.id).unwrap().unwrap().content, "later");
+        assert_eq!(
+            store.contact_of(&later.id).unwrap().as_deref(),
+            Some("alice")
+        );
+        assert!(store.conversation("carol").unwrap().is_empty());
+
+        assert!(store.delete(&later.id).unwrap());
+        assert!(!store.delete(&later.id).unwrap());
+        assert_eq!(store.summary("alice").unwrap().1, 1);
+    }
+
+    #[test]
+    fn test_reassign_and_prune() {
+        let store = MessageStore::open_in_memory().unwrap();
+        store.insert("old", &message("one", 100)).unwrap();
+        store.insert("old", &message("two", 300)).unwrap();
+        store.insert("new", &message("three", 400)).unwrap();
+
+        assert_eq!(store.reassign("old", "new").unwrap(), 2);
+        assert!(store.conversation("old").unwrap().is_empty());
+        assert_eq!(store.conversation("new").unwrap().len(), 3);
+
+        assert_eq!(store.prune_before(200).unwrap(), 1);
+        assert_eq!(store.summary("new").unwrap().1, 2);
+
+        // Disappearing messages go once they expire, whenever they were sent
+        let expiring = ChatMessage {
+            expires_at: Some(500),
+            ..message("gone soon", 450)
+        };
+        store.insert("new", &expiring).unwrap();
+        assert!(store.delete_expired(499).unwrap().is_empty());
+        assert_eq!(
+            store.delete_expired(500).unwrap(),
+            [("new".to_string(), expiring.id.clone())]
+        );
+        assert_eq!(store.summary("new").unwrap().1, 2);
+
+        // Ratchets stay with the key they were made for
+        let mut ratchet = Ratchet::default();
+        ratchet.learn("their-key");
+        store.save_ratchet("old", &ratchet).unwrap();
+        store.save_ratchet("old", &ratchet).unwrap();
+        assert!(store.load_ratchet("old").unwrap().unwrap().can_seal());
+        assert!(store.load_ratchet("new").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_newer_schema_is_refused() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+        assert!(matches!(
+            MessageStore::init(conn),
+            Err(MessageStoreError::UnsupportedVersion(_))
+        ));
+    }
+}
@@ -0,0 +1,544 @@
+//! Typed message payloads
+//!
+//! Every chat message carries a `kind` and an optional structured `payload`
+//! next to its plain `content`. The content is always a human-readable
+//! fallback, so clients that don't know a kind (including builds from before
+//! kinds existed, which ignore the extra fields) still show something sensible.
+//! New kinds are added by registering a validator and a fallback renderer.
+
+use crate::card::SignedContactCard;
+use crate::keys::{sign_content, verify_content, KeyError, RevocationStatement, RotationStatement};
+use crate::recovery::{RecoveryRequest, ShareEnvelope};
+use nostr::Keys;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+pub const KIND_TEXT: &str = "text";
+pub const KIND_LOCATION: &str = "location";
+pub const KIND_CONTACT_CARD: &str = "contact-card";
+pub const KIND_PAYMENT: &str = "payment";
+pub const KIND_GAME_MOVE: &str = "game-move";
+pub const KIND_IMAGE: &str = "image";
+/// A signed `RotationStatement` from the sender
+pub const KIND_KEY_ROTATION: &str = "key-rotation";
+/// A signed `RevocationStatement` for the sender's key
+pub const KIND_KEY_REVOCATION: &str = "key-revocation";
+/// An encrypted social recovery `ShareEnvelope`
+pub const KIND_RECOVERY_SHARE: &str = "recovery-share";
+/// A `RecoveryRequest` from a contact's recovering device
+pub const KIND_RECOVERY_REQUEST: &str = "recovery-request";
+/// A `SignedContactCard` vouching for one of the sender's contacts
+pub const KIND_INTRODUCTION: &str = "introduction";
+/// An `Edit` replacing the content of one of the sender's messages
+pub const KIND_EDIT: &str = "edit";
+/// A `Tombstone` deleting one of the sender's messages
+pub const KIND_DELETE: &str = "delete";
+/// Prefix for application-defined kinds (`custom/<name>`)
+pub const CUSTOM_PREFIX: &str = "custom/";
+
+#[derive(Error, Debug)]
+pub enum MessageTypeError {
+    #[error("Unknown message kind: {0}")]
+    UnknownKind(String),
+    #[error("Invalid {kind} payload: {reason}")]
+    InvalidPayload { kind: String, reason: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub accuracy_m: Option<f64>,
+    pub label: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactCard {
+    pub nostr_pubkey: String,
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Payment {
+    /// Lightning invoice or other payment request
+    pub request: String,
+    pub amount_sats: Option<u64>,
+    pub memo: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GameMove {
+    pub game: String,
+    pub session_id: String,
+    #[serde(rename = "move")]
+    pub move_data: Value,
+}
+
+/// A small inline image. Chat frames are capped at 64 KiB, so this is for
+/// thumbnails and screenshots rather than full-size photos.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Image {
+    pub mime_type: String,
+    /// Hex-encoded image bytes
+    pub data: String,
+    pub caption: Option<String>,
+}
+
+/// New content for an earlier message
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Edit {
+    pub message_id: String,
+    pub content: String,
+}
+
+/// Signed request to replace one of the sender's messages with a
+/// placeholder on the recipient's side
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Tombstone {
+    pub message_id: String,
+    pub deleted_at: u64,
+    /// Signature by the sender over `Tombstone::signed_content`
+    pub signature: String,
+}
+
+impl Tombstone {
+    fn signed_content(message_id: &str, deleted_at: u64) -> String {
+        format!("sneakernet-delete-v1:{}:{}", message_id, deleted_at)
+    }
+
+    pub fn new(keys: &Keys, message_id: &str, deleted_at: u64) -> Result<Self, KeyError> {
+        Ok(Self {
+            message_id: message_id.to_string(),
+            deleted_at,
+            signature: sign_content(keys, &Self::signed_content(message_id, deleted_at))?,
+        })
+    }
+
+    /// Check the tombstone was signed by `pubkey_hex`
+    pub fn verify(&self, pubkey_hex: &str) -> Result<(), KeyError> {
+        let content = Self::signed_content(&self.message_id, self.deleted_at);
+        verify_content(pubkey_hex, &content, &self.signature)
+    }
+}
+
+/// How to handle one message kind
+#[derive(Clone, Copy)]
+pub struct KindHandler {
+    /// Check a payload is well-formed
+    pub validate: fn(&Value) -> Result<(), String>,
+    /// Plain-text rendering for clients that can't show the kind
+    pub fallback: fn(&Value) -> String,
+}
+
+fn parse<T: for<'de> Deserialize<'de>>(payload: &Value) -> Result<T, String> {
+    serde_json::from_value(payload.clone()).map_err(|e| e.to_string())
+}
+
+fn validate_location(payload: &Value) -> Result<(), String> {
+    let loc: Location = parse(payload)?;
+    if !(-90.0..=90.0).contains(&loc.latitude) || !(-180.0..=180.0).contains(&loc.longitude) {
+        return Err("coordinates out of range".to_string());
+    }
+    Ok(())
+}
+
+fn fallback_location(payload: &Value) -> String {
+    match parse::<Location>(payload) {
+        Ok(loc) => match loc.label {
+            Some(label) => format!("📍 {} ({:.5}, {:.5})", label, loc.latitude, loc.longitude),
+            None => format!("📍 {:.5}, {:.5}", loc.latitude, loc.longitude),
+        },
+        Err(_) => "📍 Location".to_string(),
+    }
+}
+
+fn validate_contact_card(payload: &Value) -> Result<(), String> {
+    let card: ContactCard = parse(payload)?;
+    if card.nostr_pubkey.len() != 64 || hex::decode(&card.nostr_pubkey).is_err() {
+        return Err("nostrPubkey must be 64 hex characters".to_string());
+    }
+    Ok(())
+}
+
+fn fallback_contact_card(payload: &Value) -> String {
+    match parse::<ContactCard>(payload) {
+        Ok(ContactCard {
+            name: Some(name), ..
+        }) => format!("👤 Contact: {}", name),
+        Ok(card) => format!(
+            "👤 Contact: {}…",
+            &card.nostr_pubkey[..8.min(card.nostr_pubkey.len())]
+        ),
+        Err(_) => "👤 Contact".to_string(),
+    }
+}
+
+fn validate_payment(payload: &Value) -> Result<(), String> {
+    let payment: Payment = parse(payload)?;
+    if payment.request.trim().is_empty() {
+        return Err("request is empty".to_string());
+    }
+    Ok(())
+}
+
+fn fallback_payment(payload: &Value) -> String {
+    match parse::<Payment>(payload) {
+        Ok(Payment {
+            amount_sats: Some(sats),
+            ..
+        }) => format!("⚡ Payment request: {} sats", sats),
+        _ => "⚡ Payment request".to_string(),
+    }
+}
+
+fn validate_game_move(payload: &Value) -> Result<(), String> {
+    parse::<GameMove>(payload).map(|_| ())
+}
+
+fn fallback_game_move(payload: &Value) -> String {
+    match parse::<GameMove>(payload) {
+        Ok(m) => format!("🎲 Move in {}", m.game),
+        Err(_) => "🎲 Game move".to_string(),
+    }
+}
+
+fn validate_image(payload: &Value) -> Result<(), String> {
+    let image: Image = parse(payload)?;
+    if !image.mime_type.starts_with("image/") {
+        return Err("mimeType must be an image type".to_string());
+    }
+    hex::decode(&image.data).map_err(|_| "data must be hex".to_string())?;
+    Ok(())
+}
+
+fn fallback_image(payload: &Value) -> String {
+    match parse::<Image>(payload) {
+        Ok(Image {
+            caption: Some(caption),
+            ..
+        }) => format!("🖼 Image: {}", caption),
+        _ => "🖼 Image".to_string(),
+    }
+}
+
+fn validate_key_rotation(payload: &Value) -> Result<(), String> {
+    parse::<RotationStatement>(payload)?
+        .verify()
+        .map_err(|e| e.to_string())
+}
+
+fn fallback_key_rotation(_: &Value) -> String {
+    "🔑 Changed their key".to_string()
+}
+
+fn validate_key_revocation(payload: &Value) -> Result<(), String> {
+    parse::<RevocationStatement>(payload)?
+        .verify()
+        .map_err(|e| e.to_string())
+}
+
+fn fallback_key_revocation(_: &Value) -> String {
+    "⚠️ Their key was compromised, don't trust messages from it".to_string()
+}
+
+fn validate_recovery_share(payload: &Value) -> Result<(), String> {
+    parse::<ShareEnvelope>(payload).map(|_| ())
+}
+
+fn fallback_recovery_share(_: &Value) -> String {
+    "🔐 Recovery share".to_string()
+}
+
+fn validate_recovery_request(payload: &Value) -> Result<(), String> {
+    parse::<RecoveryRequest>(payload).map(|_| ())
+}
+
+fn fallback_recovery_request(_: &Value) -> String {
+    "🔐 Asked for help recovering an identity".to_string()
+}
+
+fn validate_introduction(payload: &Value) -> Result<(), String> {
+    parse::<SignedContactCard>(payload)?
+        .verify()
+        .map_err(|e| e.to_string())
+}
+
+fn fallback_introduction(payload: &Value) -> String {
+    match parse::<SignedContactCard>(payload) {
+        Ok(SignedContactCard {
+            name: Some(name), ..
+        }) => format!("🤝 Introduced you to {}", name),
+        _ => "🤝 Introduced you to a contact".to_string(),
+    }
+}
+
+fn validate_edit(payload: &Value) -> Result<(), String> {
+    let edit: Edit = parse(payload)?;
+    if edit.message_id.is_empty() {
+        return Err("messageId is empty".to_string());
+    }
+    Ok(())
+}
+
+fn fallback_edit(payload: &Value) -> String {
+    match parse::<Edit>(payload) {
+        Ok(edit) => format!("✏️ Edited: {}", edit.content),
+        Err(_) => "✏️ Edited a message".to_string(),
+    }
+}
+
+fn validate_tombstone(payload: &Value) -> Result<(), String> {
+    let tombstone: Tombstone = parse(payload)?;
+    if tombstone.message_id.is_empty() {
+        return Err("messageId is empty".to_string());
+    }
+    Ok(())
+}
+
+fn fallback_tombstone(_: &Value) -> String {
+    "🗑 Deleted a message".to_string()
+}
+
+fn accept_any(_: &Value) -> Result<(), String> {
+    Ok(())
+}
+
+fn fallback_unsupported(_: &Value) -> String {
+    "Unsupported message".to_string()
+}
+
+/// Known message kinds and their handlers
+#[derive(Clone)]
+pub struct MessageTypeRegistry {
+    kinds: BTreeMap<String, KindHandler>,
+}
+
+impl Default for MessageTypeRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            kinds: BTreeMap::new(),
+        };
+        registry.register(KIND_LOCATION, validate_location, fallback_location);
+        registry.register(
+            KIND_CONTACT_CARD,
+            validate_contact_card,
+            fallback_contact_card,
+        );
+        registry.register(KIND_PAYMENT, validate_payment, fallback_payment);
+        registry.register(KIND_GAME_MOVE, validate_game_move, fallback_game_move);
+        registry.register(KIND_IMAGE, validate_image, fallback_image);
+        registry.register(
+            KIND_KEY_ROTATION,
+            validate_key_rotation,
+            fallback_key_rotation,
+        );
+        registry.register(
+            KIND_KEY_REVOCATION,
+            validate_key_revocation,
+            fallback_key_revocation,
+        );
+        registry.register(
+            KIND_RECOVERY_SHARE,
+            validate_recovery_share,
+            fallback_recovery_share,
+        );
+        registry.register(
+            KIND_RECOVERY_REQUEST,
+            validate_recovery_request,
+            fallback_recovery_request,
+        );
+        registry.register(
+            KIND_INTRODUCTION,
+            validate_introduction,
+            fallback_introduction,
+        );
+        registry.register(KIND_EDIT, validate_edit, fallback_edit);
+        registry.register(KIND_DELETE, validate_tombstone, fallback_tombstone);
+        registry
+    }
+}
+
+impl MessageTypeRegistry {
+    /// Register (or replace) a message kind
+    pub fn register(
+        &mut self,
+        kind: &str,
+        validate: fn(&Value) -> Result<(), String>,
+        fallback: fn(&Value) -> String,
+    ) {
+        self.kinds
+            .insert(kind.to_string(), KindHandler { validate, fallback });
+    }
+
+    fn handler(&self, kind: &str) -> Option<KindHandler> {
+        if let Some(handler) = self.kinds.get(kind) {
+            return Some(*handler);
+        }
+        // Unregistered custom kinds are opaque but allowed
+        kind.starts_with(CUSTOM_PREFIX).then_some(KindHandler {
+            validate: accept_any,
+            fallback: fallback_unsupported,
+        })
+    }
+
+    /// Whether this client can render `kind` natively
+    pub fn is_known(&self, kind: &str) -> bool {
+        kind == KIND_TEXT || self.kinds.contains_key(kind)
+    }
+
+    /// Registered kinds (plus `text`)
+    pub fn kinds(&self) -> Vec<String> {
+        std::iter::once(KIND_TEXT.to_string())
+            .chain(self.kinds.keys().cloned())
+            .collect()
+    }
+
+    /// Validate an outgoing payload and produce its fallback text
+    pub fn prepare(&self, kind: &str, payload: &Value) -> Result<String, MessageTypeError> {
+        let handler = self
+            .handler(kind)
+            .ok_or_else(|| MessageTypeError::UnknownKind(kind.to_string()))?;
+
+        (handler.validate)(payload).map_err(|reason| MessageTypeError::InvalidPayload {
+            kind: kind.to_string(),
+            reason,
+        })?;
+
+        Ok((handler.fallback)(payload))
+    }
+
+    /// Check an incoming payload. Unknown kinds pass (the content is shown
+    /// instead); known kinds with a malformed payload do not.
+    pub fn accepts(&self, kind: &str, payload: Option<&Value>) -> bool {
+        match (self.kinds.get(kind), payload) {
+            (Some(handler), Some(payload)) => (handler.validate)(payload).is_ok(),
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_prepare_builtin_kinds() {
+        let registry = MessageTypeRegistry::default();
+
+        let text = registry
+            .prepare(
+                KIND_LOCATION,
+                &json!({ "latitude": 52.5, "longitude": 13.4, "label": "Venue" }),
+            )
+            .unwrap();
+        assert!(text.contains("Venue"));
+
+        let invalid = registry.prepare(
+            KIND_LOCATION,
+            &json!({ "latitude": 120.0, "longitude": 0.0 }),
+        );
+        assert!(matches!(
+            invalid,
+            Err(MessageTypeError::InvalidPayload { .. })
+        ));
+
+        let payment = registry
+            .prepare(
+                KIND_PAYMENT,
+                &json!({ "request": "lnbc1...", "amountSats": 2100 }),
+            )
+            .unwrap();
+        assert_eq!(payment, "⚡ Payment request: 2100 sats");
+
+        let edit = registry
+            .prepare(KIND_EDIT, &json!({ "messageId": "m1", "content": "Hello" }))
+            .unwrap();
+        assert_eq!(edit, "✏️ Edited: Hello");
+        assert!(registry
+            .prepare(KIND_EDIT, &json!({ "messageId": "", "content": "Hello" }))
+            .is_err());
+    }
+
+    #[test]
+    fn test_tombstone_signature() {
+        let keys = Keys::generate();
+        let pubkey = keys.public_key().to_hex();
+        let tombstone = Tombstone::new(&keys, "m1", 1_000).unwrap();
+        assert!(tombstone.verify(&pubkey).is_ok());
+
+        // It covers the message and the time, and only the signer's key
+        let other = Tombstone {
+            message_id: "m2".to_string(),
+            ..tombstone.clone()
+        };
+        assert!(other.verify(&pubkey).is_err());
+        assert!(tombstone
+            .verify(&Keys::generate().public_key().to_hex())
+            .is_err());
+    }
+
+    #[test]
+    fn test_unknown_and_custom_kinds() {
+        let mut registry = MessageTypeRegistry::default();
+
+        assert!(matches!(
+            registry.prepare("sticker", &json!({})),
+            Err(MessageTypeError::UnknownKind(_))
+        ));
+        assert_eq!(
+            registry
+                .prepare("custom/tictactoe", &json!({ "cell": 4 }))
+                .unwrap(),
+            "Unsupported message"
+        );
+
+        // Kinds from newer clients are accepted and rendered from their content
+        assert!(!registry.is_known("sticker"));
+        assert!(registry.accepts("sticker", Some(&json!({ "id": 1 }))));
+        assert!(!registry.accepts(KIND_PAYMENT, Some(&json!({ "request": "" }))));
+
+        registry.register("custom/tictactoe", accept_any, |_| {
+            "Tic-tac-toe move".to_string()
+        });
+        assert!(registry.is_known("custom/tictactoe"));
+        assert_eq!(
+            registry.prepare("custom/tictactoe", &json!({})).unwrap(),
+            "Tic-tac-toe move"
+        );
+    }
+
+    #[test]
+    fn test_introduction_must_be_signed() {
+        use crate::exchange::Contact;
+        use nostr::Keys;
+
+        let registry = MessageTypeRegistry::default();
+        let friend = Contact::new(&Keys::generate().public_key().to_hex(), "endpoint");
+        let card =
+            SignedContactCard::new(&Keys::generate(), &friend, Some("Alice".to_string()), 1_000)
+                .unwrap();
+
+        let payload = serde_json::to_value(&card).unwrap();
+        assert_eq!(
+            registry.prepare(KIND_INTRODUCTION, &payload).unwrap(),
+            "🤝 Introduced you to Alice"
+        );
+
+        // A card renamed after signing no longer verifies
+        let mut tampered = card;
+        tampered.name = Some("Mallory".to_string());
+        let tampered = serde_json::to_value(&tampered).unwrap();
+        assert!(!registry.accepts(KIND_INTRODUCTION, Some(&tampered)));
+    }
+}
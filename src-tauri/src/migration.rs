@@ -0,0 +1,311 @@
+//! Moving an identity to a new phone through a sequence of QR codes
+//!
+//! The old phone packs the secret key and the contact list into a bundle,
+//! encrypts it under a passphrase (Argon2id and XChaCha20-Poly1305, as for
+//! sealed keys) and splits the ciphertext over several QR codes. The new
+//! phone scans them in any order; once all have arrived, the passphrase
+//! opens the bundle. Nothing passes through a server, and a photo of the
+//! codes is useless without the passphrase.
+
+use crate::exchange::Contact;
+use crate::keys::{KdfParams, StoredKeys, MIN_PASSPHRASE_LEN};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key as CipherKey, XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// `type` field of a migration QR chunk
+pub const MIGRATION_TYPE: &str = "sneakernet-migration";
+
+/// Migration format version
+pub const MIGRATION_VERSION: u32 = 1;
+
+/// Hex characters of ciphertext per QR code, small enough to scan easily
+const CHUNK_DATA_LEN: usize = 800;
+
+/// Most QR codes a migration may span
+pub const MAX_CHUNKS: u32 = 256;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum MigrationError {
+    #[error("Passphrase must be at least {MIN_PASSPHRASE_LEN} characters")]
+    WeakPassphrase,
+    #[error("Not a migration code: {0}")]
+    InvalidChunk(String),
+    #[error("Unsupported migration version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Code belongs to a different migration")]
+    DifferentMigration,
+    #[error("Missing {0} of the migration codes")]
+    Incomplete(u32),
+    #[error("Migration too large for {MAX_CHUNKS} codes")]
+    TooLarge,
+    #[error("Wrong passphrase or damaged codes")]
+    DecryptionFailed,
+    #[error("Encryption error: {0}")]
+    EncryptionError(String),
+    #[error("Serialization error: {0}")]
+    SerializationError(String),
+}
+
+/// What is carried to the new phone. The old phone exports its keys
+/// borrowed (`MigrationBundle<&StoredKeys>`) rather than copying them.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationBundle<K = StoredKeys> {
+    pub keys: K,
+    pub contacts: Vec<Contact>,
+    pub created_at: u64,
+}
+
+/// One QR code of a migration. Every chunk carries the KDF parameters and
+/// nonce so the codes can be scanned in any order.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationChunk {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub version: u32,
+    /// Random ID shared by the chunks of one migration (hex)
+    pub id: String,
+    pub index: u32,
+    pub total: u32,
+    pub kdf: KdfParams,
+    pub nonce_hex: String,
+    /// This chunk's slice of the ciphertext (hex)
+    pub data: String,
+}
+
+impl MigrationChunk {
+    pub fn parse(payload: &str) -> Result<Self, MigrationError> {
+        let chunk: Self = serde_json::from_str(payload.trim())
+            .map_err(|e| MigrationError::InvalidChunk(e.to_string()))?;
+        if chunk.msg_type != MIGRATION_TYPE {
+            return Err(MigrationError::InvalidChunk(format!(
+                "unexpected type {}",
+                chunk.msg_type
+            )));
+        }
+        if chunk.version != MIGRATION_VERSION {
+            return Err(MigrationError::UnsupportedVersion(chunk.version));
+        }
+        if chunk.total == 0 || chunk.total > MAX_CHUNKS || chunk.index >= chunk.total {
+            return Err(MigrationError::InvalidChunk(
+                "bad chunk numbering".to_string(),
+            ));
+        }
+        Ok(chunk)
+    }
+}
+
+/// Encrypt `bundle` under `passphrase` and split it into QR payloads
+pub fn export<K: Serialize>(
+    bundle: &MigrationBundle<K>,
+    passphrase: &str,
+) -> Result<Vec<String>, MigrationError> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(MigrationError::WeakPassphrase);
+    }
+
+    let plaintext = Zeroizing::new(
+        serde_json::to_vec(bundle)
+            .map_err(|e| MigrationError::SerializationError(e.to_string()))?,
+    );
+    let id = hex::encode(rand::random::<[u8; 8]>());
+    let kdf = KdfParams::new_random();
+    let key = kdf
+        .derive_key(passphrase)
+        .map_err(|e| MigrationError::EncryptionError(e.to_string()))?;
+    let cipher = XChaCha20Poly1305::new(CipherKey::from_slice(key.as_slice()));
+    let nonce = rand::random::<[u8; 24]>();
+    let ciphertext = cipher
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &plaintext,
+                aad: id.as_bytes(),
+            },
+        )
+        .map_err(|e| MigrationError::EncryptionError(e.to_string()))?;
+
+    let data = hex::encode(ciphertext);
+    let total = data.len().div_ceil(CHUNK_DATA_LEN);
+    let total = u32::try_from(total)
+        .ok()
+        .filter(|&t| t <= MAX_CHUNKS)
+        .ok_or(MigrationError::TooLarge)?;
+
+    data.as_bytes()
+        .chunks(CHUNK_DATA_LEN)
+        .enumerate()
+        .map(|(index, slice)| {
+            let chunk = MigrationChunk {
+                msg_type: MIGRATION_TYPE.to_string(),
+                version: MIGRATION_VERSION,
+                id: id.clone(),
+                index: index as u32,
+                total,
+                kdf: kdf.clone(),
+                nonce_hex: hex::encode(nonce),
+                // Hex is ASCII, so any byte boundary is a char boundary
+                data: String::from_utf8_lossy(slice).into_owned(),
+            };
+            serde_json::to_string(&chunk)
+                .map_err(|e| MigrationError::SerializationError(e.to_string()))
+        })
+        .collect()
+}
+
+/// How far the scan of a migration has got
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationProgress {
+    pub received: u32,
+    pub total: u32,
+    pub complete: bool,
+}
+
+/// Chunks scanned so far on the new phone
+#[derive(Default)]
+pub struct MigrationAssembler {
+    chunks: Vec<Option<MigrationChunk>>,
+}
+
+impl MigrationAssembler {
+    /// Add a scanned chunk; scanning the same code twice is harmless
+    pub fn add(&mut self, chunk: MigrationChunk) -> Result<MigrationProgress, MigrationError> {
+        match self.chunks.iter().flatten().next() {
+            Some(first)
+                if first.id != chunk.id
+                    || first.total != chunk.total
+                    || first.kdf != chunk.kdf
+                    || first.nonce_hex != chunk.nonce_hex =>
+            {
+                return Err(MigrationError::DifferentMigration);
+            }
+            Some(_) => {}
+            None => self.chunks = vec![None; chunk.total as usize],
+        }
+
+        let index = chunk.index as usize;
+        self.chunks[index] = Some(chunk);
+        Ok(self.progress())
+    }
+
+    pub fn progress(&self) -> MigrationProgress {
+        let received = self.chunks.iter().flatten().count() as u32;
+        let total = self.chunks.len() as u32;
+        MigrationProgress {
+            received,
+            total,
+            complete: total > 0 && received == total,
+        }
+    }
+
+    /// Decrypt the bundle once every chunk has been scanned
+    pub fn open(&self, passphrase: &str) -> Result<MigrationBundle, MigrationError> {
+        let progress = self.progress();
+        if !progress.complete {
+            return Err(MigrationError::Incomplete(
+                progress.total - progress.received,
+            ));
+        }
+        let chunks: Vec<&MigrationChunk> = self.chunks.iter().flatten().collect();
+        let first = chunks[0];
+
+        let data: String = chunks.iter().map(|c| c.data.as_str()).collect();
+        let ciphertext = hex::decode(data).map_err(|_| MigrationError::DecryptionFailed)?;
+        let nonce = hex::decode(&first.nonce_hex).map_err(|_| MigrationError::DecryptionFailed)?;
+        if nonce.len() != 24 {
+            return Err(MigrationError::DecryptionFailed);
+        }
+
+        let key = first
+            .kdf
+            .derive_key(passphrase)
+            .map_err(|_| MigrationError::DecryptionFailed)?;
+        let cipher = XChaCha20Poly1305::new(CipherKey::from_slice(key.as_slice()));
+        let plaintext = Zeroizing::new(
+            cipher
+                .decrypt(
+                    XNonce::from_slice(&nonce),
+                    Payload {
+                        msg: &ciphertext,
+                        aad: first.id.as_bytes(),
+                    },
+                )
+                .map_err(|_| MigrationError::DecryptionFailed)?,
+        );
+
+        serde_json::from_slice(&plaintext)
+            .map_err(|e| MigrationError::SerializationError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::generate_keypair;
+
+    fn bundle(contacts: usize) -> MigrationBundle {
+        let (_, keys) = generate_keypair().unwrap();
+        let contacts = (0..contacts)
+            .map(|i| Contact::new(&format!("{:064x}", i), &format!("{:064x}", i + 1)))
+            .collect();
+        MigrationBundle {
+            keys,
+            contacts,
+            created_at: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn test_round_trip_out_of_order() {
+        let original = bundle(20);
+        let payloads = export(&original, "correct horse battery").unwrap();
+        assert!(payloads.len() > 1);
+
+        let mut assembler = MigrationAssembler::default();
+        for payload in payloads.iter().rev().chain(payloads.first()) {
+            assembler
+                .add(MigrationChunk::parse(payload).unwrap())
+                .unwrap();
+        }
+        let progress = assembler.progress();
+        assert!(progress.complete);
+        assert_eq!(progress.total as usize, payloads.len());
+
+        assert!(matches!(
+            assembler.open("wrong passphrase!"),
+            Err(MigrationError::DecryptionFailed)
+        ));
+        let restored = assembler.open("correct horse battery").unwrap();
+        assert_eq!(restored.keys.public_key_hex, original.keys.public_key_hex);
+        assert_eq!(restored.contacts.len(), 20);
+    }
+
+    #[test]
+    fn test_incomplete_and_mixed_migrations() {
+        let first = export(&bundle(20), "correct horse battery").unwrap();
+        let second = export(&bundle(20), "correct horse battery").unwrap();
+
+        let mut assembler = MigrationAssembler::default();
+        assembler
+            .add(MigrationChunk::parse(&first[0]).unwrap())
+            .unwrap();
+        assert_eq!(
+            assembler.add(MigrationChunk::parse(&second[1]).unwrap()),
+            Err(MigrationError::DifferentMigration)
+        );
+        assert!(matches!(
+            assembler.open("correct horse battery"),
+            Err(MigrationError::Incomplete(_))
+        ));
+
+        assert!(matches!(
+            MigrationChunk::parse("{\"type\":\"sneakernet-exchange\"}"),
+            Err(MigrationError::InvalidChunk(_))
+        ));
+    }
+}
@@ -0,0 +1,311 @@
+//! In-process stand-ins for NFC and QR during desktop development
+//!
+//! With the `mock-transport` feature, the desktop NFC commands talk to an
+//! emulated NTAG215 sticker instead of a PC/SC reader, and two extra
+//! commands put a QR payload "on screen" and "scan" it. Both live in a
+//! `MockField`: in memory for tests, or in a small JSON file in the temp
+//! directory so two app instances on one machine see the same tag and screen.
+//! Set `SNEAKERNET_MOCK_FIELD` to use a different file, e.g. to run several
+//! pairs side by side.
+//!
+//! The tag speaks the same APDUs as a sticker on an ACR122U, so the exchange
+//! goes through the real Type 2 read/write and NDEF code. Started nodes are
+//! also published in the field, so each instance can look up the node ID the
+//! other derived for their relationship and connect to it.
+
+use crate::hce::build_mime_message;
+use crate::nfc::{chunk_payload, NfcError};
+use crate::pcsc_nfc::{exchange_payloads, read_ndef, write_type2_ndef, CardChannel};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+/// Environment variable overriding the shared field file
+pub const FIELD_PATH_VAR: &str = "SNEAKERNET_MOCK_FIELD";
+
+const FIELD_FILE: &str = "sneakernet-mock-field.json";
+
+/// NTAG215: 135 pages of 4 bytes
+const TAG_PAGES: usize = 135;
+const PAGE_SIZE: usize = 4;
+
+const SW_OK: [u8; 2] = [0x90, 0x00];
+const SW_WRONG_PARAMS: [u8; 2] = [0x6B, 0x00];
+const SW_UNSUPPORTED: [u8; 2] = [0x6D, 0x00];
+
+/// An NDEF-formatted Type 2 tag held in memory
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MockTag {
+    memory: Vec<u8>,
+}
+
+impl Default for MockTag {
+    fn default() -> Self {
+        let mut memory = vec![0u8; TAG_PAGES * PAGE_SIZE];
+        // Capability container (page 3) and an empty NDEF TLV
+        memory[12..16].copy_from_slice(&[0xE1, 0x10, 0x3E, 0x00]);
+        memory[16..19].copy_from_slice(&[0x03, 0x00, 0xFE]);
+        Self { memory }
+    }
+}
+
+impl CardChannel for MockTag {
+    fn transmit(&mut self, apdu: &[u8]) -> Result<Vec<u8>, NfcError> {
+        if apdu.len() < 5 || apdu[0] != 0xFF {
+            return Ok(SW_UNSUPPORTED.to_vec());
+        }
+
+        let start = apdu[3] as usize * PAGE_SIZE;
+        let mut response = match apdu[1] {
+            // READ BINARY: four pages
+            0xB0 => {
+                let mut pages = self.memory.get(start..).unwrap_or_default().to_vec();
+                pages.resize(4 * PAGE_SIZE, 0);
+                pages
+            }
+            // UPDATE BINARY: one page
+            0xD6 => {
+                let (Some(page), Some(data)) = (
+                    self.memory.get_mut(start..start + PAGE_SIZE),
+                    apdu.get(5..5 + PAGE_SIZE),
+                ) else {
+                    return Ok(SW_WRONG_PARAMS.to_vec());
+                };
+                page.copy_from_slice(data);
+                Vec::new()
+            }
+            _ => return Ok(SW_UNSUPPORTED.to_vec()),
+        };
+        response.extend_from_slice(&SW_OK);
+        Ok(response)
+    }
+}
+
+/// Everything the two "devices" share
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct FieldState {
+    tag: MockTag,
+    /// Payload of the QR code currently on screen
+    qr: Option<String>,
+    /// Started nodes by `"{our_pubkey}:{their_pubkey}"`
+    nodes: HashMap<String, String>,
+}
+
+enum Backing {
+    Memory(Mutex<FieldState>),
+    File(PathBuf),
+}
+
+/// The shared tag, screen and node directory
+pub struct MockField {
+    backing: Backing,
+}
+
+fn node_key(our_pubkey: &str, their_pubkey: &str) -> String {
+    format!("{}:{}", our_pubkey, their_pubkey)
+}
+
+impl MockField {
+    pub fn in_memory() -> Self {
+        Self {
+            backing: Backing::Memory(Mutex::new(FieldState::default())),
+        }
+    }
+
+    /// A field shared through the file at `path`
+    pub fn file(path: PathBuf) -> Self {
+        Self {
+            backing: Backing::File(path),
+        }
+    }
+
+    /// The field used by the app: `$SNEAKERNET_MOCK_FIELD`, or a file in the
+    /// temp directory
+    pub fn global() -> &'static MockField {
+        static FIELD: OnceLock<MockField> = OnceLock::new();
+        FIELD.get_or_init(|| {
+            let path = std::env::var_os(FIELD_PATH_VAR)
+                .map(PathBuf::from)
+                .unwrap_or_else(|| std::env::temp_dir().join(FIELD_FILE));
+            MockField::file(path)
+        })
+    }
+
+    /// Run `f` on the field state, saving any changes
+    fn with_state<R>(&self, f: impl FnOnce(&mut FieldState) -> R) -> Result<R, NfcError> {
+        match &self.backing {
+            Backing::Memory(state) => Ok(f(&mut state.lock().unwrap())),
+            Backing::File(path) => {
+                // A missing or unreadable file is a blank field
+                let mut state: FieldState = std::fs::read(path)
+                    .ok()
+                    .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+                    .unwrap_or_default();
+                let result = f(&mut state);
+
+                let json =
+                    serde_json::to_vec(&state).map_err(|e| NfcError::Other(e.to_string()))?;
+                let tmp = path.with_extension("tmp");
+                std::fs::write(&tmp, json)
+                    .and_then(|_| std::fs::rename(&tmp, path))
+                    .map_err(|e| NfcError::Other(e.to_string()))?;
+                Ok(result)
+            }
+        }
+    }
+
+    /// Read the SneakerNet records on the tag
+    pub fn read_exchange_payloads(&self) -> Result<Vec<Vec<u8>>, NfcError> {
+        let message = self.with_state(|state| read_ndef(&mut state.tag))??;
+        let payloads = exchange_payloads(&message);
+        if payloads.is_empty() {
+            return Err(NfcError::NoExchangeMessage);
+        }
+        Ok(payloads)
+    }
+
+    /// Write `payload` to the tag as SneakerNet records, chunked if long
+    pub fn write_exchange_payload(&self, payload: &str) -> Result<(), NfcError> {
+        let message = build_mime_message(
+            crate::exchange::NDEF_MIME_TYPE.as_bytes(),
+            &chunk_payload(payload.as_bytes()),
+        );
+        self.with_state(|state| write_type2_ndef(&mut state.tag, &message))?
+    }
+
+    /// Put a QR payload on the shared screen
+    pub fn show_qr(&self, payload: &str) -> Result<(), NfcError> {
+        self.with_state(|state| state.qr = Some(payload.to_string()))
+    }
+
+    /// The QR payload on the shared screen, if any
+    pub fn scan_qr(&self) -> Result<Option<String>, NfcError> {
+        self.with_state(|state| state.qr.clone())
+    }
+
+    /// Record the node we started for a contact
+    pub fn publish_node(
+        &self,
+        our_pubkey: &str,
+        their_pubkey: &str,
+        node_id: &str,
+    ) -> Result<(), NfcError> {
+        self.with_state(|state| {
+            state
+                .nodes
+                .insert(node_key(our_pubkey, their_pubkey), node_id.to_string());
+        })
+    }
+
+    /// The node the contact started for us, if they have
+    pub fn lookup_node(
+        &self,
+        our_pubkey: &str,
+        their_pubkey: &str,
+    ) -> Result<Option<String>, NfcError> {
+        self.with_state(|state| {
+            state
+                .nodes
+                .get(&node_key(their_pubkey, our_pubkey))
+                .cloned()
+        })
+    }
+
+    /// Blank tag, empty screen, no nodes
+    pub fn reset(&self) -> Result<(), NfcError> {
+        self.with_state(|state| *state = FieldState::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::ExchangeMessage;
+    use crate::iroh_derive::{derive_endpoint_id, derive_peer_endpoint_id};
+    use crate::keys::generate_keypair;
+
+    #[test]
+    fn test_nfc_exchange_over_mock_tag() {
+        let field = MockField::in_memory();
+        let (alice, alice_stored) = generate_keypair().unwrap();
+        let (bob, bob_stored) = generate_keypair().unwrap();
+        let alice_pk = alice.public_key().to_hex();
+        let bob_pk = bob.public_key().to_hex();
+
+        assert!(matches!(
+            field.read_exchange_payloads(),
+            Err(NfcError::NoExchangeMessage)
+        ));
+
+        // Alice broadcasts, Bob reads
+        let initial = ExchangeMessage::new_initial(&alice).unwrap();
+        field
+            .write_exchange_payload(&initial.to_json().unwrap())
+            .unwrap();
+        let read = field.read_exchange_payloads().unwrap();
+        let msg = ExchangeMessage::from_json(std::str::from_utf8(&read[0]).unwrap()).unwrap();
+        msg.verify(Some(&bob_pk)).unwrap();
+        assert_eq!(msg.pubkey, alice_pk);
+
+        // Bob responds, Alice reads
+        let response = ExchangeMessage::new_response(&bob, &alice_pk).unwrap();
+        field
+            .write_exchange_payload(&response.to_json().unwrap())
+            .unwrap();
+        let read = field.read_exchange_payloads().unwrap();
+        let msg = ExchangeMessage::from_json(std::str::from_utf8(&read[0]).unwrap()).unwrap();
+        msg.verify(Some(&alice_pk)).unwrap();
+        assert_eq!(msg.pubkey, bob_pk);
+
+        // Each side derives its node for the other and finds the other's
+        let alice_secret = alice_stored.secret_bytes().unwrap();
+        let bob_secret = bob_stored.secret_bytes().unwrap();
+        let alice_node = derive_endpoint_id(&alice_secret, &alice_pk, &bob_pk).unwrap();
+        let bob_node = derive_endpoint_id(&bob_secret, &bob_pk, &alice_pk).unwrap();
+        field.publish_node(&alice_pk, &bob_pk, &alice_node).unwrap();
+        field.publish_node(&bob_pk, &alice_pk, &bob_node).unwrap();
+
+        assert_eq!(
+            field.lookup_node(&alice_pk, &bob_pk).unwrap(),
+            Some(bob_node.clone())
+        );
+        assert_eq!(
+            field.lookup_node(&bob_pk, &alice_pk).unwrap(),
+            Some(alice_node.clone())
+        );
+
+        // Which is also what each derives for the other from their shared secret
+        assert_eq!(
+            derive_peer_endpoint_id(&alice_secret, &alice_pk, &bob_pk).unwrap(),
+            bob_node
+        );
+        assert_eq!(
+            derive_peer_endpoint_id(&bob_secret, &bob_pk, &alice_pk).unwrap(),
+            alice_node
+        );
+    }
+
+    #[test]
+    fn test_file_field_is_shared() {
+        let path =
+            std::env::temp_dir().join(format!("sneakernet-mock-{}.json", uuid::Uuid::new_v4()));
+        let first = MockField::file(path.clone());
+        let second = MockField::file(path.clone());
+
+        assert_eq!(second.scan_qr().unwrap(), None);
+        first.show_qr("payload").unwrap();
+        assert_eq!(second.scan_qr().unwrap().as_deref(), Some("payload"));
+
+        first.write_exchange_payload("{}").unwrap();
+        assert_eq!(
+            second.read_exchange_payloads().unwrap(),
+            vec![b"{}".to_vec()]
+        );
+
+        second.reset().unwrap();
+        assert_eq!(first.scan_qr().unwrap(), None);
+        let _ = std::fs::remove_file(path);
+    }
+}
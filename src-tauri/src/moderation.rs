@@ -0,0 +1,242 @@
+//! Abuse reports and inbound message filtering
+//!
+//! Everything here is local: a report is a note to ourselves (and optionally
+//! an evidence bundle the user can hand to someone else), and filters only
+//! decide what we keep. Nothing is sent to the reported contact.
+
+use crate::chat::ChatMessage;
+use crate::exchange::Contact;
+use crate::keys::{sign_content, verify_content};
+use crate::settings::FilterSettings;
+use nostr::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum ModerationError {
+    #[error("Signing error: {0}")]
+    SigningError(String),
+    #[error("Evidence signature is invalid")]
+    InvalidSignature,
+    #[error("Serialization error: {0}")]
+    SerializationError(String),
+}
+
+/// Why an inbound message was dropped
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "reason")]
+pub enum FilterReason {
+    /// Sender is blocked
+    Blocked,
+    /// Sender's key was revoked as compromised
+    Revoked,
+    /// Message contains a link and the sender hasn't been reviewed
+    UrlFromUnverified,
+    /// Message contains a blocked keyword
+    Keyword { keyword: String },
+    /// Message is longer than the configured limit
+    TooLong { max_chars: usize },
+}
+
+impl std::fmt::Display for FilterReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilterReason::Blocked => write!(f, "sender is blocked"),
+            FilterReason::Revoked => write!(f, "sender's key was revoked"),
+            FilterReason::UrlFromUnverified => write!(f, "link from unverified contact"),
+            FilterReason::Keyword { keyword } => write!(f, "blocked keyword \"{}\"", keyword),
+            FilterReason::TooLong { max_chars } => {
+                write!(f, "longer than {} characters", max_chars)
+            }
+        }
+    }
+}
+
+/// A local abuse report
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub id: String,
+    pub contact_id: String,
+    pub contact_pubkey: String,
+    pub reason: String,
+    pub created_at: u64,
+    /// The contact was blocked as part of this report
+    pub blocked: bool,
+}
+
+impl Report {
+    pub fn new(contact_id: &str, contact_pubkey: &str, reason: &str, blocked: bool) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            contact_id: contact_id.to_string(),
+            contact_pubkey: contact_pubkey.to_string(),
+            reason: reason.trim().to_string(),
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            blocked,
+        }
+    }
+}
+
+/// A report plus the conversation it concerns, signed by the reporter.
+///
+/// Chat messages carry no per-message signature, so the bundle's integrity
+/// rests on the reporter's signature over the whole bundle: it proves who
+/// exported it and that it hasn't been edited since, not who wrote each message.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EvidenceBundle {
+    pub report: Report,
+    pub messages: Vec<ChatMessage>,
+    pub reporter: String,
+    pub signature: String,
+}
+
+impl EvidenceBundle {
+    fn signing_content(
+        report: &Report,
+        messages: &[ChatMessage],
+        reporter: &str,
+    ) -> Result<String, ModerationError> {
+        let body = serde_json::to_string(&(report, messages))
+            .map_err(|e| ModerationError::SerializationError(e.to_string()))?;
+        Ok(format!("sneakernet-evidence:{}:{}", reporter, body))
+    }
+
+    pub fn new(
+        keys: &Keys,
+        report: Report,
+        messages: Vec<ChatMessage>,
+    ) -> Result<Self, ModerationError> {
+        let reporter = keys.public_key().to_hex();
+        let content = Self::signing_content(&report, &messages, &reporter)?;
+        let signature = sign_content(keys, &content)
+            .map_err(|e| ModerationError::SigningError(e.to_string()))?;
+
+        Ok(Self {
+            report,
+            messages,
+            reporter,
+            signature,
+        })
+    }
+
+    pub fn verify(&self) -> Result<(), ModerationError> {
+        let content = Self::signing_content(&self.report, &self.messages, &self.reporter)?;
+        verify_content(&self.reporter, &content, &self.signature)
+            .map_err(|_| ModerationError::InvalidSignature)
+    }
+}
+
+/// Whether `content` contains something that looks like a link
+fn contains_url(content: &str) -> bool {
+    let lower = content.to_lowercase();
+    lower.contains("http://") || lower.contains("https://") || lower.contains("www.")
+}
+
+/// Per-contact rules the receive path applies before any content filters
+#[derive(Clone, Debug, Default)]
+pub struct InboundPolicy {
+    /// Pubkeys of blocked contacts
+    pub blocked: HashSet<String>,
+    /// Keys their owners revoked as compromised
+    pub revoked: HashSet<String>,
+    /// Pubkeys of contacts the user hasn't reviewed
+    pub unverified: HashSet<String>,
+    pub filters: FilterSettings,
+}
+
+impl InboundPolicy {
+    /// Build the policy from the stored contacts and filter settings
+    pub fn from_contacts(contacts: &[Contact], filters: FilterSettings) -> Self {
+        Self {
+            blocked: contacts
+                .iter()
+                .filter(|c| c.blocked)
+                .map(|c| c.nostr_pubkey.clone())
+                .collect(),
+            revoked: contacts
+                .iter()
+                .filter(|c| c.revoked_at.is_some())
+                .map(|c| c.nostr_pubkey.clone())
+                .collect(),
+            unverified: contacts
+                .iter()
+                .filter(|c| c.needs_review)
+                .map(|c| c.nostr_pubkey.clone())
+                .collect(),
+            filters,
+        }
+    }
+
+    /// Decide whether to keep a message from `sender_pubkey`
+    pub fn check(&self, sender_pubkey: &str, content: &str) -> Result<(), FilterReason> {
+        if self.blocked.contains(sender_pubkey) {
+            return Err(FilterReason::Blocked);
+        }
+        if self.revoked.contains(sender_pubkey) {
+            return Err(FilterReason::Revoked);
+        }
+
+        if self.filters.drop_urls_from_unverified
+            && self.unverified.contains(sender_pubkey)
+            && contains_url(content)
+        {
+            return Err(FilterReason::UrlFromUnverified);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inbound_policy() {
+        let mut troll = Contact::new("troll", "endpoint");
+        troll.blocked = true;
+        let mut stranger = Contact::new("stranger", "endpoint");
+        stranger.needs_review = true;
+        let mut leaked = Contact::new("leaked", "endpoint");
+        leaked.revoked_at = Some(1_000);
+        let friend = Contact::new("friend", "endpoint");
+
+        let filters = FilterSettings {
+            drop_urls_from_unverified: true,
+            ..Default::default()
+        };
+        let policy = InboundPolicy::from_contacts(&[troll, stranger, leaked, friend], filters);
+
+        assert_eq!(policy.check("troll", "hi"), Err(FilterReason::Blocked));
+        assert_eq!(policy.check("leaked", "hi"), Err(FilterReason::Revoked));
+        assert_eq!(
+            policy.check("stranger", "see https://example.com"),
+            Err(FilterReason::UrlFromUnverified)
+        );
+        assert!(policy.check("stranger", "hello").is_ok());
+        assert!(policy.check("friend", "see https://example.com").is_ok());
+    }
+
+    #[test]
+    fn test_evidence_bundle() {
+        let keys = Keys::generate();
+        let report = Report::new("id", "troll", "spam", true);
+        let messages = vec![ChatMessage::new_outgoing("buy now", "troll")];
+
+        let mut bundle = EvidenceBundle::new(&keys, report, messages).unwrap();
+        bundle.verify().unwrap();
+
+        bundle.messages[0].content = "edited".to_string();
+        assert!(matches!(
+            bundle.verify(),
+            Err(ModerationError::InvalidSignature)
+        ));
+    }
+}
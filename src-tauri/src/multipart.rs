@@ -0,0 +1,369 @@
+//! Animated multi-part QR codes for payloads too large to scan in one
+//!
+//! Modelled on BC-UR's fountain codes: the payload is cut into equal
+//! fragments and the animated code cycles through frames carrying either a
+//! single fragment or the XOR of a few. After the plain fragments have been
+//! shown once, the mixed frames keep filling whatever gaps the scanner has,
+//! so a missed frame doesn't mean waiting for it to come round again.
+//!
+//! A frame is `sn-mp:<seq>-<count>/<length>-<checksum>/<base64url data>`,
+//! where `seq` starts at 1, `count` is the number of fragments, `length` is
+//! the payload length in bytes and `checksum` the first four bytes of its
+//! SHA-256 (hex). Frames 1 to `count` are the plain fragments in order.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// Prefix that marks a QR code as one frame of a multi-part payload
+pub const FRAME_PREFIX: &str = "sn-mp:";
+
+/// Payload bytes per frame, small enough for a low-density QR code
+pub const FRAGMENT_LEN: usize = 200;
+
+/// Most fragments a payload may be cut into
+pub const MAX_FRAGMENTS: u32 = 256;
+
+/// Most fragments XORed into one mixed frame
+const MAX_MIX_DEGREE: usize = 3;
+
+/// Longest payload still shown as a single, plain QR code
+pub const MAX_SINGLE_QR_LEN: usize = 600;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum MultipartError {
+    #[error("Not a multi-part frame: {0}")]
+    InvalidFrame(String),
+    #[error("Payload too large for {MAX_FRAGMENTS} frames")]
+    TooLarge,
+    #[error("Missing {0} of the payload's frames")]
+    Incomplete(u32),
+    #[error("Assembled payload doesn't match its checksum")]
+    ChecksumMismatch,
+}
+
+/// Whether a scanned QR code is one frame of a multi-part payload
+pub fn is_frame(payload: &str) -> bool {
+    payload.starts_with(FRAME_PREFIX)
+}
+
+fn checksum(message: &[u8]) -> u32 {
+    let digest = Sha256::digest(message);
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]])
+}
+
+/// Fragments mixed into frame `seq`. Both sides derive the same choice from
+/// the sequence number and the payload checksum.
+fn fragment_indices(seq: u32, count: u32, checksum: u32) -> Vec<usize> {
+    let count = count as usize;
+    if seq as usize <= count {
+        return vec![seq as usize - 1];
+    }
+
+    // splitmix64, seeded per frame
+    let mut state = (u64::from(checksum) << 32) | u64::from(seq);
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+
+    let degree = 1 + (next() as usize) % MAX_MIX_DEGREE.min(count);
+    let mut indices: Vec<usize> = (0..count).collect();
+    for i in 0..degree {
+        let j = i + (next() as usize) % (count - i);
+        indices.swap(i, j);
+    }
+    indices.truncate(degree);
+    indices.sort_unstable();
+    indices
+}
+
+fn xor_into(target: &mut [u8], other: &[u8]) {
+    for (t, o) in target.iter_mut().zip(other) {
+        *t ^= o;
+    }
+}
+
+/// One scanned frame
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Frame {
+    seq: u32,
+    count: u32,
+    message_len: u32,
+    checksum: u32,
+    data: Vec<u8>,
+}
+
+impl Frame {
+    fn parse(payload: &str) -> Result<Self, MultipartError> {
+        let invalid = |reason: &str| MultipartError::InvalidFrame(reason.to_string());
+        let body = payload
+            .trim()
+            .strip_prefix(FRAME_PREFIX)
+            .ok_or_else(|| invalid("missing prefix"))?;
+
+        let mut parts = body.splitn(3, '/');
+        let (Some(position), Some(header), Some(data)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid("missing fields"));
+        };
+        let (seq, count) = position
+            .split_once('-')
+            .ok_or_else(|| invalid("bad sequence"))?;
+        let (message_len, checksum) = header
+            .split_once('-')
+            .ok_or_else(|| invalid("bad header"))?;
+
+        let frame = Self {
+            seq: seq.parse().map_err(|_| invalid("bad sequence"))?,
+            count: count.parse().map_err(|_| invalid("bad sequence"))?,
+            message_len: message_len.parse().map_err(|_| invalid("bad header"))?,
+            checksum: u32::from_str_radix(checksum, 16).map_err(|_| invalid("bad checksum"))?,
+            data: URL_SAFE_NO_PAD
+                .decode(data)
+                .map_err(|_| invalid("bad data"))?,
+        };
+        let fragment_len = (frame.message_len as usize).div_ceil(frame.count.max(1) as usize);
+        if frame.seq == 0
+            || frame.count == 0
+            || frame.count > MAX_FRAGMENTS
+            || frame.data.len() != fragment_len
+        {
+            return Err(invalid("bad frame numbering"));
+        }
+        Ok(frame)
+    }
+
+    fn encode(&self) -> String {
+        format!(
+            "{}{}-{}/{}-{:08x}/{}",
+            FRAME_PREFIX,
+            self.seq,
+            self.count,
+            self.message_len,
+            self.checksum,
+            URL_SAFE_NO_PAD.encode(&self.data)
+        )
+    }
+}
+
+/// Produces the frames of a payload
+#[derive(Clone, Debug)]
+pub struct FountainEncoder {
+    fragments: Vec<Vec<u8>>,
+    message_len: u32,
+    checksum: u32,
+}
+
+impl FountainEncoder {
+    pub fn new(message: &[u8]) -> Result<Self, MultipartError> {
+        let count = message.len().div_ceil(FRAGMENT_LEN).max(1);
+        if count > MAX_FRAGMENTS as usize {
+            return Err(MultipartError::TooLarge);
+        }
+
+        // Equal fragments, the last padded with zeros
+        let fragment_len = message.len().div_ceil(count);
+        let fragments = (0..count)
+            .map(|i| {
+                let start = (i * fragment_len).min(message.len());
+                let end = (start + fragment_len).min(message.len());
+                let mut fragment = message[start..end].to_vec();
+                fragment.resize(fragment_len, 0);
+                fragment
+            })
+            .collect();
+
+        Ok(Self {
+            fragments,
+            message_len: message.len() as u32,
+            checksum: checksum(message),
+        })
+    }
+
+    /// Number of plain fragments; that many frames are enough if none is missed
+    pub fn fragment_count(&self) -> u32 {
+        self.fragments.len() as u32
+    }
+
+    /// Frame `seq` (from 1); any number of frames can be produced
+    pub fn frame(&self, seq: u32) -> String {
+        let count = self.fragment_count();
+        let mut data = vec![0; self.fragments[0].len()];
+        for index in fragment_indices(seq, count, self.checksum) {
+            xor_into(&mut data, &self.fragments[index]);
+        }
+        Frame {
+            seq,
+            count,
+            message_len: self.message_len,
+            checksum: self.checksum,
+            data,
+        }
+        .encode()
+    }
+
+    /// The first `len` frames, for an animation to cycle through
+    pub fn frames(&self, len: u32) -> Vec<String> {
+        (1..=len).map(|seq| self.frame(seq)).collect()
+    }
+}
+
+/// QR codes to show for `payload`: the payload itself if it fits in one,
+/// otherwise a loop of frames with as many mixed frames as plain ones
+pub fn qr_frames(payload: &str) -> Result<Vec<String>, MultipartError> {
+    if payload.len() <= MAX_SINGLE_QR_LEN {
+        return Ok(vec![payload.to_string()]);
+    }
+    let encoder = FountainEncoder::new(payload.as_bytes())?;
+    Ok(encoder.frames(encoder.fragment_count() * 2))
+}
+
+/// How far the scan of a multi-part payload has got
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct MultipartProgress {
+    pub received: u32,
+    pub total: u32,
+    pub complete: bool,
+}
+
+/// Frames scanned so far
+#[derive(Debug, Default)]
+pub struct FountainDecoder {
+    /// (count, message length, checksum) of the payload being assembled
+    header: Option<(u32, u32, u32)>,
+    fragments: Vec<Option<Vec<u8>>>,
+    /// Mixed frames still holding more than one unknown fragment
+    mixed: Vec<(Vec<usize>, Vec<u8>)>,
+}
+
+impl FountainDecoder {
+    /// Add a scanned frame. A frame of a different payload starts over, as
+    /// when the scanner is pointed at someone else's code.
+    pub fn add(&mut self, payload: &str) -> Result<MultipartProgress, MultipartError> {
+        let frame = Frame::parse(payload)?;
+        let header = (frame.count, frame.message_len, frame.checksum);
+        if self.header != Some(header) {
+            *self = Self {
+                header: Some(header),
+                fragments: vec![None; frame.count as usize],
+                mixed: Vec::new(),
+            };
+        }
+
+        let indices = fragment_indices(frame.seq, frame.count, frame.checksum);
+        self.absorb(indices, frame.data);
+        Ok(self.progress())
+    }
+
+    /// Peel known fragments off a frame; one left unknown is thereby solved,
+    /// which may in turn solve frames kept earlier
+    fn absorb(&mut self, indices: Vec<usize>, data: Vec<u8>) {
+        let mut queue = vec![(indices, data)];
+        while let Some((indices, mut data)) = queue.pop() {
+            let mut unknown = Vec::new();
+            for index in indices {
+                match &self.fragments[index] {
+                    Some(known) => xor_into(&mut data, known),
+                    None => unknown.push(index),
+                }
+            }
+            match unknown.as_slice() {
+                [] => {}
+                [index] => {
+                    self.fragments[*index] = Some(data);
+                    queue.append(&mut self.mixed);
+                }
+                _ => self.mixed.push((unknown, data)),
+            }
+        }
+    }
+
+    pub fn progress(&self) -> MultipartProgress {
+        let received = self.fragments.iter().flatten().count() as u32;
+        let total = self.fragments.len() as u32;
+        MultipartProgress {
+            received,
+            total,
+            complete: total > 0 && received == total,
+        }
+    }
+
+    /// The assembled payload, once every fragment is known
+    pub fn message(&self) -> Result<Vec<u8>, MultipartError> {
+        let progress = self.progress();
+        let Some((_, message_len, expected)) = self.header.filter(|_| progress.complete) else {
+            return Err(MultipartError::Incomplete(
+                progress.total.max(1) - progress.received,
+            ));
+        };
+
+        let mut message: Vec<u8> = self.fragments.iter().flatten().flatten().copied().collect();
+        message.truncate(message_len as usize);
+        if checksum(&message) != expected {
+            return Err(MultipartError::ChecksumMismatch);
+        }
+        Ok(message)
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_round_trip_with_missed_frames() {
+        let message = payload(1_000);
+        let encoder = FountainEncoder::new(&message).unwrap();
+        let count = encoder.fragment_count();
+        assert_eq!(count, 5);
+
+        // Miss the first two plain frames; mixed frames fill the gaps
+        let mut decoder = FountainDecoder::default();
+        for seq in 3..=count * 10 {
+            let progress = decoder.add(&encoder.frame(seq)).unwrap();
+            if progress.complete {
+                break;
+            }
+        }
+        assert!(decoder.progress().complete);
+        assert_eq!(decoder.message().unwrap(), message);
+    }
+
+    #[test]
+    fn test_single_frame_and_restart() {
+        let short = b"hello".to_vec();
+        let encoder = FountainEncoder::new(&short).unwrap();
+        assert_eq!(encoder.fragment_count(), 1);
+        assert_eq!(qr_frames("hello").unwrap(), vec!["hello".to_string()]);
+
+        let long = FountainEncoder::new(&payload(600)).unwrap();
+        let mut decoder = FountainDecoder::default();
+        decoder.add(&long.frame(1)).unwrap();
+        assert_eq!(decoder.message(), Err(MultipartError::Incomplete(2)));
+
+        // Pointed at another code: start over with it
+        let progress = decoder.add(&encoder.frame(1)).unwrap();
+        assert_eq!(progress.total, 1);
+        assert_eq!(decoder.message().unwrap(), short);
+
+        assert!(matches!(
+            decoder.add("sn2:abc"),
+            Err(MultipartError::InvalidFrame(_))
+        ));
+    }
+}
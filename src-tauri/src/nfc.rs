@@ -0,0 +1,329 @@
+//! NFC error classification, retry, and resumable exchange state
+//!
+//! The NFC plugin surfaces every failure as a plain string. This module maps
+//! those strings onto categories the exchange flow can act on: transient
+//! failures (tag lost, transceive errors) are retried within the same session,
+//! everything else is returned to the frontend as a typed error.
+//!
+//! A payload too long for one short NDEF record (node hints and a profile
+//! make it so) is written as several records of the same type, each holding
+//! one chunk: `sn-ch:<seq>/<count>/<checksum>:<data>`, where `seq` starts at
+//! 1 and `checksum` is the first four bytes of the payload's SHA-256 (hex).
+//! The receive path puts the chunks back together before decoding.
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Maximum attempts for a single NFC operation before giving up
+pub const MAX_NFC_ATTEMPTS: u32 = 3;
+
+/// Prefix of an NDEF record holding one chunk of a longer payload
+pub const CHUNK_PREFIX: &str = "sn-ch:";
+
+/// Longest payload written as a single record, the limit of a short record
+pub const MAX_RECORD_LEN: usize = 255;
+
+/// Payload bytes per chunk, leaving room for the chunk header
+const CHUNK_DATA_LEN: usize = 224;
+
+/// Most chunks a payload may be split into
+const MAX_CHUNKS: usize = 64;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum NfcError {
+    #[error("NFC is not available on this device")]
+    NotAvailable,
+    #[error("Tag was lost, hold the devices together and tap again")]
+    TagLost,
+    #[error("NFC transceive failed: {0}")]
+    Transceive(String),
+    #[error("NFC session was cancelled")]
+    Cancelled,
+    #[error("No valid exchange message found")]
+    NoExchangeMessage,
+    #[error("Exchange verification failed: {0}")]
+    Verification(String),
+    #[error("No keys found")]
+    NoKeys,
+    /// The tag holds a Nostr contact from another app, saved unverified
+    #[error("Saved from another Nostr app's tag as an unverified contact")]
+    ThirdPartyContact(String),
+    #[error("NFC error: {0}")]
+    Other(String),
+}
+
+impl NfcError {
+    /// Classify a raw error message from the NFC plugin
+    pub fn classify(message: &str) -> Self {
+        let lower = message.to_lowercase();
+
+        if lower.contains("tag was lost") || lower.contains("taglost") || lower.contains("tag lost")
+        {
+            NfcError::TagLost
+        } else if lower.contains("transceive")
+            || lower.contains("ioexception")
+            || lower.contains("timed out")
+            || lower.contains("timeout")
+        {
+            NfcError::Transceive(message.to_string())
+        } else if lower.contains("cancel") {
+            NfcError::Cancelled
+        } else if lower.contains("not available") || lower.contains("disabled") {
+            NfcError::NotAvailable
+        } else {
+            NfcError::Other(message.to_string())
+        }
+    }
+
+    /// Whether retrying the same operation on the next tap may succeed
+    pub fn is_transient(&self) -> bool {
+        matches!(self, NfcError::TagLost | NfcError::Transceive(_))
+    }
+
+    /// Stable category identifier for the frontend
+    pub fn kind(&self) -> &'static str {
+        match self {
+            NfcError::NotAvailable => "notAvailable",
+            NfcError::TagLost => "tagLost",
+            NfcError::Transceive(_) => "transceive",
+            NfcError::Cancelled => "cancelled",
+            NfcError::NoExchangeMessage => "noExchangeMessage",
+            NfcError::Verification(_) => "verification",
+            NfcError::NoKeys => "noKeys",
+            NfcError::ThirdPartyContact(_) => "thirdPartyContact",
+            NfcError::Other(_) => "other",
+        }
+    }
+}
+
+impl From<String> for NfcError {
+    fn from(message: String) -> Self {
+        NfcError::Other(message)
+    }
+}
+
+/// Serialized as `{ kind, message, retryable }` so the UI can branch on the category
+impl Serialize for NfcError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("NfcError", 3)?;
+        s.serialize_field("kind", self.kind())?;
+        s.serialize_field("message", &self.to_string())?;
+        s.serialize_field("retryable", &self.is_transient())?;
+        s.end()
+    }
+}
+
+/// Run an NFC operation, re-arming it after transient failures.
+///
+/// Each attempt waits for a new tap, so the user only has to hold the
+/// devices together again rather than restart the exchange.
+pub fn retry_transient<T>(
+    mut op: impl FnMut(u32) -> Result<T, NfcError>,
+) -> Result<T, NfcError> {
+    let mut attempt = 1;
+    loop {
+        match op(attempt) {
+            Err(e) if e.is_transient() && attempt < MAX_NFC_ATTEMPTS => attempt += 1,
+            result => return result,
+        }
+    }
+}
+
+/// Progress of the current NFC exchange, kept so a failed step can be resumed
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct NfcExchangeState {
+    /// Signed payload prepared for writing (reused on re-tap so the nonce is stable)
+    pub pending_payload: Option<String>,
+    /// Whose pubkey the pending payload responds to (None for the initial broadcast)
+    pub pending_for: Option<String>,
+    /// Verified pubkey received from the other device
+    pub their_pubkey: Option<String>,
+    /// Our initial message went out, so the next read must be their response
+    pub awaiting_response: bool,
+    /// Last error seen in this exchange
+    pub last_error: Option<NfcError>,
+}
+
+impl NfcExchangeState {
+    /// Return the prepared payload for `their_pubkey`, if one is pending
+    pub fn reusable_payload(&self, their_pubkey: Option<&str>) -> Option<&str> {
+        if self.pending_for.as_deref() == their_pubkey {
+            self.pending_payload.as_deref()
+        } else {
+            None
+        }
+    }
+
+    /// Remember a prepared payload until it is written successfully
+    pub fn set_pending(&mut self, payload: String, their_pubkey: Option<&str>) {
+        self.pending_payload = Some(payload);
+        self.pending_for = their_pubkey.map(str::to_string);
+    }
+
+    /// Mark the pending payload as delivered
+    pub fn clear_pending(&mut self) {
+        self.pending_payload = None;
+        self.pending_for = None;
+        self.last_error = None;
+    }
+
+    /// Reset the whole exchange
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// First four bytes of the payload's SHA-256, as hex
+fn chunk_checksum(payload: &[u8]) -> String {
+    hex::encode(&Sha256::digest(payload)[..4])
+}
+
+/// Split a payload into NDEF record payloads. One that fits a short record
+/// is left whole, so older apps can still read it.
+pub fn chunk_payload(payload: &[u8]) -> Vec<Vec<u8>> {
+    if payload.len() <= MAX_RECORD_LEN {
+        return vec![payload.to_vec()];
+    }
+    let checksum = chunk_checksum(payload);
+    let count = payload.len().div_ceil(CHUNK_DATA_LEN);
+    payload
+        .chunks(CHUNK_DATA_LEN)
+        .enumerate()
+        .map(|(i, data)| {
+            let mut record =
+                format!("{}{}/{}/{}:", CHUNK_PREFIX, i + 1, count, checksum).into_bytes();
+            record.extend_from_slice(data);
+            record
+        })
+        .collect()
+}
+
+/// `(seq, count, checksum, data)` of a chunk record
+fn parse_chunk(record: &[u8]) -> Option<(usize, usize, &str, &[u8])> {
+    let rest = record.strip_prefix(CHUNK_PREFIX.as_bytes())?;
+    let colon = rest.iter().position(|&b| b == b':')?;
+    let header = std::str::from_utf8(&rest[..colon]).ok()?;
+    let mut fields = header.splitn(3, '/');
+    let seq = fields.next()?.parse().ok()?;
+    let count = fields.next()?.parse().ok()?;
+    let checksum = fields.next()?;
+    let valid = (1..=MAX_CHUNKS).contains(&count) && (1..=count).contains(&seq);
+    valid.then_some((seq, count, checksum, &rest[colon + 1..]))
+}
+
+/// Put chunked records back together. Whole payloads come first, then any
+/// record that isn't a chunk; a payload missing a chunk or failing its
+/// checksum is dropped.
+pub fn reassemble_chunks(records: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+    let mut payloads: BTreeMap<(String, usize), BTreeMap<usize, Vec<u8>>> = BTreeMap::new();
+    let mut others = Vec::new();
+    for record in records {
+        match parse_chunk(&record) {
+            Some((seq, count, checksum, data)) => {
+                payloads
+                    .entry((checksum.to_string(), count))
+                    .or_default()
+                    .insert(seq, data.to_vec());
+            }
+            None => others.push(record),
+        }
+    }
+
+    let mut whole: Vec<Vec<u8>> = payloads
+        .into_iter()
+        .filter(|((_, count), chunks)| chunks.len() == *count)
+        .filter_map(|((checksum, _), chunks)| {
+            let payload = chunks.into_values().flatten().collect::<Vec<u8>>();
+            (chunk_checksum(&payload) == checksum).then_some(payload)
+        })
+        .collect();
+    whole.extend(others);
+    whole
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_errors() {
+        assert_eq!(
+            NfcError::classify("android.nfc.TagLostException: Tag was lost."),
+            NfcError::TagLost
+        );
+        assert!(matches!(
+            NfcError::classify("Transceive failed"),
+            NfcError::Transceive(_)
+        ));
+        assert_eq!(NfcError::classify("User cancelled"), NfcError::Cancelled);
+        assert!(matches!(NfcError::classify("boom"), NfcError::Other(_)));
+    }
+
+    #[test]
+    fn test_retry_transient() {
+        let mut calls = 0;
+        let result = retry_transient(|_| {
+            calls += 1;
+            if calls < 2 {
+                Err(NfcError::TagLost)
+            } else {
+                Ok("done")
+            }
+        });
+        assert_eq!(result, Ok("done"));
+        assert_eq!(calls, 2);
+
+        // Non-transient errors are returned immediately
+        let mut calls = 0;
+        let result: Result<(), _> = retry_transient(|_| {
+            calls += 1;
+            Err(NfcError::Cancelled)
+        });
+        assert_eq!(result, Err(NfcError::Cancelled));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_reusable_payload() {
+        let mut state = NfcExchangeState::default();
+        state.set_pending("payload".to_string(), Some("abc"));
+
+        assert_eq!(state.reusable_payload(Some("abc")), Some("payload"));
+        assert_eq!(state.reusable_payload(Some("def")), None);
+        assert_eq!(state.reusable_payload(None), None);
+
+        state.clear_pending();
+        assert_eq!(state.reusable_payload(Some("abc")), None);
+    }
+
+    #[test]
+    fn test_chunked_records() {
+        // Short payloads stay a single, plain record
+        assert_eq!(chunk_payload(b"sn2:abc"), vec![b"sn2:abc".to_vec()]);
+
+        let payload: Vec<u8> = (0..700u32).map(|i| b'a' + (i % 26) as u8).collect();
+        let mut records = chunk_payload(&payload);
+        assert_eq!(records.len(), 4);
+        assert!(records.iter().all(|r| r.len() <= MAX_RECORD_LEN));
+
+        // Order doesn't matter, and other records are kept after
+        records.reverse();
+        records.push(b"nostr:npub1xyz".to_vec());
+        assert_eq!(
+            reassemble_chunks(records.clone()),
+            vec![payload.clone(), b"nostr:npub1xyz".to_vec()]
+        );
+
+        // A missing or altered chunk drops the payload
+        let mut missing = records.clone();
+        missing.remove(0);
+        assert_eq!(reassemble_chunks(missing), vec![b"nostr:npub1xyz".to_vec()]);
+        let mut altered = records;
+        *altered[0].last_mut().unwrap() ^= 1;
+        assert_eq!(reassemble_chunks(altered), vec![b"nostr:npub1xyz".to_vec()]);
+    }
+}
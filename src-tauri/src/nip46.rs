@@ -0,0 +1,387 @@
+//! NIP-46 remote signing ("Nostr Connect")
+//!
+//! With a remote signer (Amber, nsecBunker, ...) the secret key never reaches
+//! this device. We talk to the signer through a Nostr relay: requests and
+//! responses are kind 24133 events, NIP-44 encrypted between the signer and
+//! a client key of our own. `RemoteSigner` implements `keys::Signer`, so
+//! exchange messages and roster entries can come from it; they name the
+//! content-event signature scheme (`keys::SigScheme`). Anything that needs
+//! the raw secret key (the Iroh identity, NIP-44 payloads) still needs local
+//! keys.
+//!
+//! Each request opens its own relay connection (`relay::request`). Signing
+//! is rare and may wait for the user to approve it on the signer, so there is
+//! little to gain from keeping one open.
+
+use crate::keys::{
+    generate_keypair, restore_keys, sign_event, EventTemplate, KeyError, SignedEvent, Signer,
+    SignerFuture, StoredKeys,
+};
+use crate::relay::{self, RelayError};
+use nostr::nips::nip44;
+use nostr::{Keys, PublicKey};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Event kind of NIP-46 requests and responses
+pub const NOSTR_CONNECT_KIND: u16 = 24_133;
+
+/// How long to wait for an answer, which may need the user's approval
+pub const REQUEST_TIMEOUT_SECS: u64 = 120;
+
+#[derive(Error, Debug)]
+pub enum Nip46Error {
+    #[error("Invalid bunker URI: {0}")]
+    InvalidUri(String),
+    #[error("Relay error: {0}")]
+    Relay(String),
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+    #[error("The signer didn't answer in time")]
+    Timeout,
+    #[error("The signer needs approval at {0}")]
+    AuthRequired(String),
+    #[error("The signer refused: {0}")]
+    Rejected(String),
+    #[error("Invalid response from the signer: {0}")]
+    InvalidResponse(String),
+}
+
+impl From<Nip46Error> for KeyError {
+    fn from(e: Nip46Error) -> Self {
+        KeyError::SigningError(e.to_string())
+    }
+}
+
+impl From<RelayError> for Nip46Error {
+    fn from(e: RelayError) -> Self {
+        match e {
+            RelayError::Timeout => Nip46Error::Timeout,
+            e => Nip46Error::Relay(e.to_string()),
+        }
+    }
+}
+
+fn invalid_response(e: impl ToString) -> Nip46Error {
+    Nip46Error::InvalidResponse(e.to_string())
+}
+
+/// A parsed `bunker://<signer pubkey>?relay=wss://...&secret=...`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BunkerUri {
+    pub signer_pubkey: String,
+    pub relays: Vec<String>,
+    pub secret: Option<String>,
+}
+
+impl BunkerUri {
+    pub fn parse(uri: &str) -> Result<Self, Nip46Error> {
+        let invalid = |e: &str| Nip46Error::InvalidUri(e.to_string());
+        let url = reqwest::Url::parse(uri.trim()).map_err(|e| invalid(&e.to_string()))?;
+        if url.scheme() != "bunker" {
+            return Err(invalid("expected bunker://"));
+        }
+
+        let signer_pubkey = url
+            .host_str()
+            .ok_or_else(|| invalid("missing signer pubkey"))?;
+        PublicKey::from_hex(signer_pubkey).map_err(|e| invalid(&e.to_string()))?;
+
+        let mut relays = Vec::new();
+        let mut secret = None;
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "relay" if value.starts_with("wss://") || value.starts_with("ws://") => {
+                    relays.push(value.into_owned())
+                }
+                "secret" => secret = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+        if relays.is_empty() {
+            return Err(invalid("no relay given"));
+        }
+
+        Ok(Self {
+            signer_pubkey: signer_pubkey.to_string(),
+            relays,
+            secret,
+        })
+    }
+}
+
+/// What we keep to reach the signer again after a restart
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteSignerSession {
+    pub uri: BunkerUri,
+    /// Our side of the encrypted channel; it can't sign as the user
+    pub client_keys: StoredKeys,
+    /// The identity the signer signs for
+    pub user_pubkey: String,
+}
+
+/// A NIP-46 request
+#[derive(Serialize)]
+struct Request<'a> {
+    id: &'a str,
+    method: &'a str,
+    params: &'a [String],
+}
+
+/// A NIP-46 response
+#[derive(Deserialize)]
+struct Response {
+    id: String,
+    #[serde(default)]
+    result: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Signs through a NIP-46 remote signer
+pub struct RemoteSigner {
+    session: RemoteSignerSession,
+    client_keys: Keys,
+    signer_pubkey: PublicKey,
+}
+
+impl RemoteSigner {
+    /// Pair with the signer behind `uri` and learn which identity it holds
+    pub async fn connect(uri: BunkerUri) -> Result<Self, Nip46Error> {
+        let (_, client_keys) = generate_keypair().map_err(invalid_response)?;
+        let mut signer = Self::restore(RemoteSignerSession {
+            uri,
+            client_keys,
+            user_pubkey: String::new(),
+        })?;
+
+        let uri = &signer.session.uri;
+        let connect_params = [
+            uri.signer_pubkey.clone(),
+            uri.secret.clone().unwrap_or_default(),
+        ];
+        signer.request("connect", &connect_params).await?;
+
+        let user_pubkey = signer.request("get_public_key", &[]).await?;
+        PublicKey::from_hex(&user_pubkey).map_err(invalid_response)?;
+        signer.session.user_pubkey = user_pubkey;
+        Ok(signer)
+    }
+
+    /// Pick up a session paired earlier
+    pub fn restore(session: RemoteSignerSession) -> Result<Self, Nip46Error> {
+        let client_keys = restore_keys(&session.client_keys).map_err(invalid_response)?;
+        let signer_pubkey = PublicKey::from_hex(&session.uri.signer_pubkey)
+            .map_err(|e| Nip46Error::InvalidUri(e.to_string()))?;
+        Ok(Self {
+            session,
+            client_keys,
+            signer_pubkey,
+        })
+    }
+
+    pub fn session(&self) -> &RemoteSignerSession {
+        &self.session
+    }
+
+    /// Send a request through the first relay that works
+    async fn request(&self, method: &str, params: &[String]) -> Result<String, Nip46Error> {
+        let mut last_error = Nip46Error::Relay("no relays".to_string());
+        for relay in &self.session.uri.relays {
+            match self.request_via(relay, method, params).await {
+                Err(e @ Nip46Error::Relay(_)) => last_error = e,
+                result => return result,
+            }
+        }
+        Err(last_error)
+    }
+
+    async fn request_via(
+        &self,
+        relay: &str,
+        method: &str,
+        params: &[String],
+    ) -> Result<String, Nip46Error> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let request = serde_json::to_string(&Request {
+            id: &id,
+            method,
+            params,
+        })
+        .map_err(invalid_response)?;
+        let content = nip44::encrypt(
+            self.client_keys.secret_key(),
+            &self.signer_pubkey,
+            request,
+            nip44::Version::V2,
+        )
+        .map_err(|e| Nip46Error::Encryption(e.to_string()))?;
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let event = sign_event(
+            &self.client_keys,
+            &EventTemplate {
+                created_at: now,
+                kind: NOSTR_CONNECT_KIND,
+                tags: vec![vec![
+                    "p".to_string(),
+                    self.session.uri.signer_pubkey.clone(),
+                ]],
+                content,
+            },
+        );
+
+        // The signer's events to our client key
+        let filter = json!({
+            "kinds": [NOSTR_CONNECT_KIND],
+            "authors": [self.session.uri.signer_pubkey],
+            "#p": [event.pubkey],
+            "since": now.saturating_sub(10),
+        });
+        relay::request(
+            relay,
+            filter,
+            &event,
+            Duration::from_secs(REQUEST_TIMEOUT_SECS),
+            |reply| self.read_response(reply, &id).transpose(),
+        )
+        .await?
+    }
+
+    /// The result in `event` if it is the signer's answer to request `id`
+    fn read_response(&self, event: &SignedEvent, id: &str) -> Result<Option<String>, Nip46Error> {
+        // Ignore anything not from the signer or not addressed to us
+        if event.kind != NOSTR_CONNECT_KIND || event.pubkey != self.session.uri.signer_pubkey {
+            return Ok(None);
+        }
+        let Ok(plaintext) = nip44::decrypt(
+            self.client_keys.secret_key(),
+            &self.signer_pubkey,
+            &event.content,
+        ) else {
+            return Ok(None);
+        };
+
+        let response: Response = serde_json::from_str(&plaintext).map_err(invalid_response)?;
+        if response.id != id {
+            return Ok(None);
+        }
+        if response.result.as_deref() == Some("auth_url") {
+            return Err(Nip46Error::AuthRequired(response.error.unwrap_or_default()));
+        }
+        if let Some(error) = response.error.filter(|e| !e.is_empty()) {
+            return Err(Nip46Error::Rejected(error));
+        }
+        response
+            .result
+            .map(Some)
+            .ok_or_else(|| invalid_response("empty result"))
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn public_key_hex(&self) -> String {
+        self.session.user_pubkey.clone()
+    }
+
+    fn sign_event<'a>(&'a self, event: &'a EventTemplate) -> SignerFuture<'a, SignedEvent> {
+        Box::pin(async move {
+            let unsigned = json!({
+                "pubkey": self.session.user_pubkey,
+                "created_at": event.created_at,
+                "kind": event.kind,
+                "tags": event.tags,
+                "content": event.content,
+            });
+            let result = self.request("sign_event", &[unsigned.to_string()]).await?;
+
+            // It must have signed exactly what we asked, as the user
+            let signed: SignedEvent = serde_json::from_str(&result).map_err(invalid_response)?;
+            if signed.pubkey != self.session.user_pubkey || signed.template() != *event {
+                return Err(invalid_response("signed a different event").into());
+            }
+            signed.verify()?;
+            Ok(signed)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn test_parse_bunker_uri() {
+        let pubkey = Keys::generate().public_key().to_hex();
+        let uri = BunkerUri::parse(&format!(
+            "bunker://{pubkey}?relay=wss%3A%2F%2Frelay.example.com&relay=https://no&secret=abc"
+        ))
+        .unwrap();
+        assert_eq!(uri.signer_pubkey, pubkey);
+        assert_eq!(uri.relays, vec!["wss://relay.example.com".to_string()]);
+        assert_eq!(uri.secret.as_deref(), Some("abc"));
+
+        assert!(BunkerUri::parse(&format!("bunker://{pubkey}")).is_err());
+        assert!(BunkerUri::parse("bunker://nothex?relay=wss://r").is_err());
+        assert!(BunkerUri::parse(&format!("nostrconnect://{pubkey}?relay=wss://r")).is_err());
+    }
+
+    #[test]
+    fn test_read_signer_response() {
+        let (signer_keys, _) = generate_keypair().unwrap();
+        let uri = BunkerUri {
+            signer_pubkey: signer_keys.public_key().to_hex(),
+            relays: vec!["wss://relay.example.com".to_string()],
+            secret: None,
+        };
+        let (_, client_keys) = generate_keypair().unwrap();
+        let client_pubkey = PublicKey::from_hex(&client_keys.public_key_hex).unwrap();
+        let client = RemoteSigner::restore(RemoteSignerSession {
+            uri,
+            client_keys,
+            user_pubkey: String::new(),
+        })
+        .unwrap();
+
+        // The signer answers request "r1" to our client key
+        let answer = |from: &Keys, body: Value| {
+            let content = nip44::encrypt(
+                from.secret_key(),
+                &client_pubkey,
+                body.to_string(),
+                nip44::Version::V2,
+            )
+            .unwrap();
+            sign_event(
+                from,
+                &EventTemplate {
+                    created_at: 1,
+                    kind: NOSTR_CONNECT_KIND,
+                    tags: vec![vec!["p".to_string(), client_pubkey.to_hex()]],
+                    content,
+                },
+            )
+        };
+
+        let ok = answer(&signer_keys, json!({"id": "r1", "result": "ack"}));
+        assert_eq!(client.read_response(&ok, "r1").unwrap().unwrap(), "ack");
+        // A different request's answer, or someone else's, is skipped
+        assert_eq!(client.read_response(&ok, "r2").unwrap(), None);
+        let stranger = answer(&Keys::generate(), json!({"id": "r1", "result": "ack"}));
+        assert_eq!(client.read_response(&stranger, "r1").unwrap(), None);
+
+        let refused = answer(&signer_keys, json!({"id": "r1", "error": "denied"}));
+        assert!(matches!(
+            client.read_response(&refused, "r1"),
+            Err(Nip46Error::Rejected(_))
+        ));
+    }
+}
@@ -0,0 +1,222 @@
+//! Outbound queue for unreachable contacts
+//!
+//! A message sent while the contact has no connection, or whose send fails
+//! on the way out, is saved here instead of failing. A contact's queue is
+//! flushed, oldest first, as soon as a connection to them is bound again
+//! (ours or theirs), and a queued message can be retried or cancelled by
+//! hand meanwhile. Queued messages keep the ID and timestamp they were
+//! composed with, so the UI can show them as pending until they go out.
+
+use crate::chat::{ChatManager, ChatMessage};
+use crate::commands::{load_outbox_from_store, save_outbox_to_store};
+use crate::state::AppState;
+use iroh_quinn::Connection;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use thiserror::Error;
+
+/// Most messages that can wait in the outbox at once
+pub const MAX_QUEUED_MESSAGES: usize = 500;
+
+/// Event emitted with a queued message once it has been sent
+pub const OUTBOX_SENT_EVENT: &str = "chat://outbox-sent";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum OutboxError {
+    #[error("Too many queued messages")]
+    TooMany,
+    #[error("Queued message not found")]
+    NotFound,
+}
+
+/// A message waiting for its contact to be reachable
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedMessage {
+    pub contact_pubkey: String,
+    /// The message as it will be sent
+    pub message: ChatMessage,
+    /// Failed send attempts, including the one that queued it
+    pub attempts: u32,
+    /// Why the last attempt didn't go out
+    pub last_error: Option<String>,
+}
+
+impl QueuedMessage {
+    pub fn new(contact_pubkey: &str, message: ChatMessage, error: &str) -> Self {
+        Self {
+            contact_pubkey: contact_pubkey.to_string(),
+            message,
+            attempts: 1,
+            last_error: Some(error.to_string()),
+        }
+    }
+}
+
+/// Add `queued` to the end of the outbox
+pub fn enqueue(outbox: &mut Vec<QueuedMessage>, queued: QueuedMessage) -> Result<(), OutboxError> {
+    if outbox.len() >= MAX_QUEUED_MESSAGES {
+        return Err(OutboxError::TooMany);
+    }
+    outbox.push(queued);
+    Ok(())
+}
+
+/// Messages queued for a contact, oldest first
+pub fn for_contact(outbox: &[QueuedMessage], contact_pubkey: &str) -> Vec<QueuedMessage> {
+    outbox
+        .iter()
+        .filter(|q| q.contact_pubkey == contact_pubkey)
+        .cloned()
+        .collect()
+}
+
+/// Save a message that couldn't be sent, returning it as still pending
+pub fn queue(
+    app: &AppHandle,
+    contact_pubkey: &str,
+    message: ChatMessage,
+    error: &str,
+) -> Result<ChatMessage, String> {
+    let mut outbox = load_outbox_from_store(app);
+    enqueue(
+        &mut outbox,
+        QueuedMessage::new(contact_pubkey, message.clone(), error),
+    )
+    .map_err(|e| e.to_string())?;
+    save_outbox_to_store(app, &outbox)?;
+    Ok(message)
+}
+
+/// Send a contact's queued messages in the background
+pub fn spawn_flush(app: AppHandle, contact_pubkey: String) {
+    tauri::async_runtime::spawn(async move {
+        flush(&app, &contact_pubkey).await;
+    });
+}
+
+/// Send a contact's queued messages over their current connection, oldest
+/// first. Stops at the first failure so the rest keep their order.
+pub async fn flush(app: &AppHandle, contact_pubkey: &str) {
+    let state = app.state::<AppState>();
+    let Some(connection) = state
+        .iroh_node
+        .read()
+        .await
+        .get_connection(contact_pubkey)
+        .cloned()
+    else {
+        return;
+    };
+
+    // Holding the chat manager throughout keeps two flushes from sending a
+    // message twice
+    let mut chat_manager = state.chat_manager.write().await;
+    let Some(manager) = chat_manager.as_mut() else {
+        return;
+    };
+    for queued in for_contact(&load_outbox_from_store(app), contact_pubkey) {
+        let result = send_queued(manager, &connection, &queued).await;
+        let sent = result.is_ok();
+        record_result(app, &queued.message.id, result);
+        if !sent {
+            break;
+        }
+    }
+}
+
+/// Try one queued message again now, over the contact's current connection
+pub async fn retry(app: &AppHandle, message_id: &str) -> Result<ChatMessage, String> {
+    let find = || {
+        load_outbox_from_store(app)
+            .into_iter()
+            .find(|q| q.message.id == message_id)
+            .ok_or(OutboxError::NotFound.to_string())
+    };
+    let state = app.state::<AppState>();
+    let connection = state
+        .iroh_node
+        .read()
+        .await
+        .get_connection(&find()?.contact_pubkey)
+        .cloned();
+
+    let mut chat_manager = state.chat_manager.write().await;
+    // A flush may have sent it meanwhile
+    let queued = find()?;
+    let result = match (connection, chat_manager.as_mut()) {
+        (Some(connection), Some(manager)) => send_queued(manager, &connection, &queued).await,
+        (None, _) => Err("Not connected to contact".to_string()),
+        (_, None) => Err("Chat manager not initialized".to_string()),
+    };
+    record_result(app, message_id, result.clone());
+    result
+}
+
+async fn send_queued(
+    manager: &mut ChatManager,
+    connection: &Connection,
+    queued: &QueuedMessage,
+) -> Result<ChatMessage, String> {
+    let message = manager
+        .send_composed(connection, &queued.contact_pubkey, queued.message.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+    manager.record_retries(&message.id, queued.message.timestamp, queued.attempts);
+    Ok(message)
+}
+
+/// Drop a sent message from the outbox, or note why it is still queued.
+/// The outbox is reloaded so messages queued or cancelled meanwhile are kept.
+fn record_result(app: &AppHandle, message_id: &str, result: Result<ChatMessage, String>) {
+    let mut outbox = load_outbox_from_store(app);
+    match result {
+        Ok(message) => {
+            outbox.retain(|q| q.message.id != message_id);
+            let _ = app.emit(OUTBOX_SENT_EVENT, message);
+        }
+        Err(error) => {
+            let Some(entry) = outbox.iter_mut().find(|q| q.message.id == message_id) else {
+                return;
+            };
+            entry.attempts += 1;
+            entry.last_error = Some(error);
+        }
+    }
+    let _ = save_outbox_to_store(app, &outbox);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queued(contact_pubkey: &str, content: &str) -> QueuedMessage {
+        let message = ChatMessage::new_outgoing(content, "me");
+        QueuedMessage::new(contact_pubkey, message, "Not connected to contact")
+    }
+
+    #[test]
+    fn test_queue_keeps_order_per_contact() {
+        let mut outbox = Vec::new();
+        for (contact, content) in [("alice", "one"), ("bob", "hi"), ("alice", "two")] {
+            enqueue(&mut outbox, queued(contact, content)).unwrap();
+        }
+
+        let alice: Vec<_> = for_contact(&outbox, "alice")
+            .into_iter()
+            .map(|q| q.message.content)
+            .collect();
+        assert_eq!(alice, ["one", "two"]);
+        assert!(for_contact(&outbox, "carol").is_empty());
+        assert_eq!(outbox[0].attempts, 1);
+    }
+
+    #[test]
+    fn test_queue_is_capped() {
+        let mut outbox = vec![queued("alice", "hi"); MAX_QUEUED_MESSAGES];
+        assert_eq!(
+            enqueue(&mut outbox, queued("alice", "one more")),
+            Err(OutboxError::TooMany)
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Printable paper contact cards
+//!
+//! For handing out where tapping phones with everyone isn't practical, like
+//! a conference: a business-card-sized SVG with our static exchange payload
+//! as a QR code, our npub, and fingerprint words the recipient can read back
+//! against the contact once it's saved. It is rendered here rather than in
+//! the webview so the same payload always gives the same file, byte for byte.
+
+use crate::safety::fingerprint_words;
+use nostr::nips::nip19::ToBech32;
+use nostr::PublicKey;
+use qrcode::{Color, EcLevel, QrCode};
+use thiserror::Error;
+
+/// Card size in tenths of a millimetre: a standard 85 x 55 mm business card
+const CARD_WIDTH: usize = 850;
+const CARD_HEIGHT: usize = 550;
+
+/// Space around the QR code and the text
+const MARGIN: usize = 30;
+
+/// Side of the QR code, including its quiet zone
+const QR_SIZE: usize = CARD_HEIGHT - 2 * MARGIN;
+
+/// Modules of blank space around the QR code, as scanners expect
+const QUIET_ZONE: usize = 4;
+
+/// Left edge of the text column
+const TEXT_X: usize = QR_SIZE + 2 * MARGIN;
+
+/// Longest display name printed before it's cut short
+const MAX_NAME_CHARS: usize = 14;
+
+/// npub characters per printed line
+const NPUB_LINE_CHARS: usize = 21;
+
+/// Fingerprint words per printed line
+const WORDS_PER_LINE: usize = 4;
+
+#[derive(Error, Debug)]
+pub enum PaperError {
+    #[error("Invalid pubkey")]
+    InvalidPubkey,
+    #[error("The exchange payload is too large to print as a QR code")]
+    TooLarge,
+}
+
+/// Escape text for an SVG text node
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The QR code modules as one path, in module units with the quiet zone
+fn qr_path(code: &QrCode) -> String {
+    let width = code.width();
+    code.to_colors()
+        .iter()
+        .enumerate()
+        .filter(|(_, color)| **color == Color::Dark)
+        .map(|(i, _)| {
+            format!(
+                "M{} {}h1v1h-1z",
+                i % width + QUIET_ZONE,
+                i / width + QUIET_ZONE
+            )
+        })
+        .collect()
+}
+
+/// The card for `payload`, signed by `pubkey` (hex), as an SVG document
+pub fn render_card(payload: &str, pubkey: &str, name: Option<&str>) -> Result<String, PaperError> {
+    let npub = PublicKey::from_hex(pubkey)
+        .ok()
+        .and_then(|pk| pk.to_bech32().ok())
+        .ok_or(PaperError::InvalidPubkey)?;
+    let code = QrCode::with_error_correction_level(payload.as_bytes(), EcLevel::M)
+        .map_err(|_| PaperError::TooLarge)?;
+    let modules = code.width() + 2 * QUIET_ZONE;
+
+    let mut svg = format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="85mm" height="55mm" "#,
+            r#"viewBox="0 0 {w} {h}" font-family="sans-serif">"#,
+            "\n",
+            r##"<rect width="{w}" height="{h}" fill="#fff"/>"##,
+            "\n",
+            r#"<svg x="{m}" y="{m}" width="{q}" height="{q}" viewBox="0 0 {n} {n}" "#,
+            r##"shape-rendering="crispEdges"><path d="{path}" fill="#000"/></svg>"##,
+            "\n"
+        ),
+        w = CARD_WIDTH,
+        h = CARD_HEIGHT,
+        m = MARGIN,
+        q = QR_SIZE,
+        n = modules,
+        path = qr_path(&code),
+    );
+
+    let mut y = MARGIN + 50;
+    if let Some(name) = name.map(str::trim).filter(|n| !n.is_empty()) {
+        let mut shown: String = name.chars().take(MAX_NAME_CHARS).collect();
+        if name.chars().count() > MAX_NAME_CHARS {
+            shown.push('…');
+        }
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" font-size=\"34\" font-weight=\"bold\">{}</text>\n",
+            TEXT_X,
+            y,
+            escape(&shown)
+        ));
+        y += 60;
+    }
+
+    let npub_lines: Vec<&str> = npub
+        .as_bytes()
+        .chunks(NPUB_LINE_CHARS)
+        .filter_map(|chunk| std::str::from_utf8(chunk).ok())
+        .collect();
+    for line in npub_lines {
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" font-size=\"18\" font-family=\"monospace\">{}</text>\n",
+            TEXT_X, y, line
+        ));
+        y += 26;
+    }
+
+    y += 30;
+    for line in fingerprint_words(pubkey).chunks(WORDS_PER_LINE) {
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" font-size=\"22\">{}</text>\n",
+            TEXT_X,
+            y,
+            line.join(" ")
+        ));
+        y += 32;
+    }
+
+    svg.push_str(&format!(
+        "<text x=\"{}\" y=\"{}\" font-size=\"16\" fill=\"#555\">Scan with SneakerNet</text>\n",
+        TEXT_X,
+        CARD_HEIGHT - MARGIN
+    ));
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::Keys;
+
+    #[test]
+    fn test_card_is_deterministic() {
+        let pubkey = Keys::generate().public_key().to_hex();
+        let card = render_card("sn2:payload", &pubkey, Some("Alice")).unwrap();
+        assert_eq!(
+            card,
+            render_card("sn2:payload", &pubkey, Some("Alice")).unwrap()
+        );
+        assert_ne!(
+            card,
+            render_card("sn2:other", &pubkey, Some("Alice")).unwrap()
+        );
+
+        for word in fingerprint_words(&pubkey) {
+            assert!(card.contains(&word));
+        }
+        assert!(card.contains("Alice"));
+    }
+
+    #[test]
+    fn test_card_text_is_escaped() {
+        let pubkey = Keys::generate().public_key().to_hex();
+        let card = render_card("sn2:payload", &pubkey, Some("<b>&co")).unwrap();
+        assert!(card.contains("&lt;b&gt;&amp;co"));
+        assert!(!card.contains("<b>"));
+    }
+
+    #[test]
+    fn test_card_rejects_bad_input() {
+        assert!(matches!(
+            render_card("sn2:payload", "not a key", None),
+            Err(PaperError::InvalidPubkey)
+        ));
+        let pubkey = Keys::generate().public_key().to_hex();
+        assert!(matches!(
+            render_card(&"x".repeat(4_000), &pubkey, None),
+            Err(PaperError::TooLarge)
+        ));
+    }
+}
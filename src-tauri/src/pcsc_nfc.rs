@@ -0,0 +1,754 @@
+//! USB NFC readers on desktop via PC/SC
+//!
+//! With the `pcsc` feature, a reader such as the ACR122U stands in for the
+//! phone's NFC radio behind the same exchange commands:
+//!
+//! - Reading: a phone in broadcast mode emulates a Type 4 tag (see `hce`),
+//!   which we read with ISO 7816 APDUs. NTAG21x stickers (Type 2) are read
+//!   with the reader's READ BINARY pseudo-APDU.
+//! - Writing: our half of the exchange is written to a Type 2 tag. Phones
+//!   can't be written to, so a phone picks up the laptop's half from that tag
+//!   or from the QR code instead.
+//! - One tap: a phone offering the two-way exchange service (see `hce`) is
+//!   driven through the whole exchange by `tap_exchange`, which Android's
+//!   reader mode uses too.
+//!
+//! The tag protocols work over any `CardChannel`, so they are tested against
+//! the HCE responder and an in-memory tag. With the `mock-transport` feature
+//! the emulated tag in `mock_transport` replaces the reader.
+
+use crate::hce::{
+    build_mime_message, CC_FILE_ID, CLA_PROPRIETARY, EXCHANGE_AID, INS_GET_INITIAL, INS_GET_REPLY,
+    INS_PUT_RESPONSE, MAX_EXCHANGE_LEN, NDEF_AID, NDEF_FILE_ID, SW_NOT_READY, SW_REJECTED,
+};
+use crate::identifier::NOSTR_URI_PREFIX;
+use crate::nfc::{chunk_payload, NfcError};
+use std::time::Duration;
+
+/// How long a read or write waits for a tag to be presented
+pub const TAP_TIMEOUT_SECS: u64 = 30;
+
+const SW_OK: [u8; 2] = [0x90, 0x00];
+
+/// How often, and how many times, a one-tap reader asks for the card's reply
+/// while the other phone verifies our response and signs
+const REPLY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const REPLY_POLLS: u32 = 50;
+
+/// Largest chunk sent in one PUT_RESPONSE
+const MAX_PUT: usize = 0xF0;
+
+/// First user-memory page of a Type 2 tag
+const TYPE2_FIRST_PAGE: u8 = 4;
+const TYPE2_PAGE_SIZE: usize = 4;
+
+const TLV_NULL: u8 = 0x00;
+const TLV_NDEF: u8 = 0x03;
+const TLV_TERMINATOR: u8 = 0xFE;
+
+/// A card the reader is talking to
+pub trait CardChannel {
+    /// Send a command APDU and return the response including the status word
+    fn transmit(&mut self, apdu: &[u8]) -> Result<Vec<u8>, NfcError>;
+}
+
+/// Send `apdu` and return the response data if the card answered 90 00
+fn transmit_ok(card: &mut dyn CardChannel, apdu: &[u8]) -> Result<Vec<u8>, NfcError> {
+    let mut response = card.transmit(apdu)?;
+    if response.len() < 2 || response[response.len() - 2..] != SW_OK {
+        return Err(NfcError::Transceive(format!(
+            "card returned {}",
+            hex::encode(&response[response.len().saturating_sub(2)..])
+        )));
+    }
+    response.truncate(response.len() - 2);
+    Ok(response)
+}
+
+fn select(card: &mut dyn CardChannel, p1: u8, data: &[u8]) -> Result<(), NfcError> {
+    let mut apdu = vec![0x00, 0xA4, p1, 0x0C, data.len() as u8];
+    apdu.extend_from_slice(data);
+    transmit_ok(card, &apdu).map(|_| ())
+}
+
+/// Read the NDEF message from a Type 4 tag (or a phone emulating one)
+pub fn read_type4_ndef(card: &mut dyn CardChannel) -> Result<Vec<u8>, NfcError> {
+    select(card, 0x04, &NDEF_AID)?;
+    select(card, 0x00, &CC_FILE_ID)?;
+
+    let cc = transmit_ok(card, &[0x00, 0xB0, 0x00, 0x00, 0x0F])?;
+    if cc.len() < 5 {
+        return Err(NfcError::NoExchangeMessage);
+    }
+    let max_read = u16::from_be_bytes([cc[3], cc[4]]).clamp(1, 0xFF) as usize;
+
+    select(card, 0x00, &NDEF_FILE_ID)?;
+    let nlen = transmit_ok(card, &[0x00, 0xB0, 0x00, 0x00, 0x02])?;
+    if nlen.len() < 2 {
+        return Err(NfcError::NoExchangeMessage);
+    }
+    let len = u16::from_be_bytes([nlen[0], nlen[1]]) as usize;
+
+    let mut message = Vec::with_capacity(len);
+    while message.len() < len {
+        let [hi, lo] = ((message.len() + 2) as u16).to_be_bytes();
+        let le = (len - message.len()).min(max_read) as u8;
+        let chunk = transmit_ok(card, &[0x00, 0xB0, hi, lo, le])?;
+        if chunk.is_empty() {
+            return Err(NfcError::TagLost);
+        }
+        message.extend_from_slice(&chunk);
+    }
+    message.truncate(len);
+
+    Ok(message)
+}
+
+/// Read a framed message from the exchange service with `ins`
+fn read_exchange_message(card: &mut dyn CardChannel, ins: u8) -> Result<Vec<u8>, NfcError> {
+    let header = transmit_ok(card, &[CLA_PROPRIETARY, ins, 0x00, 0x00, 0x02])?;
+    if header.len() < 2 {
+        return Err(NfcError::NoExchangeMessage);
+    }
+    let len = u16::from_be_bytes([header[0], header[1]]) as usize;
+
+    let mut message = Vec::with_capacity(len);
+    while message.len() < len {
+        let [hi, lo] = ((message.len() + 2) as u16).to_be_bytes();
+        let le = (len - message.len()).min(0xFF) as u8;
+        let chunk = transmit_ok(card, &[CLA_PROPRIETARY, ins, hi, lo, le])?;
+        if chunk.is_empty() {
+            return Err(NfcError::TagLost);
+        }
+        message.extend_from_slice(&chunk);
+    }
+    message.truncate(len);
+
+    Ok(message)
+}
+
+/// Run the whole exchange in one tap with a phone offering the two-way
+/// service. `respond` verifies the card's initial message and returns our
+/// signed response to it; the card's reply to that is returned.
+pub fn tap_exchange(
+    card: &mut dyn CardChannel,
+    respond: &mut dyn FnMut(&[u8]) -> Result<Vec<u8>, NfcError>,
+) -> Result<Vec<u8>, NfcError> {
+    select(card, 0x04, &EXCHANGE_AID)?;
+    let initial = read_exchange_message(card, INS_GET_INITIAL)?;
+    let response = respond(&initial)?;
+    if response.len() > MAX_EXCHANGE_LEN {
+        return Err(NfcError::Other("Response too large".to_string()));
+    }
+
+    let mut framed = (response.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(&response);
+    for (i, chunk) in framed.chunks(MAX_PUT).enumerate() {
+        let [hi, lo] = ((i * MAX_PUT) as u16).to_be_bytes();
+        let mut apdu = vec![CLA_PROPRIETARY, INS_PUT_RESPONSE, hi, lo, chunk.len() as u8];
+        apdu.extend_from_slice(chunk);
+        transmit_ok(card, &apdu)?;
+    }
+
+    // The other phone verifies and signs while we hold still
+    for _ in 0..REPLY_POLLS {
+        let status = card.transmit(&[CLA_PROPRIETARY, INS_GET_REPLY, 0x00, 0x00, 0x02])?;
+        match status.get(status.len().saturating_sub(2)..) {
+            Some(sw) if sw == SW_NOT_READY => std::thread::sleep(REPLY_POLL_INTERVAL),
+            Some(sw) if sw == SW_REJECTED => {
+                return Err(NfcError::Verification(
+                    "The other device rejected our response".to_string(),
+                ))
+            }
+            _ => return read_exchange_message(card, INS_GET_REPLY),
+        }
+    }
+
+    Err(NfcError::Transceive(
+        "The other device did not reply in time".to_string(),
+    ))
+}
+
+/// Read four pages starting at `page` with the reader's READ BINARY pseudo-APDU
+fn read_type2_pages(card: &mut dyn CardChannel, page: u8) -> Result<Vec<u8>, NfcError> {
+    transmit_ok(card, &[0xFF, 0xB0, 0x00, page, 0x10])
+}
+
+/// Append the pages following `data` (user memory read so far)
+fn read_next_pages(card: &mut dyn CardChannel, data: &mut Vec<u8>) -> Result<(), NfcError> {
+    let page = TYPE2_FIRST_PAGE as usize + data.len() / TYPE2_PAGE_SIZE;
+    if page > u8::MAX as usize {
+        return Err(NfcError::NoExchangeMessage);
+    }
+    data.extend(read_type2_pages(card, page as u8)?);
+    Ok(())
+}
+
+/// Read the NDEF message from a Type 2 tag
+pub fn read_type2_ndef(card: &mut dyn CardChannel) -> Result<Vec<u8>, NfcError> {
+    let mut data = read_type2_pages(card, TYPE2_FIRST_PAGE)?;
+    let mut pos = 0;
+
+    loop {
+        // Make sure the TLV header (up to 4 bytes) is loaded
+        while data.len() < pos + 4 {
+            read_next_pages(card, &mut data)?;
+        }
+
+        let tlv = data[pos];
+        match tlv {
+            TLV_NULL => {
+                pos += 1;
+                continue;
+            }
+            TLV_TERMINATOR => return Err(NfcError::NoExchangeMessage),
+            _ => {}
+        }
+
+        let (len, header) = if data[pos + 1] == 0xFF {
+            (
+                u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize,
+                4,
+            )
+        } else {
+            (data[pos + 1] as usize, 2)
+        };
+        let start = pos + header;
+
+        while data.len() < start + len {
+            read_next_pages(card, &mut data)?;
+        }
+
+        if tlv == TLV_NDEF {
+            return Ok(data[start..start + len].to_vec());
+        }
+        // Lock / memory control TLVs
+        pos = start + len;
+    }
+}
+
+/// Write `message` as the NDEF message of a Type 2 tag
+pub fn write_type2_ndef(card: &mut dyn CardChannel, message: &[u8]) -> Result<(), NfcError> {
+    // Capability container: byte 2 is the data area size / 8
+    let cc = read_type2_pages(card, 3)?;
+    if cc.len() < 4 || cc[0] != 0xE1 {
+        return Err(NfcError::Other("Tag is not NDEF formatted".to_string()));
+    }
+    let capacity =
+        (cc[2] as usize * 8).min((u8::MAX - TYPE2_FIRST_PAGE) as usize * TYPE2_PAGE_SIZE);
+
+    let mut tlv = vec![TLV_NDEF];
+    if message.len() < 0xFF {
+        tlv.push(message.len() as u8);
+    } else {
+        tlv.push(0xFF);
+        tlv.extend_from_slice(&(message.len() as u16).to_be_bytes());
+    }
+    tlv.extend_from_slice(message);
+    tlv.push(TLV_TERMINATOR);
+
+    if tlv.len() > capacity {
+        return Err(NfcError::Other(format!(
+            "Tag too small ({} bytes needed, {} available)",
+            tlv.len(),
+            capacity
+        )));
+    }
+
+    while tlv.len() % TYPE2_PAGE_SIZE != 0 {
+        tlv.push(TLV_NULL);
+    }
+
+    for (i, page) in tlv.chunks(TYPE2_PAGE_SIZE).enumerate() {
+        let mut apdu = vec![0xFF, 0xD6, 0x00, TYPE2_FIRST_PAGE + i as u8, 0x04];
+        apdu.extend_from_slice(page);
+        transmit_ok(card, &apdu)?;
+    }
+
+    Ok(())
+}
+
+/// Read whichever NDEF message the presented card holds
+pub fn read_ndef(card: &mut dyn CardChannel) -> Result<Vec<u8>, NfcError> {
+    match read_type4_ndef(card) {
+        Ok(message) => Ok(message),
+        // Not a Type 4 target; try it as a sticker
+        Err(NfcError::Transceive(_)) => read_type2_ndef(card),
+        Err(e) => Err(e),
+    }
+}
+
+/// NFC Forum URI record abbreviations, indexed by the record's first byte
+const URI_PREFIXES: [&str; 36] = [
+    "",
+    "http://www.",
+    "https://www.",
+    "http://",
+    "https://",
+    "tel:",
+    "mailto:",
+    "ftp://anonymous:anonymous@",
+    "ftp://ftp.",
+    "ftps://",
+    "sftp://",
+    "smb://",
+    "nfs://",
+    "ftp://",
+    "dav://",
+    "news:",
+    "telnet://",
+    "imap:",
+    "rtsp://",
+    "urn:",
+    "pop:",
+    "sip:",
+    "sips:",
+    "tftp:",
+    "btspp://",
+    "btl2cap://",
+    "btgoep://",
+    "tcpobex://",
+    "irdaobex://",
+    "file://",
+    "urn:epc:id:",
+    "urn:epc:tag:",
+    "urn:epc:pat:",
+    "urn:epc:raw:",
+    "urn:epc:",
+    "urn:nfc:",
+];
+
+/// One record of an NDEF message
+struct NdefRecord<'a> {
+    tnf: u8,
+    kind: &'a [u8],
+    payload: &'a [u8],
+}
+
+/// The records of an NDEF message, up to the first malformed one
+fn ndef_records(message: &[u8]) -> Vec<NdefRecord<'_>> {
+    let mut records = Vec::new();
+    let mut pos = 0;
+
+    while pos + 3 <= message.len() {
+        let header = message[pos];
+        let short = header & 0x10 != 0;
+        let has_id = header & 0x08 != 0;
+        let tnf = header & 0x07;
+        let type_len = message[pos + 1] as usize;
+        pos += 2;
+
+        let payload_len = if short {
+            let len = message[pos] as usize;
+            pos += 1;
+            len
+        } else {
+            let Some(bytes) = message.get(pos..pos + 4) else {
+                break;
+            };
+            pos += 4;
+            u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize
+        };
+        let id_len = if has_id {
+            let Some(&len) = message.get(pos) else {
+                break;
+            };
+            pos += 1;
+            len as usize
+        } else {
+            0
+        };
+
+        let type_end = pos + type_len;
+        let payload_start = type_end + id_len;
+        let payload_end = payload_start + payload_len;
+        if payload_end > message.len() {
+            break;
+        }
+
+        records.push(NdefRecord {
+            tnf,
+            kind: &message[pos..type_end],
+            payload: &message[payload_start..payload_end],
+        });
+
+        pos = payload_end;
+        if header & 0x40 != 0 {
+            break;
+        }
+    }
+
+    records
+}
+
+/// Payloads of the MIME records of `mime_type` in an NDEF message
+pub fn mime_payloads(message: &[u8], mime_type: &str) -> Vec<Vec<u8>> {
+    ndef_records(message)
+        .into_iter()
+        // TNF 0x02 = media type
+        .filter(|r| r.tnf == 0x02 && r.kind == mime_type.as_bytes())
+        .map(|r| r.payload.to_vec())
+        .collect()
+}
+
+/// The URI in the payload of a well-known URI (`U`) record
+pub fn uri_from_payload(payload: &[u8]) -> Option<String> {
+    let (&code, rest) = payload.split_first()?;
+    let prefix = URI_PREFIXES.get(code as usize)?;
+    let rest = std::str::from_utf8(rest).ok()?;
+    Some(format!("{}{}", prefix, rest))
+}
+
+/// URIs of the well-known URI records in an NDEF message
+pub fn uri_records(message: &[u8]) -> Vec<String> {
+    ndef_records(message)
+        .into_iter()
+        // TNF 0x01 = NFC Forum well-known type
+        .filter(|r| r.tnf == 0x01 && r.kind == b"U")
+        .filter_map(|r| uri_from_payload(r.payload))
+        .collect()
+}
+
+/// What the receive path looks at on a tag: SneakerNet records, then any
+/// `nostr:` URIs written by other Nostr apps
+pub fn exchange_payloads(message: &[u8]) -> Vec<Vec<u8>> {
+    let mut payloads = mime_payloads(message, crate::exchange::NDEF_MIME_TYPE);
+    payloads.extend(
+        uri_records(message)
+            .into_iter()
+            .filter(|uri| uri.starts_with(NOSTR_URI_PREFIX))
+            .map(String::into_bytes),
+    );
+    payloads
+}
+
+/// Whether a PC/SC reader is connected
+pub fn reader_available() -> bool {
+    #[cfg(feature = "mock-transport")]
+    {
+        true
+    }
+
+    #[cfg(all(feature = "pcsc", not(feature = "mock-transport")))]
+    {
+        backend::list_readers()
+            .map(|r| !r.is_empty())
+            .unwrap_or(false)
+    }
+
+    #[cfg(not(any(feature = "pcsc", feature = "mock-transport")))]
+    {
+        false
+    }
+}
+
+/// Wait for a tap and return the payloads of its SneakerNet records
+pub fn read_exchange_payloads() -> Result<Vec<Vec<u8>>, NfcError> {
+    #[cfg(feature = "mock-transport")]
+    {
+        crate::mock_transport::MockField::global().read_exchange_payloads()
+    }
+
+    #[cfg(all(feature = "pcsc", not(feature = "mock-transport")))]
+    {
+        let mut card = backend::wait_for_card()?;
+        let message = read_ndef(&mut card)?;
+        Ok(exchange_payloads(&message))
+    }
+
+    #[cfg(not(any(feature = "pcsc", feature = "mock-transport")))]
+    {
+        Err(NfcError::NotAvailable)
+    }
+}
+
+/// Wait for a card on the USB reader and run `f` with it
+pub fn with_reader_card<T>(
+    f: impl FnOnce(&mut dyn CardChannel) -> Result<T, NfcError>,
+) -> Result<T, NfcError> {
+    #[cfg(feature = "pcsc")]
+    {
+        let mut card = backend::wait_for_card()?;
+        f(&mut card)
+    }
+
+    #[cfg(not(feature = "pcsc"))]
+    {
+        let _ = f;
+        Err(NfcError::NotAvailable)
+    }
+}
+
+/// Wait for a tag and write `payload` to it as SneakerNet records, chunked
+/// if it doesn't fit one
+pub fn write_exchange_payload(payload: &str) -> Result<(), NfcError> {
+    #[cfg(feature = "mock-transport")]
+    {
+        crate::mock_transport::MockField::global().write_exchange_payload(payload)
+    }
+
+    #[cfg(not(feature = "mock-transport"))]
+    {
+        let message = build_mime_message(
+            crate::exchange::NDEF_MIME_TYPE.as_bytes(),
+            &chunk_payload(payload.as_bytes()),
+        );
+
+        #[cfg(feature = "pcsc")]
+        {
+            let mut card = backend::wait_for_card()?;
+            write_type2_ndef(&mut card, &message)
+        }
+
+        #[cfg(not(feature = "pcsc"))]
+        {
+            let _ = message;
+            Err(NfcError::NotAvailable)
+        }
+    }
+}
+
+#[cfg(feature = "pcsc")]
+mod backend {
+    use super::{CardChannel, TAP_TIMEOUT_SECS};
+    use crate::nfc::NfcError;
+    use pcsc::{Card, Context, Protocols, Scope, ShareMode, MAX_BUFFER_SIZE};
+    use std::ffi::CString;
+    use std::time::{Duration, Instant};
+
+    impl CardChannel for Card {
+        fn transmit(&mut self, apdu: &[u8]) -> Result<Vec<u8>, NfcError> {
+            let mut buffer = [0u8; MAX_BUFFER_SIZE];
+            Card::transmit(self, apdu, &mut buffer)
+                .map(|response| response.to_vec())
+                .map_err(|e| match e {
+                    pcsc::Error::RemovedCard | pcsc::Error::ResetCard => NfcError::TagLost,
+                    e => NfcError::Transceive(e.to_string()),
+                })
+        }
+    }
+
+    fn context() -> Result<Context, NfcError> {
+        Context::establish(Scope::User).map_err(|_| NfcError::NotAvailable)
+    }
+
+    pub fn list_readers() -> Result<Vec<CString>, NfcError> {
+        context()?
+            .list_readers_owned()
+            .map_err(|_| NfcError::NotAvailable)
+    }
+
+    /// Poll the connected readers until a card is presented
+    pub fn wait_for_card() -> Result<Card, NfcError> {
+        let ctx = context()?;
+        let deadline = Instant::now() + Duration::from_secs(TAP_TIMEOUT_SECS);
+
+        loop {
+            let readers = ctx
+                .list_readers_owned()
+                .map_err(|_| NfcError::NotAvailable)?;
+            if readers.is_empty() {
+                return Err(NfcError::NotAvailable);
+            }
+
+            for reader in &readers {
+                if let Ok(card) = ctx.connect(reader, ShareMode::Shared, Protocols::ANY) {
+                    return Ok(card);
+                }
+            }
+
+            if Instant::now() >= deadline {
+                return Err(NfcError::Cancelled);
+            }
+            std::thread::sleep(Duration::from_millis(200));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::NDEF_MIME_TYPE;
+    use crate::hce::{build_mime_record, ExchangeResponder, HceResponder};
+
+    impl CardChannel for HceResponder {
+        fn transmit(&mut self, apdu: &[u8]) -> Result<Vec<u8>, NfcError> {
+            Ok(self.process_apdu(apdu))
+        }
+    }
+
+    /// A phone offering the two-way service, replying as soon as it hears
+    /// from the reader unless told to refuse
+    struct ExchangePhone {
+        responder: ExchangeResponder,
+        heard: Option<Vec<u8>>,
+        refuse: bool,
+    }
+
+    impl CardChannel for ExchangePhone {
+        fn transmit(&mut self, apdu: &[u8]) -> Result<Vec<u8>, NfcError> {
+            let response = self.responder.process_apdu(apdu);
+            if let Some(heard) = self.responder.take_received() {
+                if self.refuse {
+                    self.responder.reject();
+                } else {
+                    self.responder.answer(&[b"reply to ", &heard[..]].concat());
+                }
+                self.heard = Some(heard);
+            }
+            Ok(response)
+        }
+    }
+
+    /// NTAG215-sized Type 2 tag behind an ACR122U
+    struct MemoryTag {
+        memory: Vec<u8>,
+    }
+
+    impl MemoryTag {
+        fn formatted() -> Self {
+            let mut memory = vec![0u8; 135 * 4];
+            memory[12..16].copy_from_slice(&[0xE1, 0x10, 0x3E, 0x00]);
+            memory[16..19].copy_from_slice(&[TLV_NDEF, 0x00, TLV_TERMINATOR]);
+            Self { memory }
+        }
+    }
+
+    impl CardChannel for MemoryTag {
+        fn transmit(&mut self, apdu: &[u8]) -> Result<Vec<u8>, NfcError> {
+            let start = apdu[3] as usize * 4;
+            let mut response = match apdu[1] {
+                0xB0 => {
+                    let mut pages = self.memory.get(start..).unwrap_or_default().to_vec();
+                    pages.resize(16, 0);
+                    pages
+                }
+                0xD6 => {
+                    self.memory[start..start + 4].copy_from_slice(&apdu[5..9]);
+                    Vec::new()
+                }
+                _ => return Ok(vec![0x6D, 0x00]),
+            };
+            response.extend_from_slice(&SW_OK);
+            Ok(response)
+        }
+    }
+
+    #[test]
+    fn test_read_phone_hce() {
+        let payload = vec![b'x'; 600];
+        let mut phone = HceResponder::new(&payload);
+
+        let message = read_ndef(&mut phone).unwrap();
+        assert_eq!(mime_payloads(&message, NDEF_MIME_TYPE), vec![payload]);
+        assert_eq!(phone.reads(), 1);
+    }
+
+    #[test]
+    fn test_one_tap_exchange() {
+        let initial = vec![b'i'; 300];
+        let mut phone = ExchangePhone {
+            responder: ExchangeResponder::new(&initial),
+            heard: None,
+            refuse: false,
+        };
+
+        let response = vec![b'r'; 500];
+        let reply = tap_exchange(&mut phone, &mut |theirs| {
+            assert_eq!(theirs, &initial[..]);
+            Ok(response.clone())
+        })
+        .unwrap();
+
+        assert_eq!(phone.heard.as_deref(), Some(&response[..]));
+        assert_eq!(reply, [b"reply to ", &response[..]].concat());
+
+        // A phone that refuses our response ends the tap without a contact
+        phone = ExchangePhone {
+            responder: ExchangeResponder::new(&initial),
+            heard: None,
+            refuse: true,
+        };
+        assert!(matches!(
+            tap_exchange(&mut phone, &mut |_| Ok(response.clone())),
+            Err(NfcError::Verification(_))
+        ));
+    }
+
+    #[test]
+    fn test_nostr_uri_records() {
+        let npub = "nostr:npub1sg6plzptd64u62a878hep2kev88swjh3tw00gjsfl8f237lmu63q0uf63m";
+        // "nostr:" has no abbreviation, so the URI follows code 0 in full
+        let uri = build_record(0x01, b"U", &[&[0x00], npub.as_bytes()].concat());
+        let web = build_record(0x01, b"U", &[&[0x04], &b"example.com"[..]].concat());
+        let ours = build_mime_record(NDEF_MIME_TYPE.as_bytes(), b"sn2:abc");
+
+        let message = [web, uri, ours].concat();
+        assert_eq!(
+            uri_records(&message),
+            vec!["https://example.com".to_string(), npub.to_string()]
+        );
+        // Our own record comes first, then the Nostr URI; the web link is ignored
+        assert_eq!(
+            exchange_payloads(&message),
+            vec![b"sn2:abc".to_vec(), npub.as_bytes().to_vec()]
+        );
+    }
+
+    /// A short record, flagged begin/end by the caller's concatenation only
+    fn build_record(tnf: u8, kind: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut record = vec![0x10 | tnf, kind.len() as u8, payload.len() as u8];
+        record.extend_from_slice(kind);
+        record.extend_from_slice(payload);
+        record
+    }
+
+    #[test]
+    fn test_type2_roundtrip() {
+        let mut tag = MemoryTag::formatted();
+        assert!(matches!(
+            read_type2_ndef(&mut tag),
+            Ok(message) if message.is_empty()
+        ));
+
+        let payload = br#"{"version":1,"pubkey":"ab"}"#;
+        let record = build_mime_record(NDEF_MIME_TYPE.as_bytes(), payload);
+        write_type2_ndef(&mut tag, &record).unwrap();
+
+        // Not a Type 4 target, so read_ndef falls back to Type 2
+        let message = read_ndef(&mut tag).unwrap();
+        assert_eq!(
+            mime_payloads(&message, NDEF_MIME_TYPE),
+            vec![payload.to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_chunked_payload_roundtrip() {
+        let mut tag = MemoryTag::formatted();
+        let payload = format!("sn2:{}", "A".repeat(400));
+        let chunks = chunk_payload(payload.as_bytes());
+        assert_eq!(chunks.len(), 2);
+        let message = build_mime_message(NDEF_MIME_TYPE.as_bytes(), &chunks);
+        write_type2_ndef(&mut tag, &message).unwrap();
+
+        let records = exchange_payloads(&read_ndef(&mut tag).unwrap());
+        assert_eq!(records, chunks);
+        assert_eq!(
+            crate::nfc::reassemble_chunks(records),
+            vec![payload.into_bytes()]
+        );
+    }
+
+    #[test]
+    fn test_write_rejects_small_tag() {
+        let mut tag = MemoryTag::formatted();
+        tag.memory[14] = 0x02; // 16-byte data area
+
+        let record = build_mime_record(NDEF_MIME_TYPE.as_bytes(), &[0u8; 64]);
+        assert!(matches!(
+            write_type2_ndef(&mut tag, &record),
+            Err(NfcError::Other(_))
+        ));
+    }
+}
@@ -0,0 +1,134 @@
+//! Petname resolution over pubkeys
+//!
+//! A contact can be known by several names. The user's own nickname always
+//! wins, then the name given by whoever introduced them, then the name from
+//! their own profile, and finally a shortened pubkey. Because only the
+//! nickname is chosen by the user, two contacts resolving to the same name
+//! are flagged so the UI can tell them apart.
+
+use crate::exchange::Contact;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Where a resolved display name came from, highest precedence first
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum NameSource {
+    Nickname,
+    Introducer,
+    Profile,
+    Pubkey,
+}
+
+/// Display name for a contact
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedName {
+    pub name: String,
+    pub source: NameSource,
+    /// Another contact resolves to the same name
+    pub conflict: bool,
+}
+
+/// A contact with its resolved display name, as returned by `get_contacts`
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NamedContact {
+    #[serde(flatten)]
+    pub contact: Contact,
+    pub display_name: ResolvedName,
+}
+
+fn non_empty(name: &Option<String>) -> Option<&str> {
+    name.as_deref().map(str::trim).filter(|n| !n.is_empty())
+}
+
+/// Resolve a single contact's name, ignoring conflicts
+pub fn resolve(contact: &Contact) -> (String, NameSource) {
+    if let Some(name) = non_empty(&contact.nickname) {
+        (name.to_string(), NameSource::Nickname)
+    } else if let Some(name) = non_empty(&contact.introduced_as) {
+        (name.to_string(), NameSource::Introducer)
+    } else if let Some(name) = non_empty(&contact.profile_name) {
+        (name.to_string(), NameSource::Profile)
+    } else {
+        let short = &contact.nostr_pubkey[..contact.nostr_pubkey.len().min(8)];
+        (format!("{}…", short), NameSource::Pubkey)
+    }
+}
+
+/// Resolve names for all contacts, flagging names claimed by more than one pubkey
+pub fn resolve_all(contacts: Vec<Contact>) -> Vec<NamedContact> {
+    let resolved: Vec<(String, NameSource)> = contacts.iter().map(resolve).collect();
+
+    let mut claimants: HashMap<String, Vec<&str>> = HashMap::new();
+    for (contact, (name, _)) in contacts.iter().zip(&resolved) {
+        let pubkeys = claimants.entry(name.to_lowercase()).or_default();
+        if !pubkeys.contains(&contact.nostr_pubkey.as_str()) {
+            pubkeys.push(&contact.nostr_pubkey);
+        }
+    }
+
+    let conflicts: Vec<bool> = resolved
+        .iter()
+        .map(|(name, _)| claimants[&name.to_lowercase()].len() > 1)
+        .collect();
+
+    contacts
+        .into_iter()
+        .zip(resolved)
+        .zip(conflicts)
+        .map(|((contact, (name, source)), conflict)| NamedContact {
+            contact,
+            display_name: ResolvedName {
+                name,
+                source,
+                conflict,
+            },
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_precedence() {
+        let mut contact = Contact::new("abcdef0123456789", "endpoint");
+        assert_eq!(
+            resolve(&contact),
+            ("abcdef01…".to_string(), NameSource::Pubkey)
+        );
+
+        contact.profile_name = Some("satoshi".to_string());
+        assert_eq!(resolve(&contact).1, NameSource::Profile);
+
+        contact.introduced_as = Some("Alice from work".to_string());
+        assert_eq!(resolve(&contact).1, NameSource::Introducer);
+
+        contact.nickname = Some("  ".to_string());
+        assert_eq!(resolve(&contact).1, NameSource::Introducer);
+
+        contact.nickname = Some("Alice".to_string());
+        assert_eq!(
+            resolve(&contact),
+            ("Alice".to_string(), NameSource::Nickname)
+        );
+    }
+
+    #[test]
+    fn test_conflicting_names() {
+        let mut a = Contact::new("aa", "endpoint");
+        a.profile_name = Some("Bob".to_string());
+        let mut b = Contact::new("bb", "endpoint");
+        b.introduced_as = Some("bob".to_string());
+        let mut c = Contact::new("cc", "endpoint");
+        c.nickname = Some("Carol".to_string());
+
+        let named = resolve_all(vec![a, b, c]);
+        assert!(named[0].display_name.conflict);
+        assert!(named[1].display_name.conflict);
+        assert!(!named[2].display_name.conflict);
+    }
+}
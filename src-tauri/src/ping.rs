@@ -0,0 +1,126 @@
+//! Connectivity check over a chat connection
+//!
+//! A ping is a 16-byte frame (magic + random nonce) on a new bidirectional
+//! stream; the peer echoes it back on the same stream. Chat messages use
+//! unidirectional streams, so pings never interleave with them. Every chat
+//! connection gets a responder next to its message listener, which also
+//! answers identity challenges (see `binding`).
+
+use crate::binding::{Prover, BIND_MAGIC};
+use crate::iroh_node::PathType;
+use iroh_quinn::Connection;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// How long to wait for the echo
+pub const PING_TIMEOUT_SECS: u64 = 10;
+
+const PING_MAGIC: &[u8; 8] = b"SNKPING1";
+const FRAME_LEN: usize = 16;
+
+#[derive(Error, Debug)]
+pub enum PingError {
+    #[error("Ping failed: {0}")]
+    Stream(String),
+    #[error("No reply within {0}s")]
+    Timeout(u64),
+    #[error("Peer replied with the wrong frame")]
+    BadReply,
+}
+
+/// Outcome of `ping_contact`
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PingResult {
+    pub contact_id: String,
+    pub rtt_ms: u64,
+    pub path: PathType,
+    /// A connection had to be opened for the ping
+    pub connected_now: bool,
+}
+
+pub fn encode_ping(nonce: u64) -> [u8; FRAME_LEN] {
+    let mut frame = [0u8; FRAME_LEN];
+    frame[..8].copy_from_slice(PING_MAGIC);
+    frame[8..].copy_from_slice(&nonce.to_be_bytes());
+    frame
+}
+
+/// The nonce of a ping frame
+pub fn decode_ping(frame: &[u8]) -> Option<u64> {
+    if frame.len() != FRAME_LEN || &frame[..8] != PING_MAGIC {
+        return None;
+    }
+    Some(u64::from_be_bytes(frame[8..].try_into().ok()?))
+}
+
+/// Send a ping and wait for its echo, returning the round-trip time
+pub async fn ping(connection: &Connection) -> Result<Duration, PingError> {
+    let nonce = rand::random::<u64>();
+    let started = Instant::now();
+
+    let round_trip = async {
+        let (mut send, mut recv) = connection
+            .open_bi()
+            .await
+            .map_err(|e| PingError::Stream(e.to_string()))?;
+        send.write_all(&encode_ping(nonce))
+            .await
+            .map_err(|e| PingError::Stream(e.to_string()))?;
+        send.finish()
+            .map_err(|e| PingError::Stream(e.to_string()))?;
+
+        let mut reply = [0u8; FRAME_LEN];
+        recv.read_exact(&mut reply)
+            .await
+            .map_err(|e| PingError::Stream(e.to_string()))?;
+
+        match decode_ping(&reply) {
+            Some(echoed) if echoed == nonce => Ok(started.elapsed()),
+            _ => Err(PingError::BadReply),
+        }
+    };
+
+    tokio::time::timeout(Duration::from_secs(PING_TIMEOUT_SECS), round_trip)
+        .await
+        .map_err(|_| PingError::Timeout(PING_TIMEOUT_SECS))?
+}
+
+/// Echo pings on `connection` until it closes, and answer identity
+/// challenges with `prover`
+pub fn spawn_responder(connection: Connection, prover: Prover) {
+    tauri::async_runtime::spawn(async move {
+        while let Ok((mut send, mut recv)) = connection.accept_bi().await {
+            let mut frame = [0u8; FRAME_LEN];
+            if recv.read_exact(&mut frame[..8]).await.is_err() {
+                continue;
+            }
+            if &frame[..8] == BIND_MAGIC {
+                let _ = prover.answer(&mut send, &mut recv).await;
+                continue;
+            }
+            if recv.read_exact(&mut frame[8..]).await.is_err() || decode_ping(&frame).is_none() {
+                continue;
+            }
+            let _ = send.write_all(&frame).await;
+            let _ = send.finish();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let frame = encode_ping(42);
+        assert_eq!(decode_ping(&frame), Some(42));
+
+        let mut wrong = frame;
+        wrong[0] = b'X';
+        assert_eq!(decode_ping(&wrong), None);
+        assert_eq!(decode_ping(&frame[..8]), None);
+    }
+}
@@ -0,0 +1,262 @@
+//! Backend extension points
+//!
+//! Features that don't belong in the chat core (games, file sync, ...) plug
+//! in here instead. A protocol handler owns its own ALPN: the Iroh endpoint
+//! advertises it and incoming connections negotiated with it are handed to the
+//! handler whole. A message plugin owns a `custom/*` message kind and sees
+//! every accepted chat message of that kind after the content filters ran.
+//!
+//! Plugins are registered once at startup and passed in via
+//! `AppState::with_plugins`; the registry is read-only afterwards.
+
+use crate::chat::ChatMessage;
+use crate::iroh_node::CHAT_ALPN;
+use crate::message_types::CUSTOM_PREFIX;
+use iroh_quinn::Connection;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Future returned by a protocol handler for one connection
+pub type HandlerFuture = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum PluginError {
+    #[error("ALPN {0} is reserved")]
+    ReservedAlpn(String),
+    #[error("ALPN {0} already has a handler")]
+    DuplicateAlpn(String),
+    #[error("Plugin message kinds must start with \"custom/\": {0}")]
+    InvalidKind(String),
+    #[error("Message kind {0} already has a handler")]
+    DuplicateKind(String),
+}
+
+/// Handles every connection negotiated with its ALPN
+pub trait ProtocolHandler: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// ALPN this handler speaks, e.g. `b"sneakernet-tictactoe/1"`
+    fn alpn(&self) -> &[u8];
+
+    /// Take over an accepted connection. `peer_pubkey` is the contact's
+    /// Nostr pubkey when the connection is tied to a known contact.
+    fn handle(&self, connection: Connection, peer_pubkey: Option<String>) -> HandlerFuture;
+}
+
+/// Receives chat messages of one `custom/*` kind
+pub trait MessagePlugin: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn kind(&self) -> &str;
+
+    /// Called for each accepted message of this kind, incoming or outgoing
+    fn on_message(&self, message: &ChatMessage);
+}
+
+/// Summary of a registered plugin, for the UI
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum PluginInfo {
+    Protocol { name: String, alpn: String },
+    Message { name: String, kind: String },
+}
+
+/// Registered plugins
+#[derive(Clone, Default)]
+pub struct PluginRegistry {
+    protocols: BTreeMap<Vec<u8>, Arc<dyn ProtocolHandler>>,
+    message_plugins: BTreeMap<String, Arc<dyn MessagePlugin>>,
+}
+
+impl PluginRegistry {
+    /// Register a handler for a custom ALPN
+    pub fn register_protocol(
+        &mut self,
+        handler: Arc<dyn ProtocolHandler>,
+    ) -> Result<(), PluginError> {
+        let alpn = handler.alpn().to_vec();
+        let display = String::from_utf8_lossy(&alpn).to_string();
+
+        if alpn == CHAT_ALPN {
+            return Err(PluginError::ReservedAlpn(display));
+        }
+        if self.protocols.contains_key(&alpn) {
+            return Err(PluginError::DuplicateAlpn(display));
+        }
+
+        self.protocols.insert(alpn, handler);
+        Ok(())
+    }
+
+    /// Register a handler for a `custom/*` message kind
+    pub fn register_message_plugin(
+        &mut self,
+        plugin: Arc<dyn MessagePlugin>,
+    ) -> Result<(), PluginError> {
+        let kind = plugin.kind().to_string();
+
+        if !kind.starts_with(CUSTOM_PREFIX) || kind.len() == CUSTOM_PREFIX.len() {
+            return Err(PluginError::InvalidKind(kind));
+        }
+        if self.message_plugins.contains_key(&kind) {
+            return Err(PluginError::DuplicateKind(kind));
+        }
+
+        self.message_plugins.insert(kind, plugin);
+        Ok(())
+    }
+
+    /// ALPNs to advertise on the endpoint besides chat
+    pub fn alpns(&self) -> Vec<Vec<u8>> {
+        self.protocols.keys().cloned().collect()
+    }
+
+    /// Hand a connection to the handler for `alpn`, returning its future to
+    /// drive. `None` means no plugin speaks that ALPN.
+    pub fn dispatch(
+        &self,
+        alpn: &[u8],
+        connection: Connection,
+        peer_pubkey: Option<String>,
+    ) -> Option<HandlerFuture> {
+        self.protocols
+            .get(alpn)
+            .map(|handler| handler.handle(connection, peer_pubkey))
+    }
+
+    /// Pass a chat message to the plugin for its kind, if any.
+    /// Returns whether a plugin received it.
+    pub fn route(&self, message: &ChatMessage) -> bool {
+        match self.message_plugins.get(&message.kind) {
+            Some(plugin) => {
+                plugin.on_message(message);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.protocols.is_empty() && self.message_plugins.is_empty()
+    }
+
+    pub fn list(&self) -> Vec<PluginInfo> {
+        let protocols = self
+            .protocols
+            .iter()
+            .map(|(alpn, handler)| PluginInfo::Protocol {
+                name: handler.name().to_string(),
+                alpn: String::from_utf8_lossy(alpn).to_string(),
+            });
+        let messages = self
+            .message_plugins
+            .iter()
+            .map(|(kind, plugin)| PluginInfo::Message {
+                name: plugin.name().to_string(),
+                kind: kind.clone(),
+            });
+
+        protocols.chain(messages).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct Recorder {
+        kind: String,
+        seen: Mutex<Vec<String>>,
+    }
+
+    impl MessagePlugin for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn kind(&self) -> &str {
+            &self.kind
+        }
+
+        fn on_message(&self, message: &ChatMessage) {
+            self.seen.lock().unwrap().push(message.id.clone());
+        }
+    }
+
+    struct Echo(&'static [u8]);
+
+    impl ProtocolHandler for Echo {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn alpn(&self) -> &[u8] {
+            self.0
+        }
+
+        fn handle(&self, _connection: Connection, _peer_pubkey: Option<String>) -> HandlerFuture {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    fn recorder(kind: &str) -> Arc<Recorder> {
+        Arc::new(Recorder {
+            kind: kind.to_string(),
+            seen: Mutex::new(Vec::new()),
+        })
+    }
+
+    #[test]
+    fn test_message_routing() {
+        let mut registry = PluginRegistry::default();
+        let plugin = recorder("custom/tictactoe");
+        registry.register_message_plugin(plugin.clone()).unwrap();
+
+        let mut game = ChatMessage::new_outgoing("Tic-tac-toe move", "them");
+        game.kind = "custom/tictactoe".to_string();
+        assert!(registry.route(&game));
+        assert!(!registry.route(&ChatMessage::new_outgoing("hi", "them")));
+        assert_eq!(*plugin.seen.lock().unwrap(), vec![game.id.clone()]);
+
+        assert_eq!(
+            registry.register_message_plugin(recorder("custom/tictactoe")),
+            Err(PluginError::DuplicateKind("custom/tictactoe".to_string()))
+        );
+        assert!(matches!(
+            registry.register_message_plugin(recorder("location")),
+            Err(PluginError::InvalidKind(_))
+        ));
+    }
+
+    #[test]
+    fn test_protocol_registration() {
+        let mut registry = PluginRegistry::default();
+        assert!(registry.is_empty());
+
+        registry
+            .register_protocol(Arc::new(Echo(b"sneakernet-echo/1")))
+            .unwrap();
+        assert_eq!(registry.alpns(), vec![b"sneakernet-echo/1".to_vec()]);
+
+        assert!(matches!(
+            registry.register_protocol(Arc::new(Echo(b"sneakernet-echo/1"))),
+            Err(PluginError::DuplicateAlpn(_))
+        ));
+        assert!(matches!(
+            registry.register_protocol(Arc::new(Echo(CHAT_ALPN))),
+            Err(PluginError::ReservedAlpn(_))
+        ));
+        assert_eq!(
+            registry.list(),
+            vec![PluginInfo::Protocol {
+                name: "echo".to_string(),
+                alpn: "sneakernet-echo/1".to_string(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,344 @@
+//! Profiles and the app PIN, including a duress PIN
+//!
+//! Each profile has its own store file and contact database; the primary
+//! profile uses the original file names. With an app PIN set, a profile's
+//! keys are sealed with a random profile key, and the PIN lock holds that
+//! key encrypted under the PIN. A duress PIN gets a slot of its own that
+//! opens a decoy profile with an empty identity instead.
+//!
+//! The lock always has `PIN_SLOTS` slots of the same size, unused ones
+//! filled with random bytes, so it doesn't show whether a duress PIN is
+//! configured. Unlocking tries every slot, so it doesn't take measurably
+//! longer for one PIN than the other. This protects against being made to
+//! open the app; someone with the raw files can still see that a second,
+//! sealed profile exists.
+
+use crate::keys::{KdfParams, KeyError};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{Key as CipherKey, XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// Store file of the primary profile
+pub const PRIMARY_STORE_FILE: &str = "sneakernet.json";
+
+/// Contact database of the primary profile
+pub const PRIMARY_CONTACTS_DB_FILE: &str = "contacts.sqlite3";
+
+/// Message database of the primary profile
+pub const PRIMARY_MESSAGES_DB_FILE: &str = "messages.sqlite3";
+
+/// Store file holding the PIN lock, outside every profile
+pub const PIN_LOCK_FILE: &str = "pin-lock.json";
+
+/// Slots in a PIN lock: the app PIN and the duress PIN
+pub const PIN_SLOTS: usize = 2;
+
+/// Shortest PIN accepted
+pub const MIN_PIN_LEN: usize = 4;
+
+const PROFILE_ID_LEN: usize = 8;
+const PROFILE_KEY_LEN: usize = 32;
+const SLOT_PLAINTEXT_LEN: usize = PROFILE_ID_LEN + PROFILE_KEY_LEN;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ProfileError {
+    #[error("PIN must be at least {MIN_PIN_LEN} digits")]
+    InvalidPin,
+    #[error("Wrong PIN")]
+    WrongPin,
+    #[error("The duress PIN must differ from the app PIN")]
+    SamePin,
+    #[error("Encryption failed: {0}")]
+    EncryptionError(String),
+}
+
+impl From<KeyError> for ProfileError {
+    fn from(e: KeyError) -> Self {
+        ProfileError::EncryptionError(e.to_string())
+    }
+}
+
+/// Identifies a profile; all zeros is the primary profile
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProfileId([u8; PROFILE_ID_LEN]);
+
+impl ProfileId {
+    pub const PRIMARY: ProfileId = ProfileId([0; PROFILE_ID_LEN]);
+
+    pub fn new_random() -> Self {
+        loop {
+            let id = ProfileId(rand::random());
+            if id != Self::PRIMARY {
+                return id;
+            }
+        }
+    }
+
+    pub fn is_primary(&self) -> bool {
+        *self == Self::PRIMARY
+    }
+
+    pub fn store_file(&self) -> String {
+        if self.is_primary() {
+            PRIMARY_STORE_FILE.to_string()
+        } else {
+            format!("sneakernet-{}.json", hex::encode(self.0))
+        }
+    }
+
+    pub fn contacts_db_file(&self) -> String {
+        if self.is_primary() {
+            PRIMARY_CONTACTS_DB_FILE.to_string()
+        } else {
+            format!("contacts-{}.sqlite3", hex::encode(self.0))
+        }
+    }
+
+    pub fn messages_db_file(&self) -> String {
+        if self.is_primary() {
+            PRIMARY_MESSAGES_DB_FILE.to_string()
+        } else {
+            format!("messages-{}.sqlite3", hex::encode(self.0))
+        }
+    }
+}
+
+/// Whether `name` in the app data directory belongs to a profile or the
+/// PIN lock
+pub fn is_app_data_file(name: &str) -> bool {
+    (name.starts_with("sneakernet") && name.ends_with(".json"))
+        || ((name.starts_with("contacts") || name.starts_with("messages"))
+            && name.contains(".sqlite3"))
+        || name == PIN_LOCK_FILE
+}
+
+/// Random key a profile's secret key is sealed with while a PIN is set
+pub struct ProfileKey(Zeroizing<[u8; PROFILE_KEY_LEN]>);
+
+impl std::fmt::Debug for ProfileKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProfileKey(..)")
+    }
+}
+
+impl ProfileKey {
+    pub fn new_random() -> Self {
+        ProfileKey(Zeroizing::new(rand::random()))
+    }
+
+    /// As a passphrase for `SealedKeys::seal_with`
+    pub fn passphrase(&self) -> Zeroizing<String> {
+        Zeroizing::new(hex::encode(self.0.as_slice()))
+    }
+}
+
+fn encryption_error(e: impl ToString) -> ProfileError {
+    ProfileError::EncryptionError(e.to_string())
+}
+
+/// One slot of the PIN lock
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PinSlot {
+    kdf: KdfParams,
+    nonce_hex: String,
+    ciphertext_hex: String,
+}
+
+impl PinSlot {
+    fn seal(pin: &str, plaintext: &[u8], cost: &KdfParams) -> Result<Self, ProfileError> {
+        let kdf = cost.with_new_salt();
+        let key = kdf.derive_key(pin)?;
+        let nonce = rand::random::<[u8; 24]>();
+        let ciphertext = XChaCha20Poly1305::new(CipherKey::from_slice(key.as_slice()))
+            .encrypt(XNonce::from_slice(&nonce), plaintext)
+            .map_err(encryption_error)?;
+
+        Ok(Self {
+            kdf,
+            nonce_hex: hex::encode(nonce),
+            ciphertext_hex: hex::encode(ciphertext),
+        })
+    }
+
+    /// A slot no PIN opens, indistinguishable from a used one
+    fn filler(cost: &KdfParams) -> Result<Self, ProfileError> {
+        let pin = hex::encode(rand::random::<[u8; 32]>());
+        let plaintext: [u8; SLOT_PLAINTEXT_LEN] = std::array::from_fn(|_| rand::random());
+        Self::seal(&pin, &plaintext, cost)
+    }
+
+    fn for_profile(
+        pin: &str,
+        id: ProfileId,
+        key: &ProfileKey,
+        cost: &KdfParams,
+    ) -> Result<Self, ProfileError> {
+        let mut plaintext = Zeroizing::new([0u8; SLOT_PLAINTEXT_LEN]);
+        plaintext[..PROFILE_ID_LEN].copy_from_slice(&id.0);
+        plaintext[PROFILE_ID_LEN..].copy_from_slice(key.0.as_slice());
+        Self::seal(pin, plaintext.as_slice(), cost)
+    }
+
+    fn open(&self, pin: &str) -> Option<(ProfileId, ProfileKey)> {
+        let nonce = hex::decode(&self.nonce_hex)
+            .ok()
+            .filter(|n| n.len() == 24)?;
+        let ciphertext = hex::decode(&self.ciphertext_hex).ok()?;
+        let key = self.kdf.derive_key(pin).ok()?;
+        let plaintext = Zeroizing::new(
+            XChaCha20Poly1305::new(CipherKey::from_slice(key.as_slice()))
+                .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+                .ok()?,
+        );
+        if plaintext.len() != SLOT_PLAINTEXT_LEN {
+            return None;
+        }
+
+        let mut id = [0u8; PROFILE_ID_LEN];
+        id.copy_from_slice(&plaintext[..PROFILE_ID_LEN]);
+        let mut key = ProfileKey(Zeroizing::new([0u8; PROFILE_KEY_LEN]));
+        key.0.copy_from_slice(&plaintext[PROFILE_ID_LEN..]);
+        Some((ProfileId(id), key))
+    }
+}
+
+/// The app PIN and, optionally, a duress PIN
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PinLock {
+    slots: Vec<PinSlot>,
+}
+
+fn check_pin(pin: &str) -> Result<(), ProfileError> {
+    if pin.len() < MIN_PIN_LEN || !pin.chars().all(|c| c.is_ascii_digit()) {
+        return Err(ProfileError::InvalidPin);
+    }
+    Ok(())
+}
+
+impl PinLock {
+    /// A lock whose `pin` opens the primary profile
+    pub fn new(
+        pin: &str,
+        primary_key: &ProfileKey,
+        cost: &KdfParams,
+    ) -> Result<Self, ProfileError> {
+        check_pin(pin)?;
+        let mut slots = vec![PinSlot::for_profile(
+            pin,
+            ProfileId::PRIMARY,
+            primary_key,
+            cost,
+        )?];
+        while slots.len() < PIN_SLOTS {
+            slots.push(PinSlot::filler(cost)?);
+        }
+        Ok(Self { slots })
+    }
+
+    /// The profile `pin` opens, and its key. Every slot is tried.
+    pub fn open(&self, pin: &str) -> Result<(ProfileId, ProfileKey), ProfileError> {
+        self.slots
+            .iter()
+            .map(|slot| slot.open(pin))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flatten()
+            .next()
+            .ok_or(ProfileError::WrongPin)
+    }
+
+    /// Set (or replace) the duress PIN, opening `decoy`. Needs the app PIN.
+    pub fn set_duress(
+        &mut self,
+        pin: &str,
+        duress_pin: &str,
+        decoy: ProfileId,
+        decoy_key: &ProfileKey,
+        cost: &KdfParams,
+    ) -> Result<(), ProfileError> {
+        check_pin(duress_pin)?;
+        let primary = self
+            .slots
+            .iter()
+            .position(|slot| slot.open(pin).is_some_and(|(id, _)| id.is_primary()))
+            .ok_or(ProfileError::WrongPin)?;
+        if pin == duress_pin {
+            return Err(ProfileError::SamePin);
+        }
+
+        let slot = PinSlot::for_profile(duress_pin, decoy, decoy_key, cost)?;
+        // The duress slot is whichever one isn't the primary's
+        let other = (primary + 1) % PIN_SLOTS;
+        self.slots[other] = slot;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_cost() -> KdfParams {
+        KdfParams {
+            m_cost: 64,
+            t_cost: 1,
+            p_cost: 1,
+            salt_hex: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_pin_opens_primary() {
+        let key = ProfileKey::new_random();
+        let lock = PinLock::new("2468", &key, &test_cost()).unwrap();
+        assert_eq!(lock.slots.len(), PIN_SLOTS);
+
+        let (id, opened) = lock.open("2468").unwrap();
+        assert!(id.is_primary());
+        assert_eq!(opened.passphrase(), key.passphrase());
+        assert_eq!(lock.open("1357").unwrap_err(), ProfileError::WrongPin);
+        assert_eq!(
+            PinLock::new("12a4", &key, &test_cost()).unwrap_err(),
+            ProfileError::InvalidPin
+        );
+        assert_eq!(id.store_file(), PRIMARY_STORE_FILE);
+        assert!(is_app_data_file("contacts.sqlite3-wal"));
+        assert!(is_app_data_file(&ProfileId::PRIMARY.messages_db_file()));
+        assert!(!is_app_data_file(".wipe-1234"));
+    }
+
+    #[test]
+    fn test_duress_pin_opens_decoy() {
+        let cost = test_cost();
+        let key = ProfileKey::new_random();
+        let mut lock = PinLock::new("2468", &key, &cost).unwrap();
+        let before = serde_json::to_string(&lock).unwrap().len();
+
+        let decoy = ProfileId::new_random();
+        let decoy_key = ProfileKey::new_random();
+        assert_eq!(
+            lock.set_duress("0000", "1357", decoy, &decoy_key, &cost)
+                .unwrap_err(),
+            ProfileError::WrongPin
+        );
+        assert_eq!(
+            lock.set_duress("2468", "2468", decoy, &decoy_key, &cost)
+                .unwrap_err(),
+            ProfileError::SamePin
+        );
+        lock.set_duress("2468", "1357", decoy, &decoy_key, &cost)
+            .unwrap();
+
+        // Same shape with or without a duress PIN
+        assert_eq!(serde_json::to_string(&lock).unwrap().len(), before);
+
+        let (id, opened) = lock.open("1357").unwrap();
+        assert_eq!(id, decoy);
+        assert!(id.store_file().starts_with("sneakernet-"));
+        assert_eq!(opened.passphrase(), decoy_key.passphrase());
+        assert!(lock.open("2468").unwrap().0.is_primary());
+    }
+}
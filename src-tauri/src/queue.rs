@@ -0,0 +1,330 @@
+//! Concurrent pending exchanges
+//!
+//! At a busy table several people tap or scan within seconds of each other.
+//! Each peer gets its own entry keyed by pubkey, so a second scan no longer
+//! overwrites the first, and entries that never complete time out on their own.
+
+use crate::exchange::{ExchangeProfile, Negotiated, NodeHints};
+use crate::stats::Transport;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Pending exchanges older than this are dropped (matches payload expiry)
+pub const PENDING_EXCHANGE_TTL_SECS: u64 = 300;
+
+/// Most exchanges tracked at once; the oldest is dropped to make room
+pub const MAX_PENDING_EXCHANGES: usize = 16;
+
+/// How often stale pending exchanges are swept while the app runs
+pub const PENDING_SWEEP_SECS: u64 = 15;
+
+/// An exchange that has received the peer's payload but not yet completed
+#[derive(Clone, Debug)]
+pub struct PendingExchange {
+    pub their_pubkey: String,
+    pub transport: Transport,
+    pub started: Instant,
+    /// Our signed response has been delivered to them
+    pub responded: bool,
+    /// Identity their device certificate names, if they used a device key
+    pub master_pubkey: Option<String>,
+    /// How to answer them, from the version of their message
+    pub protocol: Negotiated,
+    /// Where their message said their node can be dialed
+    pub hints: NodeHints,
+    /// Profile metadata their message carried
+    pub profile: Option<ExchangeProfile>,
+    /// Nonce of their message
+    pub their_nonce: Option<String>,
+    /// Signature of their message
+    pub their_signature: Option<String>,
+    /// Nonce of our message they answer or are answered with
+    pub our_nonce: Option<String>,
+    /// Their message was a response to our initial message, whose nonce is
+    /// then the one they hold for us
+    pub answered_us: bool,
+    /// The user confirmed the short authentication strings match
+    pub sas_confirmed: bool,
+}
+
+/// Pending exchange as shown to the frontend
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingExchangeInfo {
+    pub their_pubkey: String,
+    pub transport: Transport,
+    pub age_secs: u64,
+    /// Time left before it is dropped
+    pub expires_in_secs: u64,
+    pub responded: bool,
+    pub master_pubkey: Option<String>,
+    pub sas_confirmed: bool,
+}
+
+impl PendingExchange {
+    pub fn is_stale(&self) -> bool {
+        self.started.elapsed() > Duration::from_secs(PENDING_EXCHANGE_TTL_SECS)
+    }
+
+    pub fn info(&self) -> PendingExchangeInfo {
+        let age_secs = self.started.elapsed().as_secs();
+        PendingExchangeInfo {
+            their_pubkey: self.their_pubkey.clone(),
+            transport: self.transport,
+            age_secs,
+            expires_in_secs: PENDING_EXCHANGE_TTL_SECS.saturating_sub(age_secs),
+            responded: self.responded,
+            master_pubkey: self.master_pubkey.clone(),
+            sas_confirmed: self.sas_confirmed,
+        }
+    }
+}
+
+/// Pending exchanges in arrival order
+#[derive(Debug, Default)]
+pub struct ExchangeQueue {
+    pending: Vec<PendingExchange>,
+}
+
+impl ExchangeQueue {
+    /// Track an exchange with `their_pubkey`. A repeat tap from the same peer
+    /// keeps the original entry. Returns true if the entry is new.
+    pub fn track(&mut self, their_pubkey: &str, transport: Transport) -> bool {
+        self.expire_stale();
+
+        if self.get(their_pubkey).is_some() {
+            return false;
+        }
+
+        if self.pending.len() >= MAX_PENDING_EXCHANGES {
+            self.pending.remove(0);
+        }
+
+        self.pending.push(PendingExchange {
+            their_pubkey: their_pubkey.to_string(),
+            transport,
+            started: Instant::now(),
+            responded: false,
+            master_pubkey: None,
+            protocol: Negotiated::OURS,
+            hints: NodeHints::default(),
+            profile: None,
+            their_nonce: None,
+            their_signature: None,
+            our_nonce: None,
+            answered_us: false,
+            sas_confirmed: false,
+        });
+        true
+    }
+
+    pub fn get(&self, their_pubkey: &str) -> Option<&PendingExchange> {
+        self.pending.iter().find(|p| p.their_pubkey == their_pubkey)
+    }
+
+    pub fn mark_responded(&mut self, their_pubkey: &str) {
+        if let Some(p) = self
+            .pending
+            .iter_mut()
+            .find(|p| p.their_pubkey == their_pubkey)
+        {
+            p.responded = true;
+        }
+    }
+
+    /// Record the identity `their_pubkey` is a certified device key of
+    pub fn set_master(&mut self, their_pubkey: &str, master_pubkey: &str) {
+        if let Some(p) = self
+            .pending
+            .iter_mut()
+            .find(|p| p.their_pubkey == their_pubkey)
+        {
+            p.master_pubkey = Some(master_pubkey.to_string());
+        }
+    }
+
+    /// Record the protocol negotiated with `their_pubkey`
+    pub fn set_protocol(&mut self, their_pubkey: &str, protocol: Negotiated) {
+        if let Some(p) = self
+            .pending
+            .iter_mut()
+            .find(|p| p.their_pubkey == their_pubkey)
+        {
+            p.protocol = protocol;
+        }
+    }
+
+    /// Record where `their_pubkey` can be dialed
+    pub fn set_hints(&mut self, their_pubkey: &str, hints: NodeHints) {
+        if let Some(p) = self
+            .pending
+            .iter_mut()
+            .find(|p| p.their_pubkey == their_pubkey)
+        {
+            p.hints = hints;
+        }
+    }
+
+    /// Record the profile `their_pubkey` signed into their message
+    pub fn set_profile(&mut self, their_pubkey: &str, profile: Option<ExchangeProfile>) {
+        if let Some(p) = self
+            .pending
+            .iter_mut()
+            .find(|p| p.their_pubkey == their_pubkey)
+        {
+            p.profile = profile;
+        }
+    }
+
+    /// Record the nonce and signature of their latest message.
+    /// `initial_nonce` is that of our initial message, given when theirs
+    /// answers it.
+    pub fn set_their_message(
+        &mut self,
+        their_pubkey: &str,
+        nonce: &str,
+        signature: &str,
+        initial_nonce: Option<&str>,
+    ) {
+        if let Some(p) = self
+            .pending
+            .iter_mut()
+            .find(|p| p.their_pubkey == their_pubkey)
+        {
+            if p.their_nonce.as_deref() != Some(nonce) {
+                p.sas_confirmed = false;
+            }
+            p.their_nonce = Some(nonce.to_string());
+            p.their_signature = Some(signature.to_string());
+            if let Some(initial_nonce) = initial_nonce {
+                p.our_nonce = Some(initial_nonce.to_string());
+                p.answered_us = true;
+            }
+        }
+    }
+
+    /// Record the nonce of a response we made for `their_pubkey`. Once they
+    /// have answered our initial message, that one is what they hold for us.
+    pub fn set_our_nonce(&mut self, their_pubkey: &str, nonce: &str) {
+        if let Some(p) = self
+            .pending
+            .iter_mut()
+            .find(|p| p.their_pubkey == their_pubkey && !p.answered_us)
+        {
+            if p.our_nonce.as_deref() != Some(nonce) {
+                p.sas_confirmed = false;
+            }
+            p.our_nonce = Some(nonce.to_string());
+        }
+    }
+
+    /// The user compared the short authentication string with `their_pubkey`
+    pub fn confirm_sas(&mut self, their_pubkey: &str) {
+        if let Some(p) = self
+            .pending
+            .iter_mut()
+            .find(|p| p.their_pubkey == their_pubkey)
+        {
+            p.sas_confirmed = true;
+        }
+    }
+
+    /// Remove and return the exchange with `their_pubkey`
+    pub fn complete(&mut self, their_pubkey: &str) -> Option<PendingExchange> {
+        let index = self
+            .pending
+            .iter()
+            .position(|p| p.their_pubkey == their_pubkey)?;
+        Some(self.pending.remove(index))
+    }
+
+    /// Drop timed-out exchanges and return them
+    pub fn expire_stale(&mut self) -> Vec<PendingExchange> {
+        let (stale, fresh) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(PendingExchange::is_stale);
+        self.pending = fresh;
+        stale
+    }
+
+    pub fn list(&self) -> Vec<PendingExchangeInfo> {
+        self.pending.iter().map(PendingExchange::info).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concurrent_exchanges_are_independent() {
+        let mut queue = ExchangeQueue::default();
+        assert!(queue.track("alice", Transport::Nfc));
+        assert!(queue.track("bob", Transport::Qr));
+        assert!(!queue.track("alice", Transport::Nfc));
+
+        queue.mark_responded("bob");
+        let bob = queue.complete("bob").unwrap();
+        assert!(bob.responded);
+        assert_eq!(bob.transport, Transport::Qr);
+
+        // Alice's exchange is untouched
+        assert!(!queue.get("alice").unwrap().responded);
+        assert!(queue.complete("bob").is_none());
+    }
+
+    #[test]
+    fn test_stale_exchanges_expire() {
+        let mut queue = ExchangeQueue::default();
+        queue.track("alice", Transport::Nfc);
+        queue.track("bob", Transport::Nfc);
+        queue.pending[0].started -= Duration::from_secs(PENDING_EXCHANGE_TTL_SECS + 1);
+
+        let expired = queue.expire_stale();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].their_pubkey, "alice");
+        assert_eq!(expired[0].info().expires_in_secs, 0);
+        assert_eq!(queue.list().len(), 1);
+        assert!(queue.list()[0].expires_in_secs > PENDING_EXCHANGE_TTL_SECS - 5);
+    }
+
+    #[test]
+    fn test_sas_nonces() {
+        let mut queue = ExchangeQueue::default();
+
+        // We answered their initial message; a reshown response replaces ours
+        queue.track("alice", Transport::Qr);
+        queue.set_their_message("alice", "a1", "s1", None);
+        queue.set_our_nonce("alice", "b1");
+        queue.set_our_nonce("alice", "b2");
+        queue.confirm_sas("alice");
+        let alice = queue.get("alice").unwrap();
+        assert_eq!(alice.our_nonce.as_deref(), Some("b2"));
+        assert!(alice.sas_confirmed);
+
+        // They answered ours, so a response we show afterwards doesn't count
+        queue.track("bob", Transport::Qr);
+        queue.set_their_message("bob", "c1", "s2", Some("b0"));
+        queue.set_our_nonce("bob", "b3");
+        assert_eq!(queue.get("bob").unwrap().our_nonce.as_deref(), Some("b0"));
+
+        // A new message needs confirming again
+        queue.set_their_message("alice", "a2", "s3", None);
+        assert!(!queue.get("alice").unwrap().sas_confirmed);
+    }
+
+    #[test]
+    fn test_oldest_dropped_when_full() {
+        let mut queue = ExchangeQueue::default();
+        for i in 0..=MAX_PENDING_EXCHANGES {
+            queue.track(&i.to_string(), Transport::Qr);
+        }
+
+        assert_eq!(queue.list().len(), MAX_PENDING_EXCHANGES);
+        assert!(queue.get("0").is_none());
+    }
+}
@@ -0,0 +1,514 @@
+//! Forward-secret chat encryption
+//!
+//! Chat frames are sealed end to end with keys that move forward as messages
+//! flow, so someone who later takes our Nostr key (or the device) can't
+//! decrypt messages captured earlier. Each side advertises a ratchet key in
+//! every frame. A sender starts a chain by combining a throwaway key with the
+//! other side's ratchet key (ECDH), salted with the relationship secret from
+//! `iroh_derive::derive_chat_secret`, and hashes the chain key forward once
+//! per message. Used message keys, chain keys and ratchet key secrets are
+//! deleted, and a fresh ratchet key is advertised once the last one is used,
+//! so each round trip starts new chains. After a post-quantum handshake (see
+//! `hybrid`), new chains also mix in its secret and name it in their header.
+
+use crate::hybrid::HybridSecret;
+use crate::iroh_derive::{shared_secret, DeriveError};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key as CipherKey, Nonce};
+use hkdf::Hkdf;
+use nostr::Keys;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// HKDF info string of a chain's first key; bumped whenever the scheme changes
+pub const RATCHET_CONTEXT: &[u8] = b"sneakernet-chat-ratchet-v1";
+
+/// Most messages a chain may skip ahead, and most skipped keys kept
+pub const MAX_SKIPPED_KEYS: usize = 256;
+
+/// Ratchet keys of ours kept for messages still in flight to them
+const MAX_OUR_KEYS: usize = 4;
+
+/// Receiving chains kept, newest last
+const MAX_RECEIVING_CHAINS: usize = 4;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RatchetError {
+    #[error("This contact hasn't sent a ratchet key yet")]
+    NoPeerKey,
+    #[error("Message was sealed to a ratchet key we no longer have")]
+    UnknownKey,
+    #[error("Message is too far ahead of its chain")]
+    TooFarAhead,
+    #[error("Message key was already used")]
+    Replayed,
+    #[error("Could not decrypt message")]
+    Decrypt,
+    #[error("Invalid ratchet key: {0}")]
+    InvalidKey(String),
+}
+
+impl From<DeriveError> for RatchetError {
+    fn from(e: DeriveError) -> Self {
+        RatchetError::InvalidKey(e.to_string())
+    }
+}
+
+/// Sent in the clear with each sealed frame
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RatchetHeader {
+    /// Sender's throwaway key that started this chain
+    pub ephemeral: String,
+    /// Recipient's ratchet key the chain was started with
+    pub to: String,
+    /// Position of the message in the chain
+    pub n: u32,
+    /// Sender's newest ratchet key, to seal replies to
+    pub next: String,
+    /// ID of the hybrid handshake secret mixed into the chain, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hybrid: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct RatchetKey {
+    public: String,
+    secret: Zeroizing<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct SendingChain {
+    ephemeral: String,
+    to: String,
+    #[serde(default)]
+    hybrid: Option<String>,
+    key: Zeroizing<[u8; 32]>,
+    n: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ReceivingChain {
+    ephemeral: String,
+    key: Zeroizing<[u8; 32]>,
+    n: u32,
+}
+
+/// Key of a message a chain moved past before it arrived
+#[derive(Serialize, Deserialize, Clone)]
+struct SkippedKey {
+    ephemeral: String,
+    n: u32,
+    key: Zeroizing<[u8; 32]>,
+}
+
+/// Ratchet state for one contact. It is saved after every message, since a
+/// lost state can't read what the contact sealed to it.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Ratchet {
+    /// Ratchet keys we advertised, oldest first
+    our_keys: Vec<RatchetKey>,
+    /// Newest ratchet key the contact advertised
+    their_key: Option<String>,
+    sending: Option<SendingChain>,
+    receiving: Vec<ReceivingChain>,
+    skipped: Vec<SkippedKey>,
+    /// Secret of the last hybrid handshake with the contact
+    #[serde(default)]
+    hybrid: Option<HybridSecret>,
+    /// Whether our chains mix in `hybrid`: once the contact is known to
+    /// have it too
+    #[serde(default)]
+    hybrid_sending: bool,
+}
+
+/// First key of a chain from its ECDH secret and the hybrid secret, if any
+fn chain_start(
+    seed: &[u8; 32],
+    dh: &[u8; 32],
+    hybrid: Option<&HybridSecret>,
+) -> Zeroizing<[u8; 32]> {
+    let mut ikm = Zeroizing::new(dh.to_vec());
+    if let Some(hybrid) = hybrid {
+        ikm.extend_from_slice(hybrid.secret.as_slice());
+    }
+    let hk = Hkdf::<Sha256>::new(Some(seed.as_slice()), &ikm);
+    let mut key = Zeroizing::new([0u8; 32]);
+    hk.expand(RATCHET_CONTEXT, key.as_mut_slice())
+        .expect("32 bytes is a valid HKDF length");
+    key
+}
+
+/// Move a chain key forward, returning the key of the message at its
+/// current position
+fn chain_step(chain: &mut Zeroizing<[u8; 32]>) -> Zeroizing<[u8; 32]> {
+    let hk = Hkdf::<Sha256>::new(None, chain.as_slice());
+    let mut message_key = Zeroizing::new([0u8; 32]);
+    let mut next = Zeroizing::new([0u8; 32]);
+    hk.expand(b"message", message_key.as_mut_slice())
+        .expect("32 bytes is a valid HKDF length");
+    hk.expand(b"chain", next.as_mut_slice())
+        .expect("32 bytes is a valid HKDF length");
+    *chain = next;
+    message_key
+}
+
+/// Each message key seals one message, so a fixed nonce is safe
+fn cipher(message_key: &[u8; 32]) -> (ChaCha20Poly1305, Nonce) {
+    (
+        ChaCha20Poly1305::new(CipherKey::from_slice(message_key)),
+        Nonce::default(),
+    )
+}
+
+/// The header, as authenticated along with the ciphertext
+fn header_aad(header: &RatchetHeader) -> Vec<u8> {
+    serde_json::to_vec(header).expect("header serializes")
+}
+
+impl Ratchet {
+    /// Our newest ratchet key, generated the first time it is asked for
+    pub fn advertised_key(&mut self) -> String {
+        if self.our_keys.is_empty() {
+            self.rotate();
+        }
+        self.our_keys[self.our_keys.len() - 1].public.clone()
+    }
+
+    /// Start advertising a fresh ratchet key, forgetting the oldest
+    fn rotate(&mut self) {
+        let keys = Keys::generate();
+        self.our_keys.push(RatchetKey {
+            public: keys.public_key().to_hex(),
+            secret: keys.secret_key().to_secret_hex().into(),
+        });
+        if self.our_keys.len() > MAX_OUR_KEYS {
+            self.our_keys.remove(0);
+        }
+    }
+
+    /// Note the ratchet key a contact advertised; the next message to them
+    /// starts a chain with it
+    pub fn learn(&mut self, their_key: &str) {
+        self.their_key = Some(their_key.to_string());
+    }
+
+    /// Whether messages to this contact can be sealed yet
+    pub fn can_seal(&self) -> bool {
+        self.their_key.is_some()
+    }
+
+    /// Whether a sealed message from this contact has opened. From then on
+    /// they seal everything, so a plain message didn't come from their app.
+    pub fn has_opened(&self) -> bool {
+        !self.receiving.is_empty()
+    }
+
+    /// Mix a hybrid handshake secret into chains started from now on. Until
+    /// `sending`, only the contact's chains use it: the side that answered
+    /// the offer waits for a chain naming it, which shows the other side has
+    /// it too.
+    pub fn set_hybrid(&mut self, secret: HybridSecret, sending: bool) {
+        self.hybrid = Some(secret);
+        self.hybrid_sending = sending;
+    }
+
+    /// ID of the hybrid secret, once both sides use it
+    pub fn hybrid_id(&self) -> Option<&str> {
+        self.hybrid
+            .as_ref()
+            .filter(|_| self.hybrid_sending)
+            .map(|h| h.id.as_str())
+    }
+
+    /// Encrypt `plaintext` with the next key of our sending chain
+    pub fn seal(
+        &mut self,
+        seed: &[u8; 32],
+        plaintext: &[u8],
+    ) -> Result<(RatchetHeader, Vec<u8>), RatchetError> {
+        let their_key = self.their_key.clone().ok_or(RatchetError::NoPeerKey)?;
+        let next = self.advertised_key();
+        let hybrid = self.hybrid.as_ref().filter(|_| self.hybrid_sending);
+        let hybrid_id = hybrid.map(|h| h.id.clone());
+
+        if !self
+            .sending
+            .as_ref()
+            .is_some_and(|c| c.to == their_key && c.hybrid == hybrid_id)
+        {
+            let ephemeral = Keys::generate();
+            let dh = shared_secret(&ephemeral.secret_key().secret_bytes(), &their_key)?;
+            self.sending = Some(SendingChain {
+                ephemeral: ephemeral.public_key().to_hex(),
+                to: their_key,
+                key: chain_start(seed, &dh, hybrid),
+                hybrid: hybrid_id,
+                n: 0,
+            });
+        }
+        let chain = self.sending.as_mut().expect("sending chain was just set");
+
+        let header = RatchetHeader {
+            ephemeral: chain.ephemeral.clone(),
+            to: chain.to.clone(),
+            n: chain.n,
+            next,
+            hybrid: chain.hybrid.clone(),
+        };
+        let message_key = chain_step(&mut chain.key);
+        chain.n += 1;
+
+        let (cipher, nonce) = cipher(&message_key);
+        let aad = header_aad(&header);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| RatchetError::Decrypt)?;
+        Ok((header, ciphertext))
+    }
+
+    /// Decrypt a sealed message. The state only changes if it decrypts, so
+    /// a forged frame can't push chains forward or use up keys.
+    pub fn open(
+        &mut self,
+        seed: &[u8; 32],
+        header: &RatchetHeader,
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, RatchetError> {
+        let mut next = self.clone();
+        let message_key = next.message_key(seed, header)?;
+
+        let (cipher, nonce) = cipher(&message_key);
+        let aad = header_aad(header);
+        let plaintext = cipher
+            .decrypt(
+                &nonce,
+                Payload {
+                    msg: ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| RatchetError::Decrypt)?;
+
+        // Once they use our newest key, advertise a fresh one
+        if next.our_keys.last().is_some_and(|k| k.public == header.to) {
+            next.rotate();
+        }
+        if header.hybrid.is_some() {
+            next.hybrid_sending = true;
+        }
+        next.learn(&header.next);
+        *self = next;
+        Ok(plaintext)
+    }
+
+    /// Key of the message at `header`, advancing its receiving chain
+    fn message_key(
+        &mut self,
+        seed: &[u8; 32],
+        header: &RatchetHeader,
+    ) -> Result<Zeroizing<[u8; 32]>, RatchetError> {
+        if let Some(index) = self
+            .skipped
+            .iter()
+            .position(|s| s.ephemeral == header.ephemeral && s.n == header.n)
+        {
+            return Ok(self.skipped.remove(index).key);
+        }
+
+        let index = match self
+            .receiving
+            .iter()
+            .position(|c| c.ephemeral == header.ephemeral)
+        {
+            Some(index) => index,
+            None => {
+                let ours = self
+                    .our_keys
+                    .iter()
+                    .find(|k| k.public == header.to)
+                    .ok_or(RatchetError::UnknownKey)?;
+                let secret =
+                    hex::decode(ours.secret.as_str()).map_err(|_| RatchetError::UnknownKey)?;
+                let dh = shared_secret(&Zeroizing::new(secret), &header.ephemeral)?;
+                let hybrid = match &header.hybrid {
+                    Some(id) => Some(
+                        self.hybrid
+                            .as_ref()
+                            .filter(|h| &h.id == id)
+                            .ok_or(RatchetError::UnknownKey)?,
+                    ),
+                    None => None,
+                };
+                let key = chain_start(seed, &dh, hybrid);
+                self.receiving.push(ReceivingChain {
+                    ephemeral: header.ephemeral.clone(),
+                    key,
+                    n: 0,
+                });
+                if self.receiving.len() > MAX_RECEIVING_CHAINS {
+                    self.receiving.remove(0);
+                }
+                self.receiving.len() - 1
+            }
+        };
+        let chain = &mut self.receiving[index];
+
+        if header.n < chain.n {
+            return Err(RatchetError::Replayed);
+        }
+        if (header.n - chain.n) as usize > MAX_SKIPPED_KEYS {
+            return Err(RatchetError::TooFarAhead);
+        }
+        while chain.n < header.n {
+            self.skipped.push(SkippedKey {
+                ephemeral: chain.ephemeral.clone(),
+                n: chain.n,
+                key: chain_step(&mut chain.key),
+            });
+            chain.n += 1;
+        }
+        if self.skipped.len() > MAX_SKIPPED_KEYS {
+            let excess = self.skipped.len() - MAX_SKIPPED_KEYS;
+            self.skipped.drain(..excess);
+        }
+
+        chain.n += 1;
+        Ok(chain_step(&mut chain.key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: [u8; 32] = [7; 32];
+
+    /// Two ratchets that have seen each other's first key
+    fn pair() -> (Ratchet, Ratchet) {
+        let mut alice = Ratchet::default();
+        let mut bob = Ratchet::default();
+        alice.learn(&bob.advertised_key());
+        bob.learn(&alice.advertised_key());
+        (alice, bob)
+    }
+
+    fn send(from: &mut Ratchet, to: &mut Ratchet, text: &str) -> RatchetHeader {
+        let (header, sealed) = from.seal(&SEED, text.as_bytes()).unwrap();
+        assert_eq!(to.open(&SEED, &header, &sealed).unwrap(), text.as_bytes());
+        header
+    }
+
+    #[test]
+    fn test_round_trips_start_new_chains() {
+        let (mut alice, mut bob) = pair();
+        assert!(!Ratchet::default().can_seal());
+
+        let first = send(&mut alice, &mut bob, "hi");
+        let second = send(&mut alice, &mut bob, "still me");
+        assert_eq!(first.ephemeral, second.ephemeral);
+        assert_eq!(second.n, 1);
+
+        // Bob's reply carries a fresh key, so Alice's next message starts a
+        // new chain with it
+        let reply = send(&mut bob, &mut alice, "hello");
+        assert_ne!(reply.next, first.to);
+        let third = send(&mut alice, &mut bob, "new chain");
+        assert_ne!(third.ephemeral, first.ephemeral);
+        assert_eq!(third.to, reply.next);
+        assert_eq!(third.n, 0);
+    }
+
+    #[test]
+    fn test_hybrid_secret_salts_new_chains() {
+        let (mut alice, mut bob) = pair();
+        let classic = send(&mut alice, &mut bob, "before");
+        assert!(classic.hybrid.is_none());
+
+        let secret = HybridSecret {
+            id: "offer".to_string(),
+            secret: Zeroizing::new([9; 32]),
+        };
+        // Alice made the offer; Bob answered it
+        alice.set_hybrid(secret.clone(), true);
+        bob.set_hybrid(secret, false);
+        assert!(bob.hybrid_id().is_none());
+
+        let hybrid = send(&mut alice, &mut bob, "after");
+        assert_eq!(hybrid.hybrid.as_deref(), Some("offer"));
+        assert_ne!(hybrid.ephemeral, classic.ephemeral);
+        assert_eq!(bob.hybrid_id(), Some("offer"));
+        let reply = send(&mut bob, &mut alice, "me too");
+        assert_eq!(reply.hybrid.as_deref(), Some("offer"));
+
+        // Without the secret, a chain naming it doesn't open
+        let (header, sealed) = alice.seal(&SEED, b"secret").unwrap();
+        let mut outsider = Ratchet {
+            our_keys: bob.our_keys.clone(),
+            ..Default::default()
+        };
+        assert_eq!(
+            outsider.open(&SEED, &header, &sealed),
+            Err(RatchetError::UnknownKey)
+        );
+    }
+
+    #[test]
+    fn test_out_of_order_and_replayed() {
+        let (mut alice, mut bob) = pair();
+        let one = alice.seal(&SEED, b"one").unwrap();
+        let two = alice.seal(&SEED, b"two").unwrap();
+
+        assert_eq!(bob.open(&SEED, &two.0, &two.1).unwrap(), b"two");
+        assert_eq!(bob.open(&SEED, &one.0, &one.1).unwrap(), b"one");
+        // Each key opens one message
+        assert_eq!(bob.open(&SEED, &one.0, &one.1), Err(RatchetError::Replayed));
+
+        let mut far = one.0.clone();
+        far.n = MAX_SKIPPED_KEYS as u32 + 5;
+        assert_eq!(
+            bob.open(&SEED, &far, &one.1),
+            Err(RatchetError::TooFarAhead)
+        );
+    }
+
+    #[test]
+    fn test_forged_and_stale_frames() {
+        let (mut alice, mut bob) = pair();
+        let (header, mut sealed) = alice.seal(&SEED, b"hi").unwrap();
+
+        // A different relationship secret or a flipped bit doesn't open,
+        // and doesn't use up the key
+        assert_eq!(
+            bob.open(&[8; 32], &header, &sealed),
+            Err(RatchetError::Decrypt)
+        );
+        sealed[0] ^= 1;
+        assert_eq!(
+            bob.open(&SEED, &header, &sealed),
+            Err(RatchetError::Decrypt)
+        );
+        sealed[0] ^= 1;
+        bob.open(&SEED, &header, &sealed).unwrap();
+
+        // Once enough round trips pass, the secret of an old key is gone
+        for _ in 0..=MAX_OUR_KEYS {
+            send(&mut bob, &mut alice, "ping");
+            send(&mut alice, &mut bob, "pong");
+        }
+        let mut stale = header.clone();
+        stale.ephemeral = Keys::generate().public_key().to_hex();
+        assert_eq!(
+            bob.open(&SEED, &stale, &sealed),
+            Err(RatchetError::UnknownKey)
+        );
+    }
+}
@@ -0,0 +1,411 @@
+//! Social recovery through trusted contacts
+//!
+//! The owner splits their key with `backup::split_key` and sends one share to
+//! each chosen guardian as a `recovery-share` chat message, NIP-44 encrypted
+//! to that guardian so it can't be read from chat history. Guardians keep the
+//! shares they hold in a `GuardianStore`.
+//!
+//! To recover, the new device starts with a temporary identity, exchanges
+//! with its guardians in person as usual, and sends each a `recovery-request`
+//! naming the lost key. A guardian releases their share only after their user
+//! approves (having seen the requester face to face), re-encrypted to the
+//! temporary key. Once the `RecoverySession` holds enough shares of one set
+//! the lost identity is reassembled and replaces the temporary one.
+
+use crate::backup::{recover_key, BackupError, KeyShare};
+use crate::keys::StoredKeys;
+use nostr::nips::nip44;
+use nostr::{Keys, PublicKey};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum RecoveryError {
+    #[error("Encryption error: {0}")]
+    EncryptionError(String),
+    #[error("Share doesn't match its envelope")]
+    EnvelopeMismatch,
+    #[error("Share is for a different identity")]
+    WrongOwner,
+    #[error("No share held for this identity")]
+    NoShareHeld,
+    #[error("No such recovery request")]
+    NoSuchRequest,
+    #[error(transparent)]
+    Backup(#[from] BackupError),
+}
+
+fn encryption_error(e: impl ToString) -> RecoveryError {
+    RecoveryError::EncryptionError(e.to_string())
+}
+
+/// Payload of a `recovery-share` message: one key share, encrypted from the
+/// sender to the recipient
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareEnvelope {
+    /// Identity the share belongs to
+    pub owner_pubkey: String,
+    pub set_id: String,
+    pub index: u8,
+    /// NIP-44 ciphertext of the `KeyShare` JSON
+    pub encrypted_share: String,
+}
+
+impl ShareEnvelope {
+    /// Encrypt `share` from `keys` to `recipient_pubkey`
+    pub fn seal(
+        keys: &Keys,
+        recipient_pubkey: &str,
+        share: &KeyShare,
+    ) -> Result<Self, RecoveryError> {
+        let recipient = PublicKey::from_hex(recipient_pubkey).map_err(encryption_error)?;
+        let json = share.to_json()?;
+        let encrypted_share =
+            nip44::encrypt(keys.secret_key(), &recipient, json, nip44::Version::V2)
+                .map_err(encryption_error)?;
+
+        Ok(Self {
+            owner_pubkey: share.pubkey.clone(),
+            set_id: share.set_id.clone(),
+            index: share.index,
+            encrypted_share,
+        })
+    }
+
+    /// Decrypt a share `sender_pubkey` sent to `keys`
+    pub fn open(&self, keys: &Keys, sender_pubkey: &str) -> Result<KeyShare, RecoveryError> {
+        let sender = PublicKey::from_hex(sender_pubkey).map_err(encryption_error)?;
+        let json = nip44::decrypt(keys.secret_key(), &sender, &self.encrypted_share)
+            .map_err(encryption_error)?;
+        let share = KeyShare::from_json(&json)?;
+
+        if share.pubkey != self.owner_pubkey
+            || share.set_id != self.set_id
+            || share.index != self.index
+        {
+            return Err(RecoveryError::EnvelopeMismatch);
+        }
+        Ok(share)
+    }
+}
+
+/// Payload of a `recovery-request` message
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryRequest {
+    /// Identity being recovered
+    pub owner_pubkey: String,
+}
+
+/// Who the owner gave shares to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GuardianSet {
+    pub set_id: String,
+    pub threshold: u8,
+    pub guardians: Vec<String>,
+    pub created_at: u64,
+}
+
+/// A share we hold for a contact, still encrypted to us
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct HeldShare {
+    pub envelope: ShareEnvelope,
+    pub received_at: u64,
+}
+
+/// A request for a share we hold, waiting for the user's approval
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingRecoveryRequest {
+    pub owner_pubkey: String,
+    /// Temporary identity of the recovering device
+    pub requester_pubkey: String,
+    pub received_at: u64,
+}
+
+/// Guardian side: shares held for others and requests for them
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GuardianStore {
+    held: Vec<HeldShare>,
+    requests: Vec<PendingRecoveryRequest>,
+}
+
+impl GuardianStore {
+    /// Keep a share from its owner, replacing one from an earlier split
+    pub fn hold(&mut self, envelope: ShareEnvelope, now: u64) {
+        self.held
+            .retain(|held| held.envelope.owner_pubkey != envelope.owner_pubkey);
+        self.held.push(HeldShare {
+            envelope,
+            received_at: now,
+        });
+    }
+
+    pub fn held(&self) -> &[HeldShare] {
+        &self.held
+    }
+
+    pub fn held_for(&self, owner_pubkey: &str) -> Option<&HeldShare> {
+        self.held
+            .iter()
+            .find(|held| held.envelope.owner_pubkey == owner_pubkey)
+    }
+
+    /// Queue a request for approval. Requests for identities we hold nothing
+    /// for are dropped, as are repeats.
+    pub fn add_request(&mut self, owner_pubkey: &str, requester_pubkey: &str, now: u64) -> bool {
+        if self.held_for(owner_pubkey).is_none()
+            || self.find_request(owner_pubkey, requester_pubkey).is_some()
+        {
+            return false;
+        }
+        self.requests.push(PendingRecoveryRequest {
+            owner_pubkey: owner_pubkey.to_string(),
+            requester_pubkey: requester_pubkey.to_string(),
+            received_at: now,
+        });
+        true
+    }
+
+    pub fn requests(&self) -> &[PendingRecoveryRequest] {
+        &self.requests
+    }
+
+    fn find_request(&self, owner_pubkey: &str, requester_pubkey: &str) -> Option<usize> {
+        self.requests
+            .iter()
+            .position(|r| r.owner_pubkey == owner_pubkey && r.requester_pubkey == requester_pubkey)
+    }
+
+    /// Remove a request once approved or declined
+    pub fn take_request(
+        &mut self,
+        owner_pubkey: &str,
+        requester_pubkey: &str,
+    ) -> Result<PendingRecoveryRequest, RecoveryError> {
+        self.find_request(owner_pubkey, requester_pubkey)
+            .map(|i| self.requests.remove(i))
+            .ok_or(RecoveryError::NoSuchRequest)
+    }
+
+    /// Re-encrypt the share held for `owner_pubkey` to `requester_pubkey`
+    pub fn release(
+        &self,
+        keys: &Keys,
+        owner_pubkey: &str,
+        requester_pubkey: &str,
+    ) -> Result<ShareEnvelope, RecoveryError> {
+        let held = self
+            .held_for(owner_pubkey)
+            .ok_or(RecoveryError::NoShareHeld)?;
+        let share = held.envelope.open(keys, owner_pubkey)?;
+        ShareEnvelope::seal(keys, requester_pubkey, &share)
+    }
+}
+
+/// Where a recovery stands
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum RecoveryPhase {
+    /// Requests sent, no shares back yet
+    Requested,
+    /// Some shares back, not enough of one set
+    Collecting,
+    /// Enough shares to reassemble the identity
+    Ready,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReceivedShare {
+    from_pubkey: String,
+    share: KeyShare,
+}
+
+/// Requester side: an in-progress recovery of one identity
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoverySession {
+    owner_pubkey: String,
+    requested_from: Vec<String>,
+    received: Vec<ReceivedShare>,
+    started_at: u64,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryStatus {
+    pub owner_pubkey: String,
+    pub phase: RecoveryPhase,
+    pub requested_from: Vec<String>,
+    /// Guardians whose share arrived
+    pub received_from: Vec<String>,
+    /// Shares needed, once the first has arrived
+    pub threshold: Option<u8>,
+    pub started_at: u64,
+}
+
+impl RecoverySession {
+    pub fn new(owner_pubkey: &str, requested_from: Vec<String>, now: u64) -> Self {
+        Self {
+            owner_pubkey: owner_pubkey.to_string(),
+            requested_from,
+            received: Vec::new(),
+            started_at: now,
+        }
+    }
+
+    pub fn owner_pubkey(&self) -> &str {
+        &self.owner_pubkey
+    }
+
+    /// Record more guardians asked
+    pub fn add_requested(&mut self, pubkeys: &[String]) {
+        for pubkey in pubkeys {
+            if !self.expects(pubkey) {
+                self.requested_from.push(pubkey.clone());
+            }
+        }
+    }
+
+    /// Whether we asked `pubkey` for a share
+    pub fn expects(&self, pubkey: &str) -> bool {
+        self.requested_from.iter().any(|p| p == pubkey)
+    }
+
+    /// Add a share returned by a guardian. A repeat of a share already held
+    /// replaces it.
+    pub fn add_share(&mut self, from_pubkey: &str, share: KeyShare) -> Result<(), RecoveryError> {
+        if share.pubkey != self.owner_pubkey {
+            return Err(RecoveryError::WrongOwner);
+        }
+        self.received.retain(|r| {
+            r.from_pubkey != from_pubkey
+                && !(r.share.set_id == share.set_id && r.share.index == share.index)
+        });
+        self.received.push(ReceivedShare {
+            from_pubkey: from_pubkey.to_string(),
+            share,
+        });
+        Ok(())
+    }
+
+    /// Shares of the set closest to its threshold, and that threshold
+    fn best_set(&self) -> Option<(Vec<KeyShare>, u8)> {
+        self.received
+            .iter()
+            .map(|r| {
+                let shares: Vec<KeyShare> = self
+                    .received
+                    .iter()
+                    .filter(|other| other.share.set_id == r.share.set_id)
+                    .map(|other| other.share.clone())
+                    .collect();
+                (shares, r.share.threshold)
+            })
+            .max_by_key(|(shares, threshold)| shares.len() as i32 - *threshold as i32)
+    }
+
+    pub fn phase(&self) -> RecoveryPhase {
+        match self.best_set() {
+            None => RecoveryPhase::Requested,
+            Some((shares, threshold)) if shares.len() >= threshold as usize => RecoveryPhase::Ready,
+            Some(_) => RecoveryPhase::Collecting,
+        }
+    }
+
+    pub fn status(&self) -> RecoveryStatus {
+        RecoveryStatus {
+            owner_pubkey: self.owner_pubkey.clone(),
+            phase: self.phase(),
+            requested_from: self.requested_from.clone(),
+            received_from: self
+                .received
+                .iter()
+                .map(|r| r.from_pubkey.clone())
+                .collect(),
+            threshold: self.best_set().map(|(_, threshold)| threshold),
+            started_at: self.started_at,
+        }
+    }
+
+    /// Reassemble the identity from the shares received
+    pub fn recover(&self) -> Result<StoredKeys, RecoveryError> {
+        let (shares, _) = self
+            .best_set()
+            .ok_or(BackupError::NotEnoughShares { needed: 2, got: 0 })?;
+        Ok(recover_key(&shares)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::split_key;
+    use crate::keys::generate_keypair;
+
+    #[test]
+    fn test_share_round_trip_through_guardian() {
+        let (owner, owner_stored) = generate_keypair().unwrap();
+        let (guardian, _) = generate_keypair().unwrap();
+        let (temporary, _) = generate_keypair().unwrap();
+        let owner_pk = owner.public_key().to_hex();
+        let guardian_pk = guardian.public_key().to_hex();
+        let temporary_pk = temporary.public_key().to_hex();
+
+        let shares = split_key(&owner_stored, 3, 2).unwrap();
+        let envelope = ShareEnvelope::seal(&owner, &guardian_pk, &shares[0]).unwrap();
+
+        // Only the guardian can open what the owner sent
+        assert!(envelope.open(&temporary, &owner_pk).is_err());
+
+        let mut store = GuardianStore::default();
+        store.hold(envelope, 100);
+        assert!(!store.add_request(&temporary_pk, &temporary_pk, 200));
+        assert!(store.add_request(&owner_pk, &temporary_pk, 200));
+        assert!(!store.add_request(&owner_pk, &temporary_pk, 201));
+
+        let request = store.take_request(&owner_pk, &temporary_pk).unwrap();
+        let released = store
+            .release(&guardian, &request.owner_pubkey, &request.requester_pubkey)
+            .unwrap();
+        assert_eq!(released.open(&temporary, &guardian_pk).unwrap(), shares[0]);
+        assert!(store.requests().is_empty());
+    }
+
+    #[test]
+    fn test_session_becomes_ready() {
+        let (owner, owner_stored) = generate_keypair().unwrap();
+        let owner_pk = owner.public_key().to_hex();
+        let shares = split_key(&owner_stored, 3, 2).unwrap();
+
+        let mut session = RecoverySession::new(&owner_pk, vec!["a".into(), "b".into()], 0);
+        assert_eq!(session.phase(), RecoveryPhase::Requested);
+        assert!(session.recover().is_err());
+
+        session.add_share("a", shares[2].clone()).unwrap();
+        // The same guardian sending twice doesn't count twice
+        session.add_share("a", shares[2].clone()).unwrap();
+        assert_eq!(session.phase(), RecoveryPhase::Collecting);
+        assert_eq!(session.status().threshold, Some(2));
+
+        let (other, other_stored) = generate_keypair().unwrap();
+        let stranger = split_key(&other_stored, 2, 2).unwrap();
+        assert!(matches!(
+            session.add_share("b", stranger[0].clone()),
+            Err(RecoveryError::WrongOwner)
+        ));
+        assert_ne!(other.public_key().to_hex(), owner_pk);
+
+        session.add_share("b", shares[0].clone()).unwrap();
+        assert_eq!(session.phase(), RecoveryPhase::Ready);
+        assert_eq!(
+            session.recover().unwrap().secret_key_hex,
+            owner_stored.secret_key_hex
+        );
+    }
+}
@@ -0,0 +1,271 @@
+//! Minimal Nostr relay client
+//!
+//! Just enough of NIP-01 to publish an event and fetch the stored events
+//! matching a filter, plus `request` for protocols that publish an event and
+//! wait for an answer to it (NIP-46). Each call opens its own WebSocket
+//! connection and closes it when done: remote exchanges check in now and
+//! then rather than keeping a subscription open, so there is nothing to gain
+//! from a connection pool.
+
+use crate::keys::SignedEvent;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::time::Duration;
+use thiserror::Error;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+/// How long to wait for a relay to accept the connection
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// How long to wait for a relay to acknowledge an event or finish sending
+/// stored events
+const RESPONSE_TIMEOUT_SECS: u64 = 15;
+
+#[derive(Error, Debug)]
+pub enum RelayError {
+    #[error("Relay connection failed: {0}")]
+    Connection(String),
+    #[error("Relay didn't answer in time")]
+    Timeout,
+    #[error("Relay rejected the event: {0}")]
+    Rejected(String),
+    #[error("Relay closed the subscription: {0}")]
+    Closed(String),
+}
+
+fn connection_error(e: impl ToString) -> RelayError {
+    RelayError::Connection(e.to_string())
+}
+
+/// A message from a relay, as far as we care about it
+#[derive(Debug, PartialEq, Eq)]
+pub enum RelayMessage {
+    /// A stored or live event for subscription `.0`
+    Event(String, SignedEvent),
+    /// All stored events for the subscription have been sent
+    EndOfStored(String),
+    /// Whether event `id` was accepted, and why not
+    Ok {
+        id: String,
+        accepted: bool,
+        reason: String,
+    },
+    /// The relay ended subscription `.0`
+    Closed(String, String),
+    /// A notice, or something this client doesn't use
+    Other,
+}
+
+impl RelayMessage {
+    /// Parse a relay frame. Events whose signature doesn't check out are
+    /// treated as noise.
+    pub fn parse(text: &str) -> Self {
+        let Ok(Value::Array(frame)) = serde_json::from_str::<Value>(text) else {
+            return Self::Other;
+        };
+        let string = |index: usize| {
+            frame
+                .get(index)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        match frame.first().and_then(Value::as_str) {
+            Some("EVENT") => frame
+                .get(2)
+                .and_then(|event| serde_json::from_value::<SignedEvent>(event.clone()).ok())
+                .filter(|event| event.verify().is_ok())
+                .map_or(Self::Other, |event| Self::Event(string(1), event)),
+            Some("EOSE") => Self::EndOfStored(string(1)),
+            Some("OK") => Self::Ok {
+                id: string(1),
+                accepted: frame.get(2) == Some(&Value::Bool(true)),
+                reason: string(3),
+            },
+            Some("CLOSED") => Self::Closed(string(1), string(2)),
+            _ => Self::Other,
+        }
+    }
+}
+
+type RelaySocket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn connect(relay: &str) -> Result<RelaySocket, RelayError> {
+    let (ws, _) = tokio::time::timeout(
+        Duration::from_secs(CONNECT_TIMEOUT_SECS),
+        tokio_tungstenite::connect_async(relay),
+    )
+    .await
+    .map_err(|_| connection_error("connection timed out"))?
+    .map_err(connection_error)?;
+    Ok(ws)
+}
+
+async fn send(ws: &mut RelaySocket, frame: Value) -> Result<(), RelayError> {
+    ws.send(WsMessage::Text(frame.to_string()))
+        .await
+        .map_err(connection_error)
+}
+
+/// Read relay messages until `handle` has an outcome, or fail with
+/// `Timeout` after `timeout`
+async fn read_until<T>(
+    ws: &mut RelaySocket,
+    timeout: Duration,
+    mut handle: impl FnMut(RelayMessage) -> Option<Result<T, RelayError>>,
+) -> Result<T, RelayError> {
+    tokio::time::timeout(timeout, async {
+        while let Some(message) = ws.next().await {
+            if let WsMessage::Text(text) = message.map_err(connection_error)? {
+                if let Some(outcome) = handle(RelayMessage::parse(&text)) {
+                    return outcome;
+                }
+            }
+        }
+        Err(connection_error("relay closed the connection"))
+    })
+    .await
+    .map_err(|_| RelayError::Timeout)?
+}
+
+async fn unsubscribe(mut ws: RelaySocket, subscription: &str) {
+    let _ = send(&mut ws, json!(["CLOSE", subscription])).await;
+    let _ = ws.close(None).await;
+}
+
+/// Publish `event` to `relay` and wait for it to be accepted
+pub async fn publish(relay: &str, event: &SignedEvent) -> Result<(), RelayError> {
+    let mut ws = connect(relay).await?;
+    send(&mut ws, json!(["EVENT", event])).await?;
+
+    let result = read_until(
+        &mut ws,
+        Duration::from_secs(RESPONSE_TIMEOUT_SECS),
+        |message| match message {
+            RelayMessage::Ok {
+                id, accepted: true, ..
+            } if id == event.id => Some(Ok(())),
+            RelayMessage::Ok { id, reason, .. } if id == event.id => {
+                Some(Err(RelayError::Rejected(reason)))
+            }
+            _ => None,
+        },
+    )
+    .await;
+
+    let _ = ws.close(None).await;
+    result
+}
+
+/// Events stored on `relay` matching `filter` (a NIP-01 filter object)
+pub async fn fetch(relay: &str, filter: Value) -> Result<Vec<SignedEvent>, RelayError> {
+    let mut ws = connect(relay).await?;
+    let subscription = uuid::Uuid::new_v4().to_string();
+    send(&mut ws, json!(["REQ", subscription, filter])).await?;
+
+    let mut events = Vec::new();
+    let result = read_until(
+        &mut ws,
+        Duration::from_secs(RESPONSE_TIMEOUT_SECS),
+        |message| match message {
+            RelayMessage::Event(id, event) if id == subscription => {
+                events.push(event);
+                None
+            }
+            RelayMessage::EndOfStored(id) if id == subscription => Some(Ok(())),
+            RelayMessage::Closed(id, reason) if id == subscription => {
+                Some(Err(RelayError::Closed(reason)))
+            }
+            _ => None,
+        },
+    )
+    .await;
+
+    unsubscribe(ws, &subscription).await;
+    result.map(|()| events)
+}
+
+/// Publish `event` to `relay` and wait up to `timeout` for an answer to it
+/// among the events matching `filter`, stored or live. `answer` picks it
+/// out, returning `None` for anything else. The subscription is opened
+/// first, so an answer can't slip past in between.
+pub async fn request<T>(
+    relay: &str,
+    filter: Value,
+    event: &SignedEvent,
+    timeout: Duration,
+    mut answer: impl FnMut(&SignedEvent) -> Option<T>,
+) -> Result<T, RelayError> {
+    let mut ws = connect(relay).await?;
+    let subscription = uuid::Uuid::new_v4().to_string();
+    send(&mut ws, json!(["REQ", subscription, filter])).await?;
+    send(&mut ws, json!(["EVENT", event])).await?;
+
+    let result = read_until(&mut ws, timeout, |message| match message {
+        RelayMessage::Event(id, reply) if id == subscription => answer(&reply).map(Ok),
+        RelayMessage::Closed(id, reason) if id == subscription => {
+            Some(Err(RelayError::Closed(reason)))
+        }
+        // Our event being accepted means waiting on; only a refusal ends it
+        RelayMessage::Ok {
+            id,
+            accepted: false,
+            reason,
+        } if id == event.id => Some(Err(RelayError::Rejected(reason))),
+        _ => None,
+    })
+    .await;
+
+    unsubscribe(ws, &subscription).await;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{sign_event, EventTemplate};
+    use nostr::Keys;
+
+    #[test]
+    fn test_parse_relay_messages() {
+        let event = sign_event(
+            &Keys::generate(),
+            &EventTemplate {
+                created_at: 1,
+                kind: 1,
+                tags: Vec::new(),
+                content: "hello".to_string(),
+            },
+        );
+        let frame = json!(["EVENT", "sub", event]).to_string();
+        assert_eq!(
+            RelayMessage::parse(&frame),
+            RelayMessage::Event("sub".to_string(), event.clone())
+        );
+
+        // A forged event is dropped
+        let mut forged = event.clone();
+        forged.content = "changed".to_string();
+        let frame = json!(["EVENT", "sub", forged]).to_string();
+        assert_eq!(RelayMessage::parse(&frame), RelayMessage::Other);
+
+        assert_eq!(
+            RelayMessage::parse(r#"["EOSE","sub"]"#),
+            RelayMessage::EndOfStored("sub".to_string())
+        );
+        assert_eq!(
+            RelayMessage::parse(r#"["OK","abc",false,"blocked: spam"]"#),
+            RelayMessage::Ok {
+                id: "abc".to_string(),
+                accepted: false,
+                reason: "blocked: spam".to_string(),
+            }
+        );
+        assert_eq!(
+            RelayMessage::parse(r#"["NOTICE","hi"]"#),
+            RelayMessage::Other
+        );
+    }
+}
@@ -0,0 +1,351 @@
+//! Remote exchange over Nostr DMs
+//!
+//! For two people who can't meet, the same signed `ExchangeMessage`s travel
+//! through Nostr relays instead of NFC or a screen. Each message is NIP-44
+//! encrypted to the other person and sent as a regular (stored) event, so it
+//! waits on the relay until they check in. Both messages name their
+//! recipient, and the event must be signed by the key the exchange message is
+//! from, so a relay can only drop messages, not swap them.
+//!
+//! Nobody checked anything in person, so the contact is saved as remote and
+//! unverified; a later physical exchange upgrades it like any other
+//! unverified contact. Because the DM and the exchange message are signed by
+//! the same key, remote exchanges need local keys, not a device subkey or a
+//! remote signer.
+
+use crate::exchange::{ExchangeMessage, TimestampTolerance};
+use crate::keys::{sign_event, EventTemplate, SignedEvent};
+use nostr::nips::nip44;
+use nostr::{Keys, PublicKey};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Event kind of an encrypted remote exchange message. A regular kind, so
+/// relays keep it until the recipient fetches it, and not kind 4, so chat
+/// clients don't show it as an undecryptable DM.
+pub const REMOTE_EXCHANGE_KIND: u16 = 4_733;
+
+/// The other person may take days to check in
+pub const REMOTE_TOLERANCE: TimestampTolerance = TimestampTolerance {
+    max_age_secs: REMOTE_EXCHANGE_TTL_SECS,
+    max_future_secs: 120,
+};
+
+/// How long an unanswered remote exchange (or request) is kept
+pub const REMOTE_EXCHANGE_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Overlap when fetching since the last check, for relay clock skew
+const FETCH_OVERLAP_SECS: u64 = 600;
+
+#[derive(Error, Debug)]
+pub enum RemoteError {
+    #[error("Invalid pubkey: {0}")]
+    InvalidPubkey(String),
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+    #[error("Not a remote exchange message for us")]
+    NotForUs,
+    #[error("Invalid exchange message: {0}")]
+    InvalidMessage(String),
+    #[error("Not a relay URL: {0}")]
+    InvalidRelay(String),
+    #[error("No relays to exchange through")]
+    NoRelays,
+}
+
+/// Trim, check and dedupe relay URLs typed by the user
+pub fn parse_relays(relays: &[String]) -> Result<Vec<String>, RemoteError> {
+    let mut parsed: Vec<String> = Vec::new();
+    for relay in relays.iter().map(|r| r.trim()).filter(|r| !r.is_empty()) {
+        if !relay.starts_with("wss://") && !relay.starts_with("ws://") {
+            return Err(RemoteError::InvalidRelay(relay.to_string()));
+        }
+        let relay = relay.trim_end_matches('/').to_string();
+        if !parsed.contains(&relay) {
+            parsed.push(relay);
+        }
+    }
+    if parsed.is_empty() {
+        return Err(RemoteError::NoRelays);
+    }
+    Ok(parsed)
+}
+
+/// Encrypt `payload` (an encoded exchange message) to `their_pubkey` as an
+/// event signed by `keys`
+pub fn seal(
+    keys: &Keys,
+    their_pubkey: &str,
+    payload: &str,
+    now: u64,
+) -> Result<SignedEvent, RemoteError> {
+    let recipient =
+        PublicKey::from_hex(their_pubkey).map_err(|e| RemoteError::InvalidPubkey(e.to_string()))?;
+    let content = nip44::encrypt(keys.secret_key(), &recipient, payload, nip44::Version::V2)
+        .map_err(|e| RemoteError::Encryption(e.to_string()))?;
+
+    Ok(sign_event(
+        keys,
+        &EventTemplate {
+            created_at: now,
+            kind: REMOTE_EXCHANGE_KIND,
+            tags: vec![vec!["p".to_string(), their_pubkey.to_string()]],
+            content,
+        },
+    ))
+}
+
+/// Decrypt and verify a remote exchange event addressed to `keys`. The
+/// exchange message must name us and come from the event's author.
+pub fn open(keys: &Keys, event: &SignedEvent) -> Result<ExchangeMessage, RemoteError> {
+    let our_pubkey = keys.public_key().to_hex();
+    let addressed_to_us = event
+        .tags
+        .iter()
+        .any(|tag| tag.first().map(String::as_str) == Some("p") && tag.get(1) == Some(&our_pubkey));
+    if event.kind != REMOTE_EXCHANGE_KIND || !addressed_to_us || event.verify().is_err() {
+        return Err(RemoteError::NotForUs);
+    }
+
+    let sender = PublicKey::from_hex(&event.pubkey)
+        .map_err(|e| RemoteError::InvalidPubkey(e.to_string()))?;
+    let payload = nip44::decrypt(keys.secret_key(), &sender, &event.content)
+        .map_err(|e| RemoteError::Encryption(e.to_string()))?;
+    let msg = ExchangeMessage::decode(payload.as_bytes())
+        .map_err(|e| RemoteError::InvalidMessage(e.to_string()))?;
+
+    if msg.pubkey != event.pubkey {
+        return Err(RemoteError::InvalidMessage(
+            "signed by someone other than the sender".to_string(),
+        ));
+    }
+    msg.verify_response(&our_pubkey, REMOTE_TOLERANCE)
+        .map_err(|e| RemoteError::InvalidMessage(e.to_string()))?;
+
+    Ok(msg)
+}
+
+/// A remote exchange we started, waiting for their answer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteExchange {
+    pub their_pubkey: String,
+    /// Relays our message went to, and where their answer is expected
+    pub relays: Vec<String>,
+    pub started_at: u64,
+}
+
+/// Someone's exchange message waiting for us to accept or decline it
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteRequest {
+    pub their_pubkey: String,
+    /// Relays it was found on, where our answer goes
+    pub relays: Vec<String>,
+    pub received_at: u64,
+    /// Their message, already verified
+    pub message: ExchangeMessage,
+}
+
+/// A waiting request as shown to the user
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteRequestInfo {
+    pub their_pubkey: String,
+    /// The name in their signed profile
+    pub display_name: Option<String>,
+    pub received_at: u64,
+}
+
+impl From<&RemoteRequest> for RemoteRequestInfo {
+    fn from(request: &RemoteRequest) -> Self {
+        Self {
+            their_pubkey: request.their_pubkey.clone(),
+            display_name: request
+                .message
+                .profile
+                .as_ref()
+                .and_then(|p| p.display_name.clone()),
+            received_at: request.received_at,
+        }
+    }
+}
+
+/// Remote exchanges in both directions, persisted across restarts
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteExchangeStore {
+    pub pending: Vec<RemoteExchange>,
+    pub requests: Vec<RemoteRequest>,
+    /// Relays checked for requests from people we haven't asked
+    #[serde(default)]
+    pub relays: Vec<String>,
+    /// When relays were last checked
+    #[serde(default)]
+    pub last_checked: u64,
+}
+
+impl RemoteExchangeStore {
+    /// Start waiting on `their_pubkey`, replacing an earlier attempt
+    pub fn add_pending(&mut self, exchange: RemoteExchange) {
+        self.pending
+            .retain(|p| p.their_pubkey != exchange.their_pubkey);
+        self.pending.push(exchange);
+    }
+
+    /// Every relay an answer or request may turn up on
+    pub fn relays_to_check(&self) -> Vec<String> {
+        let mut relays = self.relays.clone();
+        for relay in self.pending.iter().flat_map(|p| &p.relays) {
+            if !relays.contains(relay) {
+                relays.push(relay.clone());
+            }
+        }
+        relays
+    }
+
+    pub fn take_pending(&mut self, their_pubkey: &str) -> Option<RemoteExchange> {
+        let index = self
+            .pending
+            .iter()
+            .position(|p| p.their_pubkey == their_pubkey)?;
+        Some(self.pending.remove(index))
+    }
+
+    /// Queue a request, keeping only the newest from each person
+    pub fn add_request(&mut self, request: RemoteRequest) {
+        if let Some(existing) = self
+            .requests
+            .iter_mut()
+            .find(|r| r.their_pubkey == request.their_pubkey)
+        {
+            if existing.message.timestamp < request.message.timestamp {
+                *existing = request;
+            }
+            return;
+        }
+        self.requests.push(request);
+    }
+
+    pub fn take_request(&mut self, their_pubkey: &str) -> Option<RemoteRequest> {
+        let index = self
+            .requests
+            .iter()
+            .position(|r| r.their_pubkey == their_pubkey)?;
+        Some(self.requests.remove(index))
+    }
+
+    /// Drop anything older than `REMOTE_EXCHANGE_TTL_SECS`
+    pub fn expire(&mut self, now: u64) {
+        let cutoff = now.saturating_sub(REMOTE_EXCHANGE_TTL_SECS);
+        self.pending.retain(|p| p.started_at >= cutoff);
+        self.requests.retain(|r| r.received_at >= cutoff);
+    }
+
+    /// Where to fetch from: anything newer than the last check, or the whole
+    /// window on the first one
+    pub fn fetch_since(&self, now: u64) -> u64 {
+        let oldest = now.saturating_sub(REMOTE_EXCHANGE_TTL_SECS);
+        self.last_checked
+            .saturating_sub(FETCH_OVERLAP_SECS)
+            .max(oldest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let (alice, bob) = (Keys::generate(), Keys::generate());
+        let bob_pk = bob.public_key().to_hex();
+        let msg = ExchangeMessage::new_response(&alice, &bob_pk).unwrap();
+        let event = seal(&alice, &bob_pk, &msg.to_compact().unwrap(), now()).unwrap();
+
+        let opened = open(&bob, &event).unwrap();
+        assert_eq!(opened.pubkey, alice.public_key().to_hex());
+
+        // Nobody else can read it, and it isn't addressed to them
+        assert!(matches!(
+            open(&Keys::generate(), &event),
+            Err(RemoteError::NotForUs)
+        ));
+    }
+
+    #[test]
+    fn test_message_must_match_sender() {
+        let (alice, bob, mallory) = (Keys::generate(), Keys::generate(), Keys::generate());
+        let bob_pk = bob.public_key().to_hex();
+
+        // Mallory forwards Alice's message under her own event
+        let msg = ExchangeMessage::new_response(&alice, &bob_pk).unwrap();
+        let event = seal(&mallory, &bob_pk, &msg.to_compact().unwrap(), now()).unwrap();
+        assert!(matches!(
+            open(&bob, &event),
+            Err(RemoteError::InvalidMessage(_))
+        ));
+
+        // A message that doesn't name Bob isn't an answer to him
+        let initial = ExchangeMessage::new_initial(&alice).unwrap();
+        let event = seal(&alice, &bob_pk, &initial.to_compact().unwrap(), now()).unwrap();
+        assert!(open(&bob, &event).is_err());
+    }
+
+    #[test]
+    fn test_store_expiry_and_requests() {
+        let (alice, bob) = (Keys::generate(), Keys::generate());
+        let bob_pk = bob.public_key().to_hex();
+        let mut store = RemoteExchangeStore::default();
+        let now = now();
+
+        store.add_pending(RemoteExchange {
+            their_pubkey: "old".to_string(),
+            relays: Vec::new(),
+            started_at: now - REMOTE_EXCHANGE_TTL_SECS - 1,
+        });
+        let request = |timestamp: u64| {
+            let mut message = ExchangeMessage::new_response(&alice, &bob_pk).unwrap();
+            message.timestamp = timestamp;
+            RemoteRequest {
+                their_pubkey: message.pubkey.clone(),
+                relays: Vec::new(),
+                received_at: now,
+                message,
+            }
+        };
+        store.add_request(request(now - 10));
+        store.add_request(request(now));
+        assert_eq!(store.requests.len(), 1);
+        assert_eq!(store.requests[0].message.timestamp, now);
+
+        store.expire(now);
+        assert!(store.pending.is_empty());
+        assert!(store.take_request(&alice.public_key().to_hex()).is_some());
+        assert_eq!(store.fetch_since(now), now - REMOTE_EXCHANGE_TTL_SECS);
+    }
+
+    #[test]
+    fn test_parse_relays() {
+        let relays = parse_relays(&[
+            " wss://relay.example.com/ ".to_string(),
+            "wss://relay.example.com".to_string(),
+            String::new(),
+        ])
+        .unwrap();
+        assert_eq!(relays, vec!["wss://relay.example.com".to_string()]);
+
+        assert!(matches!(
+            parse_relays(&["https://relay.example.com".to_string()]),
+            Err(RemoteError::InvalidRelay(_))
+        ));
+        assert!(matches!(parse_relays(&[]), Err(RemoteError::NoRelays)));
+    }
+}
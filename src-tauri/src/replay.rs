@@ -0,0 +1,191 @@
+//! Nonces of accepted exchange messages, for rejecting replays
+//!
+//! A captured broadcast stays valid for its whole timestamp window, so
+//! someone who recorded it could play it back to start an exchange in its
+//! sender's name. Each accepted message's nonce is remembered per pubkey
+//! until the tolerance it was accepted under would refuse it as expired, and
+//! a second message with the same nonce is refused.
+//!
+//! Entries leave by expiry, not by count: live messages are forgotten within
+//! minutes, so there is no shared cap for one busy sender to flush everyone
+//! else's nonces through. Only a sender's own entries beyond
+//! `MAX_NONCES_PER_SENDER` are dropped, soonest to expire first.
+
+use crate::exchange::{ScanError, TimestampTolerance};
+use crate::settings::{MAX_FUTURE_SKEW_SECS, MAX_STATIC_TAG_AGE_DAYS};
+use serde::{Deserialize, Serialize};
+
+/// Most nonces kept for one sender
+pub const MAX_NONCES_PER_SENDER: usize = 50;
+
+/// How long nonces saved before entries had an expiry are kept: the longest
+/// any message could stay acceptable
+const LEGACY_RETENTION_SECS: u64 =
+    MAX_STATIC_TAG_AGE_DAYS as u64 * 24 * 60 * 60 + MAX_FUTURE_SKEW_SECS;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+struct SeenNonce {
+    pubkey: String,
+    nonce: String,
+    /// The message's own timestamp
+    timestamp: u64,
+    /// When the message would be refused as too old, so a replay of it is
+    /// refused anyway
+    #[serde(default)]
+    expires_at: Option<u64>,
+}
+
+impl SeenNonce {
+    fn expires_at(&self) -> u64 {
+        self.expires_at
+            .unwrap_or_else(|| self.timestamp.saturating_add(LEGACY_RETENTION_SECS))
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct NonceCache {
+    seen: Vec<SeenNonce>,
+}
+
+impl NonceCache {
+    /// Whether a message from `pubkey` with `nonce` was accepted before
+    pub fn contains(&self, pubkey: &str, nonce: &str) -> bool {
+        self.seen
+            .iter()
+            .any(|s| s.pubkey == pubkey && s.nonce.eq_ignore_ascii_case(nonce))
+    }
+
+    /// Refuse a message from `pubkey` accepted before. `pending_nonce` is
+    /// that of the message behind a still-pending exchange with them, which
+    /// may come again when a tap is retried; any other seen nonce is a replay.
+    pub fn check(
+        &self,
+        pubkey: &str,
+        nonce: &str,
+        pending_nonce: Option<&str>,
+    ) -> Result<(), ScanError> {
+        if pending_nonce.is_some_and(|pending| pending.eq_ignore_ascii_case(nonce)) {
+            return Ok(());
+        }
+        if self.contains(pubkey, nonce) {
+            return Err(ScanError::Replayed);
+        }
+        Ok(())
+    }
+
+    /// Remember a message accepted under `tolerance`, forgetting nonces of
+    /// messages that would be rejected as expired by now
+    pub fn insert(
+        &mut self,
+        pubkey: &str,
+        nonce: &str,
+        timestamp: u64,
+        tolerance: TimestampTolerance,
+        now: u64,
+    ) {
+        self.seen.retain(|s| s.expires_at() >= now);
+        self.seen.push(SeenNonce {
+            pubkey: pubkey.to_string(),
+            nonce: nonce.to_ascii_lowercase(),
+            timestamp,
+            expires_at: Some(timestamp.saturating_add(tolerance.max_age_secs)),
+        });
+
+        // Over the cap, drop one of the sender's earlier entries, never the
+        // one just accepted
+        let from_sender = self.seen.iter().filter(|s| s.pubkey == pubkey).count();
+        if from_sender > MAX_NONCES_PER_SENDER {
+            let newest = self.seen.len() - 1;
+            let soonest = self.seen[..newest]
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| s.pubkey == pubkey)
+                .min_by_key(|(_, s)| s.expires_at())
+                .map(|(index, _)| index);
+            if let Some(index) = soonest {
+                self.seen.remove(index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIVE: TimestampTolerance = TimestampTolerance::LIVE;
+
+    #[test]
+    fn test_replayed_nonce_is_seen() {
+        let mut cache = NonceCache::default();
+        cache.insert("alice", "ABCD", 1_000, LIVE, 1_000);
+
+        assert!(cache.contains("alice", "abcd"));
+        assert!(!cache.contains("bob", "abcd"));
+        assert!(!cache.contains("alice", "ef01"));
+
+        let json = serde_json::to_string(&cache).unwrap();
+        let restored: NonceCache = serde_json::from_str(&json).unwrap();
+        assert!(restored.contains("alice", "abcd"));
+    }
+
+    #[test]
+    fn test_pending_exchange_only_exempts_its_own_nonce() {
+        let mut cache = NonceCache::default();
+        cache.insert("alice", "0a", 1_000, LIVE, 1_000);
+        cache.insert("alice", "0b", 2_000, LIVE, 2_000);
+
+        // A retried tap of the message the pending exchange came from
+        assert!(cache.check("alice", "0b", Some("0B")).is_ok());
+        // An older captured broadcast replayed while that exchange is open
+        assert!(matches!(
+            cache.check("alice", "0a", Some("0b")),
+            Err(ScanError::Replayed)
+        ));
+        assert!(matches!(
+            cache.check("alice", "0b", None),
+            Err(ScanError::Replayed)
+        ));
+        assert!(cache.check("alice", "0c", Some("0b")).is_ok());
+    }
+
+    #[test]
+    fn test_expired_nonces_are_forgotten() {
+        let mut cache = NonceCache::default();
+        cache.insert("alice", "01", 1_000, LIVE, 1_000);
+        cache.insert("dave", "04", 1_000, TimestampTolerance::STATIC_TAG, 1_000);
+        let later = 1_000 + LIVE.max_age_secs;
+        cache.insert("bob", "02", later, LIVE, later);
+        assert!(cache.contains("alice", "01"));
+
+        // Past the live window, but a static tag's message is still good
+        cache.insert("carol", "03", later, LIVE, later + 1);
+        assert!(!cache.contains("alice", "01"));
+        assert!(cache.contains("bob", "02"));
+        assert!(cache.contains("dave", "04"));
+    }
+
+    #[test]
+    fn test_one_sender_cannot_flush_others() {
+        let mut cache = NonceCache::default();
+        cache.insert("alice", "a1", 1_000, LIVE, 1_000);
+        for i in 0..MAX_NONCES_PER_SENDER * 10 {
+            cache.insert("mallory", &format!("{:04x}", i), 1_000, LIVE, 1_000);
+        }
+        assert!(cache.contains("alice", "a1"));
+        assert_eq!(cache.seen.len(), MAX_NONCES_PER_SENDER + 1);
+        // The flooder only pushes out their own oldest
+        assert!(!cache.contains("mallory", "0000"));
+        let last = format!("{:04x}", MAX_NONCES_PER_SENDER * 10 - 1);
+        assert!(cache.contains("mallory", &last));
+    }
+
+    #[test]
+    fn test_nonces_saved_without_expiry_are_kept() {
+        let saved = r#"{"seen":[{"pubkey":"alice","nonce":"01","timestamp":1000}]}"#;
+        let mut cache: NonceCache = serde_json::from_str(saved).unwrap();
+        cache.insert("bob", "02", 2_000, LIVE, 2_000 + LIVE.max_age_secs);
+        assert!(cache.contains("alice", "01"));
+    }
+}
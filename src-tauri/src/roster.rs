@@ -0,0 +1,306 @@
+//! Round-robin exchange for three or more people
+//!
+//! Everyone at the table adds themselves to one roster in turn. The first
+//! person starts it with their signed exchange message; the next scans it,
+//! appends their own and shows the longer roster to the one after, and so on.
+//! Besides its exchange message, each entry is signed over the roster ID and
+//! every entry before it, so entries can't be dropped, reordered or carried
+//! into another roster. A second lap takes the complete roster back round,
+//! and every device saves each member it hasn't seen yet as the roster
+//! reaches it.
+
+use crate::exchange::{ExchangeMessage, TimestampTolerance};
+use crate::keys::{verify_content_as, SigScheme, Signer};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum RosterError {
+    #[error("Not a group exchange roster")]
+    InvalidFormat,
+    #[error("This roster belongs to a different group exchange")]
+    WrongRoster,
+    #[error("Roster entry {index} is invalid: {reason}")]
+    InvalidEntry { index: usize, reason: String },
+    #[error("Someone is on the roster twice")]
+    DuplicateMember,
+    #[error("The roster is full ({MAX_ROSTER_MEMBERS} people)")]
+    Full,
+    #[error("Two people added themselves at once; pass one roster round in order")]
+    Diverged,
+    #[error("Signing error: {0}")]
+    SigningError(String),
+}
+
+/// Message type of a group exchange roster
+pub const ROSTER_TYPE: &str = "sneakernet-roster";
+
+/// Most people in one group exchange
+pub const MAX_ROSTER_MEMBERS: usize = 20;
+
+/// Going round a table twice takes far longer than a tap, so roster entries
+/// are accepted for an hour
+pub const ROSTER_TOLERANCE: TimestampTolerance = TimestampTolerance {
+    max_age_secs: 60 * 60,
+    max_future_secs: 120,
+};
+
+/// One person's place on the roster
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RosterEntry {
+    /// Their initial exchange message
+    pub message: ExchangeMessage,
+    /// Signature by the same key over the roster up to and including this entry
+    pub chain_signature: String,
+    /// How `chain_signature` was made; signed along with the roster
+    #[serde(default, skip_serializing_if = "SigScheme::is_digest")]
+    pub chain_scheme: SigScheme,
+}
+
+/// Roster passed from person to person
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Roster {
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub id: String,
+    pub entries: Vec<RosterEntry>,
+}
+
+/// Someone on the roster, for showing who has joined
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RosterMember {
+    pub pubkey: String,
+    pub display_name: Option<String>,
+}
+
+impl Roster {
+    /// Start a new roster with our own exchange message
+    pub async fn start(signer: &dyn Signer, message: ExchangeMessage) -> Result<Self, RosterError> {
+        let mut roster = Self {
+            msg_type: ROSTER_TYPE.to_string(),
+            id: Uuid::new_v4().to_string(),
+            entries: Vec::new(),
+        };
+        roster.append(signer, message).await?;
+        Ok(roster)
+    }
+
+    /// Add our exchange message to the end, signing the roster so far
+    pub async fn append(
+        &mut self,
+        signer: &dyn Signer,
+        message: ExchangeMessage,
+    ) -> Result<(), RosterError> {
+        if self.contains(&message.pubkey) {
+            return Err(RosterError::DuplicateMember);
+        }
+        if self.entries.len() >= MAX_ROSTER_MEMBERS {
+            return Err(RosterError::Full);
+        }
+        let chain_scheme = signer.sig_scheme();
+        let content = self.chain_content(self.entries.len(), &message, chain_scheme);
+        let chain_signature = signer
+            .sign_content(&content)
+            .await
+            .map_err(|e| RosterError::SigningError(e.to_string()))?;
+        self.entries.push(RosterEntry {
+            message,
+            chain_signature,
+            chain_scheme,
+        });
+        Ok(())
+    }
+
+    /// What the entry at `index` signs: the roster ID, a hash of the entries
+    /// before it, its own exchange message and, unless it's a plain digest
+    /// signature, how it is signed
+    fn chain_content(&self, index: usize, message: &ExchangeMessage, scheme: SigScheme) -> String {
+        let mut hasher = Sha256::new();
+        for entry in &self.entries[..index] {
+            hasher.update(entry.message.signature.as_bytes());
+            hasher.update(entry.chain_signature.as_bytes());
+        }
+        let mut content = format!(
+            "{}:{}:{}:{}:{}",
+            ROSTER_TYPE,
+            self.id,
+            index,
+            hex::encode(hasher.finalize()),
+            message.signature
+        );
+        if !scheme.is_digest() {
+            content.push_str(&format!(":scheme:{}", scheme.as_str()));
+        }
+        content
+    }
+
+    /// Whether `pubkey` has an entry
+    pub fn contains(&self, pubkey: &str) -> bool {
+        self.entries.iter().any(|e| e.message.pubkey == pubkey)
+    }
+
+    /// Check every entry: its exchange message (an initial one, within
+    /// `tolerance`) and its signature over the roster before it
+    pub fn verify(&self, tolerance: TimestampTolerance) -> Result<(), RosterError> {
+        if self.msg_type != ROSTER_TYPE || self.entries.is_empty() {
+            return Err(RosterError::InvalidFormat);
+        }
+        if self.entries.len() > MAX_ROSTER_MEMBERS {
+            return Err(RosterError::Full);
+        }
+        for (index, entry) in self.entries.iter().enumerate() {
+            let invalid = |reason: String| RosterError::InvalidEntry { index, reason };
+            let message = &entry.message;
+            if message.their_pubkey.is_some() {
+                return Err(invalid("not an initial exchange message".to_string()));
+            }
+            message
+                .verify_with(None, tolerance)
+                .map_err(|e| invalid(e.to_string()))?;
+            if self.entries[..index]
+                .iter()
+                .any(|e| e.message.pubkey == message.pubkey)
+            {
+                return Err(RosterError::DuplicateMember);
+            }
+            verify_content_as(
+                &message.pubkey,
+                &self.chain_content(index, message, entry.chain_scheme),
+                &entry.chain_signature,
+                entry.chain_scheme,
+            )
+            .map_err(|e| invalid(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Take `other` if it continues ours. Returns whether it added anyone.
+    pub fn merge(&mut self, other: Roster) -> Result<bool, RosterError> {
+        if other.id != self.id {
+            return Err(RosterError::WrongRoster);
+        }
+        let diverged = self
+            .entries
+            .iter()
+            .zip(&other.entries)
+            .any(|(ours, theirs)| ours.chain_signature != theirs.chain_signature);
+        if diverged {
+            return Err(RosterError::Diverged);
+        }
+        if other.entries.len() > self.entries.len() {
+            *self = other;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    pub fn members(&self) -> Vec<RosterMember> {
+        self.entries
+            .iter()
+            .map(|e| RosterMember {
+                pubkey: e.message.pubkey.clone(),
+                display_name: e
+                    .message
+                    .profile
+                    .as_ref()
+                    .and_then(|p| p.display_name.clone()),
+            })
+            .collect()
+    }
+
+    pub fn to_json(&self) -> Result<String, RosterError> {
+        serde_json::to_string(self).map_err(|_| RosterError::InvalidFormat)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, RosterError> {
+        serde_json::from_str(json).map_err(|_| RosterError::InvalidFormat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::Keys;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn join(roster: &mut Roster, keys: &Keys) {
+        let message = ExchangeMessage::new_initial(keys).unwrap();
+        block_on(roster.append(keys, message)).unwrap();
+    }
+
+    fn roster_of(people: &[Keys]) -> Roster {
+        let message = ExchangeMessage::new_initial(&people[0]).unwrap();
+        let mut roster = block_on(Roster::start(&people[0], message)).unwrap();
+        for keys in &people[1..] {
+            join(&mut roster, keys);
+        }
+        roster
+    }
+
+    #[test]
+    fn test_roster_round_trip() {
+        let people: Vec<Keys> = (0..3).map(|_| Keys::generate()).collect();
+        let roster = roster_of(&people);
+
+        let scanned = Roster::from_json(&roster.to_json().unwrap()).unwrap();
+        scanned.verify(ROSTER_TOLERANCE).unwrap();
+        assert_eq!(scanned.members().len(), 3);
+        assert!(people
+            .iter()
+            .all(|k| scanned.contains(&k.public_key().to_hex())));
+
+        // Joining twice is refused
+        let mut again = scanned.clone();
+        let message = ExchangeMessage::new_initial(&people[1]).unwrap();
+        assert!(matches!(
+            block_on(again.append(&people[1], message)),
+            Err(RosterError::DuplicateMember)
+        ));
+    }
+
+    #[test]
+    fn test_tampered_roster_rejected() {
+        let people: Vec<Keys> = (0..3).map(|_| Keys::generate()).collect();
+
+        // Dropping an entry breaks the chain of the ones after it
+        let mut dropped = roster_of(&people);
+        dropped.entries.remove(1);
+        assert!(matches!(
+            dropped.verify(ROSTER_TOLERANCE),
+            Err(RosterError::InvalidEntry { index: 1, .. })
+        ));
+
+        // An entry can't be moved into another roster
+        let mut moved = roster_of(&people);
+        moved.id = Uuid::new_v4().to_string();
+        assert!(moved.verify(ROSTER_TOLERANCE).is_err());
+    }
+
+    #[test]
+    fn test_merge_takes_longer_roster() {
+        let people: Vec<Keys> = (0..3).map(|_| Keys::generate()).collect();
+        let mut ours = roster_of(&people[..2]);
+        let mut longer = ours.clone();
+        join(&mut longer, &people[2]);
+
+        assert!(ours.merge(longer.clone()).unwrap());
+        assert_eq!(ours.entries.len(), 3);
+        assert!(!ours.merge(longer).unwrap());
+
+        // Someone else joining the same two-person roster forks it
+        let mut fork = roster_of(&people[..2]);
+        fork.id = ours.id.clone();
+        assert!(matches!(ours.merge(fork), Err(RosterError::Diverged)));
+    }
+}
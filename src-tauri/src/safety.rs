@@ -0,0 +1,311 @@
+//! Safety numbers for checking an exchange after the fact
+//!
+//! Both sides derive the same number from the two pubkeys, so two people can
+//! read theirs to each other (in person, or over a call they trust) and know
+//! they hold each other's real keys. The pubkeys are sorted first, which makes
+//! the number the same on both devices. Hashing is repeated to make finding a
+//! key whose number collides with someone else's expensive.
+//!
+//! A short authentication string (SAS) is checked during an exchange instead:
+//! it also covers the nonces of the two messages, so it is fresh each time and
+//! short enough to compare at a glance before the contact is saved. Someone
+//! swapping a QR code in between would have to match it with keys of their own.
+//!
+//! A session code is shown on both screens from the moment a response is made:
+//! three words from the ID binding the response to the message it answers, so
+//! a response relayed from another exchange shows a different code.
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Domain separation for the safety number hash
+const SAFETY_NUMBER_CONTEXT: &[u8] = b"sneakernet-safety-number-v1";
+
+/// Times the hash is repeated
+const SAFETY_NUMBER_ITERATIONS: usize = 1024;
+
+/// Groups of five digits in a safety number
+pub const SAFETY_NUMBER_GROUPS: usize = 6;
+
+/// Domain separation for the short authentication string hash
+const SAS_CONTEXT: &[u8] = b"sneakernet-sas-v1";
+
+/// Emoji in a short authentication string, six bits each
+pub const SAS_EMOJI_COUNT: usize = 4;
+
+/// Digits in the numeric form of a short authentication string
+pub const SAS_DIGITS: usize = 6;
+
+/// Words in the short code of an exchange session, six bits each
+pub const SESSION_CODE_WORDS: usize = 3;
+
+/// Domain separation for the fingerprint words of a single key
+const FINGERPRINT_CONTEXT: &[u8] = b"sneakernet-fingerprint-v1";
+
+/// Words in a key fingerprint, six bits each
+pub const FINGERPRINT_WORD_COUNT: usize = 8;
+
+/// The name of each SAS emoji, in the same order, for where emoji won't do
+const SAS_WORDS: [&str; 64] = [
+    "dog",
+    "cat",
+    "lion",
+    "horse",
+    "unicorn",
+    "pig",
+    "elephant",
+    "rabbit",
+    "panda",
+    "rooster",
+    "penguin",
+    "turtle",
+    "fish",
+    "octopus",
+    "butterfly",
+    "flower",
+    "tree",
+    "cactus",
+    "mushroom",
+    "globe",
+    "moon",
+    "cloud",
+    "fire",
+    "banana",
+    "apple",
+    "strawberry",
+    "corn",
+    "pizza",
+    "cake",
+    "heart",
+    "smiley",
+    "robot",
+    "hat",
+    "glasses",
+    "spanner",
+    "santa",
+    "thumbs",
+    "umbrella",
+    "hourglass",
+    "clock",
+    "gift",
+    "bulb",
+    "book",
+    "pencil",
+    "paperclip",
+    "scissors",
+    "lock",
+    "key",
+    "hammer",
+    "telephone",
+    "flag",
+    "train",
+    "bicycle",
+    "aeroplane",
+    "rocket",
+    "trophy",
+    "ball",
+    "guitar",
+    "trumpet",
+    "bell",
+    "anchor",
+    "headphones",
+    "folder",
+    "pin",
+];
+
+/// One emoji per six-bit value, picked to be easy to tell apart and name
+const SAS_EMOJI: [&str; 64] = [
+    "🐶", "🐱", "🦁", "🐎", "🦄", "🐷", "🐘", "🐰", "🐼", "🐓", "🐧", "🐢", "🐟", "🐙", "🦋", "🌷",
+    "🌳", "🌵", "🍄", "🌏", "🌙", "☁️", "🔥", "🍌", "🍎", "🍓", "🌽", "🍕", "🎂", "❤️", "😀", "🤖",
+    "🎩", "👓", "🔧", "🎅", "👍", "☂️", "⌛", "⏰", "🎁", "💡", "📕", "✏️", "📎", "✂️", "🔒", "🔑",
+    "🔨", "☎️", "🏁", "🚂", "🚲", "✈️", "🚀", "🏆", "⚽", "🎸", "🎺", "🔔", "⚓", "🎧", "📁", "📌",
+];
+
+/// A fingerprint of two pubkeys
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SafetyNumber {
+    /// Five-digit groups, to be read aloud or compared side by side
+    pub groups: Vec<String>,
+}
+
+impl SafetyNumber {
+    /// The safety number for `our_pubkey` and `their_pubkey` (hex)
+    pub fn derive(our_pubkey: &str, their_pubkey: &str) -> Self {
+        let mut pubkeys = [
+            our_pubkey.to_ascii_lowercase(),
+            their_pubkey.to_ascii_lowercase(),
+        ];
+        pubkeys.sort();
+        let [first, second] = pubkeys;
+
+        let mut hash: [u8; 32] = Sha256::new()
+            .chain_update(SAFETY_NUMBER_CONTEXT)
+            .chain_update(&first)
+            .chain_update(&second)
+            .finalize()
+            .into();
+        for _ in 1..SAFETY_NUMBER_ITERATIONS {
+            hash = Sha256::new()
+                .chain_update(hash)
+                .chain_update(first.as_bytes())
+                .finalize()
+                .into();
+        }
+
+        // Five bytes per group keeps the modulo bias negligible
+        let groups = hash
+            .chunks_exact(5)
+            .take(SAFETY_NUMBER_GROUPS)
+            .map(|chunk| {
+                let value = chunk.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64);
+                format!("{:05}", value % 100_000)
+            })
+            .collect();
+
+        Self { groups }
+    }
+
+    /// All groups separated by spaces
+    pub fn display(&self) -> String {
+        self.groups.join(" ")
+    }
+}
+
+/// Short code both people compare before an exchange is saved
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ShortAuthString {
+    pub emoji: Vec<String>,
+    /// The same check as digits, for reading aloud
+    pub digits: String,
+}
+
+impl ShortAuthString {
+    /// The SAS for our message and theirs, each given as (pubkey, nonce) hex.
+    /// The pairs are sorted by pubkey so both devices hash the same input.
+    pub fn derive(ours: (&str, &str), theirs: (&str, &str)) -> Self {
+        let mut pairs = [ours, theirs]
+            .map(|(pubkey, nonce)| (pubkey.to_ascii_lowercase(), nonce.to_ascii_lowercase()));
+        pairs.sort();
+
+        let mut hasher = Sha256::new().chain_update(SAS_CONTEXT);
+        for (pubkey, nonce) in &pairs {
+            hasher.update(pubkey.as_bytes());
+            hasher.update(nonce.as_bytes());
+        }
+        let hash: [u8; 32] = hasher.finalize().into();
+
+        // Four six-bit emoji from the first three bytes, digits from the next five
+        let bits = hash[..3].iter().fold(0u32, |acc, &b| (acc << 8) | b as u32);
+        let emoji = (0..SAS_EMOJI_COUNT)
+            .map(|i| {
+                let index = (bits >> (6 * (SAS_EMOJI_COUNT - 1 - i))) & 0x3f;
+                SAS_EMOJI[index as usize].to_string()
+            })
+            .collect();
+        let value = hash[3..8]
+            .iter()
+            .fold(0u64, |acc, &b| (acc << 8) | b as u64);
+        let digits = format!("{:06}", value % 10u64.pow(SAS_DIGITS as u32));
+
+        Self { emoji, digits }
+    }
+}
+
+/// Words naming one pubkey, printed beside it so whoever types or scans it
+/// in can check they got the right key
+pub fn fingerprint_words(pubkey: &str) -> Vec<String> {
+    let hash: [u8; 32] = Sha256::new()
+        .chain_update(FINGERPRINT_CONTEXT)
+        .chain_update(pubkey.to_ascii_lowercase().as_bytes())
+        .finalize()
+        .into();
+
+    // Eight six-bit words from the first six bytes
+    let bits = hash[..6].iter().fold(0u64, |acc, &b| (acc << 8) | b as u64);
+    (0..FINGERPRINT_WORD_COUNT)
+        .map(|i| {
+            let index = (bits >> (6 * (FINGERPRINT_WORD_COUNT - 1 - i))) & 0x3f;
+            SAS_WORDS[index as usize].to_string()
+        })
+        .collect()
+}
+
+/// The short code for an exchange session, from its ID (hex, see
+/// `exchange::session_id`), as words joined by dashes
+pub fn session_code(session_id: &str) -> String {
+    let bytes = hex::decode(session_id).unwrap_or_default();
+    let bits = bytes
+        .iter()
+        .take(3)
+        .fold(0u32, |acc, &b| (acc << 8) | b as u32);
+    (0..SESSION_CODE_WORDS)
+        .map(|i| {
+            let index = (bits >> (6 * (SESSION_CODE_WORDS - 1 - i))) & 0x3f;
+            SAS_WORDS[index as usize]
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "a1f3e59a8c2d4b7e9f0c1d2e3f4a5b6c7d8e9f0a1b2c3d4e5f6a7b8c9d0e1f2a";
+    const BOB: &str = "0b1c2d3e4f5a6b7c8d9e0f1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b1c";
+
+    #[test]
+    fn test_same_number_on_both_sides() {
+        let ours = SafetyNumber::derive(ALICE, BOB);
+        assert_eq!(ours, SafetyNumber::derive(BOB, ALICE));
+        assert_eq!(ours.groups.len(), SAFETY_NUMBER_GROUPS);
+        assert!(ours
+            .groups
+            .iter()
+            .all(|g| g.len() == 5 && g.chars().all(|c| c.is_ascii_digit())));
+        assert_eq!(ours.display().len(), SAFETY_NUMBER_GROUPS * 6 - 1);
+    }
+
+    #[test]
+    fn test_different_keys_differ() {
+        let carol = "c".repeat(64);
+        assert_ne!(
+            SafetyNumber::derive(ALICE, BOB),
+            SafetyNumber::derive(ALICE, &carol)
+        );
+    }
+
+    #[test]
+    fn test_sas_matches_on_both_sides() {
+        let (alice_nonce, bob_nonce) = ("11".repeat(16), "22".repeat(16));
+        let ours = ShortAuthString::derive((ALICE, &alice_nonce), (BOB, &bob_nonce));
+        assert_eq!(
+            ours,
+            ShortAuthString::derive((BOB, &bob_nonce), (ALICE, &alice_nonce))
+        );
+        assert_eq!(ours.emoji.len(), SAS_EMOJI_COUNT);
+        assert_eq!(ours.digits.len(), SAS_DIGITS);
+
+        // A different nonce (a substituted or replayed message) changes it
+        let other = ShortAuthString::derive((ALICE, &alice_nonce), (BOB, &"33".repeat(16)));
+        assert_ne!(ours, other);
+    }
+
+    #[test]
+    fn test_fingerprint_words() {
+        let words = fingerprint_words(ALICE);
+        assert_eq!(words.len(), FINGERPRINT_WORD_COUNT);
+        assert_eq!(words, fingerprint_words(&ALICE.to_ascii_uppercase()));
+        assert_ne!(words, fingerprint_words(BOB));
+    }
+
+    #[test]
+    fn test_session_code() {
+        let code = session_code(&"ab".repeat(16));
+        assert_eq!(code.split('-').count(), SESSION_CODE_WORDS);
+        assert_eq!(code, session_code(&"AB".repeat(16)));
+        assert_ne!(code, session_code(&"cd".repeat(16)));
+    }
+}
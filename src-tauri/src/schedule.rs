@@ -0,0 +1,215 @@
+//! Scheduled messages
+//!
+//! Messages are saved with a send time and picked up by a background task.
+//! Once a message is due it goes out over the contact's existing connection;
+//! if there isn't one it stays queued and is retried on the next tick, so a
+//! message scheduled for 9am is sent whenever the contact is next reachable.
+
+use crate::chat::ChatMessage;
+use crate::commands::{load_scheduled_from_store, save_scheduled_to_store};
+use crate::state::AppState;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// How often the scheduler looks for due messages
+pub const SCHEDULER_TICK_SECS: u64 = 15;
+
+/// Most messages that can be scheduled at once
+pub const MAX_SCHEDULED_MESSAGES: usize = 200;
+
+/// Event emitted when a scheduled message has been sent
+pub const SCHEDULED_SENT_EVENT: &str = "chat://scheduled-sent";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ScheduleError {
+    #[error("Message is empty")]
+    EmptyMessage,
+    #[error("Too many scheduled messages")]
+    TooMany,
+}
+
+/// A message waiting for its send time
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledMessage {
+    pub id: String,
+    pub contact_pubkey: String,
+    pub content: String,
+    /// Unix timestamp to send at
+    pub send_at: u64,
+    pub created_at: u64,
+    /// Failed send attempts since the message became due
+    #[serde(default)]
+    pub attempts: u32,
+    /// Why the last attempt didn't go out
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+/// Payload of `SCHEDULED_SENT_EVENT`
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledSent {
+    pub scheduled_id: String,
+    pub message: ChatMessage,
+}
+
+impl ScheduledMessage {
+    pub fn new(
+        contact_pubkey: &str,
+        content: &str,
+        send_at: u64,
+        now: u64,
+    ) -> Result<Self, ScheduleError> {
+        if content.trim().is_empty() {
+            return Err(ScheduleError::EmptyMessage);
+        }
+
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            contact_pubkey: contact_pubkey.to_string(),
+            content: content.to_string(),
+            send_at,
+            created_at: now,
+            attempts: 0,
+            last_error: None,
+        })
+    }
+
+    pub fn is_due(&self, now: u64) -> bool {
+        self.send_at <= now
+    }
+}
+
+/// Add `message`, keeping the list ordered by send time
+pub fn insert(
+    scheduled: &mut Vec<ScheduledMessage>,
+    message: ScheduledMessage,
+) -> Result<(), ScheduleError> {
+    if scheduled.len() >= MAX_SCHEDULED_MESSAGES {
+        return Err(ScheduleError::TooMany);
+    }
+
+    let index = scheduled.partition_point(|m| m.send_at <= message.send_at);
+    scheduled.insert(index, message);
+    Ok(())
+}
+
+/// Messages due at `now`, earliest first
+pub fn due(scheduled: &[ScheduledMessage], now: u64) -> Vec<ScheduledMessage> {
+    scheduled
+        .iter()
+        .filter(|m| m.is_due(now))
+        .cloned()
+        .collect()
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Check for due messages every `SCHEDULER_TICK_SECS` for the life of the app
+pub fn spawn_scheduler(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(SCHEDULER_TICK_SECS));
+        loop {
+            tick.tick().await;
+            for message in due(&load_scheduled_from_store(&app), now_secs()) {
+                let result = send_scheduled(&app, &message).await;
+                record_result(&app, &message.id, result);
+            }
+        }
+    });
+}
+
+/// Send one due message over the contact's current connection
+async fn send_scheduled(
+    app: &AppHandle,
+    scheduled: &ScheduledMessage,
+) -> Result<ChatMessage, String> {
+    let state = app.state::<AppState>();
+    let connection = state
+        .iroh_node
+        .read()
+        .await
+        .get_connection(&scheduled.contact_pubkey)
+        .cloned()
+        .ok_or("Not connected to contact")?;
+
+    let mut chat_manager = state.chat_manager.write().await;
+    let manager = chat_manager
+        .as_mut()
+        .ok_or("Chat manager not initialized")?;
+    let message = manager
+        .send_message(&connection, &scheduled.contact_pubkey, &scheduled.content)
+        .await
+        .map_err(|e| e.to_string())?;
+    manager.record_retries(&message.id, scheduled.created_at, scheduled.attempts);
+
+    Ok(message)
+}
+
+/// Drop a sent message from the schedule, or note why it is still queued.
+/// The list is reloaded so messages scheduled or cancelled meanwhile are kept.
+fn record_result(app: &AppHandle, id: &str, result: Result<ChatMessage, String>) {
+    let mut scheduled = load_scheduled_from_store(app);
+    match result {
+        Ok(message) => {
+            scheduled.retain(|m| m.id != id);
+            let _ = app.emit(
+                SCHEDULED_SENT_EVENT,
+                ScheduledSent {
+                    scheduled_id: id.to_string(),
+                    message,
+                },
+            );
+        }
+        Err(error) => {
+            let Some(entry) = scheduled.iter_mut().find(|m| m.id == id) else {
+                return;
+            };
+            entry.attempts += 1;
+            entry.last_error = Some(error);
+        }
+    }
+    let _ = save_scheduled_to_store(app, &scheduled);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_rejects_empty() {
+        assert_eq!(
+            ScheduledMessage::new("pk", "  ", 100, 0).unwrap_err(),
+            ScheduleError::EmptyMessage
+        );
+        let message = ScheduledMessage::new("pk", "hi", 100, 0).unwrap();
+        assert!(!message.is_due(99));
+        assert!(message.is_due(100));
+    }
+
+    #[test]
+    fn test_insert_orders_by_send_time() {
+        let mut scheduled = Vec::new();
+        for send_at in [300, 100, 200, 100] {
+            insert(
+                &mut scheduled,
+                ScheduledMessage::new("pk", "hi", send_at, 0).unwrap(),
+            )
+            .unwrap();
+        }
+
+        let times: Vec<u64> = scheduled.iter().map(|m| m.send_at).collect();
+        assert_eq!(times, vec![100, 100, 200, 300]);
+        assert_eq!(due(&scheduled, 150).len(), 2);
+        assert!(due(&scheduled, 50).is_empty());
+    }
+}
@@ -0,0 +1,622 @@
+//! Backend-owned user settings with defaults, validation, and migrations
+//!
+//! Settings are stored as a single JSON object. Every section uses
+//! `#[serde(default)]`, so older stores missing newer fields still load, and
+//! the `version` field drives explicit migrations for anything that changes
+//! shape.
+
+use crate::exchange::{ExchangeProfile, TimestampTolerance, MAX_NIP05_LEN};
+use crate::identifier::Identifier;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+/// Current settings schema version
+pub const SETTINGS_VERSION: u32 = 1;
+
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    #[error("Invalid setting {field}: {reason}")]
+    Invalid { field: &'static str, reason: String },
+    #[error("Failed to parse settings: {0}")]
+    ParseError(String),
+    #[error("Settings version {0} is newer than this app supports")]
+    UnsupportedVersion(u32),
+}
+
+/// Longest display name on our profile card, in characters
+pub const MAX_DISPLAY_NAME_CHARS: usize = 64;
+
+/// What we show about ourselves on a profile card and in exchange messages
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ProfileSettings {
+    pub display_name: Option<String>,
+    /// Avatar image URL (https)
+    pub avatar_url: Option<String>,
+    /// SHA-256 of the avatar image (hex)
+    pub avatar_hash: Option<String>,
+    /// Our NIP-05 identifier (`name@domain`)
+    pub nip05: Option<String>,
+}
+
+impl ProfileSettings {
+    /// The profile to sign into exchange messages, if any of it is set
+    pub fn exchange_profile(&self) -> Option<ExchangeProfile> {
+        ExchangeProfile {
+            display_name: self.display_name.clone(),
+            avatar_hash: self.avatar_hash.clone(),
+            nip05: self.nip05.clone(),
+        }
+        .non_empty()
+    }
+}
+
+/// Desktop window behavior
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DesktopSettings {
+    /// Closing the window hides it to the system tray and keeps the node running
+    pub run_in_tray: bool,
+}
+
+/// Message persistence behavior
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PersistenceSettings {
+    /// Keep chat history across restarts
+    pub persist_messages: bool,
+    /// Delete messages older than this many days (None = keep forever)
+    pub retention_days: Option<u32>,
+}
+
+/// Iroh relay configuration
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RelaySettings {
+    pub use_relays: bool,
+    /// Custom relay URL (None = use default n0 relays)
+    pub custom_relay_url: Option<String>,
+}
+
+impl Default for RelaySettings {
+    fn default() -> Self {
+        Self {
+            use_relays: true,
+            custom_relay_url: None,
+        }
+    }
+}
+
+/// Notification preferences
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NotificationSettings {
+    pub enabled: bool,
+    /// Show message content in notifications
+    pub show_preview: bool,
+    pub sound: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            show_preview: false,
+            sound: true,
+        }
+    }
+}
+
+/// Bandwidth limits
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BandwidthSettings {
+    /// Avoid optional network traffic (previews, background sync)
+    pub low_data_mode: bool,
+    /// Largest attachment we send or accept
+    pub max_attachment_bytes: u64,
+}
+
+impl Default for BandwidthSettings {
+    fn default() -> Self {
+        Self {
+            low_data_mode: false,
+            max_attachment_bytes: 10 * 1024 * 1024,
+        }
+    }
+}
+
+/// Security toggles
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SecuritySettings {
+    /// Require unlocking the app before keys can be used
+    pub require_unlock: bool,
+    /// Lock again after this many idle minutes (None = never)
+    pub auto_lock_minutes: Option<u32>,
+    /// Ask the OS to block screenshots of the app
+    pub block_screenshots: bool,
+    /// Offer contacts who support it a post-quantum hybrid chat handshake
+    pub post_quantum_chat: bool,
+}
+
+impl Default for SecuritySettings {
+    fn default() -> Self {
+        Self {
+            require_unlock: false,
+            auto_lock_minutes: None,
+            block_screenshots: true,
+            post_quantum_chat: false,
+        }
+    }
+}
+
+/// Longest a static NFC tag's payload may be accepted for, in days
+pub const MAX_STATIC_TAG_AGE_DAYS: u32 = 365;
+
+/// Furthest ahead of our clock a payload may ever be allowed to be dated
+pub const MAX_FUTURE_SKEW_SECS: u64 = 600;
+
+/// Most new senders or payloads per sender a minute that can be configured
+pub const MAX_INBOUND_LIMIT: u32 = 1000;
+
+/// How far exchange message timestamps may be from our clock, and how many
+/// inbound payloads are processed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExchangeSettings {
+    /// Oldest live payload (QR on screen, NFC tap) accepted, in seconds
+    pub max_age_secs: u64,
+    /// How far ahead of our clock a payload may be dated, in seconds
+    pub max_future_secs: u64,
+    /// Oldest payload accepted from a static NFC tag, in days
+    pub static_tag_max_age_days: u32,
+    /// Different senders whose payloads are processed per minute; 0 for no cap
+    pub max_senders_per_minute: u32,
+    /// Messages one sender may send per minute; 0 for no cap
+    pub max_payloads_per_sender: u32,
+    /// Hold payloads with bad signatures for review
+    pub quarantine_bad_signatures: bool,
+    /// Page our web links open, e.g. `https://example.org/x`, hosting the
+    /// one in `web-link/`; `None` to share `sneakernet://` links instead
+    pub web_link_base: Option<String>,
+}
+
+impl Default for ExchangeSettings {
+    fn default() -> Self {
+        Self {
+            max_age_secs: TimestampTolerance::LIVE.max_age_secs,
+            max_future_secs: TimestampTolerance::LIVE.max_future_secs,
+            static_tag_max_age_days: 30,
+            max_senders_per_minute: 30,
+            max_payloads_per_sender: 6,
+            quarantine_bad_signatures: true,
+            web_link_base: None,
+        }
+    }
+}
+
+impl ExchangeSettings {
+    /// Tolerance for live exchanges
+    pub fn live(&self) -> TimestampTolerance {
+        TimestampTolerance {
+            max_age_secs: self.max_age_secs,
+            max_future_secs: self.max_future_secs,
+        }
+    }
+
+    /// Tolerance for payloads written ahead of time to a tag
+    pub fn static_tag(&self) -> TimestampTolerance {
+        TimestampTolerance {
+            max_age_secs: u64::from(self.static_tag_max_age_days) * 24 * 60 * 60,
+            max_future_secs: self.max_future_secs,
+        }
+    }
+}
+
+/// Inbound message filters
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FilterSettings {
+    /// Drop messages containing links from contacts we haven't reviewed
+    pub drop_urls_from_unverified: bool,
+    /// Drop messages containing any of these (case-insensitive)
+    pub blocked_keywords: Vec<String>,
+    /// Quarantine messages longer than this many characters (None = no limit)
+    pub max_message_chars: Option<u32>,
+}
+
+/// Experimental subsystem that can ship dark and be enabled per user
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FeatureFlag {
+    GossipGroups,
+    Nip17Fallback,
+    CoverTraffic,
+}
+
+/// Runtime feature flags (all off by default)
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FeatureFlags {
+    /// Group chats over iroh-gossip
+    pub gossip_groups: bool,
+    /// Fall back to NIP-17 DMs via relays when a contact is unreachable over Iroh
+    pub nip17_fallback: bool,
+    /// Send dummy traffic to obscure real messaging patterns
+    pub cover_traffic: bool,
+}
+
+impl FeatureFlags {
+    pub fn is_enabled(&self, flag: FeatureFlag) -> bool {
+        match flag {
+            FeatureFlag::GossipGroups => self.gossip_groups,
+            FeatureFlag::Nip17Fallback => self.nip17_fallback,
+            FeatureFlag::CoverTraffic => self.cover_traffic,
+        }
+    }
+
+    pub fn set(&mut self, flag: FeatureFlag, enabled: bool) {
+        match flag {
+            FeatureFlag::GossipGroups => self.gossip_groups = enabled,
+            FeatureFlag::Nip17Fallback => self.nip17_fallback = enabled,
+            FeatureFlag::CoverTraffic => self.cover_traffic = enabled,
+        }
+    }
+}
+
+/// All user settings
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub version: u32,
+    pub persistence: PersistenceSettings,
+    pub relays: RelaySettings,
+    pub notifications: NotificationSettings,
+    pub bandwidth: BandwidthSettings,
+    pub security: SecuritySettings,
+    pub filters: FilterSettings,
+    pub desktop: DesktopSettings,
+    pub profile: ProfileSettings,
+    pub exchange: ExchangeSettings,
+    pub features: FeatureFlags,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            persistence: PersistenceSettings::default(),
+            relays: RelaySettings::default(),
+            notifications: NotificationSettings::default(),
+            bandwidth: BandwidthSettings::default(),
+            security: SecuritySettings::default(),
+            filters: FilterSettings::default(),
+            desktop: DesktopSettings::default(),
+            profile: ProfileSettings::default(),
+            exchange: ExchangeSettings::default(),
+            features: FeatureFlags::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Check that every value is in range
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        if let Some(days) = self.persistence.retention_days {
+            if !(1..=3650).contains(&days) {
+                return Err(SettingsError::Invalid {
+                    field: "persistence.retentionDays",
+                    reason: "must be between 1 and 3650".to_string(),
+                });
+            }
+        }
+
+        if let Some(ref url) = self.relays.custom_relay_url {
+            if !(url.starts_with("https://") || url.starts_with("http://")) {
+                return Err(SettingsError::Invalid {
+                    field: "relays.customRelayUrl",
+                    reason: "must be an http(s) URL".to_string(),
+                });
+            }
+        }
+
+        if self.bandwidth.max_attachment_bytes > 100 * 1024 * 1024 {
+            return Err(SettingsError::Invalid {
+                field: "bandwidth.maxAttachmentBytes",
+                reason: "must be at most 100 MiB".to_string(),
+            });
+        }
+
+        if let Some(minutes) = self.security.auto_lock_minutes {
+            if !(1..=1440).contains(&minutes) {
+                return Err(SettingsError::Invalid {
+                    field: "security.autoLockMinutes",
+                    reason: "must be between 1 and 1440".to_string(),
+                });
+            }
+        }
+
+        if self.filters.blocked_keywords.len() > 500 {
+            return Err(SettingsError::Invalid {
+                field: "filters.blockedKeywords",
+                reason: "at most 500 keywords".to_string(),
+            });
+        }
+
+        if let Some(max) = self.filters.max_message_chars {
+            if !(1..=65536).contains(&max) {
+                return Err(SettingsError::Invalid {
+                    field: "filters.maxMessageChars",
+                    reason: "must be between 1 and 65536".to_string(),
+                });
+            }
+        }
+
+        if let Some(ref name) = self.profile.display_name {
+            if name.chars().count() > MAX_DISPLAY_NAME_CHARS {
+                return Err(SettingsError::Invalid {
+                    field: "profile.displayName",
+                    reason: "at most 64 characters".to_string(),
+                });
+            }
+        }
+
+        if let Some(ref url) = self.profile.avatar_url {
+            if !url.starts_with("https://") {
+                return Err(SettingsError::Invalid {
+                    field: "profile.avatarUrl",
+                    reason: "must be an https URL".to_string(),
+                });
+            }
+        }
+
+        if let Some(ref hash) = self.profile.avatar_hash {
+            if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(SettingsError::Invalid {
+                    field: "profile.avatarHash",
+                    reason: "must be a hex SHA-256 hash".to_string(),
+                });
+            }
+        }
+
+        if let Some(ref nip05) = self.profile.nip05 {
+            if !matches!(Identifier::parse(nip05), Ok(Identifier::Nip05 { .. }))
+                || nip05.len() > MAX_NIP05_LEN
+            {
+                return Err(SettingsError::Invalid {
+                    field: "profile.nip05",
+                    reason: "must be a NIP-05 identifier like name@example.com".to_string(),
+                });
+            }
+        }
+
+        if !(30..=3600).contains(&self.exchange.max_age_secs) {
+            return Err(SettingsError::Invalid {
+                field: "exchange.maxAgeSecs",
+                reason: "must be between 30 and 3600".to_string(),
+            });
+        }
+
+        if self.exchange.max_future_secs > MAX_FUTURE_SKEW_SECS {
+            return Err(SettingsError::Invalid {
+                field: "exchange.maxFutureSecs",
+                reason: format!("must be at most {}", MAX_FUTURE_SKEW_SECS),
+            });
+        }
+
+        if !(1..=MAX_STATIC_TAG_AGE_DAYS).contains(&self.exchange.static_tag_max_age_days) {
+            return Err(SettingsError::Invalid {
+                field: "exchange.staticTagMaxAgeDays",
+                reason: format!("must be between 1 and {}", MAX_STATIC_TAG_AGE_DAYS),
+            });
+        }
+
+        if self.exchange.max_senders_per_minute > MAX_INBOUND_LIMIT {
+            return Err(SettingsError::Invalid {
+                field: "exchange.maxSendersPerMinute",
+                reason: format!("must be at most {}", MAX_INBOUND_LIMIT),
+            });
+        }
+
+        if self.exchange.max_payloads_per_sender > MAX_INBOUND_LIMIT {
+            return Err(SettingsError::Invalid {
+                field: "exchange.maxPayloadsPerSender",
+                reason: format!("must be at most {}", MAX_INBOUND_LIMIT),
+            });
+        }
+
+        if let Some(ref base) = self.exchange.web_link_base {
+            if !base.starts_with("https://") || base.contains(['#', '?']) {
+                return Err(SettingsError::Invalid {
+                    field: "exchange.webLinkBase",
+                    reason: "must be an https URL without a query or fragment".to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply a partial update (JSON merge patch) and validate the result
+    pub fn apply_patch(&self, patch: &Value) -> Result<Settings, SettingsError> {
+        let mut value =
+            serde_json::to_value(self).map_err(|e| SettingsError::ParseError(e.to_string()))?;
+        merge_patch(&mut value, patch);
+
+        let mut updated: Settings =
+            serde_json::from_value(value).map_err(|e| SettingsError::ParseError(e.to_string()))?;
+        // The schema version is owned by the backend
+        updated.version = SETTINGS_VERSION;
+        updated.validate()?;
+
+        Ok(updated)
+    }
+}
+
+/// Load settings from their stored JSON, migrating older versions
+pub fn migrate(mut value: Value) -> Result<Settings, SettingsError> {
+    let mut version = value.get("version").and_then(Value::as_u64).unwrap_or(0) as u32;
+
+    if version > SETTINGS_VERSION {
+        return Err(SettingsError::UnsupportedVersion(version));
+    }
+
+    while version < SETTINGS_VERSION {
+        value = migrate_step(version, value);
+        version += 1;
+    }
+
+    let settings: Settings =
+        serde_json::from_value(value).map_err(|e| SettingsError::ParseError(e.to_string()))?;
+    settings.validate()?;
+
+    Ok(settings)
+}
+
+/// Upgrade stored settings from `version` to `version + 1`
+fn migrate_step(version: u32, mut value: Value) -> Value {
+    match version {
+        // Unversioned settings already had the v1 shape
+        0 => {
+            if let Some(obj) = value.as_object_mut() {
+                obj.insert("version".to_string(), Value::from(1));
+            }
+            value
+        }
+        _ => value,
+    }
+}
+
+/// JSON merge patch (RFC 7386), except `null` clears a value instead of removing the key
+fn merge_patch(target: &mut Value, patch: &Value) {
+    match (target.as_object_mut(), patch.as_object()) {
+        (Some(target_obj), Some(patch_obj)) => {
+            for (key, patch_value) in patch_obj {
+                if patch_value.is_null() {
+                    target_obj.insert(key.clone(), Value::Null);
+                } else {
+                    merge_patch(
+                        target_obj.entry(key.clone()).or_insert(Value::Null),
+                        patch_value,
+                    );
+                }
+            }
+        }
+        _ => *target = patch.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_defaults_are_valid() {
+        let settings = Settings::default();
+        settings.validate().unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert!(settings.relays.use_relays);
+    }
+
+    #[test]
+    fn test_apply_patch() {
+        let settings = Settings::default();
+        let updated = settings
+            .apply_patch(&json!({ "relays": { "useRelays": false }, "version": 99 }))
+            .unwrap();
+
+        assert!(!updated.relays.use_relays);
+        assert_eq!(updated.version, SETTINGS_VERSION);
+        // Untouched sections keep their values
+        assert_eq!(updated.notifications, settings.notifications);
+
+        let invalid = settings.apply_patch(&json!({ "persistence": { "retentionDays": 0 } }));
+        assert!(matches!(invalid, Err(SettingsError::Invalid { .. })));
+    }
+
+    #[test]
+    fn test_exchange_tolerance() {
+        let settings = Settings::default();
+        assert_eq!(settings.exchange.live(), TimestampTolerance::LIVE);
+        assert_eq!(
+            settings.exchange.static_tag(),
+            TimestampTolerance::STATIC_TAG
+        );
+
+        let updated = settings
+            .apply_patch(&json!({ "exchange": { "maxAgeSecs": 900, "maxFutureSecs": 30 } }))
+            .unwrap();
+        assert_eq!(updated.exchange.live().max_age_secs, 900);
+        assert_eq!(updated.exchange.static_tag().max_future_secs, 30);
+
+        let invalid = settings.apply_patch(&json!({ "exchange": { "maxFutureSecs": 86400 } }));
+        assert!(matches!(invalid, Err(SettingsError::Invalid { .. })));
+
+        // Inbound limits can be lifted but not set absurdly high
+        let uncapped = settings
+            .apply_patch(&json!({ "exchange": { "maxSendersPerMinute": 0 } }))
+            .unwrap();
+        assert_eq!(uncapped.exchange.max_senders_per_minute, 0);
+        assert!(uncapped.exchange.quarantine_bad_signatures);
+        let invalid =
+            settings.apply_patch(&json!({ "exchange": { "maxPayloadsPerSender": 100000 } }));
+        assert!(matches!(invalid, Err(SettingsError::Invalid { .. })));
+
+        // Web links only point at a page once one is configured
+        assert_eq!(settings.exchange.web_link_base, None);
+        let hosted = settings
+            .apply_patch(&json!({ "exchange": { "webLinkBase": "https://example.org/x" } }))
+            .unwrap();
+        assert_eq!(
+            hosted.exchange.web_link_base.as_deref(),
+            Some("https://example.org/x")
+        );
+        let invalid =
+            settings.apply_patch(&json!({ "exchange": { "webLinkBase": "sneakernet://x" } }));
+        assert!(matches!(invalid, Err(SettingsError::Invalid { .. })));
+    }
+
+    #[test]
+    fn test_exchange_profile() {
+        let settings = Settings::default();
+        assert_eq!(settings.profile.exchange_profile(), None);
+
+        let updated = settings
+            .apply_patch(&json!({ "profile": { "displayName": "Alice", "nip05": "alice@x.com" } }))
+            .unwrap();
+        let profile = updated.profile.exchange_profile().unwrap();
+        assert_eq!(profile.display_name.as_deref(), Some("Alice"));
+        assert_eq!(profile.nip05.as_deref(), Some("alice@x.com"));
+
+        let invalid = settings.apply_patch(&json!({ "profile": { "avatarHash": "abc" } }));
+        assert!(matches!(invalid, Err(SettingsError::Invalid { .. })));
+        let invalid = settings.apply_patch(&json!({ "profile": { "nip05": "not an address" } }));
+        assert!(matches!(invalid, Err(SettingsError::Invalid { .. })));
+    }
+
+    #[test]
+    fn test_feature_flags() {
+        let mut flags = FeatureFlags::default();
+        assert!(!flags.is_enabled(FeatureFlag::CoverTraffic));
+
+        flags.set(FeatureFlag::CoverTraffic, true);
+        assert!(flags.is_enabled(FeatureFlag::CoverTraffic));
+        assert!(!flags.is_enabled(FeatureFlag::GossipGroups));
+
+        let flag: FeatureFlag = serde_json::from_value(json!("nip17Fallback")).unwrap();
+        assert_eq!(flag, FeatureFlag::Nip17Fallback);
+    }
+
+    #[test]
+    fn test_migrate_unversioned() {
+        let settings = migrate(json!({ "persistence": { "persistMessages": true } })).unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert!(settings.persistence.persist_messages);
+
+        let future = migrate(json!({ "version": SETTINGS_VERSION + 1 }));
+        assert!(matches!(future, Err(SettingsError::UnsupportedVersion(_))));
+    }
+}
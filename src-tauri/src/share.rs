@@ -0,0 +1,248 @@
+//! Android share-sheet target
+//!
+//! When the user shares text or an image from another app, `MainActivity`
+//! hands the intent's content to `onShareText` / `onShareImage` below. The
+//! item waits here until the UI picks a contact with `share_to_contact`,
+//! which sends it through the normal chat pipeline (images as an `image`
+//! typed message). Only one share is pending at a time; a new one replaces it.
+//!
+//! The `MainActivity` override and the `ACTION_SEND` intent filter aren't in
+//! this repository yet, so the entry points are only built with the
+//! `android-share` feature; without it nothing is ever pending.
+
+use crate::message_types::{Image, KIND_IMAGE};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::{Mutex, OnceLock};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Largest image we accept, so the hex-encoded payload fits in one chat frame
+pub const MAX_SHARED_IMAGE_BYTES: usize = 24 * 1024;
+
+/// Longest text we accept from a share intent
+pub const MAX_SHARED_TEXT_CHARS: usize = 8192;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ShareError {
+    #[error("Shared content is empty")]
+    Empty,
+    #[error("Unsupported shared type: {0}")]
+    UnsupportedType(String),
+    #[error("Shared image is too large ({0} bytes, max 24 KiB)")]
+    ImageTooLarge(usize),
+}
+
+/// What was shared
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SharedContent {
+    Text(String),
+    Image { mime_type: String, data: Vec<u8> },
+}
+
+/// A share waiting for the user to pick a contact
+#[derive(Clone, Debug)]
+pub struct PendingShare {
+    pub id: String,
+    pub content: SharedContent,
+    pub received_at: u64,
+}
+
+/// What the UI shows while picking a contact (image bytes stay in the backend)
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingShareInfo {
+    pub id: String,
+    pub mime_type: String,
+    pub text: Option<String>,
+    pub size_bytes: usize,
+    pub received_at: u64,
+}
+
+impl PendingShare {
+    fn new(content: SharedContent) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            content,
+            received_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        }
+    }
+
+    pub fn text(text: &str) -> Result<Self, ShareError> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(ShareError::Empty);
+        }
+        let text: String = text.chars().take(MAX_SHARED_TEXT_CHARS).collect();
+        Ok(Self::new(SharedContent::Text(text)))
+    }
+
+    pub fn image(mime_type: &str, data: Vec<u8>) -> Result<Self, ShareError> {
+        if !mime_type.starts_with("image/") {
+            return Err(ShareError::UnsupportedType(mime_type.to_string()));
+        }
+        if data.is_empty() {
+            return Err(ShareError::Empty);
+        }
+        if data.len() > MAX_SHARED_IMAGE_BYTES {
+            return Err(ShareError::ImageTooLarge(data.len()));
+        }
+        Ok(Self::new(SharedContent::Image {
+            mime_type: mime_type.to_string(),
+            data,
+        }))
+    }
+
+    pub fn info(&self) -> PendingShareInfo {
+        let (mime_type, text, size_bytes) = match &self.content {
+            SharedContent::Text(text) => ("text/plain".to_string(), Some(text.clone()), text.len()),
+            SharedContent::Image { mime_type, data } => (mime_type.clone(), None, data.len()),
+        };
+
+        PendingShareInfo {
+            id: self.id.clone(),
+            mime_type,
+            text,
+            size_bytes,
+            received_at: self.received_at,
+        }
+    }
+
+    /// Typed payload for an image share, with an optional caption
+    pub fn image_payload(&self, caption: Option<&str>) -> Option<Value> {
+        match &self.content {
+            SharedContent::Image { mime_type, data } => serde_json::to_value(Image {
+                mime_type: mime_type.clone(),
+                data: hex::encode(data),
+                caption: caption
+                    .map(str::trim)
+                    .filter(|c| !c.is_empty())
+                    .map(String::from),
+            })
+            .ok(),
+            SharedContent::Text(_) => None,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self.content {
+            SharedContent::Text(_) => crate::message_types::KIND_TEXT,
+            SharedContent::Image { .. } => KIND_IMAGE,
+        }
+    }
+}
+
+/// The share waiting for a contact, filled in from the Android activity
+fn pending_share() -> &'static Mutex<Option<PendingShare>> {
+    static PENDING: OnceLock<Mutex<Option<PendingShare>>> = OnceLock::new();
+    PENDING.get_or_init(|| Mutex::new(None))
+}
+
+/// Replace the pending share
+pub fn set_pending(share: PendingShare) {
+    *pending_share().lock().unwrap() = Some(share);
+}
+
+/// The pending share, if any
+pub fn pending() -> Option<PendingShare> {
+    pending_share().lock().unwrap().clone()
+}
+
+/// Clear the pending share if it is still `id` (a newer share is kept)
+pub fn clear(id: &str) -> bool {
+    let mut pending = pending_share().lock().unwrap();
+    if pending.as_ref().is_some_and(|s| s.id == id) {
+        *pending = None;
+        true
+    } else {
+        false
+    }
+}
+
+/// JNI entry point for `MainActivity.onShareText(String)`
+#[cfg(all(target_os = "android", feature = "android-share"))]
+#[no_mangle]
+pub extern "system" fn Java_net_sneaker_app_MainActivity_onShareText<'local>(
+    mut env: jni::JNIEnv<'local>,
+    _class: jni::objects::JClass<'local>,
+    text: jni::objects::JString<'local>,
+) {
+    let text: String = match env.get_string(&text) {
+        Ok(text) => text.into(),
+        Err(_) => return,
+    };
+
+    if let Ok(share) = PendingShare::text(&text) {
+        set_pending(share);
+    }
+}
+
+/// JNI entry point for `MainActivity.onShareImage(String, ByteArray)`
+#[cfg(all(target_os = "android", feature = "android-share"))]
+#[no_mangle]
+pub extern "system" fn Java_net_sneaker_app_MainActivity_onShareImage<'local>(
+    mut env: jni::JNIEnv<'local>,
+    _class: jni::objects::JClass<'local>,
+    mime_type: jni::objects::JString<'local>,
+    data: jni::objects::JByteArray<'local>,
+) {
+    let mime_type: String = match env.get_string(&mime_type) {
+        Ok(mime_type) => mime_type.into(),
+        Err(_) => return,
+    };
+    let data = env.convert_byte_array(&data).unwrap_or_default();
+
+    if let Ok(share) = PendingShare::image(&mime_type, data) {
+        set_pending(share);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_types::MessageTypeRegistry;
+
+    #[test]
+    fn test_share_validation() {
+        assert_eq!(PendingShare::text("   ").unwrap_err(), ShareError::Empty);
+        assert!(matches!(
+            PendingShare::image("application/pdf", vec![1]),
+            Err(ShareError::UnsupportedType(_))
+        ));
+        assert_eq!(
+            PendingShare::image("image/png", vec![0; MAX_SHARED_IMAGE_BYTES + 1]).unwrap_err(),
+            ShareError::ImageTooLarge(MAX_SHARED_IMAGE_BYTES + 1)
+        );
+
+        let text = PendingShare::text(" https://example.com ").unwrap();
+        assert_eq!(text.info().text.as_deref(), Some("https://example.com"));
+        assert!(text.image_payload(None).is_none());
+    }
+
+    #[test]
+    fn test_image_payload_is_valid_message() {
+        let share = PendingShare::image("image/jpeg", vec![0xFF, 0xD8, 0xFF]).unwrap();
+        let payload = share.image_payload(Some("Slides")).unwrap();
+
+        let fallback = MessageTypeRegistry::default()
+            .prepare(share.kind(), &payload)
+            .unwrap();
+        assert_eq!(fallback, "🖼 Image: Slides");
+    }
+
+    #[test]
+    fn test_clear_keeps_newer_share() {
+        let first = PendingShare::text("first").unwrap();
+        let first_id = first.id.clone();
+        set_pending(first);
+        set_pending(PendingShare::text("second").unwrap());
+
+        assert!(!clear(&first_id));
+        let second = pending().unwrap();
+        assert!(clear(&second.id));
+        assert!(pending().is_none());
+    }
+}
@@ -0,0 +1,266 @@
+//! Shared application state
+//!
+//! Everything here is reachable from async commands and background tasks.
+//! The Iroh node and chat manager sit behind `tokio::sync::RwLock` because
+//! they are held across network awaits. Keys, contacts and settings are
+//! cached in `tokio::sync::watch` channels: reads are a cheap synchronous
+//! borrow from any context (keys are only ever borrowed, through
+//! `with_keys`, so the secret is never copied), writes go through the store
+//! helpers, which update the cache (so nothing rereads the store per
+//! command), and tasks can `subscribe` to react to changes. Contacts are persisted in their own
+//! SQLite database, opened on first use.
+//!
+//! The remaining small pieces of exchange bookkeeping use `std::sync::Mutex`
+//! and are only ever locked for a few statements, never across an await.
+
+use crate::audio::AudioDecoder;
+use crate::beacon::Beacon;
+use crate::chat::SharedChatManager;
+use crate::conference::Conference;
+use crate::contact_store::ContactStore;
+use crate::exchange::Contact;
+use crate::intake::{InboundLimiter, PayloadQuarantine};
+use crate::iroh_node::{IrohConfig, IrohNode, SharedIrohNode};
+use crate::keys::{ExportConfirmation, StoredKeys};
+use crate::migration::MigrationAssembler;
+use crate::multipart::FountainDecoder;
+use crate::nfc::NfcExchangeState;
+use crate::nip46::RemoteSigner;
+use crate::plugins::PluginRegistry;
+use crate::profile::{ProfileId, ProfileKey};
+use crate::queue::ExchangeQueue;
+use crate::roster::Roster;
+use crate::settings::Settings;
+use crate::stats::ExchangeTimer;
+use crate::trace::ConnectionTrace;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{watch, Notify, RwLock};
+
+/// A value loaded lazily from the store and kept until the next write
+pub struct StoreCache<T> {
+    value: watch::Sender<Option<T>>,
+}
+
+impl<T> Default for StoreCache<T> {
+    fn default() -> Self {
+        Self {
+            value: watch::Sender::new(None),
+        }
+    }
+}
+
+impl<T> StoreCache<T> {
+    /// Compute something from the cached value without copying it, if
+    /// loaded. `f` runs under the cache's read lock, so it must not write to
+    /// this cache.
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.value.borrow().as_ref().map(f)
+    }
+
+    /// Whether the value is loaded
+    pub fn is_loaded(&self) -> bool {
+        self.value.borrow().is_some()
+    }
+
+    /// Replace the cached value after it was written to the store
+    pub fn set(&self, value: T) {
+        self.value.send_replace(Some(value));
+    }
+
+    /// Drop the cached value so the next read goes to the store
+    pub fn invalidate(&self) {
+        self.value.send_replace(None);
+    }
+
+    /// Watch for changes
+    pub fn subscribe(&self) -> watch::Receiver<Option<T>> {
+        self.value.subscribe()
+    }
+}
+
+impl<T: Clone> StoreCache<T> {
+    /// The cached value, if loaded
+    pub fn get(&self) -> Option<T> {
+        self.value.borrow().clone()
+    }
+
+    /// The cached value, loading it with `load` on first use
+    pub fn get_or_load(&self, load: impl FnOnce() -> T) -> T {
+        if let Some(value) = self.get() {
+            return value;
+        }
+
+        let loaded = load();
+        // Keep a value written while we were loading
+        self.value.send_if_modified(|current| {
+            if current.is_none() {
+                *current = Some(loaded.clone());
+                true
+            } else {
+                false
+            }
+        });
+        self.get().unwrap_or(loaded)
+    }
+}
+
+/// Application state
+pub struct AppState {
+    /// Our keys (loaded from store on first use)
+    pub keys: StoreCache<StoredKeys>,
+    /// Contacts (loaded from store on first use)
+    pub contacts: StoreCache<Vec<Contact>>,
+    /// Contact database backing `contacts`
+    pub contact_db: Mutex<Option<ContactStore>>,
+    /// Settings (loaded and migrated from store on first use)
+    pub settings: StoreCache<Settings>,
+    /// Iroh node for p2p networking
+    pub iroh_node: SharedIrohNode,
+    /// Chat manager for messaging
+    pub chat_manager: SharedChatManager,
+    /// In-progress NFC exchange, kept so a failed tap can be resumed
+    pub nfc_exchange: Mutex<NfcExchangeState>,
+    /// Start time of the exchange in progress, for local statistics
+    pub exchange_timer: Mutex<Option<ExchangeTimer>>,
+    /// Conference beacon, when active
+    pub beacon: Mutex<Option<Beacon>>,
+    /// Conference mode window and the attendees waiting for approval
+    pub conference: Mutex<Option<Conference>>,
+    /// Exchanges that have heard from a peer but not completed yet, keyed by pubkey
+    pub exchange_queue: Mutex<ExchangeQueue>,
+    /// Inbound exchange payloads counted against the configured limits
+    pub inbound_limiter: Mutex<InboundLimiter>,
+    /// Inbound exchange payloads whose signatures failed
+    pub payload_quarantine: Mutex<PayloadQuarantine>,
+    /// Nonce of the initial exchange message we made last, for the short
+    /// authentication string once someone answers it
+    pub initial_nonce: Mutex<Option<String>>,
+    /// Contact (by ID) the next exchange re-verifies, and since when
+    pub reverify: Mutex<Option<(String, Instant)>>,
+    /// Connection trace being captured, if any
+    pub connection_trace: Mutex<Option<ConnectionTrace>>,
+    /// Pending confirmation for exporting the secret key
+    pub export_confirmation: Mutex<Option<ExportConfirmation>>,
+    /// Profile whose store and contact database are in use
+    pub profile: Mutex<ProfileId>,
+    /// Key the active profile's keys are sealed with, while unlocked by PIN
+    pub profile_key: Mutex<Option<ProfileKey>>,
+    /// NIP-46 signer holding our secret key, when one is paired
+    pub remote_signer: Mutex<Option<Arc<RemoteSigner>>>,
+    /// Migration QR codes scanned so far
+    pub migration: Mutex<MigrationAssembler>,
+    /// Frames of an animated exchange QR code scanned so far
+    pub qr_frames: Mutex<FountainDecoder>,
+    /// Group exchange roster we're on, while it goes round the table
+    pub roster: Mutex<Option<Roster>>,
+    /// Frames of an animated roster code scanned so far
+    pub roster_frames: Mutex<FountainDecoder>,
+    /// Listener for an audio broadcast, while one is running
+    pub audio_decoder: Mutex<Option<AudioDecoder>>,
+    /// Exchange links the app was opened with, until the frontend takes them
+    pub pending_uris: Mutex<Vec<String>>,
+    /// Wakes a local-network host or guest waiting on the other device
+    pub lan_cancel: Notify,
+    /// Wakes a conference host waiting for its next guest
+    pub conference_cancel: Notify,
+    /// Backend extensions registered at startup
+    pub plugins: Arc<PluginRegistry>,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self {
+            keys: StoreCache::default(),
+            contacts: StoreCache::default(),
+            contact_db: Mutex::new(None),
+            settings: StoreCache::default(),
+            iroh_node: Arc::new(RwLock::new(IrohNode::new(IrohConfig::default()))),
+            chat_manager: Arc::new(RwLock::new(None)),
+            nfc_exchange: Mutex::new(NfcExchangeState::default()),
+            exchange_timer: Mutex::new(None),
+            beacon: Mutex::new(None),
+            conference: Mutex::new(None),
+            exchange_queue: Mutex::new(ExchangeQueue::default()),
+            inbound_limiter: Mutex::new(InboundLimiter::default()),
+            payload_quarantine: Mutex::new(PayloadQuarantine::default()),
+            initial_nonce: Mutex::new(None),
+            reverify: Mutex::new(None),
+            connection_trace: Mutex::new(None),
+            export_confirmation: Mutex::new(None),
+            profile: Mutex::new(ProfileId::PRIMARY),
+            profile_key: Mutex::new(None),
+            remote_signer: Mutex::new(None),
+            migration: Mutex::new(MigrationAssembler::default()),
+            qr_frames: Mutex::new(FountainDecoder::default()),
+            roster: Mutex::new(None),
+            roster_frames: Mutex::new(FountainDecoder::default()),
+            audio_decoder: Mutex::new(None),
+            pending_uris: Mutex::new(Vec::new()),
+            lan_cancel: Notify::new(),
+            conference_cancel: Notify::new(),
+            plugins: Arc::new(PluginRegistry::default()),
+        }
+    }
+}
+
+impl AppState {
+    /// App state with backend plugins registered
+    pub fn with_plugins(plugins: PluginRegistry) -> Self {
+        Self {
+            plugins: Arc::new(plugins),
+            ..Self::default()
+        }
+    }
+
+    /// Compute something from our keys without copying them, if loaded.
+    /// Nothing that writes the keys may run inside `f`.
+    pub fn with_keys<R>(&self, f: impl FnOnce(&StoredKeys) -> R) -> Option<R> {
+        self.keys.with(f)
+    }
+
+    /// Our hex public key, if keys are loaded
+    pub fn our_pubkey(&self) -> Option<String> {
+        self.with_keys(|k| k.public_key_hex.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_cache() {
+        let cache: StoreCache<Vec<u32>> = StoreCache::default();
+        let mut changes = cache.subscribe();
+
+        let mut loads = 0;
+        assert_eq!(
+            cache.get_or_load(|| {
+                loads += 1;
+                vec![1]
+            }),
+            vec![1]
+        );
+        assert_eq!(
+            cache.get_or_load(|| {
+                loads += 1;
+                vec![2]
+            }),
+            vec![1]
+        );
+        assert_eq!(loads, 1);
+        assert!(changes.has_changed().unwrap());
+        changes.mark_unchanged();
+
+        cache.set(vec![3]);
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(cache.get(), Some(vec![3]));
+        assert_eq!(cache.with(|value| value[0]), Some(3));
+
+        cache.invalidate();
+        assert!(!cache.is_loaded());
+        assert_eq!(cache.with(|value| value[0]), None);
+        assert_eq!(cache.get_or_load(|| vec![4]), vec![4]);
+    }
+}
@@ -0,0 +1,205 @@
+//! Local exchange timing and reliability statistics
+//!
+//! Nothing here leaves the device. The numbers answer a single question:
+//! on this phone, which transport is the more reliable way to exchange keys?
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Exchanges idle longer than this are treated as abandoned when a new one starts
+pub const STALE_EXCHANGE_SECS: u64 = 600;
+
+/// Transport an exchange ran over
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Nfc,
+    Qr,
+    Lan,
+    Audio,
+    /// A `sneakernet://` link sent through another app
+    Link,
+    /// Encrypted DMs through Nostr relays, for people who can't meet
+    Nostr,
+}
+
+/// Counters for a single transport
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TransportStats {
+    /// Exchanges started
+    pub started: u32,
+    /// Exchanges that produced a contact
+    pub completed: u32,
+    /// Sum of completed exchange durations
+    pub total_duration_ms: u64,
+    pub fastest_ms: Option<u64>,
+    pub slowest_ms: Option<u64>,
+    /// Failure counts keyed by `stage:reason`
+    pub failures: BTreeMap<String, u32>,
+}
+
+impl TransportStats {
+    /// Average duration of completed exchanges
+    pub fn average_ms(&self) -> Option<u64> {
+        (self.completed > 0).then(|| self.total_duration_ms / self.completed as u64)
+    }
+
+    /// Fraction of started exchanges that completed
+    pub fn success_rate(&self) -> Option<f64> {
+        (self.started > 0).then(|| self.completed as f64 / self.started as f64)
+    }
+}
+
+/// Persisted statistics for all transports
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ExchangeStats {
+    pub nfc: TransportStats,
+    pub qr: TransportStats,
+    #[serde(default)]
+    pub lan: TransportStats,
+    #[serde(default)]
+    pub audio: TransportStats,
+    #[serde(default)]
+    pub link: TransportStats,
+    #[serde(default)]
+    pub nostr: TransportStats,
+}
+
+impl ExchangeStats {
+    pub fn transport(&self, transport: Transport) -> &TransportStats {
+        match transport {
+            Transport::Nfc => &self.nfc,
+            Transport::Qr => &self.qr,
+            Transport::Lan => &self.lan,
+            Transport::Audio => &self.audio,
+            Transport::Link => &self.link,
+            Transport::Nostr => &self.nostr,
+        }
+    }
+
+    fn transport_mut(&mut self, transport: Transport) -> &mut TransportStats {
+        match transport {
+            Transport::Nfc => &mut self.nfc,
+            Transport::Qr => &mut self.qr,
+            Transport::Lan => &mut self.lan,
+            Transport::Audio => &mut self.audio,
+            Transport::Link => &mut self.link,
+            Transport::Nostr => &mut self.nostr,
+        }
+    }
+
+    pub fn record_start(&mut self, transport: Transport) {
+        self.transport_mut(transport).started += 1;
+    }
+
+    pub fn record_success(&mut self, transport: Transport, duration: Duration) {
+        let ms = duration.as_millis() as u64;
+        let stats = self.transport_mut(transport);
+
+        stats.completed += 1;
+        stats.total_duration_ms += ms;
+        stats.fastest_ms = Some(stats.fastest_ms.map_or(ms, |f| f.min(ms)));
+        stats.slowest_ms = Some(stats.slowest_ms.map_or(ms, |s| s.max(ms)));
+    }
+
+    pub fn record_failure(&mut self, transport: Transport, stage: &str, reason: &str) {
+        *self
+            .transport_mut(transport)
+            .failures
+            .entry(format!("{}:{}", stage, reason))
+            .or_insert(0) += 1;
+    }
+}
+
+/// Summary returned by `get_exchange_stats`
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExchangeStatsSummary {
+    pub stats: ExchangeStats,
+    pub nfc_average_ms: Option<u64>,
+    pub qr_average_ms: Option<u64>,
+    pub lan_average_ms: Option<u64>,
+    pub audio_average_ms: Option<u64>,
+    pub link_average_ms: Option<u64>,
+    pub nostr_average_ms: Option<u64>,
+    pub nfc_success_rate: Option<f64>,
+    pub qr_success_rate: Option<f64>,
+    pub lan_success_rate: Option<f64>,
+    pub audio_success_rate: Option<f64>,
+    pub link_success_rate: Option<f64>,
+    pub nostr_success_rate: Option<f64>,
+}
+
+impl From<ExchangeStats> for ExchangeStatsSummary {
+    fn from(stats: ExchangeStats) -> Self {
+        Self {
+            nfc_average_ms: stats.nfc.average_ms(),
+            qr_average_ms: stats.qr.average_ms(),
+            lan_average_ms: stats.lan.average_ms(),
+            audio_average_ms: stats.audio.average_ms(),
+            link_average_ms: stats.link.average_ms(),
+            nostr_average_ms: stats.nostr.average_ms(),
+            nfc_success_rate: stats.nfc.success_rate(),
+            qr_success_rate: stats.qr.success_rate(),
+            lan_success_rate: stats.lan.success_rate(),
+            audio_success_rate: stats.audio.success_rate(),
+            link_success_rate: stats.link.success_rate(),
+            nostr_success_rate: stats.nostr.success_rate(),
+            stats,
+        }
+    }
+}
+
+/// Start time of the exchange currently in progress
+#[derive(Clone, Copy, Debug)]
+pub struct ExchangeTimer {
+    pub transport: Transport,
+    pub started: Instant,
+}
+
+impl ExchangeTimer {
+    pub fn start(transport: Transport) -> Self {
+        Self {
+            transport,
+            started: Instant::now(),
+        }
+    }
+
+    /// Whether this timer belongs to an abandoned exchange
+    pub fn is_stale(&self) -> bool {
+        self.started.elapsed() > Duration::from_secs(STALE_EXCHANGE_SECS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_success() {
+        let mut stats = ExchangeStats::default();
+        stats.record_start(Transport::Nfc);
+        stats.record_start(Transport::Nfc);
+        stats.record_success(Transport::Nfc, Duration::from_millis(1000));
+        stats.record_success(Transport::Nfc, Duration::from_millis(3000));
+
+        assert_eq!(stats.nfc.average_ms(), Some(2000));
+        assert_eq!(stats.nfc.fastest_ms, Some(1000));
+        assert_eq!(stats.nfc.slowest_ms, Some(3000));
+        assert_eq!(stats.nfc.success_rate(), Some(1.0));
+        assert_eq!(stats.qr.average_ms(), None);
+    }
+
+    #[test]
+    fn test_record_failure() {
+        let mut stats = ExchangeStats::default();
+        stats.record_failure(Transport::Qr, "scan", "expired");
+        stats.record_failure(Transport::Qr, "scan", "expired");
+
+        assert_eq!(stats.qr.failures.get("scan:expired"), Some(&2));
+        assert!(stats.nfc.failures.is_empty());
+    }
+}
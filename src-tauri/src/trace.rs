@@ -0,0 +1,209 @@
+//! Connection trace capture for debugging connectivity
+//!
+//! While a trace is running for a contact, the connect path, the path
+//! watcher and the chat stream code record what happens to that contact's
+//! connection: relay and discovery state, the connect attempt, holepunching
+//! and path changes, streams opened and accepted, and the close reason. The
+//! finished trace is returned as JSON-serializable data the user can export
+//! and attach to a bug report.
+//!
+//! Only one trace runs at a time; starting another replaces it.
+
+use crate::iroh_node::PathType;
+use crate::state::AppState;
+use futures_lite::StreamExt;
+use serde::Serialize;
+use std::time::Instant;
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+/// Events kept per trace; later ones are counted but dropped
+pub const MAX_TRACE_EVENTS: usize = 1000;
+
+/// What happened
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TraceEventKind {
+    TraceStarted,
+    /// Relay our endpoint is registered with
+    HomeRelay,
+    /// Addresses known for the peer before connecting
+    DiscoveryLookup,
+    ConnectStarted,
+    Connected,
+    ConnectFailed,
+    /// Peer proved it holds the contact's key
+    IdentityBound,
+    /// Peer failed the identity challenge and was disconnected
+    IdentityRejected,
+    PathChanged,
+    /// Direct path being negotiated while traffic goes over the relay
+    HolepunchAttempt,
+    StreamOpened,
+    StreamAccepted,
+    ConnectionClosed,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceEvent {
+    /// Milliseconds since the trace started
+    pub at_ms: u64,
+    pub kind: TraceEventKind,
+    pub detail: Option<String>,
+}
+
+/// Timeline of one contact's connection
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionTrace {
+    pub id: String,
+    pub contact_pubkey: String,
+    /// Unix timestamps
+    pub started_at: u64,
+    pub stopped_at: Option<u64>,
+    pub events: Vec<TraceEvent>,
+    /// Events past `MAX_TRACE_EVENTS`
+    pub dropped_events: u32,
+    #[serde(skip)]
+    started: Instant,
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+impl ConnectionTrace {
+    pub fn new(contact_pubkey: &str) -> Self {
+        let mut trace = Self {
+            id: Uuid::new_v4().to_string(),
+            contact_pubkey: contact_pubkey.to_string(),
+            started_at: now_secs(),
+            stopped_at: None,
+            events: Vec::new(),
+            dropped_events: 0,
+            started: Instant::now(),
+        };
+        trace.record(TraceEventKind::TraceStarted, None);
+        trace
+    }
+
+    pub fn record(&mut self, kind: TraceEventKind, detail: Option<String>) {
+        if self.events.len() >= MAX_TRACE_EVENTS {
+            self.dropped_events += 1;
+            return;
+        }
+
+        self.events.push(TraceEvent {
+            at_ms: self.started.elapsed().as_millis() as u64,
+            kind,
+            detail,
+        });
+    }
+
+    pub fn finish(mut self) -> Self {
+        self.stopped_at = Some(now_secs());
+        self
+    }
+}
+
+/// Record an event if a trace is running for `contact_pubkey`
+pub fn record(
+    state: &AppState,
+    contact_pubkey: &str,
+    kind: TraceEventKind,
+    detail: Option<String>,
+) {
+    if let Some(trace) = state.connection_trace.lock().unwrap().as_mut() {
+        if trace.contact_pubkey == contact_pubkey {
+            trace.record(kind, detail);
+        }
+    }
+}
+
+/// Whether the trace with `trace_id` is still running
+fn is_running(app: &AppHandle, trace_id: &str) -> bool {
+    app.state::<AppState>()
+        .connection_trace
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|t| t.id == trace_id)
+}
+
+/// Record path changes to the peer on `connection` while `trace_id` runs
+pub fn spawn_path_watcher(app: AppHandle, trace_id: String, contact_pubkey: String) {
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<AppState>();
+        let stream = {
+            let node = state.iroh_node.read().await;
+            node.get_connection(&contact_pubkey)
+                .and_then(|connection| node.path_changes(connection))
+        };
+        let Some(mut stream) = stream else {
+            return;
+        };
+
+        while let Some(path) = stream.next().await {
+            if !is_running(&app, &trace_id) {
+                break;
+            }
+            if path == PathType::Mixed {
+                record(
+                    &state,
+                    &contact_pubkey,
+                    TraceEventKind::HolepunchAttempt,
+                    None,
+                );
+            }
+            record(
+                &state,
+                &contact_pubkey,
+                TraceEventKind::PathChanged,
+                Some(format!("{:?}", path).to_lowercase()),
+            );
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_records_in_order() {
+        let mut trace = ConnectionTrace::new("pk");
+        trace.record(TraceEventKind::ConnectStarted, Some("node".to_string()));
+        trace.record(TraceEventKind::Connected, None);
+
+        let trace = trace.finish();
+        let kinds: Vec<_> = trace.events.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TraceEventKind::TraceStarted,
+                TraceEventKind::ConnectStarted,
+                TraceEventKind::Connected
+            ]
+        );
+        assert!(trace.stopped_at.is_some());
+
+        let json = serde_json::to_value(&trace).unwrap();
+        assert_eq!(json["events"][1]["kind"], "connectStarted");
+        assert_eq!(json["events"][1]["detail"], "node");
+    }
+
+    #[test]
+    fn test_trace_caps_events() {
+        let mut trace = ConnectionTrace::new("pk");
+        for _ in 0..MAX_TRACE_EVENTS + 5 {
+            trace.record(TraceEventKind::StreamAccepted, None);
+        }
+
+        assert_eq!(trace.events.len(), MAX_TRACE_EVENTS);
+        assert_eq!(trace.dropped_events, 6);
+    }
+}
@@ -0,0 +1,79 @@
+//! Desktop system tray
+//!
+//! With `desktop.runInTray` set, closing the main window only hides it; the
+//! Iroh node and background listeners keep running and the tray icon brings
+//! the window back. Quitting from the tray menu stops the node and exits.
+
+use crate::commands::load_settings_from_store;
+use crate::listener::MAIN_WINDOW;
+use crate::state::AppState;
+use tauri::menu::{Menu, MenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Manager, Window, WindowEvent};
+
+const MENU_OPEN: &str = "open";
+const MENU_QUIT: &str = "quit";
+
+/// Create the tray icon and its menu
+pub fn setup(app: &AppHandle) -> tauri::Result<()> {
+    let open = MenuItem::with_id(app, MENU_OPEN, "Open SneakerNet", true, None::<&str>)?;
+    let quit = MenuItem::with_id(app, MENU_QUIT, "Quit", true, None::<&str>)?;
+    let menu = Menu::with_items(app, &[&open, &quit])?;
+
+    let mut builder = TrayIconBuilder::with_id("main")
+        .tooltip("SneakerNet")
+        .menu(&menu)
+        .on_menu_event(|app, event| match event.id.as_ref() {
+            MENU_OPEN => show_main_window(app),
+            MENU_QUIT => quit(app),
+            _ => {}
+        })
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                show_main_window(tray.app_handle());
+            }
+        });
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    builder.build(app)?;
+
+    Ok(())
+}
+
+/// Bring the main window back and focus it
+pub fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window(MAIN_WINDOW) {
+        let _ = window.show();
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+/// Hide instead of closing when tray mode is on
+pub fn on_window_event(window: &Window, event: &WindowEvent) {
+    if let WindowEvent::CloseRequested { api, .. } = event {
+        if window.label() == MAIN_WINDOW
+            && load_settings_from_store(window.app_handle())
+                .desktop
+                .run_in_tray
+        {
+            api.prevent_close();
+            let _ = window.hide();
+        }
+    }
+}
+
+/// Stop the node cleanly, then exit
+fn quit(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let _ = app.state::<AppState>().iroh_node.write().await.stop().await;
+        app.exit(0);
+    });
+}
@@ -0,0 +1,171 @@
+//! Local web-of-trust scoring
+//!
+//! Scores are computed on demand from data already on the device and never
+//! shared. A contact met in person starts at medium trust; contacts saved
+//! without confirmation start low. Mutual contacts (other contacts who share a group
+//! with them) and the age of the relationship add to the score.
+
+use crate::exchange::Contact;
+use crate::groups::Group;
+use serde::{Deserialize, Serialize};
+
+/// Points for an exchange the user confirmed in person
+const IN_PERSON_POINTS: u32 = 50;
+/// Points for a contact saved automatically and not yet reviewed
+const UNREVIEWED_POINTS: u32 = 20;
+/// Points per mutual contact, and the cap on their total
+const MUTUAL_CONTACT_POINTS: u32 = 5;
+const MAX_MUTUAL_POINTS: u32 = 30;
+/// Points per 30 days known, and the cap on their total
+const AGE_POINTS_PER_MONTH: u32 = 1;
+const MAX_AGE_POINTS: u32 = 10;
+
+/// Coarse trust bucket for UI warnings
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TrustLevel {
+    Low,
+    Medium,
+    High,
+}
+
+impl TrustLevel {
+    fn from_score(score: u32) -> Self {
+        match score {
+            0..=39 => TrustLevel::Low,
+            40..=69 => TrustLevel::Medium,
+            _ => TrustLevel::High,
+        }
+    }
+}
+
+/// One input to a trust score
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustFactor {
+    pub name: &'static str,
+    pub points: u32,
+}
+
+/// Trust score for a contact, with the factors that produced it
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactTrust {
+    pub contact_id: String,
+    /// 0-100
+    pub score: u32,
+    pub level: TrustLevel,
+    /// Pubkeys of our other contacts who share a group with this one
+    pub mutual_contacts: Vec<String>,
+    pub factors: Vec<TrustFactor>,
+}
+
+/// Other contacts who are in at least one group with `contact`
+fn mutual_contacts(contact: &Contact, contacts: &[Contact], groups: &[Group]) -> Vec<String> {
+    let shared: Vec<&Group> = groups
+        .iter()
+        .filter(|g| g.is_member(&contact.nostr_pubkey))
+        .collect();
+
+    contacts
+        .iter()
+        .filter(|c| c.nostr_pubkey != contact.nostr_pubkey)
+        .filter(|c| shared.iter().any(|g| g.is_member(&c.nostr_pubkey)))
+        .map(|c| c.nostr_pubkey.clone())
+        .collect()
+}
+
+/// Score `contact` against the rest of our contacts and groups
+pub fn score_contact(
+    contact: &Contact,
+    contacts: &[Contact],
+    groups: &[Group],
+    now: u64,
+) -> ContactTrust {
+    let mut factors = Vec::new();
+
+    if contact.needs_review {
+        factors.push(TrustFactor {
+            name: "unreviewed",
+            points: UNREVIEWED_POINTS,
+        });
+    } else {
+        factors.push(TrustFactor {
+            name: "inPerson",
+            points: IN_PERSON_POINTS,
+        });
+    }
+
+    let mutual = mutual_contacts(contact, contacts, groups);
+    if !mutual.is_empty() {
+        factors.push(TrustFactor {
+            name: "mutualContacts",
+            points: (mutual.len() as u32 * MUTUAL_CONTACT_POINTS).min(MAX_MUTUAL_POINTS),
+        });
+    }
+
+    let months = (now.saturating_sub(contact.exchanged_at) / (30 * 24 * 60 * 60)) as u32;
+    if months > 0 {
+        factors.push(TrustFactor {
+            name: "age",
+            points: (months * AGE_POINTS_PER_MONTH).min(MAX_AGE_POINTS),
+        });
+    }
+
+    let score = factors.iter().map(|f| f.points).sum::<u32>().min(100);
+
+    ContactTrust {
+        contact_id: contact.id.clone(),
+        score,
+        level: TrustLevel::from_score(score),
+        mutual_contacts: mutual,
+        factors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::Keys;
+
+    #[test]
+    fn test_in_person_outranks_unreviewed() {
+        let met = Contact::new("aa", "endpoint");
+        let mut auto = Contact::new("bb", "endpoint");
+        auto.needs_review = true;
+        let contacts = vec![met.clone(), auto.clone()];
+
+        let met_trust = score_contact(&met, &contacts, &[], met.exchanged_at);
+        let auto_trust = score_contact(&auto, &contacts, &[], auto.exchanged_at);
+
+        assert_eq!(met_trust.level, TrustLevel::Medium);
+        assert_eq!(auto_trust.level, TrustLevel::Low);
+        assert!(met_trust.score > auto_trust.score);
+    }
+
+    #[test]
+    fn test_mutual_contacts_and_age() {
+        let admin = Keys::generate();
+        let alice = Contact::new(&admin.public_key().to_hex(), "endpoint");
+        let bob = Contact::new("bb", "endpoint");
+        let carol = Contact::new("cc", "endpoint");
+        let contacts = vec![alice.clone(), bob.clone(), carol];
+
+        // Alice created a group we're both in with Bob
+        let mut group = Group::new(&admin, "meetup");
+        group.members.push(crate::groups::GroupMember {
+            pubkey: "bb".to_string(),
+            admin: false,
+        });
+
+        let year_later = alice.exchanged_at + 365 * 24 * 60 * 60;
+        let trust = score_contact(&alice, &contacts, &[group], year_later);
+
+        assert_eq!(trust.mutual_contacts, vec!["bb".to_string()]);
+        assert_eq!(
+            trust.score,
+            IN_PERSON_POINTS + MUTUAL_CONTACT_POINTS + MAX_AGE_POINTS
+        );
+        assert_eq!(trust.level, TrustLevel::Medium);
+    }
+}
@@ -0,0 +1,100 @@
+//! Typing indicators over QUIC datagrams
+//!
+//! A typing notification is a 9-byte datagram (magic + 1 if typing, 0 if
+//! stopped) on a bound chat connection. Datagrams are unreliable and never
+//! retransmitted, which suits a hint that is stale a moment later anyway:
+//! the sender repeats it every `TYPING_REFRESH_SECS` while the user types,
+//! and the receiver treats an indicator it hasn't heard about for
+//! `TYPING_EXPIRY_SECS` as stopped, so a lost "stopped" doesn't linger.
+
+use iroh_quinn::Connection;
+use serde::Serialize;
+use thiserror::Error;
+
+/// How often the UI should repeat a typing notification while typing
+pub const TYPING_REFRESH_SECS: u64 = 3;
+
+/// How long a typing notification holds without a refresh
+pub const TYPING_EXPIRY_SECS: u64 = 6;
+
+const TYPING_MAGIC: &[u8; 8] = b"SNKTYPE1";
+const FRAME_LEN: usize = 9;
+
+#[derive(Error, Debug)]
+pub enum TypingError {
+    #[error("Failed to send typing notification: {0}")]
+    Send(String),
+}
+
+/// Payload of the `chat://typing` event
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TypingEvent {
+    pub contact_pubkey: String,
+    pub typing: bool,
+    /// Seconds until the indicator should be dropped without a refresh
+    pub expires_in_secs: u64,
+}
+
+impl TypingEvent {
+    pub fn new(contact_pubkey: &str, typing: bool) -> Self {
+        Self {
+            contact_pubkey: contact_pubkey.to_string(),
+            typing,
+            expires_in_secs: TYPING_EXPIRY_SECS,
+        }
+    }
+}
+
+pub fn encode_typing(typing: bool) -> [u8; FRAME_LEN] {
+    let mut frame = [0u8; FRAME_LEN];
+    frame[..8].copy_from_slice(TYPING_MAGIC);
+    frame[8] = u8::from(typing);
+    frame
+}
+
+/// Whether a datagram says the peer is typing, if it is a typing notification
+pub fn decode_typing(frame: &[u8]) -> Option<bool> {
+    if frame.len() != FRAME_LEN || &frame[..8] != TYPING_MAGIC {
+        return None;
+    }
+    match frame[8] {
+        0 => Some(false),
+        1 => Some(true),
+        _ => None,
+    }
+}
+
+/// Tell the peer on `connection` whether we are typing. Lost datagrams
+/// aren't retried.
+pub fn send_typing(connection: &Connection, typing: bool) -> Result<(), TypingError> {
+    connection
+        .send_datagram(encode_typing(typing).to_vec().into())
+        .map_err(|e| TypingError::Send(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        assert_eq!(decode_typing(&encode_typing(true)), Some(true));
+        assert_eq!(decode_typing(&encode_typing(false)), Some(false));
+
+        let mut wrong = encode_typing(true);
+        wrong[8] = 2;
+        assert_eq!(decode_typing(&wrong), None);
+        wrong[0] = b'X';
+        assert_eq!(decode_typing(&wrong[..]), None);
+        assert_eq!(decode_typing(b"SNKTYPE1"), None);
+    }
+
+    #[test]
+    fn test_event_serializes_camel_case() {
+        let json = serde_json::to_value(TypingEvent::new("alice", true)).unwrap();
+        assert_eq!(json["contactPubkey"], "alice");
+        assert_eq!(json["typing"], true);
+        assert_eq!(json["expiresInSecs"], TYPING_EXPIRY_SECS);
+    }
+}
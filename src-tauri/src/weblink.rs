@@ -0,0 +1,218 @@
+//! One-time web links for starting an exchange
+//!
+//! A `sneakernet://` link only opens where the app is installed, and some
+//! messengers won't make it clickable. A web link is a plain HTTPS URL: our
+//! exchange payload is encrypted with a fresh random key and published to a
+//! Nostr relay under a throwaway key, with a NIP-40 expiration. The URL names
+//! the relay and event, and carries the key in its fragment, which browsers
+//! never send to a server, so the relay and the web host only ever see
+//! ciphertext and nothing ties the event to our identity.
+//!
+//! The page a link opens is whatever the user sets as
+//! `exchange.webLinkBase`; `web-link/` has a static one to host. Without
+//! one, `create_web_link` hands out a plain `sneakernet://` link instead.
+
+use crate::exchange::EXCHANGE_URI_PREFIX;
+use crate::keys::{sign_event, EventTemplate, SignedEvent};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{Key as CipherKey, XChaCha20Poly1305, XNonce};
+use nostr::Keys;
+use serde_json::{json, Value};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// Event kind holding an encrypted exchange payload for a web link
+pub const WEB_LINK_KIND: u16 = 4_734;
+
+/// How long a web link can be opened; relays drop the event after this
+pub const WEB_LINK_TTL_SECS: u64 = 60 * 60;
+
+#[derive(Error, Debug)]
+pub enum WebLinkError {
+    #[error("Not a SneakerNet web link")]
+    InvalidLink,
+    #[error("This link has expired")]
+    Expired,
+    #[error("The link's exchange is no longer on its relay")]
+    NotFound,
+    #[error("Encryption error")]
+    Encryption,
+    #[error("The link's exchange couldn't be decrypted")]
+    Decryption,
+}
+
+/// A web link: where the encrypted payload is, and the key to it
+pub struct WebLink {
+    pub event_id: String,
+    pub relay: String,
+    key: Zeroizing<[u8; 32]>,
+}
+
+impl WebLink {
+    /// Encrypt `payload` under a new key into an event for `relay` that
+    /// expires after `WEB_LINK_TTL_SECS`
+    pub fn seal(payload: &str, relay: &str, now: u64) -> Result<(Self, SignedEvent), WebLinkError> {
+        let key = Zeroizing::new(rand::random::<[u8; 32]>());
+        let nonce = rand::random::<[u8; 24]>();
+        let ciphertext = XChaCha20Poly1305::new(CipherKey::from_slice(key.as_slice()))
+            .encrypt(XNonce::from_slice(&nonce), payload.as_bytes())
+            .map_err(|_| WebLinkError::Encryption)?;
+        let content = URL_SAFE_NO_PAD.encode([nonce.as_slice(), &ciphertext].concat());
+
+        let event = sign_event(
+            &Keys::generate(),
+            &EventTemplate {
+                created_at: now,
+                kind: WEB_LINK_KIND,
+                tags: vec![vec![
+                    "expiration".to_string(),
+                    (now + WEB_LINK_TTL_SECS).to_string(),
+                ]],
+                content,
+            },
+        );
+        let link = Self {
+            event_id: event.id.clone(),
+            relay: relay.to_string(),
+            key,
+        };
+        Ok((link, event))
+    }
+
+    /// `<base>#<event id>.<key>.<relay>`, `base` being the page to open
+    pub fn to_url(&self, base: &str) -> String {
+        format!(
+            "{}#{}.{}.{}",
+            base,
+            self.event_id,
+            URL_SAFE_NO_PAD.encode(self.key.as_slice()),
+            self.relay
+        )
+    }
+
+    /// Read a link on any https page, since the sender picks the host, or
+    /// one the static page handed to the app as `sneakernet://exchange/#...`
+    pub fn parse(url: &str) -> Result<Self, WebLinkError> {
+        let url = url.trim();
+        let handed_off = url
+            .get(..EXCHANGE_URI_PREFIX.len())
+            .is_some_and(|scheme| scheme.eq_ignore_ascii_case(EXCHANGE_URI_PREFIX));
+        if !url.starts_with("https://") && !handed_off {
+            return Err(WebLinkError::InvalidLink);
+        }
+        let (_, fragment) = url.split_once('#').ok_or(WebLinkError::InvalidLink)?;
+
+        let mut parts = fragment.splitn(3, '.');
+        let (Some(event_id), Some(key), Some(relay)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(WebLinkError::InvalidLink);
+        };
+        let valid_id = event_id.len() == 64 && event_id.chars().all(|c| c.is_ascii_hexdigit());
+        let valid_relay = relay.starts_with("wss://") || relay.starts_with("ws://");
+        let key: [u8; 32] = URL_SAFE_NO_PAD
+            .decode(key)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(WebLinkError::InvalidLink)?;
+        if !valid_id || !valid_relay {
+            return Err(WebLinkError::InvalidLink);
+        }
+
+        Ok(Self {
+            event_id: event_id.to_ascii_lowercase(),
+            relay: relay.to_string(),
+            key: Zeroizing::new(key),
+        })
+    }
+
+    /// NIP-01 filter for the link's event
+    pub fn filter(&self) -> Value {
+        json!({ "ids": [self.event_id], "kinds": [WEB_LINK_KIND] })
+    }
+
+    /// Decrypt the payload from the link's event, found among `events`
+    pub fn open(&self, events: &[SignedEvent], now: u64) -> Result<String, WebLinkError> {
+        let event = events
+            .iter()
+            .find(|e| e.id == self.event_id && e.kind == WEB_LINK_KIND)
+            .ok_or(WebLinkError::NotFound)?;
+        let expires_at = event
+            .tags
+            .iter()
+            .find(|tag| tag.first().map(String::as_str) == Some("expiration"))
+            .and_then(|tag| tag.get(1)?.parse::<u64>().ok())
+            .ok_or(WebLinkError::InvalidLink)?;
+        if now > expires_at {
+            return Err(WebLinkError::Expired);
+        }
+
+        let data = URL_SAFE_NO_PAD
+            .decode(&event.content)
+            .map_err(|_| WebLinkError::Decryption)?;
+        if data.len() < 24 {
+            return Err(WebLinkError::Decryption);
+        }
+        let (nonce, ciphertext) = data.split_at(24);
+        let payload = XChaCha20Poly1305::new(CipherKey::from_slice(self.key.as_slice()))
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| WebLinkError::Decryption)?;
+        String::from_utf8(payload).map_err(|_| WebLinkError::Decryption)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RELAY: &str = "wss://relay.example.com";
+    const BASE: &str = "https://links.example.org/x";
+
+    #[test]
+    fn test_web_link_round_trip() {
+        let (link, event) = WebLink::seal("sn2:payload", RELAY, 1_000).unwrap();
+        let url = link.to_url(BASE);
+        assert!(url.starts_with(BASE));
+        assert!(!event.content.contains("payload"));
+
+        let parsed = WebLink::parse(&url).unwrap();
+        assert_eq!(parsed.relay, RELAY);
+        assert_eq!(parsed.filter()["ids"][0], json!(event.id));
+        assert_eq!(parsed.open(&[event.clone()], 1_000).unwrap(), "sn2:payload");
+
+        // Handed to the app by the static page
+        let fragment = url.split_once('#').unwrap().1;
+        let handed_off = WebLink::parse(&format!("{}#{}", EXCHANGE_URI_PREFIX, fragment)).unwrap();
+        assert_eq!(handed_off.open(&[event], 1_000).unwrap(), "sn2:payload");
+    }
+
+    #[test]
+    fn test_web_link_expires() {
+        let (link, event) = WebLink::seal("sn2:payload", RELAY, 1_000).unwrap();
+        assert!(matches!(
+            link.open(&[event], 1_000 + WEB_LINK_TTL_SECS + 1),
+            Err(WebLinkError::Expired)
+        ));
+        assert!(matches!(link.open(&[], 1_000), Err(WebLinkError::NotFound)));
+    }
+
+    #[test]
+    fn test_wrong_key_or_bad_link_rejected() {
+        let (link, event) = WebLink::seal("sn2:payload", RELAY, 1_000).unwrap();
+        let (other, _) = WebLink::seal("sn2:other", RELAY, 1_000).unwrap();
+        let url = other
+            .to_url(BASE)
+            .replacen(&other.event_id, &link.event_id, 1);
+        let wrong_key = WebLink::parse(&url).unwrap();
+        assert!(matches!(
+            wrong_key.open(&[event], 1_000),
+            Err(WebLinkError::Decryption)
+        ));
+
+        assert!(WebLink::parse("https://example.com/x#abc.def.wss://r").is_err());
+        assert!(WebLink::parse(&format!("{}#{}.key", BASE, link.event_id)).is_err());
+        let plain_http = link.to_url("http://links.example.org/x");
+        assert!(WebLink::parse(&plain_http).is_err());
+    }
+}
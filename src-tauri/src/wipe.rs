@@ -0,0 +1,144 @@
+//! Erasing everything the app has stored
+//!
+//! A wipe first moves every data file into a staging directory next to
+//! them. Each move is a rename within one directory tree, and a failed move
+//! puts the earlier ones back, so either all the data is gone from where the
+//! app reads it or none is. Only then are the staged files overwritten and
+//! removed. If the app dies before that finishes, `finish_interrupted` does
+//! it on the next start.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Prefix of staging directories inside the app data directory
+pub const STAGING_PREFIX: &str = ".wipe-";
+
+/// Files moved out of the way and waiting to be shredded
+pub struct StagedWipe {
+    dir: PathBuf,
+}
+
+/// Move `files` (names inside `data_dir`; missing ones are skipped) into a
+/// new staging directory, or leave everything in place on error
+pub fn stage(data_dir: &Path, files: &[&str]) -> io::Result<StagedWipe> {
+    let dir = data_dir.join(format!("{}{}", STAGING_PREFIX, uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir)?;
+
+    let mut moved: Vec<&str> = Vec::new();
+    for &name in files {
+        let from = data_dir.join(name);
+        if !from.exists() {
+            continue;
+        }
+        if let Err(e) = fs::rename(&from, dir.join(name)) {
+            for name in moved {
+                let _ = fs::rename(dir.join(name), data_dir.join(name));
+            }
+            let _ = fs::remove_dir(&dir);
+            return Err(e);
+        }
+        moved.push(name);
+    }
+
+    Ok(StagedWipe { dir })
+}
+
+impl StagedWipe {
+    /// Overwrite and delete the staged files
+    pub fn shred(self) -> io::Result<()> {
+        shred_dir(&self.dir)
+    }
+}
+
+/// Overwrite a file with zeros before removing it. Flash storage may keep
+/// old blocks around, so this is a best effort on top of deleting it.
+fn shred_file(path: &Path) -> io::Result<()> {
+    let len = File::open(path)?.metadata()?.len();
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let zeros = [0u8; 4096];
+    let mut remaining = len;
+    while remaining > 0 {
+        let chunk = remaining.min(zeros.len() as u64) as usize;
+        file.write_all(&zeros[..chunk])?;
+        remaining -= chunk as u64;
+    }
+    file.sync_all()?;
+    drop(file);
+    fs::remove_file(path)
+}
+
+fn shred_dir(dir: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            shred_dir(&path)?;
+        } else {
+            shred_file(&path)?;
+        }
+    }
+    fs::remove_dir(dir)
+}
+
+/// Shred anything left staged by a wipe that didn't finish
+pub fn finish_interrupted(data_dir: &Path) {
+    let Ok(entries) = fs::read_dir(data_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(STAGING_PREFIX)
+        {
+            let _ = shred_dir(&entry.path());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_data_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sneakernet-wipe-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_stage_and_shred() {
+        let dir = temp_data_dir();
+        fs::write(dir.join("store.json"), b"{\"nostr_keys\":1}").unwrap();
+        fs::write(dir.join("contacts.sqlite3"), vec![7u8; 10_000]).unwrap();
+        fs::write(dir.join("unrelated.txt"), b"keep").unwrap();
+
+        let staged = stage(&dir, &["store.json", "contacts.sqlite3", "missing"]).unwrap();
+        // Gone from where the app looks as soon as staging succeeds
+        assert!(!dir.join("store.json").exists());
+        assert!(!dir.join("contacts.sqlite3").exists());
+
+        staged.shred().unwrap();
+        let left: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(left, vec![std::ffi::OsString::from("unrelated.txt")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_finish_interrupted_wipe() {
+        let dir = temp_data_dir();
+        fs::write(dir.join("store.json"), b"{}").unwrap();
+
+        // Staged but never shredded, as if the app was killed
+        let staging_dir = stage(&dir, &["store.json"]).unwrap().dir;
+        assert!(staging_dir.exists());
+
+        finish_interrupted(&dir);
+        assert!(!staging_dir.exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir(dir).unwrap();
+    }
+}
@@ -2,10 +2,15 @@ import { createSignal, onMount, Show, type Component } from 'solid-js';
 import KeyDisplay from './components/KeyDisplay';
 import NFCExchange from './components/NFCExchange';
 import QRExchange from './components/QRExchange';
+import LANExchange from './components/LANExchange';
+import AudioExchange from './components/AudioExchange';
+import PendingExchanges from './components/PendingExchanges';
+import GroupExchange from './components/GroupExchange';
+import RemoteExchange from './components/RemoteExchange';
 import ContactList from './components/ContactList';
 import Chat from './components/Chat';
 import type { TabId, NostrKeys, Contact, ExchangeMode } from './lib/types';
-import { hasKeys, generateKeys, getPublicKey, getContacts, isNfcAvailable } from './lib/tauri';
+import { hasKeys, generateKeys, getPublicKey, getContacts, isNfcAvailable, takePendingExchangeUris, onExchangeUri, processExchangeUri, isWebLink, openWebLink, completeExchange, getExchangeUri, startReverify, cancelReverify, onContactIntroduced } from './lib/tauri';
 
 const App: Component = () => {
   const [activeTab, setActiveTab] = createSignal<TabId>('keys');
@@ -16,6 +21,8 @@ const App: Component = () => {
   const [exchangeMode, setExchangeMode] = createSignal<ExchangeMode>('nfc');
   const [nfcAvailable, setNfcAvailable] = createSignal<boolean>(true);
   const [chatContact, setChatContact] = createSignal<Contact | null>(null);
+  const [linkNotice, setLinkNotice] = createSignal<string | null>(null);
+  const [reverifying, setReverifying] = createSignal<Contact | null>(null);
 
   onMount(async () => {
     try {
@@ -45,6 +52,14 @@ const App: Component = () => {
         setNfcAvailable(false);
         setExchangeMode('qr');
       }
+
+      // Exchange links the app was opened with, now and later
+      await openExchangeLinks();
+      await onExchangeUri(openExchangeLinks);
+      // Contacts introduced to us over chat
+      await onContactIntroduced((contact) => {
+        setContacts(prev => [contact, ...prev.filter(c => c.id !== contact.id)]);
+      });
     } catch (err) {
       console.error('Initialization error:', err);
       setError(err instanceof Error ? err.message : 'Failed to initialize');
@@ -63,10 +78,58 @@ const App: Component = () => {
   };
 
   const onExchangeComplete = (contact: Contact) => {
-    setContacts(prev => [contact, ...prev]);
+    // A re-verified or already known contact replaces its old entry
+    setContacts(prev => [contact, ...prev.filter(c => c.id !== contact.id)]);
+    setReverifying(null);
     setActiveTab('contacts');
   };
 
+  // Contacts saved as a group exchange roster reaches us, or by a remote
+  // exchange; stay on the exchange tab, as there may be more to come
+  const onGroupContacts = (added: Contact[]) => {
+    const ids = new Set(added.map(c => c.id));
+    setContacts(prev => [...added, ...prev.filter(c => !ids.has(c.id))]);
+  };
+
+  // Exchange with an existing contact again to refresh and re-verify them
+  const reverify = async (contact: Contact) => {
+    try {
+      await startReverify(contact.id);
+      setReverifying(contact);
+      setActiveTab('exchange');
+    } catch (err) {
+      setError(err instanceof Error ? err.message : String(err));
+    }
+  };
+
+  const stopReverifying = async () => {
+    await cancelReverify();
+    setReverifying(null);
+  };
+
+  // A sneakernet://exchange/... or web link is verified like a scanned QR
+  // code; if it was their first message, our reply link goes to the clipboard
+  const openExchangeLinks = async () => {
+    for (const uri of await takePendingExchangeUris()) {
+      try {
+        const result = isWebLink(uri) ? await openWebLink(uri) : await processExchangeUri(uri);
+        const contact = await completeExchange(result.theirPubkey);
+        onExchangeComplete(contact);
+        if (result.needsReply) {
+          await navigator.clipboard.writeText(await getExchangeUri(result.theirPubkey));
+          setLinkNotice('Contact added. Your reply link was copied: send it back to them.');
+        } else {
+          setLinkNotice('Contact added from their link.');
+        }
+      } catch (err) {
+        const message = typeof err === 'object' && err !== null && 'message' in err
+          ? String((err as { message: unknown }).message)
+          : String(err);
+        setLinkNotice(`Couldn't open the exchange link: ${message}`);
+      }
+    }
+  };
+
   const openChat = (contact: Contact) => {
     setChatContact(contact);
     setActiveTab('chat');
@@ -100,11 +163,26 @@ const App: Component = () => {
 
           <Show when={activeTab() !== 'chat'}>
             <div class="content">
+              <Show when={linkNotice()}>
+                <div class="card" onClick={() => setLinkNotice(null)}>
+                  <p class="status-detail">{linkNotice()}</p>
+                </div>
+              </Show>
+
               <Show when={activeTab() === 'keys'}>
                 <KeyDisplay keys={keys()} />
               </Show>
               
               <Show when={activeTab() === 'exchange'}>
+                <Show when={reverifying()}>
+                  <div class="card">
+                    <p class="status-detail">
+                      Re-verifying {reverifying()!.nickname ?? reverifying()!.profileName ?? reverifying()!.nostrPubkey.slice(0, 16)}:
+                      exchange with them as usual.
+                    </p>
+                    <button class="btn btn-secondary" onClick={stopReverifying}>Cancel</button>
+                  </div>
+                </Show>
                 <div class="exchange-mode-toggle">
                   <button 
                     class={`mode-btn ${exchangeMode() === 'nfc' ? 'active' : ''}`}
@@ -119,6 +197,30 @@ const App: Component = () => {
                   >
                     QR Code
                   </button>
+                  <button 
+                    class={`mode-btn ${exchangeMode() === 'lan' ? 'active' : ''}`}
+                    onClick={() => setExchangeMode('lan')}
+                  >
+                    Wi-Fi
+                  </button>
+                  <button 
+                    class={`mode-btn ${exchangeMode() === 'audio' ? 'active' : ''}`}
+                    onClick={() => setExchangeMode('audio')}
+                  >
+                    Sound
+                  </button>
+                  <button 
+                    class={`mode-btn ${exchangeMode() === 'group' ? 'active' : ''}`}
+                    onClick={() => setExchangeMode('group')}
+                  >
+                    Group
+                  </button>
+                  <button 
+                    class={`mode-btn ${exchangeMode() === 'remote' ? 'active' : ''}`}
+                    onClick={() => setExchangeMode('remote')}
+                  >
+                    Remote
+                  </button>
                 </div>
                 
                 <Show when={exchangeMode() === 'nfc'}>
@@ -134,6 +236,36 @@ const App: Component = () => {
                     onComplete={onExchangeComplete} 
                   />
                 </Show>
+
+                <Show when={exchangeMode() === 'lan'}>
+                  <LANExchange 
+                    keys={keys()} 
+                    onComplete={onExchangeComplete} 
+                  />
+                </Show>
+
+                <Show when={exchangeMode() === 'audio'}>
+                  <AudioExchange 
+                    keys={keys()} 
+                    onComplete={onExchangeComplete} 
+                  />
+                </Show>
+
+                <Show when={exchangeMode() === 'group'}>
+                  <GroupExchange 
+                    keys={keys()} 
+                    onContacts={onGroupContacts} 
+                  />
+                </Show>
+
+                <Show when={exchangeMode() === 'remote'}>
+                  <RemoteExchange 
+                    keys={keys()} 
+                    onContacts={onGroupContacts} 
+                  />
+                </Show>
+
+                <PendingExchanges />
               </Show>
               
               <Show when={activeTab() === 'contacts'}>
@@ -141,6 +273,7 @@ const App: Component = () => {
                   contacts={contacts()} 
                   onRefresh={refreshContacts}
                   onOpenChat={openChat}
+                  onReverify={reverify}
                 />
               </Show>
             </div>
@@ -0,0 +1,231 @@
+import { createSignal, onCleanup, type Component, Show } from 'solid-js';
+import type { NostrKeys, Contact, AudioExchangeStatus, ScanError } from '../lib/types';
+import {
+  startAudioBroadcast,
+  startAudioReceive,
+  pushAudioSamples,
+  stopAudioReceive,
+  completeExchange,
+} from '../lib/tauri';
+
+interface AudioExchangeProps {
+  keys: NostrKeys | null;
+  onComplete: (contact: Contact) => void;
+}
+
+const errorMessage = (err: unknown, fallback: string): string => {
+  if (err instanceof Error) return err.message;
+  if (typeof err === 'object' && err !== null && 'message' in err) {
+    return (err as ScanError).message;
+  }
+  return typeof err === 'string' ? err : fallback;
+};
+
+// Microphone audio is sent to the backend in chunks of about this length
+const CHUNK_SECS = 0.25;
+
+const AudioExchange: Component<AudioExchangeProps> = (props) => {
+  const [status, setStatus] = createSignal<AudioExchangeStatus>({ state: 'idle' });
+  let player: HTMLAudioElement | undefined;
+  let recorder: { context: AudioContext; stream: MediaStream } | undefined;
+
+  const stopPlaying = () => {
+    player?.pause();
+    player = undefined;
+  };
+
+  const stopListening = async () => {
+    if (recorder) {
+      recorder.stream.getTracks().forEach((track) => track.stop());
+      await recorder.context.close();
+      recorder = undefined;
+    }
+    await stopAudioReceive();
+  };
+
+  // Play our payload in a loop until stopped
+  const play = async (theirPk?: string) => {
+    try {
+      const url = await startAudioBroadcast(theirPk);
+      stopPlaying();
+      player = new Audio(url);
+      player.loop = true;
+      await player.play();
+      setStatus({ state: 'playing', responding: theirPk !== undefined });
+    } catch (err) {
+      console.error('Audio broadcast error:', err);
+      setStatus({ state: 'error', message: errorMessage(err, 'Failed to play sound') });
+    }
+  };
+
+  const heard = async (theirPubkey: string) => {
+    await stopListening();
+    try {
+      const contact = await completeExchange(theirPubkey);
+      setStatus({ state: 'complete', contact });
+      // Play our response so they can finish too
+      await play(theirPubkey);
+      setStatus({ state: 'complete', contact });
+    } catch (err) {
+      setStatus({ state: 'error', message: errorMessage(err, 'Exchange failed') });
+    }
+  };
+
+  const listen = async () => {
+    stopPlaying();
+    try {
+      // Raw audio: echo cancellation and noise suppression eat the tones
+      const stream = await navigator.mediaDevices.getUserMedia({
+        audio: { echoCancellation: false, noiseSuppression: false, autoGainControl: false },
+      });
+      const context = new AudioContext();
+      recorder = { context, stream };
+      await startAudioReceive(context.sampleRate);
+      setStatus({ state: 'listening' });
+
+      const source = context.createMediaStreamSource(stream);
+      const processor = context.createScriptProcessor(4096, 1, 1);
+      const chunkLen = Math.round(context.sampleRate * CHUNK_SECS);
+      let pending: number[] = [];
+      let sending = Promise.resolve();
+
+      processor.onaudioprocess = (event) => {
+        pending.push(...event.inputBuffer.getChannelData(0));
+        if (pending.length < chunkLen) return;
+        const chunk = pending;
+        pending = [];
+        sending = sending.then(async () => {
+          if (status().state !== 'listening') return;
+          try {
+            const theirPubkey = await pushAudioSamples(chunk);
+            if (theirPubkey) await heard(theirPubkey);
+          } catch (err) {
+            await stopListening();
+            setStatus({ state: 'error', message: errorMessage(err, 'Listening failed') });
+          }
+        });
+      };
+      source.connect(processor);
+      processor.connect(context.destination);
+    } catch (err) {
+      console.error('Audio receive error:', err);
+      await stopListening();
+      setStatus({ state: 'error', message: errorMessage(err, 'Microphone unavailable') });
+    }
+  };
+
+  const reset = async () => {
+    stopPlaying();
+    await stopListening();
+    setStatus({ state: 'idle' });
+  };
+
+  const done = (contact: Contact) => {
+    stopPlaying();
+    props.onComplete(contact);
+    setStatus({ state: 'idle' });
+  };
+
+  onCleanup(() => {
+    stopPlaying();
+    stopListening().catch(() => {});
+  });
+
+  const getStatusText = () => {
+    const s = status();
+    switch (s.state) {
+      case 'idle':
+        return 'Sound Exchange';
+      case 'playing':
+        return s.responding ? 'Playing Your Reply' : 'Playing Your Sound';
+      case 'listening':
+        return 'Listening...';
+      case 'complete':
+        return 'Exchange Complete!';
+      case 'error':
+        return 'Exchange Failed';
+    }
+  };
+
+  const getStatusDetail = () => {
+    const s = status();
+    switch (s.state) {
+      case 'idle':
+        return 'Hold the phones close together in a quiet place';
+      case 'playing':
+        return 'Keep the phones close until the other device has heard it';
+      case 'listening':
+        return 'Waiting to hear the other device';
+      case 'complete':
+        return 'Contact added. Keep playing until they have your reply';
+      case 'error':
+        return s.message;
+    }
+  };
+
+  return (
+    <div class="qr-exchange">
+      <h2 class={`status-text ${status().state === 'complete' ? 'status-success' : ''} ${status().state === 'error' ? 'status-error' : ''}`}>
+        {getStatusText()}
+      </h2>
+
+      <p class="status-detail">{getStatusDetail()}</p>
+
+      <Show when={status().state === 'idle'}>
+        <div class="qr-buttons">
+          <button class="btn btn-primary" onClick={() => play()} disabled={!props.keys}>
+            Play My Sound
+          </button>
+          <button
+            class="btn btn-secondary"
+            onClick={listen}
+            disabled={!props.keys}
+            style={{ "margin-top": "12px" }}
+          >
+            Listen
+          </button>
+        </div>
+      </Show>
+
+      <Show when={status().state === 'playing'}>
+        <div class="qr-buttons">
+          <button class="btn btn-primary" onClick={listen}>
+            Now Listen for Theirs
+          </button>
+          <button class="btn btn-secondary" onClick={reset} style={{ "margin-top": "12px" }}>
+            Stop
+          </button>
+        </div>
+      </Show>
+
+      <Show when={status().state === 'listening'}>
+        <button class="btn btn-secondary" onClick={reset}>
+          Cancel
+        </button>
+      </Show>
+
+      <Show when={status().state === 'error'}>
+        <button class="btn btn-primary" onClick={reset} style={{ "margin-top": "16px" }}>
+          Try Again
+        </button>
+      </Show>
+
+      <Show when={status().state === 'complete' ? status() as { state: 'complete'; contact: Contact } : null}>
+        {(state) => (
+          <>
+            <div class="card" style={{ "margin-top": "24px", "text-align": "left" }}>
+              <div class="card-header">New Contact</div>
+              <div class="contact-pubkey">{state().contact.nostrPubkey}</div>
+              <div class="contact-iroh">Iroh: {state().contact.irohEndpointId}</div>
+            </div>
+            <button class="btn btn-primary" onClick={() => done(state().contact)} style={{ "margin-top": "16px" }}>
+              Done
+            </button>
+          </>
+        )}
+      </Show>
+    </div>
+  );
+};
+
+export default AudioExchange;
@@ -50,8 +50,10 @@ const Chat: Component<ChatProps> = (props) => {
       const status = await startIroh(props.contact.nostrPubkey);
       setIrohStatus(status);
 
-      // Try to connect to the contact
-      await connectToContact(props.contact.nostrPubkey, props.contact.irohEndpointId);
+      // Dial the contact if we know their node; otherwise they dial us
+      if (props.contact.irohEndpointId) {
+        await connectToContact(props.contact.nostrPubkey, props.contact.irohEndpointId);
+      }
 
       // Load existing messages
       const existingMessages = await getMessages(props.contact.nostrPubkey);
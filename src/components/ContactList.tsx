@@ -1,14 +1,22 @@
-import { type Component, For, Show } from 'solid-js';
-import type { Contact } from '../lib/types';
-import { deleteContact } from '../lib/tauri';
+import { createSignal, type Component, For, Show } from 'solid-js';
+import QRCode from 'qrcode';
+import type { Contact, ContactShare } from '../lib/types';
+import { deleteContact, introduceContact, getContactSharePayload } from '../lib/tauri';
 
 interface ContactListProps {
   contacts: Contact[];
   onRefresh: () => void;
   onOpenChat: (contact: Contact) => void;
+  onReverify: (contact: Contact) => void;
 }
 
 const ContactList: Component<ContactListProps> = (props) => {
+  // Contact whose card we're about to send, and what became of the last one
+  const [introducing, setIntroducing] = createSignal<string | null>(null);
+  const [introNotice, setIntroNotice] = createSignal<string | null>(null);
+  // Contact shown as a QR code for someone else to scan
+  const [sharing, setSharing] = createSignal<{ contactId: string; share: ContactShare; qr: string } | null>(null);
+
   const formatDate = (timestamp: number) => {
     const date = new Date(timestamp * 1000);
     return date.toLocaleDateString(undefined, {
@@ -25,6 +33,52 @@ const ContactList: Component<ContactListProps> = (props) => {
     return `${pubkey.slice(0, 10)}...${pubkey.slice(-10)}`;
   };
 
+  const METHOD_LABELS: Record<NonNullable<Contact['exchangeMethod']>, string> = {
+    nfc: 'NFC',
+    qr: 'QR code',
+    lan: 'local network',
+    audio: 'audio',
+    link: 'link',
+    nostr: 'Nostr DM',
+  };
+
+  // How far we trust that this key is really theirs
+  const trustLabel = (contact: Contact) => {
+    if (contact.revokedAt) return 'Key revoked, exchange again';
+    if (contact.verified) return 'Verified';
+    if (contact.origin === 'introduction' && contact.needsReview) return 'Introduced, unverified';
+    if (contact.origin === 'remote' && contact.needsReview) return 'Remote, unverified';
+    if (contact.needsReview || contact.origin === 'nip19') return 'Unverified';
+    return contact.exchangeMethod
+      ? `Exchanged by ${METHOD_LABELS[contact.exchangeMethod]}`
+      : 'Exchanged';
+  };
+
+  // Send our signed card for `contact` to `to` over chat
+  const introduce = async (contact: Contact, to: Contact) => {
+    setIntroducing(null);
+    try {
+      await introduceContact(contact.id, to.nostrPubkey);
+      setIntroNotice(`Introduced ${truncatePubkey(contact.nostrPubkey)} to ${truncatePubkey(to.nostrPubkey)}`);
+    } catch (err) {
+      setIntroNotice(err instanceof Error ? err.message : String(err));
+    }
+  };
+
+  const toggleShare = async (contact: Contact) => {
+    if (sharing()?.contactId === contact.id) {
+      setSharing(null);
+      return;
+    }
+    try {
+      const share = await getContactSharePayload(contact.id);
+      const qr = await QRCode.toDataURL(share.qrPayload, { width: 240, margin: 2 });
+      setSharing({ contactId: contact.id, share, qr });
+    } catch (err) {
+      console.error('Failed to share contact:', err);
+    }
+  };
+
   const handleDelete = async (id: string) => {
     if (confirm('Delete this contact?')) {
       try {
@@ -45,6 +99,10 @@ const ContactList: Component<ContactListProps> = (props) => {
         </span>
       </div>
 
+      <Show when={introNotice()}>
+        <p class="status-detail">{introNotice()}</p>
+      </Show>
+
       <Show when={props.contacts.length > 0} fallback={
         <div class="empty-state">
           <div class="empty-state-icon">
@@ -81,9 +139,58 @@ const ContactList: Component<ContactListProps> = (props) => {
                     </svg>
                     Chat
                   </button>
+                  <button 
+                    class="chat-button"
+                    onClick={() => props.onReverify(contact)}
+                    style={{ "margin-left": '8px' }}
+                  >
+                    Re-verify
+                  </button>
+                  <button 
+                    class="chat-button"
+                    onClick={() => setIntroducing(introducing() === contact.id ? null : contact.id)}
+                    style={{ "margin-left": '8px' }}
+                  >
+                    Introduce
+                  </button>
+                  <button 
+                    class="chat-button"
+                    onClick={() => toggleShare(contact)}
+                    style={{ "margin-left": '8px' }}
+                  >
+                    Share
+                  </button>
                 </div>
+                <Show when={sharing()?.contactId === contact.id ? sharing() : null}>
+                  {(s) => (
+                    <div class="qr-container contact-share">
+                      <img src={s().qr} alt="Contact QR code" class="qr-image" />
+                      <div class="status-detail">
+                        {s().share.displayName ?? truncatePubkey(s().share.npub)}: not verified by scanning this
+                      </div>
+                    </div>
+                  )}
+                </Show>
+                <Show when={introducing() === contact.id}>
+                  <select
+                    class="introduce-select"
+                    onChange={(e) => {
+                      const to = props.contacts.find((c) => c.id === e.currentTarget.value);
+                      if (to) introduce(contact, to);
+                    }}
+                  >
+                    <option value="">Introduce to...</option>
+                    <For each={props.contacts.filter((c) => c.id !== contact.id && !c.blocked)}>
+                      {(other) => (
+                        <option value={other.id}>
+                          {other.nickname ?? other.profileName ?? truncatePubkey(other.nostrPubkey)}
+                        </option>
+                      )}
+                    </For>
+                  </select>
+                </Show>
                 <div class="contact-meta">
-                  <span>{formatDate(contact.exchangedAt)}</span>
+                  <span>{formatDate(contact.exchangedAt)} · {trustLabel(contact)}</span>
                   <button 
                     onClick={() => handleDelete(contact.id)}
                     style={{ 
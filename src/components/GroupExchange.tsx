@@ -0,0 +1,164 @@
+import { createSignal, onCleanup, type Component, For, Show } from 'solid-js';
+import QRCode from 'qrcode';
+import { scan, cancel, Format } from '@tauri-apps/plugin-barcode-scanner';
+import type { NostrKeys, Contact, RosterMember, MultipartProgress } from '../lib/types';
+import { startGroupExchange, scanGroupExchange, endGroupExchange } from '../lib/tauri';
+
+interface GroupExchangeProps {
+  keys: NostrKeys | null;
+  onContacts: (contacts: Contact[]) => void;
+}
+
+// Time each frame of an animated code stays on screen
+const FRAME_INTERVAL_MS = 250;
+
+const truncatePubkey = (pubkey: string) => `${pubkey.slice(0, 8)}...${pubkey.slice(-8)}`;
+
+// Round-robin exchange: the roster goes round the table twice, each person
+// scanning the one before and showing the longer roster to the next
+const GroupExchange: Component<GroupExchangeProps> = (props) => {
+  const [active, setActive] = createSignal(false);
+  const [scanning, setScanning] = createSignal(false);
+  const [progress, setProgress] = createSignal<MultipartProgress | null>(null);
+  const [qrDataUrl, setQrDataUrl] = createSignal<string | null>(null);
+  const [members, setMembers] = createSignal<RosterMember[]>([]);
+  const [error, setError] = createSignal<string | null>(null);
+  let frameTimer: ReturnType<typeof setInterval> | undefined;
+
+  const stopAnimation = () => {
+    if (frameTimer !== undefined) {
+      clearInterval(frameTimer);
+      frameTimer = undefined;
+    }
+  };
+
+  const showFrames = async (frames: string[]) => {
+    const dataUrls = await Promise.all(frames.map((frame) => QRCode.toDataURL(frame, {
+      width: 280,
+      margin: 2,
+      color: { dark: '#1a1a2e', light: '#ffffff' },
+    })));
+    stopAnimation();
+    setQrDataUrl(dataUrls[0]);
+    if (dataUrls.length > 1) {
+      let index = 0;
+      frameTimer = setInterval(() => {
+        index = (index + 1) % dataUrls.length;
+        setQrDataUrl(dataUrls[index]);
+      }, FRAME_INTERVAL_MS);
+    }
+  };
+
+  const start = async () => {
+    try {
+      setError(null);
+      await showFrames(await startGroupExchange());
+      setMembers([]);
+      setActive(true);
+    } catch (err) {
+      setError(err instanceof Error ? err.message : String(err));
+    }
+  };
+
+  // Scan the roster from the person before us, frame by frame if animated
+  const scanRoster = async () => {
+    try {
+      setError(null);
+      setScanning(true);
+      for (;;) {
+        const result = await scan({ windowed: false, formats: [Format.QRCode] });
+        if (!result.content) break;
+
+        const scanned = await scanGroupExchange(result.content);
+        setProgress(scanned.progress);
+        if (scanned.progress.complete) {
+          setMembers(scanned.members);
+          if (scanned.added.length > 0) props.onContacts(scanned.added);
+          await showFrames(scanned.frames);
+          setActive(true);
+          break;
+        }
+      }
+    } catch (err) {
+      setError(err instanceof Error ? err.message : String(err));
+    } finally {
+      setScanning(false);
+      setProgress(null);
+    }
+  };
+
+  const finish = async () => {
+    stopAnimation();
+    await endGroupExchange();
+    setActive(false);
+    setQrDataUrl(null);
+    setMembers([]);
+  };
+
+  onCleanup(async () => {
+    stopAnimation();
+    if (scanning()) {
+      try {
+        await cancel();
+      } catch {
+        // Ignore
+      }
+    }
+  });
+
+  return (
+    <div class="qr-exchange">
+      <h2 class="status-text">Group Exchange</h2>
+      <p class="status-detail">
+        {scanning()
+          ? progress() && !progress()!.complete
+            ? `Keep scanning: ${progress()!.received} of ${progress()!.total} parts`
+            : 'Point camera at the previous person\'s code'
+          : active()
+            ? 'Show this to the next person. Once it has been round twice, everyone has everyone.'
+            : 'Start a roster, or scan the one from the person before you'}
+      </p>
+
+      <Show when={active() && qrDataUrl()}>
+        <div class="qr-container">
+          <img src={qrDataUrl()!} alt="Group roster QR code" class="qr-image" />
+        </div>
+      </Show>
+
+      <Show when={members().length > 0}>
+        <ul class="pending-list roster-list">
+          <For each={members()}>
+            {(m) => <li class="pending-item">{m.displayName ?? truncatePubkey(m.pubkey)}</li>}
+          </For>
+        </ul>
+      </Show>
+
+      <Show when={error()}>
+        <p class="status-detail status-error">{error()}</p>
+      </Show>
+
+      <div class="qr-buttons">
+        <Show when={!active()}>
+          <button class="btn btn-primary" onClick={start} disabled={!props.keys || scanning()}>
+            Start Roster
+          </button>
+        </Show>
+        <button
+          class="btn btn-secondary"
+          onClick={scanRoster}
+          disabled={!props.keys || scanning()}
+          style={{ "margin-top": "12px" }}
+        >
+          Scan Previous Person
+        </button>
+        <Show when={active()}>
+          <button class="btn btn-secondary" onClick={finish} style={{ "margin-top": "12px" }}>
+            Done
+          </button>
+        </Show>
+      </div>
+    </div>
+  );
+};
+
+export default GroupExchange;
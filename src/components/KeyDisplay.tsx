@@ -1,11 +1,26 @@
-import { type Component, Show } from 'solid-js';
+import { createSignal, type Component, Show } from 'solid-js';
 import type { NostrKeys } from '../lib/types';
+import { getPaperCard } from '../lib/tauri';
 
 interface KeyDisplayProps {
   keys: NostrKeys | null;
 }
 
 const KeyDisplay: Component<KeyDisplayProps> = (props) => {
+  const [paperCard, setPaperCard] = createSignal<string | null>(null);
+  const [cardError, setCardError] = createSignal<string | null>(null);
+
+  // Show the printable card, then open the print dialog for it
+  const printCard = async () => {
+    try {
+      setCardError(null);
+      setPaperCard(await getPaperCard());
+      window.print();
+    } catch (err) {
+      setCardError(err instanceof Error ? err.message : String(err));
+    }
+  };
+
   const copyToClipboard = async (text: string) => {
     try {
       await navigator.clipboard.writeText(text);
@@ -50,6 +65,19 @@ const KeyDisplay: Component<KeyDisplayProps> = (props) => {
               <p class="pubkey-label">Tap to copy</p>
             </div>
 
+            <div class="card">
+              <button class="btn btn-secondary" onClick={printCard}>
+                Print Contact Card
+              </button>
+              <Show when={cardError()}>
+                <p class="status-detail status-error">{cardError()}</p>
+              </Show>
+              {/* Rendered by the backend; contains only escaped text */}
+              <Show when={paperCard()}>
+                <div class="paper-card" innerHTML={paperCard()!} />
+              </Show>
+            </div>
+
             <div class="card" style={{ "background": "transparent", "border": "1px solid var(--bg-card)" }}>
               <p style={{ "font-size": "14px", "color": "var(--text-secondary)", "line-height": "1.5" }}>
                 This is your Nostr identity. Share it with others to let them find you on the Nostr network.
@@ -0,0 +1,193 @@
+import { createSignal, onCleanup, type Component, For, Show } from 'solid-js';
+import type { NostrKeys, Contact, LANExchangeStatus, LanPeer } from '../lib/types';
+import {
+  hostLanExchange,
+  onLanHost,
+  discoverLanExchanges,
+  joinLanExchange,
+  cancelLanExchange,
+  completeExchange,
+} from '../lib/tauri';
+
+interface LANExchangeProps {
+  keys: NostrKeys | null;
+  onComplete: (contact: Contact) => void;
+}
+
+const errorMessage = (err: unknown, fallback: string): string => {
+  if (err instanceof Error) return err.message;
+  return typeof err === 'string' ? err : fallback;
+};
+
+const LANExchange: Component<LANExchangeProps> = (props) => {
+  const [status, setStatus] = createSignal<LANExchangeStatus>({ state: 'idle' });
+
+  // Both sides end the same way: confirm the verified peer as a contact
+  const finish = async (theirPubkey: string) => {
+    setStatus({ state: 'processing' });
+    const contact = await completeExchange(theirPubkey);
+    setStatus({ state: 'complete', contact });
+    setTimeout(() => {
+      props.onComplete(contact);
+      setStatus({ state: 'idle' });
+    }, 3000);
+  };
+
+  const host = async () => {
+    setStatus({ state: 'hosting', name: null });
+    const unlisten = await onLanHost((info) => setStatus({ state: 'hosting', name: info.name }));
+    try {
+      await finish(await hostLanExchange());
+    } catch (err) {
+      console.error('LAN host error:', err);
+      setStatus({ state: 'error', message: errorMessage(err, 'Exchange failed') });
+    } finally {
+      unlisten();
+    }
+  };
+
+  const discover = async () => {
+    setStatus({ state: 'discovering' });
+    try {
+      const peers = await discoverLanExchanges();
+      setStatus({ state: 'choosing', peers });
+    } catch (err) {
+      setStatus({ state: 'error', message: errorMessage(err, 'Discovery failed') });
+    }
+  };
+
+  const join = async (peer: LanPeer) => {
+    setStatus({ state: 'processing' });
+    try {
+      await finish(await joinLanExchange(peer.address));
+    } catch (err) {
+      console.error('LAN join error:', err);
+      setStatus({ state: 'error', message: errorMessage(err, 'Exchange failed') });
+    }
+  };
+
+  const cancel = async () => {
+    await cancelLanExchange();
+    setStatus({ state: 'idle' });
+  };
+
+  onCleanup(() => {
+    const state = status().state;
+    if (state === 'hosting' || state === 'processing') {
+      cancelLanExchange().catch(() => {});
+    }
+  });
+
+  const getStatusText = () => {
+    const s = status();
+    switch (s.state) {
+      case 'idle':
+        return 'Local Network Exchange';
+      case 'hosting':
+        return 'Waiting for the Other Device';
+      case 'discovering':
+        return 'Looking for Devices...';
+      case 'choosing':
+        return 'Choose a Device';
+      case 'processing':
+        return 'Exchanging...';
+      case 'complete':
+        return 'Exchange Complete!';
+      case 'error':
+        return 'Exchange Failed';
+    }
+  };
+
+  const getStatusDetail = () => {
+    const s = status();
+    switch (s.state) {
+      case 'idle':
+        return 'Both devices must be on the same Wi-Fi network';
+      case 'hosting':
+        return s.name ? `On the other device, pick ${s.name}` : 'Starting...';
+      case 'discovering':
+        return 'Searching the local network';
+      case 'choosing':
+        return s.peers.length > 0
+          ? 'Pick the name shown on the other device'
+          : 'No devices found. Is the other device hosting?';
+      case 'processing':
+        return 'Verifying signatures...';
+      case 'complete':
+        return 'Contact added successfully';
+      case 'error':
+        return s.message;
+    }
+  };
+
+  return (
+    <div class="qr-exchange">
+      <h2 class={`status-text ${status().state === 'complete' ? 'status-success' : ''} ${status().state === 'error' ? 'status-error' : ''}`}>
+        {getStatusText()}
+      </h2>
+
+      <p class="status-detail">{getStatusDetail()}</p>
+
+      <Show when={status().state === 'idle'}>
+        <div class="qr-buttons">
+          <button class="btn btn-primary" onClick={host} disabled={!props.keys}>
+            Host Exchange
+          </button>
+          <button
+            class="btn btn-secondary"
+            onClick={discover}
+            disabled={!props.keys}
+            style={{ "margin-top": "12px" }}
+          >
+            Join Exchange
+          </button>
+        </div>
+      </Show>
+
+      <Show when={status().state === 'choosing' ? status() as { state: 'choosing'; peers: LanPeer[] } : null}>
+        {(state) => (
+          <div class="qr-buttons">
+            <For each={state().peers}>
+              {(peer) => (
+                <button class="btn btn-primary" onClick={() => join(peer)} style={{ "margin-bottom": "12px" }}>
+                  {peer.name}
+                </button>
+              )}
+            </For>
+            <button class="btn btn-secondary" onClick={discover}>
+              Search Again
+            </button>
+          </div>
+        )}
+      </Show>
+
+      <Show when={status().state === 'hosting' || status().state === 'processing'}>
+        <button class="btn btn-secondary" onClick={cancel}>
+          Cancel
+        </button>
+      </Show>
+
+      <Show when={status().state === 'error'}>
+        <button
+          class="btn btn-primary"
+          onClick={() => setStatus({ state: 'idle' })}
+          style={{ "margin-top": "16px" }}
+        >
+          Try Again
+        </button>
+      </Show>
+
+      <Show when={status().state === 'complete' ? status() as { state: 'complete'; contact: Contact } : null}>
+        {(state) => (
+          <div class="card" style={{ "margin-top": "24px", "text-align": "left" }}>
+            <div class="card-header">New Contact</div>
+            <div class="contact-pubkey">{state().contact.nostrPubkey}</div>
+            <div class="contact-iroh">Iroh: {state().contact.irohEndpointId}</div>
+          </div>
+        )}
+      </Show>
+    </div>
+  );
+};
+
+export default LANExchange;
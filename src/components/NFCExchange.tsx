@@ -1,6 +1,6 @@
-import { createSignal, type Component, Show } from 'solid-js';
-import type { NostrKeys, Contact } from '../lib/types';
-import { startNfcBroadcast, startNfcReceive, writeNfcResponse, completeExchange, isNfcAvailable } from '../lib/tauri';
+import { createSignal, onCleanup, onMount, type Component, Show } from 'solid-js';
+import type { NostrKeys, Contact, NfcError, ExchangePhase, OneTapSupport } from '../lib/types';
+import { startNfcBroadcast, startNfcReceive, writeNfcResponse, retryNfcWrite, getNfcExchangeState, onExchangeState, completeExchange, isNfcAvailable, resetNfcExchange, startHceExchange, onHceExchange, tapHceExchange, getOneTapSupport, writeContactTag } from '../lib/tauri';
 
 interface NFCExchangeProps {
   keys: NostrKeys | null;
@@ -15,13 +15,47 @@ type NfcState =
   | { state: 'receiving' }     // Scanning for their pubkey
   | { state: 'received'; theirPubkey: string }  // Got their pubkey
   | { state: 'responding' }    // Writing our response
+  | { state: 'offering' }      // One tap: waiting for the other phone to tap us
+  | { state: 'writing-tag' }   // Writing our contact to a sticker
+  | { state: 'tag-written' }
+  | { state: 'unverified' }    // Read another Nostr app's tag; saved unverified
   | { state: 'verifying' }     // Completing exchange
   | { state: 'complete'; contact: Contact }
-  | { state: 'error'; message: string };
+  | { state: 'error'; message: string; canRetryWrite?: boolean };
+
+// NFC commands reject with a categorized NfcError
+const errorMessage = (err: unknown): string => {
+  if (err instanceof Error) return err.message;
+  if (typeof err === 'object' && err !== null && 'message' in err) {
+    return (err as NfcError).message;
+  }
+  return typeof err === 'string' ? err : 'Exchange failed';
+};
+
+// What the backend reports while a command is running
+const PHASE_DETAIL: Partial<Record<ExchangePhase, string>> = {
+  waitingForTap: 'Waiting for the other device...',
+  tagDetected: 'Device detected, hold still...',
+  payloadRead: 'Read their key, checking signature...',
+  verified: 'Signature verified',
+  awaitingResponse: 'Sent! Waiting for their response...',
+  responded: 'Response delivered',
+};
 
 const NFCExchange: Component<NFCExchangeProps> = (props) => {
   const [status, setStatus] = createSignal<NfcState>({ state: 'idle' });
   const [nfcSupported, setNfcSupported] = createSignal<boolean | null>(null);
+  const [phase, setPhase] = createSignal<ExchangePhase | null>(null);
+  const [oneTap, setOneTap] = createSignal<OneTapSupport>({ offer: false, tap: false });
+
+  let unlistenPhase: (() => void) | undefined;
+  onMount(async () => {
+    getOneTapSupport().then(setOneTap).catch(() => {});
+    unlistenPhase = await onExchangeState((progress) => {
+      if (progress.transport === 'nfc') setPhase(progress.phase);
+    });
+  });
+  onCleanup(() => unlistenPhase?.());
 
   const checkNfcSupport = async () => {
     try {
@@ -55,9 +89,21 @@ const NFCExchange: Component<NFCExchangeProps> = (props) => {
     }
 
     // Show role selection
+    setPhase(null);
     setStatus({ state: 'choose-role' });
   };
 
+  // A write that broke off keeps its signed payload, so tapping again writes
+  // the same one instead of restarting the exchange
+  const fail = async (err: unknown) => {
+    const nfcState = await getNfcExchangeState().catch(() => null);
+    setStatus({
+      state: 'error',
+      message: errorMessage(err),
+      canRetryWrite: nfcState?.pendingPayload != null,
+    });
+  };
+
   // SENDER FLOW: Write our pubkey first, then wait to receive their response
   const startAsSender = async () => {
     try {
@@ -86,10 +132,7 @@ const NFCExchange: Component<NFCExchangeProps> = (props) => {
 
     } catch (err) {
       console.error('NFC sender error:', err);
-      setStatus({ 
-        state: 'error', 
-        message: err instanceof Error ? err.message : 'Exchange failed' 
-      });
+      await fail(err);
     }
   };
 
@@ -118,15 +161,89 @@ const NFCExchange: Component<NFCExchangeProps> = (props) => {
       }, 2000);
 
     } catch (err) {
+      if ((err as NfcError)?.kind === 'thirdPartyContact') {
+        setStatus({ state: 'unverified' });
+        return;
+      }
       console.error('NFC receiver error:', err);
-      setStatus({ 
-        state: 'error', 
-        message: err instanceof Error ? err.message : 'Exchange failed' 
+      await fail(err);
+    }
+  };
+
+  const finish = async (theirPubkey: string) => {
+    setStatus({ state: 'verifying' });
+    const contact = await completeExchange(theirPubkey);
+    setStatus({ state: 'complete', contact });
+    setTimeout(() => {
+      props.onComplete(contact);
+      setStatus({ state: 'idle' });
+    }, 2000);
+  };
+
+  // Tap again after a broken write, then carry on where the flow left off
+  const retryWrite = async () => {
+    try {
+      setStatus({ state: 'responding' });
+      let theirPubkey = await retryNfcWrite();
+      if (!theirPubkey) {
+        // Our broadcast went out this time; now read their response
+        setStatus({ state: 'receiving' });
+        theirPubkey = await startNfcReceive();
+      }
+      await finish(theirPubkey);
+    } catch (err) {
+      console.error('NFC retry error:', err);
+      await fail(err);
+    }
+  };
+
+  // ONE TAP, this phone emulates a card: the other phone's tap does the rest
+  const startAsCard = async () => {
+    let unlisten: (() => void) | undefined;
+    try {
+      setStatus({ state: 'offering' });
+      unlisten = await onHceExchange(async (outcome) => {
+        unlisten?.();
+        if (outcome.error) {
+          setStatus({ state: 'error', message: outcome.error.message });
+        } else if (outcome.theirPubkey) {
+          await finish(outcome.theirPubkey).catch((err) =>
+            setStatus({ state: 'error', message: errorMessage(err) }));
+        }
       });
+      await startHceExchange();
+    } catch (err) {
+      unlisten?.();
+      console.error('NFC one-tap error:', err);
+      setStatus({ state: 'error', message: errorMessage(err) });
+    }
+  };
+
+  // ONE TAP, this phone reads: tap the phone that chose "Be Tapped"
+  const startAsReader = async () => {
+    try {
+      setStatus({ state: 'receiving' });
+      await finish(await tapHceExchange());
+    } catch (err) {
+      console.error('NFC one-tap error:', err);
+      setStatus({ state: 'error', message: errorMessage(err) });
+    }
+  };
+
+  // Put our contact on a blank sticker for a business card or poster
+  const writeSticker = async () => {
+    try {
+      setStatus({ state: 'writing-tag' });
+      await writeContactTag();
+      setStatus({ state: 'tag-written' });
+    } catch (err) {
+      console.error('NFC sticker error:', err);
+      setStatus({ state: 'error', message: errorMessage(err) });
     }
   };
 
   const cancelExchange = () => {
+    resetNfcExchange().catch(() => {});
     setStatus({ state: 'idle' });
   };
 
@@ -145,6 +262,8 @@ const NFCExchange: Component<NFCExchangeProps> = (props) => {
       case 'sending':
       case 'receiving':
       case 'responding':
+      case 'offering':
+      case 'writing-tag':
       case 'verifying':
         return (
           <svg class="nfc-icon scanning" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="1.5">
@@ -160,6 +279,8 @@ const NFCExchange: Component<NFCExchangeProps> = (props) => {
           </svg>
         );
       case 'complete':
+      case 'tag-written':
+      case 'unverified':
         return (
           <svg class="nfc-icon" viewBox="0 0 24 24" fill="none" stroke="var(--success)" stroke-width="2">
             <path d="M22 11.08V12a10 10 0 1 1-5.93-9.14" />
@@ -203,6 +324,14 @@ const NFCExchange: Component<NFCExchangeProps> = (props) => {
         return 'Key Received!';
       case 'responding':
         return 'Sending Response...';
+      case 'offering':
+        return 'Ready to Be Tapped';
+      case 'writing-tag':
+        return 'Hold a Blank Sticker';
+      case 'tag-written':
+        return 'Sticker Written!';
+      case 'unverified':
+        return 'Contact Saved (Unverified)';
       case 'verifying':
         return 'Verifying...';
       case 'complete':
@@ -229,6 +358,14 @@ const NFCExchange: Component<NFCExchangeProps> = (props) => {
         return `Received: ${s.theirPubkey.slice(0, 16)}...`;
       case 'responding':
         return 'Sending your signed response...';
+      case 'offering':
+        return 'Hold still while the other phone taps yours';
+      case 'writing-tag':
+        return 'Hold a blank NFC sticker (NTAG) to your device';
+      case 'tag-written':
+        return 'Anyone who taps it can add you for the next few weeks';
+      case 'unverified':
+        return "From another Nostr app's tag. Exchange in person to verify them";
       case 'verifying':
         return 'Verifying signatures and deriving keys';
       case 'complete':
@@ -240,7 +377,7 @@ const NFCExchange: Component<NFCExchangeProps> = (props) => {
 
   const isActive = () => {
     const s = status();
-    return ['sending', 'sent', 'receiving', 'received', 'responding', 'verifying'].includes(s.state);
+    return ['sending', 'sent', 'receiving', 'received', 'responding', 'offering', 'writing-tag', 'verifying'].includes(s.state);
   };
 
   return (
@@ -252,6 +389,9 @@ const NFCExchange: Component<NFCExchangeProps> = (props) => {
       </h2>
       
       <p class="status-detail">{getStatusDetail()}</p>
+      <Show when={isActive() && phase() && PHASE_DETAIL[phase()!]}>
+        <p class="status-detail">{PHASE_DETAIL[phase()!]}</p>
+      </Show>
 
       {/* Idle state - Start button */}
       <Show when={status().state === 'idle'}>
@@ -262,6 +402,20 @@ const NFCExchange: Component<NFCExchangeProps> = (props) => {
         >
           Start NFC Exchange
         </button>
+        <button
+          class="btn btn-text"
+          onClick={writeSticker}
+          disabled={!props.keys}
+          style={{ "margin-top": "12px" }}
+        >
+          Write Contact Sticker
+        </button>
+      </Show>
+
+      <Show when={status().state === 'tag-written' || status().state === 'unverified'}>
+        <button class="btn btn-primary" onClick={() => setStatus({ state: 'idle' })}>
+          Done
+        </button>
       </Show>
 
       {/* Role selection */}
@@ -283,6 +437,28 @@ const NFCExchange: Component<NFCExchangeProps> = (props) => {
             Receive First
           </button>
           <p class="role-hint">Choose this if the other device will Send</p>
+
+          <Show when={oneTap().offer}>
+            <button
+              class="btn btn-secondary"
+              onClick={startAsCard}
+              style={{ "margin-top": "16px" }}
+            >
+              Be Tapped (One Tap)
+            </button>
+          </Show>
+          <Show when={oneTap().tap}>
+            <button
+              class="btn btn-secondary"
+              onClick={startAsReader}
+              style={{ "margin-top": "8px" }}
+            >
+              Tap Them (One Tap)
+            </button>
+          </Show>
+          <Show when={oneTap().offer || oneTap().tap}>
+            <p class="role-hint">Both phones Android: one is tapped, the other taps, and you're done</p>
+          </Show>
           
           <button 
             class="btn btn-text" 
@@ -303,6 +479,11 @@ const NFCExchange: Component<NFCExchangeProps> = (props) => {
 
       {/* Error state - Try again */}
       <Show when={status().state === 'error'}>
+        <Show when={(status() as { canRetryWrite?: boolean }).canRetryWrite}>
+          <button class="btn btn-primary" onClick={retryWrite} style={{ "margin-top": "16px" }}>
+            Tap Again
+          </button>
+        </Show>
         <button 
           class="btn btn-primary" 
           onClick={startExchange}
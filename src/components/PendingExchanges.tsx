@@ -0,0 +1,71 @@
+import { createSignal, onCleanup, onMount, type Component, For, Show } from 'solid-js';
+import type { PendingExchange } from '../lib/types';
+import { getPendingExchanges, cancelPendingExchange, onPendingExchangesExpired } from '../lib/tauri';
+
+// How often the list (and its countdowns) is refreshed
+const REFRESH_MS = 5000;
+
+const truncatePubkey = (pubkey: string) => `${pubkey.slice(0, 8)}...${pubkey.slice(-8)}`;
+
+// Exchanges that have heard from a peer but not finished, so a half-done
+// exchange can be seen, finished on the other device or cancelled
+const PendingExchanges: Component = () => {
+  const [pending, setPending] = createSignal<PendingExchange[]>([]);
+  const [expired, setExpired] = createSignal<string | null>(null);
+
+  const refresh = async () => {
+    try {
+      setPending(await getPendingExchanges());
+    } catch (err) {
+      console.error('Failed to load pending exchanges:', err);
+    }
+  };
+
+  const cancelOne = async (theirPubkey: string) => {
+    await cancelPendingExchange(theirPubkey);
+    await refresh();
+  };
+
+  onMount(async () => {
+    await refresh();
+    const timer = setInterval(refresh, REFRESH_MS);
+    const unlisten = await onPendingExchangesExpired((gone) => {
+      const names = gone.map((p) => truncatePubkey(p.theirPubkey)).join(', ');
+      setExpired(`Timed out before they finished: ${names}`);
+      refresh();
+    });
+    onCleanup(() => {
+      clearInterval(timer);
+      unlisten();
+    });
+  });
+
+  return (
+    <Show when={pending().length > 0 || expired()}>
+      <div class="pending-exchanges">
+        <h3>Waiting to finish</h3>
+        <Show when={expired()}>
+          <p class="status-detail">{expired()}</p>
+        </Show>
+        <ul class="pending-list">
+          <For each={pending()}>
+            {(p) => (
+              <li class="pending-item">
+                <span>
+                  {truncatePubkey(p.theirPubkey)} · {p.transport.toUpperCase()}
+                  {p.responded ? ' · answered' : ''}
+                </span>
+                <span class="pending-expiry">{Math.ceil(p.expiresInSecs / 60)} min left</span>
+                <button class="btn btn-secondary" onClick={() => cancelOne(p.theirPubkey)}>
+                  Cancel
+                </button>
+              </li>
+            )}
+          </For>
+        </ul>
+      </div>
+    </Show>
+  );
+};
+
+export default PendingExchanges;
@@ -1,17 +1,40 @@
 import { createSignal, onCleanup, type Component, Show } from 'solid-js';
 import QRCode from 'qrcode';
 import { scan, cancel, Format } from '@tauri-apps/plugin-barcode-scanner';
-import type { NostrKeys, Contact, QRExchangeStatus } from '../lib/types';
-import { getExchangeQrPayload, processScannedQr, completeExchange } from '../lib/tauri';
+import type { NostrKeys, Contact, QRExchangeStatus, ScanError } from '../lib/types';
+import { getExchangeQrPayload, processScannedQr, completeExchange, getExchangeUri, createWebLink, isWebLink, openWebLink, getExchangeTextPayload, processExchangeText, getExchangeSas, getSessionCode, confirmExchangeSas, cancelPendingExchange } from '../lib/tauri';
 
 interface QRExchangeProps {
   keys: NostrKeys | null;
   onComplete: (contact: Contact) => void;
 }
 
+// process_scanned_qr rejects with a ScanError explaining what went wrong
+const errorMessage = (err: unknown, fallback: string): string => {
+  if (err instanceof Error) return err.message;
+  if (typeof err === 'object' && err !== null && 'message' in err) {
+    return (err as ScanError).message;
+  }
+  return typeof err === 'string' ? err : fallback;
+};
+
+// Time each frame of an animated code stays on screen
+const FRAME_INTERVAL_MS = 250;
+
 const QRExchange: Component<QRExchangeProps> = (props) => {
   const [status, setStatus] = createSignal<QRExchangeStatus>({ state: 'idle' });
   const [qrDataUrl, setQrDataUrl] = createSignal<string | null>(null);
+  const [linkCopied, setLinkCopied] = createSignal(false);
+  const [webLinkCopied, setWebLinkCopied] = createSignal(false);
+  const [textNotice, setTextNotice] = createSignal<string | null>(null);
+  let frameTimer: ReturnType<typeof setInterval> | undefined;
+
+  const stopAnimation = () => {
+    if (frameTimer !== undefined) {
+      clearInterval(frameTimer);
+      frameTimer = undefined;
+    }
+  };
 
   // Generate QR code when showing
   const generateQR = async (theirPk?: string) => {
@@ -21,20 +44,28 @@ const QRExchange: Component<QRExchangeProps> = (props) => {
     }
 
     try {
-      // Get the exchange payload
-      const payload = await getExchangeQrPayload(theirPk);
+      // Get the exchange payload (several frames if it's too large for one code)
+      const frames = await getExchangeQrPayload(theirPk);
       
-      // Generate QR code
-      const dataUrl = await QRCode.toDataURL(payload, {
+      // Generate QR codes
+      const dataUrls = await Promise.all(frames.map((frame) => QRCode.toDataURL(frame, {
         width: 280,
         margin: 2,
         color: {
           dark: '#1a1a2e',
           light: '#ffffff'
         }
-      });
+      })));
       
-      setQrDataUrl(dataUrl);
+      stopAnimation();
+      setQrDataUrl(dataUrls[0]);
+      if (dataUrls.length > 1) {
+        let index = 0;
+        frameTimer = setInterval(() => {
+          index = (index + 1) % dataUrls.length;
+          setQrDataUrl(dataUrls[index]);
+        }, FRAME_INTERVAL_MS);
+      }
       setStatus({ state: 'showing-qr' });
     } catch (err) {
       console.error('QR generation error:', err);
@@ -45,45 +76,145 @@ const QRExchange: Component<QRExchangeProps> = (props) => {
     }
   };
 
+  // Share our payload as a sneakernet:// link for someone far away; they
+  // open it and send their reply link back
+  const shareLink = async () => {
+    try {
+      const uri = await getExchangeUri();
+      if (navigator.share) {
+        await navigator.share({ title: 'SneakerNet', url: uri });
+      } else {
+        await navigator.clipboard.writeText(uri);
+        setLinkCopied(true);
+      }
+    } catch (err) {
+      setStatus({ state: 'error', message: errorMessage(err, 'Failed to share link') });
+    }
+  };
+
+  // Share a one-time https:// link instead, for messengers that won't open
+  // app links; it expires after an hour. Without a web link page set in
+  // settings this is our sneakernet:// link
+  const shareWebLink = async () => {
+    try {
+      const url = await createWebLink();
+      if (navigator.share) {
+        await navigator.share({ title: 'SneakerNet', url });
+      } else {
+        await navigator.clipboard.writeText(url);
+        setWebLinkCopied(true);
+      }
+    } catch (err) {
+      setStatus({ state: 'error', message: errorMessage(err, 'Failed to create web link') });
+    }
+  };
+
+  // When nothing else works: copy our payload as text for email or chat
+  const copyText = async () => {
+    try {
+      await navigator.clipboard.writeText(await getExchangeTextPayload());
+      setTextNotice('Your code was copied as text. Paste it into a message to them.');
+    } catch (err) {
+      setStatus({ state: 'error', message: errorMessage(err, 'Failed to copy text') });
+    }
+  };
+
+  // Take their pasted code or web link; if it was their first message, our
+  // reply goes to the clipboard
+  const pasteText = async () => {
+    try {
+      const text = await navigator.clipboard.readText();
+      const result = isWebLink(text) ? await openWebLink(text.trim()) : await processExchangeText(text);
+      const contact = await completeExchange(result.theirPubkey);
+      if (result.needsReply) {
+        await navigator.clipboard.writeText(await getExchangeTextPayload(result.theirPubkey));
+        setTextNotice('Contact added. Your reply was copied: send it back to them.');
+      } else {
+        setTextNotice(null);
+      }
+      props.onComplete(contact);
+    } catch (err) {
+      setStatus({ state: 'error', message: errorMessage(err, 'Failed to read pasted text') });
+    }
+  };
+
   // Start scanning
   const startScanning = async () => {
     try {
       setStatus({ state: 'scanning' });
       
-      // Use Tauri barcode scanner
-      const result = await scan({
-        windowed: false,
-        formats: [Format.QRCode],
-      });
+      // Use Tauri barcode scanner, scanning again until an animated code is complete
+      let pubkey: string | null = null;
+      while (pubkey === null) {
+        const result = await scan({
+          windowed: false,
+          formats: [Format.QRCode],
+        });
+        if (!result.content) break;
+
+        const scanned = await processScannedQr(result.content);
+        if (scanned.unverifiedContact) {
+          const contact = scanned.unverifiedContact;
+          setStatus({ state: 'complete', contact });
+          setTimeout(() => {
+            props.onComplete(contact);
+            reset();
+          }, 3000);
+          return;
+        }
+        pubkey = scanned.theirPubkey;
+        if (pubkey === null) {
+          setStatus({ state: 'scanning', progress: scanned.progress });
+        }
+      }
       
-      if (result.content) {
-        // Process the scanned data
-        setStatus({ state: 'processing', theirPubkey: '' });
-        const pubkey = await processScannedQr(result.content);
+      if (pubkey) {
         setStatus({ state: 'processing', theirPubkey: pubkey });
         
-        // Complete the exchange
-        const contact = await completeExchange(pubkey);
-        setStatus({ state: 'complete', contact });
-        
         // Show QR with their pubkey included (for mutual verification)
         await generateQR(pubkey);
+        if (status().state === 'error') return;
         
-        // Notify parent after a brief delay
-        setTimeout(() => {
-          props.onComplete(contact);
-          reset();
-        }, 3000);
+        // Both people compare the short code before the contact is saved
+        const [sas, sessionCode] = await Promise.all([getExchangeSas(pubkey), getSessionCode(pubkey)]);
+        setStatus({ state: 'confirming', theirPubkey: pubkey, sas, sessionCode });
       }
     } catch (err) {
       console.error('Scan error:', err);
       setStatus({ 
         state: 'error', 
-        message: err instanceof Error ? err.message : 'Scan failed' 
+        message: errorMessage(err, 'Scan failed')
       });
     }
   };
 
+  // The codes match on both screens: save the contact
+  const confirmSas = async (theirPubkey: string) => {
+    try {
+      await confirmExchangeSas(theirPubkey);
+      const contact = await completeExchange(theirPubkey);
+      setStatus({ state: 'complete', contact });
+
+      // Notify parent after a brief delay
+      setTimeout(() => {
+        props.onComplete(contact);
+        reset();
+      }, 3000);
+    } catch (err) {
+      setStatus({ state: 'error', message: errorMessage(err, 'Failed to save contact') });
+    }
+  };
+
+  // The codes differ: someone may have swapped a QR code in between
+  const rejectSas = async (theirPubkey: string) => {
+    await cancelPendingExchange(theirPubkey).catch(() => {});
+    stopAnimation();
+    setStatus({
+      state: 'error',
+      message: 'The codes did not match, so the exchange was cancelled. Try again in person.',
+    });
+  };
+
   // Cancel scanning
   const cancelScanning = async () => {
     try {
@@ -96,12 +227,14 @@ const QRExchange: Component<QRExchangeProps> = (props) => {
 
   // Reset state
   const reset = () => {
+    stopAnimation();
     setStatus({ state: 'idle' });
     setQrDataUrl(null);
   };
 
   // Cleanup on unmount
   onCleanup(async () => {
+    stopAnimation();
     if (status().state === 'scanning') {
       try {
         await cancel();
@@ -162,6 +295,8 @@ const QRExchange: Component<QRExchangeProps> = (props) => {
         return 'Scanning...';
       case 'processing':
         return 'Processing...';
+      case 'confirming':
+        return 'Compare Codes';
       case 'complete':
         return 'Exchange Complete!';
       case 'error':
@@ -177,11 +312,17 @@ const QRExchange: Component<QRExchangeProps> = (props) => {
       case 'showing-qr':
         return 'Have the other person scan this code, then scan theirs';
       case 'scanning':
-        return 'Point camera at the other device\'s QR code';
+        return s.progress
+          ? `Keep scanning: ${s.progress.received} of ${s.progress.total} parts`
+          : 'Point camera at the other device\'s QR code';
       case 'processing':
         return s.theirPubkey ? `Processing: ${s.theirPubkey.slice(0, 16)}...` : 'Verifying...';
+      case 'confirming':
+        return 'Let them scan this code, then check both screens show the same emoji and digits';
       case 'complete':
-        return 'Contact added successfully';
+        return s.contact.origin === 'nip19'
+          ? 'Contact saved (unverified): exchange in person to verify'
+          : 'Contact added successfully';
       case 'error':
         return s.message;
     }
@@ -198,7 +339,7 @@ const QRExchange: Component<QRExchangeProps> = (props) => {
         {getStatusIcon()}
       </Show>
       
-      <Show when={qrDataUrl() && (status().state === 'showing-qr' || status().state === 'complete')}>
+      <Show when={qrDataUrl() && ['showing-qr', 'confirming', 'complete'].includes(status().state)}>
         <div class="qr-container">
           <img src={qrDataUrl()!} alt="QR Code" class="qr-image" />
         </div>
@@ -227,6 +368,41 @@ const QRExchange: Component<QRExchangeProps> = (props) => {
           >
             Scan QR Code
           </button>
+          <button 
+            class="btn btn-secondary" 
+            onClick={shareLink}
+            disabled={!props.keys}
+            style={{ "margin-top": "12px" }}
+          >
+            {linkCopied() ? 'Link Copied' : 'Share as Link'}
+          </button>
+          <button 
+            class="btn btn-secondary" 
+            onClick={shareWebLink}
+            disabled={!props.keys}
+            style={{ "margin-top": "12px" }}
+          >
+            {webLinkCopied() ? 'Web Link Copied' : 'Share as Web Link'}
+          </button>
+          <button 
+            class="btn btn-secondary" 
+            onClick={copyText}
+            disabled={!props.keys}
+            style={{ "margin-top": "12px" }}
+          >
+            Copy as Text
+          </button>
+          <button 
+            class="btn btn-secondary" 
+            onClick={pasteText}
+            disabled={!props.keys}
+            style={{ "margin-top": "12px" }}
+          >
+            Paste Their Text
+          </button>
+          <Show when={textNotice()}>
+            <p class="status-detail">{textNotice()}</p>
+          </Show>
         </div>
       </Show>
 
@@ -248,6 +424,30 @@ const QRExchange: Component<QRExchangeProps> = (props) => {
         </div>
       </Show>
 
+      <Show when={(() => { const s = status(); return s.state === 'confirming' ? s : null; })()}>
+        {(s) => (
+          <div class="sas">
+            <div class="sas-emoji">{s().sas.emoji.join(' ')}</div>
+            <div class="sas-digits">{s().sas.digits}</div>
+            <Show when={s().sessionCode}>
+              <div class="sas-session">Session: {s().sessionCode}</div>
+            </Show>
+            <div class="qr-buttons">
+              <button class="btn btn-primary" onClick={() => confirmSas(s().theirPubkey)}>
+                They Match
+              </button>
+              <button
+                class="btn btn-secondary"
+                onClick={() => rejectSas(s().theirPubkey)}
+                style={{ "margin-top": "12px" }}
+              >
+                They Don't Match
+              </button>
+            </div>
+          </div>
+        )}
+      </Show>
+
       <Show when={isActive()}>
         <button class="btn btn-secondary" onClick={cancelScanning}>
           Cancel
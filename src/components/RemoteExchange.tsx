@@ -0,0 +1,175 @@
+import { createSignal, onMount, type Component, For, Show } from 'solid-js';
+import type { NostrKeys, Contact, RemoteExchangeStatus } from '../lib/types';
+import {
+  startRemoteExchange,
+  checkRemoteExchanges,
+  acceptRemoteExchange,
+  declineRemoteExchange,
+  cancelRemoteExchange,
+  setRemoteRelays,
+  getRemoteExchanges,
+} from '../lib/tauri';
+
+interface RemoteExchangeProps {
+  keys: NostrKeys | null;
+  onContacts: (contacts: Contact[]) => void;
+}
+
+const truncatePubkey = (pubkey: string) => `${pubkey.slice(0, 8)}...${pubkey.slice(-8)}`;
+
+// One relay URL per line or comma
+const parseRelays = (text: string) => text.split(/[\s,]+/).filter((r) => r.length > 0);
+
+// Exchange with someone who can't be met, over encrypted Nostr DMs. Both
+// sides sign as usual, but the contact stays unverified until you meet.
+const RemoteExchange: Component<RemoteExchangeProps> = (props) => {
+  const [status, setStatus] = createSignal<RemoteExchangeStatus | null>(null);
+  const [identifier, setIdentifier] = createSignal('');
+  const [relays, setRelays] = createSignal('');
+  const [watched, setWatched] = createSignal('');
+  const [busy, setBusy] = createSignal(false);
+  const [notice, setNotice] = createSignal<string | null>(null);
+  const [error, setError] = createSignal<string | null>(null);
+
+  const refresh = async () => {
+    const current = await getRemoteExchanges();
+    setStatus(current);
+    setWatched(current.relays.join('\n'));
+  };
+
+  onMount(refresh);
+
+  // Run `action` with the buttons disabled, showing any error
+  const run = async (action: () => Promise<void>) => {
+    try {
+      setError(null);
+      setNotice(null);
+      setBusy(true);
+      await action();
+      await refresh();
+    } catch (err) {
+      setError(err instanceof Error ? err.message : String(err));
+    } finally {
+      setBusy(false);
+    }
+  };
+
+  const send = () => run(async () => {
+    await startRemoteExchange(identifier().trim(), parseRelays(relays()));
+    setIdentifier('');
+    setNotice('Sent. Check back once they have answered.');
+  });
+
+  const check = () => run(async () => {
+    const result = await checkRemoteExchanges();
+    if (result.completed.length > 0) props.onContacts(result.completed);
+    setNotice(result.completed.length > 0
+      ? `${result.completed.length} exchange(s) completed`
+      : 'Nothing new');
+  });
+
+  const accept = (theirPubkey: string) => run(async () => {
+    props.onContacts([await acceptRemoteExchange(theirPubkey)]);
+  });
+
+  const saveWatched = () => run(async () => {
+    await setRemoteRelays(parseRelays(watched()));
+  });
+
+  return (
+    <div class="qr-exchange remote-exchange">
+      <h2 class="status-text">Remote Exchange</h2>
+      <p class="status-detail">
+        For someone you can't meet. They'll be saved as unverified until you exchange in person.
+      </p>
+
+      <div class="remote-form">
+        <input
+          type="text"
+          placeholder="npub1… or name@domain"
+          value={identifier()}
+          onInput={(e) => setIdentifier(e.currentTarget.value)}
+        />
+        <textarea
+          placeholder="Relays, e.g. wss://relay.example.com (blank: theirs)"
+          value={relays()}
+          onInput={(e) => setRelays(e.currentTarget.value)}
+        />
+        <button
+          class="btn btn-primary"
+          onClick={send}
+          disabled={!props.keys || busy() || identifier().trim().length === 0}
+        >
+          Send Exchange
+        </button>
+        <button class="btn btn-secondary" onClick={check} disabled={!props.keys || busy()}>
+          Check for Answers
+        </button>
+      </div>
+
+      <Show when={notice()}>
+        <p class="status-detail">{notice()}</p>
+      </Show>
+      <Show when={error()}>
+        <p class="status-detail status-error">{error()}</p>
+      </Show>
+
+      <Show when={(status()?.requests.length ?? 0) > 0}>
+        <h3>Waiting for you</h3>
+        <ul class="pending-list">
+          <For each={status()!.requests}>
+            {(r) => (
+              <li class="pending-item">
+                <span>{r.displayName ?? truncatePubkey(r.theirPubkey)}</span>
+                <button class="btn btn-primary" onClick={() => accept(r.theirPubkey)} disabled={busy()}>
+                  Accept
+                </button>
+                <button
+                  class="btn btn-secondary"
+                  onClick={() => run(() => declineRemoteExchange(r.theirPubkey))}
+                  disabled={busy()}
+                >
+                  Decline
+                </button>
+              </li>
+            )}
+          </For>
+        </ul>
+      </Show>
+
+      <Show when={(status()?.pending.length ?? 0) > 0}>
+        <h3>Waiting for them</h3>
+        <ul class="pending-list">
+          <For each={status()!.pending}>
+            {(p) => (
+              <li class="pending-item">
+                <span>{truncatePubkey(p.theirPubkey)}</span>
+                <button
+                  class="btn btn-secondary"
+                  onClick={() => run(() => cancelRemoteExchange(p.theirPubkey))}
+                  disabled={busy()}
+                >
+                  Cancel
+                </button>
+              </li>
+            )}
+          </For>
+        </ul>
+      </Show>
+
+      <div class="remote-form">
+        <label for="remote-watched">Relays to check for new requests</label>
+        <textarea
+          id="remote-watched"
+          value={watched()}
+          onInput={(e) => setWatched(e.currentTarget.value)}
+        />
+        <button class="btn btn-secondary" onClick={saveWatched} disabled={busy()}>
+          Save Relays
+        </button>
+      </div>
+    </div>
+  );
+};
+
+export default RemoteExchange;
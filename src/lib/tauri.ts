@@ -1,5 +1,6 @@
 import { invoke } from '@tauri-apps/api/core';
-import type { Contact, NostrKeys, IrohStatus, ChatMessage } from './types';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
+import type { Contact, NostrKeys, KeyProtection, KeyAuditEntry, RotationStatement, RotationResult, RevocationResult, RemoteSignerInfo, DeviceCertificate, MigrationProgress, SafetyNumber, ShortAuthString, GuardianSet, HeldShare, PendingRecoveryRequest, RecoveryStatus, IrohStatus, ChatMessage, NfcExchangeState, HceExchangeOutcome, OneTapSupport, ExchangeStatsSummary, DuplicateGroup, Settings, SettingsPatch, FeatureFlag, FeatureFlags, Group, MembershipChange, BroadcastChannel, BroadcastPost, BeaconStatus, ConferenceStatus, QuarantinedPayload, PendingExchange, ExchangeSession, ExchangeProgress, QrScanResult, RosterScanResult, ExchangeUriResult, LanPeer, LanHostInfo, RemoteExchange, RemoteCheckResult, RemoteExchangeStatus, ContactTrust, NamedContact, ConversationSummary, Report, QuarantinedMessage, PluginInfo, PendingShare, ContactQuery, ContactPage, TrustLevel, ScheduledMessage, ScheduledSent, QueuedMessage, MessageInfo, GroupDelivery, TypingEvent, ExpiredMessage, ProfileCard, ContactShare, PingResult, ConnectionTrace } from './types';
 
 // Key management commands
 export async function hasKeys(): Promise<boolean> {
@@ -10,6 +11,205 @@ export async function generateKeys(): Promise<NostrKeys> {
   return invoke<NostrKeys>('generate_keys');
 }
 
+// Accepts an nsec or a 64-character hex secret key
+export async function importKeys(secretKey: string): Promise<NostrKeys> {
+  return invoke<NostrKeys>('import_keys', { secretKey });
+}
+
+// Watch an identity by its npub; nothing can be signed until importKeys
+export async function initWatchOnly(pubkey: string): Promise<NostrKeys> {
+  return invoke<NostrKeys>('init_watch_only', { pubkey });
+}
+
+// Shamir backup: split the secret key into n QR shares, any k of which
+// restore it on a fresh install
+export async function splitKeyShares(n: number, k: number): Promise<string[]> {
+  return invoke<string[]>('split_key_shares', { n, k });
+}
+
+export async function recoverFromShares(shares: string[]): Promise<NostrKeys> {
+  return invoke<NostrKeys>('recover_from_shares', { shares });
+}
+
+// Secret key export: request a token, show the warning, then pass the token
+// back once the user confirms. The token is single-use and expires after 60s.
+export async function requestSecretKeyExport(): Promise<string> {
+  return invoke<string>('request_secret_key_export');
+}
+
+export async function exportSecretKey(confirmationToken: string): Promise<string> {
+  return invoke<string>('export_secret_key', { confirmationToken });
+}
+
+// Passphrase protection for the stored secret key
+export async function getKeyAuditLog(since?: number): Promise<KeyAuditEntry[]> {
+  return invoke<KeyAuditEntry[]>('get_key_audit_log', { since: since ?? null });
+}
+
+export async function getKeyProtection(): Promise<KeyProtection> {
+  return invoke<KeyProtection>('get_key_protection');
+}
+
+// Sets or changes the passphrase; keys must be unlocked
+export async function setKeyPassphrase(passphrase: string): Promise<void> {
+  return invoke<void>('set_key_passphrase', { passphrase });
+}
+
+export async function unlockKeys(passphrase: string): Promise<NostrKeys> {
+  return invoke<NostrKeys>('unlock_keys', { passphrase });
+}
+
+export async function lockKeys(): Promise<void> {
+  return invoke<void>('lock_keys');
+}
+
+export async function removeKeyPassphrase(passphrase: string): Promise<void> {
+  return invoke<void>('remove_key_passphrase', { passphrase });
+}
+
+// Pair with a NIP-46 signer (Amber, nsecBunker) from its bunker:// URI
+export async function connectRemoteSigner(bunkerUri: string): Promise<RemoteSignerInfo> {
+  return invoke<RemoteSignerInfo>('connect_remote_signer', { bunkerUri });
+}
+
+export async function getRemoteSigner(): Promise<RemoteSignerInfo | null> {
+  return invoke<RemoteSignerInfo | null>('get_remote_signer');
+}
+
+export async function disconnectRemoteSigner(): Promise<void> {
+  return invoke<void>('disconnect_remote_signer');
+}
+
+export async function enableDeviceSubkey(deviceIndex: number, deviceName: string): Promise<DeviceCertificate> {
+  return invoke<DeviceCertificate>('enable_device_subkey', { deviceIndex, deviceName });
+}
+
+export async function getDeviceCertificate(): Promise<DeviceCertificate | null> {
+  return invoke<DeviceCertificate | null>('get_device_certificate');
+}
+
+export async function disableDeviceSubkey(): Promise<void> {
+  return invoke<void>('disable_device_subkey');
+}
+
+export async function exportIdentityQr(passphrase: string): Promise<string[]> {
+  return invoke<string[]>('export_identity_qr', { passphrase });
+}
+
+export async function scanIdentityQr(payload: string): Promise<MigrationProgress> {
+  return invoke<MigrationProgress>('scan_identity_qr', { payload });
+}
+
+export async function importIdentityQr(passphrase: string): Promise<NostrKeys> {
+  return invoke<NostrKeys>('import_identity_qr', { passphrase });
+}
+
+export async function hasAppPin(): Promise<boolean> {
+  return invoke<boolean>('has_app_pin');
+}
+
+// Replaces any key passphrase; changing the PIN clears the duress PIN
+export async function setAppPin(pin: string, currentPin?: string): Promise<void> {
+  return invoke<void>('set_app_pin', { pin, currentPin: currentPin ?? null });
+}
+
+// A second PIN that opens an empty decoy identity
+export async function setDuressPin(pin: string, duressPin: string): Promise<void> {
+  return invoke<void>('set_duress_pin', { pin, duressPin });
+}
+
+export async function unlockWithPin(pin: string): Promise<NostrKeys> {
+  return invoke<NostrKeys>('unlock_with_pin', { pin });
+}
+
+// Replace our key; connected contacts are told over chat, others need the
+// returned statement. The Iroh node is stopped. Sealed keys need the passphrase.
+export async function rotateKeys(passphrase?: string): Promise<RotationResult> {
+  return invoke<RotationResult>('rotate_keys', { passphrase: passphrase ?? null });
+}
+
+export async function getRotationStatements(): Promise<RotationStatement[]> {
+  return invoke<RotationStatement[]>('get_rotation_statements');
+}
+
+// A contact's rotation statement from NFC, QR or a file
+export async function importRotationStatement(payload: string): Promise<Contact> {
+  return invoke<Contact>('import_rotation_statement', { payload });
+}
+
+// Revoke our key as compromised; connected contacts are told over chat and,
+// given relays, it is published there too. Others need the returned statement.
+export async function revokeKey(reason?: string, relays?: string[]): Promise<RevocationResult> {
+  return invoke<RevocationResult>('revoke_key', { reason: reason ?? null, relays: relays ?? null });
+}
+
+// A contact's revocation statement from NFC, QR or a file
+export async function importRevocationStatement(payload: string): Promise<Contact> {
+  return invoke<Contact>('import_revocation_statement', { payload });
+}
+
+// Social recovery. The owner gives each guardian a share over chat; a new
+// device asks for them back from a temporary identity, and each guardian
+// approves after seeing the requester in person.
+export async function distributeRecoveryShares(guardians: string[], threshold: number): Promise<GuardianSet> {
+  return invoke<GuardianSet>('distribute_recovery_shares', { guardians, threshold });
+}
+
+export async function getRecoveryGuardians(): Promise<GuardianSet | null> {
+  return invoke<GuardianSet | null>('get_recovery_guardians');
+}
+
+export async function getHeldRecoveryShares(): Promise<HeldShare[]> {
+  return invoke<HeldShare[]>('get_held_recovery_shares');
+}
+
+export async function getRecoveryRequests(): Promise<PendingRecoveryRequest[]> {
+  return invoke<PendingRecoveryRequest[]>('get_recovery_requests');
+}
+
+export async function approveRecoveryRequest(ownerPubkey: string, requesterPubkey: string): Promise<void> {
+  return invoke<void>('approve_recovery_request', { ownerPubkey, requesterPubkey });
+}
+
+export async function declineRecoveryRequest(ownerPubkey: string, requesterPubkey: string): Promise<void> {
+  return invoke<void>('decline_recovery_request', { ownerPubkey, requesterPubkey });
+}
+
+export async function onRecoveryRequest(handler: (request: PendingRecoveryRequest) => void): Promise<UnlistenFn> {
+  return listen<PendingRecoveryRequest>('recovery://request', (event) => handler(event.payload));
+}
+
+export async function startSocialRecovery(ownerPubkey: string, guardians: string[]): Promise<RecoveryStatus> {
+  return invoke<RecoveryStatus>('start_social_recovery', { ownerPubkey, guardians });
+}
+
+export async function getSocialRecoveryStatus(): Promise<RecoveryStatus | null> {
+  return invoke<RecoveryStatus | null>('get_social_recovery_status');
+}
+
+export async function onRecoveryProgress(handler: (status: RecoveryStatus) => void): Promise<UnlistenFn> {
+  return listen<RecoveryStatus>('recovery://progress', (event) => handler(event.payload));
+}
+
+export async function cancelSocialRecovery(): Promise<void> {
+  return invoke<void>('cancel_social_recovery');
+}
+
+// Replaces the temporary identity with the recovered one
+export async function completeSocialRecovery(): Promise<NostrKeys> {
+  return invoke<NostrKeys>('complete_social_recovery');
+}
+
+// Panic wipe: erases keys, contacts, chat history and all other app data.
+// Listen for onWiped to return to onboarding.
+export async function wipeAllData(): Promise<void> {
+  return invoke<void>('wipe_all_data');
+}
+
+export async function onWiped(handler: () => void): Promise<UnlistenFn> {
+  return listen<null>('app://wiped', () => handler());
+}
+
 export async function getPublicKey(): Promise<NostrKeys> {
   return invoke<NostrKeys>('get_public_key');
 }
@@ -22,6 +222,11 @@ export async function startNfcBroadcast(): Promise<string> {
   return invoke<string>('start_nfc_broadcast');
 }
 
+// Stop serving our exchange message (Android HCE)
+export async function stopNfcBroadcast(): Promise<void> {
+  return invoke<void>('stop_nfc_broadcast');
+}
+
 // Start receiving/scanning for NFC exchange message (receiver mode)
 export async function startNfcReceive(): Promise<string> {
   // Returns the received pubkey from NFC scan
@@ -37,33 +242,565 @@ export async function writeNfcResponse(theirPubkey: string): Promise<void> {
   return invoke<void>('write_nfc_response', { theirPubkey });
 }
 
-export async function completeExchange(theirPubkey: string): Promise<Contact> {
-  return invoke<Contact>('complete_exchange', { theirPubkey });
+// Write the payload prepared for a write that broke off again; resolves to
+// whose response it was, or null for our initial broadcast
+export async function retryNfcWrite(): Promise<string | null> {
+  return invoke<string | null>('retry_nfc_write');
+}
+
+// Write our contact to a blank NFC sticker as a long-lived payload
+export async function writeContactTag(): Promise<void> {
+  return invoke<void>('write_contact_tag');
+}
+
+// One-tap exchange: offer it over Host Card Emulation (Android); returns our pubkey
+export async function startHceExchange(): Promise<string> {
+  return invoke<string>('start_hce_exchange');
+}
+
+// Fired when a phone tapped us and the one-tap exchange finished
+export async function onHceExchange(handler: (outcome: HceExchangeOutcome) => void): Promise<UnlistenFn> {
+  return listen<HceExchangeOutcome>('exchange://hce', (event) => handler(event.payload));
+}
+
+// One-tap exchange: tap a phone offering it; returns their pubkey
+export async function tapHceExchange(): Promise<string> {
+  return invoke<string>('tap_hce_exchange');
+}
+
+// Which sides of the one-tap exchange this build and device support
+export async function getOneTapSupport(): Promise<OneTapSupport> {
+  return invoke<OneTapSupport>('get_one_tap_support');
+}
+
+// Resumable NFC exchange state (survives a lost tap)
+export async function getNfcExchangeState(): Promise<NfcExchangeState> {
+  return invoke<NfcExchangeState>('get_nfc_exchange_state');
+}
+
+export async function resetNfcExchange(): Promise<void> {
+  return invoke<void>('reset_nfc_exchange');
+}
+
+// `note` is an optional context note ("Bitcoin meetup Nov 2025") stored on the contact
+// Short authentication string of a pending exchange, to compare on both devices
+export async function getExchangeSas(theirPubkey: string): Promise<ShortAuthString> {
+  return invoke<ShortAuthString>('get_exchange_sas', { theirPubkey });
+}
+
+// Words naming the exchange session, shown on both screens; null if their app has none
+export async function getSessionCode(theirPubkey: string): Promise<string | null> {
+  return invoke<string | null>('get_session_code', { theirPubkey });
+}
+
+// Required before completing a QR exchange
+export async function confirmExchangeSas(theirPubkey: string): Promise<void> {
+  return invoke('confirm_exchange_sas', { theirPubkey });
+}
+
+export async function completeExchange(theirPubkey: string, note?: string): Promise<Contact> {
+  return invoke<Contact>('complete_exchange', { theirPubkey, note: note ?? null });
+}
+
+// Exchanges in progress with several peers at once, oldest first
+export async function getPendingExchanges(): Promise<PendingExchange[]> {
+  return invoke<PendingExchange[]>('get_pending_exchanges');
+}
+
+export async function cancelPendingExchange(theirPubkey: string): Promise<void> {
+  return invoke<void>('cancel_pending_exchange', { theirPubkey });
+}
+
+// Pending exchanges dropped for taking too long
+export async function onPendingExchangesExpired(handler: (expired: PendingExchange[]) => void): Promise<UnlistenFn> {
+  return listen<PendingExchange[]>('exchange://pending-expired', (event) => handler(event.payload));
+}
+
+// The handshake in the foreground; call on resume to pick up where it stopped
+export async function getExchangeSession(): Promise<ExchangeSession> {
+  return invoke<ExchangeSession>('get_exchange_session');
+}
+
+export async function resetExchangeSession(): Promise<void> {
+  return invoke<void>('reset_exchange_session');
+}
+
+// Fired on every handshake transition
+export async function onExchangeSession(handler: (session: ExchangeSession) => void): Promise<UnlistenFn> {
+  return listen<ExchangeSession>('exchange://session', (event) => handler(event.payload));
+}
+
+// Finer-grained phases of the exchange command in progress (tap detected,
+// payload read, verified...), for a live status line
+export async function onExchangeState(handler: (progress: ExchangeProgress) => void): Promise<UnlistenFn> {
+  return listen<ExchangeProgress>('exchange://state', (event) => handler(event.payload));
+}
+
+// Beacon mode commands (rapid back-to-back exchanges at events)
+export async function startBeaconMode(): Promise<BeaconStatus> {
+  return invoke<BeaconStatus>('start_beacon_mode');
+}
+
+// Poll this to keep the shown payload fresh; it is re-signed every 30 seconds
+export async function getBeaconStatus(): Promise<BeaconStatus | null> {
+  return invoke<BeaconStatus | null>('get_beacon_status');
+}
+
+export async function stopBeaconMode(): Promise<void> {
+  return invoke<void>('stop_beacon_mode');
+}
+
+export async function beaconIngest(payload: string): Promise<Contact> {
+  return invoke<Contact>('beacon_ingest', { payload });
+}
+
+// Inbound exchange payloads whose signatures failed, oldest first
+export async function getQuarantinedPayloads(): Promise<QuarantinedPayload[]> {
+  return invoke<QuarantinedPayload[]>('get_quarantined_payloads');
+}
+
+export async function clearQuarantinedPayloads(): Promise<void> {
+  return invoke<void>('clear_quarantined_payloads');
+}
+
+export async function onPayloadQuarantined(
+  handler: (payload: QuarantinedPayload) => void,
+): Promise<UnlistenFn> {
+  return listen<QuarantinedPayload>('exchange://quarantined', (event) => handler(event.payload));
+}
+
+// Conference mode commands (host a local-network exchange for a window,
+// then approve everyone who joined in one go)
+export async function startConferenceMode(windowSecs?: number): Promise<ConferenceStatus> {
+  return invoke<ConferenceStatus>('start_conference_mode', { windowSecs });
+}
+
+export async function getConferenceStatus(): Promise<ConferenceStatus | null> {
+  return invoke<ConferenceStatus | null>('get_conference_status');
+}
+
+export async function stopConferenceMode(): Promise<ConferenceStatus | null> {
+  return invoke<ConferenceStatus | null>('stop_conference_mode');
+}
+
+export async function approveConferenceAttendees(
+  theirPubkeys: string[],
+  note?: string,
+): Promise<Contact[]> {
+  return invoke<Contact[]>('approve_conference_attendees', { theirPubkeys, note });
+}
+
+export async function dismissConferenceAttendees(
+  theirPubkeys: string[],
+): Promise<ConferenceStatus | null> {
+  return invoke<ConferenceStatus | null>('dismiss_conference_attendees', { theirPubkeys });
+}
+
+// A guest joined the conference, its window closed or attendees were approved
+export async function onConferenceStatus(
+  handler: (status: ConferenceStatus | null) => void,
+): Promise<UnlistenFn> {
+  return listen<ConferenceStatus | null>('exchange://conference', (event) => handler(event.payload));
 }
 
 // QR Exchange commands
-export async function getExchangeQrPayload(theirPubkey?: string): Promise<string> {
-  return invoke<string>('get_exchange_qr_payload', { theirPubkey: theirPubkey ?? null });
+// One QR code, or the frames of an animated code to show in a loop when the payload is too large
+export async function getExchangeQrPayload(theirPubkey?: string, legacy?: boolean): Promise<string[]> {
+  return invoke<string[]>('get_exchange_qr_payload', { theirPubkey: theirPubkey ?? null, legacy: legacy ?? null });
+}
+
+// Accepts a whole code or one animated frame; rejects with a ScanError ({ code, message }) when refused
+export async function processScannedQr(qrData: string): Promise<QrScanResult> {
+  return invoke<QrScanResult>('process_scanned_qr', { qrData });
+}
+
+// Group exchange commands
+// Starts a roster with us on it; returns its QR code frames
+export async function startGroupExchange(): Promise<string[]> {
+  return invoke<string[]>('start_group_exchange');
+}
+
+export async function getGroupExchangePayload(): Promise<string[]> {
+  return invoke<string[]>('get_group_exchange_payload');
+}
+
+// Accepts a whole roster code or one animated frame
+export async function scanGroupExchange(qrData: string): Promise<RosterScanResult> {
+  return invoke<RosterScanResult>('scan_group_exchange', { qrData });
+}
+
+export async function endGroupExchange(): Promise<void> {
+  return invoke('end_group_exchange');
+}
+
+// Link exchange commands
+// A sneakernet://exchange/... link to send through any messenger
+export async function getExchangeUri(theirPubkey?: string): Promise<string> {
+  return invoke<string>('get_exchange_uri', { theirPubkey: theirPubkey ?? null });
+}
+
+// Verified like a scanned QR code; rejects with a ScanError when refused
+export async function processExchangeUri(uri: string): Promise<ExchangeUriResult> {
+  return invoke<ExchangeUriResult>('process_exchange_uri', { uri });
+}
+
+// Our payload as plain text to paste into email or chat
+export async function getExchangeTextPayload(theirPubkey?: string): Promise<string> {
+  return invoke<string>('get_exchange_text_payload', { theirPubkey: theirPubkey ?? null });
+}
+
+// Pasted text with a payload (or exchange link) somewhere in it; verified
+// like a scanned QR code
+export async function processExchangeText(text: string): Promise<ExchangeUriResult> {
+  return invoke<ExchangeUriResult>('process_exchange_text', { text });
+}
+
+// One-time HTTPS link to our encrypted payload on a relay (by default the
+// first one watched for remote exchanges); the key stays in the fragment.
+// Without exchange.webLinkBase set, our plain sneakernet:// link instead
+export async function createWebLink(relay?: string): Promise<string> {
+  return invoke<string>('create_web_link', { relay: relay ?? null });
+}
+
+// A web link on any host, or one the static page handed over
+export function isWebLink(uri: string): boolean {
+  return /^(https:\/\/[^#\s]+|sneakernet:\/\/exchange\/)#[0-9a-f]{64}\./i.test(uri.trim());
+}
+
+// Fetched from its relay, decrypted and verified like a scanned QR code
+export async function openWebLink(url: string): Promise<ExchangeUriResult> {
+  return invoke<ExchangeUriResult>('open_web_link', { url });
+}
+
+// Links the app was opened with; call on startup and on every onExchangeUri
+export async function takePendingExchangeUris(): Promise<string[]> {
+  return invoke<string[]>('take_pending_exchange_uris');
+}
+
+export async function onExchangeUri(handler: () => void): Promise<UnlistenFn> {
+  return listen('exchange://uri', () => handler());
+}
+
+// Audio exchange commands
+// A data:audio/wav URL of our payload, to play in a loop
+export async function startAudioBroadcast(theirPubkey?: string): Promise<string> {
+  return invoke<string>('start_audio_broadcast', { theirPubkey: theirPubkey ?? null });
+}
+
+export async function startAudioReceive(sampleRate: number): Promise<void> {
+  return invoke<void>('start_audio_receive', { sampleRate });
+}
+
+// Resolves with their pubkey once a broadcast has been heard and verified;
+// rejects with a ScanError when what was heard is refused
+export async function pushAudioSamples(samples: number[]): Promise<string | null> {
+  return invoke<string | null>('push_audio_samples', { samples });
+}
+
+export async function stopAudioReceive(): Promise<void> {
+  return invoke<void>('stop_audio_receive');
+}
+
+// Local-network exchange commands
+// Host: resolves with their pubkey once a device has connected and the handshake is done
+export async function hostLanExchange(): Promise<string> {
+  return invoke<string>('host_lan_exchange');
 }
 
-export async function processScannedQr(qrData: string): Promise<string> {
-  return invoke<string>('process_scanned_qr', { qrData });
+// Fired with the name a host is advertised under, to show on its screen
+export async function onLanHost(handler: (info: LanHostInfo) => void): Promise<UnlistenFn> {
+  return listen<LanHostInfo>('exchange://lan-host', (event) => handler(event.payload));
+}
+
+// Guest: browse for hosts for a few seconds, then join one
+export async function discoverLanExchanges(): Promise<LanPeer[]> {
+  return invoke<LanPeer[]>('discover_lan_exchanges');
+}
+
+export async function joinLanExchange(address: string): Promise<string> {
+  return invoke<string>('join_lan_exchange', { address });
+}
+
+export async function cancelLanExchange(): Promise<void> {
+  return invoke<void>('cancel_lan_exchange');
+}
+
+// Remote exchange over encrypted Nostr DMs; the contact is saved unverified.
+// `theirPubkey` is an npub or NIP-05 address; empty `relays` uses theirs.
+export async function startRemoteExchange(theirPubkey: string, relays: string[]): Promise<RemoteExchange> {
+  return invoke<RemoteExchange>('start_remote_exchange', { theirPubkey, relays });
+}
+
+export async function checkRemoteExchanges(): Promise<RemoteCheckResult> {
+  return invoke<RemoteCheckResult>('check_remote_exchanges');
+}
+
+export async function acceptRemoteExchange(theirPubkey: string): Promise<Contact> {
+  return invoke<Contact>('accept_remote_exchange', { theirPubkey });
+}
+
+export async function declineRemoteExchange(theirPubkey: string): Promise<void> {
+  return invoke<void>('decline_remote_exchange', { theirPubkey });
+}
+
+export async function cancelRemoteExchange(theirPubkey: string): Promise<void> {
+  return invoke<void>('cancel_remote_exchange', { theirPubkey });
+}
+
+// Relays watched for requests from people we haven't asked
+export async function setRemoteRelays(relays: string[]): Promise<string[]> {
+  return invoke<string[]>('set_remote_relays', { relays });
+}
+
+export async function getRemoteExchanges(): Promise<RemoteExchangeStatus> {
+  return invoke<RemoteExchangeStatus>('get_remote_exchanges');
+}
+
+// Mock transport (builds with the mock-transport feature): a QR screen and
+// node directory shared by instances on this machine
+export async function mockShowQr(payload: string): Promise<void> {
+  return invoke<void>('mock_show_qr', { payload });
+}
+
+export async function mockScanQr(): Promise<string> {
+  return invoke<string>('mock_scan_qr');
+}
+
+export async function mockLookupNodeId(contactPubkey: string): Promise<string | null> {
+  return invoke<string | null>('mock_lookup_node_id', { contactPubkey });
+}
+
+// Local exchange statistics
+export async function getExchangeStats(): Promise<ExchangeStatsSummary> {
+  return invoke<ExchangeStatsSummary>('get_exchange_stats');
+}
+
+export async function resetExchangeStats(): Promise<void> {
+  return invoke<void>('reset_exchange_stats');
 }
 
 // Contact management commands
-export async function getContacts(): Promise<Contact[]> {
-  return invoke<Contact[]>('get_contacts');
+export async function getContacts(): Promise<NamedContact[]> {
+  return invoke<NamedContact[]>('get_contacts');
 }
 
 export async function deleteContact(id: string): Promise<void> {
   return invoke<void>('delete_contact', { id });
 }
 
+export async function queryContacts(query: ContactQuery, trustLevel?: TrustLevel): Promise<ContactPage> {
+  return invoke<ContactPage>('query_contacts', { query, trustLevel: trustLevel ?? null });
+}
+
+// Omitted fields are left unchanged; an empty nickname or notes string clears it
+export async function updateContact(
+  id: string,
+  update: { nickname?: string; notes?: string; tags?: string[] },
+): Promise<Contact> {
+  return invoke<Contact>('update_contact', {
+    id,
+    nickname: update.nickname ?? null,
+    notes: update.notes ?? null,
+    tags: update.tags ?? null,
+  });
+}
+
+// Returns a signed evidence bundle (JSON) when exportEvidence is set
+export async function reportContact(
+  id: string,
+  reason: string,
+  block: boolean,
+  exportEvidence: boolean,
+): Promise<string | null> {
+  return invoke<string | null>('report_contact', { id, reason, block, exportEvidence });
+}
+
+export async function getReports(): Promise<Report[]> {
+  return invoke<Report[]>('get_reports');
+}
+
+export async function setContactBlocked(id: string, blocked: boolean): Promise<Contact> {
+  return invoke<Contact>('set_contact_blocked', { id, blocked });
+}
+
+export async function markContactReviewed(id: string): Promise<Contact> {
+  return invoke<Contact>('mark_contact_reviewed', { id });
+}
+
+export async function getSafetyNumber(contactId: string): Promise<SafetyNumber> {
+  return invoke<SafetyNumber>('get_safety_number', { contactId });
+}
+
+// Have the next exchange refresh this contact (following a rotated key)
+// instead of being refused as an existing contact
+export async function startReverify(contactId: string): Promise<void> {
+  return invoke('start_reverify', { contactId });
+}
+
+export async function cancelReverify(): Promise<void> {
+  return invoke('cancel_reverify');
+}
+
+// Refresh a contact from the exchange just run with theirPubkey
+export async function reverifyContact(contactId: string, theirPubkey: string, note?: string): Promise<Contact> {
+  return invoke<Contact>('reverify_contact', { contactId, theirPubkey, note: note ?? null });
+}
+
+export async function markContactVerified(id: string, verified: boolean): Promise<Contact> {
+  return invoke<Contact>('mark_contact_verified', { id, verified });
+}
+
+// Signed card vouching for a contact, to show as a QR code or share as a file
+export async function exportContactCard(contactId: string): Promise<string> {
+  return invoke<string>('export_contact_card', { contactId });
+}
+
+// Adds the card's contact as introduced and pending review
+export async function importContactCard(payload: string): Promise<Contact> {
+  return invoke<Contact>('import_contact_card', { payload });
+}
+
+// Someone else's npub/nprofile as a QR payload, to show a third person
+export async function getContactSharePayload(contactId: string): Promise<ContactShare> {
+  return invoke<ContactShare>('get_contact_share_payload', { contactId });
+}
+
+// Send our signed card for a contact to another contact over chat
+export async function introduceContact(contactId: string, toPubkey: string): Promise<ChatMessage> {
+  return invoke<ChatMessage>('introduce_contact', { contactId, toPubkey });
+}
+
+// A contact introduced us to someone; the contact is saved unverified
+export async function onContactIntroduced(handler: (contact: Contact) => void): Promise<UnlistenFn> {
+  return listen<Contact>('contacts://introduced', (event) => handler(event.payload));
+}
+
+// npub or NIP-05 address; the contact is unverified until exchanged in person
+export async function addContactByIdentifier(identifier: string): Promise<Contact> {
+  return invoke<Contact>('add_contact_by_identifier', { identifier });
+}
+
+export async function getMyProfileCard(): Promise<ProfileCard> {
+  return invoke<ProfileCard>('get_my_profile_card');
+}
+
+// A business-card-sized SVG with our static exchange QR code, npub and
+// fingerprint words, for printing
+export async function getPaperCard(): Promise<string> {
+  return invoke<string>('get_paper_card');
+}
+
+// Accepts a SneakerNet profile card or a bare npub; the contact stays unverified
+export async function importProfileCard(payload: string): Promise<Contact> {
+  return invoke<Contact>('import_profile_card', { payload });
+}
+
+export async function getContactTrust(id: string): Promise<ContactTrust> {
+  return invoke<ContactTrust>('get_contact_trust', { id });
+}
+
+// All contacts' trust scores, highest first
+export async function getTrustScores(): Promise<ContactTrust[]> {
+  return invoke<ContactTrust[]>('get_trust_scores');
+}
+
+export async function findDuplicateContacts(): Promise<DuplicateGroup[]> {
+  return invoke<DuplicateGroup[]>('find_duplicate_contacts');
+}
+
+export async function mergeContacts(keepId: string, mergeIds: string[]): Promise<Contact> {
+  return invoke<Contact>('merge_contacts', { keepId, mergeIds });
+}
+
 // Check NFC availability
 export async function isNfcAvailable(): Promise<boolean> {
   return invoke<boolean>('is_nfc_available');
 }
 
+// Settings commands
+export async function getSettings(): Promise<Settings> {
+  return invoke<Settings>('get_settings');
+}
+
+export async function updateSettings(patch: SettingsPatch): Promise<Settings> {
+  return invoke<Settings>('update_settings', { patch });
+}
+
+export async function getFeatureFlags(): Promise<FeatureFlags> {
+  return invoke<FeatureFlags>('get_feature_flags');
+}
+
+export async function setFeatureFlag(flag: FeatureFlag, enabled: boolean): Promise<FeatureFlags> {
+  return invoke<FeatureFlags>('set_feature_flag', { flag, enabled });
+}
+
+// Group commands
+export async function createGroup(name: string): Promise<Group> {
+  return invoke<Group>('create_group', { name });
+}
+
+export async function getGroups(): Promise<Group[]> {
+  return invoke<Group[]>('get_groups');
+}
+
+export async function addGroupMember(groupId: string, memberPubkey: string, admin = false): Promise<MembershipChange> {
+  return invoke<MembershipChange>('add_group_member', { groupId, memberPubkey, admin });
+}
+
+export async function removeGroupMember(groupId: string, memberPubkey: string): Promise<MembershipChange> {
+  return invoke<MembershipChange>('remove_group_member', { groupId, memberPubkey });
+}
+
+export async function leaveGroup(groupId: string): Promise<MembershipChange> {
+  return invoke<MembershipChange>('leave_group', { groupId });
+}
+
+export async function applyMembershipChange(change: MembershipChange): Promise<Group> {
+  return invoke<Group>('apply_membership_change', { change });
+}
+
+// Group invites: inviter shows invite -> invitee shows acceptance -> inviter returns key delivery
+export async function createGroupInvite(groupId: string): Promise<string> {
+  return invoke<string>('create_group_invite', { groupId });
+}
+
+export async function acceptGroupInvite(payload: string): Promise<string> {
+  return invoke<string>('accept_group_invite', { payload });
+}
+
+export async function admitGroupInvitee(payload: string): Promise<string> {
+  return invoke<string>('admit_group_invitee', { payload });
+}
+
+export async function receiveGroupKey(payload: string): Promise<Group> {
+  return invoke<Group>('receive_group_key', { payload });
+}
+
+// Broadcast channel commands
+export async function createBroadcastChannel(name: string): Promise<BroadcastChannel> {
+  return invoke<BroadcastChannel>('create_broadcast_channel', { name });
+}
+
+export async function subscribeBroadcastChannel(ownerPubkey: string, name: string): Promise<BroadcastChannel> {
+  return invoke<BroadcastChannel>('subscribe_broadcast_channel', { ownerPubkey, name });
+}
+
+export async function removeBroadcastChannel(channelId: string): Promise<void> {
+  return invoke<void>('remove_broadcast_channel', { channelId });
+}
+
+export async function getBroadcastChannels(): Promise<BroadcastChannel[]> {
+  return invoke<BroadcastChannel[]>('get_broadcast_channels');
+}
+
+export async function publishBroadcast(channelId: string, content: string): Promise<BroadcastPost> {
+  return invoke<BroadcastPost>('publish_broadcast', { channelId, content });
+}
+
+export async function receiveBroadcastPost(post: BroadcastPost): Promise<boolean> {
+  return invoke<boolean>('receive_broadcast_post', { post });
+}
+
 // Iroh chat commands
 export async function startIroh(contactPubkey: string): Promise<IrohStatus> {
   return invoke<IrohStatus>('start_iroh', { contactPubkey });
@@ -81,10 +818,177 @@ export async function connectToContact(contactPubkey: string, theirNodeId: strin
   return invoke<void>('connect_to_contact', { contactPubkey, theirNodeId });
 }
 
+// Pass theirNodeId to connect first when there is no open connection
+export async function pingContact(contactId: string, theirNodeId?: string): Promise<PingResult> {
+  return invoke<PingResult>('ping_contact', { contactId, theirNodeId: theirNodeId ?? null });
+}
+
+export async function startConnectionTrace(contactPubkey: string): Promise<string> {
+  return invoke<string>('start_connection_trace', { contactPubkey });
+}
+
+export async function stopConnectionTrace(): Promise<ConnectionTrace> {
+  return invoke<ConnectionTrace>('stop_connection_trace');
+}
+
+// Queued in the outbox (and returned pending) if the contact can't be reached; sent once they reconnect
 export async function sendMessage(contactPubkey: string, content: string): Promise<ChatMessage> {
   return invoke<ChatMessage>('send_message', { contactPubkey, content });
 }
 
+// Sent to every connected member; per-member delivery is in getMessageInfo
+export async function sendGroupMessage(groupId: string, content: string): Promise<ChatMessage> {
+  return invoke<ChatMessage>('send_group_message', { groupId, content });
+}
+
+// Send read receipts for group messages to their senders
+export async function markGroupRead(groupId: string, messageIds: string[]): Promise<void> {
+  return invoke<void>('mark_group_read', { groupId, messageIds });
+}
+
+// Fired when a member's receipt for one of our group messages counts
+export async function onGroupReceipt(handler: (delivery: GroupDelivery) => void): Promise<UnlistenFn> {
+  return listen<GroupDelivery>('chat://group-receipt', (event) => handler(event.payload));
+}
+
+export async function getOutbox(contactPubkey?: string): Promise<QueuedMessage[]> {
+  return invoke<QueuedMessage[]>('get_outbox', { contactPubkey: contactPubkey ?? null });
+}
+
+export async function retryMessage(messageId: string): Promise<ChatMessage> {
+  return invoke<ChatMessage>('retry_message', { messageId });
+}
+
+export async function cancelQueuedMessage(messageId: string): Promise<void> {
+  return invoke<void>('cancel_queued_message', { messageId });
+}
+
+// Fired with a queued message once it has gone out
+export async function onOutboxSent(handler: (message: ChatMessage) => void): Promise<UnlistenFn> {
+  return listen<ChatMessage>('chat://outbox-sent', (event) => handler(event.payload));
+}
+
+export async function sendTypedMessage(
+  contactPubkey: string,
+  kind: string,
+  payload: unknown
+): Promise<ChatMessage> {
+  return invoke<ChatMessage>('send_typed_message', { contactPubkey, kind, payload });
+}
+
 export async function getMessages(contactPubkey: string): Promise<ChatMessage[]> {
   return invoke<ChatMessage[]>('get_messages', { contactPubkey });
 }
+
+export async function getMessageInfo(messageId: string): Promise<MessageInfo> {
+  return invoke<MessageInfo>('get_message_info', { messageId });
+}
+
+export async function getMessageKinds(): Promise<string[]> {
+  return invoke<string[]>('get_message_kinds');
+}
+
+export async function getPlugins(): Promise<PluginInfo[]> {
+  return invoke<PluginInfo[]>('get_plugins');
+}
+
+export async function getQuarantinedMessages(): Promise<QuarantinedMessage[]> {
+  return invoke<QuarantinedMessage[]>('get_quarantined_messages');
+}
+
+export async function releaseQuarantinedMessage(messageId: string): Promise<ChatMessage> {
+  return invoke<ChatMessage>('release_quarantined_message', { messageId });
+}
+
+export async function discardQuarantinedMessage(messageId: string): Promise<void> {
+  return invoke<void>('discard_quarantined_message', { messageId });
+}
+
+export async function getConversations(): Promise<ConversationSummary[]> {
+  return invoke<ConversationSummary[]>('get_conversations');
+}
+
+// Fired by the background listener for every accepted incoming message
+export async function onChatMessage(handler: (message: ChatMessage) => void): Promise<UnlistenFn> {
+  return listen<ChatMessage>('chat://message', (event) => handler(event.payload));
+}
+
+// Edit a text message we sent, within 15 minutes of sending it; returns it with the new content
+export async function editMessage(contactPubkey: string, messageId: string, content: string): Promise<ChatMessage> {
+  return invoke<ChatMessage>('edit_message', { contactPubkey, messageId, content });
+}
+
+// Fired with a message the contact edited, holding its new content
+export async function onMessageEdited(handler: (message: ChatMessage) => void): Promise<UnlistenFn> {
+  return listen<ChatMessage>('chat://edited', (event) => handler(event.payload));
+}
+
+// Delete a message here; forEveryone (own messages only) also replaces the contact's copy with a placeholder
+export async function deleteMessage(messageId: string, forEveryone: boolean): Promise<void> {
+  return invoke('delete_message', { messageId, forEveryone });
+}
+
+// Fired with the placeholder of a message the contact deleted
+export async function onMessageDeleted(handler: (message: ChatMessage) => void): Promise<UnlistenFn> {
+  return listen<ChatMessage>('chat://deleted', (event) => handler(event.payload));
+}
+
+// Tell a contact whether we're typing; repeat every few seconds while typing (lossy datagrams)
+export async function setTyping(contactPubkey: string, typing: boolean): Promise<void> {
+  return invoke('set_typing', { contactPubkey, typing });
+}
+
+// Fired when a contact starts or stops typing; drop the indicator after expiresInSecs without a refresh
+export async function onTyping(handler: (event: TypingEvent) => void): Promise<UnlistenFn> {
+  return listen<TypingEvent>('chat://typing', (event) => handler(event.payload));
+}
+
+// Make new messages disappear after secs (30s to 4 weeks), or keep them with null; the shorter of ours and theirs applies
+export async function setDisappearingMessages(contactId: string, secs: number | null): Promise<Contact> {
+  return invoke<Contact>('set_disappearing_messages', { contactId, secs });
+}
+
+// Fired with the messages each sweep removed after their timer ran out
+export async function onMessagesExpired(handler: (expired: ExpiredMessage[]) => void): Promise<UnlistenFn> {
+  return listen<ExpiredMessage[]>('chat://expired', (event) => handler(event.payload));
+}
+
+// Fired with an outgoing message whose status changed (e.g. the contact acknowledged it)
+export async function onMessageStatus(handler: (message: ChatMessage) => void): Promise<UnlistenFn> {
+  return listen<ChatMessage>('chat://status', (event) => handler(event.payload));
+}
+
+// Sent once due and the contact is connected; queued until then
+export async function scheduleMessage(contactPubkey: string, content: string, sendAt: number): Promise<ScheduledMessage> {
+  return invoke<ScheduledMessage>('schedule_message', { contactPubkey, content, sendAt });
+}
+
+export async function getScheduledMessages(contactPubkey?: string): Promise<ScheduledMessage[]> {
+  return invoke<ScheduledMessage[]>('get_scheduled_messages', { contactPubkey: contactPubkey ?? null });
+}
+
+export async function cancelScheduledMessage(id: string): Promise<void> {
+  return invoke<void>('cancel_scheduled_message', { id });
+}
+
+export async function onScheduledSent(handler: (sent: ScheduledSent) => void): Promise<UnlistenFn> {
+  return listen<ScheduledSent>('chat://scheduled-sent', (event) => handler(event.payload));
+}
+
+// Share sheet
+
+export async function getPendingShare(): Promise<PendingShare | null> {
+  return invoke<PendingShare | null>('get_pending_share');
+}
+
+export async function shareToContact(
+  shareId: string,
+  contactPubkey: string,
+  caption?: string
+): Promise<ChatMessage> {
+  return invoke<ChatMessage>('share_to_contact', { shareId, contactPubkey, caption: caption ?? null });
+}
+
+export async function discardPendingShare(shareId: string): Promise<boolean> {
+  return invoke<boolean>('discard_pending_share', { shareId });
+}
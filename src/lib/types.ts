@@ -4,13 +4,207 @@ export interface NostrKeys {
   publicKeyBech32: string; // npub format
 }
 
+// One use of our secret key, from the key audit log
+export interface KeyAuditEntry {
+  at: number;
+  purpose: 'identityExport' | 'keyRotation' | 'keyRevocation' | 'shareBackup' | 'socialRecovery'
+    | 'exchangeSign' | 'responseSign' | 'irohDerivation' | 'deviceCertificate';
+  detail: string | null;     // Contact pubkey or other context
+}
+
+// Passphrase protection of the stored secret key
+export interface KeyProtection {
+  encrypted: boolean;
+  unlocked: boolean;       // false until unlockKeys when encrypted
+  hardwareBacked: boolean; // wrapped by the Android Keystore
+  watchOnly: boolean;      // only the npub; the secret key is on another device
+  corrupt: string | null;  // why the stored keys failed the integrity check
+}
+
+// Old key announcing its successor, signed by both keys
+export interface RotationStatement {
+  type: 'sneakernet-key-rotation';
+  version: number;
+  oldPubkey: string;
+  newPubkey: string;
+  rotatedAt: number;
+  oldSignature: string;
+  newSignature: string;
+}
+
+export interface RotationResult {
+  keys: NostrKeys;
+  statement: string;         // JSON to hand to contacts that weren't connected
+  notified: string[];        // Pubkeys told over an open connection
+}
+
+export interface RevocationStatement {
+  type: 'sneakernet-key-revocation';
+  version: number;
+  pubkey: string;
+  revokedAt: number;
+  reason: string | null;
+  signature: string;
+}
+
+export interface RevocationResult {
+  statement: string;         // JSON to hand to contacts that weren't connected
+  notified: string[];        // Pubkeys told over an open connection
+  published: string[];       // Relays that accepted it
+}
+
+// NIP-46 signer that holds our secret key instead of this device
+export interface RemoteSignerInfo {
+  publicKeyHex: string;
+  npub: string;
+  relays: string[];
+}
+
+// This device's subkey, certified by our master identity
+export interface DeviceCertificate {
+  type: string;
+  masterPubkey: string;
+  devicePubkey: string;
+  deviceIndex: number;
+  deviceName: string;
+  issuedAt: number;
+  signature: string;
+}
+
+// Migration QR codes scanned so far on the new phone
+export interface MigrationProgress {
+  received: number;
+  total: number;
+  complete: boolean;
+}
+
+// Social recovery: contacts holding shares of our key
+export interface GuardianSet {
+  setId: string;
+  threshold: number;
+  guardians: string[];
+  createdAt: number;
+}
+
+// A share we hold for a contact, still encrypted to us
+export interface HeldShare {
+  envelope: {
+    ownerPubkey: string;
+    setId: string;
+    index: number;
+    encryptedShare: string;
+  };
+  receivedAt: number;
+}
+
+export interface PendingRecoveryRequest {
+  ownerPubkey: string;
+  requesterPubkey: string;   // Temporary identity of the recovering device
+  receivedAt: number;
+}
+
+export type RecoveryPhase = 'requested' | 'collecting' | 'ready';
+
+export interface RecoveryStatus {
+  ownerPubkey: string;
+  phase: RecoveryPhase;
+  requestedFrom: string[];
+  receivedFrom: string[];
+  threshold: number | null;  // Known once the first share arrives
+  startedAt: number;
+}
+
 // Contact from NFC exchange
 export interface Contact {
   id: string;
   nostrPubkey: string;       // hex-encoded Nostr pubkey
-  irohEndpointId: string;    // Iroh endpoint ID (base32)
+  irohEndpointId: string;    // Their Iroh node ID for us (base32), '' until known
   exchangedAt: number;       // Unix timestamp
   nickname: string | null;
+  notes: string | null;
+  tags: string[];
+  introducedAs: string | null;  // Name given by whoever introduced them
+  introducedBy: string | null;  // Pubkey of whoever introduced them
+  profileName: string | null;   // Name from their own profile
+  needsReview: boolean;      // Saved automatically (beacon mode), not yet confirmed
+  blocked: boolean;          // Messages from this contact are dropped
+  previousPubkeys: string[]; // Keys they rotated away from, oldest first
+  verified: boolean;         // Safety number compared out of band
+  masterPubkey: string | null;  // Identity their device key is certified for
+  relayUrl: string | null;   // Relay their node said it can be reached through
+  origin: ContactOrigin;
+  nostrRelays: string[];     // Relay hints from an nprofile
+  avatarHash: string | null; // SHA-256 of their avatar, from their exchange message
+  nip05: string | null;      // NIP-05 they claimed in their exchange message (unchecked)
+  exchangeMethod: 'nfc' | 'qr' | 'lan' | 'audio' | 'link' | 'nostr' | null;  // How they were exchanged with
+  exchangeSignature: string | null;  // Signature of their exchange message
+  revokedAt: number | null;  // When they revoked their key as compromised
+  disappearAfterSecs: number | null;  // Our disappearing message timer for them
+}
+
+// How a contact was added: a signed SneakerNet exchange, a plain npub or
+// nprofile from another Nostr app, or a signed introduction from a contact
+// (the last two unverified until exchanged in person)
+export type ContactOrigin = 'exchange' | 'nip19' | 'introduction' | 'remote';
+
+// Five-digit groups both sides derive from the two pubkeys
+export interface SafetyNumber {
+  groups: string[];
+}
+
+// Short code both people compare before a QR exchange is saved
+export interface ShortAuthString {
+  emoji: string[];
+  digits: string;
+}
+
+// Our own card to show or print; qrPayload is signed
+export interface ProfileCard {
+  npub: string;
+  pubkey: string;
+  displayName: string | null;
+  avatarUrl: string | null;
+  qrPayload: string;
+}
+
+// A stored contact as a plain npub/nprofile QR code, to show someone else
+export interface ContactShare {
+  npub: string;
+  pubkey: string;
+  displayName: string | null;  // Caption only; not in the payload
+  relays: string[];
+  qrPayload: string;           // nostr:nprofile1… or nostr:npub1…
+}
+
+// Display name resolved from nickname > introducer > profile > pubkey
+export interface ResolvedName {
+  name: string;
+  source: 'nickname' | 'introducer' | 'profile' | 'pubkey';
+  conflict: boolean;         // Another contact resolves to the same name
+}
+
+export interface NamedContact extends Contact {
+  displayName: ResolvedName;
+}
+
+// Filters and paging for queryContacts (limit defaults to, and is capped at, 500)
+export interface ContactQuery {
+  pubkey?: string;
+  tag?: string;
+  offset?: number;
+  limit?: number;
+}
+
+// One page of contacts, most recently exchanged first
+export interface ContactPage {
+  contacts: NamedContact[];
+  total: number;             // Matches across all pages
+}
+
+// Contacts stored more than once under the same pubkey
+export interface DuplicateGroup {
+  nostrPubkey: string;
+  contactIds: string[];
 }
 
 // NFC Exchange message format
@@ -22,6 +216,8 @@ export interface ExchangeMessage {
   timestamp: number;
   nonce: string;
   signature: string;
+  nodeId?: string;           // Sender's Iroh node ID for the recipient (responses)
+  relayUrl?: string;         // Relay the sender's node can be reached through
 }
 
 // Exchange states
@@ -34,15 +230,137 @@ export type ExchangeStatus =
   | { state: 'complete'; contact: Contact }
   | { state: 'error'; message: string };
 
-// Exchange mode (NFC or QR)
-export type ExchangeMode = 'nfc' | 'qr';
+// Categorized NFC error returned by NFC commands
+export interface NfcError {
+  kind: 'notAvailable' | 'tagLost' | 'transceive' | 'cancelled' | 'noExchangeMessage' | 'verification' | 'noKeys' | 'thirdPartyContact' | 'other';
+  message: string;
+  retryable: boolean;
+}
+
+// Resumable NFC exchange state
+export interface NfcExchangeState {
+  pendingPayload: string | null;
+  pendingFor: string | null;
+  theirPubkey: string | null;
+  awaitingResponse: boolean;
+  lastError: NfcError | null;
+}
+
+// How a one-tap exchange served over Host Card Emulation ended
+export interface HceExchangeOutcome {
+  theirPubkey: string | null;
+  error: NfcError | null;
+}
+
+// Which sides of the one-tap exchange this device can take
+export interface OneTapSupport {
+  offer: boolean; // be tapped (card emulation)
+  tap: boolean;   // tap a phone offering it
+}
+
+// Why a scanned QR payload was rejected
+export interface ScanError {
+  code: 'notSneakernetPayload' | 'wrongVersion' | 'expired' | 'clockSkew' | 'badSignature' | 'wrongRecipient' | 'selfScan' | 'alreadyContact' | 'replayed' | 'wrongSession' | 'rateLimited';
+  message: string;
+  skewSecs: number | null;   // Payload timestamp minus our clock, for 'expired' and 'clockSkew'
+}
+
+// Exchange mode (NFC, QR, local network or sound)
+export type ExchangeMode = 'nfc' | 'qr' | 'lan' | 'audio' | 'group' | 'remote';
+
+// Progress through an animated (multi-part) QR code
+export interface MultipartProgress {
+  received: number;
+  total: number;
+  complete: boolean;
+}
+
+// One scanned QR code or animated frame; theirPubkey is set once the payload is whole
+export interface QrScanResult {
+  theirPubkey: string | null;
+  progress: MultipartProgress;
+  unverifiedContact: Contact | null;  // Plain npub/nprofile, saved with no exchange
+}
+
+// Someone on a group exchange roster
+export interface RosterMember {
+  pubkey: string;
+  displayName: string | null;
+}
+
+// One scanned roster code or animated frame; the rest is filled in once it's whole
+export interface RosterScanResult {
+  progress: MultipartProgress;
+  members: RosterMember[];
+  added: Contact[];   // Contacts saved from this scan
+  frames: string[];   // Roster with us on it, for the next person
+}
 
 // QR Exchange states
 export type QRExchangeStatus =
   | { state: 'idle' }
   | { state: 'showing-qr' }
-  | { state: 'scanning' }
+  | { state: 'scanning'; progress?: MultipartProgress }
   | { state: 'processing'; theirPubkey: string }
+  | { state: 'confirming'; theirPubkey: string; sas: ShortAuthString; sessionCode: string | null }
+  | { state: 'complete'; contact: Contact }
+  | { state: 'error'; message: string };
+
+// A device hosting a local-network exchange
+export interface LanPeer {
+  name: string;      // e.g. "SneakerNet-1A2B", shown on the host's screen
+  address: string;   // ip:port
+}
+
+export interface LanHostInfo {
+  name: string;
+  port: number;
+}
+
+// A remote exchange we started, waiting for their answer over Nostr
+export interface RemoteExchange {
+  theirPubkey: string;
+  relays: string[];
+  startedAt: number;
+}
+
+// A remote exchange someone else started, waiting for us
+export interface RemoteRequestInfo {
+  theirPubkey: string;
+  displayName: string | null;
+  receivedAt: number;
+}
+
+export interface RemoteCheckResult {
+  completed: Contact[];   // Contacts whose answer arrived
+  requests: RemoteRequestInfo[];
+}
+
+export interface RemoteExchangeStatus {
+  pending: RemoteExchange[];
+  requests: RemoteRequestInfo[];
+  relays: string[];       // Watched for requests
+}
+
+export type LANExchangeStatus =
+  | { state: 'idle' }
+  | { state: 'hosting'; name: string | null }
+  | { state: 'discovering' }
+  | { state: 'choosing'; peers: LanPeer[] }
+  | { state: 'processing' }
+  | { state: 'complete'; contact: Contact }
+  | { state: 'error'; message: string };
+
+// An opened sneakernet://exchange/... link
+export interface ExchangeUriResult {
+  theirPubkey: string;
+  needsReply: boolean;   // their first message: send them our link back
+}
+
+export type AudioExchangeStatus =
+  | { state: 'idle' }
+  | { state: 'playing'; responding: boolean }
+  | { state: 'listening' }
   | { state: 'complete'; contact: Contact }
   | { state: 'error'; message: string };
 
@@ -64,4 +382,372 @@ export interface ChatMessage {
   senderPubkey: string;
   timestamp: number;
   isOutgoing: boolean;
+  kind: string;              // 'text', 'location', 'contact-card', 'payment', 'game-move', 'key-rotation', 'custom/...'
+  payload?: unknown;         // Structured payload; content is the plain-text fallback
+  flags?: string[];          // Labels attached by content filters
+  status?: MessageStatus;    // Outgoing messages only
+  editedAt?: number;         // When content was last replaced by an edit
+  editHistory?: PreviousVersion[]; // Versions replaced by edits, oldest first
+  deletedAt?: number;        // Sender deleted it; content is the placeholder
+  expiresAt?: number;        // Disappears from both sides at this time
+  groupId?: string;          // Group it was sent to; kept in that group's conversation
+}
+
+// An earlier version of an edited message
+export interface PreviousVersion {
+  content: string;
+  timestamp: number;         // When this version was written
+}
+
+// A message removed once its disappearing timer ran out
+export interface ExpiredMessage {
+  contactPubkey: string;
+  messageId: string;
+}
+
+// A contact started or stopped typing
+export interface TypingEvent {
+  contactPubkey: string;
+  typing: boolean;
+  expiresInSecs: number;     // Treat as stopped if not refreshed by then
+}
+
+// Status shown on an outgoing message; 'delivered' once the contact acknowledges it
+export type MessageStatus = 'pending' | 'sent' | 'delivered' | 'failed';
+
+// Furthest point an outgoing message has reached
+export type DeliveryStatus = 'queued' | 'sent' | 'delivered' | 'read' | 'failed';
+
+// Delivery timeline of an outgoing message (Unix seconds)
+export interface DeliveryInfo {
+  messageId: string;
+  contactPubkey: string;
+  status: DeliveryStatus;
+  queuedAt: number;
+  sentAt: number | null;
+  deliveredAt: number | null;
+  readAt: number | null;
+  transport: string | null;  // e.g. 'iroh'
+  retries: number;           // Failed attempts before it went out
+  error: string | null;
+}
+
+export interface MessageInfo {
+  message: ChatMessage;
+  delivery: DeliveryInfo | null;  // null for incoming messages
+  groupDelivery?: GroupDelivery;  // Messages we sent a group
+}
+
+// Per-member delivery of a group message we sent
+export interface GroupDelivery {
+  messageId: string;
+  groupId: string;
+  members: DeliveryInfo[];   // One per member; contactPubkey is the member
+  delivered: number;         // Members who acknowledged it
+  read: number;              // Members who read it
+}
+
+// How packets currently reach a peer
+export type PathType = 'direct' | 'relay' | 'mixed' | 'unknown';
+
+export interface PingResult {
+  contactId: string;
+  rttMs: number;
+  path: PathType;
+  connectedNow: boolean;     // A connection was opened just for the ping
+}
+
+export type TraceEventKind =
+  | 'traceStarted'
+  | 'homeRelay'
+  | 'discoveryLookup'
+  | 'connectStarted'
+  | 'connected'
+  | 'connectFailed'
+  | 'identityBound'
+  | 'identityRejected'
+  | 'pathChanged'
+  | 'holepunchAttempt'
+  | 'streamOpened'
+  | 'streamAccepted'
+  | 'connectionClosed';
+
+export interface TraceEvent {
+  atMs: number;              // Since the trace started
+  kind: TraceEventKind;
+  detail: string | null;
+}
+
+// Connection timeline captured between startConnectionTrace and stopConnectionTrace
+export interface ConnectionTrace {
+  id: string;
+  contactPubkey: string;
+  startedAt: number;
+  stoppedAt: number | null;
+  events: TraceEvent[];
+  droppedEvents: number;
+}
+
+// Message waiting to be sent at sendAt (Unix seconds)
+export interface ScheduledMessage {
+  id: string;
+  contactPubkey: string;
+  content: string;
+  sendAt: number;
+  createdAt: number;
+  attempts: number;          // Failed attempts since it became due
+  lastError: string | null;  // e.g. not connected; it stays queued
+}
+
+// Payload of the chat://scheduled-sent event
+export interface ScheduledSent {
+  scheduledId: string;
+  message: ChatMessage;
+}
+
+// A message waiting in the outbox for its contact to be reachable
+export interface QueuedMessage {
+  contactPubkey: string;
+  message: ChatMessage;      // Keeps its id; status stays 'pending' until sent
+  attempts: number;          // Failed attempts, including the one that queued it
+  lastError: string | null;
+}
+
+// Local exchange statistics for one transport
+export interface TransportStats {
+  started: number;
+  completed: number;
+  totalDurationMs: number;
+  fastestMs: number | null;
+  slowestMs: number | null;
+  failures: Record<string, number>; // keyed by "stage:reason"
+}
+
+export interface ExchangeStatsSummary {
+  stats: {
+    nfc: TransportStats;
+    qr: TransportStats;
+    lan: TransportStats;
+    audio: TransportStats;
+    link: TransportStats;
+    nostr: TransportStats;
+  };
+  nfcAverageMs: number | null;
+  qrAverageMs: number | null;
+  lanAverageMs: number | null;
+  audioAverageMs: number | null;
+  linkAverageMs: number | null;
+  nostrAverageMs: number | null;
+  nfcSuccessRate: number | null;
+  qrSuccessRate: number | null;
+  lanSuccessRate: number | null;
+  audioSuccessRate: number | null;
+  linkSuccessRate: number | null;
+  nostrSuccessRate: number | null;
+}
+
+// Backend-owned settings
+export interface Settings {
+  version: number;
+  persistence: { persistMessages: boolean; retentionDays: number | null };
+  relays: { useRelays: boolean; customRelayUrl: string | null };
+  notifications: { enabled: boolean; showPreview: boolean; sound: boolean };
+  bandwidth: { lowDataMode: boolean; maxAttachmentBytes: number };
+  security: {
+    requireUnlock: boolean;
+    autoLockMinutes: number | null;
+    blockScreenshots: boolean;
+    postQuantumChat: boolean;
+  };
+  filters: { dropUrlsFromUnverified: boolean; blockedKeywords: string[]; maxMessageChars: number | null };
+  desktop: { runInTray: boolean };
+  profile: { displayName: string | null; avatarUrl: string | null; avatarHash: string | null; nip05: string | null };
+  exchange: {
+    maxAgeSecs: number;
+    maxFutureSecs: number;
+    staticTagMaxAgeDays: number;
+    maxSendersPerMinute: number;    // 0 for no cap
+    maxPayloadsPerSender: number;   // Per minute; 0 for no cap
+    quarantineBadSignatures: boolean;
+    webLinkBase: string | null;     // Page web links open; null for sneakernet:// links
+  };
+  features: FeatureFlags;
+}
+
+// Experimental subsystems, off by default
+export type FeatureFlag = 'gossipGroups' | 'nip17Fallback' | 'coverTraffic';
+export type FeatureFlags = Record<FeatureFlag, boolean>;
+
+// Partial update accepted by update_settings (null clears optional values)
+export type SettingsPatch = { [K in keyof Settings]?: Partial<Settings[K]> };
+
+// Groups (behind the gossipGroups feature flag)
+export interface GroupMember {
+  pubkey: string;
+  admin: boolean;
+}
+
+export type MembershipAction =
+  | { action: 'add'; member: string; admin: boolean }
+  | { action: 'remove'; member: string }
+  | { action: 'leave' };
+
+// Signed roster change, distributed to every member
+export type MembershipChange = MembershipAction & {
+  id: string;
+  groupId: string;
+  author: string;
+  timestamp: number;
+  signature: string;
+};
+
+export interface Group {
+  id: string;
+  name: string;
+  creator: string;
+  createdAt: number;
+  members: GroupMember[];
+  changes: MembershipChange[];
+}
+
+export interface BroadcastPost {
+  channelId: string;
+  author: string;
+  seq: number;
+  content: string;
+  timestamp: number;
+  signature: string;
+}
+
+export interface BroadcastChannel {
+  id: string;
+  name: string;
+  owner: string;
+  isOwner: boolean;
+  posts: BroadcastPost[];
+}
+
+// Conference beacon mode
+export interface BeaconStatus {
+  payload: string;
+  expiresInSecs: number;
+  completed: number;
+}
+
+// Inbound exchange payload held for failing its signature check
+export interface QuarantinedPayload {
+  id: string;
+  pubkey: string | null;     // Key it claims to be signed by
+  transport: 'nfc' | 'qr' | 'lan' | 'audio' | 'link' | null;
+  receivedAt: number;
+  payload: string;           // Cut to 4096 characters
+}
+
+// Conference mode: a local-network exchange hosted for a time window
+export interface ConferenceAttendee {
+  theirPubkey: string;
+  displayName: string | null;
+  nip05: string | null;
+  masterPubkey: string | null;
+  joinedAt: number;          // Unix seconds their handshake finished
+}
+
+export interface ConferenceStatus {
+  host: LanHostInfo;         // Name guests pick on their screen
+  open: boolean;             // Still accepting guests
+  remainingSecs: number;
+  attendees: ConferenceAttendee[];  // Waiting for approval, in arrival order
+}
+
+// Exchange that has heard from a peer but not completed yet
+export interface PendingExchange {
+  theirPubkey: string;
+  transport: 'nfc' | 'qr' | 'lan' | 'audio' | 'link';
+  ageSecs: number;
+  expiresInSecs: number;     // Dropped when this reaches 0
+  responded: boolean;
+  masterPubkey: string | null;
+  sasConfirmed: boolean;     // Short authentication string compared
+}
+
+// Handshake step of the exchange in the foreground
+export type SessionStage = 'idle' | 'broadcast' | 'received' | 'responded' | 'complete';
+
+export interface ExchangeSession {
+  stage: SessionStage;
+  transport: 'nfc' | 'qr' | 'lan' | 'audio' | 'link' | null;
+  theirPubkey: string | null;
+  startedAt: number;
+  updatedAt: number;
+}
+
+// Live progress of the running exchange command, from `exchange://state`
+export type ExchangePhase =
+  | 'waitingForTap'
+  | 'tagDetected'
+  | 'payloadRead'
+  | 'verified'
+  | 'awaitingResponse'
+  | 'responded'
+  | 'failed';
+
+export interface ExchangeProgress {
+  phase: ExchangePhase;
+  transport: 'nfc' | 'qr' | 'lan' | 'audio' | 'link' | 'nostr';
+  theirPubkey: string | null;  // Once their message is verified
+  at: number;
+}
+
+// Local web-of-trust score for a contact (0-100)
+export type TrustLevel = 'low' | 'medium' | 'high';
+
+export interface TrustFactor {
+  name: 'inPerson' | 'unreviewed' | 'mutualContacts' | 'age';
+  points: number;
+}
+
+export interface ContactTrust {
+  contactId: string;
+  score: number;
+  level: TrustLevel;
+  mutualContacts: string[];
+  factors: TrustFactor[];
+}
+
+export interface ConversationSummary {
+  contactId: string;
+  contactPubkey: string;
+  displayName: ResolvedName;
+  lastMessage: ChatMessage | null;
+  messageCount: number;
+}
+
+// Local abuse report
+export interface Report {
+  id: string;
+  contactId: string;
+  contactPubkey: string;
+  reason: string;
+  createdAt: number;
+  blocked: boolean;
+}
+
+// Incoming message held back by a content filter
+export interface QuarantinedMessage {
+  message: ChatMessage;
+  reason: string;
+}
+
+// Backend extension registered in this build
+export type PluginInfo =
+  | { type: 'protocol'; name: string; alpn: string }
+  | { type: 'message'; name: string; kind: string };
+
+// Content shared from another app, waiting for a contact to be picked
+export interface PendingShare {
+  id: string;
+  mimeType: string;
+  text: string | null;       // Set for text shares; image bytes stay in the backend
+  sizeBytes: number;
+  receivedAt: number;
 }